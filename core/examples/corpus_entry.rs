@@ -0,0 +1,24 @@
+//! Prints the line of `test_data/corpus.txt` for a downloaded log, with its size and checksum computed from the file.
+//!
+//! ```sh
+//! cargo run -p yule_log --features dev_corpus --example corpus_entry -- <path> <firmware> <url>
+//! ```
+
+use std::env;
+use std::error::Error;
+
+use yule_log::corpus::CorpusLog;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().collect();
+    let [_, path, firmware, url] = &args[..] else {
+        eprintln!("Usage: {} <path> <firmware> <url>", args[0]);
+        std::process::exit(1);
+    };
+
+    println!(
+        "{}",
+        CorpusLog::from_file(path, firmware.as_str(), url.as_str())?
+    );
+    Ok(())
+}
@@ -0,0 +1,54 @@
+//! Compares the zstd compressed size of logs with that of their delta containers, and times both directions.
+//!
+//! Usage: `cargo run --release --features compression --example delta_footprint -- <log.ulg>...`
+
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::time::Instant;
+
+use yule_log::compression::{delta, DEFAULT_LEVEL};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let paths: Vec<String> = env::args().skip(1).collect();
+    if paths.is_empty() {
+        eprintln!("Usage: delta_footprint <log.ulg>...");
+        std::process::exit(1);
+    }
+
+    println!("log,bytes,zstd,delta,delta+zstd,encode_ms,decode_ms");
+    let (mut zstd_total, mut delta_total) = (0, 0);
+    for path in &paths {
+        let log = fs::read(path)?;
+
+        let start = Instant::now();
+        let container = delta::encode(log.as_slice(), Vec::new())?;
+        let encode_time = start.elapsed();
+
+        let start = Instant::now();
+        let restored = delta::decode(container.as_slice(), Vec::new())?;
+        let decode_time = start.elapsed();
+        if restored != log {
+            return Err(format!("{path}: the restored log differs from the original").into());
+        }
+
+        let zstd = zstd::encode_all(log.as_slice(), DEFAULT_LEVEL)?.len();
+        let delta_zstd = zstd::encode_all(container.as_slice(), DEFAULT_LEVEL)?.len();
+        zstd_total += zstd;
+        delta_total += delta_zstd;
+
+        println!(
+            "{path},{},{zstd},{},{delta_zstd},{:.1},{:.1}",
+            log.len(),
+            container.len(),
+            encode_time.as_secs_f64() * 1000.0,
+            decode_time.as_secs_f64() * 1000.0,
+        );
+    }
+
+    eprintln!(
+        "delta+zstd is {:.1}% of zstd alone",
+        100.0 * delta_total as f64 / zstd_total.max(1) as f64
+    );
+    Ok(())
+}
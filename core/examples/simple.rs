@@ -1,54 +1,45 @@
 use std::env;
-use std::error::Error;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
+use yule_log::batch::{self, BatchOptions};
 use yule_log::builder::ULogParserBuilder;
+use yule_log::errors::ULogError;
 use yule_log::model::msg::UlogMessage;
+use yule_log::parser::ULogParser;
 
-fn process_file(ulog_path: Box<Path>) -> Result<(), Box<dyn Error>> {
-    let reader = BufReader::new(File::open(ulog_path.clone())?);
-
-    let parser = ULogParserBuilder::new(reader)
+fn configure(builder: ULogParserBuilder<BufReader<File>>) -> ULogParserBuilder<BufReader<File>> {
+    builder
         .include_header(true)
         .include_timestamp(true)
         .include_padding(true)
-        .build()?;
+        .emit_ignored(true)
+        .emit_unhandled(true)
+}
+
+fn print_messages(ulog_path: &Path, parser: ULogParser<BufReader<File>>) -> Result<(), ULogError> {
+    println!("FILE: {}", ulog_path.display());
 
     for result in parser {
         let ulog_message = result?;
 
         match ulog_message {
-            /*
             UlogMessage::Header(header) => println!("HEADER: {header:?}"),
             UlogMessage::FlagBits(flag_bits) => println!("FLAG_BITS: {flag_bits:?}"),
             UlogMessage::Info(info) => println!("INFO: {info}"),
             UlogMessage::MultiInfo(multi_info) => println!("MULTI INFO: {multi_info}"),
-
-             */
-            UlogMessage::FormatDefinition(format) => {
-                if true || format.name.starts_with("telemetry_heartbeat") {
-                    println!("FORMAT_DEFINITION: {format:?}\n\n\n\n")
-                }
-            }
-            UlogMessage::AddSubscription(sub) => {
-                if true || sub.message_name.contains("heartbeat") {
-                    println!("SUBSCRIPTION_NAME: {sub:?}\n")
-                }
-            }
-            /*
+            UlogMessage::FormatDefinition(format) => println!("FORMAT_DEFINITION: {format:?}"),
             UlogMessage::Parameter(param) => println!("PARAM: {param}"),
             UlogMessage::DefaultParameter(param) => println!("PARAM DEFAULT: {param}"),
             UlogMessage::LoggedData(data) => println!("LOGGED_DATA: {data:?}"),
-
+            UlogMessage::AddSubscription(sub) => println!("SUBSCRIPTION: {sub:?}"),
             UlogMessage::LoggedString(log) => println!("LOGGED_STRING: {log}"),
             UlogMessage::TaggedLoggedString(log) => println!("TAGGED_LOGGED_STRING: {log}"),
             UlogMessage::Unhandled { msg_type, .. } => println!("Unhandled msg type: {}", msg_type as char),
             UlogMessage::Ignored { msg_type, .. } => println!("Ignored msg type:  {}", msg_type as char),
-            UlogMessage::DropoutMark(dropout) => { println!("Dropout mark: {dropout}") }
-
-             */
-            _ => {}
+            UlogMessage::DropoutMark(dropout) => println!("Dropout mark: {dropout}"),
+            UlogMessage::UnknownTyped(unknown) => println!("Unknown msg type: 0x{:02X}", unknown.msg_type),
+            UlogMessage::Custom(custom) => println!("Custom msg type: 0x{:02X}", custom.msg_type),
         }
     }
 
@@ -59,16 +50,22 @@ fn main() {
     let args: Vec<String> = env::args().collect();
 
     if args.len() != 2 {
-        eprintln!("Usage: {} <ulog file>", args[0]);
+        eprintln!("Usage: {} <ulog file or directory>", args[0]);
         std::process::exit(1);
     }
 
     let path = Path::new(&args[1]);
 
-    if path.is_file() {
-        process_file(path.into()).unwrap();
+    if path.is_dir() {
+        // One thread, so that the output of the files isn't interleaved.
+        let options = BatchOptions::new().threads(1).configure(configure);
+        let report = batch::process_dir(path, &options, print_messages).unwrap();
+
+        for (path, error) in &report.errors {
+            eprintln!("{}: {error}", path.display());
+        }
     } else {
-        eprintln!("Error: The path must specify a ulog file. Directories are not supported..");
-        std::process::exit(1);
+        let reader = BufReader::new(File::open(path).unwrap());
+        print_messages(path, configure(ULogParserBuilder::new(reader)).build().unwrap()).unwrap();
     }
 }
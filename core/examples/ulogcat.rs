@@ -7,8 +7,11 @@ use std::path::Path;
 use env_logger::Builder;
 use log::LevelFilter;
 
+use yule_log::batch::{self, BatchOptions};
 use yule_log::builder::ULogParserBuilder;
 use yule_log::encode::Encode;
+use yule_log::errors::ULogError;
+use yule_log::parser::ULogParser;
 
 fn main() -> Result<(), Box<dyn Error>> {
     // Initialize logging
@@ -25,25 +28,26 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let path = Path::new(&args[1]);
 
-    if path.is_file() {
-        process_file(path.into())?;
+    if path.is_dir() {
+        let options = BatchOptions::new().configure(configure);
+        let report = batch::process_dir(path, &options, emit)?;
+
+        for (path, error) in &report.errors {
+            eprintln!("{}: {error}", path.display());
+        }
     } else {
-        eprintln!("Error: The path must specify a ulog file. Directories are not supported..");
-        std::process::exit(1);
+        let reader = BufReader::new(File::open(path)?);
+        emit(path, configure(ULogParserBuilder::new(reader)).build()?)?;
     }
 
     Ok(())
 }
 
-fn process_file(ulog_path: Box<Path>) -> Result<(), Box<dyn Error>> {
-    let reader = BufReader::new(File::open(ulog_path.clone())?);
-
-    let parser = ULogParserBuilder::new(reader)
-        .include_header(true)
-        .include_timestamp(true)
-        .include_padding(true)
-        .build()?;
+fn configure(builder: ULogParserBuilder<BufReader<File>>) -> ULogParserBuilder<BufReader<File>> {
+    builder.include_header(true).include_timestamp(true).include_padding(true)
+}
 
+fn emit(ulog_path: &Path, parser: ULogParser<BufReader<File>>) -> Result<(), ULogError> {
     // Create the output file path.
     let output_path = ulog_path.with_extension(""); // Strip the `.ulg` extension
     let output_path = output_path.with_file_name(format!(
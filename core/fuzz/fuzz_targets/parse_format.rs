@@ -0,0 +1,40 @@
+//! Parses a log whose single format definition is the input, which must fail with an error rather than panic.
+//!
+//! `cargo +nightly fuzz run parse_format` from `core`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use yule_log::builder::ULogParserBuilder;
+
+const MAGIC: [u8; 7] = [b'U', b'L', b'o', b'g', 0x01, 0x12, 0x35];
+
+fn message(bytes: &mut Vec<u8>, msg_type: u8, payload: &[u8]) {
+    bytes.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+    bytes.push(msg_type);
+    bytes.extend_from_slice(payload);
+}
+
+fuzz_target!(|definition: &[u8]| {
+    let definition = &definition[..definition.len().min(u16::MAX as usize)];
+    // The name of the format, if any, so that it can be subscribed to and its data parsed.
+    let name = definition
+        .iter()
+        .position(|&b| b == b':')
+        .map_or(&definition[..0], |colon| &definition[..colon]);
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.push(1);
+    bytes.extend_from_slice(&0u64.to_le_bytes());
+    message(&mut bytes, b'B', &[0; 40]);
+    message(&mut bytes, b'F', definition);
+
+    let mut sub = vec![0, 0, 0];
+    sub.extend_from_slice(name);
+    message(&mut bytes, b'A', &sub);
+    message(&mut bytes, b'D', &[0; 64]);
+
+    if let Ok(parser) = ULogParserBuilder::new(bytes.as_slice()).build() {
+        for _ in parser {}
+    }
+});
@@ -0,0 +1,313 @@
+//! Extracts a value from each of a set of logs, and merges the values into one result, e.g. a histogram of
+//! the maximum vibration of each flight of a fleet.
+//!
+//! ```rust,no_run
+//! use std::sync::Arc;
+//!
+//! use yule_log::aggregate::{across_logs, AggregateOptions, Histogram};
+//! use yule_log::analysis;
+//! use yule_log::metrics::CountingMetrics;
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! # let paths: Vec<std::path::PathBuf> = Vec::new();
+//! let metrics = Arc::new(CountingMetrics::default());
+//! let options = AggregateOptions::new().metrics(metrics.clone());
+//!
+//! let report = across_logs(&paths, &options, |_path, parser| {
+//!     let stats = analysis::field_stats(parser, &["vehicle_imu_status/accel_vibration_metric"])?;
+//!     let mut histogram = Histogram::new(0.0, 20.0, 40);
+//!     histogram.insert(stats.fields[0].max);
+//!     Ok(histogram)
+//! });
+//!
+//! // `metrics.files()` can be read from another thread meanwhile, to show progress.
+//! println!("{} of {} logs failed", report.errors.len(), metrics.files());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+
+use crate::analysis::QuantileSketch;
+use crate::batch::{process_file, run_parallel, Configure};
+use crate::builder::ULogParserBuilder;
+use crate::errors::ULogError;
+use crate::metrics::{NoopMetrics, ParserMetrics};
+use crate::parser::ULogParser;
+
+/// A value which can be combined with another of its kind, e.g. a histogram.
+pub trait Merge {
+    fn merge(&mut self, other: Self);
+}
+
+impl<T> Merge for Vec<T> {
+    fn merge(&mut self, other: Self) {
+        self.extend(other);
+    }
+}
+
+impl Merge for QuantileSketch {
+    fn merge(&mut self, other: Self) {
+        QuantileSketch::merge(self, &other);
+    }
+}
+
+/// Counts values in bins of equal width.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    min: f64,
+    max: f64,
+    bins: Vec<u64>,
+    /// The number of values less than `min`.
+    pub underflow: u64,
+    /// The number of values greater than or equal to `max`.
+    pub overflow: u64,
+}
+
+impl Histogram {
+    /// Creates a histogram of `bins` bins, from `min` inclusive to `max` exclusive.
+    pub fn new(min: f64, max: f64, bins: usize) -> Self {
+        Histogram {
+            min,
+            max,
+            bins: vec![0; bins.max(1)],
+            underflow: 0,
+            overflow: 0,
+        }
+    }
+
+    /// Counts a value.  NaN values are ignored.
+    pub fn insert(&mut self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+
+        if value < self.min {
+            self.underflow += 1;
+        } else if value >= self.max {
+            self.overflow += 1;
+        } else {
+            let last = self.bins.len() - 1;
+            let bin = ((value - self.min) / self.width()) as usize;
+            self.bins[bin.min(last)] += 1;
+        }
+    }
+
+    /// The count of each bin.
+    pub fn bins(&self) -> &[u64] {
+        &self.bins
+    }
+
+    /// The range of values counted by bin `index`.
+    pub fn bin_range(&self, index: usize) -> (f64, f64) {
+        let start = self.min + self.width() * index as f64;
+        (start, start + self.width())
+    }
+
+    fn width(&self) -> f64 {
+        (self.max - self.min) / self.bins.len() as f64
+    }
+}
+
+impl Merge for Histogram {
+    /// Adds the counts of `other`, which must have the same range and number of bins.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the histograms have different bins.
+    fn merge(&mut self, other: Self) {
+        assert!(
+            self.min == other.min && self.max == other.max && self.bins.len() == other.bins.len(),
+            "Only histograms with the same bins can be merged."
+        );
+        for (count, other) in self.bins.iter_mut().zip(other.bins) {
+            *count += other;
+        }
+        self.underflow += other.underflow;
+        self.overflow += other.overflow;
+    }
+}
+
+/// Options for `across_logs()`.
+pub struct AggregateOptions {
+    threads: usize,
+    configure: Box<Configure>,
+    metrics: Arc<dyn ParserMetrics>,
+}
+
+impl Default for AggregateOptions {
+    fn default() -> Self {
+        AggregateOptions {
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            configure: Box::new(|builder| builder),
+            metrics: Arc::new(NoopMetrics),
+        }
+    }
+}
+
+impl AggregateOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of logs processed at once.  Defaults to the available parallelism.
+    #[must_use]
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Configures the parser of each log, e.g. to set a subscription allow list.
+    #[must_use]
+    pub fn configure(
+        mut self,
+        configure: impl Fn(ULogParserBuilder<BufReader<File>>) -> ULogParserBuilder<BufReader<File>>
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.configure = Box::new(configure);
+        self
+    }
+
+    /// Metrics shared by the parsers of all the logs, whose `incr_file()` hook is called as each log is
+    /// finished.
+    #[must_use]
+    pub fn metrics(mut self, metrics: Arc<dyn ParserMetrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+}
+
+/// The outcome of `across_logs()`.
+#[derive(Debug)]
+pub struct AggregateReport<T> {
+    /// The merged values of the logs which succeeded, or `None` if there were none.
+    pub value: Option<T>,
+    /// The number of logs whose values were merged.
+    pub merged: usize,
+    /// The logs which failed, ordered by path.
+    pub errors: Vec<(PathBuf, ULogError)>,
+}
+
+/// Calls `extractor` with a parser for each of `paths`, on a pool of threads, and merges the values it returns,
+/// in order of path.
+///
+/// An error returned by `extractor`, or raised while opening a log, is recorded in the report, and the other
+/// logs are still processed.
+pub fn across_logs<P, T, F>(
+    paths: &[P],
+    options: &AggregateOptions,
+    extractor: F,
+) -> AggregateReport<T>
+where
+    P: AsRef<Path>,
+    T: Merge + Send,
+    F: Fn(&Path, ULogParser<BufReader<File>>) -> Result<T, ULogError> + Sync,
+{
+    let files: Vec<PathBuf> = paths.iter().map(|p| p.as_ref().to_path_buf()).collect();
+    let configure = |builder: ULogParserBuilder<BufReader<File>>| {
+        (options.configure)(builder).metrics(options.metrics.clone())
+    };
+
+    let outcomes = run_parallel(&files, options.threads, |path| {
+        let outcome = process_file(path, &configure, &extractor);
+        options.metrics.incr_file(path, outcome.as_ref().err());
+        outcome
+    });
+
+    let mut report: AggregateReport<T> = AggregateReport {
+        value: None,
+        merged: 0,
+        errors: Vec::new(),
+    };
+    for (path, outcome) in outcomes {
+        match outcome {
+            Ok(value) => {
+                match &mut report.value {
+                    Some(merged) => merged.merge(value),
+                    None => report.value = Some(value),
+                }
+                report.merged += 1;
+            }
+            Err(e) => report.errors.push((path, e)),
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::metrics::CountingMetrics;
+    use crate::model::def::BaseType;
+    use crate::model::msg::UlogMessage;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = Histogram::new(0.0, 10.0, 5);
+        for value in [-1.0, 0.0, 1.9, 2.0, 9.99, 10.0, f64::NAN] {
+            histogram.insert(value);
+        }
+        assert_eq!(histogram.bins(), [2, 1, 0, 0, 1]);
+        assert_eq!((histogram.underflow, histogram.overflow), (1, 1));
+        assert_eq!(histogram.bin_range(1), (2.0, 4.0));
+
+        histogram.merge(histogram.clone());
+        assert_eq!(histogram.bins(), [4, 2, 0, 0, 2]);
+    }
+
+    #[test]
+    fn test_across_logs() -> Result<(), ULogError> {
+        let dir = tempfile::tempdir()?;
+        let mut paths = Vec::new();
+        for (i, peak) in [1.0, 5.0, 5.5, 9.0].into_iter().enumerate() {
+            let path = dir.path().join(format!("{i}.ulg"));
+            let log = LogBuilder::new()
+                .topic(Topic::new("vibration").field(
+                    "x",
+                    BaseType::FLOAT,
+                    Generator::Constant(peak),
+                ))
+                .build()?;
+            fs::write(&path, log)?;
+            paths.push(path);
+        }
+        fs::write(dir.path().join("corrupt.ulg"), b"not a ULOG file")?;
+        paths.push(dir.path().join("corrupt.ulg"));
+
+        let metrics = Arc::new(CountingMetrics::default());
+        let options = AggregateOptions::new().threads(2).metrics(metrics.clone());
+        let report = across_logs(&paths, &options, |_path, parser| {
+            let mut histogram = Histogram::new(0.0, 10.0, 2);
+            let mut peak = f64::NAN;
+            for msg in parser {
+                if let UlogMessage::LoggedData(data) = msg? {
+                    peak = data.data.fields[0]
+                        .value
+                        .as_f64()
+                        .unwrap_or(f64::NAN)
+                        .max(peak);
+                }
+            }
+            histogram.insert(peak);
+            Ok(histogram)
+        });
+
+        assert_eq!(report.value.unwrap().bins(), [1, 3]);
+        assert_eq!(report.merged, 4);
+        assert_eq!(report.errors.len(), 1);
+        assert!(report.errors[0].0.ends_with("corrupt.ulg"));
+        assert_eq!(metrics.files(), 5);
+        assert_eq!(metrics.messages(), 4 * 14);
+
+        Ok(())
+    }
+}
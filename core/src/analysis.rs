@@ -0,0 +1,17 @@
+//! Analyses of logged data, for work which would otherwise load the whole log into a data frame.
+
+pub mod estimator;
+pub mod power;
+pub mod rate;
+pub mod stats;
+pub mod strings;
+pub mod windows;
+
+pub use estimator::{
+    estimator_health, EstimatorHealth, RatioExceedance, RatioSummary, TestRatio, TestRatios,
+};
+pub use power::{power_report, FlightPower, PowerReport};
+pub use rate::{estimate_rate, RateEstimate, RateEstimator, RateOptions};
+pub use stats::{field_stats, FieldStats, QuantileSketch, StatsReport};
+pub use strings::{alarms, grep_messages, Alarm, AlarmKind, AlarmPatterns};
+pub use windows::{windows_around, EventWindow};
@@ -0,0 +1,415 @@
+//! The health of the state estimator, EKF2, from the test ratios of its innovations.
+//!
+//! A test ratio compares an innovation, the difference between a measurement and the estimator's prediction of it,
+//! with the bound at which the measurement is rejected: above 1.0 the measurement is rejected, and EKF2 treats the
+//! sensor as inconsistent.  The ratios are read from `estimator_innovation_test_ratios`, or from the `*_test_ratio`
+//! fields of `estimator_status` in logs from before it was split out.  Fields missing from a firmware version are
+//! `None`, rather than an error.
+//!
+//! ```rust
+//! use yule_log::analysis;
+//! use yule_log::builder::ULogParserBuilder;
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+//! let parser = ULogParserBuilder::new(input.as_slice()).build()?;
+//!
+//! let health = analysis::estimator_health(parser, 1.0)?;
+//! for exceedance in &health.exceedances {
+//!     println!("{:?} from {} to {}, peak {}", exceedance.check, exceedance.start, exceedance.end, exceedance.peak);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+
+use crate::errors::ULogError;
+use crate::model::inst;
+use crate::model::msg::{LoggedData, UlogMessage};
+
+/// The topic holding the test ratios since PX4 v1.11.
+const TEST_RATIOS_TOPIC: &str = "estimator_innovation_test_ratios";
+/// The topic which held the test ratios before.
+const STATUS_TOPIC: &str = "estimator_status";
+
+/// A consistency check of the estimator, over the innovations of one kind of measurement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum TestRatio {
+    Velocity,
+    Position,
+    Height,
+    Magnetometer,
+    Heading,
+    Airspeed,
+    HeightAboveGround,
+    Sideslip,
+    OpticalFlow,
+}
+
+impl TestRatio {
+    pub const ALL: [TestRatio; 9] = [
+        TestRatio::Velocity,
+        TestRatio::Position,
+        TestRatio::Height,
+        TestRatio::Magnetometer,
+        TestRatio::Heading,
+        TestRatio::Airspeed,
+        TestRatio::HeightAboveGround,
+        TestRatio::Sideslip,
+        TestRatio::OpticalFlow,
+    ];
+
+    // The fields of `estimator_innovation_test_ratios` whose largest element is the ratio.
+    fn test_ratios_fields(self) -> &'static [&'static str] {
+        match self {
+            TestRatio::Velocity => &["gps_hvel", "gps_vvel", "ev_hvel", "ev_vvel"],
+            TestRatio::Position => &["gps_hpos", "ev_hpos"],
+            TestRatio::Height => &["baro_vpos", "gps_vpos", "rng_vpos", "ev_vpos"],
+            TestRatio::Magnetometer => &["mag_field"],
+            TestRatio::Heading => &["heading"],
+            TestRatio::Airspeed => &["airspeed"],
+            TestRatio::HeightAboveGround => &["hagl"],
+            TestRatio::Sideslip => &["beta"],
+            TestRatio::OpticalFlow => &["flow"],
+        }
+    }
+
+    // The field of `estimator_status` holding the ratio, in the firmware versions which have it.
+    fn status_field(self) -> Option<&'static str> {
+        match self {
+            TestRatio::Velocity => Some("vel_test_ratio"),
+            TestRatio::Position => Some("pos_test_ratio"),
+            TestRatio::Height => Some("hgt_test_ratio"),
+            TestRatio::Magnetometer => Some("mag_test_ratio"),
+            TestRatio::Heading => None,
+            TestRatio::Airspeed => Some("tas_test_ratio"),
+            TestRatio::HeightAboveGround => Some("hagl_test_ratio"),
+            TestRatio::Sideslip => Some("beta_test_ratio"),
+            TestRatio::OpticalFlow => None,
+        }
+    }
+}
+
+/// The test ratios of one estimator message.  Those the message doesn't have are `None`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TestRatios {
+    pub velocity: Option<f64>,
+    pub position: Option<f64>,
+    pub height: Option<f64>,
+    pub magnetometer: Option<f64>,
+    pub heading: Option<f64>,
+    pub airspeed: Option<f64>,
+    pub height_above_ground: Option<f64>,
+    pub sideslip: Option<f64>,
+    pub optical_flow: Option<f64>,
+}
+
+impl TestRatios {
+    /// Reads the test ratios of an `estimator_innovation_test_ratios` or `estimator_status` message, or returns
+    /// `None` for the messages of other topics.  Of the fields combined into one check, e.g. the horizontal and
+    /// vertical velocity, the largest ratio is taken.
+    pub fn from_data(data: &LoggedData) -> Option<TestRatios> {
+        let mut ratios = TestRatios::default();
+        match &*data.data.name {
+            TEST_RATIOS_TOPIC => {
+                for check in TestRatio::ALL {
+                    *ratios.get_mut(check) = check
+                        .test_ratios_fields()
+                        .iter()
+                        .filter_map(|name| largest(&data.data, name))
+                        .reduce(f64::max);
+                }
+            }
+            STATUS_TOPIC => {
+                for check in TestRatio::ALL {
+                    *ratios.get_mut(check) = check
+                        .status_field()
+                        .and_then(|name| largest(&data.data, name));
+                }
+            }
+            _ => return None,
+        }
+        Some(ratios)
+    }
+
+    pub fn get(&self, check: TestRatio) -> Option<f64> {
+        match check {
+            TestRatio::Velocity => self.velocity,
+            TestRatio::Position => self.position,
+            TestRatio::Height => self.height,
+            TestRatio::Magnetometer => self.magnetometer,
+            TestRatio::Heading => self.heading,
+            TestRatio::Airspeed => self.airspeed,
+            TestRatio::HeightAboveGround => self.height_above_ground,
+            TestRatio::Sideslip => self.sideslip,
+            TestRatio::OpticalFlow => self.optical_flow,
+        }
+    }
+
+    fn get_mut(&mut self, check: TestRatio) -> &mut Option<f64> {
+        match check {
+            TestRatio::Velocity => &mut self.velocity,
+            TestRatio::Position => &mut self.position,
+            TestRatio::Height => &mut self.height,
+            TestRatio::Magnetometer => &mut self.magnetometer,
+            TestRatio::Heading => &mut self.heading,
+            TestRatio::Airspeed => &mut self.airspeed,
+            TestRatio::HeightAboveGround => &mut self.height_above_ground,
+            TestRatio::Sideslip => &mut self.sideslip,
+            TestRatio::OpticalFlow => &mut self.optical_flow,
+        }
+    }
+}
+
+/// A span in which a test ratio of an estimator instance stayed above the threshold.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RatioExceedance {
+    pub check: TestRatio,
+    /// The multi_id of the estimator, which PX4 runs one of per IMU.
+    pub instance: u8,
+    /// The timestamp of the first sample above the threshold.
+    pub start: u64,
+    /// The timestamp of the first sample back below it, or of the last sample of a log which ends above it.
+    pub end: u64,
+    /// The largest ratio of the span.
+    pub peak: f64,
+}
+
+/// The samples of a test ratio of an estimator instance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RatioSummary {
+    pub check: TestRatio,
+    pub instance: u8,
+    pub samples: u64,
+    pub max: f64,
+    /// The time spent above the threshold, in microseconds.
+    pub time_above_us: u64,
+}
+
+/// The outcome of `estimator_health()`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EstimatorHealth {
+    /// The threshold the ratios were compared with.
+    pub threshold: f64,
+    /// A summary of each test ratio logged, ordered by check, then instance.
+    pub ratios: Vec<RatioSummary>,
+    /// The spans above the threshold, ordered by start.
+    pub exceedances: Vec<RatioExceedance>,
+}
+
+impl EstimatorHealth {
+    /// Whether no test ratio exceeded the threshold.
+    pub fn is_healthy(&self) -> bool {
+        self.exceedances.is_empty()
+    }
+}
+
+/// The state of one test ratio of one instance.
+#[derive(Debug)]
+struct RatioTrack {
+    topic: &'static str,
+    summary: RatioSummary,
+    open: Option<RatioExceedance>,
+}
+
+/// Reads a log and compares each test ratio with `threshold`, typically 1.0, at which EKF2 rejects measurements, or
+/// 0.5 for an early warning.  See the module documentation.
+///
+/// When a log has both topics, each ratio is taken from whichever logged it first, so that no span is counted twice.
+pub fn estimator_health<I>(messages: I, threshold: f64) -> Result<EstimatorHealth, ULogError>
+where
+    I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+{
+    let mut tracks: HashMap<(TestRatio, u8), RatioTrack> = HashMap::new();
+    let mut exceedances = Vec::new();
+
+    for msg in messages {
+        let UlogMessage::LoggedData(data) = msg? else {
+            continue;
+        };
+        let Some(ratios) = TestRatios::from_data(&data) else {
+            continue;
+        };
+        let topic = if &*data.data.name == TEST_RATIOS_TOPIC {
+            TEST_RATIOS_TOPIC
+        } else {
+            STATUS_TOPIC
+        };
+        let instance = data.data.multi_id_index.unwrap_or(0);
+        let timestamp = data.timestamp;
+
+        for check in TestRatio::ALL {
+            let Some(ratio) = ratios.get(check).filter(|ratio| !ratio.is_nan()) else {
+                continue;
+            };
+            let track = tracks
+                .entry((check, instance))
+                .or_insert_with(|| RatioTrack {
+                    topic,
+                    summary: RatioSummary {
+                        check,
+                        instance,
+                        samples: 0,
+                        max: ratio,
+                        time_above_us: 0,
+                    },
+                    open: None,
+                });
+            if track.topic != topic {
+                continue;
+            }
+
+            track.summary.samples += 1;
+            track.summary.max = track.summary.max.max(ratio);
+            if ratio > threshold {
+                let open = track.open.get_or_insert(RatioExceedance {
+                    check,
+                    instance,
+                    start: timestamp,
+                    end: timestamp,
+                    peak: ratio,
+                });
+                open.end = timestamp;
+                open.peak = open.peak.max(ratio);
+            } else if let Some(mut open) = track.open.take() {
+                open.end = timestamp;
+                track.summary.time_above_us += open.end.saturating_sub(open.start);
+                exceedances.push(open);
+            }
+        }
+    }
+
+    let mut ratios = Vec::with_capacity(tracks.len());
+    for mut track in tracks.into_values() {
+        if let Some(open) = track.open.take() {
+            track.summary.time_above_us += open.end.saturating_sub(open.start);
+            exceedances.push(open);
+        }
+        ratios.push(track.summary);
+    }
+    ratios.sort_by_key(|summary| (summary.check, summary.instance));
+    exceedances.sort_by_key(|exceedance| (exceedance.start, exceedance.check, exceedance.instance));
+
+    Ok(EstimatorHealth {
+        threshold,
+        ratios,
+        exceedances,
+    })
+}
+
+// The value of a scalar field, or the largest element of an array field.
+fn largest(data: &inst::Format, name: &str) -> Option<f64> {
+    let value = &data.field(name)?.value;
+    match value.to_scalars() {
+        Some(elements) => elements
+            .iter()
+            .filter_map(inst::FieldValue::as_f64)
+            .reduce(f64::max),
+        None => value.as_f64(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::model::def::BaseType;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    #[test]
+    fn test_estimator_health() -> Result<(), ULogError> {
+        let bytes = LogBuilder::new()
+            .start_timestamp(0)
+            .duration(Duration::from_secs(10))
+            .topic(
+                Topic::new(TEST_RATIOS_TOPIC)
+                    .rate_hz(1.0)
+                    .array_field(
+                        "gps_hvel",
+                        BaseType::FLOAT,
+                        2,
+                        Generator::from_fn(|sample| match (sample.index, sample.element) {
+                            (3..=4, 1) => 1.5 + sample.index as f64,
+                            _ => 0.2,
+                        }),
+                    )
+                    .field("baro_vpos", BaseType::FLOAT, Generator::Constant(0.1)),
+            )
+            .topic(
+                // An older layout, whose velocity ratio is already read from the newer topic.
+                Topic::new(STATUS_TOPIC)
+                    .rate_hz(1.0)
+                    .field("vel_test_ratio", BaseType::FLOAT, Generator::Constant(9.0))
+                    .field(
+                        "mag_test_ratio",
+                        BaseType::FLOAT,
+                        Generator::from_fn(|sample| if sample.index == 8 { 1.2 } else { 0.3 }),
+                    ),
+            )
+            .build()?;
+
+        let health = estimator_health(ULogParserBuilder::new(bytes.as_slice()).build()?, 1.0)?;
+        assert!(!health.is_healthy());
+        assert_eq!(
+            health.exceedances,
+            [
+                RatioExceedance {
+                    check: TestRatio::Velocity,
+                    instance: 0,
+                    start: 3_000_000,
+                    end: 5_000_000,
+                    peak: 5.5,
+                },
+                RatioExceedance {
+                    check: TestRatio::Magnetometer,
+                    instance: 0,
+                    start: 8_000_000,
+                    end: 9_000_000,
+                    peak: 1.2000000476837158,
+                },
+            ]
+        );
+
+        let checks: Vec<(TestRatio, u64)> = health
+            .ratios
+            .iter()
+            .map(|summary| (summary.check, summary.time_above_us))
+            .collect();
+        assert_eq!(
+            checks,
+            [
+                (TestRatio::Velocity, 2_000_000),
+                (TestRatio::Height, 0),
+                (TestRatio::Magnetometer, 1_000_000)
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_backwards_timestamp() -> Result<(), ULogError> {
+        // A below threshold sample of msg_id 0, timestamped before the exceedance it ends.
+        let mut backwards = vec![14, 0, b'D', 0, 0];
+        backwards.extend_from_slice(&1_000_000u64.to_le_bytes());
+        backwards.extend_from_slice(&0.1f32.to_le_bytes());
+
+        let bytes = LogBuilder::new()
+            .start_timestamp(0)
+            .duration(Duration::from_secs(5))
+            .topic(Topic::new(TEST_RATIOS_TOPIC).rate_hz(1.0).field(
+                "baro_vpos",
+                BaseType::FLOAT,
+                Generator::from_fn(|sample| if sample.index >= 3 { 2.0 } else { 0.1 }),
+            ))
+            .garbage_at(Duration::from_millis(3500), &backwards)
+            .build()?;
+
+        let health = estimator_health(ULogParserBuilder::new(bytes.as_slice()).build()?, 1.0)?;
+        assert_eq!(health.exceedances.len(), 2);
+        assert_eq!(health.ratios[0].time_above_us, 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,315 @@
+//! Armed time and battery usage per flight, the usual fleet KPIs, computed in one pass over a log.
+//!
+//! A flight is a span in which the vehicle is armed, going by `vehicle_status/arming_state`, or by
+//! `actuator_armed/armed` in logs without `vehicle_status`.  The battery figures come from `battery_status`, summed
+//! or combined over its instances.
+//!
+//! ```rust
+//! use yule_log::analysis;
+//! use yule_log::builder::ULogParserBuilder;
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+//! let parser = ULogParserBuilder::new(input.as_slice()).build()?;
+//!
+//! let report = analysis::power_report(parser)?;
+//! println!("armed for {} s, {:.1} Wh", report.armed_time_us / 1_000_000, report.energy_wh());
+//! for flight in &report.flights {
+//!     println!("{}: max {:?} A, min {:?} V", flight.start, flight.max_current_a, flight.min_voltage_v);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+
+use crate::errors::ULogError;
+use crate::model::inst;
+use crate::model::msg::UlogMessage;
+
+/// `vehicle_status/arming_state` of an armed vehicle.
+const ARMING_STATE_ARMED: f64 = 2.0;
+
+/// The battery usage of one flight.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlightPower {
+    /// The timestamp at which the vehicle was armed.
+    pub start: u64,
+    /// The timestamp at which it was disarmed, or of the last message of a log which ends armed.
+    pub end: u64,
+    /// The energy drawn, from the voltage and current of each battery.
+    pub energy_wh: f64,
+    /// The charge drawn, by `battery_status/discharged_mah`, if logged.
+    pub discharged_mah: Option<f64>,
+    pub max_current_a: Option<f64>,
+    /// The lowest voltage of a battery, i.e. under load.
+    pub min_voltage_v: Option<f64>,
+}
+
+impl FlightPower {
+    pub fn duration_us(&self) -> u64 {
+        self.end.saturating_sub(self.start)
+    }
+}
+
+/// The battery usage of the flights of a log, returned by `power_report()`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PowerReport {
+    /// The total time armed, in microseconds.
+    pub armed_time_us: u64,
+    /// The flights, in order.
+    pub flights: Vec<FlightPower>,
+    /// The highest current while armed, of all flights.
+    pub max_current_a: Option<f64>,
+    /// The lowest voltage while armed, of all flights.
+    pub min_voltage_under_load_v: Option<f64>,
+}
+
+impl PowerReport {
+    /// The energy drawn by all flights, in watt-hours.
+    pub fn energy_wh(&self) -> f64 {
+        self.flights.iter().map(|flight| flight.energy_wh).sum()
+    }
+}
+
+/// The last sample of a battery instance, whose power is taken to hold until the next.
+#[derive(Debug, Clone, Copy)]
+struct BatterySample {
+    timestamp: u64,
+    power_w: Option<f64>,
+    discharged_mah: Option<f64>,
+}
+
+/// Reads a log and returns its armed time and battery usage per flight.  See the module documentation.
+///
+/// Voltages which aren't positive and negative currents, which PX4 logs for unknown values, are left out.
+pub fn power_report<I>(messages: I) -> Result<PowerReport, ULogError>
+where
+    I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+{
+    let mut report = PowerReport::default();
+    let mut flight: Option<FlightPower> = None;
+    // The charge drawn by each battery instance at the start of the current flight.
+    let mut discharged_at_start: HashMap<u8, f64> = HashMap::new();
+    let mut batteries: HashMap<u8, BatterySample> = HashMap::new();
+    let mut has_vehicle_status = false;
+    let mut last_timestamp = 0;
+
+    for msg in messages {
+        let UlogMessage::LoggedData(data) = msg? else {
+            continue;
+        };
+        let timestamp = data.timestamp;
+        last_timestamp = last_timestamp.max(timestamp);
+
+        let armed = match &*data.data.name {
+            "vehicle_status" => {
+                has_vehicle_status = true;
+                scalar(&data.data, "arming_state").map(|state| state == ARMING_STATE_ARMED)
+            }
+            "actuator_armed" if !has_vehicle_status => {
+                scalar(&data.data, "armed").map(|armed| armed != 0.0)
+            }
+            "battery_status" => {
+                let instance = data.data.multi_id_index.unwrap_or(0);
+                let voltage = scalar(&data.data, "voltage_v").filter(|&v| v > 0.0);
+                let current = scalar(&data.data, "current_a").filter(|&a| a >= 0.0);
+                let sample = BatterySample {
+                    timestamp,
+                    power_w: voltage.zip(current).map(|(v, a)| v * a),
+                    discharged_mah: scalar(&data.data, "discharged_mah")
+                        .filter(|mah| !mah.is_nan()),
+                };
+                let previous = batteries.insert(instance, sample);
+
+                if let Some(flight) = &mut flight {
+                    // A battery first logged during the flight counts from its first sample.
+                    if let Some(mah) = sample.discharged_mah {
+                        discharged_at_start.entry(instance).or_insert(mah);
+                    }
+                    if let Some(previous) = previous {
+                        flight.energy_wh += energy_wh(&previous, flight.start, timestamp);
+                    }
+                    flight.max_current_a = max(flight.max_current_a, current);
+                    flight.min_voltage_v = min(flight.min_voltage_v, voltage);
+                    flight.discharged_mah = discharged(&batteries, &discharged_at_start);
+                }
+                None
+            }
+            _ => None,
+        };
+
+        match (armed, flight.is_some()) {
+            (Some(true), false) => {
+                flight = Some(FlightPower {
+                    start: timestamp,
+                    end: timestamp,
+                    energy_wh: 0.0,
+                    discharged_mah: None,
+                    max_current_a: None,
+                    min_voltage_v: None,
+                });
+                discharged_at_start = batteries
+                    .iter()
+                    .filter_map(|(&instance, sample)| Some((instance, sample.discharged_mah?)))
+                    .collect();
+            }
+            (Some(false), true) => finish(&mut report, flight.take(), &batteries, timestamp),
+            _ => {}
+        }
+    }
+
+    finish(&mut report, flight.take(), &batteries, last_timestamp);
+    Ok(report)
+}
+
+fn finish(
+    report: &mut PowerReport,
+    flight: Option<FlightPower>,
+    batteries: &HashMap<u8, BatterySample>,
+    end: u64,
+) {
+    let Some(mut flight) = flight else {
+        return;
+    };
+    flight.end = end;
+    for last in batteries.values() {
+        flight.energy_wh += energy_wh(last, flight.start, end);
+    }
+    report.armed_time_us += flight.duration_us();
+    report.max_current_a = max(report.max_current_a, flight.max_current_a);
+    report.min_voltage_under_load_v = min(report.min_voltage_under_load_v, flight.min_voltage_v);
+    report.flights.push(flight);
+}
+
+// The energy drawn at the power of `sample` from when it was logged, or the start of the flight, until `until`.
+fn energy_wh(sample: &BatterySample, flight_start: u64, until: u64) -> f64 {
+    let from = sample.timestamp.max(flight_start);
+    match sample.power_w {
+        Some(power_w) if until > from => power_w * (until - from) as f64 / 3.6e9,
+        _ => 0.0,
+    }
+}
+
+// The charge drawn by the batteries since the start of the flight.
+fn discharged(batteries: &HashMap<u8, BatterySample>, at_start: &HashMap<u8, f64>) -> Option<f64> {
+    let drawn: Vec<f64> = batteries
+        .iter()
+        .filter_map(|(instance, sample)| Some(sample.discharged_mah? - at_start.get(instance)?))
+        .collect();
+    (!drawn.is_empty()).then(|| drawn.iter().sum())
+}
+
+fn scalar(data: &inst::Format, name: &str) -> Option<f64> {
+    data.field(name)?.value.as_f64()
+}
+
+fn max(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+fn min(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::model::def::BaseType;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    #[test]
+    fn test_power_report() -> Result<(), ULogError> {
+        // Armed from 2 s to 6 s, drawing 10 A at 16 V, and 12 A at 15 V from 4 s.
+        let loaded = |sample: &crate::testkit::Sample| sample.timestamp >= 4_000_000;
+        let bytes = LogBuilder::new()
+            .start_timestamp(0)
+            .duration(Duration::from_secs(10))
+            .topic(Topic::new("vehicle_status").rate_hz(1.0).field(
+                "arming_state",
+                BaseType::UINT8,
+                Generator::from_fn(|sample| {
+                    if (2..6).contains(&sample.index) {
+                        2.0
+                    } else {
+                        1.0
+                    }
+                }),
+            ))
+            .topic(
+                Topic::new("battery_status")
+                    .rate_hz(10.0)
+                    .field(
+                        "voltage_v",
+                        BaseType::FLOAT,
+                        Generator::from_fn(move |sample| if loaded(sample) { 15.0 } else { 16.0 }),
+                    )
+                    .field(
+                        "current_a",
+                        BaseType::FLOAT,
+                        Generator::from_fn(move |sample| if loaded(sample) { 12.0 } else { 10.0 }),
+                    )
+                    .field(
+                        "discharged_mah",
+                        BaseType::FLOAT,
+                        Generator::Ramp {
+                            start: 0.0,
+                            step: 1.0,
+                        },
+                    ),
+            )
+            .build()?;
+
+        let report = power_report(ULogParserBuilder::new(bytes.as_slice()).build()?)?;
+        assert_eq!(report.flights.len(), 1);
+        assert_eq!(report.armed_time_us, 4_000_000);
+
+        let flight = &report.flights[0];
+        assert_eq!((flight.start, flight.end), (2_000_000, 6_000_000));
+        // 160 W for 2 s, and 180 W for 2 s.
+        assert!(
+            (flight.energy_wh - 680.0 / 3600.0).abs() < 1e-9,
+            "{}",
+            flight.energy_wh
+        );
+        assert_eq!(flight.discharged_mah, Some(40.0));
+        assert_eq!(report.max_current_a, Some(12.0));
+        assert_eq!(report.min_voltage_under_load_v, Some(15.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_backwards_timestamp() -> Result<(), ULogError> {
+        // A disarmed `vehicle_status` of msg_id 0, timestamped before the flight it ends.
+        let mut backwards = vec![11, 0, b'D', 0, 0];
+        backwards.extend_from_slice(&1_000_000u64.to_le_bytes());
+        backwards.push(1);
+
+        let bytes = LogBuilder::new()
+            .start_timestamp(0)
+            .duration(Duration::from_secs(4))
+            .topic(Topic::new("vehicle_status").rate_hz(1.0).field(
+                "arming_state",
+                BaseType::UINT8,
+                Generator::from_fn(|sample| if sample.index == 2 { 2.0 } else { 1.0 }),
+            ))
+            .garbage_at(Duration::from_millis(2500), &backwards)
+            .build()?;
+
+        let report = power_report(ULogParserBuilder::new(bytes.as_slice()).build()?)?;
+        let flight = &report.flights[0];
+        assert_eq!((flight.start, flight.end), (2_000_000, 1_000_000));
+        assert_eq!(flight.duration_us(), 0);
+        assert_eq!(report.armed_time_us, 0);
+        Ok(())
+    }
+}
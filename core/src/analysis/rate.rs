@@ -0,0 +1,254 @@
+//! Estimates the nominal rate of a topic from the intervals between its messages, ignoring the intervals broken
+//! by dropouts or gaps, which make the naive count over duration too low.
+//!
+//! ```rust
+//! use yule_log::analysis::{self, RateOptions};
+//! use yule_log::builder::ULogParserBuilder;
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+//! let parser = ULogParserBuilder::new(input.as_slice()).build()?;
+//!
+//! if let Some(estimate) = analysis::estimate_rate(parser, "vehicle_local_position", &RateOptions::default())? {
+//!     println!("{:.1} Hz ({:.1} Hz naively)", estimate.rate_hz, estimate.naive_rate_hz);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+
+use crate::errors::ULogError;
+use crate::model::msg::UlogMessage;
+
+/// How the nominal interval is computed from the intervals which are kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum RateEstimator {
+    /// The median interval, which ignores jitter and the odd late message.
+    #[default]
+    Median,
+    /// The mean interval.
+    Mean,
+    /// The mean of the intervals between the given lower and upper quantiles, e.g. `(0.1, 0.9)`.
+    TrimmedMean(f64, f64),
+}
+
+/// The options of `estimate_rate()`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateOptions {
+    estimator: RateEstimator,
+    max_gap_factor: f64,
+    multi_id: Option<u8>,
+}
+
+impl Default for RateOptions {
+    fn default() -> Self {
+        RateOptions {
+            estimator: RateEstimator::Median,
+            max_gap_factor: 5.0,
+            multi_id: None,
+        }
+    }
+}
+
+impl RateOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Defaults to `RateEstimator::Median`.
+    #[must_use]
+    pub fn estimator(mut self, estimator: RateEstimator) -> Self {
+        self.estimator = estimator;
+        self
+    }
+
+    /// Intervals longer than this many times the median interval are gaps, and are ignored.  Defaults to 5.
+    #[must_use]
+    pub fn max_gap_factor(mut self, factor: f64) -> Self {
+        self.max_gap_factor = factor;
+        self
+    }
+
+    /// The instance of the topic whose rate is estimated.  Defaults to `None`, which pools the intervals of all
+    /// instances, each measured within its instance.
+    #[must_use]
+    pub fn multi_id(mut self, multi_id: Option<u8>) -> Self {
+        self.multi_id = multi_id;
+        self
+    }
+}
+
+/// The estimated rate of a topic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateEstimate {
+    pub rate_hz: f64,
+    /// The nominal interval between messages, which the rate is the inverse of.
+    pub interval_us: f64,
+    /// The number of messages.
+    pub messages: u64,
+    /// The number of intervals the estimate was computed from.
+    pub intervals: u64,
+    /// The number of intervals ignored as they span a dropout.
+    pub dropout_intervals: u64,
+    /// The number of intervals ignored as gaps.
+    pub gap_intervals: u64,
+    /// The number of messages over the time from the first to the last, for comparison.
+    pub naive_rate_hz: f64,
+}
+
+/// Reads a log and estimates the rate of `topic`.  Returns `None` if the topic has no interval to estimate from,
+/// or its messages all have the same timestamp.
+pub fn estimate_rate<I>(
+    messages: I,
+    topic: &str,
+    options: &RateOptions,
+) -> Result<Option<RateEstimate>, ULogError>
+where
+    I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+{
+    // multi_id -> the timestamp of its last message, and whether a dropout was logged since.
+    let mut last: HashMap<u8, (u64, bool)> = HashMap::new();
+    let mut intervals: Vec<u64> = Vec::new();
+    let mut dropout_intervals = 0;
+    let mut count = 0;
+    let (mut first_timestamp, mut last_timestamp) = (u64::MAX, 0);
+
+    for msg in messages {
+        match msg? {
+            UlogMessage::DropoutMark(_) => {
+                for (_, dropped) in last.values_mut() {
+                    *dropped = true;
+                }
+            }
+            UlogMessage::LoggedData(data) if *data.data.name == *topic => {
+                let multi_id = data.data.multi_id_index.unwrap_or(0);
+                if options.multi_id.is_some_and(|id| id != multi_id) {
+                    continue;
+                }
+
+                count += 1;
+                first_timestamp = first_timestamp.min(data.timestamp);
+                last_timestamp = last_timestamp.max(data.timestamp);
+                let previous = last.insert(multi_id, (data.timestamp, false));
+                match previous {
+                    Some((_, true)) => dropout_intervals += 1,
+                    Some((previous, false)) => {
+                        intervals.push(data.timestamp.saturating_sub(previous));
+                    }
+                    None => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if intervals.is_empty() {
+        return Ok(None);
+    }
+    intervals.sort_unstable();
+    let median = median(&intervals);
+    let max_interval = median * options.max_gap_factor;
+    let kept = intervals.partition_point(|&interval| interval as f64 <= max_interval);
+    let gap_intervals = (intervals.len() - kept) as u64;
+    intervals.truncate(kept);
+    if intervals.is_empty() {
+        return Ok(None);
+    }
+
+    let interval_us = match options.estimator {
+        RateEstimator::Median => median,
+        RateEstimator::Mean => mean(&intervals),
+        RateEstimator::TrimmedMean(lower, upper) => {
+            let at = |q: f64| (q.clamp(0.0, 1.0) * intervals.len() as f64) as usize;
+            let start = at(lower).min(intervals.len() - 1);
+            let end = at(upper).clamp(start + 1, intervals.len());
+            mean(&intervals[start..end])
+        }
+    };
+    if interval_us <= 0.0 {
+        return Ok(None);
+    }
+
+    let duration_us = last_timestamp.saturating_sub(first_timestamp) as f64;
+    Ok(Some(RateEstimate {
+        rate_hz: 1e6 / interval_us,
+        interval_us,
+        messages: count,
+        intervals: intervals.len() as u64,
+        dropout_intervals,
+        gap_intervals,
+        naive_rate_hz: if duration_us > 0.0 {
+            (count - 1) as f64 * 1e6 / duration_us
+        } else {
+            0.0
+        },
+    }))
+}
+
+/// The median of sorted, non empty `values`.
+fn median(values: &[u64]) -> f64 {
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] as f64 + values[mid] as f64) / 2.0
+    } else {
+        values[mid] as f64
+    }
+}
+
+fn mean(values: &[u64]) -> f64 {
+    values.iter().map(|&v| v as f64).sum::<f64>() / values.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::model::def::BaseType;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    #[test]
+    fn test_estimate_rate() -> Result<(), ULogError> {
+        let bytes = LogBuilder::new()
+            .duration(Duration::from_secs(10))
+            .topic(Topic::new("a").rate_hz(10.0).field(
+                "x",
+                BaseType::FLOAT,
+                Generator::Constant(0.0),
+            ))
+            .dropout_at(Duration::from_millis(7_050), 100)
+            .build()?;
+        // A 2 s gap, and the messages lost in the dropout.
+        let messages = ULogParserBuilder::new(bytes.as_slice())
+            .build()?
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .filter(|msg| match msg {
+                UlogMessage::LoggedData(data) => {
+                    !(3_050_000..5_000_000).contains(&data.timestamp)
+                        && !(8_050_000..8_300_000).contains(&data.timestamp)
+                }
+                _ => true,
+            })
+            .collect::<Vec<_>>();
+        let estimate = |topic, options: RateOptions| {
+            estimate_rate(messages.iter().cloned().map(Ok), topic, &options)
+        };
+
+        let median = estimate("a", RateOptions::default())?.unwrap();
+        assert_eq!(median.rate_hz, 10.0);
+        assert_eq!((median.messages, median.gap_intervals), (79, 1));
+        assert_eq!(median.dropout_intervals, 1);
+        assert!((median.naive_rate_hz - 78.0 / 9.9).abs() < 1e-9);
+
+        let mean = estimate("a", RateOptions::new().estimator(RateEstimator::Mean))?.unwrap();
+        assert!((mean.rate_hz - 10.0).abs() < 1e-6);
+        assert_eq!(mean.intervals, median.intervals);
+
+        assert_eq!(estimate("b", RateOptions::default())?, None);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,444 @@
+//! Statistics of fields, computed in one pass over a log.
+//!
+//! ```rust
+//! use yule_log::analysis;
+//! use yule_log::builder::ULogParserBuilder;
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+//! let parser = ULogParserBuilder::new(input.as_slice()).build()?;
+//!
+//! let report = analysis::field_stats(parser, &["vehicle_local_position/x", "vehicle_local_position/z"])?;
+//! for stats in &report.fields {
+//!     println!("{}: mean {}, p95 {:?}", stats.path, stats.mean, stats.quantile(0.95));
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::f64::consts::PI;
+use std::io::{self, Write};
+
+use crate::errors::ULogError;
+use crate::model::msg::UlogMessage;
+use crate::timeseries::{FieldLookup, FieldPath};
+
+/// The number of values buffered by a `QuantileSketch` before they are merged into its centroids.
+const BUFFER_SIZE: usize = 512;
+
+/// The quantiles written by `StatsReport::to_json()` and `StatsReport::write_csv()`.
+const REPORTED_QUANTILES: [(&str, f64); 5] = [
+    ("p1", 0.01),
+    ("p5", 0.05),
+    ("p50", 0.5),
+    ("p95", 0.95),
+    ("p99", 0.99),
+];
+
+/// An approximation of the distribution of a stream of values, from which quantiles can be estimated in bounded
+/// memory.
+///
+/// This is a merging t-digest: values are buffered, then merged into weighted centroids, which are kept small near
+/// the minimum and maximum so that the extreme quantiles stay accurate.  The number of centroids is about the
+/// compression, whatever the number of values.
+#[derive(Debug, Clone)]
+pub struct QuantileSketch {
+    compression: f64,
+    // (mean, weight), ordered by mean.
+    centroids: Vec<(f64, f64)>,
+    buffer: Vec<f64>,
+    count: u64,
+    min: f64,
+    max: f64,
+}
+
+impl Default for QuantileSketch {
+    fn default() -> Self {
+        Self::new(100.0)
+    }
+}
+
+impl QuantileSketch {
+    /// Creates a sketch.  A larger `compression` keeps more centroids, and so gives more accurate quantiles.
+    pub fn new(compression: f64) -> Self {
+        QuantileSketch {
+            compression: compression.max(10.0),
+            centroids: Vec::new(),
+            buffer: Vec::with_capacity(BUFFER_SIZE),
+            count: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    /// Adds a value.  NaN values are ignored.
+    pub fn insert(&mut self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+
+        self.buffer.push(value);
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        if self.buffer.len() >= BUFFER_SIZE {
+            self.flush();
+        }
+    }
+
+    /// Adds the values of another sketch.
+    pub fn merge(&mut self, other: &QuantileSketch) {
+        self.buffer.extend_from_slice(&other.buffer);
+        self.centroids.extend_from_slice(&other.centroids);
+        self.count += other.count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.flush();
+    }
+
+    /// The number of values added.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Estimates the value below which a fraction `q` of the values lie, or returns `None` if there are no values.
+    /// `q` is clamped to `[0, 1]`.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        if !self.buffer.is_empty() {
+            let mut flushed = self.clone();
+            flushed.flush();
+            return flushed.quantile(q);
+        }
+
+        let q = q.clamp(0.0, 1.0);
+        let target = q * self.count as f64;
+        let (first_mean, first_weight) = self.centroids[0];
+
+        // Each centroid is taken to lie at the middle of its weight, and values between the centroids are
+        // interpolated.  Below the first and above the last centroid, they are interpolated to the min and max.
+        if target <= first_weight / 2.0 {
+            let t = if first_weight > 0.0 {
+                target / (first_weight / 2.0)
+            } else {
+                0.0
+            };
+            return Some(self.min + (first_mean - self.min) * t);
+        }
+
+        let mut cumulative = 0.0;
+        for pair in self.centroids.windows(2) {
+            let ((mean, weight), (next_mean, next_weight)) = (pair[0], pair[1]);
+            let center = cumulative + weight / 2.0;
+            let next_center = cumulative + weight + next_weight / 2.0;
+            if target <= next_center {
+                let t = (target - center) / (next_center - center);
+                return Some(mean + (next_mean - mean) * t);
+            }
+            cumulative += weight;
+        }
+
+        let (last_mean, last_weight) = self.centroids[self.centroids.len() - 1];
+        let center = self.count as f64 - last_weight / 2.0;
+        let t = (target - center) / (last_weight / 2.0);
+        Some(last_mean + (self.max - last_mean) * t.min(1.0))
+    }
+
+    fn flush(&mut self) {
+        if self.buffer.is_empty() && self.centroids.len() <= 1 {
+            return;
+        }
+
+        let mut all: Vec<(f64, f64)> = std::mem::take(&mut self.centroids);
+        all.extend(self.buffer.drain(..).map(|value| (value, 1.0)));
+        all.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let total: f64 = all.iter().map(|&(_, weight)| weight).sum();
+        let mut merged: Vec<(f64, f64)> = Vec::with_capacity(self.compression as usize);
+        let mut before = 0.0;
+        let mut limit = self.weight_limit(0.0, total);
+
+        for (mean, weight) in all {
+            match merged.last_mut() {
+                Some((last_mean, last_weight)) if before + *last_weight + weight <= limit => {
+                    *last_weight += weight;
+                    *last_mean += (mean - *last_mean) * weight / *last_weight;
+                }
+                _ => {
+                    if let Some(&(_, last_weight)) = merged.last() {
+                        before += last_weight;
+                        limit = self.weight_limit(before, total);
+                    }
+                    merged.push((mean, weight));
+                }
+            }
+        }
+
+        self.centroids = merged;
+    }
+
+    /// The cumulative weight up to which a centroid starting at cumulative weight `before` may grow.  With the
+    /// k1 scale function, this allows centroids of about `total / compression` in the middle of the
+    /// distribution, shrinking towards the ends.
+    fn weight_limit(&self, before: f64, total: f64) -> f64 {
+        let q = before / total;
+        let k = self.compression / (2.0 * PI) * (2.0 * q - 1.0).asin();
+        let next_q = ((2.0 * PI * (k + 1.0) / self.compression)
+            .min(PI / 2.0)
+            .sin()
+            + 1.0)
+            / 2.0;
+        next_q * total
+    }
+}
+
+/// The statistics of one field.  For a field without values, `min`, `max`, `mean` and `stddev` are NaN.
+#[derive(Debug, Clone)]
+pub struct FieldStats {
+    pub path: FieldPath,
+    pub count: u64,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    /// The sample standard deviation, as from pandas' `std()`.  NaN for fewer than 2 values.
+    pub stddev: f64,
+    pub sketch: QuantileSketch,
+    // The sum of squared differences from the mean, for Welford's algorithm.
+    m2: f64,
+}
+
+impl FieldStats {
+    fn new(path: FieldPath) -> Self {
+        FieldStats {
+            path,
+            count: 0,
+            min: f64::NAN,
+            max: f64::NAN,
+            mean: f64::NAN,
+            stddev: f64::NAN,
+            sketch: QuantileSketch::default(),
+            m2: 0.0,
+        }
+    }
+
+    fn insert(&mut self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+
+        if self.count == 0 {
+            (self.min, self.max, self.mean) = (value, value, 0.0);
+        }
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+        if self.count > 1 {
+            self.stddev = (self.m2 / (self.count - 1) as f64).sqrt();
+        }
+
+        self.sketch.insert(value);
+    }
+
+    /// Estimates the `q` quantile of the field's values.  See `QuantileSketch::quantile()`.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        self.sketch.quantile(q)
+    }
+}
+
+/// The statistics of a set of fields, in the order they were requested.
+#[derive(Debug, Clone, Default)]
+pub struct StatsReport {
+    pub fields: Vec<FieldStats>,
+}
+
+impl StatsReport {
+    /// Returns the statistics of the field with the path `path`, e.g. `vehicle_local_position/x`.
+    pub fn field(&self, path: &str) -> Option<&FieldStats> {
+        let path: FieldPath = path.parse().ok()?;
+        self.fields.iter().find(|stats| stats.path == path)
+    }
+
+    /// Serializes the report as a JSON array with an object per field.  Undefined statistics are `null`.
+    pub fn to_json(&self) -> String {
+        let fields: Vec<String> = self
+            .fields
+            .iter()
+            .map(|stats| {
+                let mut members = vec![
+                    format!(
+                        "\"field\":{}",
+                        crate::export::json_string(&stats.path.to_string())
+                    ),
+                    format!("\"count\":{}", stats.count),
+                ];
+                for (name, value) in Self::values(stats) {
+                    let value = if value.is_finite() {
+                        value.to_string()
+                    } else {
+                        "null".to_owned()
+                    };
+                    members.push(format!("\"{name}\":{value}"));
+                }
+                format!("{{{}}}", members.join(","))
+            })
+            .collect();
+
+        format!("[{}]", fields.join(","))
+    }
+
+    /// Writes the report as CSV, with a row per field.  Undefined statistics are empty.
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let quantiles: Vec<&str> = REPORTED_QUANTILES.iter().map(|&(name, _)| name).collect();
+        writeln!(
+            writer,
+            "field,count,min,max,mean,stddev,{}",
+            quantiles.join(",")
+        )?;
+
+        for stats in &self.fields {
+            let values: Vec<String> = Self::values(stats)
+                .map(|(_, value)| {
+                    if value.is_nan() {
+                        String::new()
+                    } else {
+                        value.to_string()
+                    }
+                })
+                .collect();
+            writeln!(
+                writer,
+                "{},{},{}",
+                stats.path,
+                stats.count,
+                values.join(",")
+            )?;
+        }
+
+        writer.flush()
+    }
+
+    fn values(stats: &FieldStats) -> impl Iterator<Item = (&'static str, f64)> + '_ {
+        let moments = [
+            ("min", stats.min),
+            ("max", stats.max),
+            ("mean", stats.mean),
+            ("stddev", stats.stddev),
+        ];
+        let quantiles = REPORTED_QUANTILES
+            .iter()
+            .map(|&(name, q)| (name, stats.quantile(q).unwrap_or(f64::NAN)));
+        moments.into_iter().chain(quantiles)
+    }
+}
+
+/// Reads a log and computes the count, min, max, mean, standard deviation and a quantile sketch of each field in
+/// `paths`, in one pass.  NaN values are skipped.
+///
+/// Paths are of the form `topic/column` or `topic.NN/column`, as for `timeseries::envelope()`.  A field of a
+/// topic which isn't logged has a count of 0.
+pub fn field_stats<I, S>(messages: I, paths: &[S]) -> Result<StatsReport, ULogError>
+where
+    I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+    S: AsRef<str>,
+{
+    let paths = paths
+        .iter()
+        .map(|path| path.as_ref().parse())
+        .collect::<Result<Vec<FieldPath>, _>>()?;
+    let mut fields: Vec<FieldStats> = paths.iter().cloned().map(FieldStats::new).collect();
+    let mut lookup = FieldLookup::new(paths);
+
+    for msg in messages {
+        if let UlogMessage::LoggedData(data) = msg? {
+            lookup.for_each(&data, |i, value| fields[i].insert(value))?;
+        }
+    }
+
+    Ok(StatsReport { fields })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::model::def::BaseType;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    #[test]
+    fn test_sketch_quantiles() {
+        let mut sketch = QuantileSketch::default();
+        assert_eq!(sketch.quantile(0.5), None);
+
+        // A shuffled 0..100_000, with each value once.
+        for i in 0..100_000u64 {
+            sketch.insert((i * 7919 % 100_000) as f64);
+        }
+        assert_eq!(sketch.count(), 100_000);
+        assert!(sketch.centroids.len() < 200);
+        assert_eq!(sketch.quantile(0.0), Some(0.0));
+        assert_eq!(sketch.quantile(1.0), Some(99_999.0));
+        for q in [0.001, 0.01, 0.25, 0.5, 0.75, 0.99, 0.999] {
+            let estimate = sketch.quantile(q).unwrap();
+            let error = (estimate - q * 100_000.0).abs() / 100_000.0;
+            assert!(error < 0.005, "q {q}: {estimate}");
+        }
+
+        let mut merged = QuantileSketch::default();
+        merged.merge(&sketch);
+        merged.merge(&sketch);
+        assert_eq!(merged.count(), 200_000);
+        assert!((merged.quantile(0.5).unwrap() - 50_000.0).abs() < 500.0);
+    }
+
+    #[test]
+    fn test_field_stats() -> Result<(), ULogError> {
+        let bytes = LogBuilder::new()
+            .topic(
+                Topic::new("a")
+                    .field(
+                        "x",
+                        BaseType::FLOAT,
+                        Generator::Ramp {
+                            start: 1.0,
+                            step: 1.0,
+                        },
+                    )
+                    .field("y", BaseType::INT16, Generator::Constant(-3.0)),
+            )
+            .build()?;
+        let parser = ULogParserBuilder::new(bytes.as_slice()).build()?;
+
+        let report = field_stats(parser, &["a/x", "a/y", "b/x"])?;
+        let x = report.field("a/x").unwrap();
+        assert_eq!((x.count, x.min, x.max, x.mean), (10, 1.0, 10.0, 5.5));
+        assert!((x.stddev - 3.027_650_354).abs() < 1e-9);
+        assert_eq!(x.quantile(0.5), Some(5.5));
+
+        let y = report.field("a/y").unwrap();
+        assert_eq!((y.count, y.mean, y.stddev), (10, -3.0, 0.0));
+        assert_eq!(report.field("b/x").unwrap().count, 0);
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json[0]["field"], "a/x");
+        assert_eq!(json[0]["p50"], 5.5);
+        assert!(json[2]["mean"].is_null());
+
+        let mut csv = Vec::new();
+        report.write_csv(&mut csv)?;
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "field,count,min,max,mean,stddev,p1,p5,p50,p95,p99"
+        );
+        assert_eq!(lines[3], "b/x,0,,,,,,,,,");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,286 @@
+//! Searches the strings logged by the firmware, and raises alarms on the messages PX4 logs when something fails.
+//!
+//! ```rust
+//! use yule_log::analysis::{self, AlarmPatterns};
+//! use yule_log::builder::ULogParserBuilder;
+//! use yule_log::model::msg::LogLevel;
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+//!
+//! let parser = ULogParserBuilder::new(input.as_slice()).build()?;
+//! for log in analysis::grep_messages(parser, "(?i)battery", LogLevel::Warning)? {
+//!     println!("{} {:?} {}", log.timestamp, log.level, log.msg);
+//! }
+//!
+//! let parser = ULogParserBuilder::new(input.as_slice()).build()?;
+//! for alarm in analysis::alarms(parser, &AlarmPatterns::px4())? {
+//!     println!("{} {:?}: {}", alarm.timestamp, alarm.kind, alarm.msg);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use regex::Regex;
+
+use crate::errors::ULogError;
+use crate::model::msg::{LogLevel, LoggedString, UlogMessage};
+
+/// Returns the strings logged at `level_at_least` or a more severe level which match `pattern`, tagged or not, in
+/// the order logged.
+///
+/// Returns `ULogError::InvalidConfiguration` if `pattern` isn't a valid regex.
+pub fn grep_messages<I>(
+    messages: I,
+    pattern: &str,
+    level_at_least: LogLevel,
+) -> Result<Vec<LoggedString>, ULogError>
+where
+    I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+{
+    let regex = compile(pattern)?;
+    let mut found = Vec::new();
+    for msg in messages {
+        if let UlogMessage::LoggedString(log) | UlogMessage::TaggedLoggedString(log) = msg? {
+            if log.level.is_at_least(level_at_least) && regex.is_match(&log.msg) {
+                found.push(log);
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// What an alarm is about.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AlarmKind {
+    /// A fault or inconsistency of the state estimator, e.g. EKF2.
+    Estimator,
+    /// A failsafe action was triggered.
+    Failsafe,
+    Battery,
+    /// GPS fix or signal lost, or jamming detected.
+    Gps,
+    /// A sensor missing, timed out, or inconsistent with its redundant sensors.
+    Sensor,
+    /// A failed preflight check, or arming denied.
+    Preflight,
+    Geofence,
+    /// A lost RC, manual control or data link.
+    LinkLoss,
+    /// A kind added with `AlarmPatterns::with_pattern()`.
+    Custom(String),
+}
+
+/// A logged string matching an alarm pattern.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alarm {
+    pub kind: AlarmKind,
+    pub timestamp: u64,
+    pub level: LogLevel,
+    pub msg: String,
+}
+
+/// The patterns `alarms()` matches logged strings against, each with the kind of alarm it raises.  The first pattern
+/// a string matches decides its kind.
+#[derive(Debug, Clone)]
+pub struct AlarmPatterns {
+    patterns: Vec<(AlarmKind, Regex)>,
+    level_at_least: LogLevel,
+}
+
+impl AlarmPatterns {
+    /// No patterns, to be added with `with_pattern()`.
+    pub fn new() -> Self {
+        AlarmPatterns {
+            patterns: Vec::new(),
+            level_at_least: LogLevel::Debug,
+        }
+    }
+
+    /// Patterns for the failures PX4 reports, matched in strings logged at `Warning` or a more severe level.
+    pub fn px4() -> Self {
+        const PX4_PATTERNS: &[(AlarmKind, &str)] = &[
+            (
+                AlarmKind::Estimator,
+                r"(?i)\bekf\d?\b|estimator|height estimate|yaw estimate|heading estimate|position estimate",
+            ),
+            (AlarmKind::Failsafe, r"(?i)failsafe"),
+            (AlarmKind::Battery, r"(?i)battery|low voltage"),
+            (
+                AlarmKind::Gps,
+                r"(?i)\bgps\b.*(lost|jamming|spoof)|(fix|signal) lost",
+            ),
+            (
+                AlarmKind::Sensor,
+                r"(?i)(accel|gyro|compass|mag|baro|airspeed|sensor)\w*\b.*(missing|timeout|fail|inconsistent|not found)",
+            ),
+            (AlarmKind::Preflight, r"(?i)preflight fail|arming denied"),
+            (AlarmKind::Geofence, r"(?i)geofence"),
+            (
+                AlarmKind::LinkLoss,
+                r"(?i)(rc|manual control|data ?link|telemetry)\b.*\blost|no rc",
+            ),
+        ];
+
+        let patterns = PX4_PATTERNS
+            .iter()
+            .map(|(kind, pattern)| {
+                (
+                    kind.clone(),
+                    Regex::new(pattern).expect("valid built-in pattern"),
+                )
+            })
+            .collect();
+        AlarmPatterns {
+            patterns,
+            level_at_least: LogLevel::Warning,
+        }
+    }
+
+    /// Adds a pattern raising alarms of `kind`, after the patterns already added.
+    ///
+    /// Returns `ULogError::InvalidConfiguration` if `pattern` isn't a valid regex.
+    pub fn with_pattern(mut self, kind: AlarmKind, pattern: &str) -> Result<Self, ULogError> {
+        self.patterns.push((kind, compile(pattern)?));
+        Ok(self)
+    }
+
+    /// Only matches strings logged at `level` or a more severe level.
+    #[must_use]
+    pub fn level_at_least(mut self, level: LogLevel) -> Self {
+        self.level_at_least = level;
+        self
+    }
+
+    /// The kind of alarm `log` raises, if any.
+    pub fn classify(&self, log: &LoggedString) -> Option<&AlarmKind> {
+        if !log.level.is_at_least(self.level_at_least) {
+            return None;
+        }
+        self.patterns
+            .iter()
+            .find(|(_, regex)| regex.is_match(&log.msg))
+            .map(|(kind, _)| kind)
+    }
+}
+
+impl Default for AlarmPatterns {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns an alarm for each logged string which matches one of `patterns`, in the order logged.
+pub fn alarms<I>(messages: I, patterns: &AlarmPatterns) -> Result<Vec<Alarm>, ULogError>
+where
+    I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+{
+    let mut alarms = Vec::new();
+    for msg in messages {
+        if let UlogMessage::LoggedString(log) | UlogMessage::TaggedLoggedString(log) = msg? {
+            if let Some(kind) = patterns.classify(&log) {
+                alarms.push(Alarm {
+                    kind: kind.clone(),
+                    timestamp: log.timestamp,
+                    level: log.level,
+                    msg: log.msg,
+                });
+            }
+        }
+    }
+    Ok(alarms)
+}
+
+fn compile(pattern: &str) -> Result<Regex, ULogError> {
+    Regex::new(pattern)
+        .map_err(|e| ULogError::InvalidConfiguration(format!("Invalid pattern `{pattern}`: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::model::def::BaseType;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    fn log() -> Result<Vec<u8>, ULogError> {
+        // Strings are only logged in the data section, which starts with the first subscription.
+        LogBuilder::new()
+            .duration(Duration::from_secs(10))
+            .topic(Topic::new("a").rate_hz(1.0).field(
+                "x",
+                BaseType::UINT8,
+                Generator::Constant(0.0),
+            ))
+            .log_at(Duration::from_secs(1), LogLevel::Info, "Takeoff detected")
+            .log_at(
+                Duration::from_secs(2),
+                LogLevel::Warning,
+                "Low battery level! Return advised",
+            )
+            .log_at(
+                Duration::from_secs(3),
+                LogLevel::Err,
+                "EKF2 IMU0 yaw estimate error",
+            )
+            .log_at(
+                Duration::from_secs(4),
+                LogLevel::Crit,
+                "Failsafe activated: RC lost",
+            )
+            .log_at(
+                Duration::from_secs(5),
+                LogLevel::Info,
+                "battery status published",
+            )
+            .build()
+    }
+
+    #[test]
+    fn test_grep_messages() -> Result<(), ULogError> {
+        let bytes = log()?;
+        let parser = || ULogParserBuilder::new(bytes.as_slice()).build();
+
+        let found = grep_messages(parser()?, "(?i)battery", LogLevel::Debug)?;
+        assert_eq!(found.len(), 2);
+        let found = grep_messages(parser()?, "(?i)battery", LogLevel::Warning)?;
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].msg, "Low battery level! Return advised");
+        assert!(found[0].timestamp > 0);
+
+        assert!(matches!(
+            grep_messages(parser()?, "(", LogLevel::Debug),
+            Err(ULogError::InvalidConfiguration(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_alarms() -> Result<(), ULogError> {
+        let bytes = log()?;
+        let parser = || ULogParserBuilder::new(bytes.as_slice()).build();
+
+        let kinds: Vec<AlarmKind> = alarms(parser()?, &AlarmPatterns::px4())?
+            .into_iter()
+            .map(|alarm| alarm.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                AlarmKind::Battery,
+                AlarmKind::Estimator,
+                AlarmKind::Failsafe
+            ]
+        );
+
+        let patterns = AlarmPatterns::new()
+            .with_pattern(AlarmKind::Custom("takeoff".to_owned()), "Takeoff")?;
+        let found = alarms(parser()?, &patterns)?;
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].kind, AlarmKind::Custom("takeoff".to_owned()));
+        assert_eq!(found[0].level, LogLevel::Info);
+        Ok(())
+    }
+}
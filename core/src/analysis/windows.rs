@@ -0,0 +1,189 @@
+//! Extracts the data logged around events, e.g. the actuator outputs from 2 s before to 2 s after each failsafe.
+//!
+//! The log is indexed, so each window is read by seeking to its start rather than by reading the log from the
+//! start, and reading stops as soon as the window's last message has been read.
+//!
+//! ```rust
+//! use std::fs::File;
+//! use std::io::BufReader;
+//!
+//! use yule_log::analysis;
+//! use yule_log::index::ULogIndexedReader;
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let log = BufReader::new(File::open("test_data/input/sample_log_small.ulg")?);
+//! let mut reader = ULogIndexedReader::open(log)?;
+//!
+//! # let failsafes = [reader.index().topics()[0].first_timestamp().unwrap_or(0)];
+//! let windows = analysis::windows_around(&mut reader, &failsafes, 2_000_000, 2_000_000, &["actuator_outputs"])?;
+//! for window in &windows {
+//!     println!("{}: {} messages", window.event, window.messages.len());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io::{Read, Seek};
+
+use crate::errors::ULogError;
+use crate::index::{TopicIndex, ULogIndexedReader};
+use crate::model::msg::{LoggedData, UlogMessage};
+
+/// The messages of the selected topics logged around an event.
+#[derive(Debug, Clone)]
+pub struct EventWindow {
+    /// The timestamp of the event.
+    pub event: u64,
+    /// The first timestamp of the window.
+    pub start: u64,
+    /// The last timestamp of the window, inclusive.
+    pub end: u64,
+    /// The messages logged from `start` to `end`, in the order logged.
+    pub messages: Vec<LoggedData>,
+}
+
+/// Returns, for each of `events`, the messages of `topics` logged from `pre_us` before to `post_us` after it.
+///
+/// All instances of each topic are included.  Windows may overlap, in which case their messages are in each
+/// window.
+pub fn windows_around<R, S>(
+    reader: &mut ULogIndexedReader<R>,
+    events: &[u64],
+    pre_us: u64,
+    post_us: u64,
+    topics: &[S],
+) -> Result<Vec<EventWindow>, ULogError>
+where
+    R: Read + Seek,
+    S: AsRef<str>,
+{
+    let topics: Vec<&str> = topics.iter().map(AsRef::as_ref).collect();
+    let indexed: Vec<TopicIndex> = reader
+        .index()
+        .topics()
+        .iter()
+        .filter(|topic| topics.contains(&topic.name.as_str()))
+        .cloned()
+        .collect();
+
+    let mut windows = Vec::with_capacity(events.len());
+    for &event in events {
+        let start = event.saturating_sub(pre_us);
+        let end = event.saturating_add(post_us);
+        let mut window = EventWindow {
+            event,
+            start,
+            end,
+            messages: Vec::new(),
+        };
+
+        // The number of messages still to be read from each topic instance.
+        let mut remaining: Vec<usize> = indexed
+            .iter()
+            .map(|topic| topic.count_between(start, end))
+            .collect();
+        if remaining.iter().all(|&n| n == 0) {
+            windows.push(window);
+            continue;
+        }
+
+        let parser = reader.parser_at_with(start, |builder| {
+            builder.set_subscription_allow_list(topics.iter().copied())
+        })?;
+        for msg in parser {
+            let UlogMessage::LoggedData(data) = msg? else {
+                continue;
+            };
+            if data.timestamp < start || data.timestamp > end {
+                continue;
+            }
+
+            let multi_id = data.data.multi_id_index.unwrap_or(0);
+            let Some(i) = indexed
+                .iter()
+                .position(|topic| topic.multi_id == multi_id && topic.name == *data.data.name)
+            else {
+                continue;
+            };
+            if remaining[i] == 0 {
+                continue;
+            }
+
+            remaining[i] -= 1;
+            window.messages.push(data);
+            if remaining.iter().all(|&n| n == 0) {
+                break;
+            }
+        }
+
+        windows.push(window);
+    }
+
+    Ok(windows)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::model::def::BaseType;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    #[test]
+    fn test_windows_around() -> Result<(), ULogError> {
+        let topic = |name: &str, multi_id: u8| {
+            Topic::new(name).multi_id(multi_id).rate_hz(100.0).field(
+                "x",
+                BaseType::FLOAT,
+                Generator::Ramp {
+                    start: 0.0,
+                    step: 1.0,
+                },
+            )
+        };
+        let bytes = LogBuilder::new()
+            .topic(topic("actuator_outputs", 0))
+            .topic(topic("actuator_outputs", 1))
+            .topic(topic("other", 0))
+            .build()?;
+        let mut reader = ULogIndexedReader::open(Cursor::new(bytes))?;
+
+        let windows = windows_around(
+            &mut reader,
+            &[1_500_000, 1_200_000, 5_000_000],
+            100_000,
+            50_000,
+            &["actuator_outputs"],
+        )?;
+        assert_eq!(windows.len(), 3);
+
+        // 1.4 s to 1.55 s at 100 Hz, for each of 2 instances.
+        let window = &windows[0];
+        assert_eq!(
+            (window.event, window.start, window.end),
+            (1_500_000, 1_400_000, 1_550_000)
+        );
+        assert_eq!(window.messages.len(), 2 * 16);
+        assert!(window
+            .messages
+            .iter()
+            .all(|data| *data.data.name == *"actuator_outputs"
+                && (1_400_000..=1_550_000).contains(&data.timestamp)));
+
+        // Windows needn't be in order.
+        assert_eq!(windows[1].messages.len(), 2 * 16);
+        assert_eq!(windows[1].messages[0].timestamp, 1_100_000);
+
+        // After the end of the log.
+        assert!(windows[2].messages.is_empty());
+
+        assert!(
+            windows_around(&mut reader, &[1_500_000], 0, 0, &["missing"])?[0]
+                .messages
+                .is_empty()
+        );
+
+        Ok(())
+    }
+}
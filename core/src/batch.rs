@@ -0,0 +1,419 @@
+//! Processes the ULOG files of a directory in parallel, e.g. to index or export a fleet's logs.
+//!
+//! ```rust,no_run
+//! use yule_log::batch::{process_dir, BatchOptions};
+//! use yule_log::model::msg::UlogMessage;
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let options = BatchOptions::new().threads(4).state_file("logs/.processed");
+//!
+//! let report = process_dir("logs", &options, |_path, parser| {
+//!     let mut count = 0;
+//!     for msg in parser {
+//!         if let UlogMessage::LoggedData(_) = msg? {
+//!             count += 1;
+//!         }
+//!     }
+//!     Ok(count)
+//! })?;
+//!
+//! for (path, error) in &report.errors {
+//!     eprintln!("{}: {error}", path.display());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::builder::ULogParserBuilder;
+use crate::cancel::CancellationToken;
+use crate::diag;
+use crate::errors::ULogError;
+use crate::export::Provenance;
+use crate::parser::ULogParser;
+
+pub(crate) type Configure =
+    dyn Fn(ULogParserBuilder<BufReader<File>>) -> ULogParserBuilder<BufReader<File>> + Sync;
+
+/// Options for `process_dir()`.
+pub struct BatchOptions {
+    threads: usize,
+    recursive: bool,
+    state_file: Option<PathBuf>,
+    configure: Box<Configure>,
+    cancellation: Option<CancellationToken>,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        BatchOptions {
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            recursive: true,
+            state_file: None,
+            configure: Box::new(|builder| builder),
+            cancellation: None,
+        }
+    }
+}
+
+impl BatchOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of files processed at once.  Defaults to the available parallelism.
+    #[must_use]
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Whether to look for files in subdirectories.  Defaults to `true`.
+    #[must_use]
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    /// Records each file processed successfully in `path`, and skips the files already recorded there,
+    /// so that an interrupted batch can be resumed.  Files which failed are processed again.
+    #[must_use]
+    pub fn state_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.state_file = Some(path.into());
+        self
+    }
+
+    /// Configures the parser of each file, e.g. to set a subscription allow list.
+    #[must_use]
+    pub fn configure(
+        mut self,
+        configure: impl Fn(ULogParserBuilder<BufReader<File>>) -> ULogParserBuilder<BufReader<File>>
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.configure = Box::new(configure);
+        self
+    }
+
+    /// Cancels the batch once `token` is cancelled.  The files being parsed stop before their next message, and
+    /// those not started yet aren't opened.  Each is recorded in the report as a `ULogError::Cancelled` error, and not
+    /// in the state file, so a resumed batch processes them again.
+    #[must_use]
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+}
+
+/// The outcome of `process_dir()`.  Each list is ordered by path.
+#[derive(Debug)]
+pub struct BatchReport<T> {
+    pub results: Vec<(PathBuf, T)>,
+    pub errors: Vec<(PathBuf, ULogError)>,
+    /// The files skipped because the state file records them as processed.
+    pub skipped: Vec<PathBuf>,
+}
+
+/// Finds the `.ulg` files in `dir`, and calls `process` with a parser for each, on a pool of threads.
+///
+/// An error returned by `process`, or raised while opening a file, is recorded in the report, and the other
+/// files are still processed.  Errors reading the directory or the state file are returned.
+pub fn process_dir<T, F>(
+    dir: impl AsRef<Path>,
+    options: &BatchOptions,
+    process: F,
+) -> Result<BatchReport<T>, ULogError>
+where
+    T: Send,
+    F: Fn(&Path, ULogParser<BufReader<File>>) -> Result<T, ULogError> + Sync,
+{
+    let mut files = Vec::new();
+    find_files(dir.as_ref(), options.recursive, &mut files)?;
+    files.sort();
+
+    let processed = match &options.state_file {
+        Some(path) => read_state(path)?,
+        None => HashSet::new(),
+    };
+    let (skipped, files): (Vec<_>, Vec<_>) = files.into_iter().partition(|f| processed.contains(f));
+
+    let state = match &options.state_file {
+        Some(path) => Some(Mutex::new(
+            OpenOptions::new().create(true).append(true).open(path)?,
+        )),
+        None => None,
+    };
+
+    let cancellation = options.cancellation.as_ref();
+    let configure = |builder| {
+        let builder = (options.configure)(builder);
+        match cancellation {
+            Some(token) => builder.cancellation(token.clone()),
+            None => builder,
+        }
+    };
+
+    let outcomes = run_parallel(&files, options.threads, |path| {
+        let outcome = if cancellation.is_some_and(CancellationToken::is_cancelled) {
+            Err(ULogError::Cancelled)
+        } else {
+            process_file(path, &configure, &process)
+        };
+
+        if let (Ok(_), Some(state)) = (&outcome, &state) {
+            let mut state = state.lock().unwrap();
+            if let Err(e) = writeln!(state, "{}", path.display()) {
+                diag::warn!("Failed to record {} in the state file: {e}", path.display());
+            }
+        }
+        outcome
+    });
+
+    let mut report = BatchReport {
+        results: Vec::new(),
+        errors: Vec::new(),
+        skipped,
+    };
+    for (path, outcome) in outcomes {
+        match outcome {
+            Ok(result) => report.results.push((path, result)),
+            Err(e) => report.errors.push((path, e)),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Like `process_dir()`, also passing `process` the `Provenance::from_file()` of each file, e.g. for the exporters
+/// which write it in every row.  The provenance is read before the file is parsed, which reads the file twice.
+pub fn process_dir_with_provenance<T, F>(
+    dir: impl AsRef<Path>,
+    options: &BatchOptions,
+    process: F,
+) -> Result<BatchReport<T>, ULogError>
+where
+    T: Send,
+    F: Fn(&Path, &Provenance, ULogParser<BufReader<File>>) -> Result<T, ULogError> + Sync,
+{
+    process_dir(dir, options, |path, parser| {
+        let provenance = Provenance::from_file(path)?;
+        process(path, &provenance, parser)
+    })
+}
+
+/// Calls `f` for each of `files` on up to `threads` threads, and returns the outcomes ordered by path.
+pub(crate) fn run_parallel<T, F>(files: &[PathBuf], threads: usize, f: F) -> Vec<(PathBuf, T)>
+where
+    T: Send,
+    F: Fn(&Path) -> T + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let outcomes = Mutex::new(Vec::with_capacity(files.len()));
+
+    thread::scope(|scope| {
+        for _ in 0..threads.min(files.len()) {
+            scope.spawn(|| {
+                while let Some(path) = files.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let outcome = f(path);
+                    outcomes.lock().unwrap().push((path.clone(), outcome));
+                }
+            });
+        }
+    });
+
+    let mut outcomes = outcomes.into_inner().unwrap();
+    outcomes.sort_by(|(a, _), (b, _)| a.cmp(b));
+    outcomes
+}
+
+pub(crate) fn process_file<T, C, F>(path: &Path, configure: &C, process: &F) -> Result<T, ULogError>
+where
+    C: Fn(ULogParserBuilder<BufReader<File>>) -> ULogParserBuilder<BufReader<File>> + ?Sized,
+    F: Fn(&Path, ULogParser<BufReader<File>>) -> Result<T, ULogError>,
+{
+    let reader = BufReader::new(File::open(path)?);
+    let parser = configure(ULogParserBuilder::new(reader)).build()?;
+    process(path, parser)
+}
+
+pub(crate) fn find_files(
+    dir: &Path,
+    recursive: bool,
+    files: &mut Vec<PathBuf>,
+) -> Result<(), ULogError> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            if recursive {
+                find_files(&path, recursive, files)?;
+            }
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("ulg"))
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn read_state(path: &Path) -> Result<HashSet<PathBuf>, ULogError> {
+    match File::open(path) {
+        Ok(file) => BufReader::new(file)
+            .lines()
+            .map(|line| Ok(PathBuf::from(line?)))
+            .collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashSet::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::def::BaseType;
+    use crate::model::msg::UlogMessage;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    fn count_logged_data(
+        _path: &Path,
+        parser: ULogParser<BufReader<File>>,
+    ) -> Result<usize, ULogError> {
+        let mut count = 0;
+        for msg in parser {
+            if let UlogMessage::LoggedData(_) = msg? {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    fn fixture(dir: &Path) -> Result<(), ULogError> {
+        let log = |rate_hz| {
+            LogBuilder::new()
+                .topic(Topic::new("a").rate_hz(rate_hz).field(
+                    "x",
+                    BaseType::UINT8,
+                    Generator::Constant(1.0),
+                ))
+                .build()
+        };
+
+        fs::create_dir(dir.join("nested"))?;
+        fs::write(dir.join("a.ulg"), log(10.0)?)?;
+        fs::write(dir.join("b.ULG"), log(20.0)?)?;
+        fs::write(dir.join("nested/c.ulg"), log(5.0)?)?;
+        fs::write(dir.join("corrupt.ulg"), b"not a ULOG file")?;
+        fs::write(dir.join("notes.txt"), b"ignored")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_dir() -> Result<(), ULogError> {
+        let dir = tempfile::tempdir()?;
+        fixture(dir.path())?;
+
+        let report = process_dir(
+            dir.path(),
+            &BatchOptions::new().threads(3),
+            count_logged_data,
+        )?;
+        let results: Vec<_> = report
+            .results
+            .iter()
+            .map(|(path, count)| (path.strip_prefix(dir.path()).unwrap().to_path_buf(), *count))
+            .collect();
+        assert_eq!(
+            results,
+            [
+                (PathBuf::from("a.ulg"), 10),
+                (PathBuf::from("b.ULG"), 20),
+                (PathBuf::from("nested/c.ulg"), 5)
+            ]
+        );
+        assert_eq!(report.errors.len(), 1);
+        assert!(report.errors[0].0.ends_with("corrupt.ulg"));
+
+        let report = process_dir(
+            dir.path(),
+            &BatchOptions::new().recursive(false),
+            count_logged_data,
+        )?;
+        assert_eq!(report.results.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resume() -> Result<(), ULogError> {
+        let dir = tempfile::tempdir()?;
+        let logs = dir.path().join("logs");
+        fs::create_dir(&logs)?;
+        fixture(&logs)?;
+
+        let options = BatchOptions::new().state_file(dir.path().join("state"));
+        let report = process_dir(&logs, &options, count_logged_data)?;
+        assert_eq!(report.results.len(), 3);
+        assert!(report.skipped.is_empty());
+
+        // The processed files are skipped, and the failed file is retried.
+        fs::write(logs.join("d.ulg"), b"")?;
+        let report = process_dir(&logs, &options, count_logged_data)?;
+        assert!(report.results.is_empty());
+        assert_eq!(report.skipped.len(), 3);
+        assert_eq!(report.errors.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_dir_with_provenance() -> Result<(), ULogError> {
+        let dir = tempfile::tempdir()?;
+        fixture(dir.path())?;
+
+        let report = process_dir_with_provenance(
+            dir.path(),
+            &BatchOptions::new().recursive(false),
+            |_path, provenance, _parser| Ok(provenance.clone()),
+        )?;
+        let names: Vec<_> = report
+            .results
+            .iter()
+            .map(|(_, provenance)| provenance.file_name.as_deref().unwrap())
+            .collect();
+        assert_eq!(names, ["a.ulg", "b.ULG"]);
+        assert_ne!(report.results[0].1.file_hash, report.results[1].1.file_hash);
+        assert_eq!(report.errors.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cancellation() -> Result<(), ULogError> {
+        let dir = tempfile::tempdir()?;
+        fixture(dir.path())?;
+
+        // The first file cancels the batch part way through.
+        let token = CancellationToken::new();
+        let options = BatchOptions::new().threads(1).cancellation(token.clone());
+        let report = process_dir(dir.path(), &options, |path, parser| {
+            token.cancel();
+            count_logged_data(path, parser)
+        })?;
+        assert!(report.results.is_empty());
+        assert_eq!(report.errors.len(), 4);
+        assert!(report
+            .errors
+            .iter()
+            .all(|(_, e)| matches!(e, ULogError::Cancelled)));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,232 @@
+//! Names the bits of integer fields which hold flags, e.g. the failsafe flags of `vehicle_status`, so that the
+//! flags which are set can be listed by name.
+//!
+//! ```rust
+//! use yule_log::bitfield::BitFields;
+//! use yule_log::builder::ULogParserBuilder;
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let bit_fields = BitFields::new().field(
+//!     "vehicle_status/failure_detector_status",
+//!     &[(0, "roll"), (1, "pitch"), (2, "alt"), (3, "ext"), (4, "arm_esc")],
+//! )?;
+//!
+//! let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+//! for data in ULogParserBuilder::new(input.as_slice()).build()?.data_only() {
+//!     let data = data?;
+//!     if let Some(failures) = bit_fields.active(&data.data, "failure_detector_status") {
+//!         println!("{} {failures:?}", data.timestamp);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! `NdjsonExporter::bit_fields()` and `CsvExporter::bit_fields()` add the names of the flags set to the exported
+//! data.
+
+use crate::errors::ULogError;
+use crate::export::{column_names, for_each_scalar};
+use crate::model::inst::{self, FieldValue};
+use crate::timeseries::FieldPath;
+
+/// The names of the bits of a set of fields.  See the module documentation.
+#[derive(Debug, Clone, Default)]
+pub struct BitFields {
+    fields: Vec<(FieldPath, Vec<(u8, &'static str)>)>,
+}
+
+impl BitFields {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Names the bits of the integer field at `path`, e.g. `vehicle_status/failsafe_flags`, with bit 0 the least
+    /// significant.  Bits without a name are not listed.
+    ///
+    /// Returns `ULogError::InvalidFieldName` if `path` isn't a field path, or a bit is beyond 63.
+    pub fn field(mut self, path: &str, bits: &[(u8, &'static str)]) -> Result<Self, ULogError> {
+        let path: FieldPath = path.parse()?;
+        if let Some((bit, name)) = bits.iter().find(|(bit, _)| *bit >= 64) {
+            return Err(ULogError::InvalidFieldName(format!(
+                "Bit {bit} `{name}` of `{path}` is beyond the 64 bits of an integer field."
+            )));
+        }
+        let mut bits = bits.to_vec();
+        bits.sort_by_key(|&(bit, _)| bit);
+        self.fields.retain(|(p, _)| *p != path);
+        self.fields.push((path, bits));
+        Ok(self)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// The names of the bits set in `value`, if `path` is one of the fields, in order of bit.  `value` can be the
+    /// unsigned field of a `#[derive(ULogData)]` struct as it is.
+    pub fn decode(&self, path: &FieldPath, value: impl Into<u64>) -> Option<Vec<&'static str>> {
+        let (_, bits) = self.fields.iter().find(|(p, _)| p == path)?;
+        let value = value.into();
+        Some(
+            bits.iter()
+                .filter(|&&(bit, _)| value & (1 << bit) != 0)
+                .map(|&(_, name)| name)
+                .collect(),
+        )
+    }
+
+    /// The names of the bits set in `column` of `data`, if the column is one of the fields and holds an integer.
+    pub fn active(&self, data: &inst::Format, column: &str) -> Option<Vec<&'static str>> {
+        let path = FieldPath::new(&*data.name, data.multi_id_index.unwrap_or(0), column);
+        let (_, bits) = self.fields.iter().find(|(p, _)| *p == path)?;
+        let position = column_names(data)
+            .iter()
+            .position(|(name, _)| name == column)?;
+
+        let mut index = 0;
+        let mut value = None;
+        for_each_scalar(data, &mut |scalar| {
+            if index == position {
+                value = as_bits(scalar);
+            }
+            index += 1;
+        });
+
+        let value = value?;
+        Some(
+            bits.iter()
+                .filter(|&&(bit, _)| value & (1 << bit) != 0)
+                .map(|&(_, name)| name)
+                .collect(),
+        )
+    }
+
+    /// The columns of the topic instance of `data` which are fields, in the order they were named.
+    pub fn columns<'a>(&'a self, data: &'a inst::Format) -> impl Iterator<Item = &'a str> + 'a {
+        let multi_id = data.multi_id_index.unwrap_or(0);
+        self.fields
+            .iter()
+            .filter(move |(path, _)| path.topic == *data.name && path.multi_id == multi_id)
+            .map(|(path, _)| path.column.as_str())
+    }
+}
+
+// The bits of an integer or boolean.  Signed integers are taken as their two's complement.
+fn as_bits(value: &FieldValue) -> Option<u64> {
+    match *value {
+        FieldValue::ScalarU8(v) => Some(u64::from(v)),
+        FieldValue::ScalarU16(v) => Some(u64::from(v)),
+        FieldValue::ScalarU32(v) => Some(u64::from(v)),
+        FieldValue::ScalarU64(v) => Some(v),
+        FieldValue::ScalarI8(v) => Some(u64::from(v as u8)),
+        FieldValue::ScalarI16(v) => Some(u64::from(v as u16)),
+        FieldValue::ScalarI32(v) => Some(u64::from(v as u32)),
+        FieldValue::ScalarI64(v) => Some(v as u64),
+        FieldValue::ScalarBool(v) => Some(u64::from(v)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::model::def::BaseType;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    #[test]
+    fn test_bit_fields() -> Result<(), ULogError> {
+        let bit_fields = BitFields::new().field(
+            "vehicle_status/failsafe_flags",
+            &[(3, "geofence"), (0, "gps_loss"), (7, "battery")],
+        )?;
+        let bytes = LogBuilder::new()
+            .topic(
+                Topic::new("vehicle_status")
+                    .field(
+                        "failsafe_flags",
+                        BaseType::UINT8,
+                        Generator::Constant(0b1000_1001 as f64),
+                    )
+                    .field("nav_state", BaseType::UINT8, Generator::Constant(1.0)),
+            )
+            .build()?;
+        let data = ULogParserBuilder::new(bytes.as_slice())
+            .build()?
+            .data_only()
+            .next()
+            .unwrap()?;
+
+        assert_eq!(
+            bit_fields.active(&data.data, "failsafe_flags"),
+            Some(vec!["gps_loss", "geofence", "battery"])
+        );
+        assert_eq!(bit_fields.active(&data.data, "nav_state"), None);
+        assert_eq!(
+            bit_fields.columns(&data.data).collect::<Vec<_>>(),
+            ["failsafe_flags"]
+        );
+
+        let path = "vehicle_status/failsafe_flags".parse()?;
+        assert_eq!(bit_fields.decode(&path, 0b1000u16), Some(vec!["geofence"]));
+        assert_eq!(
+            bit_fields.decode(&"vehicle_status.01/failsafe_flags".parse()?, 1u8),
+            None
+        );
+
+        assert!(BitFields::new().field("vehicle_status", &[]).is_err());
+        assert!(BitFields::new()
+            .field("vehicle_status/failsafe_flags", &[(64, "x")])
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_exported_flags() -> Result<(), ULogError> {
+        use crate::export::csv::CsvExporter;
+        use crate::export::ndjson::NdjsonExporter;
+
+        let bit_fields = || {
+            BitFields::new().field(
+                "vehicle_status/failsafe_flags",
+                &[(0, "gps_loss"), (1, "rc_loss")],
+            )
+        };
+        let bytes = LogBuilder::new()
+            .topic(Topic::new("vehicle_status").field(
+                "failsafe_flags",
+                BaseType::UINT16,
+                Generator::Ramp {
+                    start: 0.0,
+                    step: 1.0,
+                },
+            ))
+            .build()?;
+        let parser = || ULogParserBuilder::new(bytes.as_slice()).build();
+
+        let mut json = Vec::new();
+        NdjsonExporter::new().bit_fields(bit_fields()?).export(
+            parser()?
+                .filter(|msg| msg.as_ref().is_ok_and(|msg| msg.is_data()))
+                .take(4),
+            &mut json,
+        )?;
+        let lines: Vec<&str> = std::str::from_utf8(&json).unwrap().lines().collect();
+        assert!(lines[0].ends_with(r#""flags":{"failsafe_flags":[]}}"#));
+        assert!(lines[3].ends_with(r#""flags":{"failsafe_flags":["gps_loss","rc_loss"]}}"#));
+
+        let dir = tempfile::tempdir()?;
+        CsvExporter::new(dir.path())
+            .bit_fields(bit_fields()?)
+            .export(parser()?)?;
+        let csv = std::fs::read_to_string(dir.path().join("vehicle_status_0.csv"))?;
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "timestamp,failsafe_flags,failsafe_flags_flags");
+        assert_eq!(lines[3], "1200000,2,rc_loss");
+        assert_eq!(lines[4], "1300000,3,gps_loss|rc_loss");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+//! Parses a log on a worker thread, and delivers its messages over a bounded channel, e.g. to the UI thread of a GUI.
+//!
+//! Requires the `thread_safe` feature, so that messages can be sent between threads.
+//!
+//! The channel holds at most `capacity` messages, so a slow consumer holds the parser back rather than the messages
+//! piling up in memory.  The worker stops at the end of the log, or as soon as the receiver is dropped, and returns
+//! the parser, e.g. for its `validation_report()`:
+//!
+//! ```rust
+//! use yule_log::bridge;
+//! use yule_log::builder::ULogParserBuilder;
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let file = std::fs::File::open("test_data/input/sample_log_small.ulg")?;
+//! let parser = ULogParserBuilder::new(std::io::BufReader::new(file)).build()?;
+//!
+//! let (worker, messages) = bridge::spawn_reader(parser, 256);
+//! for msg in messages.iter().take(10) {
+//!     println!("{:?}", msg?);
+//! }
+//! // Dropping the receiver stops the worker.
+//! drop(messages);
+//! let parser = worker.join().expect("the worker doesn't panic");
+//! println!("{:?}", parser.validation_report());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io::Read;
+use std::sync::mpsc::{self, Receiver};
+use std::thread::{self, JoinHandle};
+
+use crate::errors::ULogError;
+use crate::model::msg::UlogMessage;
+use crate::parser::ULogParser;
+
+/// Spawns a thread which parses the log, and sends each message, or error, to the receiver returned, holding at most
+/// `capacity` unreceived messages.  A `capacity` of 0 hands each message over only once it is received.
+///
+/// The thread ends when the parser is exhausted or the receiver is dropped, and returns the parser.  Errors are sent
+/// on as the parser returns them, so the parser carries on after those it can recover from, e.g. with
+/// `resync_on_error(true)`.
+pub fn spawn_reader<R>(
+    mut parser: ULogParser<R>,
+    capacity: usize,
+) -> (
+    JoinHandle<ULogParser<R>>,
+    Receiver<Result<UlogMessage, ULogError>>,
+)
+where
+    R: Read + Send + 'static,
+{
+    let (sender, receiver) = mpsc::sync_channel(capacity);
+    let worker = thread::spawn(move || {
+        for msg in parser.by_ref() {
+            if sender.send(msg).is_err() {
+                // The receiver was dropped, so no one is waiting for the rest.
+                break;
+            }
+        }
+        parser
+    });
+    (worker, receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::parser::State;
+
+    fn parser() -> Result<ULogParser<std::io::Cursor<Vec<u8>>>, ULogError> {
+        let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+        ULogParserBuilder::new(std::io::Cursor::new(input)).build()
+    }
+
+    #[test]
+    fn test_spawn_reader() -> Result<(), ULogError> {
+        let expected: Vec<String> = parser()?
+            .map(|msg| msg.map(|msg| format!("{msg:?}")))
+            .collect::<Result<_, _>>()?;
+
+        let (worker, messages) = spawn_reader(parser()?, 4);
+        let received: Vec<String> = messages
+            .iter()
+            .map(|msg| msg.map(|msg| format!("{msg:?}")))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(received, expected);
+
+        let parser = worker.join().unwrap();
+        assert_eq!(parser.state(), State::EOF);
+        Ok(())
+    }
+
+    #[test]
+    fn test_dropped_receiver_stops_worker() -> Result<(), ULogError> {
+        let (worker, messages) = spawn_reader(parser()?, 0);
+        messages.recv().unwrap()?;
+        drop(messages);
+
+        // The worker stopped after at most one more message, rather than parsing the whole log.
+        let parser = worker.join().unwrap();
+        assert_eq!(parser.state(), State::DEFINITIONS);
+        Ok(())
+    }
+}
@@ -1,15 +1,44 @@
-use std::collections::HashSet;
-use std::io::Read;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Read, Seek};
 
+use crate::cache::DefinitionsCache;
+use crate::cancel::CancellationToken;
+use crate::datastream;
+use crate::decoder::DecoderRegistry;
 use crate::errors::ULogError;
+use crate::filter::SubscriptionFilter;
+use crate::message_buf::MessageBuf;
+use crate::metrics::{NoopMetrics, ParserMetrics};
+use crate::model::msg::UlogMessage;
 use crate::parser::ULogParser;
+use crate::state::ParserState;
+use crate::validation::{DuplicateSubscriptionPolicy, TimestampRepair};
 
 pub struct ULogParserBuilder<R> {
     reader: R,
     include_header: bool,
     include_timestamp: bool,
     include_padding: bool,
-    allowed_subscription_names: Option<HashSet<String>>,
+    include_timestamp_overrides: HashMap<String, bool>,
+    include_padding_overrides: HashMap<String, bool>,
+    ignore_invalid_appended_offsets: bool,
+    error_on_message_size_mismatch: bool,
+    duplicate_subscription_policy: DuplicateSubscriptionPolicy,
+    max_subscriptions: Option<usize>,
+    timestamp_repair: Option<TimestampRepair>,
+    resync_on_error: bool,
+    strict: bool,
+    emit_ignored: bool,
+    emit_unhandled: bool,
+    recover_format_errors: bool,
+    decoders: DecoderRegistry,
+    metrics: Box<dyn ParserMetrics>,
+    definitions_cache: Option<DefinitionsCache>,
+    subscription_filter: Option<SubscriptionFilter>,
+    read_buffer_size: Option<usize>,
+    seek_forward: Option<fn(&mut R, u64) -> io::Result<u64>>,
+    resume_state: Option<ParserState>,
+    cancellation: Option<CancellationToken>,
 }
 
 impl<R: Read> ULogParserBuilder<R> {
@@ -21,7 +50,26 @@ impl<R: Read> ULogParserBuilder<R> {
             include_header: false,
             include_timestamp: false,
             include_padding: false,
-            allowed_subscription_names: None,
+            include_timestamp_overrides: HashMap::new(),
+            include_padding_overrides: HashMap::new(),
+            ignore_invalid_appended_offsets: false,
+            error_on_message_size_mismatch: false,
+            duplicate_subscription_policy: DuplicateSubscriptionPolicy::default(),
+            max_subscriptions: None,
+            timestamp_repair: None,
+            resync_on_error: false,
+            strict: false,
+            emit_ignored: false,
+            emit_unhandled: false,
+            recover_format_errors: false,
+            decoders: DecoderRegistry::new(),
+            metrics: Box::new(NoopMetrics),
+            definitions_cache: None,
+            subscription_filter: None,
+            read_buffer_size: None,
+            seek_forward: None,
+            resume_state: None,
+            cancellation: None,
         }
     }
 
@@ -43,18 +91,201 @@ impl<R: Read> ULogParserBuilder<R> {
         self
     }
 
+    /// Overrides `include_timestamp()` for the `LoggedData` messages of `topic`.
+    ///
+    /// For example, timestamps can be kept for estimator topics only, while being dropped everywhere else.
+    #[must_use]
+    pub fn include_timestamp_for(mut self, topic: impl Into<String>, include: bool) -> Self {
+        self.include_timestamp_overrides.insert(topic.into(), include);
+        self
+    }
+
+    /// Overrides `include_padding()` for the `LoggedData` messages of `topic`, including padding in nested formats.
+    ///
+    /// For example, padding can be kept only for the topics which will be re-encoded.
+    #[must_use]
+    pub fn include_padding_for(mut self, topic: impl Into<String>, include: bool) -> Self {
+        self.include_padding_overrides.insert(topic.into(), include);
+        self
+    }
+
+    /// Controls how the parser reacts to obviously invalid appended data offsets in the `FLAG_BITS` message.
+    ///
+    /// When the `data_appended` flag is set, the parser stops reading ULOG messages at the first
+    /// non-zero appended data offset.  An offset is considered invalid if it points into the header
+    /// or definitions section, into the middle of a message, or past the end of the file.
+    ///
+    /// By default, an invalid offset is reported as `ULogError::InvalidAppendedDataOffset`.
+    /// When set to `true`, the offending offset is logged and ignored instead, so slightly
+    /// corrupted logs can still be parsed.
+    #[must_use]
+    pub fn ignore_invalid_appended_offsets(mut self, ignore: bool) -> Self {
+        self.ignore_invalid_appended_offsets = ignore;
+        self
+    }
+
+    /// Controls how the parser reacts when the size of a `LoggedData` message does not match its format.
+    ///
+    /// The expected size is computed from the format definition, taking into account that trailing
+    /// padding fields may be omitted from the message, as allowed by the ULOG spec.
+    /// A mismatch usually indicates that the format definitions do not match the firmware which wrote the log.
+    ///
+    /// By default, a mismatch is logged as a warning.  When set to `true`, it is reported as
+    /// `ULogError::MessageSizeMismatch` instead.
+    #[must_use]
+    pub fn error_on_message_size_mismatch(mut self, error: bool) -> Self {
+        self.error_on_message_size_mismatch = error;
+        self
+    }
+
+    /// Sets how an `ADD_SUBSCRIPTION` message which reuses the msg_id of a current subscription is handled.
+    ///
+    /// By default, the new subscription replaces the previous one and a warning is logged.
+    /// Duplicates are always recorded in `ULogParser::validation_report()`.
+    #[must_use]
+    pub fn duplicate_subscription_policy(mut self, policy: DuplicateSubscriptionPolicy) -> Self {
+        self.duplicate_subscription_policy = policy;
+        self
+    }
+
+    /// Limits the number of subscriptions held at once, bounding the parser's memory for logs which add
+    /// and remove many subscriptions.  Removed subscriptions don't count towards the limit.
+    ///
+    /// An `ADD_SUBSCRIPTION` message beyond the limit is reported as `ULogError::SubscriptionLimitExceeded`,
+    /// and the subscription is not added.  By default, there is no limit.
+    #[must_use]
+    pub fn max_subscriptions(mut self, limit: usize) -> Self {
+        self.max_subscriptions = Some(limit);
+        self
+    }
+
+    /// Repairs `LoggedData` timestamps which go backwards, per subscription.  Each timestamp changed, or for
+    /// `TimestampRepair::OffsetOnWrap` each change of offset, is recorded in `ULogParser::validation_report()`.
+    ///
+    /// By default, timestamps are returned as logged.
+    #[must_use]
+    pub fn repair_timestamps(mut self, strategy: TimestampRepair) -> Self {
+        self.timestamp_repair = Some(strategy);
+        self
+    }
+
+    /// When set to `true`, a data section message which fails to parse is skipped, along with the bytes up to the
+    /// next SYNC message, where parsing resumes.  The skipped bytes are recorded in
+    /// `ULogParser::validation_report()`, along with the SYNC messages seen.
+    ///
+    /// By default, the error is returned, and the parser continues with the message after the corrupt one.
+    #[must_use]
+    pub fn resync_on_error(mut self, resync: bool) -> Self {
+        self.resync_on_error = resync;
+        self
+    }
+
+    /// When set to `true`, the parser returns `ULogError::UninterpretedMessage` in place of any message it can't
+    /// interpret: an `Unhandled` message, such as a data section message in the definitions section, or an
+    /// `UnknownTyped` message, whose type has no registered decoder.  For workflows which must show that every
+    /// message of a log was interpreted.
+    ///
+    /// By default, such messages are returned, and counted in `ValidationReport::message_counts`.
+    #[must_use]
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// When set to `true`, the parser returns a `UlogMessage::Ignored` for each data message of a topic left out by
+    /// `set_subscription_allow_list()` or `subscription_filter()`.
+    ///
+    /// By default, such messages are skipped without being returned, so loops over the messages needn't match and
+    /// discard them.  Either way they are counted in `ValidationReport::message_counts`.
+    #[must_use]
+    pub fn emit_ignored(mut self, emit: bool) -> Self {
+        self.emit_ignored = emit;
+        self
+    }
+
+    /// When set to `true`, the parser returns the `UlogMessage::Unhandled` messages, including the SYNC messages,
+    /// which are needed to write a log back byte for byte.
+    ///
+    /// By default, such messages are skipped without being returned, and those which the parser doesn't interpret
+    /// aren't copied.  Either way they are counted in `ValidationReport::message_counts`, and an error in `strict()`
+    /// mode.
+    ///
+    /// REMOVE_SUBSCRIPTION messages, see `UlogMessage::removed_subscription()`, are returned regardless, as the msg_id
+    /// they free may be reused for another topic.
+    #[must_use]
+    pub fn emit_unhandled(mut self, emit: bool) -> Self {
+        self.emit_unhandled = emit;
+        self
+    }
+
+    /// When set to `true`, a field of a FORMAT message which can't be parsed, e.g. of a custom format written by
+    /// other tooling, is left out of the format rather than failing the definition, and recorded in
+    /// `ValidationReport::format_issues`.  As the layout of the format is then unknown, the data messages of its
+    /// topics, and of topics nesting it, are returned as `UlogMessage::Unhandled` rather than parsed.
+    ///
+    /// By default, the parser returns a `ULogError::ParseError` naming the format and the columns of the field.
+    #[must_use]
+    pub fn recover_format_errors(mut self, recover: bool) -> Self {
+        self.recover_format_errors = recover;
+        self
+    }
+
+    /// Sets the decoders used for message types which are not defined by the ULOG spec.
+    ///
+    /// Messages with an unknown type and no registered decoder are emitted as `UlogMessage::UnknownTyped`.
+    #[must_use]
+    pub fn decoders(mut self, decoders: DecoderRegistry) -> Self {
+        self.decoders = decoders;
+        self
+    }
+
+    /// Registers a handler which decodes the messages of a custom message type, e.g. one logged by a firmware fork,
+    /// typically into a `UlogMessage::Custom`.  Adds to the decoders set by `decoders()`, which must be called first.
+    ///
+    /// Returns `ULogError::InvalidConfiguration` if `msg_type` is defined by the ULOG spec.
+    pub fn register_handler<F>(mut self, msg_type: u8, handler: F) -> Result<Self, ULogError>
+    where
+        F: Fn(&mut MessageBuf) -> Result<UlogMessage, ULogError> + Send + Sync + 'static,
+    {
+        self.decoders.register(msg_type, handler)?;
+        Ok(self)
+    }
+
+    /// Sets the hooks called as messages are read, e.g. to export throughput counters.  See `metrics::ParserMetrics`.
+    #[must_use]
+    pub fn metrics(mut self, metrics: impl ParserMetrics + 'static) -> Self {
+        self.metrics = Box::new(metrics);
+        self
+    }
+
+    /// Sets the size of the parser's read buffer, which defaults to `datastream::DEFAULT_READ_BUFFER_SIZE`.  A size
+    /// of 0 leaves the reads unbuffered, for a reader which is already buffered, e.g. a `BufReader`.
+    #[must_use]
+    pub fn read_buffer_size(mut self, size: usize) -> Self {
+        self.read_buffer_size = Some(size);
+        self
+    }
+
+    /// Takes the definitions section from `cache` if it was parsed before, or adds it to the cache once parsed.
+    /// See `cache::DefinitionsCache`.
+    #[must_use]
+    pub fn definitions_cache(mut self, cache: DefinitionsCache) -> Self {
+        self.definitions_cache = Some(cache);
+        self
+    }
+
     /// Sets the list of `LoggedData` messages that the parser will return.
     ///
     /// By default, all `LoggedData` messages will be returned, which incurs extra parsing cost.
     ///
     /// Specifying only the required messages in this allow list can greatly improve parser performance.
     ///
-    /// Any `LoggedData` messages not included in this allow list will be emitted as raw bytes in a
-    /// `UlogMessage::Ignored` variant, so no messages are lost.
+    /// Any `LoggedData` messages not included in this allow list are skipped without being parsed or copied.  They
+    /// are returned as a `UlogMessage::Ignored` variant if `emit_ignored(true)` is set.
     ///
     /// # Parameters
     /// - `subs`: An iterable collection of string-like items representing the names of `LoggedData` messages
-    ///           to be parsed fully and returned.
+    ///   to be parsed fully and returned.
     #[must_use]
     pub fn set_subscription_allow_list<I, S>(mut self, subs: I) -> Self
     where
@@ -62,7 +293,31 @@ impl<R: Read> ULogParserBuilder<R> {
         S: Into<String>,
     {
         let set: HashSet<String> = subs.into_iter().map(|s| s.into()).collect();
-        self.allowed_subscription_names = Some(set);
+        self.subscription_filter = Some(SubscriptionFilter::new(set));
+        self
+    }
+
+    /// Only decodes the `LoggedData` messages of the subscriptions which `filter` allows, skipping the others as
+    /// `set_subscription_allow_list()` does, which this replaces.  See the `filter` module.
+    #[must_use]
+    pub fn subscription_filter(mut self, filter: SubscriptionFilter) -> Self {
+        self.subscription_filter = Some(filter);
+        self
+    }
+
+    /// Makes the parser return `ULogError::Cancelled`, then end, once `token` is cancelled.  The token is checked
+    /// before each message.  See the `cancel` module.
+    #[must_use]
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Resumes parsing from a `ParserState` returned by `ULogParser::snapshot()`, with the reader positioned at
+    /// `state.offset()` in the log, rather than at its start.  See the `state` module.
+    #[must_use]
+    pub fn resume(mut self, state: ParserState) -> Self {
+        self.resume_state = Some(state);
         self
     }
 
@@ -75,9 +330,35 @@ impl<R: Read> ULogParserBuilder<R> {
                 parser.include_header = self.include_header;
                 parser.include_timestamp = self.include_timestamp;
                 parser.include_padding = self.include_padding;
+                parser.include_timestamp_overrides = self.include_timestamp_overrides;
+                parser.include_padding_overrides = self.include_padding_overrides;
+                parser.ignore_invalid_appended_offsets = self.ignore_invalid_appended_offsets;
+                parser.error_on_message_size_mismatch = self.error_on_message_size_mismatch;
+                parser.duplicate_subscription_policy = self.duplicate_subscription_policy;
+                parser.max_subscriptions = self.max_subscriptions;
+                parser.timestamp_repair = self.timestamp_repair;
+                parser.resync_on_error = self.resync_on_error;
+                parser.strict = self.strict;
+                parser.emit_ignored = self.emit_ignored;
+                parser.emit_unhandled = self.emit_unhandled;
+                parser.recover_format_errors = self.recover_format_errors;
+                parser.decoders = self.decoders;
+                parser.metrics = self.metrics;
+                parser.definitions_cache = self.definitions_cache;
+                parser.cancellation = self.cancellation;
+                if let Some(size) = self.read_buffer_size {
+                    parser.datastream.set_buffer_size(size);
+                }
+                if let Some(seek_forward) = self.seek_forward {
+                    parser.datastream.set_seek_forward(seek_forward);
+                }
 
-                if let Some(allowed_subscr) = self.allowed_subscription_names {
-                    parser.set_allowed_subscription_names(allowed_subscr);
+                if let Some(filter) = self.subscription_filter {
+                    parser.set_subscription_filter(filter);
+                }
+                // After the subscription filter, so that the msg_ids it allows are recomputed from the subscriptions.
+                if let Some(state) = self.resume_state {
+                    parser.restore(state)?;
                 }
 
                 Ok(parser)
@@ -86,3 +367,14 @@ impl<R: Read> ULogParserBuilder<R> {
         }
     }
 }
+
+impl<R: Read + Seek> ULogParserBuilder<R> {
+    /// Makes the parser seek over the bytes it skips, such as the data of topics left out by the subscription allow
+    /// list, rather than read them, so that skipping costs the same however much is skipped.  Worthwhile for files,
+    /// but not for readers which seek by reading, such as a decompressing reader.
+    #[must_use]
+    pub fn skip_by_seeking(mut self) -> Self {
+        self.seek_forward = Some(datastream::seek_forward::<R>);
+        self
+    }
+}
@@ -0,0 +1,206 @@
+//! Caches the parsed definitions section of logs, for applications which open the same logs repeatedly.
+//!
+//! A GUI, for example, may reopen a log each time a plot is added.  With a `DefinitionsCache` set on the
+//! builder, the parser reads the definitions section without decoding it, and hashes it.  If the hash is in the
+//! cache, the formats, parameters and info messages are taken from the cache instead of being parsed again.
+//!
+//! ```rust
+//! use yule_log::builder::ULogParserBuilder;
+//! use yule_log::cache::DefinitionsCache;
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let cache = DefinitionsCache::new(16);
+//! let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+//!
+//! for _ in 0..2 {
+//!     let parser = ULogParserBuilder::new(input.as_slice())
+//!         .definitions_cache(cache.clone())
+//!         .build()?;
+//!     for msg in parser {
+//!         msg?;
+//!     }
+//! }
+//! assert_eq!(cache.stats().hits, 1);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hasher;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::model::msg::UlogMessage;
+use crate::model::{Shared, StableHasher};
+use crate::parser::ULogMessageType;
+
+/// The default number of logs whose definitions are cached.
+pub const DEFAULT_CAPACITY: usize = 16;
+
+/// The hits and misses of a `DefinitionsCache`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// A cache of parsed definitions sections, keyed by a hash of their contents.
+///
+/// Clones share the same cache.  With the `thread_safe` feature, a cache can be shared between threads.
+///
+/// The cached messages are the output of the parser which first read the definitions, so a cache should only
+/// be shared by parsers with the same `DecoderRegistry`.
+#[derive(Debug, Clone)]
+pub struct DefinitionsCache {
+    inner: Shared<Mutex<Inner>>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    capacity: usize,
+    entries: HashMap<u64, Shared<[UlogMessage]>>,
+    // Keys, from the least to the most recently inserted.
+    order: VecDeque<u64>,
+    stats: CacheStats,
+}
+
+impl Default for DefinitionsCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl DefinitionsCache {
+    /// Creates a cache which holds the definitions of up to `capacity` logs.  Beyond that, the definitions cached
+    /// first are evicted.
+    pub fn new(capacity: usize) -> Self {
+        DefinitionsCache {
+            inner: Shared::new(Mutex::new(Inner {
+                capacity: capacity.max(1),
+                entries: HashMap::new(),
+                order: VecDeque::new(),
+                stats: CacheStats::default(),
+            })),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().entries.is_empty()
+    }
+
+    pub fn clear(&self) {
+        let mut inner = self.lock();
+        inner.entries.clear();
+        inner.order.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.lock().stats
+    }
+
+    /// Hashes the type and contents of each message of a definitions section.
+    pub(crate) fn key(messages: &[(ULogMessageType, Vec<u8>)]) -> u64 {
+        let mut hasher = StableHasher::default();
+        for (msg_type, contents) in messages {
+            hasher.write_u8((*msg_type).into());
+            hasher.write_usize(contents.len());
+            hasher.write(contents);
+        }
+        hasher.finish()
+    }
+
+    pub(crate) fn get(&self, key: u64) -> Option<Shared<[UlogMessage]>> {
+        let mut inner = self.lock();
+        let messages = inner.entries.get(&key).cloned();
+        match messages {
+            Some(_) => inner.stats.hits += 1,
+            None => inner.stats.misses += 1,
+        }
+        messages
+    }
+
+    pub(crate) fn insert(&self, key: u64, messages: Vec<UlogMessage>) {
+        let mut inner = self.lock();
+        if inner.entries.contains_key(&key) {
+            return;
+        }
+        if inner.entries.len() >= inner.capacity {
+            if let Some(evicted) = inner.order.pop_front() {
+                inner.entries.remove(&evicted);
+            }
+        }
+        inner.entries.insert(key, messages.into());
+        inner.order.push_back(key);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        // The cache is only modified by the methods above, which leave it consistent if they panic.
+        self.inner
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::errors::ULogError;
+    use crate::model::def::BaseType;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    fn log(sys_name: &str) -> Result<Vec<u8>, ULogError> {
+        LogBuilder::new()
+            .info("sys_name", sys_name)
+            .parameter_i32("MAV_SYS_ID", 1)
+            .topic(Topic::new("a").field(
+                "x",
+                BaseType::FLOAT,
+                Generator::Ramp {
+                    start: 0.0,
+                    step: 1.0,
+                },
+            ))
+            .build()
+    }
+
+    fn parse(bytes: &[u8], cache: &DefinitionsCache) -> Result<String, ULogError> {
+        let parser = ULogParserBuilder::new(bytes)
+            .include_header(true)
+            .definitions_cache(cache.clone())
+            .build()?;
+        let messages: Vec<_> = parser.collect::<Result<_, _>>()?;
+        Ok(format!("{messages:?}"))
+    }
+
+    #[test]
+    fn test_cached_definitions() -> Result<(), ULogError> {
+        let bytes = log("PX4")?;
+        let uncached: Vec<_> = ULogParserBuilder::new(bytes.as_slice())
+            .include_header(true)
+            .build()?
+            .collect::<Result<_, _>>()?;
+        let uncached = format!("{uncached:?}");
+
+        let cache = DefinitionsCache::new(1);
+        assert_eq!(parse(&bytes, &cache)?, uncached);
+        assert_eq!(cache.stats(), CacheStats { hits: 0, misses: 1 });
+        assert_eq!(cache.len(), 1);
+
+        // The formats are restored from the cache, so the logged data is still decoded.
+        assert_eq!(parse(&bytes, &cache)?, uncached);
+        assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 1 });
+
+        // Different definitions miss, and evict the first log's.
+        let other = log("NuttX")?;
+        assert_ne!(parse(&other, &cache)?, uncached);
+        assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 2 });
+        assert_eq!(parse(&bytes, &cache)?, uncached);
+        assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 3 });
+
+        Ok(())
+    }
+}
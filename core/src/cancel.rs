@@ -0,0 +1,78 @@
+//! Aborting a long-running parse from another thread, e.g. when the user of a GUI closes a multi-GB log.
+//!
+//! A parser built with `ULogParserBuilder::cancellation()` checks the token before each message, and once it is
+//! cancelled returns `ULogError::Cancelled`, then ends.  The token can be cloned, and any clone cancels them all:
+//!
+//! ```rust
+//! use yule_log::builder::ULogParserBuilder;
+//! use yule_log::cancel::CancellationToken;
+//! use yule_log::errors::ULogError;
+//!
+//! # fn main() -> Result<(), ULogError> {
+//! let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+//! let token = CancellationToken::new();
+//! let mut parser = ULogParserBuilder::new(input.as_slice())
+//!     .cancellation(token.clone())
+//!     .build()?;
+//!
+//! parser.next().unwrap()?;
+//! token.cancel();
+//! assert!(matches!(parser.next(), Some(Err(ULogError::Cancelled))));
+//! assert!(parser.next().is_none());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The parser stops between messages, so it can still be snapshotted with `ULogParser::snapshot()`, to resume the
+//! parse later.  `batch::BatchOptions::cancellation()` cancels a whole batch.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A flag shared between the parsers it is passed to and the code which may cancel them.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the parsers using this token, or any of its clones.  They stop before their next message.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::errors::ULogError;
+
+    #[test]
+    fn test_cancel_and_resume() -> Result<(), ULogError> {
+        let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+        let all = ULogParserBuilder::new(input.as_slice()).build()?.count();
+
+        let token = CancellationToken::new();
+        let mut parser = ULogParserBuilder::new(input.as_slice())
+            .cancellation(token.clone())
+            .build()?;
+        let mut count = parser.by_ref().take(all / 2).count();
+        token.cancel();
+        assert!(matches!(parser.next(), Some(Err(ULogError::Cancelled))));
+        assert!(parser.next().is_none());
+
+        // The cancelled parse carries on from its snapshot.
+        let state = parser.snapshot()?;
+        let rest = &input[state.offset() as usize..];
+        count += ULogParserBuilder::new(rest).resume(state).build()?.count();
+        assert_eq!(count, all);
+        Ok(())
+    }
+}
@@ -0,0 +1,318 @@
+//! Checks that a log can be read by pyulog, and so opened by Flight Review and px4tools.
+//!
+//! pyulog is stricter than this crate's parser in a few places: it looks up the `timestamp` of every
+//! subscribed message, rejects formats with duplicate field names (which it maps to numpy dtypes), and
+//! resolves every nested type when a subscription is added.  `check()` reports these problems, e.g. to
+//! validate the output of a `ULogWriter` before uploading it.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::Read;
+
+use crate::builder::ULogParserBuilder;
+use crate::errors::ULogError;
+use crate::model::def::{self, BaseType};
+use crate::model::msg::UlogMessage;
+
+/// A problem which would prevent pyulog from reading a log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompatIssue {
+    /// The log sets incompat flags which pyulog doesn't know, so it refuses to read the log.
+    UnknownIncompatFlags,
+
+    /// `message_name` is subscribed, or used as a nested type, but its format is not defined.
+    UndefinedFormat { message_name: String },
+
+    /// The subscribed format `message_name` has no `uint64_t timestamp` field.
+    MissingTimestamp { message_name: String },
+
+    /// The format `message_name` defines `field` more than once.
+    DuplicateField { message_name: String, field: String },
+}
+
+impl fmt::Display for CompatIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompatIssue::UnknownIncompatFlags => write!(f, "unknown incompat flags are set"),
+            CompatIssue::UndefinedFormat { message_name } => {
+                write!(f, "format `{message_name}` is not defined")
+            }
+            CompatIssue::MissingTimestamp { message_name } => {
+                write!(
+                    f,
+                    "subscribed format `{message_name}` has no `uint64_t timestamp` field"
+                )
+            }
+            CompatIssue::DuplicateField {
+                message_name,
+                field,
+            } => write!(
+                f,
+                "format `{message_name}` defines `{field}` more than once"
+            ),
+        }
+    }
+}
+
+/// Reads a log, and returns the problems which would prevent pyulog from reading it, in the order found.
+///
+/// Errors from this crate's parser are returned as is, except for unknown incompat flags,
+/// which are reported as `CompatIssue::UnknownIncompatFlags`.
+pub fn check<R: Read>(reader: R) -> Result<Vec<CompatIssue>, ULogError> {
+    let parser = ULogParserBuilder::new(reader).build()?;
+
+    let mut formats: HashMap<String, def::Format> = HashMap::new();
+    let mut issues = Vec::new();
+    let mut checked_formats = HashSet::new();
+
+    for msg in parser {
+        match msg {
+            // Only the appended data flag is known.  This crate's parser ignores the other bits of the first byte.
+            Ok(UlogMessage::FlagBits(flag_bits)) => {
+                if flag_bits.incompat_flags[0] & !1 != 0 {
+                    issues.push(CompatIssue::UnknownIncompatFlags);
+                }
+            }
+            Ok(UlogMessage::FormatDefinition(format)) => {
+                formats.insert(format.name.to_string(), format);
+            }
+            Ok(UlogMessage::AddSubscription(sub)) => {
+                let Some(format) = formats.get(&sub.message_name) else {
+                    issues.push(CompatIssue::UndefinedFormat {
+                        message_name: sub.message_name,
+                    });
+                    continue;
+                };
+
+                if !has_timestamp(format) {
+                    issues.push(CompatIssue::MissingTimestamp {
+                        message_name: sub.message_name.clone(),
+                    });
+                }
+                check_format(format, &formats, &mut checked_formats, &mut issues);
+            }
+            Ok(_) => {}
+            Err(ULogError::UnknownIncompatBits) => {
+                issues.push(CompatIssue::UnknownIncompatFlags);
+                break;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(issues)
+}
+
+fn has_timestamp(format: &def::Format) -> bool {
+    format.fields.iter().any(|field| {
+        field.name.as_ref() == "timestamp"
+            && field.r#type.base_type == BaseType::UINT64
+            && field.r#type.array_size.is_none()
+    })
+}
+
+// Checks `format` and the formats nested within it, reporting each format once.
+fn check_format(
+    format: &def::Format,
+    formats: &HashMap<String, def::Format>,
+    checked_formats: &mut HashSet<String>,
+    issues: &mut Vec<CompatIssue>,
+) {
+    if !checked_formats.insert(format.name.to_string()) {
+        return;
+    }
+
+    let mut names = HashSet::new();
+    for field in &format.fields {
+        if !names.insert(field.name.as_ref()) {
+            issues.push(CompatIssue::DuplicateField {
+                message_name: format.name.to_string(),
+                field: field.name.to_string(),
+            });
+        }
+
+        if let BaseType::OTHER(nested) = &field.r#type.base_type {
+            match formats.get(nested) {
+                Some(nested) => check_format(nested, formats, checked_formats, issues),
+                None => {
+                    if checked_formats.insert(nested.clone()) {
+                        issues.push(CompatIssue::UndefinedFormat {
+                            message_name: nested.clone(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message_buf::MessageBuf;
+    use crate::model::MAGIC;
+    use crate::writer::ULogWriter;
+
+    fn message(bytes: &mut Vec<u8>, msg_type: u8, payload: &[u8]) {
+        bytes.extend_from_slice(&u16::try_from(payload.len()).unwrap().to_le_bytes());
+        bytes.push(msg_type);
+        bytes.extend_from_slice(payload);
+    }
+
+    // Returns a log with the given format definitions, subscribing to `topic` with msg_id 0,
+    // followed by a single `LOGGED_DATA` message with `data`, if not empty.
+    fn log(definitions: &[&str], topic: &str, data: &[u8]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(1);
+        bytes.extend_from_slice(&0u64.to_le_bytes());
+        message(&mut bytes, b'B', &[0; 40]);
+        for definition in definitions {
+            message(&mut bytes, b'F', definition.as_bytes());
+        }
+
+        let mut sub = vec![0, 0, 0];
+        sub.extend_from_slice(topic.as_bytes());
+        message(&mut bytes, b'A', &sub);
+
+        if !data.is_empty() {
+            let mut payload = vec![0, 0];
+            payload.extend_from_slice(data);
+            message(&mut bytes, b'D', &payload);
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_corner_cases() -> Result<(), ULogError> {
+        // An empty format, and a padding only format, nested in a topic with trailing padding.
+        let mut data = 42u64.to_le_bytes().to_vec();
+        data.extend_from_slice(&[0, 0, 0, 0, 7, 0, 0, 0]);
+        let bytes = log(
+            &[
+                "empty_t:",
+                "pad_t:uint8_t[4] _padding0;",
+                "topic:uint64_t timestamp;pad_t p;empty_t e;uint8_t x;uint8_t[3] _padding0;",
+            ],
+            "topic",
+            &data,
+        );
+
+        assert_eq!(check(bytes.as_slice())?, []);
+
+        let parser = ULogParserBuilder::new(bytes.as_slice())
+            .include_header(true)
+            .include_timestamp(true)
+            .include_padding(true)
+            .build()?;
+        let mut writer = ULogWriter::new(Vec::new());
+        let mut logged = Vec::new();
+        for msg in parser {
+            let msg = msg?;
+            writer.write_message(&msg)?;
+            if let UlogMessage::LoggedData(data) = msg {
+                logged.push(data);
+            }
+        }
+
+        // The re-encoded log is identical, so it is still accepted.
+        assert_eq!(writer.into_inner()?, bytes);
+        assert_eq!(logged.len(), 1);
+        assert_eq!(logged[0].data.timestamp, Some(42));
+        let x = logged[0]
+            .data
+            .fields
+            .iter()
+            .find(|f| f.name.as_ref() == "x");
+        assert!(matches!(
+            x.map(|f| &f.value),
+            Some(crate::model::inst::FieldValue::ScalarU8(7))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_issues() -> Result<(), ULogError> {
+        let bytes = log(&["empty_t:"], "empty_t", &[]);
+        assert_eq!(
+            check(bytes.as_slice())?,
+            [CompatIssue::MissingTimestamp {
+                message_name: "empty_t".to_string()
+            }]
+        );
+
+        let bytes = log(&["t:uint32_t timestamp;"], "t", &[]);
+        assert_eq!(
+            check(bytes.as_slice())?,
+            [CompatIssue::MissingTimestamp {
+                message_name: "t".to_string()
+            }]
+        );
+
+        let bytes = log(&["t:uint64_t timestamp;uint8_t x;uint8_t x;"], "t", &[]);
+        assert_eq!(
+            check(bytes.as_slice())?,
+            [CompatIssue::DuplicateField {
+                message_name: "t".to_string(),
+                field: "x".to_string()
+            }]
+        );
+
+        let bytes = log(&["t:uint64_t timestamp;missing_t m;"], "t", &[]);
+        assert_eq!(
+            check(bytes.as_slice())?,
+            [CompatIssue::UndefinedFormat {
+                message_name: "missing_t".to_string()
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_incompat_flags() -> Result<(), ULogError> {
+        let mut bytes = log(&["t:uint64_t timestamp;"], "t", &0u64.to_le_bytes());
+        // The first incompat flags byte follows the 16 byte header, the message header and 8 compat flags bytes.
+        bytes[16 + 3 + 8] = 0x02;
+        assert_eq!(
+            check(bytes.as_slice())?,
+            [CompatIssue::UnknownIncompatFlags]
+        );
+
+        bytes[16 + 3 + 8] = 0;
+        bytes[16 + 3 + 9] = 0x01;
+        assert_eq!(
+            check(bytes.as_slice())?,
+            [CompatIssue::UnknownIncompatFlags]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sample_logs() -> Result<(), ULogError> {
+        for name in ["sample_log_small", "short_list", "powers", "trig_stats"] {
+            let input = std::fs::read(format!("test_data/input/{name}.ulg"))?;
+            assert_eq!(check(input.as_slice())?, [], "{name}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_oversized_definition() -> Result<(), ULogError> {
+        let fields: String = (0..8000).map(|i| format!("uint8_t field_{i};")).collect();
+        let format = crate::formats::parse_format(MessageBuf::from_vec(
+            format!("t:uint64_t timestamp;{fields}").into_bytes(),
+        ))?;
+
+        // The message doesn't fit in its u16 size field, so it is rejected rather than truncated.
+        let mut writer = ULogWriter::new(Vec::new());
+        assert!(writer
+            .write_message(&UlogMessage::FormatDefinition(format))
+            .is_err());
+        assert!(writer.get_ref().is_empty());
+
+        Ok(())
+    }
+}
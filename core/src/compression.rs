@@ -0,0 +1,352 @@
+pub mod delta;
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Chain, Cursor, Read, Write};
+use std::path::Path;
+
+use flate2::read::MultiGzDecoder;
+
+use crate::builder::ULogParserBuilder;
+use crate::errors::ULogError;
+use crate::writer::ULogWriter;
+
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+/// The default number of uncompressed bytes per frame written by `ZstdFrameWriter`.
+pub const DEFAULT_FRAME_SIZE: usize = 4 * 1024 * 1024;
+
+/// The default zstd compression level.
+pub const DEFAULT_LEVEL: i32 = zstd::DEFAULT_COMPRESSION_LEVEL;
+
+/// The compression format of a log, as detected from its leading magic bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn detect(magic: &[u8]) -> Compression {
+        if magic.starts_with(&ZSTD_MAGIC) {
+            Compression::Zstd
+        } else if magic.starts_with(&GZIP_MAGIC) {
+            Compression::Gzip
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// The sniffed magic bytes, followed by the rest of the input.
+type Sniffed<R> = Chain<Cursor<Vec<u8>>, R>;
+
+/// A reader which transparently decompresses gzip (.ulg.gz) and zstd (.ulg.zst) inputs.
+///
+/// Uncompressed inputs are passed through untouched.
+pub enum DecompressingReader<R: Read> {
+    Plain(Sniffed<R>),
+    Gzip(MultiGzDecoder<Sniffed<R>>),
+    Zstd(zstd::Decoder<'static, BufReader<Sniffed<R>>>),
+}
+
+impl<R: Read> DecompressingReader<R> {
+    pub fn new(mut reader: R) -> Result<Self, ULogError> {
+        let mut magic = vec![0u8; ZSTD_MAGIC.len()];
+        let mut len = 0;
+
+        // The input may be shorter than the magic, or arrive in short reads.
+        while len < magic.len() {
+            match reader.read(&mut magic[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        magic.truncate(len);
+
+        let compression = Compression::detect(&magic);
+        let sniffed = Cursor::new(magic).chain(reader);
+
+        Ok(match compression {
+            Compression::None => DecompressingReader::Plain(sniffed),
+            Compression::Gzip => DecompressingReader::Gzip(MultiGzDecoder::new(sniffed)),
+            Compression::Zstd => DecompressingReader::Zstd(zstd::Decoder::new(sniffed)?),
+        })
+    }
+
+    pub fn compression(&self) -> Compression {
+        match self {
+            DecompressingReader::Plain(_) => Compression::None,
+            DecompressingReader::Gzip(_) => Compression::Gzip,
+            DecompressingReader::Zstd(_) => Compression::Zstd,
+        }
+    }
+}
+
+impl<R: Read> Read for DecompressingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            DecompressingReader::Plain(reader) => reader.read(buf),
+            DecompressingReader::Gzip(reader) => reader.read(buf),
+            DecompressingReader::Zstd(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl<R: Read> ULogParserBuilder<DecompressingReader<R>> {
+    /// Starts a builder for a log which may be compressed with gzip or zstd.
+    ///
+    /// The compression format is detected from the magic bytes at the start of `reader`,
+    /// not from the file extension.
+    pub fn new_auto(reader: R) -> Result<Self, ULogError> {
+        Ok(ULogParserBuilder::new(DecompressingReader::new(reader)?))
+    }
+}
+
+impl ULogParserBuilder<DecompressingReader<BufReader<File>>> {
+    /// Like `new_auto()`, but opens the log at `path`.
+    pub fn open_auto(path: impl AsRef<Path>) -> Result<Self, ULogError> {
+        Self::new_auto(BufReader::new(File::open(path)?))
+    }
+}
+
+/// A writer which emits zstd compressed output as a sequence of independent frames.
+///
+/// Each frame holds at most `frame_size` bytes of uncompressed data, and carries a content checksum.
+/// The concatenated frames form a regular zstd stream, which `DecompressingReader` and the `zstd`
+/// command line tool decompress as a whole.  Since frames are independent, a reader can also start
+/// decompressing at any frame boundary, and a truncated file only loses its last frame.
+///
+/// `finish()` must be called to complete the last frame.
+pub struct ZstdFrameWriter<W: Write> {
+    encoder: Option<zstd::Encoder<'static, W>>,
+    level: i32,
+    frame_size: usize,
+    frame_len: usize,
+}
+
+impl<W: Write> ZstdFrameWriter<W> {
+    pub fn new(writer: W, level: i32) -> Result<Self, ULogError> {
+        Self::with_frame_size(writer, level, DEFAULT_FRAME_SIZE)
+    }
+
+    pub fn with_frame_size(writer: W, level: i32, frame_size: usize) -> Result<Self, ULogError> {
+        if frame_size == 0 {
+            return Err(ULogError::InvalidConfiguration(
+                "The zstd frame size must be greater than zero.".to_owned(),
+            ));
+        }
+
+        Ok(ZstdFrameWriter {
+            encoder: Some(Self::new_encoder(writer, level)?),
+            level,
+            frame_size,
+            frame_len: 0,
+        })
+    }
+
+    fn new_encoder(writer: W, level: i32) -> io::Result<zstd::Encoder<'static, W>> {
+        let mut encoder = zstd::Encoder::new(writer, level)?;
+        encoder.include_checksum(true)?;
+        Ok(encoder)
+    }
+
+    fn encoder(&mut self) -> io::Result<&mut zstd::Encoder<'static, W>> {
+        // The encoder is only missing if ending a frame failed, leaving the output incomplete.
+        self.encoder
+            .as_mut()
+            .ok_or_else(|| io::Error::other("A previous zstd frame could not be completed."))
+    }
+
+    fn end_frame(&mut self) -> io::Result<()> {
+        if let Some(encoder) = self.encoder.take() {
+            self.encoder = Some(Self::new_encoder(encoder.finish()?, self.level)?);
+        }
+        self.frame_len = 0;
+        Ok(())
+    }
+
+    /// Completes the last frame and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W, ULogError> {
+        self.encoder()?;
+        let encoder = self.encoder.take().expect("encoder() checked it is present");
+        Ok(encoder.finish()?)
+    }
+}
+
+impl<W: Write> Write for ZstdFrameWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.frame_len >= self.frame_size {
+            self.end_frame()?;
+        }
+
+        let len = buf.len().min(self.frame_size - self.frame_len);
+        let n = self.encoder()?.write(&buf[..len])?;
+        self.frame_len += n;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder()?.flush()
+    }
+}
+
+impl<W: Write> ULogWriter<ZstdFrameWriter<W>> {
+    /// Creates a writer which emits a zstd compressed log (.ulg.zst).
+    pub fn new_zstd(writer: W, level: i32) -> Result<Self, ULogError> {
+        Ok(ULogWriter::new(ZstdFrameWriter::new(writer, level)?))
+    }
+
+    /// Completes the compressed output and returns the underlying writer.
+    pub fn finish(self) -> Result<W, ULogError> {
+        self.into_inner()?.finish()
+    }
+}
+
+/// Recompresses a log, which may be uncompressed or compressed with gzip or zstd, using zstd.
+///
+/// The log contents are copied byte for byte, so the result decompresses to the exact original log.
+/// Returns the underlying writer.
+pub fn recompress<R: Read, W: Write>(reader: R, writer: W, level: i32) -> Result<W, ULogError> {
+    let mut reader = DecompressingReader::new(reader)?;
+    let mut writer = ZstdFrameWriter::new(writer, level)?;
+    io::copy(&mut reader, &mut writer)?;
+    writer.finish()
+}
+
+/// Recompresses the log at `input` using zstd, writing the result to `output`.
+pub fn recompress_file(
+    input: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    level: i32,
+) -> Result<(), ULogError> {
+    let reader = BufReader::new(File::open(input)?);
+    let writer = BufWriter::new(File::create(output)?);
+    recompress(reader, writer, level)?.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+
+    use super::*;
+    use crate::parser::ULogParser;
+
+    const SAMPLE_LOG: &str = "test_data/input/sample_log_small.ulg";
+
+    fn messages<R: Read>(parser: ULogParser<R>) -> Result<Vec<String>, ULogError> {
+        parser
+            .map(|msg| msg.map(|msg| format!("{msg:?}")))
+            .collect()
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Compression::detect(b"ULog\x01\x12\x35"), Compression::None);
+        assert_eq!(Compression::detect(&[0x1F, 0x8B, 0x08]), Compression::Gzip);
+        assert_eq!(
+            Compression::detect(&[0x28, 0xB5, 0x2F, 0xFD]),
+            Compression::Zstd
+        );
+        assert_eq!(Compression::detect(&[0x28]), Compression::None);
+        assert_eq!(Compression::detect(&[]), Compression::None);
+    }
+
+    #[test]
+    fn test_new_auto() -> Result<(), ULogError> {
+        let plain = std::fs::read(SAMPLE_LOG)?;
+        let expected = messages(ULogParser::new(plain.as_slice())?)?;
+
+        let mut gz = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(&plain)?;
+        let gz = gz.finish()?;
+
+        let zst = zstd::encode_all(plain.as_slice(), 0)?;
+
+        for (bytes, compression) in [
+            (&plain, Compression::None),
+            (&gz, Compression::Gzip),
+            (&zst, Compression::Zstd),
+        ] {
+            let reader = DecompressingReader::new(bytes.as_slice())?;
+            assert_eq!(reader.compression(), compression);
+
+            let parser = ULogParserBuilder::new_auto(bytes.as_slice())?.build()?;
+            assert_eq!(messages(parser)?, expected, "{compression:?}");
+        }
+
+        let parser = ULogParserBuilder::open_auto(SAMPLE_LOG)?.build()?;
+        assert_eq!(messages(parser)?, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_zstd_writer() -> Result<(), ULogError> {
+        let parser = ULogParserBuilder::open_auto(SAMPLE_LOG)?
+            .include_header(true)
+            .include_timestamp(true)
+            .include_padding(true)
+            .emit_unhandled(true)
+            .build()?;
+
+        let frame_writer = ZstdFrameWriter::with_frame_size(Vec::new(), DEFAULT_LEVEL, 4096)?;
+        let mut writer = ULogWriter::new(frame_writer);
+        for msg in parser {
+            writer.write_message(&msg?)?;
+        }
+        let compressed = writer.finish()?;
+
+        // Every frame starts with the zstd magic.
+        let frames = compressed
+            .windows(ZSTD_MAGIC.len())
+            .filter(|w| *w == ZSTD_MAGIC)
+            .count();
+        let plain = std::fs::read(SAMPLE_LOG)?;
+        assert!(frames >= plain.len() / 4096);
+
+        let mut decompressed = Vec::new();
+        DecompressingReader::new(compressed.as_slice())?.read_to_end(&mut decompressed)?;
+        assert_eq!(decompressed, plain);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_recompress() -> Result<(), ULogError> {
+        let plain = std::fs::read(SAMPLE_LOG)?;
+
+        let mut gz = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(&plain)?;
+        let gz = gz.finish()?;
+
+        let zst = recompress(gz.as_slice(), Vec::new(), DEFAULT_LEVEL)?;
+        assert_eq!(Compression::detect(&zst), Compression::Zstd);
+        assert_eq!(zstd::decode_all(zst.as_slice())?, plain);
+
+        let dir = tempfile::tempdir()?;
+        let output = dir.path().join("sample_log_small.ulg.zst");
+        recompress_file(SAMPLE_LOG, &output, DEFAULT_LEVEL)?;
+        assert_eq!(zstd::decode_all(File::open(&output)?)?, plain);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_short_input() -> Result<(), ULogError> {
+        let mut reader = DecompressingReader::new(&b"UL"[..])?;
+        assert_eq!(reader.compression(), Compression::None);
+
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        assert_eq!(bytes, b"UL");
+
+        Ok(())
+    }
+}
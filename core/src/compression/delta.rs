@@ -0,0 +1,446 @@
+//! An experimental archival container which stores the data of a log as delta encoded columns, to measure how
+//! much smaller fleets of logs get in long term storage than with general purpose compression alone.
+//!
+//! `encode()` moves the payload of each `LoggedData` message to the columns of its subscription, one column per
+//! scalar of the format, arrays and nested formats included.  Each value is replaced with its difference from the
+//! previous value of the column, and the columns are split into byte planes, so that slowly changing fields
+//! become runs of zeros.  The other messages, and the headers of the data messages, are kept as they are, so
+//! `decode()` restores the exact original bytes.  The container isn't compressed itself; compress it with zstd.
+//!
+//! Both directions hold the whole log in memory.  The layout of the container may change between versions, so it
+//! isn't meant for logs which need to be read by other versions of the crate.
+//!
+//! ```rust,no_run
+//! # use std::fs;
+//! use yule_log::compression::delta;
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let log = fs::read("flight.ulg")?;
+//! let container = delta::encode(log.as_slice(), Vec::new())?;
+//! assert_eq!(delta::decode(container.as_slice(), Vec::new())?, log);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::errors::ULogError;
+use crate::formats::parse_format;
+use crate::message_buf::MessageBuf;
+use crate::model::{def, Shared};
+
+const MAGIC: [u8; 4] = *b"ULDC";
+const VERSION: u8 = 1;
+
+const ULOG_MAGIC: [u8; 7] = [b'U', b'L', b'o', b'g', 0x01, 0x12, 0x35];
+const ULOG_HEADER_SIZE: usize = 16;
+const MESSAGE_HEADER_SIZE: usize = 3;
+const MSG_ID_SIZE: usize = 2;
+
+// Formats nested deeper than this aren't split into columns, which also stops formats which contain themselves.
+const MAX_NESTING: usize = 32;
+
+/// Rewrites the log read from `reader` as a delta container, and returns `writer`.
+pub fn encode<R: Read, W: Write>(mut reader: R, mut writer: W) -> Result<W, ULogError> {
+    let mut log = Vec::new();
+    reader.read_to_end(&mut log)?;
+    if !log.starts_with(&ULOG_MAGIC) || log.len() < ULOG_HEADER_SIZE {
+        return Err(ULogError::InvalidMagicBits);
+    }
+
+    let mut layouts = Layouts::default();
+    // The rows of each stream, one after another.
+    let mut rows: Vec<Vec<u8>> = Vec::new();
+    let mut skeleton = log[..ULOG_HEADER_SIZE].to_vec();
+
+    let mut pos = ULOG_HEADER_SIZE;
+    while let Some((size, kind)) = message_header(&log, pos) {
+        let start = pos + MESSAGE_HEADER_SIZE;
+        let end = start + size;
+        if end > log.len() {
+            break;
+        }
+
+        match layouts.message(kind, size, &log[start..end]) {
+            Some(stream) => {
+                rows.resize_with(layouts.streams.len(), Vec::new);
+                rows[stream].extend_from_slice(&log[start + MSG_ID_SIZE..end]);
+                skeleton.extend_from_slice(&log[pos..start + MSG_ID_SIZE]);
+            }
+            None => skeleton.extend_from_slice(&log[pos..end]),
+        }
+        pos = end;
+    }
+    rows.resize_with(layouts.streams.len(), Vec::new);
+
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&[VERSION])?;
+    write_section(&mut writer, &skeleton)?;
+    write_section(&mut writer, &log[pos..])?;
+    for (stream, rows) in layouts.streams.iter().zip(&rows) {
+        write_section(&mut writer, &to_columns(stream.row_widths(), rows))?;
+    }
+    Ok(writer)
+}
+
+/// Restores the log from the delta container read from `reader`, and returns `writer`.
+pub fn decode<R: Read, W: Write>(mut reader: R, mut writer: W) -> Result<W, ULogError> {
+    let mut container = Vec::new();
+    reader.read_to_end(&mut container)?;
+    if !container.starts_with(&MAGIC) {
+        return Err(invalid("Not a delta container."));
+    }
+    if container.get(MAGIC.len()) != Some(&VERSION) {
+        return Err(invalid("Unsupported version."));
+    }
+
+    let mut rest = &container[MAGIC.len() + 1..];
+    let skeleton = read_section(&mut rest)?;
+    let tail = read_section(&mut rest)?;
+
+    // The layouts of the streams are known once all the definitions and subscriptions have been seen.
+    let mut layouts = Layouts::default();
+    walk_skeleton(skeleton, &mut layouts, |_, _| {})?;
+    let mut rows = Vec::with_capacity(layouts.streams.len());
+    for stream in &layouts.streams {
+        let widths = stream.row_widths();
+        let columns = read_section(&mut rest)?;
+        if columns.len() != stream.rows * widths.iter().sum::<usize>() {
+            return Err(invalid("The columns don't match the data messages."));
+        }
+        rows.push(from_columns(widths, columns));
+    }
+    if !rest.is_empty() {
+        return Err(invalid("Unexpected data after the columns."));
+    }
+
+    let mut log = Vec::with_capacity(container.len());
+    let mut offsets = vec![0; rows.len()];
+    walk_skeleton(skeleton, &mut Layouts::default(), |message, stream| {
+        log.extend_from_slice(message);
+        if let Some(stream) = stream {
+            let row_size = layouts.streams[stream].row_size.unwrap_or(0);
+            let offset = offsets[stream];
+            log.extend_from_slice(&rows[stream][offset..offset + row_size]);
+            offsets[stream] += row_size;
+        }
+    })?;
+    log.extend_from_slice(tail);
+
+    writer.write_all(&log)?;
+    Ok(writer)
+}
+
+/// Calls `f` with the bytes of each message of `skeleton`, and the stream whose next row follows them, if any.
+fn walk_skeleton(
+    skeleton: &[u8],
+    layouts: &mut Layouts,
+    mut f: impl FnMut(&[u8], Option<usize>),
+) -> Result<(), ULogError> {
+    let truncated = || invalid("A message of the log is truncated.");
+
+    f(
+        skeleton.get(..ULOG_HEADER_SIZE).ok_or_else(truncated)?,
+        None,
+    );
+    let mut pos = ULOG_HEADER_SIZE;
+    while pos < skeleton.len() {
+        let (size, kind) = message_header(skeleton, pos).ok_or_else(truncated)?;
+        let start = pos + MESSAGE_HEADER_SIZE;
+        // The payloads of other data messages are in the skeleton, but only their msg_id is needed.
+        let needed = if kind == b'D' {
+            size.min(MSG_ID_SIZE)
+        } else {
+            size
+        };
+        let payload = skeleton.get(start..start + needed).ok_or_else(truncated)?;
+
+        let end = match layouts.message(kind, size, payload) {
+            Some(stream) => {
+                f(&skeleton[pos..start + MSG_ID_SIZE], Some(stream));
+                start + MSG_ID_SIZE
+            }
+            None => {
+                let end = start + size;
+                f(skeleton.get(pos..end).ok_or_else(truncated)?, None);
+                end
+            }
+        };
+        pos = end;
+    }
+    Ok(())
+}
+
+fn message_header(bytes: &[u8], pos: usize) -> Option<(usize, u8)> {
+    let header = bytes.get(pos..pos + MESSAGE_HEADER_SIZE)?;
+    Some((usize::from(LittleEndian::read_u16(header)), header[2]))
+}
+
+/// Tracks the formats and subscriptions of a log, to decide which data messages are split into columns.
+///
+/// Both directions see the same definitions and message headers in the same order, so they make the same
+/// decisions.
+#[derive(Debug, Default)]
+struct Layouts {
+    formats: HashMap<Shared<str>, Shared<def::Format>>,
+    // msg_id -> the stream of its current subscription.
+    subscriptions: HashMap<u16, usize>,
+    streams: Vec<Stream>,
+}
+
+/// The rows of a subscription.
+#[derive(Debug)]
+struct Stream {
+    // The width of each scalar of the format.
+    widths: Vec<usize>,
+    // The sizes of a row without trailing padding, and with it.
+    min_size: usize,
+    // The size of the rows, which is the size of the first data message in range.
+    row_size: Option<usize>,
+    rows: usize,
+}
+
+impl Stream {
+    /// The widths of the scalars of a row, as far as they fit in `size` bytes.
+    fn prefix(&self, size: usize) -> &[usize] {
+        let mut total = 0;
+        let count = self
+            .widths
+            .iter()
+            .take_while(|&&width| {
+                total += width;
+                total <= size
+            })
+            .count();
+        &self.widths[..count]
+    }
+
+    fn row_widths(&self) -> &[usize] {
+        self.prefix(self.row_size.unwrap_or(0))
+    }
+
+    /// Whether rows of `size` bytes can be split into columns: only trailing padding may be left out.
+    fn fits(&self, size: usize) -> bool {
+        size >= self.min_size && self.prefix(size).iter().sum::<usize>() == size
+    }
+}
+
+impl Layouts {
+    /// Returns the stream which the payload of a data message moves to, or `None` if the message is kept whole.
+    ///
+    /// The `payload` of data messages only needs to hold the msg_id.
+    fn message(&mut self, kind: u8, size: usize, payload: &[u8]) -> Option<usize> {
+        match kind {
+            b'F' => {
+                if let Ok(format) = parse_format(MessageBuf::from_vec(payload.to_vec())) {
+                    self.formats
+                        .insert(format.name.clone(), Shared::new(format));
+                }
+                None
+            }
+            b'A' if payload.len() > 3 => {
+                let msg_id = LittleEndian::read_u16(&payload[1..3]);
+                let stream = std::str::from_utf8(&payload[3..])
+                    .ok()
+                    .and_then(|name| self.formats.get(name))
+                    .and_then(|format| {
+                        let mut widths = Vec::new();
+                        scalar_widths(format, &self.formats, 0, &mut widths)?;
+                        Some(Stream {
+                            widths,
+                            min_size: format.min_byte_size(&self.formats).ok()?,
+                            row_size: None,
+                            rows: 0,
+                        })
+                    });
+                match stream {
+                    Some(stream) => {
+                        self.streams.push(stream);
+                        self.subscriptions.insert(msg_id, self.streams.len() - 1);
+                    }
+                    None => {
+                        self.subscriptions.remove(&msg_id);
+                    }
+                }
+                None
+            }
+            b'D' if size >= MSG_ID_SIZE => {
+                let msg_id = LittleEndian::read_u16(payload);
+                let index = *self.subscriptions.get(&msg_id)?;
+                let stream = &mut self.streams[index];
+                let row_size = size - MSG_ID_SIZE;
+                if stream.row_size.is_none() && stream.fits(row_size) {
+                    stream.row_size = Some(row_size);
+                }
+                if stream.row_size != Some(row_size) {
+                    return None;
+                }
+                stream.rows += 1;
+                Some(index)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn scalar_widths(
+    format: &def::Format,
+    formats: &HashMap<Shared<str>, Shared<def::Format>>,
+    depth: usize,
+    widths: &mut Vec<usize>,
+) -> Option<()> {
+    if depth > MAX_NESTING {
+        return None;
+    }
+    for field in &format.fields {
+        let count = field.r#type.array_size.unwrap_or(1);
+        match (
+            &field.r#type.base_type,
+            field.r#type.base_type.primitive_size(),
+        ) {
+            (_, Some(width)) => widths.extend(std::iter::repeat_n(width, count)),
+            (def::BaseType::OTHER(name), None) => {
+                let nested = formats.get(name.as_str())?;
+                for _ in 0..count {
+                    scalar_widths(nested, formats, depth + 1, widths)?;
+                }
+            }
+            _ => unreachable!("all other base types are primitives"),
+        }
+    }
+    Some(())
+}
+
+/// Transposes rows of scalars with `widths` into delta encoded columns, each split into byte planes.
+fn to_columns(widths: &[usize], rows: &[u8]) -> Vec<u8> {
+    let row_size: usize = widths.iter().sum();
+    let count = rows.len().checked_div(row_size).unwrap_or(0);
+    let mut columns = vec![0; rows.len()];
+
+    let mut offset = 0;
+    for &width in widths {
+        let mut previous = 0u64;
+        for row in 0..count {
+            let value = LittleEndian::read_uint(&rows[row * row_size + offset..], width);
+            let delta = value.wrapping_sub(previous);
+            previous = value;
+            for plane in 0..width {
+                columns[offset * count + plane * count + row] = (delta >> (8 * plane)) as u8;
+            }
+        }
+        offset += width;
+    }
+    columns
+}
+
+/// Reverses `to_columns()`.
+fn from_columns(widths: &[usize], columns: &[u8]) -> Vec<u8> {
+    let row_size: usize = widths.iter().sum();
+    let count = columns.len().checked_div(row_size).unwrap_or(0);
+    let mut rows = vec![0; columns.len()];
+
+    let mut offset = 0;
+    for &width in widths {
+        let mask = if width == 8 {
+            u64::MAX
+        } else {
+            (1 << (8 * width)) - 1
+        };
+        let mut value = 0u64;
+        for row in 0..count {
+            let mut delta = 0u64;
+            for plane in 0..width {
+                delta |= u64::from(columns[offset * count + plane * count + row]) << (8 * plane);
+            }
+            value = value.wrapping_add(delta) & mask;
+            LittleEndian::write_uint(&mut rows[row * row_size + offset..], value, width);
+        }
+        offset += width;
+    }
+    rows
+}
+
+fn write_section(writer: &mut impl Write, bytes: &[u8]) -> Result<(), ULogError> {
+    writer.write_all(&(bytes.len() as u64).to_le_bytes())?;
+    writer.write_all(bytes)?;
+    Ok(())
+}
+
+fn read_section<'a>(rest: &mut &'a [u8]) -> Result<&'a [u8], ULogError> {
+    let truncated = || invalid("The container is truncated.");
+    let len = rest.get(..8).ok_or_else(truncated)?;
+    let len = usize::try_from(LittleEndian::read_u64(len)).map_err(|_| truncated())?;
+    let section = rest
+        .get(8..8usize.saturating_add(len))
+        .ok_or_else(truncated)?;
+    *rest = &rest[8 + len..];
+    Ok(section)
+}
+
+fn invalid(message: &str) -> ULogError {
+    ULogError::InvalidDeltaContainer(message.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::def::BaseType;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    const SAMPLE_LOG: &str = "test_data/input/sample_log_small.ulg";
+
+    #[test]
+    fn test_round_trip() -> Result<(), ULogError> {
+        let mut log = LogBuilder::new()
+            .topic(
+                Topic::new("sensor_accel")
+                    .rate_hz(200.0)
+                    .field(
+                        "x",
+                        BaseType::FLOAT,
+                        Generator::Sine {
+                            amplitude: 9.8,
+                            period: std::time::Duration::from_secs(1),
+                        },
+                    )
+                    .array_field(
+                        "samples",
+                        BaseType::INT16,
+                        4,
+                        Generator::Ramp {
+                            start: -50.0,
+                            step: 1.0,
+                        },
+                    ),
+            )
+            .build()?;
+        // Bytes which aren't messages are carried over too.
+        log.extend_from_slice(&[0x40, 0x00]);
+
+        let container = encode(log.as_slice(), Vec::new())?;
+        assert_eq!(decode(container.as_slice(), Vec::new())?, log);
+
+        let sample = std::fs::read(SAMPLE_LOG)?;
+        let container = encode(sample.as_slice(), Vec::new())?;
+        assert_eq!(decode(container.as_slice(), Vec::new())?, sample);
+
+        assert!(matches!(
+            decode(&container[..container.len() - 1], Vec::new()),
+            Err(ULogError::InvalidDeltaContainer(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_columns() {
+        // Two rows of a u16 and a u8.
+        let rows = [0x01, 0x01, 0xFF, 0x03, 0x01, 0x01];
+        let columns = to_columns(&[2, 1], &rows);
+        assert_eq!(columns, [0x01, 0x02, 0x01, 0x00, 0xFF, 0x02]);
+        assert_eq!(from_columns(&[2, 1], &columns), rows);
+    }
+}
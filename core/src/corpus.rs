@@ -0,0 +1,368 @@
+//! A corpus of public PX4 logs, of varying firmware versions and sizes, for tests and benches.
+//!
+//! The logs are listed in a manifest, one per line, as the name, firmware version, size in bytes, SHA-256 checksum and
+//! URL of the log, separated by whitespace.  Lines starting with `#` are comments.  `test_data/corpus.txt` is the
+//! manifest of the corpus of this crate.
+//!
+//! Each log is downloaded once into the cache directory, `$YULE_LOG_CORPUS_DIR` or `yule_log_corpus` in the temporary
+//! directory, and its size and checksum are verified each time it is fetched.
+//!
+//! ```rust,no_run
+//! use yule_log::builder::ULogParserBuilder;
+//! use yule_log::corpus::Corpus;
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let corpus = Corpus::from_manifest_file(Corpus::default_cache_dir(), "test_data/corpus.txt")?;
+//! for log in corpus.logs() {
+//!     let path = corpus.fetch(log)?;
+//!     let messages = ULogParserBuilder::new(std::fs::File::open(path)?).build()?.count();
+//!     println!("{} ({}): {messages} messages", log.name, log.firmware);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+use ureq::Agent;
+
+use crate::errors::ULogError;
+
+/// A log of the corpus.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorpusLog {
+    /// The name of the log, which is also the name of its file in the cache.
+    pub name: String,
+    pub firmware: String,
+    pub size: u64,
+    /// The SHA-256 checksum of the log, in lower case hex.
+    pub sha256: String,
+    pub url: String,
+}
+
+impl CorpusLog {
+    /// The entry for the downloaded log at `path`, with its size and checksum computed from the file.  The entry
+    /// displays as its line of the manifest, so that logs are added to the manifest without writing the checksums by
+    /// hand:  see `examples/corpus_entry.rs`.
+    pub fn from_file(
+        path: impl AsRef<Path>,
+        firmware: impl Into<String>,
+        url: impl Into<String>,
+    ) -> Result<Self, ULogError> {
+        let path = path.as_ref();
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| {
+                ULogError::InvalidCorpusLog(format!("{} has no file name.", path.display()))
+            })?;
+        Ok(CorpusLog {
+            name: name.to_owned(),
+            firmware: firmware.into(),
+            size: fs::metadata(path)?.len(),
+            sha256: sha256_file(path)?,
+            url: url.into(),
+        })
+    }
+}
+
+impl fmt::Display for CorpusLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<24} {:<10} {:<11} {} {}",
+            self.name, self.firmware, self.size, self.sha256, self.url
+        )
+    }
+}
+
+/// The logs of a manifest, and the directory they are cached in.  See the module documentation.
+#[derive(Debug, Clone)]
+pub struct Corpus {
+    cache_dir: PathBuf,
+    logs: Vec<CorpusLog>,
+}
+
+impl Corpus {
+    pub fn new(cache_dir: impl Into<PathBuf>, logs: Vec<CorpusLog>) -> Self {
+        Corpus {
+            cache_dir: cache_dir.into(),
+            logs,
+        }
+    }
+
+    /// Reads the logs of a manifest.
+    ///
+    /// Returns `ULogError::InvalidCorpusLog` if a line doesn't have the five columns, or the size or checksum is
+    /// invalid.
+    pub fn from_manifest(cache_dir: impl Into<PathBuf>, manifest: &str) -> Result<Self, ULogError> {
+        let logs = manifest
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+            .map(|(i, line)| {
+                parse_line(line)
+                    .map_err(|e| ULogError::InvalidCorpusLog(format!("Line {}: {e}", i + 1)))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self::new(cache_dir, logs))
+    }
+
+    pub fn from_manifest_file(
+        cache_dir: impl Into<PathBuf>,
+        path: impl AsRef<Path>,
+    ) -> Result<Self, ULogError> {
+        Self::from_manifest(cache_dir, &fs::read_to_string(path)?)
+    }
+
+    /// `$YULE_LOG_CORPUS_DIR`, or `yule_log_corpus` in the temporary directory.
+    pub fn default_cache_dir() -> PathBuf {
+        std::env::var_os("YULE_LOG_CORPUS_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| std::env::temp_dir().join("yule_log_corpus"))
+    }
+
+    pub fn logs(&self) -> &[CorpusLog] {
+        &self.logs
+    }
+
+    /// Where `log` is cached, whether or not it has been fetched.
+    pub fn path(&self, log: &CorpusLog) -> PathBuf {
+        self.cache_dir.join(&log.name)
+    }
+
+    /// Returns the path of `log` in the cache, downloading it unless it is already cached.
+    ///
+    /// Returns `ULogError::InvalidCorpusLog` if the size or checksum of the log isn't that of the manifest.  A cached
+    /// log which fails the check is downloaded again.
+    pub fn fetch(&self, log: &CorpusLog) -> Result<PathBuf, ULogError> {
+        let path = self.path(log);
+        if path.exists() && verify(log, &path).is_ok() {
+            return Ok(path);
+        }
+
+        fs::create_dir_all(&self.cache_dir)?;
+        // Downloaded beside the log, so that an interrupted download is never taken for the log.
+        let partial = path.with_extension("part");
+        download(&log.url, &partial)?;
+        if let Err(e) = verify(log, &partial) {
+            fs::remove_file(&partial)?;
+            return Err(e);
+        }
+        fs::rename(&partial, &path)?;
+        Ok(path)
+    }
+
+    /// Fetches each log, in the order of the manifest.
+    pub fn fetch_all(&self) -> Result<Vec<PathBuf>, ULogError> {
+        self.logs.iter().map(|log| self.fetch(log)).collect()
+    }
+
+    /// The logs which are already cached and pass the check, with their paths.  Tests which shouldn't need the
+    /// network can run over these.
+    pub fn cached(&self) -> Vec<(&CorpusLog, PathBuf)> {
+        self.logs
+            .iter()
+            .map(|log| (log, self.path(log)))
+            .filter(|(log, path)| path.exists() && verify(log, path).is_ok())
+            .collect()
+    }
+}
+
+fn parse_line(line: &str) -> Result<CorpusLog, String> {
+    let columns: Vec<&str> = line.split_whitespace().collect();
+    let [name, firmware, size, sha256, url] = columns[..] else {
+        return Err(format!(
+            "Expected the name, firmware, size, sha256 and url of a log, found {} columns.",
+            columns.len()
+        ));
+    };
+    let size = size
+        .parse()
+        .map_err(|_| format!("Invalid size `{size}`."))?;
+    if sha256.len() != 64 || !sha256.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(format!("Invalid SHA-256 checksum `{sha256}`."));
+    }
+    if name.contains(['/', '\\']) {
+        return Err(format!("The name `{name}` isn't a file name."));
+    }
+
+    Ok(CorpusLog {
+        name: name.to_owned(),
+        firmware: firmware.to_owned(),
+        size,
+        sha256: sha256.to_ascii_lowercase(),
+        url: url.to_owned(),
+    })
+}
+
+fn download(url: &str, path: &Path) -> Result<(), ULogError> {
+    let mut response = Agent::new_with_defaults()
+        .get(url)
+        .call()
+        .map_err(ureq::Error::into_io)?;
+    let mut file = File::create(path)?;
+    io::copy(&mut response.body_mut().as_reader(), &mut file)?;
+    file.flush()?;
+    Ok(())
+}
+
+fn verify(log: &CorpusLog, path: &Path) -> Result<(), ULogError> {
+    let size = fs::metadata(path)?.len();
+    if size != log.size {
+        return Err(ULogError::InvalidCorpusLog(format!(
+            "{} is {size} bytes, but the manifest gives {}.",
+            log.name, log.size
+        )));
+    }
+
+    let sha256 = sha256_file(path)?;
+    if sha256 != log.sha256 {
+        return Err(ULogError::InvalidCorpusLog(format!(
+            "The SHA-256 checksum of {} is {sha256}, but the manifest gives {}.",
+            log.name, log.sha256
+        )));
+    }
+    Ok(())
+}
+
+fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+
+    /// Serves `bytes` on a local port, and counts the requests.
+    fn serve(bytes: Vec<u8>) -> io::Result<(String, Arc<AtomicUsize>)> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/sample.ulg", listener.local_addr()?);
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        let counter = requests.clone();
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok_and(|n| n > 0) && line != "\r\n" {
+                    line.clear();
+                }
+                counter.fetch_add(1, Ordering::Relaxed);
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    bytes.len()
+                );
+                let _ = stream.write_all(&bytes);
+            }
+        });
+
+        Ok((url, requests))
+    }
+
+    #[test]
+    fn test_fetch() -> Result<(), ULogError> {
+        let bytes = fs::read("test_data/input/sample_log_small.ulg")?;
+        let (url, requests) = serve(bytes.clone())?;
+        let dir = tempfile::tempdir()?;
+        let sha256 = sha256_file(Path::new("test_data/input/sample_log_small.ulg"))?;
+
+        let manifest = format!(
+            "# name firmware size sha256 url\n\nsample.ulg v1.14.0 {} {sha256} {url}\nbad.ulg v1.14.0 {} {} {url}\n",
+            bytes.len(),
+            bytes.len(),
+            "0".repeat(64)
+        );
+        let corpus = Corpus::from_manifest(dir.path(), &manifest)?;
+        let [sample, bad] = corpus.logs() else {
+            panic!("expected two logs");
+        };
+        assert_eq!(sample.firmware, "v1.14.0");
+        assert!(corpus.cached().is_empty());
+
+        let path = corpus.fetch(sample)?;
+        assert_eq!(fs::read(&path)?, bytes);
+        ULogParserBuilder::new(File::open(&path)?)
+            .build()?
+            .collect::<Result<Vec<_>, _>>()?;
+        // Cached, so not downloaded again.
+        corpus.fetch(sample)?;
+        assert_eq!(requests.load(Ordering::Relaxed), 1);
+        assert_eq!(corpus.cached().len(), 1);
+
+        assert!(matches!(
+            corpus.fetch(bad),
+            Err(ULogError::InvalidCorpusLog(_))
+        ));
+        assert!(!corpus.path(bad).exists());
+        assert!(!corpus.path(bad).with_extension("part").exists());
+
+        assert!(Corpus::from_manifest(dir.path(), "sample.ulg v1.14.0 12").is_err());
+        assert!(
+            Corpus::from_manifest(dir.path(), &format!("sample.ulg v1.14.0 x {sha256} {url}"))
+                .is_err()
+        );
+        assert!(
+            Corpus::from_manifest(dir.path(), &format!("sample.ulg v1.14.0 1 abc {url}")).is_err()
+        );
+
+        // The entry computed from the file is the line of the manifest, and reads back as the same log.
+        let entry = CorpusLog::from_file(&path, "v1.14.0", url.clone())?;
+        assert_eq!(entry, *sample);
+        let line = entry.to_string();
+        assert_eq!(Corpus::from_manifest(dir.path(), &line)?.logs(), [entry]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_manifest() -> Result<(), ULogError> {
+        Corpus::from_manifest_file(Corpus::default_cache_dir(), "test_data/corpus.txt")?;
+        Ok(())
+    }
+
+    /// Fetches the whole corpus, so only run on request: `cargo test --features dev_corpus -- --ignored`.
+    #[test]
+    #[ignore]
+    fn test_corpus_parses() -> Result<(), ULogError> {
+        let corpus =
+            Corpus::from_manifest_file(Corpus::default_cache_dir(), "test_data/corpus.txt")?;
+        // Otherwise the test would pass without parsing anything.
+        assert!(
+            !corpus.logs().is_empty(),
+            "test_data/corpus.txt lists no logs"
+        );
+        for path in corpus.fetch_all()? {
+            ULogParserBuilder::new(File::open(&path)?)
+                .build()?
+                .collect::<Result<Vec<_>, _>>()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,205 @@
+use std::io::Read;
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use chacha20::cipher::{KeyIvInit, StreamCipher};
+use chacha20::XChaCha20;
+
+use crate::errors::ULogError;
+use crate::parser::ULogParser;
+
+/// Magic bytes of an encrypted log (.ulge).
+const ENCRYPTED_MAGIC: &[u8; 7] = b"ULogEnc";
+
+/// Magic bytes of a detached key file (.ulgk), as written by older PX4 versions.
+const KEY_FILE_MAGIC: &[u8; 7] = b"ULogKey";
+
+/// The only symmetric algorithm PX4 uses for log encryption.
+const XCHACHA20_KEY_SIZE: usize = 32;
+const XCHACHA20_NONCE_SIZE: usize = 24;
+
+/// The key header at the start of an encrypted ULOG file.
+///
+/// PX4 encrypts the log contents with XChaCha20, using a random symmetric key which is itself
+/// encrypted with the configured exchange algorithm, typically RSA-OAEP, and stored in this header.
+#[derive(Debug, Clone)]
+pub struct KeyHeader {
+    pub version: u8,
+    pub timestamp: u64,
+    pub exchange_algorithm: u8,
+    pub exchange_key: u8,
+    /// The symmetric key, encrypted with `exchange_algorithm`.
+    pub wrapped_key: Vec<u8>,
+    /// The XChaCha20 nonce.
+    pub nonce: Vec<u8>,
+}
+
+impl KeyHeader {
+    /// Reads a key header from the start of an encrypted log or a key file.
+    pub fn read<R: Read>(reader: &mut R) -> Result<Self, ULogError> {
+        let mut magic = [0u8; 7];
+        reader.read_exact(&mut magic)?;
+
+        if &magic != ENCRYPTED_MAGIC && &magic != KEY_FILE_MAGIC {
+            return Err(ULogError::InvalidEncryptedLog(
+                "Invalid magic bits in key header.".to_owned(),
+            ));
+        }
+
+        let version = reader.read_u8()?;
+        let timestamp = reader.read_u64::<LittleEndian>()?;
+        let exchange_algorithm = reader.read_u8()?;
+        let exchange_key = reader.read_u8()?;
+        let key_size = reader.read_u16::<LittleEndian>()?;
+        let nonce_size = reader.read_u16::<LittleEndian>()?;
+
+        let mut wrapped_key = vec![0u8; key_size as usize];
+        reader.read_exact(&mut wrapped_key)?;
+
+        let mut nonce = vec![0u8; nonce_size as usize];
+        reader.read_exact(&mut nonce)?;
+
+        Ok(KeyHeader {
+            version,
+            timestamp,
+            exchange_algorithm,
+            exchange_key,
+            wrapped_key,
+            nonce,
+        })
+    }
+}
+
+/// A reader which decrypts the contents of an encrypted ULOG file.
+pub struct DecryptingReader<R: Read> {
+    reader: R,
+    cipher: XChaCha20,
+}
+
+impl<R: Read> DecryptingReader<R> {
+    /// Reads the key header from `reader`, and decrypts the remainder using `key`.
+    ///
+    /// `key` is the unwrapped symmetric key.  Unwrapping `KeyHeader::wrapped_key` requires the private
+    /// key belonging to the vehicle's exchange key, and is left to the caller. See `with_header()`.
+    pub fn new(mut reader: R, key: &[u8]) -> Result<Self, ULogError> {
+        let header = KeyHeader::read(&mut reader)?;
+        Self::with_header(reader, &header, key)
+    }
+
+    /// Decrypts `reader`, which is positioned immediately after a previously read key header.
+    pub fn with_header(reader: R, header: &KeyHeader, key: &[u8]) -> Result<Self, ULogError> {
+        if key.len() != XCHACHA20_KEY_SIZE {
+            return Err(ULogError::InvalidEncryptedLog(format!(
+                "Expected a {XCHACHA20_KEY_SIZE} byte key, got {} bytes.",
+                key.len()
+            )));
+        }
+
+        if header.nonce.len() != XCHACHA20_NONCE_SIZE {
+            return Err(ULogError::InvalidEncryptedLog(format!(
+                "Expected a {XCHACHA20_NONCE_SIZE} byte nonce, got {} bytes.",
+                header.nonce.len()
+            )));
+        }
+
+        let cipher = XChaCha20::new(key.into(), header.nonce.as_slice().into());
+
+        Ok(DecryptingReader { reader, cipher })
+    }
+}
+
+impl<R: Read> Read for DecryptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.cipher.apply_keystream(&mut buf[..n]);
+        Ok(n)
+    }
+}
+
+impl<R: Read> ULogParser<DecryptingReader<R>> {
+    /// Creates a parser for an encrypted ULOG file (.ulge).
+    ///
+    /// `key` is the unwrapped 32 byte XChaCha20 key. Use `ULogParserBuilder::new(DecryptingReader::new(reader, key)?)`
+    /// to configure the parser.
+    pub fn from_encrypted(reader: R, key: &[u8]) -> Result<Self, ULogError> {
+        ULogParser::new(DecryptingReader::new(reader, key)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::BufReader;
+
+    use super::*;
+
+    const KEY: [u8; 32] = [7u8; 32];
+    const NONCE: [u8; 24] = [3u8; 24];
+
+    fn encrypt(plain: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(ENCRYPTED_MAGIC);
+        bytes.push(1); // hdr_ver
+        bytes.extend_from_slice(&1234u64.to_le_bytes());
+        bytes.push(2); // exchange_algorithm: RSA-OAEP
+        bytes.push(1); // exchange_key
+        bytes.extend_from_slice(&4u16.to_le_bytes());
+        bytes.extend_from_slice(&(NONCE.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(&[0xAA; 4]);
+        bytes.extend_from_slice(&NONCE);
+
+        let mut contents = plain.to_vec();
+        XChaCha20::new(&KEY.into(), &NONCE.into()).apply_keystream(&mut contents);
+        bytes.extend_from_slice(&contents);
+        bytes
+    }
+
+    #[test]
+    fn test_key_header() -> Result<(), ULogError> {
+        let bytes = encrypt(&[]);
+        let header = KeyHeader::read(&mut bytes.as_slice())?;
+
+        assert_eq!(header.version, 1);
+        assert_eq!(header.timestamp, 1234);
+        assert_eq!(header.exchange_algorithm, 2);
+        assert_eq!(header.exchange_key, 1);
+        assert_eq!(header.wrapped_key, vec![0xAA; 4]);
+        assert_eq!(header.nonce, NONCE.to_vec());
+
+        assert!(matches!(
+            KeyHeader::read(&mut b"ULog\x01\x12\x35\x01".as_slice()),
+            Err(ULogError::InvalidEncryptedLog(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_encrypted() -> Result<(), ULogError> {
+        let plain = std::fs::read("test_data/input/sample_log_small.ulg")?;
+        let encrypted = encrypt(&plain);
+
+        let expected: Vec<String> = ULogParser::new(BufReader::new(File::open(
+            "test_data/input/sample_log_small.ulg",
+        )?))?
+        .map(|msg| msg.map(|msg| format!("{msg:?}")))
+        .collect::<Result<_, _>>()?;
+
+        let actual: Vec<String> = ULogParser::from_encrypted(encrypted.as_slice(), &KEY)?
+            .map(|msg| msg.map(|msg| format!("{msg:?}")))
+            .collect::<Result<_, _>>()?;
+
+        assert!(!actual.is_empty());
+        assert_eq!(expected, actual);
+
+        // A wrong key produces garbage, which fails the header check.
+        let result = ULogParser::from_encrypted(encrypted.as_slice(), &[8u8; 32])?.next();
+        assert!(matches!(result, Some(Err(ULogError::InvalidHeader))));
+
+        assert!(matches!(
+            ULogParser::from_encrypted(encrypted.as_slice(), &KEY[..16]),
+            Err(ULogError::InvalidEncryptedLog(_))
+        ));
+
+        Ok(())
+    }
+}
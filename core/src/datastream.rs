@@ -1,34 +1,151 @@
-use std::io::{ErrorKind, Read};
+use std::io::{self, ErrorKind, IoSliceMut, Read, Seek, SeekFrom};
 
 use byteorder::{ByteOrder, LittleEndian};
 
+use crate::diag;
 use crate::errors::ULogError;
 
+/// The default size of the read buffer of a `DataStream`.
+pub const DEFAULT_READ_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Reads the primitive values of a log from `reader`.
+///
+/// Reads are buffered, so that the many small reads of a message's fields don't each reach the reader, which
+/// matters for readers without a buffer of their own, such as sockets.  A read at least as large as the buffer fills
+/// the destination and refills the buffer with one vectored read.
 #[derive(Debug)]
 pub struct DataStream<R: Read> {
     reader: R,
+    buf: Box<[u8]>,
+    // The unread bytes of the buffer are `buf[pos..filled]`.
+    pos: usize,
+    filled: usize,
+    // Moves the reader on by up to the given number of bytes, returning how far it moved.  Set for seekable readers.
+    seek_forward: Option<fn(&mut R, u64) -> io::Result<u64>>,
     pub(crate) num_bytes_read: usize,
     pub(crate) eof: bool,
 }
 
+impl<R: Read + Seek> DataStream<R> {
+    /// Moves the underlying reader to `offset`, counted from the start of the stream.
+    pub(crate) fn seek_to(&mut self, offset: usize) -> Result<(), ULogError> {
+        self.reader.seek(SeekFrom::Start(offset as u64))?;
+        self.discard_buffer();
+        self.num_bytes_read = offset;
+        self.eof = false;
+        Ok(())
+    }
+}
+
+/// Moves `reader` on by up to `num_bytes`, stopping at its end, and returns how far it moved.
+pub(crate) fn seek_forward<R: Seek>(reader: &mut R, num_bytes: u64) -> io::Result<u64> {
+    let position = reader.stream_position()?;
+    let end = reader.seek(SeekFrom::End(0))?;
+    let target = position.saturating_add(num_bytes).min(end.max(position));
+    reader.seek(SeekFrom::Start(target))?;
+    Ok(target - position)
+}
+
 impl<R: Read> DataStream<R> {
     pub fn new(reader: R) -> DataStream<R> {
+        Self::with_buffer_size(reader, DEFAULT_READ_BUFFER_SIZE)
+    }
+
+    /// Like `new()`, with a read buffer of `size` bytes.  A size of 0 leaves the reads unbuffered.
+    pub fn with_buffer_size(reader: R, size: usize) -> DataStream<R> {
         DataStream {
             reader,
+            buf: vec![0; size].into_boxed_slice(),
+            pos: 0,
+            filled: 0,
+            seek_forward: None,
             num_bytes_read: 0,
             eof: false,
         }
     }
 
+    /// Makes `skip()` seek over the bytes with `seek_forward`, rather than read them.
+    pub(crate) fn set_seek_forward(&mut self, seek_forward: fn(&mut R, u64) -> io::Result<u64>) {
+        self.seek_forward = Some(seek_forward);
+    }
+
+    /// Replaces the read buffer with one of `size` bytes.  Bytes already buffered are kept.
+    pub(crate) fn set_buffer_size(&mut self, size: usize) {
+        let buffered = &self.buf[self.pos..self.filled];
+        let mut buf = vec![0; size.max(buffered.len())].into_boxed_slice();
+        buf[..buffered.len()].copy_from_slice(buffered);
+        self.filled = buffered.len();
+        self.pos = 0;
+        self.buf = buf;
+    }
+
+    /// Puts `bytes`, which were the last read, back in front of the unread bytes, so that they are read again.  The
+    /// buffer grows if it can't hold them.
+    pub(crate) fn unread(&mut self, bytes: &[u8]) {
+        let buffered = &self.buf[self.pos..self.filled];
+        let len = bytes.len() + buffered.len();
+        let mut buf = vec![0; self.buf.len().max(len)].into_boxed_slice();
+        buf[..bytes.len()].copy_from_slice(bytes);
+        buf[bytes.len()..len].copy_from_slice(buffered);
+        (self.buf, self.pos, self.filled) = (buf, 0, len);
+        self.num_bytes_read -= bytes.len();
+        self.eof = false;
+    }
+
+    fn discard_buffer(&mut self) {
+        self.pos = 0;
+        self.filled = 0;
+    }
+
+    // Fills `dst` from the buffer, then from the reader, refilling the buffer in the same read.
+    fn fill_exact(&mut self, dst: &mut [u8]) -> io::Result<()> {
+        let buffered = (self.filled - self.pos).min(dst.len());
+        dst[..buffered].copy_from_slice(&self.buf[self.pos..self.pos + buffered]);
+        self.pos += buffered;
+
+        let mut dst = &mut dst[buffered..];
+        while !dst.is_empty() {
+            self.discard_buffer();
+            // Small reads are served from the buffer.  Larger ones go straight to `dst`, with the buffer refilled in
+            // the same read by readers which support vectored reads.
+            let read = if dst.len() < self.buf.len() {
+                self.reader.read(&mut self.buf)
+            } else {
+                self.reader
+                    .read_vectored(&mut [IoSliceMut::new(dst), IoSliceMut::new(&mut self.buf)])
+            };
+            let n = match read {
+                Ok(0) => return Err(io::Error::from(ErrorKind::UnexpectedEof)),
+                Ok(n) => n,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+
+            if dst.len() < self.buf.len() {
+                self.filled = n;
+                let len = n.min(dst.len());
+                dst[..len].copy_from_slice(&self.buf[..len]);
+                self.pos = len;
+                dst = &mut dst[len..];
+            } else if n < dst.len() {
+                dst = &mut dst[n..];
+            } else {
+                self.filled = n - dst.len();
+                dst = &mut [];
+            }
+        }
+        Ok(())
+    }
+
     pub fn read_exact(&mut self, buf: &mut [u8]) -> Result<usize, ULogError> {
-        log::trace!(
+        diag::trace!(
             "datastream read from:  [{:04X}-{:04X}]",
             self.num_bytes_read,
             self.num_bytes_read + buf.len()
         );
         self.num_bytes_read += buf.len();
 
-        match self.reader.read_exact(buf) {
+        match self.fill_exact(buf) {
             Ok(()) => Ok(buf.len()),
             Err(err) => match err.kind() {
                 // Eof is not technically an error, so signal it by reporting 0 bytes read and setting eof true.
@@ -41,28 +158,47 @@ impl<R: Read> DataStream<R> {
         }
     }
 
-    /// Skips the specified number of bytes in the underlying reader.
+    /// Skips the specified number of bytes in the underlying reader.  Skips which the buffer can't hold are seeked over
+    /// if the reader is seekable, and otherwise read without being stored.
     pub fn skip(&mut self, num_bytes: usize) -> Result<usize, ULogError> {
-        let mut total_skipped = 0;
-        while total_skipped < num_bytes {
-            // Calculate how many bytes remaining to skip
-            let bytes_to_skip = num_bytes - total_skipped;
-
-            // Attempt to read bytes without storing them
-            let bytes_read = self
-                .reader
-                .by_ref()
-                .take(bytes_to_skip as u64)
-                .read_to_end(&mut vec![])
-                .map_err(ULogError::Io)?;
-
-            if bytes_read == 0 {
-                break; // End of stream reached
-            }
-            total_skipped += bytes_read;
+        let buffered = (self.filled - self.pos).min(num_bytes);
+        self.pos += buffered;
+
+        let remaining = num_bytes - buffered;
+        let skipped = buffered
+            + if remaining == 0 {
+                0
+            } else if remaining < self.buf.len() {
+                self.discard_buffer();
+                let mut skipped = 0;
+                while skipped < remaining {
+                    let n = match self.reader.read(&mut self.buf) {
+                        Ok(n) => n,
+                        Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                        Err(err) => return Err(ULogError::Io(err)),
+                    };
+                    if n == 0 {
+                        break;
+                    }
+                    let used = n.min(remaining - skipped);
+                    (self.pos, self.filled) = (used, n);
+                    skipped += used;
+                }
+                skipped
+            } else if let Some(seek_forward) = self.seek_forward {
+                seek_forward(&mut self.reader, remaining as u64)? as usize
+            } else {
+                io::copy(
+                    &mut self.reader.by_ref().take(remaining as u64),
+                    &mut io::sink(),
+                )? as usize
+            };
+
+        if skipped < num_bytes {
+            self.eof = true;
         }
-        self.num_bytes_read += total_skipped;
-        Ok(total_skipped)
+        self.num_bytes_read += skipped;
+        Ok(skipped)
     }
 
     pub fn read_u8(&mut self) -> Result<u8, ULogError> {
@@ -276,7 +412,7 @@ mod tests {
                 }
                 "bool" => {
                     assert_eq!(length, mem::size_of::<bool>());
-                    assert_eq!(datastream.read_bool()?, true);
+                    assert!(datastream.read_bool()?);
                 }
                 "str" => {
                     assert_eq!(length, 5);
@@ -354,4 +490,89 @@ mod tests {
 
         Ok(())
     }
+
+    /// Counts the reads which reach the reader.
+    struct CountingReader<R> {
+        reader: R,
+        reads: usize,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.reads += 1;
+            self.reader.read(buf)
+        }
+    }
+
+    #[test]
+    fn test_buffered_reads() -> Result<(), ULogError> {
+        let bytes: Vec<u8> = (0..=255).cycle().take(1000).collect();
+
+        for size in [0, 3, 64, DEFAULT_READ_BUFFER_SIZE] {
+            let reader = CountingReader {
+                reader: bytes.as_slice(),
+                reads: 0,
+            };
+            let mut datastream = DataStream::with_buffer_size(reader, size);
+
+            assert_eq!(datastream.read_u16()?, u16::from_le_bytes([0, 1]));
+            let mut block = [0; 10];
+            datastream.read_exact(&mut block)?;
+            assert_eq!(block, [2, 3, 4, 5, 6, 7, 8, 9, 10, 11]);
+            assert_eq!(datastream.skip(100)?, 100);
+            assert_eq!(datastream.read_u8()?, 112);
+            let mut rest = vec![0; 887];
+            assert_eq!(datastream.read_exact(&mut rest)?, 887);
+            assert_eq!(rest[..], bytes[113..]);
+            assert_eq!(datastream.num_bytes_read, 1000);
+
+            assert_eq!(datastream.read_u8()?, 0);
+            assert!(datastream.eof);
+
+            if size == DEFAULT_READ_BUFFER_SIZE {
+                // The log fits the buffer, so it is read at once, and the reader is only asked for more at the end.
+                assert_eq!(datastream.reader.reads, 2);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_seek_discards_buffer() -> Result<(), ULogError> {
+        let bytes: Vec<u8> = (0..100).collect();
+        let mut datastream = DataStream::new(std::io::Cursor::new(bytes));
+
+        assert_eq!(datastream.read_u8()?, 0);
+        datastream.seek_to(50)?;
+        assert_eq!(datastream.read_u8()?, 50);
+
+        // Resizing keeps the buffered bytes.
+        datastream.set_buffer_size(4);
+        assert_eq!(datastream.read_u32()?, u32::from_le_bytes([51, 52, 53, 54]));
+        assert_eq!(datastream.num_bytes_read, 55);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_skip_by_seeking() -> Result<(), ULogError> {
+        let bytes: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+        let mut datastream = DataStream::with_buffer_size(std::io::Cursor::new(bytes), 64);
+        datastream.set_seek_forward(seek_forward);
+
+        assert_eq!(datastream.read_u8()?, 0);
+        // Within the buffer, then past it.
+        assert_eq!(datastream.skip(10)?, 10);
+        assert_eq!(datastream.read_u8()?, 11);
+        assert_eq!(datastream.skip(5000)?, 5000);
+        assert_eq!(datastream.read_u8()?, (5012 % 256) as u8);
+        assert_eq!(datastream.reader.position(), 5013 + 63);
+
+        assert_eq!(datastream.skip(6000)?, 10_000 - 5013);
+        assert!(datastream.eof);
+        assert_eq!(datastream.num_bytes_read, 10_000);
+
+        Ok(())
+    }
 }
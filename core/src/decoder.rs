@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::errors::ULogError;
+use crate::message_buf::MessageBuf;
+use crate::model::msg::{UlogMessage, UnknownTyped};
+use crate::parser::ULogMessageType;
+
+/// A user supplied function which decodes the contents of a message with a custom message type.
+///
+/// The `MessageBuf` contains the message contents, without the message header.
+pub type DecodeFn = dyn Fn(&mut MessageBuf) -> Result<UlogMessage, ULogError> + Send + Sync;
+
+/// A registry of decoders for message types which are not part of the ULOG spec.
+///
+/// Firmware forks sometimes log custom or experimental message types.  By default, the parser
+/// emits these as `UlogMessage::UnknownTyped`, carrying the message type and raw payload.
+/// Registering a decoder for a message type allows such messages to be decoded without forking this crate.
+///
+/// # Example
+///
+/// ```rust
+/// use yule_log::decoder::DecoderRegistry;
+/// use yule_log::model::msg::{LogLevel, LoggedString, UlogMessage};
+///
+/// let mut decoders = DecoderRegistry::new();
+///
+/// // A fork which logs plain text messages with message type 'T'.
+/// decoders.register(b'T', |buf| {
+///     let timestamp = buf.take_u64()?;
+///     let msg = String::from_utf8(buf.advance(buf.len())?.to_vec())?;
+///     Ok(UlogMessage::LoggedString(LoggedString { level: LogLevel::Info, tag: None, timestamp, msg }))
+/// }).unwrap();
+/// ```
+#[derive(Default)]
+pub struct DecoderRegistry {
+    decoders: HashMap<u8, Box<DecodeFn>>,
+}
+
+impl DecoderRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a decoder for the given message type, replacing any previously registered decoder.
+    ///
+    /// Returns `ULogError::InvalidConfiguration` if `msg_type` is one of the message types defined
+    /// by the ULOG spec, as these are always decoded by the parser itself.
+    pub fn register<F>(&mut self, msg_type: u8, decoder: F) -> Result<(), ULogError>
+    where
+        F: Fn(&mut MessageBuf) -> Result<UlogMessage, ULogError> + Send + Sync + 'static,
+    {
+        if ULogMessageType::from(msg_type).is_known() {
+            return Err(ULogError::InvalidConfiguration(format!(
+                "Cannot register a decoder for the standard ULOG message type '{}' (0x{msg_type:02X}).",
+                msg_type as char
+            )));
+        }
+
+        self.decoders.insert(msg_type, Box::new(decoder));
+        Ok(())
+    }
+
+    pub fn contains(&self, msg_type: u8) -> bool {
+        self.decoders.contains_key(&msg_type)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.decoders.is_empty()
+    }
+
+    /// Decodes a message with a custom message type.
+    ///
+    /// If no decoder is registered for `msg_type`, the message is returned as `UlogMessage::UnknownTyped`.
+    /// A `UlogMessage::Custom` returned by the decoder is given the message type and payload of the message.
+    pub fn decode(&self, msg_type: u8, mut message_buf: MessageBuf) -> Result<UlogMessage, ULogError> {
+        match self.decoders.get(&msg_type) {
+            Some(decoder) => match decoder(&mut message_buf)? {
+                UlogMessage::Custom(mut custom) => {
+                    custom.msg_type = msg_type;
+                    custom.payload = message_buf.into_bytes();
+                    Ok(UlogMessage::Custom(custom))
+                }
+                other => Ok(other),
+            },
+            None => Ok(UlogMessage::UnknownTyped(UnknownTyped {
+                msg_type,
+                payload: message_buf.into_remaining_bytes(),
+            })),
+        }
+    }
+}
+
+impl fmt::Debug for DecoderRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut msg_types: Vec<_> = self.decoders.keys().collect();
+        msg_types.sort();
+        f.debug_struct("DecoderRegistry")
+            .field("msg_types", &msg_types)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let mut decoders = DecoderRegistry::new();
+        decoders
+            .register(b'X', |buf| {
+                Ok(UlogMessage::UnknownTyped(UnknownTyped {
+                    msg_type: b'X',
+                    payload: vec![buf.take_u8()? + 1],
+                }))
+            })
+            .unwrap();
+
+        match decoders.decode(b'X', MessageBuf::from_vec(vec![41])).unwrap() {
+            UlogMessage::UnknownTyped(msg) => assert_eq!(msg.payload, vec![42]),
+            other => panic!("Unexpected message {other:?}"),
+        }
+
+        // Unregistered types are passed through untouched.
+        match decoders.decode(b'Y', MessageBuf::from_vec(vec![1, 2, 3])).unwrap() {
+            UlogMessage::UnknownTyped(msg) => {
+                assert_eq!(msg.msg_type, b'Y');
+                assert_eq!(msg.payload, vec![1, 2, 3]);
+            }
+            other => panic!("Unexpected message {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_register_standard_type() {
+        let mut decoders = DecoderRegistry::new();
+        let result = decoders.register(b'D', |buf| {
+            Ok(UlogMessage::UnknownTyped(UnknownTyped {
+                msg_type: b'D',
+                payload: buf.advance(buf.len())?.to_vec(),
+            }))
+        });
+
+        assert!(matches!(result, Err(ULogError::InvalidConfiguration(_))));
+        assert!(decoders.is_empty());
+    }
+}
@@ -0,0 +1,255 @@
+//! Computes channels from the aligned samples of several topics, and adds them to the messages as the data of a
+//! topic of their own, so that exporters and analyses see them like logged fields.
+//!
+//! Each channel is a function of an `AlignedRow` of `join::aligned()`, computed each time a row is complete:
+//!
+//! ```rust
+//! use yule_log::builder::ULogParserBuilder;
+//! use yule_log::derived::DerivedChannels;
+//! use yule_log::timeseries;
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+//! let parser = ULogParserBuilder::new(input.as_slice()).build()?;
+//!
+//! let channels = DerivedChannels::new(["battery_status"], 0).derive_channel("power_w", |row| {
+//!     let voltage = row.value(0, "voltage_v").unwrap_or(f64::NAN);
+//!     voltage * row.value(0, "current_a").unwrap_or(f64::NAN)
+//! });
+//!
+//! let envelope = timeseries::envelope(channels.apply(parser), "derived/power_w", 1_000_000)?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod expr;
+
+use std::collections::VecDeque;
+
+use crate::errors::ULogError;
+use crate::derived::expr::Expr;
+use crate::join::{AlignedRow, Aligner};
+use crate::model::def::{self, BaseType, TypeExpr};
+use crate::model::inst::{self, FieldValue};
+use crate::model::msg::{LoggedData, UlogMessage};
+use crate::model::Shared;
+
+/// The msg_id of the data messages of the derived topic, which no subscription of a log uses.
+pub const DERIVED_MSG_ID: u16 = u16::MAX;
+
+/// A function computing a channel.  NaN when the channel can't be computed from a row.
+pub type ChannelFn = dyn Fn(&AlignedRow) -> f64 + Send + Sync;
+
+/// Channels computed from the samples of a set of topics.  See the module documentation.
+pub struct DerivedChannels {
+    topics: Vec<String>,
+    tolerance_us: u64,
+    topic_name: String,
+    channels: Vec<(String, Box<ChannelFn>)>,
+}
+
+impl DerivedChannels {
+    /// Computes channels from `topics`, aligned as by `join::aligned()` with `tolerance_us`.
+    pub fn new<T, S>(topics: T, tolerance_us: u64) -> Self
+    where
+        T: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        DerivedChannels {
+            topics: topics.into_iter().map(Into::into).collect(),
+            tolerance_us,
+            topic_name: "derived".to_owned(),
+            channels: Vec::new(),
+        }
+    }
+
+    /// The name of the topic the channels are added as.  Defaults to `derived`.
+    #[must_use]
+    pub fn topic_name(mut self, name: impl Into<String>) -> Self {
+        self.topic_name = name.into();
+        self
+    }
+
+    /// Adds a channel, which is a `double` field of the derived topic.
+    #[must_use]
+    pub fn derive_channel<F>(mut self, name: impl Into<String>, channel: F) -> Self
+    where
+        F: Fn(&AlignedRow) -> f64 + Send + Sync + 'static,
+    {
+        self.channels.push((name.into(), Box::new(channel)));
+        self
+    }
+
+    /// Adds a channel computed by an expression, e.g. `sqrt(vx*vx + vy*vy)`.  See `expr`.
+    ///
+    /// Returns `ULogError::InvalidExpression` if the expression can't be parsed.
+    pub fn derive_expr(self, name: impl Into<String>, expression: &str) -> Result<Self, ULogError> {
+        let expr = Expr::parse(expression)?;
+        Ok(self.derive_channel(name, move |row| expr.eval(row)))
+    }
+
+    /// Returns `messages`, with a data message of the derived topic after each row of the topics.
+    pub fn apply<I>(self, messages: I) -> Derived<I::IntoIter>
+    where
+        I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+    {
+        let double = TypeExpr {
+            base_type: BaseType::DOUBLE,
+            array_size: None,
+        };
+        let mut fields = vec![def::Field {
+            name: "timestamp".into(),
+            r#type: TypeExpr {
+                base_type: BaseType::UINT64,
+                array_size: None,
+            },
+        }];
+        fields.extend(self.channels.iter().map(|(name, _)| def::Field {
+            name: name.as_str().into(),
+            r#type: double.clone(),
+        }));
+        let format = def::Format {
+            name: self.topic_name.as_str().into(),
+            fields,
+            padding: 0,
+        };
+
+        Derived {
+            messages: messages.into_iter(),
+            aligner: Aligner::new(&self.topics, self.tolerance_us),
+            format: Shared::new(format),
+            channels: self.channels,
+            queued: VecDeque::new(),
+        }
+    }
+}
+
+/// The iterator returned by `DerivedChannels::apply()`.
+pub struct Derived<I> {
+    messages: I,
+    aligner: Aligner,
+    format: Shared<def::Format>,
+    channels: Vec<(String, Box<ChannelFn>)>,
+    queued: VecDeque<UlogMessage>,
+}
+
+impl<I> Derived<I> {
+    fn message(&self, row: &AlignedRow) -> UlogMessage {
+        let fields = self
+            .format
+            .fields
+            .iter()
+            .skip(1)
+            .zip(&self.channels)
+            .map(|(field, (_, channel))| inst::Field {
+                name: field.name.clone(),
+                r#type: field.r#type.clone(),
+                value: FieldValue::ScalarF64(channel(row)),
+            })
+            .collect();
+
+        UlogMessage::LoggedData(LoggedData {
+            timestamp: row.timestamp,
+            msg_id: DERIVED_MSG_ID,
+            data: inst::Format {
+                timestamp: Some(row.timestamp),
+                name: self.format.name.clone(),
+                fields,
+                field_indices: (1..=self.channels.len() as u16).collect(),
+                multi_id_index: None,
+                def_format: self.format.clone(),
+            },
+        })
+    }
+}
+
+impl<I> Iterator for Derived<I>
+where
+    I: Iterator<Item = Result<UlogMessage, ULogError>>,
+{
+    type Item = Result<UlogMessage, ULogError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(msg) = self.queued.pop_front() {
+            return Some(Ok(msg));
+        }
+
+        let data = match self.messages.next() {
+            None => return self.aligner.finish().map(|row| Ok(self.message(&row))),
+            Some(Ok(UlogMessage::LoggedData(data))) => data,
+            other => return other,
+        };
+
+        let Some(index) = self.aligner.position(&data) else {
+            return Some(Ok(UlogMessage::LoggedData(data)));
+        };
+        match self.aligner.push(index, data.timestamp, data.data.clone()) {
+            // The row ended before this sample.
+            Some(row) => {
+                self.queued.push_back(UlogMessage::LoggedData(data));
+                Some(Ok(self.message(&row)))
+            }
+            None => Some(Ok(UlogMessage::LoggedData(data))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+    use crate::timeseries;
+
+    #[test]
+    fn test_derived_channels() -> Result<(), ULogError> {
+        let bytes = LogBuilder::new()
+            .topic(
+                Topic::new("battery_status")
+                    .field("voltage_v", BaseType::FLOAT, Generator::Constant(16.0))
+                    .field(
+                        "current_a",
+                        BaseType::FLOAT,
+                        Generator::Ramp {
+                            start: 1.0,
+                            step: 1.0,
+                        },
+                    ),
+            )
+            .build()?;
+        let parser = ULogParserBuilder::new(bytes.as_slice()).build()?;
+
+        let channels = DerivedChannels::new(["battery_status"], 0)
+            .derive_channel("power_w", |row| {
+                row.value(0, "voltage_v").unwrap() * row.value(0, "current_a").unwrap()
+            })
+            .derive_channel("missing", |row| row.value(0, "x").unwrap_or(f64::NAN))
+            .derive_expr("current_ma", "\"battery_status/current_a\" * 1e3")?;
+        let messages = channels.apply(parser).collect::<Result<Vec<_>, _>>()?;
+
+        let derived: Vec<_> = messages
+            .iter()
+            .filter(|msg| msg.topic_name() == Some("derived"))
+            .collect();
+        assert_eq!(derived.len(), 10);
+        // A row is only complete once the next sample arrives, so each derived message comes right after the
+        // sample it was computed from.
+        let first_data = messages.iter().position(UlogMessage::is_data).unwrap();
+        assert_eq!(messages[first_data + 1].topic_name(), Some("derived"));
+
+        let UlogMessage::LoggedData(first) = derived[0] else {
+            unreachable!()
+        };
+        assert_eq!(first.timestamp, 1_000_000);
+        assert_eq!(first.data.fields[0].value, FieldValue::ScalarF64(16.0));
+        assert!(matches!(first.data.fields[1].value, FieldValue::ScalarF64(v) if v.is_nan()));
+        assert_eq!(first.data.fields[2].value, FieldValue::ScalarF64(1000.0));
+
+        // The channels are fields like any other.
+        let envelope =
+            timeseries::envelope(messages.into_iter().map(Ok), "derived/power_w", 1_000_000)?;
+        assert_eq!(envelope[0].max, 160.0);
+
+        Ok(())
+    }
+}
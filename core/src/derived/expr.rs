@@ -0,0 +1,449 @@
+//! Arithmetic expressions over the columns of aligned samples, for channels defined in configuration rather than
+//! in Rust, e.g. `sqrt(vx*vx + vy*vy)` or `rad2deg("vehicle_attitude/pitch")`.
+//!
+//! Expressions have numbers, the operators `+ - * / ^`, parentheses, the constant `pi`, and the functions `abs`,
+//! `sqrt`, `exp`, `ln`, `log10`, `sin`, `cos`, `tan`, `asin`, `acos`, `atan`, `floor`, `ceil`, `rad2deg`,
+//! `deg2rad`, and of two arguments `atan2`, `hypot`, `pow`, `min` and `max`.  Any other name is a column, as
+//! exported, e.g. `q[0]` or `setpoint.vx`, of the first topic which has it.  A column of a given topic is written in
+//! double quotes as `"topic/column"`, as `/` is division otherwise.  A column which no topic has evaluates to NaN.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::errors::ULogError;
+use crate::join::AlignedRow;
+
+/// A parsed expression.  See the module documentation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    source: String,
+    root: Node,
+}
+
+/// A column named by an expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variable {
+    /// The topic, followed by `.NN` for a multi_id other than 0, if named.
+    pub topic: Option<String>,
+    pub column: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Number(f64),
+    Variable(Variable),
+    Neg(Box<Node>),
+    Binary(char, Box<Node>, Box<Node>),
+    Call(Function, Vec<Node>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Function {
+    Abs,
+    Sqrt,
+    Exp,
+    Ln,
+    Log10,
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    Floor,
+    Ceil,
+    Rad2Deg,
+    Deg2Rad,
+    Atan2,
+    Hypot,
+    Pow,
+    Min,
+    Max,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "abs" => Function::Abs,
+            "sqrt" => Function::Sqrt,
+            "exp" => Function::Exp,
+            "ln" => Function::Ln,
+            "log10" => Function::Log10,
+            "sin" => Function::Sin,
+            "cos" => Function::Cos,
+            "tan" => Function::Tan,
+            "asin" => Function::Asin,
+            "acos" => Function::Acos,
+            "atan" => Function::Atan,
+            "floor" => Function::Floor,
+            "ceil" => Function::Ceil,
+            "rad2deg" => Function::Rad2Deg,
+            "deg2rad" => Function::Deg2Rad,
+            "atan2" => Function::Atan2,
+            "hypot" => Function::Hypot,
+            "pow" => Function::Pow,
+            "min" => Function::Min,
+            "max" => Function::Max,
+            _ => return None,
+        })
+    }
+
+    fn arity(self) -> usize {
+        match self {
+            Function::Atan2 | Function::Hypot | Function::Pow | Function::Min | Function::Max => 2,
+            _ => 1,
+        }
+    }
+
+    fn apply(self, args: &[f64]) -> f64 {
+        let x = args[0];
+        match self {
+            Function::Abs => x.abs(),
+            Function::Sqrt => x.sqrt(),
+            Function::Exp => x.exp(),
+            Function::Ln => x.ln(),
+            Function::Log10 => x.log10(),
+            Function::Sin => x.sin(),
+            Function::Cos => x.cos(),
+            Function::Tan => x.tan(),
+            Function::Asin => x.asin(),
+            Function::Acos => x.acos(),
+            Function::Atan => x.atan(),
+            Function::Floor => x.floor(),
+            Function::Ceil => x.ceil(),
+            Function::Rad2Deg => x.to_degrees(),
+            Function::Deg2Rad => x.to_radians(),
+            Function::Atan2 => x.atan2(args[1]),
+            Function::Hypot => x.hypot(args[1]),
+            Function::Pow => x.powf(args[1]),
+            Function::Min => x.min(args[1]),
+            Function::Max => x.max(args[1]),
+        }
+    }
+}
+
+impl Expr {
+    pub fn parse(source: &str) -> Result<Self, ULogError> {
+        let mut parser = Parser {
+            source,
+            tokens: tokenize(source)?,
+            position: 0,
+        };
+        let root = parser.expr()?;
+        if let Some(token) = parser.tokens.get(parser.position) {
+            return Err(parser.error(&format!("unexpected {token}")));
+        }
+        Ok(Expr {
+            source: source.to_owned(),
+            root,
+        })
+    }
+
+    /// The columns named by the expression, in the order they appear.
+    pub fn variables(&self) -> Vec<&Variable> {
+        let mut variables = Vec::new();
+        self.root.variables(&mut variables);
+        variables
+    }
+
+    /// Evaluates the expression for a row of `join::aligned()`.
+    pub fn eval(&self, row: &AlignedRow) -> f64 {
+        self.eval_with(&|variable| {
+            row.samples.iter().enumerate().find_map(|(i, sample)| {
+                let matches = variable.topic.as_deref().is_none_or(|topic| {
+                    match sample.multi_id_index.filter(|&id| id != 0) {
+                        Some(id) => topic == format!("{}.{id:02}", sample.name),
+                        None => topic == &*sample.name,
+                    }
+                });
+                if matches {
+                    row.value(i, &variable.column)
+                } else {
+                    None
+                }
+            })
+        })
+    }
+
+    /// Evaluates the expression with the values of its variables given by `lookup`.
+    pub fn eval_with(&self, lookup: &dyn Fn(&Variable) -> Option<f64>) -> f64 {
+        self.root.eval(lookup)
+    }
+}
+
+impl FromStr for Expr {
+    type Err = ULogError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        Expr::parse(source)
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Node {
+    fn eval(&self, lookup: &dyn Fn(&Variable) -> Option<f64>) -> f64 {
+        match self {
+            Node::Number(value) => *value,
+            Node::Variable(variable) => lookup(variable).unwrap_or(f64::NAN),
+            Node::Neg(node) => -node.eval(lookup),
+            Node::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.eval(lookup), rhs.eval(lookup));
+                match op {
+                    '+' => lhs + rhs,
+                    '-' => lhs - rhs,
+                    '*' => lhs * rhs,
+                    '/' => lhs / rhs,
+                    _ => lhs.powf(rhs),
+                }
+            }
+            Node::Call(function, args) => {
+                let args: Vec<f64> = args.iter().map(|arg| arg.eval(lookup)).collect();
+                function.apply(&args)
+            }
+        }
+    }
+
+    fn variables<'a>(&'a self, variables: &mut Vec<&'a Variable>) {
+        match self {
+            Node::Number(_) => {}
+            Node::Variable(variable) => variables.push(variable),
+            Node::Neg(node) => node.variables(variables),
+            Node::Binary(_, lhs, rhs) => {
+                lhs.variables(variables);
+                rhs.variables(variables);
+            }
+            Node::Call(_, args) => args.iter().for_each(|arg| arg.variables(variables)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Symbol(char),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(value) => write!(f, "number {value}"),
+            Token::Name(name) => write!(f, "`{name}`"),
+            Token::Symbol(symbol) => write!(f, "`{symbol}`"),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, ULogError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                let exponent_sign = (c == '+' || c == '-') && source[..i].ends_with(['e', 'E']);
+                if !(c.is_ascii_alphanumeric() || c == '.' || exponent_sign) {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let number = &source[start..end];
+            let value = number.parse().map_err(|_| {
+                ULogError::InvalidExpression(format!("`{number}` is not a number in `{source}`."))
+            })?;
+            tokens.push(Token::Number(value));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_alphanumeric() || matches!(c, '_' | '.' | '[' | ']')) {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            tokens.push(Token::Name(source[start..end].to_owned()));
+        } else if c == '"' {
+            chars.next();
+            let name: String = chars
+                .by_ref()
+                .map(|(_, c)| c)
+                .take_while(|&c| c != '"')
+                .collect();
+            if !source[start + 1..].contains('"') {
+                return Err(ULogError::InvalidExpression(format!(
+                    "Unterminated quote in `{source}`."
+                )));
+            }
+            tokens.push(Token::Name(name));
+        } else if "+-*/^(),".contains(c) {
+            tokens.push(Token::Symbol(c));
+            chars.next();
+        } else {
+            return Err(ULogError::InvalidExpression(format!(
+                "Unexpected `{c}` in `{source}`."
+            )));
+        }
+    }
+    Ok(tokens)
+}
+
+// A recursive descent parser of the tokens, by increasing precedence: sums, products, negation and powers.
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, reason: &str) -> ULogError {
+        ULogError::InvalidExpression(format!("{reason} in `{}`.", self.source))
+    }
+
+    fn next_symbol_in(&mut self, symbols: &str) -> Option<char> {
+        match self.tokens.get(self.position) {
+            Some(&Token::Symbol(symbol)) if symbols.contains(symbol) => {
+                self.position += 1;
+                Some(symbol)
+            }
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), ULogError> {
+        self.next_symbol_in(&symbol.to_string())
+            .map(|_| ())
+            .ok_or_else(|| self.error(&format!("expected `{symbol}`")))
+    }
+
+    fn expr(&mut self) -> Result<Node, ULogError> {
+        let mut node = self.term()?;
+        while let Some(op) = self.next_symbol_in("+-") {
+            node = Node::Binary(op, Box::new(node), Box::new(self.term()?));
+        }
+        Ok(node)
+    }
+
+    fn term(&mut self) -> Result<Node, ULogError> {
+        let mut node = self.unary()?;
+        while let Some(op) = self.next_symbol_in("*/") {
+            node = Node::Binary(op, Box::new(node), Box::new(self.unary()?));
+        }
+        Ok(node)
+    }
+
+    fn unary(&mut self) -> Result<Node, ULogError> {
+        if self.next_symbol_in("-").is_some() {
+            return Ok(Node::Neg(Box::new(self.unary()?)));
+        }
+        let base = self.atom()?;
+        if self.next_symbol_in("^").is_some() {
+            return Ok(Node::Binary('^', Box::new(base), Box::new(self.unary()?)));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<Node, ULogError> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        match token {
+            Some(Token::Number(value)) => Ok(Node::Number(value)),
+            Some(Token::Symbol('(')) => {
+                let node = self.expr()?;
+                self.expect(')')?;
+                Ok(node)
+            }
+            Some(Token::Name(name)) if self.next_symbol_in("(").is_some() => {
+                let function = Function::from_name(&name)
+                    .ok_or_else(|| self.error(&format!("unknown function `{name}`")))?;
+                let mut args = vec![self.expr()?];
+                while self.next_symbol_in(",").is_some() {
+                    args.push(self.expr()?);
+                }
+                self.expect(')')?;
+                if args.len() != function.arity() {
+                    return Err(self.error(&format!(
+                        "`{name}` takes {} argument(s), not {}",
+                        function.arity(),
+                        args.len()
+                    )));
+                }
+                Ok(Node::Call(function, args))
+            }
+            Some(Token::Name(name)) if name == "pi" => Ok(Node::Number(std::f64::consts::PI)),
+            Some(Token::Name(name)) => {
+                let (topic, column) = match name.split_once('/') {
+                    Some((topic, column)) => (Some(topic.to_owned()), column.to_owned()),
+                    None => (None, name),
+                };
+                Ok(Node::Variable(Variable { topic, column }))
+            }
+            Some(token) => Err(self.error(&format!("unexpected {token}"))),
+            None => Err(self.error("unexpected end")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(source: &str) -> f64 {
+        Expr::parse(source).unwrap().eval_with(&|variable| match (
+            variable.topic.as_deref(),
+            variable.column.as_str(),
+        ) {
+            (None, "vx") => Some(3.0),
+            (None, "vy") => Some(4.0),
+            (Some("a.01"), "q[0]") => Some(0.5),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(eval("sqrt(vx*vx + vy*vy)"), 5.0);
+        assert_eq!(eval("1 + 2 * 3 - 4 / 2"), 5.0);
+        assert_eq!(eval("-2^2 + 2^-1"), -3.5);
+        assert_eq!(eval("2^3^2"), 512.0);
+        assert_eq!(eval("(1 + 2) * 3"), 9.0);
+        assert_eq!(eval("rad2deg(pi / 2)"), 90.0);
+        assert_eq!(eval("max(vx, 1.5e1) + hypot(vx, vy)"), 20.0);
+        assert_eq!(eval("\"a.01/q[0]\" * 2"), 1.0);
+        assert_eq!(eval("vy/vx*3"), 4.0);
+        assert!(eval("vz + 1").is_nan());
+
+        let expr: Expr = "atan2(vy, vx) + vx".parse().unwrap();
+        let columns: Vec<_> = expr.variables().iter().map(|v| v.column.as_str()).collect();
+        assert_eq!(columns, ["vy", "vx", "vx"]);
+        assert_eq!(expr.to_string(), "atan2(vy, vx) + vx");
+    }
+
+    #[test]
+    fn test_invalid() {
+        for source in [
+            "",
+            "1 +",
+            "(1",
+            "1 2",
+            "sqrt()",
+            "sqrt(1, 2)",
+            "nope(1)",
+            "1 $ 2",
+            "1.2.3",
+            "\"vx",
+        ] {
+            assert!(
+                matches!(Expr::parse(source), Err(ULogError::InvalidExpression(_))),
+                "{source}"
+            );
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! Diagnostics macros.  With the `tracing` feature, these emit `tracing` events, which are recorded within
+//! the parser's per message spans.  Otherwise they emit `log` records.
+
+macro_rules! trace {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        tracing::trace!($($arg)*);
+        #[cfg(not(feature = "tracing"))]
+        log::trace!($($arg)*);
+    }};
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)*);
+        #[cfg(not(feature = "tracing"))]
+        log::debug!($($arg)*);
+    }};
+}
+
+macro_rules! warn_ {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        tracing::warn!($($arg)*);
+        #[cfg(not(feature = "tracing"))]
+        log::warn!($($arg)*);
+    }};
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        tracing::error!($($arg)*);
+        #[cfg(not(feature = "tracing"))]
+        log::error!($($arg)*);
+    }};
+}
+
+pub(crate) use {debug, error, trace, warn_ as warn};
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    use crate::errors::ULogError;
+    use crate::parser::ULogParser;
+
+    type Spans = Arc<Mutex<Vec<(&'static str, Option<String>)>>>;
+
+    /// Records the name and `topic` field of each new span.
+    #[derive(Default)]
+    struct SpanRecorder {
+        spans: Spans,
+    }
+
+    struct TopicVisitor(Option<String>);
+
+    impl Visit for TopicVisitor {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            if field.name() == "topic" {
+                self.0 = Some(format!("{value:?}"));
+            }
+        }
+    }
+
+    impl Subscriber for SpanRecorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut visitor = TopicVisitor(None);
+            span.record(&mut visitor);
+
+            let mut spans = self.spans.lock().unwrap();
+            spans.push((span.metadata().name(), visitor.0));
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, _: &Event<'_>) {}
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn test_spans() -> Result<(), ULogError> {
+        let recorder = SpanRecorder::default();
+        let spans = recorder.spans.clone();
+
+        let input = std::fs::read("test_data/input/short_list.ulg")?;
+        let data = tracing::subscriber::with_default(recorder, || -> Result<usize, ULogError> {
+            let messages: Vec<_> = ULogParser::new(input.as_slice())?.collect::<Result<_, _>>()?;
+            Ok(messages.len())
+        })?;
+
+        let spans = spans.lock().unwrap();
+        assert!(spans.iter().any(|(name, _)| *name == "ulog_message"));
+
+        let topics: Vec<_> = spans
+            .iter()
+            .filter(|(name, _)| *name == "logged_data")
+            .map(|(_, topic)| topic.as_deref())
+            .collect();
+        assert_eq!(topics.len(), 10);
+        assert!(topics.iter().all(|topic| *topic == Some("list_data")));
+        assert!(data >= 10);
+
+        Ok(())
+    }
+}
@@ -16,8 +16,8 @@ impl std::fmt::Display for inst::FieldValue {
             FieldValue::ScalarI16(v) => write!(f, "{v}"),
             FieldValue::ScalarI32(v) => write!(f, "{v}"),
             FieldValue::ScalarI64(v) => write!(f, "{v}"),
-            FieldValue::ScalarF32(v) => write!(f, "{v}"),
-            FieldValue::ScalarF64(v) => write!(f, "{v}"),
+            FieldValue::ScalarF32(v) => fmt_float(*v, f),
+            FieldValue::ScalarF64(v) => fmt_float(*v, f),
             FieldValue::ScalarBool(v) => write!(f, "{v}"),
             FieldValue::ScalarChar(c) => write!(f, "'{c}'"),
             FieldValue::ScalarOther(fmt) => write!(f, "{{{fmt}}}"),
@@ -31,8 +31,8 @@ impl std::fmt::Display for inst::FieldValue {
             FieldValue::ArrayI16(arr) => Ok(fmt_array(arr, f)?),
             FieldValue::ArrayI32(arr) => Ok(fmt_array(arr, f)?),
             FieldValue::ArrayI64(arr) => Ok(fmt_array(arr, f)?),
-            FieldValue::ArrayF32(arr) => Ok(fmt_array(arr, f)?),
-            FieldValue::ArrayF64(arr) => Ok(fmt_array(arr, f)?),
+            FieldValue::ArrayF32(arr) => fmt_float_array(arr, f),
+            FieldValue::ArrayF64(arr) => fmt_float_array(arr, f),
             FieldValue::ArrayBool(arr) => Ok(fmt_array(arr, f)?),
 
             FieldValue::ArrayChar(arr) => {
@@ -58,6 +58,26 @@ fn fmt_array<T: fmt::Display>(arr: &[T], f: &mut Formatter<'_>) -> fmt::Result {
     write!(f, "[{s}]")
 }
 
+/// Writes a float as the shortest decimal which parses back to it, or with the precision of the format, e.g.
+/// `{:.3}`.
+fn fmt_float<T: ryu::Float + fmt::Display>(v: T, f: &mut Formatter<'_>) -> fmt::Result {
+    match f.precision() {
+        Some(precision) => write!(f, "{v:.precision$}"),
+        None => f.write_str(&crate::export::shortest(v)),
+    }
+}
+
+fn fmt_float_array<T: ryu::Float + fmt::Display>(arr: &[T], f: &mut Formatter<'_>) -> fmt::Result {
+    f.write_str("[")?;
+    for (i, &v) in arr.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        fmt_float(v, f)?;
+    }
+    f.write_str("]")
+}
+
 impl fmt::Display for msg::Parameter {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(f, "{}: {}", self.key, self.value)
@@ -68,7 +88,7 @@ impl fmt::Display for inst::ParameterValue {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
             inst::ParameterValue::INT32(val) => write!(f, "{val}"),
-            inst::ParameterValue::FLOAT(val) => write!(f, "{val}"),
+            inst::ParameterValue::FLOAT(val) => fmt_float(*val, f),
         }
     }
 }
@@ -112,8 +132,8 @@ impl fmt::Display for msg::Info {
             FieldValue::ScalarI16(val) => write!(f, "{val}")?,
             FieldValue::ScalarI32(val) => write!(f, "{val}")?,
             FieldValue::ScalarI64(val) => write!(f, "{val}")?,
-            FieldValue::ScalarF32(val) => write!(f, "{val}")?,
-            FieldValue::ScalarF64(val) => write!(f, "{val}")?,
+            FieldValue::ScalarF32(val) => fmt_float(*val, f)?,
+            FieldValue::ScalarF64(val) => fmt_float(*val, f)?,
             FieldValue::ScalarBool(val) => write!(f, "{val}")?,
             FieldValue::ScalarChar(ch) => write!(f, "{ch}")?,
             FieldValue::ScalarOther(fmt) => write!(f, "{{{fmt}}}")?,
@@ -127,8 +147,8 @@ impl fmt::Display for msg::Info {
             FieldValue::ArrayI16(arr) => fmt_array(arr, f)?,
             FieldValue::ArrayI32(arr) => fmt_array(arr, f)?,
             FieldValue::ArrayI64(arr) => fmt_array(arr, f)?,
-            FieldValue::ArrayF32(arr) => fmt_array(arr, f)?,
-            FieldValue::ArrayF64(arr) => fmt_array(arr, f)?,
+            FieldValue::ArrayF32(arr) => fmt_float_array(arr, f)?,
+            FieldValue::ArrayF64(arr) => fmt_float_array(arr, f)?,
             FieldValue::ArrayBool(arr) => fmt_array(arr, f)?,
 
             FieldValue::ArrayChar(arr) => {
@@ -166,8 +186,8 @@ impl fmt::Display for msg::MultiInfo {
             FieldValue::ScalarI16(val) => write!(f, "{val}")?,
             FieldValue::ScalarI32(val) => write!(f, "{val}")?,
             FieldValue::ScalarI64(val) => write!(f, "{val}")?,
-            FieldValue::ScalarF32(val) => write!(f, "{val}")?,
-            FieldValue::ScalarF64(val) => write!(f, "{val}")?,
+            FieldValue::ScalarF32(val) => fmt_float(*val, f)?,
+            FieldValue::ScalarF64(val) => fmt_float(*val, f)?,
             FieldValue::ScalarBool(val) => write!(f, "{val}")?,
             FieldValue::ScalarChar(ch) => write!(f, "{ch}")?,
             FieldValue::ScalarOther(fmt) => write!(f, "{{{fmt}}}")?,
@@ -181,8 +201,8 @@ impl fmt::Display for msg::MultiInfo {
             FieldValue::ArrayI16(arr) => fmt_array(arr, f)?,
             FieldValue::ArrayI32(arr) => fmt_array(arr, f)?,
             FieldValue::ArrayI64(arr) => fmt_array(arr, f)?,
-            FieldValue::ArrayF32(arr) => fmt_array(arr, f)?,
-            FieldValue::ArrayF64(arr) => fmt_array(arr, f)?,
+            FieldValue::ArrayF32(arr) => fmt_float_array(arr, f)?,
+            FieldValue::ArrayF64(arr) => fmt_float_array(arr, f)?,
             FieldValue::ArrayBool(arr) => fmt_array(arr, f)?,
 
             FieldValue::ArrayChar(arr) => {
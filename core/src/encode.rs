@@ -24,7 +24,17 @@ impl Encode for UlogMessage {
                 let mut content_buf = Vec::new();
                 other.encode_content(&mut content_buf)?;
 
-                let msg_size = content_buf.len() as u16;
+                let msg_size = u16::try_from(content_buf.len()).map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "{:?} message of {} bytes exceeds the maximum message size of {} bytes",
+                            other.message_type(),
+                            content_buf.len(),
+                            u16::MAX
+                        ),
+                    )
+                })?;
                 writer.write_all(&msg_size.to_le_bytes())?;
                 writer.write_all(&[u8::from(other.message_type())])?;
                 writer.write_all(&content_buf)?;
@@ -36,7 +46,7 @@ impl Encode for UlogMessage {
 
 impl UlogMessage {
     // Return the message type code as u8
-    fn message_type(&self) -> ULogMessageType {
+    pub(crate) fn message_type(&self) -> ULogMessageType {
         match self {
             UlogMessage::FlagBits(_) => ULogMessageType::FLAG_BITS,
             UlogMessage::FormatDefinition(_) => ULogMessageType::FORMAT,
@@ -52,12 +62,14 @@ impl UlogMessage {
             UlogMessage::Unhandled { msg_type, .. } | UlogMessage::Ignored { msg_type, .. } => {
                 ULogMessageType::from(*msg_type)
             }
+            UlogMessage::UnknownTyped(unknown) => ULogMessageType::from(unknown.msg_type),
+            UlogMessage::Custom(custom) => ULogMessageType::from(custom.msg_type),
             UlogMessage::Header(_) => unreachable!("Handled separately"),
         }
     }
 
     // Encode the inner content bytes without prefix (size/type)
-    fn encode_content<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+    pub(crate) fn encode_content<W: Write>(&self, writer: &mut W) -> io::Result<()> {
         match self {
             UlogMessage::FlagBits(flag_bits) => flag_bits.encode(writer),
             UlogMessage::FormatDefinition(format) => format.encode(writer),
@@ -74,6 +86,8 @@ impl UlogMessage {
                 message_contents, ..
             } => writer.write_all(message_contents),
             UlogMessage::Ignored { msg_type: _ } => { Ok(())},
+            UlogMessage::UnknownTyped(unknown) => writer.write_all(&unknown.payload),
+            UlogMessage::Custom(custom) => writer.write_all(&custom.payload),
             UlogMessage::Header(_) => unreachable!("Handled separately"),
         }
     }
@@ -108,20 +122,34 @@ impl Encode for msg::Subscription {
 impl Encode for LoggedData {
     fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
         writer.write_all(&self.msg_id.to_le_bytes())?;
-        self.data.encode(writer)
+        self.data.encode_fields(writer, false)
     }
 }
 
+// ------------------------ Info and parameter keys ------------------------
+
+// Encodes the length prefixed `<type> <name>` key of INFO, INFO_MULTIPLE and parameter messages.
+fn encode_key<W: Write>(writer: &mut W, r#type: &def::TypeExpr, key: &str) -> io::Result<()> {
+    let mut key_bytes: Vec<u8> = r#type.encode_to_vec()?;
+    key_bytes.push(b' ');
+    key_bytes.extend_from_slice(key.as_bytes());
+
+    let key_len = u8::try_from(key_bytes.len()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Key `{key}` exceeds the maximum key length of 255 bytes"),
+        )
+    })?;
+
+    writer.write_all(&[key_len])?;
+    writer.write_all(&key_bytes)
+}
+
 // ------------------------ msg::Info ------------------------
 
 impl Encode for msg::Info {
     fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
-        let mut key_bytes: Vec<u8> = self.r#type.encode_to_vec()?;
-        key_bytes.push(b' ');
-        key_bytes.extend_from_slice(self.key.as_bytes());
-
-        writer.write_all(&[key_bytes.len() as u8])?;
-        writer.write_all(&key_bytes)?;
+        encode_key(writer, &self.r#type, &self.key)?;
         self.value.encode(writer)?;
         Ok(())
     }
@@ -133,12 +161,7 @@ impl Encode for msg::MultiInfo {
     fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
         writer.write_all(&[self.is_continued as u8])?;
 
-        let mut key_bytes: Vec<u8> = self.r#type.encode_to_vec()?;
-        key_bytes.push(b' ');
-        key_bytes.extend_from_slice(self.key.as_bytes());
-
-        writer.write_all(&[key_bytes.len() as u8])?;
-        writer.write_all(&key_bytes)?;
+        encode_key(writer, &self.r#type, &self.key)?;
         self.value.encode(writer)?;
         Ok(())
     }
@@ -148,12 +171,7 @@ impl Encode for msg::MultiInfo {
 
 impl Encode for msg::Parameter {
     fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
-        let mut key_bytes: Vec<u8> = self.r#type.encode_to_vec()?;
-        key_bytes.push(b' ');
-        key_bytes.extend_from_slice(self.key.as_bytes());
-
-        writer.write_all(&[key_bytes.len() as u8])?;
-        writer.write_all(&key_bytes)?;
+        encode_key(writer, &self.r#type, &self.key)?;
         self.value.encode(writer)?;
         Ok(())
     }
@@ -165,12 +183,7 @@ impl Encode for msg::DefaultParameter {
     fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
         writer.write_all(&[self.default_types])?;
 
-        let mut key_bytes: Vec<u8> = self.r#type.encode_to_vec()?;
-        key_bytes.push(b' ');
-        key_bytes.extend_from_slice(self.key.as_bytes());
-
-        writer.write_all(&[key_bytes.len() as u8])?;
-        writer.write_all(&key_bytes)?;
+        encode_key(writer, &self.r#type, &self.key)?;
         self.value.encode(writer)?;
         Ok(())
     }
@@ -263,9 +276,44 @@ impl Encode for def::BaseType {
 
 impl Encode for inst::Format {
     fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
-        for field in &self.fields {
-            field.encode(writer)?;
+        self.encode_fields(writer, true)
+    }
+}
+
+impl inst::Format {
+    // Encodes the fields of the format, re-inserting fields removed by `LoggedData::filter_fields()` or
+    // skipped by the parser: the timestamp from `self.timestamp` and padding as zeros.
+    // Trailing padding, which may be omitted from the end of a LOGGED_DATA message, is only re-inserted
+    // if `trailing_padding` is set.
+    fn encode_fields<W: Write>(&self, writer: &mut W, trailing_padding: bool) -> io::Result<()> {
+        if self.field_indices.is_empty() {
+            for field in &self.fields {
+                field.encode(writer)?;
+            }
+            return Ok(());
+        }
+
+        let last_index = self.field_indices.last().map_or(0, |&i| i as usize);
+        let mut fields = self.fields.iter().zip(&self.field_indices).peekable();
+
+        for (def_index, def_field) in self.def_format.fields.iter().enumerate() {
+            if let Some((field, _)) = fields.next_if(|(_, &i)| i as usize == def_index) {
+                field.encode(writer)?;
+            } else if def_field.is_padding() {
+                if def_index < last_index || trailing_padding {
+                    let size = def_field.r#type.array_size.unwrap_or(1);
+                    writer.write_all(&vec![0; size])?;
+                }
+            } else if def_field.name.as_ref() == "timestamp" {
+                writer.write_all(&self.timestamp.unwrap_or_default().to_le_bytes())?;
+            } else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Field {} of {} is missing.", def_field.name, self.name),
+                ));
+            }
         }
+
         Ok(())
     }
 }
@@ -41,6 +41,12 @@ pub enum ULogError {
     #[error("Cound not find subscription for msg_id: {0}")]
     UndefinedSubscription(u16),
 
+    #[error("Duplicate subscription for msg_id: {0}")]
+    DuplicateSubscription(u16),
+
+    #[error("Subscription limit exceeded: {0}")]
+    SubscriptionLimitExceeded(String),
+
     #[error("Unknown Incompat Bits")]
     UnknownIncompatBits,
 
@@ -61,4 +67,55 @@ pub enum ULogError {
 
     #[error("Invalid parser configuration: {0}")]
     InvalidConfiguration(String),
+
+    #[error("Invalid appended data offset: {0}")]
+    InvalidAppendedDataOffset(String),
+
+    #[error("Message size mismatch: {0}")]
+    MessageSizeMismatch(String),
+
+    #[error("Message too large: {0}")]
+    MessageTooLarge(String),
+
+    #[error("Invalid encrypted log: {0}")]
+    InvalidEncryptedLog(String),
+
+    #[error("Export error: {0}")]
+    ExportError(String),
+
+    #[error("Invalid index file: {0}")]
+    InvalidIndex(String),
+
+    #[error("Invalid delta container: {0}")]
+    InvalidDeltaContainer(String),
+
+    #[error("Invalid expression: {0}")]
+    InvalidExpression(String),
+
+    #[error("Uninterpreted message: {0}")]
+    UninterpretedMessage(String),
+
+    #[error("Invalid corpus log: {0}")]
+    InvalidCorpusLog(String),
+
+    #[error("Unresolved format: {0}")]
+    UnresolvedFormat(String),
+
+    #[error("Invalid parser state: {0}")]
+    InvalidParserState(String),
+
+    #[error("The parse was cancelled.")]
+    Cancelled,
+}
+
+/// Why a value can't be converted exactly, as returned by `FieldValue::to_i64_checked()` and
+/// `FieldValue::to_f64_checked()`.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CastError {
+    #[error("The value is out of range of the target type.")]
+    Overflow,
+    #[error("The value can't be represented exactly in the target type.")]
+    PrecisionLoss,
+    #[error("The value isn't a number.")]
+    NotNumeric,
 }
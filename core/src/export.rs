@@ -0,0 +1,527 @@
+//! Exporters which convert ULOG files to formats used by common analysis tools.
+
+pub mod avro;
+pub mod columns;
+pub mod csv;
+#[cfg(feature = "hdf5")]
+pub mod hdf5;
+pub mod identifiers;
+pub mod ndjson;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod proto;
+pub mod sql;
+pub mod timestream;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::hash::Hasher;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use crate::builder::ULogParserBuilder;
+use crate::diag;
+use crate::errors::{CastError, ULogError};
+use crate::metadata::LogMetadata;
+use crate::model::def::BaseType;
+use crate::model::inst::{self, FieldValue};
+use crate::model::msg::LoggedData;
+use crate::model::StableHasher;
+
+/// The units of exported columns, keyed by topic and column name.
+///
+/// ULOG files do not carry units, so these have to be supplied by the caller,
+/// e.g. from the PX4 message definitions.
+#[derive(Debug, Clone, Default)]
+pub struct Units {
+    units: HashMap<(String, String), String>,
+}
+
+impl Units {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, topic: &str, column: &str, unit: &str) {
+        self.units
+            .insert((topic.to_owned(), column.to_owned()), unit.to_owned());
+    }
+
+    pub fn get(&self, topic: &str, column: &str) -> Option<&str> {
+        self.units
+            .get(&(topic.to_owned(), column.to_owned()))
+            .map(String::as_str)
+    }
+}
+
+/// Where exported rows came from, written as constant columns of every row by the exporters which take it, so that
+/// the rows of many logs can share a table, e.g. `SqlExporter::export_with_provenance()`.  Values which are `None`
+/// are written as nulls, or empty cells.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Provenance {
+    pub file_name: Option<String>,
+    /// A digest of the bytes of the file, to tell apart files of the same name.
+    pub file_hash: Option<String>,
+    /// The vehicle which logged the log, by `VehicleIdentity::fleet_id()`: its `sys_uuid`, or system name and
+    /// MAVLink system id.
+    pub vehicle_uuid: Option<String>,
+}
+
+impl Provenance {
+    /// The names of the provenance columns, in the order of `columns()`.
+    pub const COLUMNS: [&'static str; 3] = ["file_name", "file_hash", "vehicle_uuid"];
+
+    /// Reads the provenance of the log at `path`: its file name, the FNV-1a hash of its bytes as 16 hex digits, and
+    /// the fleet id of its `LogMetadata::vehicle_identity()`.  The hash is stable across builds, but is no protection against
+    /// tampering.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ULogError> {
+        let path = path.as_ref();
+
+        let mut hasher = StableHasher::default();
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut buf = [0; 64 * 1024];
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.write(&buf[..n]);
+        }
+
+        let parser = ULogParserBuilder::new(BufReader::new(File::open(path)?))
+            .set_subscription_allow_list(Vec::<String>::new())
+            .build()?;
+        let metadata = LogMetadata::read(parser)?;
+
+        Ok(Provenance {
+            file_name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned()),
+            file_hash: Some(format!("{:016x}", hasher.finish())),
+            vehicle_uuid: metadata.vehicle_identity().fleet_id(),
+        })
+    }
+
+    /// The provenance columns with their values, named by `COLUMNS`.
+    pub fn columns(&self) -> [(&'static str, Option<&str>); 3] {
+        let [file_name, file_hash, vehicle_uuid] = Self::COLUMNS;
+        [
+            (file_name, self.file_name.as_deref()),
+            (file_hash, self.file_hash.as_deref()),
+            (vehicle_uuid, self.vehicle_uuid.as_deref()),
+        ]
+    }
+}
+
+/// The values which an exporter couldn't convert exactly to the types of its destination, counted per topic.
+///
+/// Exporters still write these values, as documented for each exporter, e.g. wrapping a `u64` above `i64::MAX`
+/// into a signed 64 bit column, and log a warning with the totals.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LossyCasts {
+    /// topic -> the number of values out of range of their destination type.
+    pub overflows: BTreeMap<String, u64>,
+    /// topic -> the number of values which were rounded.
+    pub precision_losses: BTreeMap<String, u64>,
+}
+
+impl LossyCasts {
+    pub fn is_empty(&self) -> bool {
+        self.overflows.is_empty() && self.precision_losses.is_empty()
+    }
+
+    /// The number of lossy casts of all topics.
+    pub fn total(&self) -> u64 {
+        self.overflows
+            .values()
+            .chain(self.precision_losses.values())
+            .sum()
+    }
+
+    pub(crate) fn record(&mut self, topic: &str, error: CastError) {
+        let counts = match error {
+            CastError::Overflow => &mut self.overflows,
+            CastError::PrecisionLoss => &mut self.precision_losses,
+            CastError::NotNumeric => return,
+        };
+        match counts.get_mut(topic) {
+            Some(count) => *count += 1,
+            None => {
+                counts.insert(topic.to_owned(), 1);
+            }
+        }
+    }
+
+    pub(crate) fn add(&mut self, other: &LossyCasts) {
+        for (counts, other) in [
+            (&mut self.overflows, &other.overflows),
+            (&mut self.precision_losses, &other.precision_losses),
+        ] {
+            for (topic, count) in other {
+                *counts.entry(topic.clone()).or_default() += count;
+            }
+        }
+    }
+
+    /// Logs a warning with the totals, unless there were no lossy casts.
+    pub(crate) fn warn(&self, exporter: &str) {
+        if self.is_empty() {
+            return;
+        }
+        let summary = |counts: &BTreeMap<String, u64>| {
+            counts
+                .iter()
+                .map(|(topic, count)| format!("{topic}: {count}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        diag::warn!(
+            "{exporter} converted {} values lossily. Overflows: [{}]. Precision losses: [{}].",
+            self.total(),
+            summary(&self.overflows),
+            summary(&self.precision_losses)
+        );
+    }
+}
+
+/// How exporters write floats which are NaN or infinite, which most formats and databases have no single
+/// representation for.  Each exporter documents its default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidFloatPolicy {
+    /// Written as an empty CSV cell or a JSON `null`.  Columnar exporters, which have no nulls, keep the value.
+    AsNull,
+    /// Written as `nan`, `inf` or `-inf`, the way `pyulog` writes them: a bare CSV cell, or a JSON string.
+    /// Columnar exporters keep the value.
+    AsNaNString,
+    /// Messages holding a NaN or infinite float are not exported.
+    Drop,
+    /// Written as 0.
+    ClampToZero,
+}
+
+impl InvalidFloatPolicy {
+    /// Returns true if the message with the data `format` is not exported.
+    pub(crate) fn drops(self, format: &inst::Format) -> bool {
+        self == InvalidFloatPolicy::Drop && has_invalid_float(format)
+    }
+
+    /// Formats a scalar value as a CSV cell.  See `format_scalar()`.
+    pub(crate) fn format_scalar(
+        self,
+        value: &FieldValue,
+        float_format: FloatFormat,
+    ) -> Option<String> {
+        if !is_invalid_float(value) {
+            return format_scalar_with(value, float_format);
+        }
+
+        match self {
+            InvalidFloatPolicy::AsNull => Some(String::new()),
+            InvalidFloatPolicy::ClampToZero => Some("0".to_owned()),
+            InvalidFloatPolicy::AsNaNString | InvalidFloatPolicy::Drop => format_scalar(value),
+        }
+    }
+}
+
+/// How exporters write finite floats.  Both formats use `.` as the decimal separator whatever the locale.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FloatFormat {
+    /// The shortest decimal which parses back to the same `f32` or `f64`, e.g. `0.1`, `2` or `1e-7`, so that
+    /// exports of the same log compare equal across versions and tools.
+    #[default]
+    Shortest,
+    /// A fixed number of digits after the decimal point, e.g. `0.100` with 3.  Values may not parse back to the
+    /// same float.
+    Fixed(usize),
+}
+
+impl FloatFormat {
+    pub fn format_f32(self, v: f32) -> String {
+        match self {
+            FloatFormat::Shortest => shortest(v),
+            FloatFormat::Fixed(digits) => format!("{v:.digits$}"),
+        }
+    }
+
+    pub fn format_f64(self, v: f64) -> String {
+        match self {
+            FloatFormat::Shortest => shortest(v),
+            FloatFormat::Fixed(digits) => format!("{v:.digits$}"),
+        }
+    }
+}
+
+/// Formats a float with `ryu`, without the `.0` of whole numbers.  Non finite floats are `NaN`, `inf` and `-inf`.
+pub(crate) fn shortest<F: ryu::Float>(v: F) -> String {
+    let mut buffer = ryu::Buffer::new();
+    let formatted = buffer.format(v);
+    formatted.strip_suffix(".0").unwrap_or(formatted).to_owned()
+}
+
+/// Returns true if `value` is a NaN or infinite float.
+pub(crate) fn is_invalid_float(value: &FieldValue) -> bool {
+    match value {
+        FieldValue::ScalarF32(v) => !v.is_finite(),
+        FieldValue::ScalarF64(v) => !v.is_finite(),
+        _ => false,
+    }
+}
+
+/// Returns true if any field of `format`, including array elements and nested fields, is a NaN or infinite
+/// float.
+pub(crate) fn has_invalid_float(format: &inst::Format) -> bool {
+    format
+        .fields
+        .iter()
+        .any(|field| value_has_invalid_float(&field.value))
+}
+
+pub(crate) fn value_has_invalid_float(value: &FieldValue) -> bool {
+    match value {
+        FieldValue::ScalarOther(nested) => has_invalid_float(nested),
+        FieldValue::ArrayOther(nested) => nested.iter().any(has_invalid_float),
+        FieldValue::ArrayF32(v) => v.iter().any(|v| !v.is_finite()),
+        FieldValue::ArrayF64(v) => v.iter().any(|v| !v.is_finite()),
+        value => is_invalid_float(value),
+    }
+}
+
+/// Assigns consecutive indices to topic instances, in order of appearance.
+///
+/// Instances are identified by topic name and multi_id, so that subscriptions which are
+/// repeated under a different msg_id are exported together.
+#[derive(Debug, Default)]
+pub(crate) struct TopicIndex {
+    by_msg_id: HashMap<u16, (usize, u8)>,
+    by_instance: HashMap<(String, u8), usize>,
+}
+
+impl TopicIndex {
+    /// Returns the index and multi_id of the topic instance of `data`.
+    ///
+    /// An index equal to the number of previously seen instances denotes a new instance.
+    pub(crate) fn get(&mut self, data: &LoggedData) -> (usize, u8) {
+        if let Some(&entry) = self.by_msg_id.get(&data.msg_id) {
+            return entry;
+        }
+
+        let multi_id = data.data.multi_id_index.unwrap_or(0);
+        let next_index = self.by_instance.len();
+        let index = *self
+            .by_instance
+            .entry((data.data.name.to_string(), multi_id))
+            .or_insert(next_index);
+
+        self.by_msg_id.insert(data.msg_id, (index, multi_id));
+        (index, multi_id)
+    }
+}
+
+// Skips the fields which are not exported as columns.  The timestamp is exported separately, as the first column.
+fn is_exported(field: &inst::Field, top_level: bool) -> bool {
+    let is_timestamp = top_level && field.name.as_ref() == "timestamp";
+    !field.name.starts_with("_padding") && !is_timestamp
+}
+
+/// Returns the names and types of the scalar columns of `format`, named the way `pyulog` names them:
+/// `field[i]` for array elements and `field.nested` for nested fields.
+pub(crate) fn column_names(format: &inst::Format) -> Vec<(String, BaseType)> {
+    let mut columns = Vec::new();
+    column_names_sub(format, "", &mut columns);
+    columns
+}
+
+fn column_names_sub(format: &inst::Format, prefix: &str, columns: &mut Vec<(String, BaseType)>) {
+    for field in format
+        .fields
+        .iter()
+        .filter(|f| is_exported(f, prefix.is_empty()))
+    {
+        let name = format!("{prefix}{}", field.name);
+
+        match &field.value {
+            FieldValue::ScalarOther(nested) => {
+                column_names_sub(nested, &format!("{name}."), columns)
+            }
+            FieldValue::ArrayOther(nested) => {
+                for (i, nested) in nested.iter().enumerate() {
+                    column_names_sub(nested, &format!("{name}[{i}]."), columns);
+                }
+            }
+            value => match value.to_scalars() {
+                Some(scalars) => columns.extend(
+                    (0..scalars.len())
+                        .map(|i| (format!("{name}[{i}]"), field.r#type.base_type.clone())),
+                ),
+                None => columns.push((name, field.r#type.base_type.clone())),
+            },
+        }
+    }
+}
+
+/// Returns a hash of the layout of the columns of `format`: the fingerprints of its format and those nested in it, and
+/// the fields selected of each.  Equal hashes give equal `column_names()`, so the schema of a topic needs checking
+/// only when the hash changes, e.g. in a later log.
+pub(crate) fn layout_fingerprint(format: &inst::Format) -> u64 {
+    let mut hasher = StableHasher::default();
+    layout_fingerprint_sub(format, &mut hasher);
+    hasher.finish()
+}
+
+fn layout_fingerprint_sub(format: &inst::Format, hasher: &mut StableHasher) {
+    hasher.write_u64(format.def_format.fingerprint());
+    hasher.write_usize(format.field_indices.len());
+    for &index in &format.field_indices {
+        hasher.write_u16(index);
+    }
+    for field in &format.fields {
+        match &field.value {
+            FieldValue::ScalarOther(nested) => layout_fingerprint_sub(nested, hasher),
+            // The elements of an array share a format.
+            FieldValue::ArrayOther(nested) => {
+                if let Some(nested) = nested.first() {
+                    layout_fingerprint_sub(nested, hasher);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Calls `f` with the scalar value of each column of `format`, in the order of `column_names()`.
+pub(crate) fn for_each_scalar(format: &inst::Format, f: &mut impl FnMut(&FieldValue)) {
+    for_each_scalar_sub(format, true, f);
+}
+
+fn for_each_scalar_sub(format: &inst::Format, top_level: bool, f: &mut impl FnMut(&FieldValue)) {
+    for field in format.fields.iter().filter(|fd| is_exported(fd, top_level)) {
+        match &field.value {
+            FieldValue::ScalarOther(nested) => for_each_scalar_sub(nested, false, f),
+            FieldValue::ArrayOther(nested) => {
+                for nested in nested {
+                    for_each_scalar_sub(nested, false, f);
+                }
+            }
+            value => match value.to_scalars() {
+                Some(scalars) => scalars.iter().for_each(&mut *f),
+                None => f(value),
+            },
+        }
+    }
+}
+
+/// Formats a scalar value the way `pyulog` does: booleans and chars as integers, and non finite
+/// floats as `nan`, `inf` and `-inf`.
+///
+/// Returns `None` for arrays and nested formats.
+pub(crate) fn format_scalar(value: &FieldValue) -> Option<String> {
+    format_scalar_with(value, FloatFormat::Shortest)
+}
+
+/// Formats a scalar value like `format_scalar()`, with finite floats in `float_format`.
+pub(crate) fn format_scalar_with(value: &FieldValue, float_format: FloatFormat) -> Option<String> {
+    Some(match value {
+        FieldValue::ScalarU8(v) => v.to_string(),
+        FieldValue::ScalarU16(v) => v.to_string(),
+        FieldValue::ScalarU32(v) => v.to_string(),
+        FieldValue::ScalarU64(v) => v.to_string(),
+        FieldValue::ScalarI8(v) => v.to_string(),
+        FieldValue::ScalarI16(v) => v.to_string(),
+        FieldValue::ScalarI32(v) => v.to_string(),
+        FieldValue::ScalarI64(v) => v.to_string(),
+        FieldValue::ScalarF32(v) => format_float(f64::from(*v), float_format.format_f32(*v)),
+        FieldValue::ScalarF64(v) => format_float(*v, float_format.format_f64(*v)),
+        FieldValue::ScalarBool(v) => u8::from(*v).to_string(),
+        FieldValue::ScalarChar(v) => v.0.to_string(),
+        _ => return None,
+    })
+}
+
+fn format_float(v: f64, formatted: String) -> String {
+    if v.is_nan() {
+        "nan".to_owned()
+    } else if v.is_infinite() {
+        if v > 0.0 { "inf" } else { "-inf" }.to_owned()
+    } else {
+        formatted
+    }
+}
+
+/// Quotes and escapes `s` as a JSON string.
+pub(crate) fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if u32::from(c) < 0x20 => quoted.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::CChar;
+
+    #[test]
+    fn test_provenance_from_file() -> Result<(), ULogError> {
+        let provenance = Provenance::from_file("test_data/input/sample_log_small.ulg")?;
+        assert_eq!(
+            provenance.file_name.as_deref(),
+            Some("sample_log_small.ulg")
+        );
+        let hash = provenance.file_hash.as_deref().unwrap();
+        assert_eq!(hash.len(), 16);
+        assert_ne!(
+            Provenance::from_file("test_data/input/short_list.ulg")?
+                .file_hash
+                .as_deref(),
+            Some(hash)
+        );
+        assert_eq!(
+            provenance.columns()[0],
+            ("file_name", Some("sample_log_small.ulg"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_scalar() {
+        assert_eq!(format_scalar(&FieldValue::ScalarF32(0.1)).unwrap(), "0.1");
+        assert_eq!(
+            format_scalar(&FieldValue::ScalarF64(f64::NAN)).unwrap(),
+            "nan"
+        );
+        assert_eq!(
+            format_scalar(&FieldValue::ScalarF32(f32::NEG_INFINITY)).unwrap(),
+            "-inf"
+        );
+        assert_eq!(format_scalar(&FieldValue::ScalarBool(true)).unwrap(), "1");
+        assert_eq!(
+            format_scalar(&FieldValue::ScalarChar(CChar(b'A'))).unwrap(),
+            "65"
+        );
+        assert!(format_scalar(&FieldValue::ArrayU8(vec![1])).is_none());
+
+        assert_eq!(format_scalar(&FieldValue::ScalarF64(1e-7)).unwrap(), "1e-7");
+        assert_eq!(format_scalar(&FieldValue::ScalarF32(-2.0)).unwrap(), "-2");
+        assert_eq!(
+            format_scalar_with(&FieldValue::ScalarF32(0.1), FloatFormat::Fixed(3)).unwrap(),
+            "0.100"
+        );
+    }
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string("plain"), "\"plain\"");
+        assert_eq!(json_string("a\"b\\c\n\u{1}"), "\"a\\\"b\\\\c\\n\\u0001\"");
+    }
+}
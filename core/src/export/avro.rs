@@ -0,0 +1,454 @@
+//! Exports the `LoggedData` messages of ULOG files as Avro, with a schema derived from the format of each topic.
+//!
+//! The record of a message has a `timestamp` (`long`) and a `multi_id` (`int`) field, followed by the fields of
+//! its format, other than padding.  Nested formats become named records, arrays become Avro arrays, and char
+//! arrays become strings.  Unsigned types are stored in the next larger signed type, except `uint64_t`, which is
+//! stored in a `long` and wraps above 2^63.
+//!
+//! `AvroExporter` writes an Avro object container file per topic.  `schema()` and `encode()` are the building
+//! blocks, e.g. to publish single records to a broker:
+//!
+//! ```rust,no_run
+//! # use std::fs::File;
+//! # use std::io::BufReader;
+//! # use yule_log::builder::ULogParserBuilder;
+//! use yule_log::export::avro::AvroExporter;
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let parser = ULogParserBuilder::new(BufReader::new(File::open("sample.ulg")?)).build()?;
+//! let files = AvroExporter::new("sample_avro").export(parser)?;
+//! println!("Exported {} topics", files.len());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::errors::ULogError;
+use crate::export::{for_each_scalar, is_exported, json_string, LossyCasts};
+use crate::model::def::{self, BaseType};
+use crate::model::inst::{self, FieldValue};
+use crate::model::msg::{LoggedData, UlogMessage};
+use crate::model::{CCharSlice, Shared};
+
+/// Returns the Avro schema of the records of messages with `format`, as JSON.  Nested formats are resolved
+/// using `formats`.
+pub fn schema(
+    format: &def::Format,
+    formats: &HashMap<Shared<str>, Shared<def::Format>>,
+) -> Result<String, ULogError> {
+    let mut fields = vec![
+        r#"{"name":"timestamp","type":"long"}"#.to_owned(),
+        r#"{"name":"multi_id","type":"int"}"#.to_owned(),
+    ];
+    let mut defined = HashSet::new();
+    defined.insert(format.name.to_string());
+    for field in format
+        .fields
+        .iter()
+        .filter(|f| !f.is_padding() && &*f.name != "timestamp")
+    {
+        fields.push(field_schema(field, formats, &mut defined)?);
+    }
+
+    Ok(format!(
+        r#"{{"type":"record","name":{},"fields":[{}]}}"#,
+        json_string(&format.name),
+        fields.join(",")
+    ))
+}
+
+/// Returns the Avro schema of the records of messages like `data`, resolving nested formats from the message.
+pub fn data_schema(data: &inst::Format) -> Result<String, ULogError> {
+    let mut formats = HashMap::new();
+    collect_formats(data, &mut formats);
+    schema(&data.def_format, &formats)
+}
+
+/// Appends the Avro binary encoding of the record of `data` to `out`.
+pub fn encode(data: &LoggedData, out: &mut Vec<u8>) {
+    write_long(data.timestamp as i64, out);
+    write_long(i64::from(data.data.multi_id_index.unwrap_or(0)), out);
+    encode_format(&data.data, true, out);
+}
+
+fn collect_formats(format: &inst::Format, formats: &mut HashMap<Shared<str>, Shared<def::Format>>) {
+    for field in &format.fields {
+        let nested = match &field.value {
+            FieldValue::ScalarOther(nested) => Some(&**nested),
+            FieldValue::ArrayOther(nested) => nested.first(),
+            _ => None,
+        };
+        if let Some(nested) = nested {
+            formats.insert(nested.def_format.name.clone(), nested.def_format.clone());
+            collect_formats(nested, formats);
+        }
+    }
+}
+
+fn field_schema(
+    field: &def::Field,
+    formats: &HashMap<Shared<str>, Shared<def::Format>>,
+    defined: &mut HashSet<String>,
+) -> Result<String, ULogError> {
+    let item = match &field.r#type.base_type {
+        BaseType::CHAR => {
+            return Ok(format!(
+                r#"{{"name":{},"type":"string"}}"#,
+                json_string(&field.name)
+            ))
+        }
+        BaseType::OTHER(name) => {
+            if defined.contains(name) {
+                json_string(name)
+            } else {
+                let nested = formats
+                    .get(name.as_str())
+                    .ok_or_else(|| ULogError::UndefinedFormat(name.clone()))?;
+                defined.insert(name.clone());
+                let fields = nested
+                    .fields
+                    .iter()
+                    .filter(|f| !f.is_padding())
+                    .map(|f| field_schema(f, formats, defined))
+                    .collect::<Result<Vec<_>, _>>()?;
+                format!(
+                    r#"{{"type":"record","name":{},"fields":[{}]}}"#,
+                    json_string(name),
+                    fields.join(",")
+                )
+            }
+        }
+        base_type => json_string(primitive(base_type)),
+    };
+
+    let r#type = match field.r#type.array_size {
+        Some(_) => format!(r#"{{"type":"array","items":{item}}}"#),
+        None => item,
+    };
+    Ok(format!(
+        r#"{{"name":{},"type":{}}}"#,
+        json_string(&field.name),
+        r#type
+    ))
+}
+
+fn primitive(base_type: &BaseType) -> &'static str {
+    match base_type {
+        BaseType::UINT8 | BaseType::UINT16 | BaseType::INT8 | BaseType::INT16 | BaseType::INT32 => {
+            "int"
+        }
+        BaseType::UINT32 | BaseType::UINT64 | BaseType::INT64 => "long",
+        BaseType::FLOAT => "float",
+        BaseType::DOUBLE => "double",
+        BaseType::BOOL => "boolean",
+        BaseType::CHAR | BaseType::OTHER(_) => unreachable!("not a primitive type"),
+    }
+}
+
+fn encode_format(format: &inst::Format, top_level: bool, out: &mut Vec<u8>) {
+    for field in format.fields.iter().filter(|f| is_exported(f, top_level)) {
+        encode_value(&field.value, out);
+    }
+}
+
+fn encode_value(value: &FieldValue, out: &mut Vec<u8>) {
+    match value {
+        FieldValue::ScalarU8(v) => write_long(i64::from(*v), out),
+        FieldValue::ScalarU16(v) => write_long(i64::from(*v), out),
+        FieldValue::ScalarU32(v) => write_long(i64::from(*v), out),
+        FieldValue::ScalarU64(v) => write_long(*v as i64, out),
+        FieldValue::ScalarI8(v) => write_long(i64::from(*v), out),
+        FieldValue::ScalarI16(v) => write_long(i64::from(*v), out),
+        FieldValue::ScalarI32(v) => write_long(i64::from(*v), out),
+        FieldValue::ScalarI64(v) => write_long(*v, out),
+        FieldValue::ScalarF32(v) => out.extend_from_slice(&v.to_le_bytes()),
+        FieldValue::ScalarF64(v) => out.extend_from_slice(&v.to_le_bytes()),
+        FieldValue::ScalarBool(v) => out.push(u8::from(*v)),
+        FieldValue::ScalarChar(v) => write_bytes([*v].to_string_lossy().as_bytes(), out),
+        FieldValue::ScalarOther(nested) => encode_format(nested, false, out),
+        FieldValue::ArrayChar(v) => write_bytes(v.to_string_lossy_trimmed().as_bytes(), out),
+        FieldValue::ArrayOther(v) => {
+            write_array(v.len(), out);
+            for nested in v {
+                encode_format(nested, false, out);
+            }
+            if !v.is_empty() {
+                write_long(0, out);
+            }
+        }
+        array => {
+            let scalars = array.to_scalars().unwrap_or_default();
+            write_array(scalars.len(), out);
+            for scalar in &scalars {
+                encode_value(scalar, out);
+            }
+            if !scalars.is_empty() {
+                write_long(0, out);
+            }
+        }
+    }
+}
+
+/// Writes the count of the only block of an array, or the end of an empty array.
+fn write_array(len: usize, out: &mut Vec<u8>) {
+    write_long(len as i64, out);
+}
+
+/// Writes an `int` or `long`, zig-zag encoded as a variable length integer.
+fn write_long(v: i64, out: &mut Vec<u8>) {
+    let mut n = ((v << 1) ^ (v >> 63)) as u64;
+    while n >= 0x80 {
+        out.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn write_bytes(bytes: &[u8], out: &mut Vec<u8>) {
+    write_long(bytes.len() as i64, out);
+    out.extend_from_slice(bytes);
+}
+
+/// Writes records to an Avro object container file, with no compression.
+pub struct AvroFileWriter<W: Write> {
+    writer: W,
+    sync: [u8; 16],
+    block: Vec<u8>,
+    block_records: u64,
+    records_per_block: u64,
+}
+
+impl<W: Write> AvroFileWriter<W> {
+    /// Writes the header of a file of records with `schema`.
+    pub fn new(mut writer: W, schema: &str) -> Result<Self, ULogError> {
+        // The sync marker only has to be unlikely to appear in the data.
+        let mut sync = [0; 16];
+        for (i, chunk) in sync.chunks_mut(8).enumerate() {
+            let mut hasher = DefaultHasher::new();
+            (schema, i).hash(&mut hasher);
+            chunk.copy_from_slice(&hasher.finish().to_le_bytes());
+        }
+
+        let mut header = b"Obj\x01".to_vec();
+        write_long(2, &mut header);
+        write_bytes(b"avro.schema", &mut header);
+        write_bytes(schema.as_bytes(), &mut header);
+        write_bytes(b"avro.codec", &mut header);
+        write_bytes(b"null", &mut header);
+        write_long(0, &mut header);
+        header.extend_from_slice(&sync);
+        writer.write_all(&header)?;
+
+        Ok(AvroFileWriter {
+            writer,
+            sync,
+            block: Vec::new(),
+            block_records: 0,
+            records_per_block: 4096,
+        })
+    }
+
+    /// The number of records buffered before a block is written.  Defaults to 4096.
+    #[must_use]
+    pub fn records_per_block(mut self, records: u64) -> Self {
+        self.records_per_block = records.max(1);
+        self
+    }
+
+    /// Appends an encoded record, e.g. by `encode()`.
+    pub fn append(&mut self, record: &[u8]) -> Result<(), ULogError> {
+        self.block.extend_from_slice(record);
+        self.block_records += 1;
+        if self.block_records >= self.records_per_block {
+            self.write_block()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the last block, and returns the writer.
+    pub fn finish(mut self) -> Result<W, ULogError> {
+        self.write_block()?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_block(&mut self) -> Result<(), ULogError> {
+        if self.block_records == 0 {
+            return Ok(());
+        }
+        let mut header = Vec::new();
+        write_long(self.block_records as i64, &mut header);
+        write_long(self.block.len() as i64, &mut header);
+        self.writer.write_all(&header)?;
+        self.writer.write_all(&self.block)?;
+        self.writer.write_all(&self.sync)?;
+        self.block.clear();
+        self.block_records = 0;
+        Ok(())
+    }
+}
+
+/// Writes an Avro file per topic, named after the topic, with the records of all its instances.
+#[derive(Debug, Clone)]
+pub struct AvroExporter {
+    out_dir: PathBuf,
+}
+
+impl AvroExporter {
+    pub fn new(out_dir: impl AsRef<Path>) -> Self {
+        AvroExporter {
+            out_dir: out_dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Writes the files, and returns their paths, in the order of the topics' first messages.
+    pub fn export<I>(&self, messages: I) -> Result<Vec<PathBuf>, ULogError>
+    where
+        I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+    {
+        fs::create_dir_all(&self.out_dir)?;
+
+        let mut paths = Vec::new();
+        let mut files: HashMap<String, AvroFileWriter<BufWriter<File>>> = HashMap::new();
+        let mut record = Vec::new();
+        let mut lossy_casts = LossyCasts::default();
+
+        for message in messages {
+            let UlogMessage::LoggedData(data) = message? else {
+                continue;
+            };
+
+            let topic = &*data.data.name;
+            if !files.contains_key(topic) {
+                let path = self.out_dir.join(format!("{topic}.avro"));
+                let writer = BufWriter::new(File::create(&path)?);
+                files.insert(
+                    topic.to_owned(),
+                    AvroFileWriter::new(writer, &data_schema(&data.data)?)?,
+                );
+                paths.push(path);
+            }
+
+            // Avro has no unsigned types, so a `u64` above `i64::MAX` wraps.
+            for_each_scalar(&data.data, &mut |value| {
+                if let FieldValue::ScalarU64(_) = value {
+                    if let Err(error) = value.to_i64_checked() {
+                        lossy_casts.record(topic, error);
+                    }
+                }
+            });
+            record.clear();
+            encode(&data, &mut record);
+            files
+                .get_mut(topic)
+                .expect("the file was created")
+                .append(&record)?;
+        }
+
+        for file in files.into_values() {
+            file.finish()?;
+        }
+        lossy_casts.warn("The Avro exporter");
+        Ok(paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::formats::parse_format;
+    use crate::message_buf::MessageBuf;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    fn format(definition: &str) -> def::Format {
+        parse_format(MessageBuf::from_vec(definition.as_bytes().to_vec())).unwrap()
+    }
+
+    #[test]
+    fn test_schema() -> Result<(), ULogError> {
+        let point = format("point:float x;float y;uint8_t[4] _padding0;");
+        let top =
+            format("path:uint64_t timestamp;point[2] points;point origin;char[8] name;uint64_t n;");
+
+        let mut formats = HashMap::new();
+        assert!(matches!(
+            schema(&top, &formats),
+            Err(ULogError::UndefinedFormat(_))
+        ));
+        formats.insert(point.name.clone(), Shared::new(point));
+
+        let schema = schema(&top, &formats)?;
+        let json: serde_json::Value = serde_json::from_str(&schema).unwrap();
+        assert_eq!(json["name"], "path");
+        let fields = json["fields"].as_array().unwrap();
+        let names: Vec<&str> = fields.iter().map(|f| f["name"].as_str().unwrap()).collect();
+        assert_eq!(
+            names,
+            ["timestamp", "multi_id", "points", "origin", "name", "n"]
+        );
+        assert_eq!(fields[2]["type"]["type"], "array");
+        assert_eq!(fields[2]["type"]["items"]["fields"][1]["name"], "y");
+        // The second use of a record refers to it by name.
+        assert_eq!(fields[3]["type"], "point");
+        assert_eq!(fields[4]["type"], "string");
+        assert_eq!(fields[5]["type"], "long");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_export() -> Result<(), ULogError> {
+        let bytes = LogBuilder::new()
+            .topic(
+                Topic::new("a")
+                    .rate_hz(2.0)
+                    .field("x", BaseType::INT16, Generator::Constant(-2.0))
+                    .array_field("v", BaseType::FLOAT, 2, Generator::Constant(1.0)),
+            )
+            .build()?;
+        let data: Vec<LoggedData> = ULogParserBuilder::new(bytes.as_slice())
+            .build()?
+            .filter_map(|m| match m {
+                Ok(UlogMessage::LoggedData(data)) => Some(data),
+                _ => None,
+            })
+            .collect();
+
+        let mut record = Vec::new();
+        encode(&data[0], &mut record);
+        let mut expected = vec![0x80, 0x89, 0x7a, 0x00, 0x03, 0x04];
+        expected.extend_from_slice(&1.0f32.to_le_bytes());
+        expected.extend_from_slice(&1.0f32.to_le_bytes());
+        expected.push(0x00);
+        assert_eq!(record, expected);
+
+        let dir = tempfile::tempdir()?;
+        let paths = AvroExporter::new(dir.path())
+            .export(ULogParserBuilder::new(bytes.as_slice()).build()?)?;
+        assert_eq!(paths, [dir.path().join("a.avro")]);
+        let file = fs::read(&paths[0])?;
+        assert!(file.starts_with(b"Obj\x01"));
+        let schema = data_schema(&data[0].data)?;
+        assert!(file
+            .windows(schema.len())
+            .any(|window| window == schema.as_bytes()));
+        // One block of the two records, and its sync marker.
+        let mut block = vec![0x04, 0x00];
+        for data in &data {
+            encode(data, &mut block);
+        }
+        block[1] = 2 * (block.len() - 2) as u8;
+        let (rest, sync) = file.split_at(file.len() - 16);
+        assert!(rest.ends_with(&block));
+        assert!(rest[..rest.len() - block.len()].ends_with(sync));
+
+        Ok(())
+    }
+}
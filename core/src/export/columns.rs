@@ -0,0 +1,268 @@
+//! Collects the `LoggedData` messages of a ULOG file into typed column arrays, one set per topic instance.
+//!
+//! This is the in-memory representation used by columnar exporters.  Columns are named and
+//! flattened the same way as by the CSV exporter.
+
+use crate::errors::ULogError;
+use crate::export::{
+    column_names, for_each_scalar, is_invalid_float, InvalidFloatPolicy, TopicIndex,
+};
+use crate::model::def::BaseType;
+use crate::model::inst::{self, FieldValue};
+use crate::model::msg::UlogMessage;
+
+/// The values of a column.  Booleans and chars are stored as `U8`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnData {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+    U64(Vec<u64>),
+    I8(Vec<i8>),
+    I16(Vec<i16>),
+    I32(Vec<i32>),
+    I64(Vec<i64>),
+    F32(Vec<f32>),
+    F64(Vec<f64>),
+}
+
+impl ColumnData {
+    fn new(base_type: &BaseType) -> Result<Self, ULogError> {
+        Ok(match base_type {
+            BaseType::UINT8 | BaseType::BOOL | BaseType::CHAR => ColumnData::U8(Vec::new()),
+            BaseType::UINT16 => ColumnData::U16(Vec::new()),
+            BaseType::UINT32 => ColumnData::U32(Vec::new()),
+            BaseType::UINT64 => ColumnData::U64(Vec::new()),
+            BaseType::INT8 => ColumnData::I8(Vec::new()),
+            BaseType::INT16 => ColumnData::I16(Vec::new()),
+            BaseType::INT32 => ColumnData::I32(Vec::new()),
+            BaseType::INT64 => ColumnData::I64(Vec::new()),
+            BaseType::FLOAT => ColumnData::F32(Vec::new()),
+            BaseType::DOUBLE => ColumnData::F64(Vec::new()),
+            BaseType::OTHER(name) => {
+                return Err(ULogError::InternalError(format!(
+                    "Nested type {name} cannot be stored in a column."
+                )))
+            }
+        })
+    }
+
+    fn push(&mut self, value: &FieldValue) -> Result<(), ULogError> {
+        match (self, value) {
+            (ColumnData::U8(v), FieldValue::ScalarU8(x)) => v.push(*x),
+            (ColumnData::U8(v), FieldValue::ScalarBool(x)) => v.push(u8::from(*x)),
+            (ColumnData::U8(v), FieldValue::ScalarChar(x)) => v.push(x.0),
+            (ColumnData::U16(v), FieldValue::ScalarU16(x)) => v.push(*x),
+            (ColumnData::U32(v), FieldValue::ScalarU32(x)) => v.push(*x),
+            (ColumnData::U64(v), FieldValue::ScalarU64(x)) => v.push(*x),
+            (ColumnData::I8(v), FieldValue::ScalarI8(x)) => v.push(*x),
+            (ColumnData::I16(v), FieldValue::ScalarI16(x)) => v.push(*x),
+            (ColumnData::I32(v), FieldValue::ScalarI32(x)) => v.push(*x),
+            (ColumnData::I64(v), FieldValue::ScalarI64(x)) => v.push(*x),
+            (ColumnData::F32(v), FieldValue::ScalarF32(x)) => v.push(*x),
+            (ColumnData::F64(v), FieldValue::ScalarF64(x)) => v.push(*x),
+            (column, value) => {
+                return Err(ULogError::TypeMismatch(format!(
+                    "Cannot store {value:?} in a {} column.",
+                    column.type_name()
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    fn push_zero(&mut self) {
+        match self {
+            ColumnData::U8(v) => v.push(0),
+            ColumnData::U16(v) => v.push(0),
+            ColumnData::U32(v) => v.push(0),
+            ColumnData::U64(v) => v.push(0),
+            ColumnData::I8(v) => v.push(0),
+            ColumnData::I16(v) => v.push(0),
+            ColumnData::I32(v) => v.push(0),
+            ColumnData::I64(v) => v.push(0),
+            ColumnData::F32(v) => v.push(0.0),
+            ColumnData::F64(v) => v.push(0.0),
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            ColumnData::U8(_) => "uint8_t",
+            ColumnData::U16(_) => "uint16_t",
+            ColumnData::U32(_) => "uint32_t",
+            ColumnData::U64(_) => "uint64_t",
+            ColumnData::I8(_) => "int8_t",
+            ColumnData::I16(_) => "int16_t",
+            ColumnData::I32(_) => "int32_t",
+            ColumnData::I64(_) => "int64_t",
+            ColumnData::F32(_) => "float",
+            ColumnData::F64(_) => "double",
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            ColumnData::U8(v) => v.len(),
+            ColumnData::U16(v) => v.len(),
+            ColumnData::U32(v) => v.len(),
+            ColumnData::U64(v) => v.len(),
+            ColumnData::I8(v) => v.len(),
+            ColumnData::I16(v) => v.len(),
+            ColumnData::I32(v) => v.len(),
+            ColumnData::I64(v) => v.len(),
+            ColumnData::F32(v) => v.len(),
+            ColumnData::F64(v) => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub name: String,
+    /// The type of the field in the ULOG format.
+    pub r#type: BaseType,
+    pub data: ColumnData,
+}
+
+/// The columns of a topic instance.  All columns have the same length as `timestamps`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopicColumns {
+    pub name: String,
+    pub multi_id: u8,
+    pub timestamps: Vec<u64>,
+    pub columns: Vec<Column>,
+}
+
+impl TopicColumns {
+    fn new(format: &inst::Format, multi_id: u8) -> Result<Self, ULogError> {
+        let columns = column_names(format)
+            .into_iter()
+            .map(|(name, r#type)| {
+                Ok(Column {
+                    data: ColumnData::new(&r#type)?,
+                    name,
+                    r#type,
+                })
+            })
+            .collect::<Result<_, ULogError>>()?;
+
+        Ok(TopicColumns {
+            name: format.name.to_string(),
+            multi_id,
+            timestamps: Vec::new(),
+            columns,
+        })
+    }
+
+    fn push(
+        &mut self,
+        timestamp: u64,
+        format: &inst::Format,
+        policy: InvalidFloatPolicy,
+    ) -> Result<(), ULogError> {
+        let mut columns = self.columns.iter_mut();
+        let mut result = Ok(());
+
+        for_each_scalar(format, &mut |value| {
+            if result.is_ok() {
+                result = match columns.next() {
+                    Some(column)
+                        if policy == InvalidFloatPolicy::ClampToZero && is_invalid_float(value) =>
+                    {
+                        column.data.push_zero();
+                        Ok(())
+                    }
+                    Some(column) => column.data.push(value),
+                    None => Err(ULogError::TypeMismatch(format!(
+                        "More fields than columns in message for {}.",
+                        format.name
+                    ))),
+                };
+            }
+        });
+
+        result?;
+        self.timestamps.push(timestamp);
+        Ok(())
+    }
+
+    pub fn column(&self, name: &str) -> Option<&Column> {
+        self.columns.iter().find(|c| c.name == name)
+    }
+}
+
+/// Collects the `LoggedData` messages in `messages` into columns, in order of first appearance of each topic instance.
+///
+/// NaN and infinite floats are kept.  See `collect_with()`.
+pub fn collect<I>(messages: I) -> Result<Vec<TopicColumns>, ULogError>
+where
+    I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+{
+    collect_with(messages, InvalidFloatPolicy::AsNaNString)
+}
+
+/// Like `collect()`, but treats NaN and infinite floats according to `policy`.  As columns have no nulls,
+/// `InvalidFloatPolicy::AsNull` keeps them, like `InvalidFloatPolicy::AsNaNString`.
+pub fn collect_with<I>(
+    messages: I,
+    policy: InvalidFloatPolicy,
+) -> Result<Vec<TopicColumns>, ULogError>
+where
+    I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+{
+    let mut topics: Vec<TopicColumns> = Vec::new();
+    let mut topic_index = TopicIndex::default();
+
+    for message in messages {
+        let UlogMessage::LoggedData(data) = message? else {
+            continue;
+        };
+
+        let (index, multi_id) = topic_index.get(&data);
+        if index == topics.len() {
+            topics.push(TopicColumns::new(&data.data, multi_id)?);
+        }
+
+        if !policy.drops(&data.data) {
+            topics[index].push(data.timestamp, &data.data, policy)?;
+        }
+    }
+
+    Ok(topics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+
+    #[test]
+    fn test_collect() -> Result<(), ULogError> {
+        let input = std::fs::read("test_data/input/short_list.ulg")?;
+        let parser = ULogParserBuilder::new(input.as_slice()).build()?;
+
+        let topics = collect(parser)?;
+        let topic = &topics[0];
+
+        assert_eq!(topic.multi_id, 0);
+        assert_eq!(topic.columns.len(), 8);
+        assert_eq!(topic.timestamps[..2], [0, 10]);
+
+        let column = topic.column("list[1]").unwrap();
+        assert_eq!(column.r#type, BaseType::DOUBLE);
+        match &column.data {
+            ColumnData::F64(values) => {
+                assert_eq!(values.len(), topic.timestamps.len());
+                assert_eq!(values[..2], [1.0, 11.0]);
+            }
+            other => panic!("Unexpected column data {other:?}"),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,500 @@
+//! Exports the `LoggedData` messages of a ULOG file to one CSV file per topic instance.
+//!
+//! The layout follows `ulog2csv` from `pyulog`, so that existing downstream scripts keep working:
+//!
+//! - Each topic instance is written to `<topic>_<multi_id>.csv`, with a `multi_id` of 0 for
+//!   topics which have a single instance.
+//! - The first column is the `timestamp`, followed by the fields in format order.  Array elements
+//!   are named `field[i]` and nested fields `field.nested`.  Padding fields are omitted.
+//! - Booleans and chars are written as integers.
+//!
+//! In addition, a `manifest.json` file describes the exported topics, their fields, message counts and time ranges.
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::bitfield::BitFields;
+use crate::errors::ULogError;
+use crate::export::{
+    column_names, for_each_scalar, json_string, FloatFormat, InvalidFloatPolicy, Provenance,
+    TopicIndex, Units,
+};
+use crate::model::def::BaseType;
+use crate::model::inst;
+use crate::model::msg::UlogMessage;
+
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// Exports `messages` to CSV files in `out_dir`, which is created if necessary.
+///
+/// `messages` is typically a `ULogParser`.  Any `LoggedData` messages it was configured to ignore
+/// are not exported.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use std::fs::File;
+/// # use std::io::BufReader;
+/// # use yule_log::builder::ULogParserBuilder;
+/// # use yule_log::export::csv;
+/// # fn main() -> Result<(), yule_log::errors::ULogError> {
+/// let parser = ULogParserBuilder::new(BufReader::new(File::open("sample.ulg")?)).build()?;
+///
+/// let manifest = csv::export_dir(parser, "sample_csv")?;
+/// println!("Exported {} topics", manifest.topics.len());
+/// # Ok(())
+/// # }
+/// ```
+pub fn export_dir<I>(messages: I, out_dir: impl AsRef<Path>) -> Result<Manifest, ULogError>
+where
+    I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+{
+    CsvExporter::new(out_dir).export(messages)
+}
+
+/// A configurable CSV exporter.  See `export_dir()`.
+#[derive(Debug, Clone)]
+pub struct CsvExporter {
+    out_dir: PathBuf,
+    units: Units,
+    invalid_floats: InvalidFloatPolicy,
+    float_format: FloatFormat,
+    bit_fields: BitFields,
+    provenance: Option<Provenance>,
+}
+
+impl CsvExporter {
+    pub fn new(out_dir: impl AsRef<Path>) -> Self {
+        CsvExporter {
+            out_dir: out_dir.as_ref().to_path_buf(),
+            units: Units::default(),
+            invalid_floats: InvalidFloatPolicy::AsNaNString,
+            float_format: FloatFormat::default(),
+            bit_fields: BitFields::default(),
+            provenance: None,
+        }
+    }
+
+    /// Records the unit of a column in the manifest.  `column` is the CSV column name, such as `q[0]`.
+    #[must_use]
+    pub fn unit(mut self, topic: &str, column: &str, unit: &str) -> Self {
+        self.units.insert(topic, column, unit);
+        self
+    }
+
+    /// Records the units of several columns in the manifest.
+    #[must_use]
+    pub fn units(mut self, units: Units) -> Self {
+        self.units = units;
+        self
+    }
+
+    /// How NaN and infinite floats are written.  Defaults to `InvalidFloatPolicy::AsNaNString`, as by `pyulog`.
+    #[must_use]
+    pub fn invalid_floats(mut self, policy: InvalidFloatPolicy) -> Self {
+        self.invalid_floats = policy;
+        self
+    }
+
+    /// How finite floats are written.  Defaults to `FloatFormat::Shortest`.
+    #[must_use]
+    pub fn float_format(mut self, format: FloatFormat) -> Self {
+        self.float_format = format;
+        self
+    }
+
+    /// Adds a `<column>_flags` column after the other columns for each bit field of a topic, with the names of the
+    /// flags set, separated by `|`.
+    #[must_use]
+    pub fn bit_fields(mut self, bit_fields: BitFields) -> Self {
+        self.bit_fields = bit_fields;
+        self
+    }
+
+    /// Adds the `Provenance::COLUMNS` as the last columns of every file, with the same values in every row.
+    #[must_use]
+    pub fn provenance(mut self, provenance: Provenance) -> Self {
+        self.provenance = Some(provenance);
+        self
+    }
+
+    pub fn export<I>(&self, messages: I) -> Result<Manifest, ULogError>
+    where
+        I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+    {
+        fs::create_dir_all(&self.out_dir)?;
+
+        let mut topics: Vec<TopicFile> = Vec::new();
+        let mut topic_index = TopicIndex::default();
+        let mut values: Vec<String> = Vec::new();
+        let provenance: Vec<String> = self
+            .provenance
+            .iter()
+            .flat_map(Provenance::columns)
+            .map(|(_, value)| value.map(csv_cell).unwrap_or_default())
+            .collect();
+
+        for message in messages {
+            let UlogMessage::LoggedData(data) = message? else {
+                continue;
+            };
+
+            let (index, multi_id) = topic_index.get(&data);
+            if index == topics.len() {
+                topics.push(self.create_topic_file(&data.data, multi_id)?);
+            }
+
+            if self.invalid_floats.drops(&data.data) {
+                continue;
+            }
+            let topic = &mut topics[index];
+
+            values.clear();
+            values.push(data.timestamp.to_string());
+            for_each_scalar(&data.data, &mut |value| {
+                values.extend(self.invalid_floats.format_scalar(value, self.float_format));
+            });
+            for column in &topic.flag_columns {
+                let flags = self
+                    .bit_fields
+                    .active(&data.data, column)
+                    .unwrap_or_default();
+                values.push(flags.join("|"));
+            }
+            values.extend(provenance.iter().cloned());
+
+            topic.writer.write_all(values.join(",").as_bytes())?;
+            topic.writer.write_all(b"\n")?;
+
+            let manifest = &mut topic.manifest;
+            if manifest.count == 0 {
+                manifest.first_timestamp = data.timestamp;
+            }
+            manifest.count += 1;
+            manifest.first_timestamp = manifest.first_timestamp.min(data.timestamp);
+            manifest.last_timestamp = manifest.last_timestamp.max(data.timestamp);
+        }
+
+        let mut manifest = Manifest {
+            topics: Vec::with_capacity(topics.len()),
+        };
+
+        for mut topic in topics {
+            topic.writer.flush()?;
+            manifest.topics.push(topic.manifest);
+        }
+
+        manifest
+            .topics
+            .sort_by(|a, b| (&a.name, a.multi_id).cmp(&(&b.name, b.multi_id)));
+
+        fs::write(self.out_dir.join(MANIFEST_FILE_NAME), manifest.to_json())?;
+
+        Ok(manifest)
+    }
+
+    fn create_topic_file(
+        &self,
+        format: &inst::Format,
+        multi_id: u8,
+    ) -> Result<TopicFile, ULogError> {
+        let name = format.name.to_string();
+        let file = format!("{}_{multi_id}.csv", name.replace('/', "_"));
+
+        let mut fields: Vec<FieldManifest> =
+            std::iter::once(("timestamp".to_owned(), BaseType::UINT64))
+                .chain(column_names(format))
+                .map(|(column, base_type)| FieldManifest {
+                    unit: self.units.get(&name, &column).map(str::to_owned),
+                    name: column,
+                    r#type: base_type.to_string(),
+                })
+                .collect();
+        let flag_columns: Vec<String> = self
+            .bit_fields
+            .columns(format)
+            .filter(|column| fields.iter().any(|field| field.name == *column))
+            .map(str::to_owned)
+            .collect();
+        fields.extend(flag_columns.iter().map(|column| FieldManifest {
+            name: format!("{column}_flags"),
+            r#type: "flags".to_owned(),
+            unit: None,
+        }));
+        if self.provenance.is_some() {
+            fields.extend(Provenance::COLUMNS.iter().map(|column| FieldManifest {
+                name: (*column).to_owned(),
+                r#type: "string".to_owned(),
+                unit: None,
+            }));
+        }
+
+        let mut writer = BufWriter::new(File::create(self.out_dir.join(&file))?);
+        let header: Vec<&str> = fields.iter().map(|f| f.name.as_str()).collect();
+        writer.write_all(header.join(",").as_bytes())?;
+        writer.write_all(b"\n")?;
+
+        Ok(TopicFile {
+            manifest: TopicManifest {
+                name,
+                multi_id,
+                file,
+                fields,
+                count: 0,
+                first_timestamp: 0,
+                last_timestamp: 0,
+            },
+            writer,
+            flag_columns,
+        })
+    }
+}
+
+// Quotes `s` if it holds a comma, quote or line break.
+fn csv_cell(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+struct TopicFile {
+    manifest: TopicManifest,
+    writer: BufWriter<File>,
+    // The bit field columns, each followed by a column of its flags.
+    flag_columns: Vec<String>,
+}
+
+/// Describes the CSV files written by an export.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Manifest {
+    pub topics: Vec<TopicManifest>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopicManifest {
+    pub name: String,
+    pub multi_id: u8,
+    /// The CSV file name, relative to the export directory.
+    pub file: String,
+    pub fields: Vec<FieldManifest>,
+    pub count: u64,
+    pub first_timestamp: u64,
+    pub last_timestamp: u64,
+}
+
+/// A CSV column.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldManifest {
+    pub name: String,
+    pub r#type: String,
+    pub unit: Option<String>,
+}
+
+impl Manifest {
+    /// Returns the first and last timestamps over all exported topics.
+    pub fn time_range(&self) -> Option<(u64, u64)> {
+        let start = self.topics.iter().map(|t| t.first_timestamp).min()?;
+        let end = self.topics.iter().map(|t| t.last_timestamp).max()?;
+        Some((start, end))
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\n  \"topics\": [");
+
+        for (i, topic) in self.topics.iter().enumerate() {
+            json.push_str(if i == 0 { "\n" } else { ",\n" });
+            json.push_str(&format!(
+                "    {{\n      \"name\": {},\n      \"multi_id\": {},\n      \"file\": {},\n      \"count\": {},\n      \"first_timestamp\": {},\n      \"last_timestamp\": {},\n      \"fields\": [",
+                json_string(&topic.name),
+                topic.multi_id,
+                json_string(&topic.file),
+                topic.count,
+                topic.first_timestamp,
+                topic.last_timestamp,
+            ));
+
+            for (j, field) in topic.fields.iter().enumerate() {
+                let unit = field
+                    .unit
+                    .as_deref()
+                    .map_or_else(|| "null".to_owned(), json_string);
+                json.push_str(if j == 0 { "\n" } else { ",\n" });
+                json.push_str(&format!(
+                    "        {{ \"name\": {}, \"type\": {}, \"unit\": {unit} }}",
+                    json_string(&field.name),
+                    json_string(&field.r#type),
+                ));
+            }
+
+            json.push_str("\n      ]\n    }");
+        }
+
+        json.push_str("\n  ],\n  \"time_range\": ");
+        match self.time_range() {
+            Some((start, end)) => {
+                json.push_str(&format!("{{ \"start\": {start}, \"end\": {end} }}"))
+            }
+            None => json.push_str("null"),
+        }
+        json.push_str("\n}\n");
+
+        json
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    #[test]
+    fn test_export_dir() -> Result<(), ULogError> {
+        let dir = tempfile::tempdir()?;
+        let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+
+        let parser = ULogParserBuilder::new(input.as_slice()).build()?;
+        let manifest = CsvExporter::new(dir.path())
+            .unit("vehicle_local_position", "x", "m")
+            .export(parser)?;
+
+        assert!(!manifest.topics.is_empty());
+
+        for topic in &manifest.topics {
+            let csv = fs::read_to_string(dir.path().join(&topic.file))?;
+            let mut lines = csv.lines();
+
+            let header: Vec<&str> = lines.next().unwrap().split(',').collect();
+            let names: Vec<&str> = topic.fields.iter().map(|f| f.name.as_str()).collect();
+            assert_eq!(header, names);
+            assert_eq!(header[0], "timestamp");
+            assert!(!header.iter().any(|h| h.contains("_padding")));
+
+            let mut count = 0;
+            for line in lines {
+                assert_eq!(
+                    line.split(',').count(),
+                    header.len(),
+                    "{}: {line}",
+                    topic.file
+                );
+                count += 1;
+            }
+            assert_eq!(count, topic.count);
+            assert!(topic.first_timestamp <= topic.last_timestamp);
+        }
+
+        let json = fs::read_to_string(dir.path().join(MANIFEST_FILE_NAME))?;
+        assert!(json.contains("\"time_range\": { \"start\": "));
+
+        if let Some(topic) = manifest
+            .topics
+            .iter()
+            .find(|t| t.name == "vehicle_local_position")
+        {
+            let x = topic.fields.iter().find(|f| f.name == "x").unwrap();
+            assert_eq!(x.unit.as_deref(), Some("m"));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_flatten() -> Result<(), ULogError> {
+        let input = std::fs::read("test_data/input/short_list.ulg")?;
+        let parser = ULogParserBuilder::new(input.as_slice()).build()?;
+
+        let dir = tempfile::tempdir()?;
+        let manifest = export_dir(parser, dir.path())?;
+
+        let topic = &manifest.topics[0];
+        assert_eq!(topic.file, format!("{}_0.csv", topic.name));
+
+        let csv = fs::read_to_string(dir.path().join(&topic.file))?;
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            "timestamp,list[0],list[1],list[2],list[3],list[4],list[5],list[6],list[7]"
+        );
+        assert_eq!(lines.next().unwrap(), "0,0,1,2,3,4,5,6,7");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_provenance() -> Result<(), ULogError> {
+        let input = std::fs::read("test_data/input/short_list.ulg")?;
+        let parser = ULogParserBuilder::new(input.as_slice()).build()?;
+
+        let dir = tempfile::tempdir()?;
+        let provenance = Provenance {
+            file_name: Some("flight, 1.ulg".to_owned()),
+            file_hash: Some("00ff".to_owned()),
+            vehicle_uuid: None,
+        };
+        let manifest = CsvExporter::new(dir.path())
+            .provenance(provenance)
+            .export(parser)?;
+
+        let topic = &manifest.topics[0];
+        assert_eq!(topic.fields.last().unwrap().name, "vehicle_uuid");
+        let csv = fs::read_to_string(dir.path().join(&topic.file))?;
+        let mut lines = csv.lines();
+        assert!(lines
+            .next()
+            .unwrap()
+            .ends_with(",list[7],file_name,file_hash,vehicle_uuid"));
+        assert_eq!(
+            lines.next().unwrap(),
+            "0,0,1,2,3,4,5,6,7,\"flight, 1.ulg\",00ff,"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_floats() -> Result<(), ULogError> {
+        let bytes = LogBuilder::new()
+            .topic(Topic::new("a").rate_hz(2.0).field(
+                "x",
+                BaseType::FLOAT,
+                Generator::from_fn(|sample| match sample.index {
+                    0 => f64::NAN,
+                    _ => 1.5,
+                }),
+            ))
+            .build()?;
+        let export = |policy| -> Result<String, ULogError> {
+            let dir = tempfile::tempdir()?;
+            let parser = ULogParserBuilder::new(bytes.as_slice()).build()?;
+            let manifest = CsvExporter::new(dir.path())
+                .invalid_floats(policy)
+                .export(parser)?;
+            Ok(fs::read_to_string(
+                dir.path().join(&manifest.topics[0].file),
+            )?)
+        };
+
+        assert_eq!(
+            export(InvalidFloatPolicy::AsNaNString)?,
+            "timestamp,x\n1000000,nan\n1500000,1.5\n"
+        );
+        assert_eq!(
+            export(InvalidFloatPolicy::AsNull)?,
+            "timestamp,x\n1000000,\n1500000,1.5\n"
+        );
+        assert_eq!(
+            export(InvalidFloatPolicy::ClampToZero)?,
+            "timestamp,x\n1000000,0\n1500000,1.5\n"
+        );
+        assert_eq!(
+            export(InvalidFloatPolicy::Drop)?,
+            "timestamp,x\n1500000,1.5\n"
+        );
+
+        Ok(())
+    }
+}
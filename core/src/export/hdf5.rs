@@ -0,0 +1,138 @@
+//! Exports the `LoggedData` messages of a ULOG file to HDF5.
+//!
+//! Each topic instance is written to a group named `<topic>_<multi_id>`, with `topic` and `multi_id`
+//! attributes.  The group holds one dataset per column, named as by the CSV exporter, starting with
+//! the `timestamp` in microseconds.  Each column dataset has a `type` attribute holding the ULOG type,
+//! and a `unit` attribute if its unit is known.
+
+use std::path::{Path, PathBuf};
+
+use hdf5::types::VarLenUnicode;
+use hdf5::{Dataset, Group, H5Type, Location};
+
+use crate::errors::ULogError;
+use crate::export::columns::{self, ColumnData, TopicColumns};
+use crate::export::{InvalidFloatPolicy, Units};
+use crate::model::msg::UlogMessage;
+
+/// Exports `messages` to a new HDF5 file at `path`.
+pub fn export_file<I>(messages: I, path: impl AsRef<Path>) -> Result<(), ULogError>
+where
+    I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+{
+    Hdf5Exporter::new(path).export(messages)
+}
+
+/// A configurable HDF5 exporter.  See `export_file()`.
+#[derive(Debug, Clone)]
+pub struct Hdf5Exporter {
+    path: PathBuf,
+    units: Units,
+    invalid_floats: InvalidFloatPolicy,
+}
+
+impl Hdf5Exporter {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Hdf5Exporter {
+            path: path.as_ref().to_path_buf(),
+            units: Units::default(),
+            invalid_floats: InvalidFloatPolicy::AsNaNString,
+        }
+    }
+
+    /// Records the unit of a column, as a `unit` attribute of its dataset.
+    #[must_use]
+    pub fn unit(mut self, topic: &str, column: &str, unit: &str) -> Self {
+        self.units.insert(topic, column, unit);
+        self
+    }
+
+    #[must_use]
+    pub fn units(mut self, units: Units) -> Self {
+        self.units = units;
+        self
+    }
+
+    /// How NaN and infinite floats are written.  HDF5 stores them as they are, so only
+    /// `InvalidFloatPolicy::Drop` and `InvalidFloatPolicy::ClampToZero` change them.  Defaults to keeping them.
+    #[must_use]
+    pub fn invalid_floats(mut self, policy: InvalidFloatPolicy) -> Self {
+        self.invalid_floats = policy;
+        self
+    }
+
+    /// Collects `messages` in memory, then writes them to the HDF5 file.
+    pub fn export<I>(&self, messages: I) -> Result<(), ULogError>
+    where
+        I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+    {
+        let topics = columns::collect_with(messages, self.invalid_floats)?;
+
+        let file = hdf5::File::create(&self.path).map_err(hdf5_error)?;
+        for topic in &topics {
+            self.write_topic(&file, topic).map_err(hdf5_error)?;
+        }
+        file.close().map_err(hdf5_error)?;
+
+        Ok(())
+    }
+
+    fn write_topic(&self, file: &hdf5::File, topic: &TopicColumns) -> hdf5::Result<()> {
+        let group_name = format!("{}_{}", topic.name.replace('/', "_"), topic.multi_id);
+        let group = file.create_group(&group_name)?;
+
+        write_str_attr(&group, "topic", &topic.name)?;
+        group
+            .new_attr::<u8>()
+            .create("multi_id")?
+            .write_scalar(&topic.multi_id)?;
+
+        let timestamps = write_dataset(&group, "timestamp", &topic.timestamps)?;
+        write_str_attr(&timestamps, "type", "uint64_t")?;
+        write_str_attr(&timestamps, "unit", "us")?;
+
+        for column in &topic.columns {
+            let name = column.name.as_str();
+            let dataset = match &column.data {
+                ColumnData::U8(v) => write_dataset(&group, name, v)?,
+                ColumnData::U16(v) => write_dataset(&group, name, v)?,
+                ColumnData::U32(v) => write_dataset(&group, name, v)?,
+                ColumnData::U64(v) => write_dataset(&group, name, v)?,
+                ColumnData::I8(v) => write_dataset(&group, name, v)?,
+                ColumnData::I16(v) => write_dataset(&group, name, v)?,
+                ColumnData::I32(v) => write_dataset(&group, name, v)?,
+                ColumnData::I64(v) => write_dataset(&group, name, v)?,
+                ColumnData::F32(v) => write_dataset(&group, name, v)?,
+                ColumnData::F64(v) => write_dataset(&group, name, v)?,
+            };
+
+            write_str_attr(&dataset, "type", &column.r#type.to_string())?;
+            if let Some(unit) = self.units.get(&topic.name, name) {
+                write_str_attr(&dataset, "unit", unit)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn write_dataset<T: H5Type>(group: &Group, name: &str, values: &[T]) -> hdf5::Result<Dataset> {
+    let dataset = group.new_dataset::<T>().shape(values.len()).create(name)?;
+    dataset.write_raw(values)?;
+    Ok(dataset)
+}
+
+fn write_str_attr(location: &Location, name: &str, value: &str) -> hdf5::Result<()> {
+    let value: VarLenUnicode = value
+        .parse()
+        .map_err(|_| hdf5::Error::from("Attribute values cannot contain NUL characters."))?;
+
+    location
+        .new_attr::<VarLenUnicode>()
+        .create(name)?
+        .write_scalar(&value)
+}
+
+fn hdf5_error(e: hdf5::Error) -> ULogError {
+    ULogError::ExportError(format!("HDF5: {e}"))
+}
@@ -0,0 +1,285 @@
+//! Converts column and topic names into identifiers which databases and columnar formats accept.
+//!
+//! Exported column names, such as `q[0]` and `setpoint.vx`, are not valid SQL identifiers.  `sanitize_identifier()`
+//! converts a name for a `Dialect`, and `IdentifierMap` makes the identifiers of a table unique, and maps them back
+//! to the names they came from:
+//!
+//! ```rust
+//! use yule_log::export::identifiers::{sanitize_identifier, Dialect, IdentifierMap};
+//!
+//! assert_eq!(sanitize_identifier("q[0]", Dialect::Postgres), "q_0");
+//!
+//! let mut columns = IdentifierMap::new(Dialect::Postgres);
+//! assert_eq!(columns.insert("accel.x"), "accel_x");
+//! assert_eq!(columns.insert("accel_x"), "accel_x_2");
+//! assert_eq!(columns.original("accel_x_2"), Some("accel_x"));
+//! ```
+
+use std::collections::{HashMap, HashSet};
+
+/// The rules for the identifiers of a database or format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    /// Identifiers of ASCII letters, digits and underscores, not starting with a digit, and not reserved words.
+    Sql,
+    /// Like `Sql`, but in lower case, as PostgreSQL folds unquoted identifiers, and at most 63 bytes.
+    Postgres,
+    /// Like `Sql`, at most 64 bytes.
+    MySql,
+    /// Like `Sql`.  SQLite compares identifiers without regard to case.
+    Sqlite,
+    /// Any non empty name.  Only names which are empty are changed.
+    Arrow,
+}
+
+impl Dialect {
+    fn max_len(self) -> Option<usize> {
+        match self {
+            Dialect::Postgres => Some(63),
+            Dialect::MySql => Some(64),
+            Dialect::Sql | Dialect::Sqlite | Dialect::Arrow => None,
+        }
+    }
+
+    /// Returns the key under which identifiers collide, e.g. their lower case for dialects which ignore case.
+    fn collision_key(self, identifier: &str) -> String {
+        match self {
+            Dialect::Arrow => identifier.to_owned(),
+            _ => identifier.to_ascii_lowercase(),
+        }
+    }
+}
+
+// Words which are reserved in all the SQL dialects, and so can't be used as unquoted identifiers.
+const RESERVED_WORDS: &[&str] = &[
+    "all",
+    "and",
+    "as",
+    "by",
+    "case",
+    "check",
+    "column",
+    "create",
+    "default",
+    "delete",
+    "distinct",
+    "drop",
+    "else",
+    "end",
+    "from",
+    "group",
+    "having",
+    "in",
+    "insert",
+    "into",
+    "is",
+    "join",
+    "like",
+    "not",
+    "null",
+    "on",
+    "or",
+    "order",
+    "primary",
+    "references",
+    "select",
+    "table",
+    "then",
+    "to",
+    "union",
+    "unique",
+    "update",
+    "user",
+    "values",
+    "when",
+    "where",
+    "with",
+];
+
+/// Converts `name` into an identifier for `dialect`.
+///
+/// For the SQL dialects, each run of characters other than ASCII letters, digits and underscores is replaced by
+/// a single underscore, and trailing underscores are removed, so `q[0]` becomes `q_0`.  An identifier starting
+/// with a digit, or which is a reserved word, is prefixed with an underscore.  Long identifiers are truncated.
+pub fn sanitize_identifier(name: &str, dialect: Dialect) -> String {
+    if dialect == Dialect::Arrow {
+        return if name.is_empty() {
+            "_".to_owned()
+        } else {
+            name.to_owned()
+        };
+    }
+
+    let mut identifier = String::with_capacity(name.len());
+    let mut replaced = false;
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            identifier.push(if dialect == Dialect::Postgres {
+                c.to_ascii_lowercase()
+            } else {
+                c
+            });
+            replaced = false;
+        } else if !replaced {
+            identifier.push('_');
+            replaced = true;
+        }
+    }
+    if replaced {
+        identifier.pop();
+    }
+
+    let starts_with_digit = identifier.starts_with(|c: char| c.is_ascii_digit());
+    if identifier.is_empty()
+        || starts_with_digit
+        || RESERVED_WORDS.contains(&identifier.to_ascii_lowercase().as_str())
+    {
+        identifier.insert(0, '_');
+    }
+
+    if let Some(max_len) = dialect.max_len() {
+        identifier.truncate(max_len);
+    }
+    identifier
+}
+
+/// Assigns unique identifiers to a set of names, e.g. the columns of a table, and maps them back.
+///
+/// Names which sanitize to an identifier already taken get a numeric suffix, `_2`, `_3`, etc.
+#[derive(Debug, Clone)]
+pub struct IdentifierMap {
+    dialect: Dialect,
+    by_name: HashMap<String, String>,
+    // The columns which aren't among the names, e.g. key columns added by an exporter.
+    reserved: HashMap<String, String>,
+    by_identifier: HashMap<String, String>,
+    // The collision keys of the identifiers.
+    taken: HashSet<String>,
+}
+
+impl IdentifierMap {
+    pub fn new(dialect: Dialect) -> Self {
+        IdentifierMap {
+            dialect,
+            by_name: HashMap::new(),
+            reserved: HashMap::new(),
+            by_identifier: HashMap::new(),
+            taken: HashSet::new(),
+        }
+    }
+
+    /// Returns the identifier of `name`, assigning one if `name` is new.
+    pub fn insert(&mut self, name: &str) -> &str {
+        if !self.by_name.contains_key(name) {
+            let identifier = self.unique_identifier(name);
+            self.taken.insert(self.dialect.collision_key(&identifier));
+            self.by_identifier
+                .insert(identifier.clone(), name.to_owned());
+            self.by_name.insert(name.to_owned(), identifier);
+        }
+        &self.by_name[name]
+    }
+
+    /// Returns the identifier of `name`, a column which isn't one of the names, e.g. a key column added by an exporter,
+    /// assigning one if it is new.  Its identifier is taken apart from the names, so that one which sanitizes to it,
+    /// i.e. a field of the same name, gets a suffix.
+    pub fn insert_reserved(&mut self, name: &str) -> &str {
+        if !self.reserved.contains_key(name) {
+            let identifier = self.unique_identifier(name);
+            self.taken.insert(self.dialect.collision_key(&identifier));
+            self.by_identifier
+                .insert(identifier.clone(), name.to_owned());
+            self.reserved.insert(name.to_owned(), identifier);
+        }
+        &self.reserved[name]
+    }
+
+    /// Returns the identifier assigned to the reserved column `name`.
+    pub fn reserved(&self, name: &str) -> Option<&str> {
+        self.reserved.get(name).map(String::as_str)
+    }
+
+    /// Returns the identifier assigned to `name`.
+    pub fn identifier(&self, name: &str) -> Option<&str> {
+        self.by_name.get(name).map(String::as_str)
+    }
+
+    /// Returns the name which was assigned `identifier`.
+    pub fn original(&self, identifier: &str) -> Option<&str> {
+        self.by_identifier.get(identifier).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_name.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    fn unique_identifier(&self, name: &str) -> String {
+        let base = sanitize_identifier(name, self.dialect);
+        let is_taken =
+            |identifier: &str| self.taken.contains(&self.dialect.collision_key(identifier));
+        if !is_taken(&base) {
+            return base;
+        }
+
+        (2..)
+            .map(|n| {
+                let suffix = format!("_{n}");
+                let mut identifier = base.clone();
+                if let Some(max_len) = self.dialect.max_len() {
+                    identifier.truncate(max_len - suffix.len());
+                }
+                identifier + &suffix
+            })
+            .find(|identifier| !is_taken(identifier))
+            .expect("an unused suffix exists")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_identifier() {
+        assert_eq!(sanitize_identifier("q[0]", Dialect::Sql), "q_0");
+        assert_eq!(
+            sanitize_identifier("setpoint.vx", Dialect::Sql),
+            "setpoint_vx"
+        );
+        assert_eq!(sanitize_identifier("a[1].b[2]", Dialect::Sql), "a_1_b_2");
+        assert_eq!(sanitize_identifier("Vx", Dialect::Postgres), "vx");
+        assert_eq!(sanitize_identifier("Vx", Dialect::Sqlite), "Vx");
+        assert_eq!(sanitize_identifier("0x", Dialect::Sql), "_0x");
+        assert_eq!(sanitize_identifier("order", Dialect::MySql), "_order");
+        assert_eq!(sanitize_identifier("[]", Dialect::Sql), "_");
+        assert_eq!(sanitize_identifier("q[0]", Dialect::Arrow), "q[0]");
+        assert_eq!(
+            sanitize_identifier(&"x".repeat(100), Dialect::Postgres).len(),
+            63
+        );
+    }
+
+    #[test]
+    fn test_identifier_map() {
+        let mut map = IdentifierMap::new(Dialect::Sqlite);
+        assert_eq!(map.insert("a.b"), "a_b");
+        assert_eq!(map.insert("a[b]"), "a_b_2");
+        assert_eq!(map.insert("A_B"), "A_B_3");
+        assert_eq!(map.insert("a.b"), "a_b");
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.identifier("a[b]"), Some("a_b_2"));
+        assert_eq!(map.original("A_B_3"), Some("A_B"));
+        assert_eq!(map.original("a_b_4"), None);
+
+        let mut map = IdentifierMap::new(Dialect::Postgres);
+        let long = "x".repeat(70);
+        assert_eq!(map.insert(&long).len(), 63);
+        let suffixed = map.insert(&format!("{long}y")).to_owned();
+        assert_eq!(suffixed.len(), 63);
+        assert!(suffixed.ends_with("x_2"));
+    }
+}
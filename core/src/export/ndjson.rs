@@ -0,0 +1,445 @@
+//! Exports ULOG messages as JSON Lines (NDJSON), one JSON object per message.
+//!
+//! Each object has a `type` key identifying the message kind, e.g.
+//!
+//! ```text
+//! {"type":"data","topic":"vehicle_local_position","multi_id":0,"timestamp":123456,"data":{"x":1.5,"q":[1,0,0,0]}}
+//! {"type":"logging","level":"INFO","tag":null,"timestamp":123460,"message":"Takeoff detected"}
+//! ```
+//!
+//! Char arrays are written as strings, nested formats as objects and, by default, non finite floats as `null`.
+//! Padding fields are omitted.  Definitions section messages which only describe the layout of the log,
+//! such as formats and subscriptions, are not exported.
+
+use std::io::Write;
+
+use crate::bitfield::BitFields;
+use crate::errors::ULogError;
+use crate::export::{
+    json_string, value_has_invalid_float, FloatFormat, InvalidFloatPolicy, Provenance,
+};
+use crate::model::inst::{self, FieldValue, ParameterValue};
+use crate::model::msg::UlogMessage;
+use crate::model::CCharSlice;
+
+/// Converts a message to a single line JSON object, or returns `None` if the message is not exported.
+pub fn to_json(message: &UlogMessage) -> Option<String> {
+    NdjsonExporter::new().to_json(message)
+}
+
+/// Writes `messages` to `writer`, one line per exported message.  Returns the number of lines written.
+pub fn export<I, W>(messages: I, writer: &mut W) -> Result<u64, ULogError>
+where
+    I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+    W: Write,
+{
+    NdjsonExporter::new().export(messages, writer)
+}
+
+/// A configurable NDJSON exporter.  See `export()`.
+#[derive(Debug, Clone)]
+pub struct NdjsonExporter {
+    invalid_floats: InvalidFloatPolicy,
+    float_format: FloatFormat,
+    bit_fields: BitFields,
+    provenance: Option<Provenance>,
+}
+
+impl Default for NdjsonExporter {
+    fn default() -> Self {
+        NdjsonExporter {
+            invalid_floats: InvalidFloatPolicy::AsNull,
+            float_format: FloatFormat::default(),
+            bit_fields: BitFields::default(),
+            provenance: None,
+        }
+    }
+}
+
+impl NdjsonExporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How NaN and infinite floats are written.  Defaults to `InvalidFloatPolicy::AsNull`.
+    #[must_use]
+    pub fn invalid_floats(mut self, policy: InvalidFloatPolicy) -> Self {
+        self.invalid_floats = policy;
+        self
+    }
+
+    /// How finite floats are written.  Defaults to `FloatFormat::Shortest`.
+    #[must_use]
+    pub fn float_format(mut self, format: FloatFormat) -> Self {
+        self.float_format = format;
+        self
+    }
+
+    /// Adds the names of the flags set in the bit fields of a data message, as a `flags` object with an array
+    /// per column, e.g. `"flags":{"failsafe_flags":["gps_loss"]}`.
+    #[must_use]
+    pub fn bit_fields(mut self, bit_fields: BitFields) -> Self {
+        self.bit_fields = bit_fields;
+        self
+    }
+
+    /// Adds the `Provenance::COLUMNS` as keys of every object, e.g. `"file_name":"flight_1.ulg"`, with `null`
+    /// for values which are `None`.
+    #[must_use]
+    pub fn provenance(mut self, provenance: Provenance) -> Self {
+        self.provenance = Some(provenance);
+        self
+    }
+
+    /// Converts a message to a single line JSON object, or returns `None` if the message is not exported.
+    pub fn to_json(&self, message: &UlogMessage) -> Option<String> {
+        if self.invalid_floats == InvalidFloatPolicy::Drop && message_has_invalid_float(message) {
+            return None;
+        }
+        let mut json = message_json(message, self.invalid_floats, self.float_format)?;
+
+        if let UlogMessage::LoggedData(data) = message {
+            let flags: Vec<String> = self
+                .bit_fields
+                .columns(&data.data)
+                .filter_map(|column| {
+                    let names = self.bit_fields.active(&data.data, column)?;
+                    let names: Vec<String> = names.into_iter().map(json_string).collect();
+                    Some(format!("{}:[{}]", json_string(column), names.join(",")))
+                })
+                .collect();
+            if !flags.is_empty() {
+                json.pop();
+                json.push_str(&format!(",\"flags\":{{{}}}}}", flags.join(",")));
+            }
+        }
+
+        if let Some(provenance) = &self.provenance {
+            json.pop();
+            for (column, value) in provenance.columns() {
+                let value = value.map_or_else(|| "null".to_owned(), json_string);
+                json.push_str(&format!(",\"{column}\":{value}"));
+            }
+            json.push('}');
+        }
+
+        Some(json)
+    }
+
+    /// Writes `messages` to `writer`, one line per exported message.  Returns the number of lines written.
+    pub fn export<I, W>(&self, messages: I, writer: &mut W) -> Result<u64, ULogError>
+    where
+        I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+        W: Write,
+    {
+        let mut lines = 0;
+
+        for message in messages {
+            if let Some(line) = self.to_json(&message?) {
+                writer.write_all(line.as_bytes())?;
+                writer.write_all(b"\n")?;
+                lines += 1;
+            }
+        }
+
+        writer.flush()?;
+        Ok(lines)
+    }
+}
+
+fn message_has_invalid_float(message: &UlogMessage) -> bool {
+    match message {
+        UlogMessage::LoggedData(data) => crate::export::has_invalid_float(&data.data),
+        UlogMessage::Info(info) => value_has_invalid_float(&info.value),
+        UlogMessage::MultiInfo(info) => value_has_invalid_float(&info.value),
+        UlogMessage::Parameter(param) => parameter_is_invalid(&param.value),
+        UlogMessage::DefaultParameter(param) => parameter_is_invalid(&param.value),
+        _ => false,
+    }
+}
+
+fn parameter_is_invalid(value: &ParameterValue) -> bool {
+    matches!(value, ParameterValue::FLOAT(v) if !v.is_finite())
+}
+
+fn message_json(
+    message: &UlogMessage,
+    policy: InvalidFloatPolicy,
+    float_format: FloatFormat,
+) -> Option<String> {
+    Some(match message {
+        UlogMessage::Header(header) => format!(
+            "{{\"type\":\"header\",\"version\":{},\"timestamp\":{}}}",
+            header.version, header.timestamp
+        ),
+        UlogMessage::LoggedData(data) => format!(
+            "{{\"type\":\"data\",\"topic\":{},\"multi_id\":{},\"timestamp\":{},\"data\":{}}}",
+            json_string(&data.data.name),
+            data.data.multi_id_index.unwrap_or(0),
+            data.timestamp,
+            format_json(&data.data, policy, float_format)
+        ),
+        UlogMessage::Info(info) => format!(
+            "{{\"type\":\"info\",\"key\":{},\"value\":{}}}",
+            json_string(&info.key),
+            value_json(&info.value, policy, float_format)
+        ),
+        UlogMessage::MultiInfo(info) => format!(
+            "{{\"type\":\"multi_info\",\"key\":{},\"is_continued\":{},\"value\":{}}}",
+            json_string(&info.key),
+            info.is_continued,
+            value_json(&info.value, policy, float_format)
+        ),
+        UlogMessage::Parameter(param) => format!(
+            "{{\"type\":\"parameter\",\"key\":{},\"value\":{}}}",
+            json_string(&param.key),
+            parameter_json(&param.value, policy, float_format)
+        ),
+        UlogMessage::DefaultParameter(param) => format!(
+            "{{\"type\":\"default_parameter\",\"key\":{},\"default_types\":{},\"value\":{}}}",
+            json_string(&param.key),
+            param.default_types,
+            parameter_json(&param.value, policy, float_format)
+        ),
+        UlogMessage::LoggedString(log) | UlogMessage::TaggedLoggedString(log) => format!(
+            "{{\"type\":\"logging\",\"level\":\"{}\",\"tag\":{},\"timestamp\":{},\"message\":{}}}",
+            log.level,
+            log.tag
+                .map_or_else(|| "null".to_owned(), |tag| tag.to_string()),
+            log.timestamp,
+            json_string(&log.msg)
+        ),
+        UlogMessage::DropoutMark(dropout) => format!(
+            "{{\"type\":\"dropout\",\"duration_ms\":{}}}",
+            dropout.duration
+        ),
+        UlogMessage::UnknownTyped(unknown) => format!(
+            "{{\"type\":\"unknown\",\"msg_type\":{},\"size\":{}}}",
+            unknown.msg_type,
+            unknown.payload.len()
+        ),
+        UlogMessage::Custom(custom) => format!(
+            "{{\"type\":\"custom\",\"msg_type\":{},\"size\":{}}}",
+            custom.msg_type,
+            custom.payload.len()
+        ),
+        UlogMessage::FlagBits(_)
+        | UlogMessage::FormatDefinition(_)
+        | UlogMessage::AddSubscription(_)
+        | UlogMessage::Unhandled { .. }
+        | UlogMessage::Ignored { .. } => return None,
+    })
+}
+
+fn format_json(
+    format: &inst::Format,
+    policy: InvalidFloatPolicy,
+    float_format: FloatFormat,
+) -> String {
+    let fields: Vec<String> = format
+        .fields
+        .iter()
+        .filter(|field| !field.name.starts_with("_padding"))
+        .map(|field| {
+            format!(
+                "{}:{}",
+                json_string(&field.name),
+                value_json(&field.value, policy, float_format)
+            )
+        })
+        .collect();
+
+    format!("{{{}}}", fields.join(","))
+}
+
+fn value_json(value: &FieldValue, policy: InvalidFloatPolicy, float_format: FloatFormat) -> String {
+    match value {
+        FieldValue::ScalarU8(v) => v.to_string(),
+        FieldValue::ScalarU16(v) => v.to_string(),
+        FieldValue::ScalarU32(v) => v.to_string(),
+        FieldValue::ScalarU64(v) => v.to_string(),
+        FieldValue::ScalarI8(v) => v.to_string(),
+        FieldValue::ScalarI16(v) => v.to_string(),
+        FieldValue::ScalarI32(v) => v.to_string(),
+        FieldValue::ScalarI64(v) => v.to_string(),
+        FieldValue::ScalarF32(v) => float_json(f64::from(*v), float_format.format_f32(*v), policy),
+        FieldValue::ScalarF64(v) => float_json(*v, float_format.format_f64(*v), policy),
+        FieldValue::ScalarBool(v) => v.to_string(),
+        FieldValue::ScalarChar(v) => json_string(&[*v].to_string_lossy()),
+        FieldValue::ScalarOther(nested) => format_json(nested, policy, float_format),
+        FieldValue::ArrayChar(v) => json_string(&v.to_string_lossy_trimmed()),
+        FieldValue::ArrayOther(v) => {
+            let items: Vec<String> = v
+                .iter()
+                .map(|nested| format_json(nested, policy, float_format))
+                .collect();
+            format!("[{}]", items.join(","))
+        }
+        array => {
+            let items: Vec<String> = array
+                .to_scalars()
+                .unwrap_or_default()
+                .iter()
+                .map(|value| value_json(value, policy, float_format))
+                .collect();
+            format!("[{}]", items.join(","))
+        }
+    }
+}
+
+fn float_json(v: f64, formatted: String, policy: InvalidFloatPolicy) -> String {
+    if v.is_finite() {
+        return formatted;
+    }
+
+    match policy {
+        InvalidFloatPolicy::AsNull | InvalidFloatPolicy::Drop => "null".to_owned(),
+        InvalidFloatPolicy::AsNaNString => {
+            let formatted = crate::export::format_scalar(&FieldValue::ScalarF64(v));
+            json_string(&formatted.unwrap_or_default())
+        }
+        InvalidFloatPolicy::ClampToZero => "0".to_owned(),
+    }
+}
+
+fn parameter_json(
+    value: &ParameterValue,
+    policy: InvalidFloatPolicy,
+    float_format: FloatFormat,
+) -> String {
+    match value {
+        ParameterValue::INT32(v) => v.to_string(),
+        ParameterValue::FLOAT(v) => float_json(f64::from(*v), float_format.format_f32(*v), policy),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::model::CChar;
+
+    #[test]
+    fn test_export() -> Result<(), ULogError> {
+        let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+        let parser = ULogParserBuilder::new(input.as_slice())
+            .include_header(true)
+            .build()?;
+
+        let mut output = Vec::new();
+        let lines = export(parser, &mut output)?;
+        let output = String::from_utf8(output)?;
+
+        assert_eq!(output.lines().count() as u64, lines);
+
+        for line in output.lines() {
+            let value: serde_json::Value =
+                serde_json::from_str(line).unwrap_or_else(|e| panic!("Invalid JSON {line}: {e}"));
+            assert!(value["type"].is_string());
+        }
+
+        let first: serde_json::Value =
+            serde_json::from_str(output.lines().next().unwrap()).unwrap();
+        assert_eq!(first["type"], "header");
+
+        let data = output
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .find(|value| value["type"] == "data")
+            .expect("No data messages");
+        assert!(data["topic"].is_string());
+        assert!(data["timestamp"].is_u64());
+        assert!(data["data"].is_object());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_provenance() -> Result<(), ULogError> {
+        let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+        let parser = ULogParserBuilder::new(input.as_slice()).build()?;
+        let provenance = Provenance {
+            file_name: Some("sample_log_small.ulg".to_owned()),
+            file_hash: None,
+            vehicle_uuid: Some("0001".to_owned()),
+        };
+
+        let mut output = Vec::new();
+        NdjsonExporter::new()
+            .provenance(provenance)
+            .export(parser, &mut output)?;
+        for line in String::from_utf8(output)?.lines() {
+            let value: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert_eq!(value["file_name"], "sample_log_small.ulg", "{line}");
+            assert!(value["file_hash"].is_null());
+            assert_eq!(value["vehicle_uuid"], "0001");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_value_json() {
+        let policy = InvalidFloatPolicy::AsNull;
+        let json = |value: &FieldValue| value_json(value, policy, FloatFormat::Shortest);
+        assert_eq!(json(&FieldValue::ScalarF32(f32::NAN)), "null");
+        assert_eq!(json(&FieldValue::ArrayU8(vec![1, 2])), "[1,2]");
+        assert_eq!(
+            json(&FieldValue::ArrayChar(vec![
+                CChar(b'h'),
+                CChar(b'i'),
+                CChar(0)
+            ])),
+            "\"hi\""
+        );
+
+        let array = FieldValue::ArrayF64(vec![1.5, f64::NEG_INFINITY]);
+        assert_eq!(
+            value_json(
+                &array,
+                InvalidFloatPolicy::AsNaNString,
+                FloatFormat::Shortest
+            ),
+            "[1.5,\"-inf\"]"
+        );
+        assert_eq!(
+            value_json(
+                &array,
+                InvalidFloatPolicy::ClampToZero,
+                FloatFormat::Shortest
+            ),
+            "[1.5,0]"
+        );
+        assert_eq!(
+            value_json(&array, InvalidFloatPolicy::AsNull, FloatFormat::Fixed(3)),
+            "[1.500,null]"
+        );
+        assert!(value_has_invalid_float(&array));
+
+        // Floats round trip to the same bits.
+        for v in [0.1f32, 1e-7, 3.4028235e38, -0.0, 16777217.0] {
+            let formatted = json(&FieldValue::ScalarF32(v));
+            assert_eq!(formatted.parse::<f32>().unwrap().to_bits(), v.to_bits());
+        }
+        assert_eq!(json(&FieldValue::ScalarF64(2.0)), "2");
+    }
+
+    #[test]
+    fn test_drop_invalid_floats() {
+        let parameter = |value| {
+            UlogMessage::Parameter(crate::model::msg::Parameter {
+                key: "MPC_XY_VEL_MAX".to_owned(),
+                r#type: crate::model::def::TypeExpr {
+                    base_type: crate::model::def::BaseType::FLOAT,
+                    array_size: None,
+                },
+                value: ParameterValue::FLOAT(value),
+            })
+        };
+        let exporter = NdjsonExporter::new().invalid_floats(InvalidFloatPolicy::Drop);
+        assert!(exporter.to_json(&parameter(12.0)).is_some());
+        assert!(exporter.to_json(&parameter(f32::NAN)).is_none());
+        assert!(to_json(&parameter(f32::NAN))
+            .unwrap()
+            .ends_with("\"value\":null}"));
+    }
+}
@@ -0,0 +1,391 @@
+//! Describes the formats of a log as protobuf messages, and encodes `LoggedData` messages in the protobuf wire
+//! format, so that services can consume them as strongly typed messages, e.g. over gRPC.
+//!
+//! `descriptor_for()` returns a serialized `google.protobuf.FileDescriptorSet`, which tools such as `protoc`,
+//! `buf` and the dynamic message libraries of most languages load.  The message of a format is in the `px4`
+//! package and named after the format.  Its field 1 is the `timestamp` (`uint64`), field 2 the `multi_id`
+//! (`uint32`), and the fields of the format, other than padding, follow from field 3 in order.  Unsigned types
+//! are `uint32` or `uint64`, signed types `sint32` or `sint64`, arrays are repeated fields, char arrays strings,
+//! and nested formats messages, numbered from field 1.
+//!
+//! ```rust,no_run
+//! # use yule_log::builder::ULogParserBuilder;
+//! # use yule_log::model::msg::UlogMessage;
+//! use yule_log::export::proto;
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! # let parser = ULogParserBuilder::new(std::fs::File::open("flight.ulg")?).build()?;
+//! for message in parser {
+//!     if let UlogMessage::LoggedData(data) = message? {
+//!         let descriptor = proto::data_descriptor(&data.data)?;
+//!         let mut payload = Vec::new();
+//!         proto::encode(&data, &mut payload);
+//!         // Send the payload to a service which knows the descriptor.
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+
+use crate::errors::ULogError;
+use crate::export::is_exported;
+use crate::model::def::{self, BaseType};
+use crate::model::inst::{self, FieldValue};
+use crate::model::msg::LoggedData;
+use crate::model::{CCharSlice, Shared};
+
+/// The package of the generated messages.
+pub const PACKAGE: &str = "px4";
+
+// The numbers of the `FieldDescriptorProto` types and labels used.
+const TYPE_DOUBLE: u64 = 1;
+const TYPE_FLOAT: u64 = 2;
+const TYPE_UINT64: u64 = 4;
+const TYPE_BOOL: u64 = 8;
+const TYPE_STRING: u64 = 9;
+const TYPE_MESSAGE: u64 = 11;
+const TYPE_UINT32: u64 = 13;
+const TYPE_SINT32: u64 = 17;
+const TYPE_SINT64: u64 = 18;
+const LABEL_OPTIONAL: u64 = 1;
+const LABEL_REPEATED: u64 = 3;
+
+// Wire types.
+const VARINT: u64 = 0;
+const FIXED64: u64 = 1;
+const LENGTH_DELIMITED: u64 = 2;
+const FIXED32: u64 = 5;
+
+/// Returns a serialized `FileDescriptorSet` with the message of `format`, and of the formats nested in it, which
+/// are resolved using `formats`.
+pub fn descriptor_for(
+    format: &def::Format,
+    formats: &HashMap<Shared<str>, Shared<def::Format>>,
+) -> Result<Vec<u8>, ULogError> {
+    let mut messages = Vec::new();
+    let mut described = HashSet::new();
+    describe(format, true, formats, &mut described, &mut messages)?;
+
+    let mut file = Vec::new();
+    write_string(1, &format!("{}.proto", format.name), &mut file);
+    write_string(2, PACKAGE, &mut file);
+    for message in &messages {
+        write_bytes(4, message, &mut file);
+    }
+    write_string(12, "proto3", &mut file);
+
+    let mut set = Vec::new();
+    write_bytes(1, &file, &mut set);
+    Ok(set)
+}
+
+/// Returns the descriptor of messages like `data`, resolving nested formats from the message.
+pub fn data_descriptor(data: &inst::Format) -> Result<Vec<u8>, ULogError> {
+    let mut formats = HashMap::new();
+    collect_formats(data, &mut formats);
+    descriptor_for(&data.def_format, &formats)
+}
+
+/// Appends the protobuf encoding of `data` to `out`.
+pub fn encode(data: &LoggedData, out: &mut Vec<u8>) {
+    write_tag(1, VARINT, out);
+    write_varint(data.timestamp, out);
+    write_tag(2, VARINT, out);
+    write_varint(u64::from(data.data.multi_id_index.unwrap_or(0)), out);
+    encode_format(&data.data, true, out);
+}
+
+fn collect_formats(format: &inst::Format, formats: &mut HashMap<Shared<str>, Shared<def::Format>>) {
+    for field in &format.fields {
+        let nested = match &field.value {
+            FieldValue::ScalarOther(nested) => Some(&**nested),
+            FieldValue::ArrayOther(nested) => nested.first(),
+            _ => None,
+        };
+        if let Some(nested) = nested {
+            formats.insert(nested.def_format.name.clone(), nested.def_format.clone());
+            collect_formats(nested, formats);
+        }
+    }
+}
+
+/// Appends the `DescriptorProto` of `format`, and of the formats nested in it which aren't yet described, to
+/// `messages`.
+fn describe(
+    format: &def::Format,
+    top_level: bool,
+    formats: &HashMap<Shared<str>, Shared<def::Format>>,
+    described: &mut HashSet<String>,
+    messages: &mut Vec<Vec<u8>>,
+) -> Result<(), ULogError> {
+    described.insert(format.name.to_string());
+
+    let mut message = Vec::new();
+    write_string(1, &format.name, &mut message);
+    let mut number = 1;
+    if top_level {
+        write_bytes(
+            2,
+            &field_descriptor("timestamp", 1, TYPE_UINT64, false, None),
+            &mut message,
+        );
+        write_bytes(
+            2,
+            &field_descriptor("multi_id", 2, TYPE_UINT32, false, None),
+            &mut message,
+        );
+        number = 3;
+    }
+
+    for field in format
+        .fields
+        .iter()
+        .filter(|f| !(f.is_padding() || top_level && &*f.name == "timestamp"))
+    {
+        let repeated = field.r#type.array_size.is_some();
+        let descriptor = match &field.r#type.base_type {
+            BaseType::CHAR => field_descriptor(&field.name, number, TYPE_STRING, false, None),
+            BaseType::OTHER(name) => {
+                if !described.contains(name) {
+                    let nested = formats
+                        .get(name.as_str())
+                        .ok_or_else(|| ULogError::UndefinedFormat(name.clone()))?;
+                    describe(nested, false, formats, described, messages)?;
+                }
+                let type_name = format!(".{PACKAGE}.{name}");
+                field_descriptor(
+                    &field.name,
+                    number,
+                    TYPE_MESSAGE,
+                    repeated,
+                    Some(&type_name),
+                )
+            }
+            base_type => {
+                field_descriptor(&field.name, number, field_type(base_type), repeated, None)
+            }
+        };
+        write_bytes(2, &descriptor, &mut message);
+        number += 1;
+    }
+
+    messages.push(message);
+    Ok(())
+}
+
+fn field_descriptor(
+    name: &str,
+    number: u64,
+    r#type: u64,
+    repeated: bool,
+    type_name: Option<&str>,
+) -> Vec<u8> {
+    let mut field = Vec::new();
+    write_string(1, name, &mut field);
+    write_tag(3, VARINT, &mut field);
+    write_varint(number, &mut field);
+    write_tag(4, VARINT, &mut field);
+    write_varint(
+        if repeated {
+            LABEL_REPEATED
+        } else {
+            LABEL_OPTIONAL
+        },
+        &mut field,
+    );
+    write_tag(5, VARINT, &mut field);
+    write_varint(r#type, &mut field);
+    if let Some(type_name) = type_name {
+        write_string(6, type_name, &mut field);
+    }
+    field
+}
+
+fn field_type(base_type: &BaseType) -> u64 {
+    match base_type {
+        BaseType::UINT8 | BaseType::UINT16 | BaseType::UINT32 => TYPE_UINT32,
+        BaseType::UINT64 => TYPE_UINT64,
+        BaseType::INT8 | BaseType::INT16 | BaseType::INT32 => TYPE_SINT32,
+        BaseType::INT64 => TYPE_SINT64,
+        BaseType::FLOAT => TYPE_FLOAT,
+        BaseType::DOUBLE => TYPE_DOUBLE,
+        BaseType::BOOL => TYPE_BOOL,
+        BaseType::CHAR => TYPE_STRING,
+        BaseType::OTHER(_) => TYPE_MESSAGE,
+    }
+}
+
+fn encode_format(format: &inst::Format, top_level: bool, out: &mut Vec<u8>) {
+    let first = if top_level { 3 } else { 1 };
+    let fields = format.fields.iter().filter(|f| is_exported(f, top_level));
+    for (number, field) in (first..).zip(fields) {
+        encode_field(number, &field.value, out);
+    }
+}
+
+fn encode_field(number: u64, value: &FieldValue, out: &mut Vec<u8>) {
+    match value {
+        FieldValue::ArrayChar(v) => write_string(number, &v.to_string_lossy_trimmed(), out),
+        FieldValue::ScalarChar(v) => write_string(number, &[*v].to_string_lossy(), out),
+        FieldValue::ScalarOther(nested) => {
+            let mut message = Vec::new();
+            encode_format(nested, false, &mut message);
+            write_bytes(number, &message, out);
+        }
+        FieldValue::ArrayOther(v) => {
+            let mut message = Vec::new();
+            for nested in v {
+                message.clear();
+                encode_format(nested, false, &mut message);
+                write_bytes(number, &message, out);
+            }
+        }
+        value => match value.to_scalars() {
+            // Repeated scalars are packed.
+            Some(scalars) => {
+                let mut packed = Vec::new();
+                for scalar in &scalars {
+                    encode_scalar(scalar, &mut packed);
+                }
+                write_bytes(number, &packed, out);
+            }
+            None => {
+                write_tag(number, wire_type(value), out);
+                encode_scalar(value, out);
+            }
+        },
+    }
+}
+
+fn wire_type(value: &FieldValue) -> u64 {
+    match value {
+        FieldValue::ScalarF32(_) => FIXED32,
+        FieldValue::ScalarF64(_) => FIXED64,
+        _ => VARINT,
+    }
+}
+
+/// Writes a scalar without its tag.
+fn encode_scalar(value: &FieldValue, out: &mut Vec<u8>) {
+    let zigzag = |v: i64| ((v << 1) ^ (v >> 63)) as u64;
+    match *value {
+        FieldValue::ScalarU8(v) => write_varint(u64::from(v), out),
+        FieldValue::ScalarU16(v) => write_varint(u64::from(v), out),
+        FieldValue::ScalarU32(v) => write_varint(u64::from(v), out),
+        FieldValue::ScalarU64(v) => write_varint(v, out),
+        FieldValue::ScalarI8(v) => write_varint(zigzag(i64::from(v)), out),
+        FieldValue::ScalarI16(v) => write_varint(zigzag(i64::from(v)), out),
+        FieldValue::ScalarI32(v) => write_varint(zigzag(i64::from(v)), out),
+        FieldValue::ScalarI64(v) => write_varint(zigzag(v), out),
+        FieldValue::ScalarF32(v) => out.extend_from_slice(&v.to_le_bytes()),
+        FieldValue::ScalarF64(v) => out.extend_from_slice(&v.to_le_bytes()),
+        FieldValue::ScalarBool(v) => write_varint(u64::from(v), out),
+        _ => unreachable!("only scalars are written without a tag"),
+    }
+}
+
+fn write_varint(mut n: u64, out: &mut Vec<u8>) {
+    while n >= 0x80 {
+        out.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn write_tag(number: u64, wire_type: u64, out: &mut Vec<u8>) {
+    write_varint((number << 3) | wire_type, out);
+}
+
+fn write_bytes(number: u64, bytes: &[u8], out: &mut Vec<u8>) {
+    write_tag(number, LENGTH_DELIMITED, out);
+    write_varint(bytes.len() as u64, out);
+    out.extend_from_slice(bytes);
+}
+
+fn write_string(number: u64, s: &str, out: &mut Vec<u8>) {
+    write_bytes(number, s.as_bytes(), out);
+}
+
+#[cfg(test)]
+mod tests {
+    use prost::Message;
+    use prost_types::field_descriptor_proto::{Label, Type};
+    use prost_types::FileDescriptorSet;
+
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::formats::parse_format;
+    use crate::message_buf::MessageBuf;
+    use crate::model::msg::UlogMessage;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    fn format(definition: &str) -> def::Format {
+        parse_format(MessageBuf::from_vec(definition.as_bytes().to_vec())).unwrap()
+    }
+
+    #[test]
+    fn test_descriptor_for() -> Result<(), ULogError> {
+        let point = format("point:float x;int16_t y;uint8_t[2] _padding0;");
+        let top = format("path:uint64_t timestamp;point[2] points;point origin;char[8] name;");
+        let mut formats = HashMap::new();
+        formats.insert(point.name.clone(), Shared::new(point));
+
+        let set = FileDescriptorSet::decode(descriptor_for(&top, &formats)?.as_slice()).unwrap();
+        let file = &set.file[0];
+        assert_eq!(
+            (file.name(), file.package(), file.syntax()),
+            ("path.proto", "px4", "proto3")
+        );
+
+        let names: Vec<&str> = file.message_type.iter().map(|m| m.name()).collect();
+        assert_eq!(names, ["point", "path"]);
+        let fields = &file.message_type[1].field;
+        let summary: Vec<(&str, i32, Type, Label)> = fields
+            .iter()
+            .map(|f| (f.name(), f.number(), f.r#type(), f.label()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("timestamp", 1, Type::Uint64, Label::Optional),
+                ("multi_id", 2, Type::Uint32, Label::Optional),
+                ("points", 3, Type::Message, Label::Repeated),
+                ("origin", 4, Type::Message, Label::Optional),
+                ("name", 5, Type::String, Label::Optional),
+            ]
+        );
+        assert_eq!(fields[3].type_name(), ".px4.point");
+        assert_eq!(file.message_type[0].field[1].r#type(), Type::Sint32);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode() -> Result<(), ULogError> {
+        let bytes = LogBuilder::new()
+            .topic(
+                Topic::new("a")
+                    .field("x", BaseType::INT16, Generator::Constant(-2.0))
+                    .array_field("v", BaseType::UINT8, 2, Generator::Constant(5.0)),
+            )
+            .build()?;
+        let data = ULogParserBuilder::new(bytes.as_slice())
+            .build()?
+            .find_map(|m| match m {
+                Ok(UlogMessage::LoggedData(data)) => Some(data),
+                _ => None,
+            })
+            .unwrap();
+
+        let mut payload = Vec::new();
+        encode(&data, &mut payload);
+        assert_eq!(
+            payload,
+            [0x08, 0xc0, 0x84, 0x3d, 0x10, 0x00, 0x18, 0x03, 0x22, 0x02, 0x05, 0x05]
+        );
+
+        Ok(())
+    }
+}
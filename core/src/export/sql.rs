@@ -0,0 +1,1175 @@
+//! Exports the `LoggedData` messages of ULOG files as SQL statements, one table per topic.
+//!
+//! Each table has a `timestamp` and a `multi_id` column, followed by the columns of the topic, named as by the
+//! CSV exporter and converted to identifiers with `identifiers::sanitize_identifier()`.  The statements can be
+//! piped to a database client, e.g. `psql`.
+//!
+//! An `SqlExporter` remembers the tables it has created, so that many logs can be exported into the same tables.
+//! When a later log has new fields, e.g. after a firmware update, or a field whose type has grown, the exporter
+//! alters the table first, according to its `SchemaPolicy`:
+//!
+//! ```rust,no_run
+//! # use std::fs::File;
+//! # use std::io::{BufReader, BufWriter};
+//! # use yule_log::builder::ULogParserBuilder;
+//! # use yule_log::export::identifiers::Dialect;
+//! # use yule_log::export::sql::SqlExporter;
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let mut exporter = SqlExporter::new(Dialect::Postgres);
+//! let mut output = BufWriter::new(File::create("fleet.sql")?);
+//!
+//! for path in ["flight_1.ulg", "flight_2.ulg"] {
+//!     let parser = ULogParserBuilder::new(BufReader::new(File::open(path)?)).build()?;
+//!     exporter.export(parser, &mut output)?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+
+use crate::errors::{CastError, ULogError};
+use crate::export::identifiers::{Dialect, IdentifierMap};
+use crate::export::{
+    column_names, for_each_scalar, is_invalid_float, layout_fingerprint, InvalidFloatPolicy,
+    LossyCasts, Provenance,
+};
+use crate::model::def::BaseType;
+use crate::model::inst::{self, FieldValue};
+use crate::model::msg::UlogMessage;
+
+/// What the exporter does when a topic's fields differ from the columns of its table.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaPolicy {
+    /// New fields are added as columns with `ALTER TABLE`, and columns are widened to hold larger types, e.g.
+    /// from `INTEGER` to `BIGINT`, or from an integer type to `DOUBLE PRECISION`.
+    #[default]
+    Evolve,
+
+    /// The export fails with `ULogError::ExportError` if a table would have to be altered.
+    Strict,
+}
+
+/// The primary key of the tables created by the exporter, which decides which rows are duplicates.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PrimaryKey {
+    /// No primary key.  Exporting a log twice inserts its rows twice.
+    #[default]
+    None,
+
+    /// The `timestamp` and `multi_id` columns.  As each topic has its own table, this identifies a message of
+    /// a topic instance, so overlapping logs of one vehicle have duplicate rows.
+    TimeTopic,
+
+    /// A `log_file` column holding the name passed to `SqlExporter::export_log()`, or the file name of the
+    /// provenance passed to `SqlExporter::export_with_provenance()`, and the `timestamp` and `multi_id` columns,
+    /// so that the logs of different vehicles can share tables.
+    TimeFile,
+
+    /// An `id` column numbered by the database.  Rows never conflict.
+    Surrogate,
+}
+
+impl PrimaryKey {
+    /// The names of the columns which make up the key, other than a surrogate key.
+    fn columns(self) -> &'static [&'static str] {
+        match self {
+            PrimaryKey::TimeTopic => &["timestamp", "multi_id"],
+            PrimaryKey::TimeFile => &[LOG_COLUMN, "timestamp", "multi_id"],
+            PrimaryKey::None | PrimaryKey::Surrogate => &[],
+        }
+    }
+}
+
+/// What an insert does when a row with the same primary key exists.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// The insert fails, as usual in SQL.
+    #[default]
+    Error,
+
+    /// The row is skipped, with `ON CONFLICT DO NOTHING`, or `INSERT IGNORE` for MySQL, which also turns some
+    /// other errors into warnings.
+    Ignore,
+
+    /// The existing row is updated, with `ON CONFLICT ... DO UPDATE`, or `ON DUPLICATE KEY UPDATE` for MySQL.
+    Upsert,
+}
+
+/// The SQL type of a column.  Unsigned types are stored in the next larger signed type, except `uint64_t`, which is
+/// stored in `BIGINT`, as it is the largest integer type of all the dialects.  Values above `i64::MAX` are written as
+/// the negative numbers they wrap to, which the databases accept, and counted in the `lossy_casts()` of the exporter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SqlType {
+    SmallInt,
+    Integer,
+    BigInt,
+    Real,
+    Double,
+}
+
+impl SqlType {
+    fn from_base_type(base_type: &BaseType) -> Self {
+        match base_type {
+            BaseType::UINT8
+            | BaseType::INT8
+            | BaseType::INT16
+            | BaseType::BOOL
+            | BaseType::CHAR => SqlType::SmallInt,
+            BaseType::UINT16 | BaseType::INT32 => SqlType::Integer,
+            BaseType::UINT32 | BaseType::INT64 | BaseType::UINT64 => SqlType::BigInt,
+            BaseType::FLOAT => SqlType::Real,
+            // Nested formats are flattened into scalar columns, so this is only reached for doubles.
+            BaseType::DOUBLE | BaseType::OTHER(_) => SqlType::Double,
+        }
+    }
+
+    /// Returns the smallest type which holds the values of both types.
+    pub fn widen(self, other: SqlType) -> SqlType {
+        let is_float = |t: SqlType| t >= SqlType::Real;
+        match (is_float(self), is_float(other)) {
+            (false, false) | (true, true) => self.max(other),
+            // Integers are widened to a double, which holds all but the largest 64 bit integers exactly.
+            _ => SqlType::Double,
+        }
+    }
+
+    pub fn name(self, dialect: Dialect) -> &'static str {
+        match (dialect, self) {
+            (Dialect::Sqlite, SqlType::SmallInt | SqlType::Integer | SqlType::BigInt) => "INTEGER",
+            (Dialect::Sqlite, SqlType::Real | SqlType::Double) => "REAL",
+            (Dialect::MySql, SqlType::Integer) => "INT",
+            (Dialect::MySql, SqlType::Real) => "FLOAT",
+            (Dialect::MySql, SqlType::Double) => "DOUBLE",
+            (_, SqlType::SmallInt) => "SMALLINT",
+            (_, SqlType::Integer) => "INTEGER",
+            (_, SqlType::BigInt) => "BIGINT",
+            (_, SqlType::Real) => "REAL",
+            (_, SqlType::Double) => "DOUBLE PRECISION",
+        }
+    }
+}
+
+/// The columns of a table created by the exporter.
+#[derive(Debug, Clone)]
+struct Table {
+    identifier: String,
+    columns: IdentifierMap,
+    types: HashMap<String, SqlType>,
+    /// Whether the table has the `Provenance::COLUMNS`.
+    provenance: bool,
+    /// The `layout_fingerprint()` of the format the columns were last checked against.
+    layout: Option<u64>,
+}
+
+impl Table {
+    // The identifier of the key column `name`, which the exporter added or is a column of every topic.
+    fn key_column(&self, name: &str) -> &str {
+        self.columns
+            .reserved(name)
+            .or_else(|| self.columns.identifier(name))
+            .expect("the key columns were defined")
+    }
+}
+
+/// An SQL exporter for one or many logs.  See the module documentation.
+#[derive(Debug, Clone)]
+pub struct SqlExporter {
+    dialect: Dialect,
+    schema_policy: SchemaPolicy,
+    primary_key: PrimaryKey,
+    on_conflict: ConflictPolicy,
+    rows_per_insert: usize,
+    rows_per_transaction: Option<usize>,
+    invalid_floats: InvalidFloatPolicy,
+    table_names: IdentifierMap,
+    tables: HashMap<String, Table>,
+    lossy_casts: LossyCasts,
+}
+
+impl SqlExporter {
+    /// Creates an exporter writing statements for `dialect`, which must be one of the SQL dialects.
+    pub fn new(dialect: Dialect) -> Self {
+        SqlExporter {
+            dialect,
+            schema_policy: SchemaPolicy::default(),
+            primary_key: PrimaryKey::default(),
+            on_conflict: ConflictPolicy::default(),
+            rows_per_insert: 1,
+            rows_per_transaction: None,
+            invalid_floats: InvalidFloatPolicy::AsNull,
+            table_names: IdentifierMap::new(dialect),
+            tables: HashMap::new(),
+            lossy_casts: LossyCasts::default(),
+        }
+    }
+
+    /// How the exporter handles fields which don't match the columns of their table.  Defaults to
+    /// `SchemaPolicy::Evolve`.
+    #[must_use]
+    pub fn schema_policy(mut self, policy: SchemaPolicy) -> Self {
+        self.schema_policy = policy;
+        self
+    }
+
+    /// The primary key of the tables the exporter creates.  Defaults to `PrimaryKey::None`.  Tables which
+    /// exist already keep their key.
+    #[must_use]
+    pub fn primary_key(mut self, primary_key: PrimaryKey) -> Self {
+        self.primary_key = primary_key;
+        self
+    }
+
+    /// What inserts do with rows whose primary key exists.  Defaults to `ConflictPolicy::Error`.  The other
+    /// policies need `PrimaryKey::TimeTopic` or `PrimaryKey::TimeFile`, and a dialect other than `Dialect::Sql`.
+    #[must_use]
+    pub fn on_conflict(mut self, policy: ConflictPolicy) -> Self {
+        self.on_conflict = policy;
+        self
+    }
+
+    /// The most rows inserted by each statement, with a multi-row `VALUES` list.  Defaults to 1.  Larger
+    /// statements load much faster, but under `ConflictPolicy::Upsert`, PostgreSQL rejects a statement which
+    /// updates a row twice, e.g. for a log with duplicate messages.
+    #[must_use]
+    pub fn rows_per_insert(mut self, rows: usize) -> Self {
+        self.rows_per_insert = rows.max(1);
+        self
+    }
+
+    /// The number of rows after which the open transaction is committed, or `None`, the default, for no
+    /// explicit transactions, i.e. one transaction per statement in most clients.  A transaction may hold more
+    /// rows if they were inserted by one statement.
+    #[must_use]
+    pub fn rows_per_transaction(mut self, rows: Option<usize>) -> Self {
+        self.rows_per_transaction = rows.map(|rows| rows.max(1));
+        self
+    }
+
+    /// How NaN and infinite floats are written.  Defaults to `InvalidFloatPolicy::AsNull`, which writes `NULL`.
+    /// `InvalidFloatPolicy::AsNaNString` writes `'NaN'`, `'Infinity'` and `'-Infinity'`, and is only supported by
+    /// `Dialect::Postgres`:  SQLite would store them as text in float columns, and MySQL and standard SQL reject them.
+    #[must_use]
+    pub fn invalid_floats(mut self, policy: InvalidFloatPolicy) -> Self {
+        self.invalid_floats = policy;
+        self
+    }
+
+    /// Returns the identifier of the table of `topic`, if it was created.
+    pub fn table(&self, topic: &str) -> Option<&str> {
+        self.tables
+            .get(topic)
+            .map(|table| table.identifier.as_str())
+    }
+
+    /// The values of all exports so far which didn't fit their columns exactly: `u64`s above `i64::MAX`, which
+    /// wrap, and 64 bit integers in columns widened to floats, which the database rounds.
+    pub fn lossy_casts(&self) -> &LossyCasts {
+        &self.lossy_casts
+    }
+
+    /// Writes the statements which create or alter the tables of `messages`, and insert their rows, to `writer`.
+    /// Returns the number of rows inserted.
+    ///
+    /// Fails with `ULogError::InvalidConfiguration` under `PrimaryKey::TimeFile`, which needs `export_log()`.
+    pub fn export<I, W>(&mut self, messages: I, writer: &mut W) -> Result<u64, ULogError>
+    where
+        I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+        W: Write,
+    {
+        self.export_rows(None, None, messages, writer)
+    }
+
+    /// Like `export()`, with `log` as the value of the `log_file` column of `PrimaryKey::TimeFile`, e.g. the
+    /// file name of the log.
+    pub fn export_log<I, W>(
+        &mut self,
+        log: &str,
+        messages: I,
+        writer: &mut W,
+    ) -> Result<u64, ULogError>
+    where
+        I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+        W: Write,
+    {
+        self.export_rows(Some(log), None, messages, writer)
+    }
+
+    /// Like `export()`, with the `Provenance::COLUMNS` holding `provenance` in every row.  The columns are added
+    /// to the tables which lack them, as `TEXT`, or `VARCHAR(255)` for MySQL and standard SQL.  Under
+    /// `PrimaryKey::TimeFile`, the file name of `provenance` is the value of the `log_file` column.
+    pub fn export_with_provenance<I, W>(
+        &mut self,
+        provenance: &Provenance,
+        messages: I,
+        writer: &mut W,
+    ) -> Result<u64, ULogError>
+    where
+        I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+        W: Write,
+    {
+        let log = provenance.file_name.as_deref();
+        self.export_rows(log, Some(provenance), messages, writer)
+    }
+
+    fn export_rows<I, W>(
+        &mut self,
+        log: Option<&str>,
+        provenance: Option<&Provenance>,
+        messages: I,
+        writer: &mut W,
+    ) -> Result<u64, ULogError>
+    where
+        I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+        W: Write,
+    {
+        self.check_configuration(log)?;
+
+        let mut batches = Batches {
+            inserts: BTreeMap::new(),
+            rows_per_insert: self.rows_per_insert,
+            rows_per_transaction: self.rows_per_transaction,
+            begin: match self.dialect {
+                Dialect::MySql | Dialect::Sql => "START TRANSACTION;",
+                _ => "BEGIN;",
+            },
+            transaction: None,
+        };
+        let mut values: Vec<String> = Vec::new();
+        let mut rows = 0;
+        let mut lossy_casts = LossyCasts::default();
+        let provenance_values: Vec<String> = provenance
+            .iter()
+            .flat_map(|provenance| provenance.columns())
+            .map(|(_, value)| value.map_or_else(|| "NULL".to_owned(), string_literal))
+            .collect();
+
+        for message in messages {
+            let UlogMessage::LoggedData(data) = message? else {
+                continue;
+            };
+
+            let stale = batches
+                .inserts
+                .get(&data.msg_id)
+                .is_none_or(|batch| *batch.topic != *data.data.name);
+            if stale {
+                let mut alterations = Vec::new();
+                let insert =
+                    self.prepare_table(&data.data, provenance.is_some(), &mut alterations)?;
+                // Tables are altered outside transactions, as MySQL commits before each ALTER TABLE.
+                if !alterations.is_empty() {
+                    batches.flush(writer)?;
+                    writer.write_all(&alterations)?;
+                }
+                batches.inserts.insert(
+                    data.msg_id,
+                    Batch {
+                        topic: data.data.name.to_string(),
+                        insert,
+                        rows: Vec::new(),
+                    },
+                );
+            }
+            if self.invalid_floats.drops(&data.data) {
+                continue;
+            }
+
+            values.clear();
+            if self.primary_key == PrimaryKey::TimeFile {
+                values.extend(log.map(string_literal));
+            }
+            values.push(data.timestamp.to_string());
+            values.push(data.data.multi_id_index.unwrap_or(0).to_string());
+            let batch = &batches.inserts[&data.msg_id];
+            let mut types = batch.insert.types.iter();
+            for_each_scalar(&data.data, &mut |value| {
+                let sql_type = *types.next().expect("each scalar has a column");
+                if let Some(error) = lossy_cast(value, sql_type) {
+                    lossy_casts.record(&batch.topic, error);
+                }
+                values.extend(self.literal(value, sql_type));
+            });
+            values.extend(provenance_values.iter().cloned());
+
+            batches.push(data.msg_id, format!("({})", values.join(", ")), writer)?;
+            rows += 1;
+        }
+
+        batches.flush(writer)?;
+        writer.flush()?;
+        lossy_casts.warn("The SQL exporter");
+        self.lossy_casts.add(&lossy_casts);
+        Ok(rows)
+    }
+
+    fn check_configuration(&self, log: Option<&str>) -> Result<(), ULogError> {
+        let error = |message: &str| Err(ULogError::InvalidConfiguration(message.to_owned()));
+
+        if self.dialect == Dialect::Arrow {
+            return error("Arrow is not an SQL dialect.");
+        }
+        if self.primary_key == PrimaryKey::TimeFile && log.is_none() {
+            return error(
+                "The TimeFile primary key needs the name of the log, given to export_log() or as the file name of \
+                 the provenance.",
+            );
+        }
+        if self.invalid_floats == InvalidFloatPolicy::AsNaNString && self.dialect != Dialect::Postgres {
+            return error("Only PostgreSQL accepts NaN and infinite floats as strings.");
+        }
+        if self.on_conflict != ConflictPolicy::Error {
+            if matches!(self.primary_key, PrimaryKey::None | PrimaryKey::Surrogate) {
+                return error("Only the TimeTopic and TimeFile primary keys can conflict.");
+            }
+            if self.dialect == Dialect::Sql {
+                return error("Standard SQL has no clause to ignore or update conflicting rows.");
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates or alters the table of `format` as needed, with the provenance columns if `provenance`, and returns the
+    /// start and end of its insert statements.
+    fn prepare_table<W: Write>(
+        &mut self,
+        format: &inst::Format,
+        provenance: bool,
+        writer: &mut W,
+    ) -> Result<Insert, ULogError> {
+        let topic = format.name.to_string();
+        let columns = column_names(format);
+
+        if !self.tables.contains_key(&topic) {
+            let mut table = Table {
+                identifier: self.table_names.insert(&topic).to_owned(),
+                columns: IdentifierMap::new(self.dialect),
+                types: HashMap::new(),
+                provenance,
+                layout: None,
+            };
+            let mut definitions = Vec::new();
+            match self.primary_key {
+                PrimaryKey::Surrogate => {
+                    let identifier = table.columns.insert_reserved(SURROGATE_COLUMN);
+                    definitions.push(format!(
+                        "{identifier} {}",
+                        match self.dialect {
+                            Dialect::MySql => "BIGINT AUTO_INCREMENT PRIMARY KEY",
+                            // An alias of the rowid, which SQLite numbers.
+                            Dialect::Sqlite => "INTEGER PRIMARY KEY",
+                            _ => "BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY",
+                        }
+                    ));
+                }
+                PrimaryKey::TimeFile => {
+                    let identifier = table.columns.insert_reserved(LOG_COLUMN);
+                    definitions.push(format!("{identifier} {} NOT NULL", text_type(self.dialect)));
+                }
+                PrimaryKey::None | PrimaryKey::TimeTopic => {}
+            }
+            for (name, sql_type) in [
+                ("timestamp", SqlType::BigInt),
+                ("multi_id", SqlType::SmallInt),
+            ]
+            .into_iter()
+            .map(|(name, sql_type)| (name.to_owned(), sql_type))
+            .chain(
+                columns
+                    .iter()
+                    .map(|(name, base_type)| (name.clone(), SqlType::from_base_type(base_type))),
+            ) {
+                let identifier = table.columns.insert(&name);
+                definitions.push(format!("{identifier} {}", sql_type.name(self.dialect)));
+                table.types.insert(name, sql_type);
+            }
+            if provenance {
+                for name in Provenance::COLUMNS {
+                    let identifier = table.columns.insert(name);
+                    definitions.push(format!("{identifier} {}", text_type(self.dialect)));
+                }
+            }
+            if !self.primary_key.columns().is_empty() {
+                let keys: Vec<&str> = self
+                    .primary_key
+                    .columns()
+                    .iter()
+                    .map(|name| table.key_column(name))
+                    .collect();
+                definitions.push(format!("PRIMARY KEY ({})", keys.join(", ")));
+            }
+
+            writeln!(
+                writer,
+                "CREATE TABLE IF NOT EXISTS {} ({});",
+                table.identifier,
+                definitions.join(", ")
+            )?;
+            self.tables.insert(topic.clone(), table);
+        }
+
+        let table = self
+            .tables
+            .get_mut(&topic)
+            .expect("the table was just created");
+        // The columns need checking only when the layout differs from that they were last checked against.
+        let layout = layout_fingerprint(format);
+        if table.layout != Some(layout) {
+            for (name, base_type) in &columns {
+                let sql_type = SqlType::from_base_type(base_type);
+                let alteration = match table.types.get(name) {
+                    None => Alteration::Add(sql_type),
+                    Some(&current) if current.widen(sql_type) != current => {
+                        Alteration::Widen(current.widen(sql_type))
+                    }
+                    Some(_) => continue,
+                };
+
+                if self.schema_policy == SchemaPolicy::Strict {
+                    return Err(ULogError::ExportError(format!(
+                        "The {name} column of {topic} {} under the strict schema policy.",
+                        match alteration {
+                            Alteration::Add(_) => "is new",
+                            Alteration::Widen(_) => "has a larger type than its table column",
+                        }
+                    )));
+                }
+
+                let identifier = table.columns.insert(name).to_owned();
+                match alteration {
+                    Alteration::Add(sql_type) => writeln!(
+                        writer,
+                        "ALTER TABLE {} ADD COLUMN {identifier} {};",
+                        table.identifier,
+                        sql_type.name(self.dialect)
+                    )?,
+                    // SQLite columns hold values of any type, so they need no widening.
+                    Alteration::Widen(_) if self.dialect == Dialect::Sqlite => {}
+                    Alteration::Widen(sql_type) => {
+                        let clause = match self.dialect {
+                            Dialect::MySql => "MODIFY COLUMN",
+                            _ => "ALTER COLUMN",
+                        };
+                        let type_keyword = match self.dialect {
+                            Dialect::MySql => "",
+                            Dialect::Postgres => "TYPE ",
+                            _ => "SET DATA TYPE ",
+                        };
+                        writeln!(
+                            writer,
+                            "ALTER TABLE {} {clause} {identifier} {type_keyword}{};",
+                            table.identifier,
+                            sql_type.name(self.dialect)
+                        )?;
+                    }
+                }
+                let (Alteration::Add(sql_type) | Alteration::Widen(sql_type)) = alteration;
+                table.types.insert(name.clone(), sql_type);
+            }
+            table.layout = Some(layout);
+        }
+        if provenance && !table.provenance {
+            if self.schema_policy == SchemaPolicy::Strict {
+                return Err(ULogError::ExportError(format!(
+                    "The provenance columns of {topic} are new under the strict schema policy."
+                )));
+            }
+            for name in Provenance::COLUMNS {
+                let identifier = table.columns.insert(name);
+                writeln!(
+                    writer,
+                    "ALTER TABLE {} ADD COLUMN {identifier} {};",
+                    table.identifier,
+                    text_type(self.dialect)
+                )?;
+            }
+            table.provenance = true;
+        }
+
+        let log_column = (self.primary_key == PrimaryKey::TimeFile).then_some(LOG_COLUMN);
+        let identifier = |name: &str| {
+            table
+                .columns
+                .identifier(name)
+                .expect("all columns have identifiers")
+        };
+        let provenance_columns: &[&str] = if provenance {
+            &Provenance::COLUMNS
+        } else {
+            &[]
+        };
+        let identifiers: Vec<&str> = log_column
+            .map(|name| table.key_column(name))
+            .into_iter()
+            .chain(
+                ["timestamp", "multi_id"]
+                    .into_iter()
+                    .chain(columns.iter().map(|(name, _)| name.as_str()))
+                    .chain(provenance_columns.iter().copied())
+                    .map(identifier),
+            )
+            .collect();
+
+        let keys: Vec<&str> = self
+            .primary_key
+            .columns()
+            .iter()
+            .map(|name| table.key_column(name))
+            .collect();
+        let updates: Vec<String> = columns
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .chain(provenance_columns.iter().copied())
+            .map(identifier)
+            .map(|column| match self.dialect {
+                Dialect::MySql => format!("{column} = VALUES({column})"),
+                _ => format!("{column} = excluded.{column}"),
+            })
+            .collect();
+
+        let ignore = self.on_conflict == ConflictPolicy::Ignore
+            // A row of only key columns has nothing to update.
+            || (self.on_conflict == ConflictPolicy::Upsert && updates.is_empty());
+        let suffix = match (self.dialect, self.on_conflict) {
+            (_, ConflictPolicy::Error) => String::new(),
+            (Dialect::MySql, _) if ignore => String::new(),
+            (Dialect::MySql, _) => format!(" ON DUPLICATE KEY UPDATE {}", updates.join(", ")),
+            _ if ignore => " ON CONFLICT DO NOTHING".to_owned(),
+            _ => format!(
+                " ON CONFLICT ({}) DO UPDATE SET {}",
+                keys.join(", "),
+                updates.join(", ")
+            ),
+        };
+        let verb = if ignore && self.dialect == Dialect::MySql {
+            "INSERT IGNORE INTO"
+        } else {
+            "INSERT INTO"
+        };
+
+        Ok(Insert {
+            prefix: format!(
+                "{verb} {} ({}) VALUES ",
+                table.identifier,
+                identifiers.join(", ")
+            ),
+            suffix,
+            types: columns.iter().map(|(name, _)| table.types[name]).collect(),
+        })
+    }
+
+    /// Creates or alters the table of `format` like `export()`, and returns its identifier and columns, for
+    /// `postgres::PostgresExporter`.
+    #[cfg(feature = "postgres")]
+    pub(crate) fn prepare_copy<W: Write>(
+        &mut self,
+        format: &inst::Format,
+        writer: &mut W,
+    ) -> Result<CopyTarget, ULogError> {
+        self.prepare_table(format, false, writer)?;
+
+        let table = &self.tables[&*format.name];
+        let columns = ["timestamp".to_owned(), "multi_id".to_owned()]
+            .into_iter()
+            .chain(column_names(format).into_iter().map(|(name, _)| name))
+            .map(|name| {
+                let identifier = table
+                    .columns
+                    .identifier(&name)
+                    .expect("all columns have identifiers");
+                (identifier.to_owned(), table.types[&name])
+            })
+            .collect();
+        Ok(CopyTarget {
+            table: table.identifier.clone(),
+            columns,
+        })
+    }
+
+    /// Formats a scalar as an SQL literal for a column of `sql_type`.
+    fn literal(&self, value: &FieldValue, sql_type: SqlType) -> Option<String> {
+        if !is_invalid_float(value) {
+            return match *value {
+                // Wraps above 2^63, as documented for `SqlType`.
+                FieldValue::ScalarU64(v) if sql_type != SqlType::Double => Some((v as i64).to_string()),
+                _ => crate::export::format_scalar(value),
+            };
+        }
+
+        let v = match value {
+            FieldValue::ScalarF32(v) => f64::from(*v),
+            FieldValue::ScalarF64(v) => *v,
+            _ => unreachable!("only floats are invalid"),
+        };
+        Some(
+            match self.invalid_floats {
+                InvalidFloatPolicy::AsNull | InvalidFloatPolicy::Drop => "NULL",
+                InvalidFloatPolicy::ClampToZero => "0",
+                InvalidFloatPolicy::AsNaNString if v.is_nan() => "'NaN'",
+                InvalidFloatPolicy::AsNaNString if v > 0.0 => "'Infinity'",
+                InvalidFloatPolicy::AsNaNString => "'-Infinity'",
+            }
+            .to_owned(),
+        )
+    }
+}
+
+/// Returns why `value` can't be converted exactly to `sql_type`, if it can't.
+pub(crate) fn lossy_cast(value: &FieldValue, sql_type: SqlType) -> Option<CastError> {
+    match sql_type {
+        SqlType::SmallInt | SqlType::Integer | SqlType::BigInt => value.to_i64_checked().err(),
+        SqlType::Real => {
+            let v = value.to_f64_checked().ok()?;
+            (v.is_finite() && f64::from(v as f32) != v).then_some(CastError::PrecisionLoss)
+        }
+        SqlType::Double => value.to_f64_checked().err(),
+    }
+    .filter(|&error| error != CastError::NotNumeric)
+}
+
+// The names of the columns added for primary keys.  Fields which sanitize to the same identifiers, e.g. the `id` of
+// `battery_status`, get a suffix.
+const LOG_COLUMN: &str = "log_file";
+const SURROGATE_COLUMN: &str = "id";
+
+/// The identifier of a table, and the identifiers and types of the columns of a topic in it.
+#[cfg(feature = "postgres")]
+#[derive(Debug, Clone)]
+pub(crate) struct CopyTarget {
+    pub(crate) table: String,
+    pub(crate) columns: Vec<(String, SqlType)>,
+}
+
+/// The parts of an insert statement before and after its values, and the types of the columns of the topic.
+#[derive(Debug, Clone)]
+struct Insert {
+    prefix: String,
+    suffix: String,
+    types: Vec<SqlType>,
+}
+
+/// The rows of a subscription waiting to be inserted.
+#[derive(Debug)]
+struct Batch {
+    topic: String,
+    insert: Insert,
+    rows: Vec<String>,
+}
+
+/// The batches of the subscriptions of a log, and the open transaction.
+#[derive(Debug)]
+struct Batches {
+    // Ordered by msg_id, so that the output is reproducible.
+    inserts: BTreeMap<u16, Batch>,
+    rows_per_insert: usize,
+    rows_per_transaction: Option<usize>,
+    begin: &'static str,
+    // The number of rows inserted in the open transaction, if any.
+    transaction: Option<usize>,
+}
+
+impl Batches {
+    fn push<W: Write>(
+        &mut self,
+        msg_id: u16,
+        row: String,
+        writer: &mut W,
+    ) -> Result<(), ULogError> {
+        let batch = self
+            .inserts
+            .get_mut(&msg_id)
+            .expect("the insert was prepared");
+        batch.rows.push(row);
+        if batch.rows.len() >= self.rows_per_insert {
+            self.write(msg_id, writer)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the rows of the subscription `msg_id`, if any, as one statement.
+    fn write<W: Write>(&mut self, msg_id: u16, writer: &mut W) -> Result<(), ULogError> {
+        let batch = self
+            .inserts
+            .get_mut(&msg_id)
+            .expect("the insert was prepared");
+        if batch.rows.is_empty() {
+            return Ok(());
+        }
+
+        if self.rows_per_transaction.is_some() && self.transaction.is_none() {
+            writeln!(writer, "{}", self.begin)?;
+            self.transaction = Some(0);
+        }
+        writeln!(
+            writer,
+            "{}{}{};",
+            batch.insert.prefix,
+            batch.rows.join(", "),
+            batch.insert.suffix
+        )?;
+
+        if let (Some(limit), Some(count)) = (self.rows_per_transaction, &mut self.transaction) {
+            *count += batch.rows.len();
+            if *count >= limit {
+                writeln!(writer, "COMMIT;")?;
+                self.transaction = None;
+            }
+        }
+        batch.rows.clear();
+        Ok(())
+    }
+
+    /// Writes the rows of all the subscriptions, and commits the open transaction.
+    fn flush<W: Write>(&mut self, writer: &mut W) -> Result<(), ULogError> {
+        let msg_ids: Vec<u16> = self.inserts.keys().copied().collect();
+        for msg_id in msg_ids {
+            self.write(msg_id, writer)?;
+        }
+        if self.transaction.take().is_some() {
+            writeln!(writer, "COMMIT;")?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats `s` as an SQL string literal.
+// The type of the text columns.  MySQL can't index TEXT columns without a prefix length.
+fn text_type(dialect: Dialect) -> &'static str {
+    match dialect {
+        Dialect::MySql | Dialect::Sql => "VARCHAR(255)",
+        _ => "TEXT",
+    }
+}
+
+fn string_literal(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Alteration {
+    Add(SqlType),
+    Widen(SqlType),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    fn export(exporter: &mut SqlExporter, topic: Topic) -> Result<String, ULogError> {
+        let bytes = LogBuilder::new().topic(topic.rate_hz(2.0)).build()?;
+        let parser = ULogParserBuilder::new(bytes.as_slice()).build()?;
+        let mut output = Vec::new();
+        exporter.export(parser, &mut output)?;
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_schema_evolution() -> Result<(), ULogError> {
+        let v1 = || {
+            Topic::new("SensorCombined")
+                .field("x", BaseType::INT16, Generator::Constant(1.0))
+                .field("Vz", BaseType::FLOAT, Generator::Constant(f64::NAN))
+        };
+        let v2 = Topic::new("SensorCombined")
+            .field("x", BaseType::INT32, Generator::Constant(2.0))
+            .field("y", BaseType::UINT8, Generator::Constant(3.0));
+
+        let mut exporter = SqlExporter::new(Dialect::Postgres);
+        assert_eq!(
+            export(&mut exporter, v1())?,
+            "CREATE TABLE IF NOT EXISTS sensorcombined (timestamp BIGINT, multi_id SMALLINT, x SMALLINT, vz REAL);\n\
+             INSERT INTO sensorcombined (timestamp, multi_id, x, vz) VALUES (1000000, 0, 1, NULL);\n\
+             INSERT INTO sensorcombined (timestamp, multi_id, x, vz) VALUES (1500000, 0, 1, NULL);\n"
+        );
+        assert_eq!(exporter.table("SensorCombined"), Some("sensorcombined"));
+
+        assert_eq!(
+            export(&mut exporter, v2)?,
+            "ALTER TABLE sensorcombined ALTER COLUMN x TYPE INTEGER;\n\
+             ALTER TABLE sensorcombined ADD COLUMN y SMALLINT;\n\
+             INSERT INTO sensorcombined (timestamp, multi_id, x, y) VALUES (1000000, 0, 2, 3);\n\
+             INSERT INTO sensorcombined (timestamp, multi_id, x, y) VALUES (1500000, 0, 2, 3);\n"
+        );
+
+        // The first layout still fits the table.
+        let output = export(&mut exporter, v1())?;
+        assert!(output.starts_with("INSERT INTO"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict_schema() -> Result<(), ULogError> {
+        let mut exporter = SqlExporter::new(Dialect::Postgres)
+            .schema_policy(SchemaPolicy::Strict)
+            .invalid_floats(InvalidFloatPolicy::AsNaNString);
+        let infinite = || Topic::new("a").field("x", BaseType::FLOAT, Generator::Constant(f64::INFINITY));
+        let output = export(&mut exporter, infinite())?;
+        assert!(output.starts_with(
+            "CREATE TABLE IF NOT EXISTS a (timestamp BIGINT, multi_id SMALLINT, x REAL);\n"
+        ));
+        assert!(output.ends_with("VALUES (1500000, 0, 'Infinity');\n"));
+
+        for dialect in [Dialect::Sqlite, Dialect::MySql, Dialect::Sql] {
+            let mut exporter =
+                SqlExporter::new(dialect).invalid_floats(InvalidFloatPolicy::AsNaNString);
+            assert!(matches!(
+                export(&mut exporter, infinite()),
+                Err(ULogError::InvalidConfiguration(_))
+            ));
+        }
+
+        let result = export(
+            &mut exporter,
+            Topic::new("a").field("x", BaseType::DOUBLE, Generator::Constant(1.0)),
+        );
+        assert!(matches!(result, Err(ULogError::ExportError(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_primary_keys() -> Result<(), ULogError> {
+        let topic = || Topic::new("a").field("x", BaseType::INT32, Generator::Constant(1.0));
+        let export_log = |exporter: &mut SqlExporter, log: &str| -> Result<String, ULogError> {
+            let bytes = LogBuilder::new().topic(topic().rate_hz(1.0)).build()?;
+            let parser = ULogParserBuilder::new(bytes.as_slice()).build()?;
+            let mut output = Vec::new();
+            exporter.export_log(log, parser, &mut output)?;
+            Ok(String::from_utf8(output).unwrap())
+        };
+
+        let mut exporter = SqlExporter::new(Dialect::Postgres)
+            .primary_key(PrimaryKey::TimeFile)
+            .on_conflict(ConflictPolicy::Upsert);
+        assert_eq!(
+            export_log(&mut exporter, "it's.ulg")?,
+            "CREATE TABLE IF NOT EXISTS a (log_file TEXT NOT NULL, timestamp BIGINT, multi_id SMALLINT, x INTEGER, \
+             PRIMARY KEY (log_file, timestamp, multi_id));\n\
+             INSERT INTO a (log_file, timestamp, multi_id, x) VALUES ('it''s.ulg', 1000000, 0, 1) \
+             ON CONFLICT (log_file, timestamp, multi_id) DO UPDATE SET x = excluded.x;\n"
+        );
+        assert!(matches!(
+            export(&mut exporter, topic()),
+            Err(ULogError::InvalidConfiguration(_))
+        ));
+
+        let mut exporter = SqlExporter::new(Dialect::MySql)
+            .primary_key(PrimaryKey::TimeTopic)
+            .on_conflict(ConflictPolicy::Ignore);
+        let output = export(&mut exporter, topic())?;
+        assert!(output.contains("x INT, PRIMARY KEY (timestamp, multi_id));\n"));
+        assert!(output
+            .contains("INSERT IGNORE INTO a (timestamp, multi_id, x) VALUES (1000000, 0, 1);\n"));
+
+        let mut exporter = SqlExporter::new(Dialect::MySql)
+            .primary_key(PrimaryKey::TimeTopic)
+            .on_conflict(ConflictPolicy::Upsert);
+        assert!(
+            export(&mut exporter, topic())?.ends_with(") ON DUPLICATE KEY UPDATE x = VALUES(x);\n")
+        );
+
+        let mut exporter = SqlExporter::new(Dialect::Sqlite).primary_key(PrimaryKey::Surrogate);
+        let output = export(&mut exporter, topic())?;
+        assert!(output.starts_with(
+            "CREATE TABLE IF NOT EXISTS a (id INTEGER PRIMARY KEY, timestamp INTEGER, multi_id INTEGER, x INTEGER);\n\
+             INSERT INTO a (timestamp, multi_id, x) VALUES (1000000, 0, 1);\n"
+        ));
+
+        let mut exporter = SqlExporter::new(Dialect::Sqlite)
+            .primary_key(PrimaryKey::Surrogate)
+            .on_conflict(ConflictPolicy::Ignore);
+        assert!(matches!(
+            export(&mut exporter, topic()),
+            Err(ULogError::InvalidConfiguration(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_columns_clash_with_fields() -> Result<(), ULogError> {
+        // As the `id` of `battery_status`.
+        let battery_status = || {
+            Topic::new("battery_status")
+                .field("id", BaseType::UINT8, Generator::Constant(1.0))
+                .field("log_file", BaseType::UINT8, Generator::Constant(2.0))
+        };
+
+        let mut exporter = SqlExporter::new(Dialect::Sqlite).primary_key(PrimaryKey::Surrogate);
+        assert!(export(&mut exporter, battery_status())?.starts_with(
+            "CREATE TABLE IF NOT EXISTS battery_status (id INTEGER PRIMARY KEY, timestamp INTEGER, \
+             multi_id INTEGER, id_2 INTEGER, log_file INTEGER);\n\
+             INSERT INTO battery_status (timestamp, multi_id, id_2, log_file) VALUES (1000000, 0, 1, 2);\n"
+        ));
+
+        let bytes = LogBuilder::new().topic(battery_status().rate_hz(2.0)).build()?;
+        let mut output = Vec::new();
+        SqlExporter::new(Dialect::Postgres)
+            .primary_key(PrimaryKey::TimeFile)
+            .export_log("flight.ulg", ULogParserBuilder::new(bytes.as_slice()).build()?, &mut output)?;
+        assert!(String::from_utf8(output).unwrap().starts_with(
+            "CREATE TABLE IF NOT EXISTS battery_status (log_file TEXT NOT NULL, timestamp BIGINT, \
+             multi_id SMALLINT, id SMALLINT, log_file_2 SMALLINT, PRIMARY KEY (log_file, timestamp, multi_id));\n\
+             INSERT INTO battery_status (log_file, timestamp, multi_id, id, log_file_2) \
+             VALUES ('flight.ulg', 1000000, 0, 1, 2);\n"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_provenance() -> Result<(), ULogError> {
+        let bytes = LogBuilder::new()
+            .topic(Topic::new("a").rate_hz(1.0).field(
+                "x",
+                BaseType::INT32,
+                Generator::Constant(1.0),
+            ))
+            .build()?;
+        let export = |exporter: &mut SqlExporter, provenance: Option<&Provenance>| {
+            let parser = ULogParserBuilder::new(bytes.as_slice()).build()?;
+            let mut output = Vec::new();
+            match provenance {
+                Some(provenance) => {
+                    exporter.export_with_provenance(provenance, parser, &mut output)
+                }
+                None => exporter.export(parser, &mut output),
+            }?;
+            Ok::<_, ULogError>(String::from_utf8(output).unwrap())
+        };
+        let provenance = Provenance {
+            file_name: Some("flight_1.ulg".to_owned()),
+            file_hash: Some("00ff".to_owned()),
+            vehicle_uuid: None,
+        };
+
+        let mut exporter = SqlExporter::new(Dialect::MySql).primary_key(PrimaryKey::TimeFile);
+        assert_eq!(
+            export(&mut exporter, Some(&provenance))?,
+            "CREATE TABLE IF NOT EXISTS a (log_file VARCHAR(255) NOT NULL, timestamp BIGINT, \
+             multi_id SMALLINT, x INT, file_name VARCHAR(255), file_hash VARCHAR(255), \
+             vehicle_uuid VARCHAR(255), PRIMARY KEY (log_file, timestamp, multi_id));\n\
+             INSERT INTO a (log_file, timestamp, multi_id, x, file_name, file_hash, vehicle_uuid) \
+             VALUES ('flight_1.ulg', 1000000, 0, 1, 'flight_1.ulg', '00ff', NULL);\n"
+        );
+
+        // A table created without the columns gains them.
+        let mut exporter = SqlExporter::new(Dialect::Postgres);
+        export(&mut exporter, None)?;
+        let output = export(&mut exporter, Some(&provenance))?;
+        assert!(output.starts_with(
+            "ALTER TABLE a ADD COLUMN file_name TEXT;\n\
+             ALTER TABLE a ADD COLUMN file_hash TEXT;\n\
+             ALTER TABLE a ADD COLUMN vehicle_uuid TEXT;\n"
+        ));
+        assert!(export(&mut exporter, None)?
+            .starts_with("INSERT INTO a (timestamp, multi_id, x) VALUES"));
+
+        let mut exporter = SqlExporter::new(Dialect::Postgres).schema_policy(SchemaPolicy::Strict);
+        export(&mut exporter, None)?;
+        assert!(matches!(
+            export(&mut exporter, Some(&provenance)),
+            Err(ULogError::ExportError(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_batches() -> Result<(), ULogError> {
+        let mut exporter = SqlExporter::new(Dialect::Sqlite)
+            .rows_per_insert(4)
+            .rows_per_transaction(Some(6));
+        let bytes = LogBuilder::new()
+            .topic(Topic::new("a").field("x", BaseType::UINT8, Generator::Constant(1.0)))
+            .topic(Topic::new("b").field("y", BaseType::UINT8, Generator::Constant(2.0)))
+            .build()?;
+        let parser = ULogParserBuilder::new(bytes.as_slice()).build()?;
+        let mut output = Vec::new();
+        assert_eq!(exporter.export(parser, &mut output)?, 20);
+
+        let statements: Vec<String> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| match line.find(" VALUES ") {
+                Some(i) => format!("{} {}", &line[..i], line.matches('(').count() - 1),
+                None => line.split(' ').next().unwrap().to_owned(),
+            })
+            .collect();
+        assert_eq!(
+            statements,
+            [
+                "CREATE",
+                "BEGIN;",
+                "INSERT INTO a (timestamp, multi_id, x) 1",
+                "COMMIT;",
+                // Creating the table of b committed the row of a read before it.
+                "CREATE",
+                "BEGIN;",
+                "INSERT INTO b (timestamp, multi_id, y) 4",
+                "INSERT INTO a (timestamp, multi_id, x) 4",
+                "COMMIT;",
+                "BEGIN;",
+                "INSERT INTO b (timestamp, multi_id, y) 4",
+                "INSERT INTO a (timestamp, multi_id, x) 4",
+                "COMMIT;",
+                "BEGIN;",
+                "INSERT INTO a (timestamp, multi_id, x) 1",
+                "INSERT INTO b (timestamp, multi_id, y) 2",
+                "COMMIT;",
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_u64_above_i64_max() -> Result<(), ULogError> {
+        let topic = || Topic::new("a").field("x", BaseType::UINT64, Generator::Constant(1.8e19));
+
+        let mut exporter = SqlExporter::new(Dialect::Postgres);
+        let output = export(&mut exporter, topic())?;
+        assert!(output.contains("x BIGINT"));
+        let rows = output.matches("INSERT INTO").count() as u64;
+        assert_eq!(
+            output.matches(&format!(", {});", 18_000_000_000_000_000_000_u64 as i64)).count() as u64,
+            rows
+        );
+        assert_eq!(exporter.lossy_casts().overflows["a"], rows);
+
+        export(&mut exporter, topic())?;
+        assert_eq!(exporter.lossy_casts().total(), 2 * rows);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lossy_cast() {
+        assert_eq!(
+            lossy_cast(&FieldValue::ScalarU64(u64::MAX), SqlType::BigInt),
+            Some(CastError::Overflow)
+        );
+        assert_eq!(lossy_cast(&FieldValue::ScalarU64(7), SqlType::BigInt), None);
+        assert_eq!(
+            lossy_cast(&FieldValue::ScalarI64(i64::MAX), SqlType::Double),
+            Some(CastError::PrecisionLoss)
+        );
+        assert_eq!(
+            lossy_cast(&FieldValue::ScalarF32(f32::NAN), SqlType::Real),
+            None
+        );
+        assert_eq!(
+            lossy_cast(&FieldValue::ScalarU8(1), SqlType::SmallInt),
+            None
+        );
+    }
+
+    #[test]
+    fn test_widen() {
+        assert_eq!(SqlType::SmallInt.widen(SqlType::BigInt), SqlType::BigInt);
+        assert_eq!(SqlType::Double.widen(SqlType::Real), SqlType::Double);
+        assert_eq!(SqlType::Integer.widen(SqlType::Real), SqlType::Double);
+    }
+}
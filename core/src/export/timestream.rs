@@ -0,0 +1,540 @@
+//! Writes the `LoggedData` messages of ULOG files to an Amazon Timestream table, as multi-measure records.
+//!
+//! Each message becomes a record whose measure name is its topic, whose measures are the columns of the topic,
+//! named as by the CSV exporter, and whose dimensions are its `multi_id` and the dimensions of the exporter,
+//! e.g. the vehicle.  Records are written in batches of up to 100, the most `WriteRecords` accepts, by a bounded
+//! number of threads.
+//!
+//! The exporter doesn't depend on the AWS SDK.  Instead, it calls a `TimestreamWriter`, which is usually a thin
+//! wrapper of the SDK's `write_records()` call, mapping its errors to `WriteError`.  Throttled writes are retried
+//! with exponential backoff, and rejected records are returned in the report, rather than failing the export:
+//!
+//! ```rust,no_run
+//! # use std::time::Duration;
+//! # use yule_log::builder::ULogParserBuilder;
+//! use yule_log::export::timestream::{Record, TimestreamExporter, TimestreamWriter, WriteError};
+//!
+//! struct SdkWriter {/* An `aws_sdk_timestreamwrite::Client`, and a runtime to block on. */}
+//!
+//! impl TimestreamWriter for SdkWriter {
+//!     fn write_records(&self, database: &str, table: &str, records: &[Record]) -> Result<(), WriteError> {
+//!         // Convert the records, call `write_records()`, and map `ThrottlingException` to
+//!         // `WriteError::Throttled`, and `RejectedRecordsException` to `WriteError::Rejected`.
+//! #       Ok(())
+//!     }
+//! }
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let exporter = TimestreamExporter::new("telemetry", "flights")
+//!     .dimension("vehicle", "x500-07")
+//!     .measures("vehicle_local_position", &["x", "y", "z"])
+//!     .time_offset_us(1_700_000_000_000_000)
+//!     .concurrency(8);
+//!
+//! let parser = ULogParserBuilder::new(std::fs::File::open("flight.ulg")?).build()?;
+//! let report = exporter.export(parser, &SdkWriter {})?;
+//! for rejected in &report.rejected {
+//!     eprintln!("{} at {}: {}", rejected.record.measure_name, rejected.record.time_us, rejected.reason);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::errors::ULogError;
+use crate::export::{column_names, for_each_scalar, LossyCasts};
+use crate::model::inst::FieldValue;
+use crate::model::msg::UlogMessage;
+
+/// The most records `WriteRecords` accepts in one call.
+pub const MAX_RECORDS_PER_WRITE: usize = 100;
+
+/// The value of a measure.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MeasureValue {
+    Double(f64),
+    BigInt(i64),
+    Boolean(bool),
+}
+
+/// A multi-measure record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    /// The dimensions of the exporter, followed by `multi_id`.
+    pub dimensions: Vec<(String, String)>,
+    /// The topic of the message.
+    pub measure_name: String,
+    /// The measures, without NaN or infinite floats, which Timestream rejects.
+    pub measures: Vec<(String, MeasureValue)>,
+    /// The timestamp of the message plus the time offset of the exporter, in microseconds.
+    pub time_us: u64,
+}
+
+/// A failed call to `TimestreamWriter::write_records()`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WriteError {
+    /// The write was throttled, and no records were written.  It is retried.
+    Throttled(String),
+    /// Some records were rejected, given by their index and reason, and the others were written.
+    Rejected(Vec<(usize, String)>),
+    /// Any other error, which fails the export.
+    Other(String),
+}
+
+/// Writes batches of records to Timestream.
+pub trait TimestreamWriter {
+    fn write_records(
+        &self,
+        database: &str,
+        table: &str,
+        records: &[Record],
+    ) -> Result<(), WriteError>;
+}
+
+/// A record which Timestream rejected.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RejectedRecord {
+    pub record: Record,
+    pub reason: String,
+}
+
+/// The outcome of `TimestreamExporter::export()`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TimestreamReport {
+    /// The number of records written.
+    pub written: u64,
+    /// The records which Timestream rejected.
+    pub rejected: Vec<RejectedRecord>,
+    /// The number of writes which were throttled and retried.
+    pub retries: u64,
+    /// The `u64` values above `i64::MAX`, which wrap to negative `BIGINT` measures.
+    pub lossy_casts: LossyCasts,
+}
+
+impl TimestreamReport {
+    fn add(&mut self, other: TimestreamReport) {
+        self.written += other.written;
+        self.rejected.extend(other.rejected);
+        self.retries += other.retries;
+        self.lossy_casts.add(&other.lossy_casts);
+    }
+}
+
+/// A Timestream exporter.  See the module documentation.
+#[derive(Debug, Clone)]
+pub struct TimestreamExporter {
+    database: String,
+    table: String,
+    dimensions: Vec<(String, String)>,
+    measures: HashMap<String, Vec<String>>,
+    time_offset_us: u64,
+    concurrency: usize,
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl TimestreamExporter {
+    pub fn new(database: impl Into<String>, table: impl Into<String>) -> Self {
+        TimestreamExporter {
+            database: database.into(),
+            table: table.into(),
+            dimensions: Vec::new(),
+            measures: HashMap::new(),
+            time_offset_us: 0,
+            concurrency: 4,
+            max_retries: 8,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+
+    /// Adds a dimension to all the records, e.g. the vehicle or the flight.
+    #[must_use]
+    pub fn dimension(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.dimensions.push((name.into(), value.into()));
+        self
+    }
+
+    /// Writes only `columns` as the measures of `topic`.  By default, all the columns of each topic are written.
+    #[must_use]
+    pub fn measures<S: AsRef<str>>(mut self, topic: impl Into<String>, columns: &[S]) -> Self {
+        self.measures.insert(
+            topic.into(),
+            columns.iter().map(|c| c.as_ref().to_owned()).collect(),
+        );
+        self
+    }
+
+    /// Added to the timestamps of the log, which count from boot, to give the record times, e.g. the Unix time
+    /// of boot in microseconds.  Defaults to 0.  Timestream rejects records older than the retention of its
+    /// memory store.
+    #[must_use]
+    pub fn time_offset_us(mut self, offset: u64) -> Self {
+        self.time_offset_us = offset;
+        self
+    }
+
+    /// The most batches written at once.  Defaults to 4.
+    #[must_use]
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// The most times a throttled batch is retried, before the export fails.  Defaults to 8.
+    #[must_use]
+    pub fn max_retries(mut self, retries: u32) -> Self {
+        self.max_retries = retries;
+        self
+    }
+
+    /// The wait before the first retry of a batch, which doubles with each retry up to `max`.  Defaults to
+    /// 100 ms and 10 s.
+    #[must_use]
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Writes the records of `messages` through `writer`.
+    ///
+    /// Fails with `ULogError::DatabaseError` if a write fails other than by rejecting records, or is still
+    /// throttled after the last retry.  Records already written stay written.
+    pub fn export<I, W>(&self, messages: I, writer: &W) -> Result<TimestreamReport, ULogError>
+    where
+        I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+        W: TimestreamWriter + Sync,
+    {
+        let (sender, receiver) = mpsc::sync_channel::<Vec<Record>>(self.concurrency);
+        let receiver = Mutex::new(receiver);
+        let failed = AtomicBool::new(false);
+
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..self.concurrency)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut report = TimestreamReport::default();
+                        let mut error = None;
+                        loop {
+                            let batch = receiver.lock().expect("the lock is not poisoned").recv();
+                            let Ok(batch) = batch else {
+                                break;
+                            };
+                            // After a failure, batches are discarded until the producer notices.
+                            if error.is_some() {
+                                continue;
+                            }
+                            match self.write_batch(writer, batch) {
+                                Ok(outcome) => report.add(outcome),
+                                Err(e) => {
+                                    failed.store(true, Ordering::Relaxed);
+                                    error = Some(e);
+                                }
+                            }
+                        }
+                        (report, error)
+                    })
+                })
+                .collect();
+
+            let produced = self.produce(messages, &sender, &failed);
+            drop(sender);
+
+            let mut report = TimestreamReport::default();
+            let mut error = None;
+            for worker in workers {
+                let (outcome, e) = worker.join().expect("the worker doesn't panic");
+                report.add(outcome);
+                error = error.or(e);
+            }
+            match (produced, error) {
+                (Err(e), _) | (Ok(_), Some(e)) => Err(e),
+                (Ok(lossy_casts), None) => {
+                    lossy_casts.warn("The Timestream exporter");
+                    report.lossy_casts = lossy_casts;
+                    Ok(report)
+                }
+            }
+        })
+    }
+
+    /// Converts `messages` into batches of records, and sends them to the workers, until a worker fails.
+    /// Returns the lossy casts of the conversion.
+    fn produce<I>(
+        &self,
+        messages: I,
+        sender: &mpsc::SyncSender<Vec<Record>>,
+        failed: &AtomicBool,
+    ) -> Result<LossyCasts, ULogError>
+    where
+        I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+    {
+        let mut lossy_casts = LossyCasts::default();
+        // msg_id -> (topic, the names of its columns, which of them are measures).
+        let mut subscriptions: HashMap<u16, (String, Vec<String>, Vec<bool>)> = HashMap::new();
+        let mut batch = Vec::with_capacity(MAX_RECORDS_PER_WRITE);
+
+        for message in messages {
+            if failed.load(Ordering::Relaxed) {
+                return Ok(lossy_casts);
+            }
+            let UlogMessage::LoggedData(data) = message? else {
+                continue;
+            };
+
+            let stale = subscriptions
+                .get(&data.msg_id)
+                .is_none_or(|(topic, _, _)| *topic != *data.data.name);
+            if stale {
+                let names: Vec<String> = column_names(&data.data)
+                    .into_iter()
+                    .map(|(name, _)| name)
+                    .collect();
+                let selected = match self.measures.get(&*data.data.name) {
+                    Some(measures) => names.iter().map(|name| measures.contains(name)).collect(),
+                    None => vec![true; names.len()],
+                };
+                subscriptions.insert(data.msg_id, (data.data.name.to_string(), names, selected));
+            }
+            let (topic, names, selected) = &subscriptions[&data.msg_id];
+
+            let mut measures = Vec::new();
+            let mut column = 0;
+            for_each_scalar(&data.data, &mut |value| {
+                if selected[column] {
+                    let measure = measure_value(value);
+                    if let Some(MeasureValue::BigInt(_)) = measure {
+                        if let Err(error) = value.to_i64_checked() {
+                            lossy_casts.record(topic, error);
+                        }
+                    }
+                    measures.extend(measure.map(|v| (names[column].clone(), v)));
+                }
+                column += 1;
+            });
+            if measures.is_empty() {
+                continue;
+            }
+
+            let mut dimensions = self.dimensions.clone();
+            dimensions.push((
+                "multi_id".to_owned(),
+                data.data.multi_id_index.unwrap_or(0).to_string(),
+            ));
+            batch.push(Record {
+                dimensions,
+                measure_name: topic.clone(),
+                measures,
+                time_us: data.timestamp.saturating_add(self.time_offset_us),
+            });
+
+            if batch.len() == MAX_RECORDS_PER_WRITE {
+                let full = std::mem::replace(&mut batch, Vec::with_capacity(MAX_RECORDS_PER_WRITE));
+                // The workers only stop once the sender is dropped, so this can't fail.
+                let _ = sender.send(full);
+            }
+        }
+
+        if !batch.is_empty() {
+            let _ = sender.send(batch);
+        }
+        Ok(lossy_casts)
+    }
+
+    /// Writes a batch, retrying it while it is throttled.
+    fn write_batch<W: TimestreamWriter>(
+        &self,
+        writer: &W,
+        records: Vec<Record>,
+    ) -> Result<TimestreamReport, ULogError> {
+        let mut backoff = self.initial_backoff;
+        let mut retries = 0;
+
+        loop {
+            match writer.write_records(&self.database, &self.table, &records) {
+                Ok(()) => {
+                    return Ok(TimestreamReport {
+                        written: records.len() as u64,
+                        rejected: Vec::new(),
+                        retries,
+                        lossy_casts: LossyCasts::default(),
+                    })
+                }
+                Err(WriteError::Throttled(message)) => {
+                    if retries >= u64::from(self.max_retries) {
+                        return Err(ULogError::DatabaseError(format!(
+                            "Timestream: still throttled after {retries} retries: {message}"
+                        )));
+                    }
+                    thread::sleep(backoff);
+                    backoff = (backoff * 2).min(self.max_backoff);
+                    retries += 1;
+                }
+                Err(WriteError::Rejected(rejections)) => {
+                    let rejected: Vec<RejectedRecord> = rejections
+                        .into_iter()
+                        .filter_map(|(index, reason)| {
+                            records.get(index).map(|record| RejectedRecord {
+                                record: record.clone(),
+                                reason,
+                            })
+                        })
+                        .collect();
+                    return Ok(TimestreamReport {
+                        written: (records.len() - rejected.len()) as u64,
+                        rejected,
+                        retries,
+                        lossy_casts: LossyCasts::default(),
+                    });
+                }
+                Err(WriteError::Other(message)) => {
+                    return Err(ULogError::DatabaseError(format!("Timestream: {message}")))
+                }
+            }
+        }
+    }
+}
+
+/// Converts a scalar to a measure value, or `None` for a NaN or infinite float.
+fn measure_value(value: &FieldValue) -> Option<MeasureValue> {
+    match *value {
+        FieldValue::ScalarF32(v) => Some(f64::from(v))
+            .filter(|v| v.is_finite())
+            .map(MeasureValue::Double),
+        FieldValue::ScalarF64(v) => Some(v).filter(|v| v.is_finite()).map(MeasureValue::Double),
+        FieldValue::ScalarBool(v) => Some(MeasureValue::Boolean(v)),
+        // Wraps above 2^63, as Timestream has no unsigned type.
+        FieldValue::ScalarU64(v) => Some(MeasureValue::BigInt(v as i64)),
+        FieldValue::ScalarChar(v) => Some(MeasureValue::BigInt(i64::from(v.0))),
+        ref value => value.as_f64().map(|v| MeasureValue::BigInt(v as i64)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::model::def::BaseType;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    /// Throttles the first writes, and rejects records whose `x` is a multiple of 7.
+    #[derive(Default)]
+    struct MockWriter {
+        throttles: AtomicUsize,
+        records: Mutex<Vec<Record>>,
+    }
+
+    impl TimestreamWriter for MockWriter {
+        fn write_records(
+            &self,
+            database: &str,
+            table: &str,
+            records: &[Record],
+        ) -> Result<(), WriteError> {
+            assert_eq!((database, table), ("db", "flights"));
+            assert!(records.len() <= MAX_RECORDS_PER_WRITE);
+            if self.throttles.fetch_add(1, Ordering::Relaxed) < 3 {
+                return Err(WriteError::Throttled("rate exceeded".to_owned()));
+            }
+
+            let mut rejections = Vec::new();
+            for (i, record) in records.iter().enumerate() {
+                match record.measures.first() {
+                    Some((_, MeasureValue::BigInt(x))) if x % 7 == 0 => {
+                        rejections.push((i, "duplicate".to_owned()))
+                    }
+                    _ => self.records.lock().unwrap().push(record.clone()),
+                }
+            }
+            if rejections.is_empty() {
+                Ok(())
+            } else {
+                Err(WriteError::Rejected(rejections))
+            }
+        }
+    }
+
+    fn log() -> Result<Vec<u8>, ULogError> {
+        LogBuilder::new()
+            .topic(
+                Topic::new("a")
+                    .rate_hz(200.0)
+                    .field(
+                        "x",
+                        BaseType::INT32,
+                        Generator::Ramp {
+                            start: 1.0,
+                            step: 1.0,
+                        },
+                    )
+                    .field("y", BaseType::FLOAT, Generator::Constant(f64::NAN))
+                    .field("z", BaseType::BOOL, Generator::Constant(1.0)),
+            )
+            .build()
+    }
+
+    #[test]
+    fn test_export() -> Result<(), ULogError> {
+        let bytes = log()?;
+        let writer = MockWriter::default();
+        let exporter = TimestreamExporter::new("db", "flights")
+            .dimension("vehicle", "v1")
+            .time_offset_us(10)
+            .concurrency(3)
+            .backoff(Duration::from_millis(1), Duration::from_millis(2));
+
+        let report = exporter.export(ULogParserBuilder::new(bytes.as_slice()).build()?, &writer)?;
+        assert_eq!(report.retries, 3);
+        assert_eq!(report.rejected.len(), 200 / 7);
+        assert_eq!(report.written, 200 - 200 / 7);
+        assert_eq!(report.rejected[0].reason, "duplicate");
+
+        let mut records = writer.records.into_inner().unwrap();
+        assert_eq!(records.len() as u64, report.written);
+        records.sort_by_key(|record| record.time_us);
+        assert_eq!(
+            records[0],
+            Record {
+                dimensions: vec![
+                    ("vehicle".to_owned(), "v1".to_owned()),
+                    ("multi_id".to_owned(), "0".to_owned())
+                ],
+                measure_name: "a".to_owned(),
+                // The NaN measure is left out.
+                measures: vec![
+                    ("x".to_owned(), MeasureValue::BigInt(1)),
+                    ("z".to_owned(), MeasureValue::Boolean(true))
+                ],
+                time_us: 1_000_010,
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_throttled() -> Result<(), ULogError> {
+        let bytes = log()?;
+        let exporter = TimestreamExporter::new("db", "flights")
+            .measures("a", &["z"])
+            .max_retries(1)
+            .backoff(Duration::from_millis(1), Duration::from_millis(1));
+
+        let result = exporter.export(
+            ULogParserBuilder::new(bytes.as_slice()).build()?,
+            &MockWriter::default(),
+        );
+        assert!(matches!(result, Err(ULogError::DatabaseError(_))));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,541 @@
+//! Reads the values of some fields of some topics across a whole log, the most common analytic query, in a single
+//! pass which decodes nothing else.
+//!
+//! Only the framing of messages is read, as by `ULogParser::raw_messages()`.  The format of each topic is laid out
+//! once, see `def::Layout`, and the timestamps and values are sliced straight out of its data messages.  The messages
+//! of other topics are framed but never decoded, so, unlike with a subscription allow-list, no `LoggedData` is built,
+//! even for the topics extracted.
+//!
+//! `column()` extracts one field.  A `Plan` extracts many, of several topics, named by `FieldPath`s:
+//!
+//! ```rust
+//! use yule_log::extract::{self, Plan};
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+//! let (timestamps, z) = extract::column::<f32>(input.as_slice(), "vehicle_local_position", "z")?;
+//! assert_eq!(timestamps.len(), z.len());
+//!
+//! let plan = Plan::compile(["vehicle_local_position/x", "vehicle_local_position/y", "vehicle_attitude/q[0]"])?;
+//! for topic in plan.execute(input.as_slice())? {
+//!     for (name, values) in &topic.columns {
+//!         println!("{}/{name}: {} values", topic.topic, values.len());
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::builder::ULogParserBuilder;
+use crate::errors::ULogError;
+use crate::formats::parse_format;
+use crate::message_buf::{MessageBuf, ULogPrimitive};
+use crate::model::def::{BaseType, Layout};
+use crate::model::{def, CChar, Shared};
+use crate::parser::ULogMessageType;
+use crate::timeseries::FieldPath;
+
+/// A type which the values of a field can be extracted as: that of the field's `BaseType`.
+pub trait Scalar: ULogPrimitive {
+    const BASE_TYPE: BaseType;
+
+    /// The values of `data`, if they are of this type.
+    fn values(data: &ColumnData) -> Option<&[Self]>;
+
+    fn into_values(data: ColumnData) -> Option<Vec<Self>>;
+}
+
+/// The values of a column, as the type of its field.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnData {
+    UInt8(Vec<u8>),
+    UInt16(Vec<u16>),
+    UInt32(Vec<u32>),
+    UInt64(Vec<u64>),
+    Int8(Vec<i8>),
+    Int16(Vec<i16>),
+    Int32(Vec<i32>),
+    Int64(Vec<i64>),
+    Float(Vec<f32>),
+    Double(Vec<f64>),
+    Bool(Vec<bool>),
+    Char(Vec<CChar>),
+}
+
+macro_rules! impl_scalar {
+    ($($type:ty => $base_type:ident, $variant:ident;)*) => {
+        $(
+            impl Scalar for $type {
+                const BASE_TYPE: BaseType = BaseType::$base_type;
+
+                fn values(data: &ColumnData) -> Option<&[Self]> {
+                    match data {
+                        ColumnData::$variant(values) => Some(values),
+                        _ => None,
+                    }
+                }
+
+                fn into_values(data: ColumnData) -> Option<Vec<Self>> {
+                    match data {
+                        ColumnData::$variant(values) => Some(values),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+
+        impl ColumnData {
+            /// An empty column of `base_type`, or `None` for a nested format.
+            pub fn new(base_type: &BaseType) -> Option<Self> {
+                match base_type {
+                    $(BaseType::$base_type => Some(ColumnData::$variant(Vec::new())),)*
+                    BaseType::OTHER(_) => None,
+                }
+            }
+
+            pub fn base_type(&self) -> BaseType {
+                match self {
+                    $(ColumnData::$variant(_) => BaseType::$base_type,)*
+                }
+            }
+
+            pub fn len(&self) -> usize {
+                match self {
+                    $(ColumnData::$variant(values) => values.len(),)*
+                }
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.len() == 0
+            }
+
+            // Appends the value of the little endian `bytes`, which are as many as its type has.
+            fn push(&mut self, bytes: &[u8]) {
+                match self {
+                    $(ColumnData::$variant(values) => values.push(<$type as ULogPrimitive>::from_le_bytes(bytes)),)*
+                }
+            }
+        }
+    };
+}
+
+impl_scalar! {
+    u8 => UINT8, UInt8;
+    u16 => UINT16, UInt16;
+    u32 => UINT32, UInt32;
+    u64 => UINT64, UInt64;
+    i8 => INT8, Int8;
+    i16 => INT16, Int16;
+    i32 => INT32, Int32;
+    i64 => INT64, Int64;
+    f32 => FLOAT, Float;
+    f64 => DOUBLE, Double;
+    bool => BOOL, Bool;
+    CChar => CHAR, Char;
+}
+
+/// The columns extracted of a topic instance by a `Plan`, one value of each per data message, in the order logged.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopicColumns {
+    pub topic: String,
+    pub multi_id: u8,
+    pub timestamps: Vec<u64>,
+    /// The columns, by name, in the order of the plan.
+    pub columns: Vec<(String, ColumnData)>,
+}
+
+impl TopicColumns {
+    pub fn column(&self, name: &str) -> Option<&ColumnData> {
+        self.columns
+            .iter()
+            .find(|(column, _)| column == name)
+            .map(|(_, data)| data)
+    }
+
+    // Appends the timestamp and values of a message, checking first that it holds them all, so that the columns
+    // stay the same length.
+    fn push(
+        &mut self,
+        data: &[u8],
+        timestamp_offset: usize,
+        offsets: &[usize],
+    ) -> Result<(), ULogError> {
+        let too_short = |end: usize| end > data.len();
+        if too_short(timestamp_offset + 8)
+            || self
+                .columns
+                .iter()
+                .zip(offsets)
+                .any(|((_, column), &offset)| too_short(offset + size_of(column)))
+        {
+            return Err(ULogError::ParseError(format!(
+                "A LOGGED_DATA message of `{}` of {} bytes is too short to hold its columns.",
+                self.topic,
+                data.len()
+            )));
+        }
+
+        self.timestamps
+            .push(LittleEndian::read_u64(&data[timestamp_offset..]));
+        for ((_, column), &offset) in self.columns.iter_mut().zip(offsets) {
+            column.push(&data[offset..offset + size_of(column)]);
+        }
+        Ok(())
+    }
+}
+
+/// The fields to extract in one pass over a log, grouped by topic instance.  See the module documentation.
+///
+/// The column of each `FieldPath` is a path as for `Layout::offset_of()`, to a scalar or an array element, e.g. `z`,
+/// `accel[2]` or `esc[1].esc_rpm`.
+#[derive(Debug, Clone, Default)]
+pub struct Plan {
+    // The topic instances, in the order first named, each with its columns.
+    topics: Vec<(String, u8, Vec<String>)>,
+}
+
+// The offsets of the timestamp and of each column in a message.
+type Offsets = (usize, Vec<usize>);
+
+// A topic instance of a plan being executed.
+struct Extraction {
+    columns: TopicColumns,
+    // Located once the topic is subscribed.
+    offsets: Option<Offsets>,
+}
+
+impl Plan {
+    pub fn new() -> Self {
+        Plan::default()
+    }
+
+    /// Parses each path, e.g. `vehicle_local_position/x` or `actuator_outputs.01/output[3]`.  See
+    /// `FieldPath::from_str()`.
+    pub fn compile<I>(paths: I) -> Result<Self, ULogError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        paths.into_iter().try_fold(Plan::new(), |plan, path| {
+            Ok(plan.field(path.as_ref().parse()?))
+        })
+    }
+
+    /// Adds a field, unless it is already in the plan.
+    #[must_use]
+    pub fn field(mut self, path: FieldPath) -> Self {
+        let position = self
+            .topics
+            .iter()
+            .position(|(topic, multi_id, _)| *topic == path.topic && *multi_id == path.multi_id);
+        let columns = match position {
+            Some(i) => &mut self.topics[i].2,
+            None => {
+                self.topics.push((path.topic, path.multi_id, Vec::new()));
+                &mut self.topics.last_mut().expect("just pushed").2
+            }
+        };
+        if !columns.contains(&path.column) {
+            columns.push(path.column);
+        }
+        self
+    }
+
+    /// Reads the log, and returns the columns of each topic instance of the plan, in the order first named.  The
+    /// columns of a topic which isn't logged are empty.
+    ///
+    /// Returns `ULogError::InvalidFieldName` if a topic has no such field, and `ULogError::TypeMismatch` if a path
+    /// names a whole array or nested format.
+    pub fn execute(&self, reader: impl Read) -> Result<Vec<TopicColumns>, ULogError> {
+        let mut messages = ULogParserBuilder::new(reader).build()?.raw_messages();
+
+        let mut formats: HashMap<Shared<str>, Shared<def::Format>> = HashMap::new();
+        // Each topic is laid out once, for all its instances.
+        let mut layouts: HashMap<String, Layout> = HashMap::new();
+        let mut extractions: Vec<Extraction> = self
+            .topics
+            .iter()
+            .map(|(topic, multi_id, _)| Extraction {
+                columns: TopicColumns {
+                    topic: topic.clone(),
+                    multi_id: *multi_id,
+                    timestamps: Vec::new(),
+                    columns: Vec::new(),
+                },
+                offsets: None,
+            })
+            .collect();
+        // msg_id -> index of the extraction
+        let mut subscribed: HashMap<u16, usize> = HashMap::new();
+
+        while let Some(message) = messages.next() {
+            let (header, contents) = message?;
+            match header.msg_type {
+                ULogMessageType::FORMAT => {
+                    let format = parse_format(MessageBuf::from_vec(contents.to_vec()))?;
+                    formats.insert(format.name.clone(), Shared::new(format));
+                }
+                ULogMessageType::ADD_SUBSCRIPTION if contents.len() >= 3 => {
+                    let Some(i) = self.topics.iter().position(|(topic, multi_id, _)| {
+                        contents[0] == *multi_id && &contents[3..] == topic.as_bytes()
+                    }) else {
+                        continue;
+                    };
+                    let extraction = &mut extractions[i];
+                    if extraction.offsets.is_none() {
+                        let (topic, _, columns) = &self.topics[i];
+                        if !layouts.contains_key(topic) {
+                            let format = formats
+                                .get(topic.as_str())
+                                .ok_or_else(|| ULogError::UndefinedFormat(topic.clone()))?;
+                            layouts.insert(topic.clone(), format.layout(&formats)?);
+                        }
+                        let (offsets, data) = locate(&layouts[topic], columns)?;
+                        extraction.offsets = Some(offsets);
+                        extraction.columns.columns = columns.iter().cloned().zip(data).collect();
+                    }
+                    subscribed.insert(LittleEndian::read_u16(&contents[1..3]), i);
+                }
+                ULogMessageType::REMOVE_SUBSCRIPTION if contents.len() >= 2 => {
+                    subscribed.remove(&LittleEndian::read_u16(&contents[0..2]));
+                }
+                ULogMessageType::DATA if contents.len() >= 2 => {
+                    let Some(&i) = subscribed.get(&LittleEndian::read_u16(&contents[0..2])) else {
+                        continue;
+                    };
+                    let extraction = &mut extractions[i];
+                    let (timestamp_offset, offsets) =
+                        extraction.offsets.as_ref().expect("located on subscribing");
+                    extraction
+                        .columns
+                        .push(&contents[2..], *timestamp_offset, offsets)?;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(extractions
+            .into_iter()
+            .map(|extraction| extraction.columns)
+            .collect())
+    }
+}
+
+fn size_of(column: &ColumnData) -> usize {
+    column
+        .base_type()
+        .primitive_size()
+        .expect("columns are primitives")
+}
+
+// The offsets of `columns`, and the empty data of each.
+fn locate(layout: &Layout, columns: &[String]) -> Result<(Offsets, Vec<ColumnData>), ULogError> {
+    let timestamp = match layout.field("timestamp") {
+        Some(timestamp)
+            if timestamp.r#type.base_type == BaseType::UINT64 && timestamp.r#type.is_scalar() =>
+        {
+            timestamp.offset
+        }
+        _ => return Err(ULogError::MissingTimestamp),
+    };
+
+    let mut offsets = Vec::with_capacity(columns.len());
+    let mut data = Vec::with_capacity(columns.len());
+    for column in columns {
+        let Some((offset, size, field)) = layout.locate(column) else {
+            return Err(ULogError::InvalidFieldName(format!(
+                "`{}` has no field `{column}`.",
+                layout.name
+            )));
+        };
+        match ColumnData::new(&field.r#type.base_type) {
+            Some(column_data) if size == field.element_size => {
+                offsets.push(offset);
+                data.push(column_data);
+            }
+            _ => {
+                return Err(ULogError::TypeMismatch(format!(
+                    "`{column}` of `{}` is `{}`, not a scalar.",
+                    layout.name, field.r#type
+                )))
+            }
+        }
+    }
+    Ok(((timestamp, offsets), data))
+}
+
+/// Returns the timestamps and values of `field` in each data message of the instance of `topic` with multi_id 0.
+/// See `column_of_instance()`.
+pub fn column<T: Scalar>(
+    reader: impl Read,
+    topic: &str,
+    field: &str,
+) -> Result<(Vec<u64>, Vec<T>), ULogError> {
+    column_of_instance(reader, topic, 0, field)
+}
+
+/// Returns the timestamps and values of `field` in each data message of an instance of `topic`, in the order logged,
+/// by executing a `Plan` of the one field.
+///
+/// Both vectors are empty if the topic isn't logged.  Returns `ULogError::TypeMismatch` if the field isn't of the
+/// base type of `T`, e.g. `f32` for `float`, as well as the errors of `Plan::execute()`.
+pub fn column_of_instance<T: Scalar>(
+    reader: impl Read,
+    topic: &str,
+    multi_id: u8,
+    field: &str,
+) -> Result<(Vec<u64>, Vec<T>), ULogError> {
+    let plan = Plan::new().field(FieldPath::new(topic, multi_id, field));
+    let topic_columns = plan.execute(reader)?.pop().expect("the plan has one topic");
+
+    let Some((_, data)) = topic_columns.columns.into_iter().next() else {
+        // Not logged.
+        return Ok((Vec::new(), Vec::new()));
+    };
+    let base_type = data.base_type();
+    match T::into_values(data) {
+        Some(values) => Ok((topic_columns.timestamps, values)),
+        None => Err(ULogError::TypeMismatch(format!(
+            "`{field}` of `{topic}` is `{base_type}`, not `{}`.",
+            T::BASE_TYPE
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::inst::FieldValue;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    #[test]
+    fn test_column() -> Result<(), ULogError> {
+        let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+        let (timestamps, z) = column::<f32>(input.as_slice(), "vehicle_local_position", "z")?;
+
+        let mut expected = (Vec::new(), Vec::new());
+        for data in ULogParserBuilder::new(input.as_slice())
+            .build()?
+            .data_only()
+            .filter(|data| {
+                data.as_ref()
+                    .map_or(true, |data| &*data.data.name == "vehicle_local_position")
+            })
+        {
+            let data = data?;
+            let z = data
+                .data
+                .fields
+                .iter()
+                .find(|f| f.name.as_ref() == "z")
+                .expect("z");
+            let FieldValue::ScalarF32(z) = z.value else {
+                panic!("z is a float");
+            };
+            expected.0.push(data.timestamp);
+            expected.1.push(z);
+        }
+        assert!(!timestamps.is_empty());
+        assert_eq!((timestamps, z), expected);
+
+        let (timestamps, values) = column::<f32>(input.as_slice(), "not_logged", "z")?;
+        assert!(timestamps.is_empty() && values.is_empty());
+
+        assert!(matches!(
+            column::<f32>(input.as_slice(), "vehicle_local_position", "nope"),
+            Err(ULogError::InvalidFieldName(_))
+        ));
+        assert!(matches!(
+            column::<f64>(input.as_slice(), "vehicle_local_position", "z"),
+            Err(ULogError::TypeMismatch(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_column_of_instance() -> Result<(), ULogError> {
+        let input = LogBuilder::new()
+            .topic(Topic::new("a").array_field("x", BaseType::INT16, 3, Generator::Constant(1.0)))
+            .topic(Topic::new("a").multi_id(1).array_field(
+                "x",
+                BaseType::INT16,
+                3,
+                Generator::Constant(2.0),
+            ))
+            .build()?;
+
+        let (timestamps, x) = column_of_instance::<i16>(input.as_slice(), "a", 1, "x[2]")?;
+        assert_eq!(timestamps.len(), x.len());
+        assert!(!x.is_empty() && x.iter().all(|&x| x == 2));
+
+        // An array must be indexed.
+        assert!(matches!(
+            column::<i16>(input.as_slice(), "a", "x"),
+            Err(ULogError::TypeMismatch(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan() -> Result<(), ULogError> {
+        let input = LogBuilder::new()
+            .topic(
+                Topic::new("a")
+                    .field("x", BaseType::FLOAT, Generator::Constant(1.5))
+                    .field("flag", BaseType::BOOL, Generator::Constant(1.0)),
+            )
+            .topic(Topic::new("b").array_field("y", BaseType::UINT16, 2, Generator::Constant(7.0)))
+            .build()?;
+
+        let plan = Plan::compile(["b/y[1]", "a/flag", "a/x", "a/x", "missing/z"])?;
+        let topics = plan.execute(input.as_slice())?;
+        let names: Vec<(&str, Vec<&str>)> = topics
+            .iter()
+            .map(|t| {
+                (
+                    t.topic.as_str(),
+                    t.columns.iter().map(|(name, _)| name.as_str()).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("b", vec!["y[1]"]),
+                ("a", vec!["flag", "x"]),
+                ("missing", vec![])
+            ]
+        );
+
+        let a = &topics[1];
+        assert!(!a.timestamps.is_empty());
+        assert_eq!(
+            f32::values(a.column("x").unwrap()),
+            Some(&vec![1.5; a.timestamps.len()][..])
+        );
+        assert_eq!(
+            a.column("flag"),
+            Some(&ColumnData::Bool(vec![true; a.timestamps.len()]))
+        );
+        assert_eq!(
+            topics[0].column("y[1]"),
+            Some(&ColumnData::UInt16(vec![7; topics[0].timestamps.len()]))
+        );
+        assert!(topics[2].timestamps.is_empty());
+
+        assert!(Plan::compile(["no_slash"]).is_err());
+        assert!(matches!(
+            Plan::compile(["b/y"])?.execute(input.as_slice()),
+            Err(ULogError::TypeMismatch(_))
+        ));
+
+        Ok(())
+    }
+}
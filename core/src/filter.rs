@@ -0,0 +1,312 @@
+//! Selects the subscriptions whose data a parser decodes, by topic name, topic instance, msg_id or any predicate,
+//! combined with `and()`, `or()`, `except()` and `!`.
+//!
+//! The data of the subscriptions left out is skipped without being parsed, as for
+//! `ULogParserBuilder::set_subscription_allow_list()`, which is the same as a `SubscriptionFilter::names()` filter.
+//!
+//! ```rust
+//! use yule_log::builder::ULogParserBuilder;
+//! use yule_log::filter::SubscriptionFilter;
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+//!
+//! // All vehicle_* topics except vehicle_air_data, plus the second instance of esc_status.
+//! let filter = SubscriptionFilter::predicate(|sub| sub.message_name.starts_with("vehicle_"))
+//!     .except(SubscriptionFilter::names(["vehicle_air_data"]))
+//!     .or(SubscriptionFilter::instances([("esc_status", 1)]));
+//!
+//! let parser = ULogParserBuilder::new(input.as_slice())
+//!     .subscription_filter(filter)
+//!     .build()?;
+//! # for msg in parser { msg?; }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::Not;
+use std::sync::Arc;
+
+use crate::model::msg::Subscription;
+
+/// A predicate deciding whether the data of a subscription is decoded, for `SubscriptionFilter::predicate()`.
+pub type SubscriptionPredicate = dyn Fn(&Subscription) -> bool + Send + Sync;
+
+#[derive(Clone)]
+enum Rule {
+    All,
+    Names(HashSet<String>),
+    Ids(HashSet<u16>),
+    Instances(HashSet<(String, u8)>),
+    Predicate(Arc<SubscriptionPredicate>),
+    Not(Box<Rule>),
+    And(Box<Rule>, Box<Rule>),
+    Or(Box<Rule>, Box<Rule>),
+}
+
+impl Rule {
+    fn matches(&self, sub: &Subscription) -> bool {
+        match self {
+            Rule::All => true,
+            Rule::Names(names) => names.contains(&sub.message_name),
+            Rule::Ids(ids) => ids.contains(&sub.msg_id),
+            Rule::Instances(instances) => instances
+                .iter()
+                .any(|(name, multi_id)| *name == sub.message_name && *multi_id == sub.multi_id),
+            Rule::Predicate(predicate) => predicate(sub),
+            Rule::Not(rule) => !rule.matches(sub),
+            Rule::And(a, b) => a.matches(sub) && b.matches(sub),
+            Rule::Or(a, b) => a.matches(sub) || b.matches(sub),
+        }
+    }
+
+    fn approx_memory_usage(&self) -> usize {
+        let set = |capacity: usize, size: usize| capacity * (size + 1);
+        match self {
+            Rule::All => 0,
+            Rule::Names(names) => {
+                set(names.capacity(), std::mem::size_of::<String>())
+                    + names.iter().map(String::capacity).sum::<usize>()
+            }
+            Rule::Ids(ids) => set(ids.capacity(), std::mem::size_of::<u16>()),
+            Rule::Instances(instances) => {
+                set(instances.capacity(), std::mem::size_of::<(String, u8)>())
+                    + instances
+                        .iter()
+                        .map(|(name, _)| name.capacity())
+                        .sum::<usize>()
+            }
+            Rule::Predicate(_) => std::mem::size_of::<Arc<SubscriptionPredicate>>(),
+            Rule::Not(rule) => std::mem::size_of::<Rule>() + rule.approx_memory_usage(),
+            Rule::And(a, b) | Rule::Or(a, b) => {
+                2 * std::mem::size_of::<Rule>() + a.approx_memory_usage() + b.approx_memory_usage()
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rule::All => write!(f, "All"),
+            Rule::Names(names) => f.debug_tuple("Names").field(names).finish(),
+            Rule::Ids(ids) => f.debug_tuple("Ids").field(ids).finish(),
+            Rule::Instances(instances) => f.debug_tuple("Instances").field(instances).finish(),
+            Rule::Predicate(_) => write!(f, "Predicate"),
+            Rule::Not(rule) => f.debug_tuple("Not").field(rule).finish(),
+            Rule::And(a, b) => f.debug_tuple("And").field(a).field(b).finish(),
+            Rule::Or(a, b) => f.debug_tuple("Or").field(a).field(b).finish(),
+        }
+    }
+}
+
+/// Which subscriptions' data the parser decodes.  See the module documentation.
+///
+/// Each subscription is matched once, when it is added, so a filter costs the same per data message however it is
+/// composed.  The default filter allows all subscriptions.
+#[derive(Clone, Debug)]
+pub struct SubscriptionFilter {
+    rule: Rule,
+    // The msg_ids of the current subscriptions which the rule allows, or `None` if it allows all.
+    allowed_ids: Option<HashSet<u16>>,
+}
+
+impl Default for SubscriptionFilter {
+    fn default() -> Self {
+        SubscriptionFilter::all()
+    }
+}
+
+impl SubscriptionFilter {
+    /// Allows the subscriptions of the topics named `subscr_names`, like `names()`.
+    pub fn new(subscr_names: impl IntoIterator<Item = String>) -> Self {
+        Self::names(subscr_names)
+    }
+
+    fn from_rule(rule: Rule) -> Self {
+        let allowed_ids = match rule {
+            Rule::All => None,
+            _ => Some(HashSet::new()),
+        };
+        SubscriptionFilter { rule, allowed_ids }
+    }
+
+    /// Allows all subscriptions.
+    pub fn all() -> Self {
+        Self::from_rule(Rule::All)
+    }
+
+    /// Allows the subscriptions of the topics named `names`, of all instances.
+    pub fn names<I, S>(names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self::from_rule(Rule::Names(names.into_iter().map(Into::into).collect()))
+    }
+
+    /// Allows the subscriptions with the msg_ids `ids`.  The msg_ids of a topic differ from log to log.
+    pub fn ids(ids: impl IntoIterator<Item = u16>) -> Self {
+        Self::from_rule(Rule::Ids(ids.into_iter().collect()))
+    }
+
+    /// Allows the given instances of topics, as `(name, multi_id)` pairs.
+    pub fn instances<I, S>(instances: I) -> Self
+    where
+        I: IntoIterator<Item = (S, u8)>,
+        S: Into<String>,
+    {
+        Self::from_rule(Rule::Instances(
+            instances
+                .into_iter()
+                .map(|(name, multi_id)| (name.into(), multi_id))
+                .collect(),
+        ))
+    }
+
+    /// Allows the subscriptions for which `predicate` returns true.
+    pub fn predicate(predicate: impl Fn(&Subscription) -> bool + Send + Sync + 'static) -> Self {
+        Self::from_rule(Rule::Predicate(Arc::new(predicate)))
+    }
+
+    /// Allows the subscriptions which both filters allow.
+    #[must_use]
+    pub fn and(self, other: SubscriptionFilter) -> Self {
+        Self::from_rule(Rule::And(Box::new(self.rule), Box::new(other.rule)))
+    }
+
+    /// Allows the subscriptions which either filter allows.
+    #[must_use]
+    pub fn or(self, other: SubscriptionFilter) -> Self {
+        Self::from_rule(Rule::Or(Box::new(self.rule), Box::new(other.rule)))
+    }
+
+    /// Allows the subscriptions which this filter allows and `other` doesn't.
+    #[must_use]
+    pub fn except(self, other: SubscriptionFilter) -> Self {
+        self.and(!other)
+    }
+
+    /// Whether the filter allows `sub`.
+    pub fn matches(&self, sub: &Subscription) -> bool {
+        self.rule.matches(sub)
+    }
+
+    pub(crate) fn update_ids(&mut self, sub: &Subscription) {
+        // Because msg_ids are not known ahead of time, the rule is matched against each AddSubscription message
+        // as it comes in, and the msg_ids it allows are kept to filter the data messages efficiently.
+        let allowed = self.rule.matches(sub);
+        if let Some(ids) = &mut self.allowed_ids {
+            if allowed {
+                ids.insert(sub.msg_id);
+            } else {
+                // The msg_id may be reused from an allowed subscription which has since been removed.
+                ids.remove(&sub.msg_id);
+            }
+        }
+    }
+
+    pub(crate) fn remove_id(&mut self, msg_id: u16) {
+        if let Some(ids) = &mut self.allowed_ids {
+            ids.remove(&msg_id);
+        }
+    }
+
+    pub(crate) fn clear_ids(&mut self) {
+        if let Some(ids) = &mut self.allowed_ids {
+            ids.clear();
+        }
+    }
+
+    pub(crate) fn is_allowed(&self, msg_id: u16) -> bool {
+        match &self.allowed_ids {
+            None => true,
+            Some(ids) => ids.contains(&msg_id),
+        }
+    }
+
+    pub(crate) fn approx_memory_usage(&self) -> usize {
+        let ids = self
+            .allowed_ids
+            .as_ref()
+            .map_or(0, |ids| ids.capacity() * (std::mem::size_of::<u16>() + 1));
+        self.rule.approx_memory_usage() + ids
+    }
+}
+
+impl Not for SubscriptionFilter {
+    type Output = SubscriptionFilter;
+
+    /// Allows the subscriptions which this filter doesn't.
+    fn not(self) -> Self::Output {
+        Self::from_rule(Rule::Not(Box::new(self.rule)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::errors::ULogError;
+    use crate::model::def::BaseType;
+    use crate::model::msg::UlogMessage;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    fn subscription(name: &str, msg_id: u16, multi_id: u8) -> Subscription {
+        Subscription {
+            multi_id,
+            msg_id,
+            message_name: name.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_combinators() {
+        let filter = SubscriptionFilter::predicate(|sub| sub.message_name.starts_with("vehicle_"))
+            .except(SubscriptionFilter::names(["vehicle_air_data"]))
+            .or(SubscriptionFilter::instances([("esc_status", 1)]));
+
+        assert!(filter.matches(&subscription("vehicle_status", 0, 0)));
+        assert!(!filter.matches(&subscription("vehicle_air_data", 1, 0)));
+        assert!(filter.matches(&subscription("esc_status", 2, 1)));
+        assert!(!filter.matches(&subscription("esc_status", 3, 0)));
+        assert!(!filter.matches(&subscription("sensor_combined", 4, 0)));
+
+        let ids = SubscriptionFilter::ids([4]);
+        assert!(ids.matches(&subscription("sensor_combined", 4, 0)));
+        assert!(!(!ids).matches(&subscription("sensor_combined", 4, 0)));
+        assert!(SubscriptionFilter::all().matches(&subscription("a", 5, 0)));
+        assert!(!SubscriptionFilter::names(Vec::<String>::new()).matches(&subscription("a", 5, 0)));
+    }
+
+    #[test]
+    fn test_parser_filter() -> Result<(), ULogError> {
+        let topic = |name: &str| {
+            Topic::new(name)
+                .rate_hz(1.0)
+                .field("x", BaseType::UINT8, Generator::Constant(1.0))
+        };
+        let bytes = LogBuilder::new()
+            .topic(topic("vehicle_status"))
+            .topic(topic("vehicle_air_data"))
+            .topic(topic("sensor_combined"))
+            .build()?;
+
+        let filter = SubscriptionFilter::predicate(|sub| sub.message_name.starts_with("vehicle_"))
+            .except(SubscriptionFilter::names(["vehicle_air_data"]));
+        let parser = ULogParserBuilder::new(bytes.as_slice())
+            .subscription_filter(filter)
+            .build()?;
+
+        let mut topics = HashSet::new();
+        for msg in parser {
+            if let UlogMessage::LoggedData(data) = msg? {
+                topics.insert(data.data.name.to_string());
+            }
+        }
+        assert_eq!(topics, HashSet::from(["vehicle_status".to_owned()]));
+        Ok(())
+    }
+}
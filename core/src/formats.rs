@@ -1,84 +1,318 @@
+//! Parsing of the format definitions of FORMAT messages, e.g. `vehicle_status:uint64_t timestamp;uint8_t[3] flags;`,
+//! and of their type expressions on their own, for tools working with definitions outside a log, such as those
+//! derived from `.msg` files.
+//!
+//! `parse_format_str()` and `parse_type_expr()` are stable: they accept at least what they accept now, and return
+//! `ULogError::ParseError` for what they reject, for all 0.x releases.  The text of the errors may change.
+//!
+//! ```rust
+//! use yule_log::formats::{parse_format_str, parse_type_expr};
+//! use yule_log::model::def::BaseType;
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let format = parse_format_str("vehicle_status:uint64_t timestamp;uint8_t[3] flags;")?;
+//! assert_eq!(format.fields[1].r#type, parse_type_expr("uint8_t[3]")?);
+//!
+//! let type_expr = parse_type_expr("esc_report[8]")?;
+//! assert_eq!(type_expr.base_type, BaseType::OTHER("esc_report".to_owned()));
+//! assert_eq!(type_expr.array_size, Some(8));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::diag;
 use crate::errors::ULogError;
 use crate::message_buf::MessageBuf;
 use crate::model::{def, Shared};
 use crate::tokenizer::Token;
 use crate::tokenizer::TokenList;
+use crate::validation::FormatIssue;
+
+/// Parses a format definition, as the contents of a FORMAT message: the name, a colon, then the fields, each a type
+/// expression and a name, separated by semicolons.
+///
+/// Whitespace, comments from `//` or `#` to the end of the line, and stray semicolons are allowed.  The returned
+/// format has no `padding`, which the parser only sets for formats of a log.
+pub fn parse_format_str(definition: &str) -> Result<def::Format, ULogError> {
+    parse_format_with(definition, false).map(|(format, _)| format)
+}
+
+/// Parses a type expression, e.g. `float`, `uint8_t[8]`, or the name of a format, `esc_report[8]`.
+pub fn parse_type_expr(type_expr: &str) -> Result<def::TypeExpr, ULogError> {
+    let mut token_list = TokenList::from_str(type_expr);
+    let parsed = parse_type(&mut token_list)?;
+    match token_list.peek() {
+        None => Ok(parsed),
+        Some(token) => Err(ULogError::ParseError(format!(
+            "Invalid type expression at column {}. Expected the end, got: {token:?}",
+            token_list.column()
+        ))),
+    }
+}
 
 pub(crate) fn parse_format(message_buf: MessageBuf) -> Result<def::Format, ULogError> {
-    let str_format = String::from_utf8(message_buf.into_remaining_bytes())?;
+    parse_format_str(&String::from_utf8(message_buf.into_remaining_bytes())?)
+}
 
-    let mut token_list = TokenList::from_str(&str_format);
-    log::trace!("token_list: {token_list:?}");
+/// Parses a format, leaving out the fields which are invalid rather than failing, and returns them as
+/// `FormatIssue`s with the format.  Only an invalid name is still an error.
+pub(crate) fn parse_format_recovering(
+    message_buf: MessageBuf,
+) -> Result<(def::Format, Vec<FormatIssue>), ULogError> {
+    parse_format_with(
+        &String::from_utf8(message_buf.into_remaining_bytes())?,
+        true,
+    )
+}
+
+fn parse_format_with(
+    str_format: &str,
+    recover: bool,
+) -> Result<(def::Format, Vec<FormatIssue>), ULogError> {
+    let mut token_list = TokenList::from_str(str_format);
+    diag::trace!("token_list: {token_list:?}");
 
+    let column = token_list.column();
     let name = match token_list.consume_two()? {
         (Token::Identifier(str), Token::Colon) => str,
         (token1, token2) => {
             return Err(ULogError::ParseError(format!(
-                "Invalid format name. Expected [Identifier, Colon], got: {token1:?}, {token2:?}"
+                "Invalid format name at column {column}. Expected [Identifier, Colon], got: {token1:?}, {token2:?}"
             )));
         }
     };
 
     let mut fields: Vec<def::Field> = vec![];
+    let mut issues: Vec<FormatIssue> = vec![];
 
+    // Stray and trailing semicolons are skipped, and the semicolon after the last field is optional.
+    token_list.skip_semicolons();
     while !token_list.is_empty() {
-        fields.push(parse_field(&mut token_list)?);
-
-        match token_list.consume_one()? {
-            Token::Semicolon => {}
-            token => {
-                Err(ULogError::ParseError(format!(
-                    "Invalid format definition. Expected a Semicolon, got: {token:?}"
-                )))?;
+        let start = token_list.column();
+        let mut field_tokens = token_list.take_until_semicolon();
+        let field = parse_field(&mut field_tokens).and_then(|field| match field_tokens.peek() {
+            None => Ok(field),
+            Some(token) => Err(ULogError::ParseError(format!(
+                "Invalid format definition at column {}. Expected a Semicolon, got: {token:?}",
+                field_tokens.column()
+            ))),
+        });
+
+        match field {
+            Ok(field) => fields.push(field),
+            Err(e) => {
+                let issue = FormatIssue {
+                    format: name.to_string(),
+                    span: start..token_list.column(),
+                    error: match e {
+                        ULogError::ParseError(error) => error,
+                        e => e.to_string(),
+                    },
+                };
+                if !recover {
+                    return Err(ULogError::ParseError(issue.to_string()));
+                }
+                diag::warn!("{issue} Leaving the field out.");
+                issues.push(issue);
             }
         }
+        token_list.skip_semicolons();
     }
 
-    Ok(def::Format {
+    let format = def::Format {
         name: name.into(),
         fields,
         padding: 0,
-    })
+    };
+    Ok((format, issues))
 }
 
 pub(crate) fn parse_field(token_list: &mut TokenList) -> Result<def::Field, ULogError> {
-    log::trace!("token_list: {token_list:?}");
+    diag::trace!("token_list: {token_list:?}");
 
+    let r#type = parse_type(token_list)?;
+
+    let column = token_list.column();
+    let field_name = match token_list.consume_one()? {
+        Token::Identifier(str) => str,
+        token => Err(ULogError::ParseError(format!(
+            "Invalid field definition at column {column}. Expected an Identifier, got: {token:?}"
+        )))?,
+    };
+
+    Ok(def::Field {
+        name: Shared::from(field_name.to_string()),
+        r#type,
+    })
+}
+
+fn parse_type(token_list: &mut TokenList) -> Result<def::TypeExpr, ULogError> {
+    let column = token_list.column();
     let base_type = match token_list.consume_one()? {
         Token::Identifier(type_name) => def::BaseType::from_string(type_name),
 
         token => Err(ULogError::ParseError(format!(
-            "Invalid field definition. Expected an Identifier, got: {token:?}"
+            "Invalid field definition at column {column}. Expected an Identifier, got: {token:?}"
         )))?,
     };
 
     let mut array_size: Option<usize> = None;
 
     if token_list.peek() == Some(&Token::LBrace) {
+        let column = token_list.column();
         array_size = match token_list.consume_three()? {
             (Token::LBrace, Token::Number(size), Token::RBrace) => { Some(size) }
             (token1, token2, token3) => { Err(ULogError::ParseError(format!(
-                "Invalid array definition. Expected [LBrace, Number, RBrace], got: [{token1:?}, {token2:?}, {token3:?}]"
+                "Invalid array definition at column {column}. Expected [LBrace, Number, RBrace], got: [{token1:?}, {token2:?}, {token3:?}]"
             )))? }
         }
     }
 
-    let field_name = match token_list.consume_one()? {
-        Token::Identifier(str) => str,
-        token => Err(ULogError::ParseError(format!(
-            "Invalid field definition. Expected an Identifier, got: {token:?}"
-        )))?,
-    };
-
-    Ok(def::Field {
-        name: Shared::from(field_name.to_string()),
-        r#type: def::TypeExpr {
-            base_type,
-            array_size,
-        },
+    Ok(def::TypeExpr {
+        base_type,
+        array_size,
     })
 }
 
+/// A set of formats, which can be resolved into the order they must be defined in, each after the formats nested in
+/// it, e.g. to be written by `ULogWriter::add_formats()`.
+///
+/// ```rust
+/// use yule_log::formats::{parse_format_str, FormatRegistry};
+///
+/// # fn main() -> Result<(), yule_log::errors::ULogError> {
+/// let registry: FormatRegistry = [
+///     parse_format_str("esc_status:uint64_t timestamp;esc_report[2] esc;")?,
+///     parse_format_str("esc_report:uint32_t esc_rpm;float esc_voltage;")?,
+/// ]
+/// .into_iter()
+/// .collect();
+///
+/// let names: Vec<_> = registry.resolve()?.iter().map(|format| format.name.to_string()).collect();
+/// assert_eq!(names, ["esc_report", "esc_status"]);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct FormatRegistry {
+    // In the order of insertion, which `resolve()` keeps where it can.
+    formats: Vec<def::Format>,
+}
+
+impl FormatRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `format`, returning the format of the same name it replaces, if any.
+    pub fn insert(&mut self, format: def::Format) -> Option<def::Format> {
+        match self.formats.iter_mut().find(|f| f.name == format.name) {
+            Some(existing) => Some(std::mem::replace(existing, format)),
+            None => {
+                self.formats.push(format);
+                None
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&def::Format> {
+        self.formats.iter().find(|f| &*f.name == name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.formats.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.formats.is_empty()
+    }
+
+    /// Returns the formats so that each follows the formats nested in it, and is otherwise in the order of insertion.
+    ///
+    /// Returns `ULogError::UnresolvedFormat` naming the field of the first nested format which isn't defined, or the
+    /// formats of a cycle, e.g. `a -> b -> a`.
+    pub fn resolve(&self) -> Result<Vec<&def::Format>, ULogError> {
+        #[derive(Clone, Copy, PartialEq)]
+        enum Mark {
+            New,
+            Visiting,
+            Done,
+        }
+
+        // Visits the format at `index` after those nested in it.  `path` holds the formats being visited.
+        fn visit<'a>(
+            registry: &'a FormatRegistry,
+            index: usize,
+            marks: &mut [Mark],
+            path: &mut Vec<usize>,
+            order: &mut Vec<&'a def::Format>,
+        ) -> Result<(), ULogError> {
+            let format = &registry.formats[index];
+            match marks[index] {
+                Mark::Done => return Ok(()),
+                Mark::Visiting => {
+                    let start = path.iter().position(|&i| i == index).unwrap_or(0);
+                    let cycle: Vec<&str> = path[start..]
+                        .iter()
+                        .chain([&index])
+                        .map(|&i| &*registry.formats[i].name)
+                        .collect();
+                    return Err(ULogError::UnresolvedFormat(format!(
+                        "The formats {} contain themselves.",
+                        cycle.join(" -> ")
+                    )));
+                }
+                Mark::New => {}
+            }
+
+            marks[index] = Mark::Visiting;
+            path.push(index);
+            for field in &format.fields {
+                if let def::BaseType::OTHER(type_name) = &field.r#type.base_type {
+                    let nested = registry
+                        .formats
+                        .iter()
+                        .position(|f| *f.name == **type_name)
+                        .ok_or_else(|| {
+                            ULogError::UnresolvedFormat(format!(
+                                "`{type_name}`, the type of `{}` of `{}`, isn't defined.",
+                                field.name, format.name
+                            ))
+                        })?;
+                    visit(registry, nested, marks, path, order)?;
+                }
+            }
+            path.pop();
+            marks[index] = Mark::Done;
+            order.push(format);
+            Ok(())
+        }
+
+        let mut marks = vec![Mark::New; self.formats.len()];
+        let mut order = Vec::with_capacity(self.formats.len());
+        for index in 0..self.formats.len() {
+            visit(self, index, &mut marks, &mut Vec::new(), &mut order)?;
+        }
+        Ok(order)
+    }
+}
+
+impl FromIterator<def::Format> for FormatRegistry {
+    fn from_iter<I: IntoIterator<Item = def::Format>>(formats: I) -> Self {
+        let mut registry = FormatRegistry::new();
+        registry.extend(formats);
+        registry
+    }
+}
+
+impl Extend<def::Format> for FormatRegistry {
+    fn extend<I: IntoIterator<Item = def::Format>>(&mut self, formats: I) {
+        for format in formats {
+            self.insert(format);
+        }
+    }
+}
+
 impl def::BaseType {
     pub fn from_string(string: &str) -> def::BaseType {
         match string {
@@ -167,4 +401,135 @@ mod tests {
 
         assert_eq!(re_emitted_bytes, input);
     }
+
+    #[test]
+    fn test_parse_format_tolerance() {
+        let input = b"my_format:  uint64_t timestamp ;; // annotation\n bool[ 2 ]\tflags; # more\nfloat x\0\0";
+        let format = parse_format(MessageBuf::from_vec(input.to_vec())).unwrap();
+
+        let mut re_emitted_bytes = Vec::new();
+        format.encode(&mut re_emitted_bytes).unwrap();
+        assert_eq!(
+            str::from_utf8(&re_emitted_bytes).unwrap(),
+            "my_format:uint64_t timestamp;bool[2] flags;float x;"
+        );
+
+        for (input, column) in [
+            ("my_format:uint64_t timestamp; bool ?;", 36),
+            ("my_format:uint64_t timestamp bool", 30),
+            ("my_format:uint8_t[x] y;", 18),
+            ("my_format:uint8_t", 18),
+            ("my_format uint8_t x;", 1),
+        ] {
+            let error = parse_format(MessageBuf::from_vec(input.as_bytes().to_vec())).unwrap_err();
+            assert!(
+                error.to_string().contains(&format!("at column {column}.")),
+                "{input}: {error}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_format_recovering() {
+        let input = b"my_format:uint64_t timestamp;uint8_t[ x;float ?;bool ok";
+
+        let error = parse_format(MessageBuf::from_vec(input.to_vec())).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("format `my_format` at columns 30..40"),
+            "{error}"
+        );
+
+        let (format, issues) =
+            parse_format_recovering(MessageBuf::from_vec(input.to_vec())).unwrap();
+        assert_eq!(
+            format
+                .fields
+                .iter()
+                .map(|f| f.name.as_ref())
+                .collect::<Vec<_>>(),
+            ["timestamp", "ok"]
+        );
+        assert_eq!(
+            issues
+                .iter()
+                .map(|i| (i.format.as_str(), i.span.clone()))
+                .collect::<Vec<_>>(),
+            [("my_format", 30..40), ("my_format", 41..48)]
+        );
+        assert!(issues[1].error.contains("at column 47."), "{}", issues[1]);
+
+        assert!(
+            parse_format_recovering(MessageBuf::from_vec(b"my_format uint8_t x;".to_vec()))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_type_expr() {
+        assert_eq!(
+            parse_type_expr(" uint16_t [ 4 ] ").unwrap(),
+            def::TypeExpr {
+                base_type: def::BaseType::UINT16,
+                array_size: Some(4),
+            }
+        );
+        assert!(parse_type_expr("uint16_t x").is_err());
+        assert!(parse_type_expr("uint16_t[]").is_err());
+        assert!(parse_type_expr("").is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let registry = |definitions: &[&str]| -> FormatRegistry {
+            definitions
+                .iter()
+                .map(|definition| parse_format_str(definition).unwrap())
+                .collect()
+        };
+        let names = |formats: Vec<&def::Format>| -> Vec<String> {
+            formats
+                .iter()
+                .map(|format| format.name.to_string())
+                .collect()
+        };
+
+        let formats = registry(&[
+            "top:uint64_t timestamp;b[2] x;a y;",
+            "other:uint64_t timestamp;",
+            "a:float v;",
+            "b:a inner;",
+        ]);
+        assert_eq!(
+            names(formats.resolve().unwrap()),
+            ["a", "b", "top", "other"]
+        );
+
+        let error = registry(&["top:uint64_t timestamp;missing x;"])
+            .resolve()
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unresolved format: `missing`, the type of `x` of `top`, isn't defined."
+        );
+
+        let error = registry(&["top:uint64_t timestamp;a x;", "a:b y;", "b:a z;"])
+            .resolve()
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unresolved format: The formats a -> b -> a contain themselves."
+        );
+
+        let mut formats = registry(&["a:float v;"]);
+        assert!(formats
+            .insert(parse_format_str("a:double v;").unwrap())
+            .is_some());
+        assert_eq!(formats.len(), 1);
+        assert_eq!(
+            formats.get("a").unwrap().fields[0].r#type.base_type,
+            def::BaseType::DOUBLE
+        );
+    }
 }
@@ -0,0 +1,341 @@
+//! Reads remote logs over HTTP, with range requests.
+//!
+//! `RemoteUlogReader` implements `Read` and `Seek`, fetching the log in blocks as they are read, and keeping the
+//! most recently used blocks in a cache.  A viewer which seeks back and forth over a log, e.g. to redraw a plot,
+//! only downloads each block once while it stays in the cache.
+//!
+//! ```rust,no_run
+//! use yule_log::builder::ULogParserBuilder;
+//! use yule_log::http::RemoteUlogReader;
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let reader = RemoteUlogReader::new("https://logs.example.com/flight.ulg")?;
+//! let parser = ULogParserBuilder::new(reader).build()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Seek, SeekFrom};
+
+use ureq::Agent;
+
+use crate::errors::ULogError;
+
+/// The default number of bytes fetched by each range request.
+pub const DEFAULT_BLOCK_SIZE: usize = 256 * 1024;
+
+/// The default number of blocks cached.
+pub const DEFAULT_CACHE_BLOCKS: usize = 64;
+
+/// A seekable reader over a log served by an HTTP server which supports range requests.
+pub struct RemoteUlogReader {
+    agent: Agent,
+    url: String,
+    size: u64,
+    position: u64,
+    block_size: usize,
+    cache: BlockCache,
+    bytes_fetched: u64,
+}
+
+impl RemoteUlogReader {
+    pub fn new(url: impl Into<String>) -> Result<Self, ULogError> {
+        Self::with_cache(url, DEFAULT_BLOCK_SIZE, DEFAULT_CACHE_BLOCKS)
+    }
+
+    /// Like `new()`, but fetches `block_size` bytes per request, and caches up to `cache_blocks` blocks.
+    pub fn with_cache(
+        url: impl Into<String>,
+        block_size: usize,
+        cache_blocks: usize,
+    ) -> Result<Self, ULogError> {
+        if block_size == 0 || cache_blocks == 0 {
+            return Err(ULogError::InvalidConfiguration(
+                "The block size and cache size must be greater than zero.".to_owned(),
+            ));
+        }
+
+        let mut reader = RemoteUlogReader {
+            agent: Agent::new_with_defaults(),
+            url: url.into(),
+            size: 0,
+            position: 0,
+            block_size,
+            cache: BlockCache::new(cache_blocks),
+            bytes_fetched: 0,
+        };
+
+        // The first block holds the header and usually the start of the definitions, so request it now, and
+        // read the size of the log from its `Content-Range`.
+        let (block, size) = reader.get_range(0, block_size as u64)?;
+        reader.size = size;
+        reader.cache.insert(0, block);
+
+        Ok(reader)
+    }
+
+    /// The size of the log.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The number of bytes fetched so far.
+    pub fn bytes_fetched(&self) -> u64 {
+        self.bytes_fetched
+    }
+
+    /// Requests the `len` bytes at `start`, and returns them with the size of the log.
+    fn get_range(&mut self, start: u64, len: u64) -> io::Result<(Vec<u8>, u64)> {
+        let range = format!("bytes={start}-{}", start + len - 1);
+        let mut response = self
+            .agent
+            .get(&self.url)
+            .header("Range", &range)
+            .call()
+            .map_err(ureq::Error::into_io)?;
+
+        if response.status() != 206 {
+            return Err(io::Error::other(format!(
+                "{} doesn't support range requests: status {}",
+                self.url,
+                response.status()
+            )));
+        }
+        let size = response
+            .headers()
+            .get("Content-Range")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.rsplit_once('/'))
+            .and_then(|(_, size)| size.parse().ok())
+            .ok_or_else(|| {
+                io::Error::other(format!("{} returned no Content-Range size", self.url))
+            })?;
+
+        let block = response
+            .body_mut()
+            .read_to_vec()
+            .map_err(ureq::Error::into_io)?;
+        self.bytes_fetched += block.len() as u64;
+        Ok((block, size))
+    }
+
+    fn block(&mut self, index: u64) -> io::Result<&[u8]> {
+        if !self.cache.contains(index) {
+            let start = index * self.block_size as u64;
+            let len = (self.size - start).min(self.block_size as u64);
+            let (block, _) = self.get_range(start, len)?;
+            self.cache.insert(index, block);
+        }
+        Ok(self.cache.get(index).expect("the block was just cached"))
+    }
+}
+
+impl Read for RemoteUlogReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.position >= self.size {
+            return Ok(0);
+        }
+
+        let block_size = self.block_size as u64;
+        let offset = (self.position % block_size) as usize;
+        let block = self.block(self.position / block_size)?;
+        if offset >= block.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "The server returned a short block.",
+            ));
+        }
+
+        let len = buf.len().min(block.len() - offset);
+        buf[..len].copy_from_slice(&block[offset..offset + len]);
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl Seek for RemoteUlogReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+
+        self.position = position.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Invalid seek to a negative or overflowing position.",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+/// A least recently used cache of blocks.  Caches are small, so the recency order is a plain queue.
+struct BlockCache {
+    capacity: usize,
+    blocks: HashMap<u64, Vec<u8>>,
+    // Block indices, from least to most recently used.
+    order: VecDeque<u64>,
+}
+
+impl BlockCache {
+    fn new(capacity: usize) -> Self {
+        BlockCache {
+            capacity,
+            blocks: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    fn contains(&self, index: u64) -> bool {
+        self.blocks.contains_key(&index)
+    }
+
+    fn get(&mut self, index: u64) -> Option<&[u8]> {
+        let block = self.blocks.get(&index)?;
+        if let Some(i) = self.order.iter().position(|&b| b == index) {
+            self.order.remove(i);
+        }
+        self.order.push_back(index);
+        Some(block)
+    }
+
+    fn insert(&mut self, index: u64, block: Vec<u8>) {
+        if self.blocks.len() >= self.capacity {
+            if let Some(evicted) = self.order.pop_front() {
+                self.blocks.remove(&evicted);
+            }
+        }
+        self.blocks.insert(index, block);
+        self.order.push_back(index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::model::def::BaseType;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    /// Serves `bytes` with range requests on a local port, and counts the requests.
+    fn serve(bytes: Vec<u8>) -> io::Result<(String, Arc<AtomicUsize>)> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/flight.ulg", listener.local_addr()?);
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        let counter = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let bytes = bytes.clone();
+                let counter = counter.clone();
+                thread::spawn(move || -> io::Result<()> {
+                    let mut reader = BufReader::new(stream.try_clone()?);
+                    let mut stream = stream;
+                    loop {
+                        let mut range = None;
+                        let mut line = String::new();
+                        loop {
+                            line.clear();
+                            if reader.read_line(&mut line)? == 0 {
+                                return Ok(());
+                            }
+                            if line == "\r\n" {
+                                break;
+                            }
+                            if let Some(value) =
+                                line.to_ascii_lowercase().strip_prefix("range: bytes=")
+                            {
+                                let (start, end) = value.trim().split_once('-').unwrap();
+                                range = Some((
+                                    start.parse::<usize>().unwrap(),
+                                    end.parse::<usize>().unwrap(),
+                                ));
+                            }
+                        }
+
+                        counter.fetch_add(1, Ordering::Relaxed);
+                        let (start, end) = range.unwrap();
+                        let end = end.min(bytes.len() - 1);
+                        let body = &bytes[start..=end];
+                        write!(
+                            stream,
+                            "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {start}-{end}/{}\r\nContent-Length: {}\r\n\r\n",
+                            bytes.len(),
+                            body.len()
+                        )?;
+                        stream.write_all(body)?;
+                    }
+                });
+            }
+        });
+
+        Ok((url, requests))
+    }
+
+    #[test]
+    fn test_remote_reader() -> Result<(), ULogError> {
+        let bytes = LogBuilder::new()
+            .topic(Topic::new("a").field(
+                "x",
+                BaseType::FLOAT,
+                Generator::Ramp {
+                    start: 0.0,
+                    step: 1.0,
+                },
+            ))
+            .build()?;
+        let (url, requests) = serve(bytes.clone())?;
+
+        let mut reader = RemoteUlogReader::with_cache(url, 64, 4)?;
+        assert_eq!(reader.size(), bytes.len() as u64);
+
+        let expected: Vec<_> = ULogParserBuilder::new(bytes.as_slice())
+            .build()?
+            .collect::<Result<_, _>>()?;
+        let read: Vec<_> = ULogParserBuilder::new(&mut reader)
+            .build()?
+            .collect::<Result<_, _>>()?;
+        assert_eq!(format!("{read:?}"), format!("{expected:?}"));
+        assert_eq!(reader.bytes_fetched(), bytes.len() as u64);
+
+        // The last block is cached, so reading it again makes no request.
+        let fetched = requests.load(Ordering::Relaxed);
+        reader.seek(SeekFrom::End(-8))?;
+        let mut tail = Vec::new();
+        reader.read_to_end(&mut tail)?;
+        assert_eq!(tail, bytes[bytes.len() - 8..]);
+        assert_eq!(requests.load(Ordering::Relaxed), fetched);
+
+        // The first block was evicted, so it is fetched again.
+        reader.seek(SeekFrom::Start(0))?;
+        let mut magic = [0u8; 7];
+        reader.read_exact(&mut magic)?;
+        assert_eq!(&magic, b"ULog\x01\x12\x35");
+        assert_eq!(requests.load(Ordering::Relaxed), fetched + 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_block_cache() {
+        let mut cache = BlockCache::new(2);
+        cache.insert(0, vec![0]);
+        cache.insert(1, vec![1]);
+        assert!(cache.get(0).is_some());
+
+        // Block 1 is the least recently used.
+        cache.insert(2, vec![2]);
+        assert!(cache.contains(0));
+        assert!(!cache.contains(1));
+        assert!(cache.contains(2));
+    }
+}
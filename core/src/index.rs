@@ -0,0 +1,650 @@
+//! Indexes the data messages of a log by topic and timestamp, so that parsing can start at any time without
+//! reading the log from the start.
+//!
+//! Building the index reads the whole log once.  It can be saved to a sidecar index file (.uli), and loaded
+//! when the log is reopened, so that seeking to a time in a multi-GB log doesn't rescan it:
+//!
+//! ```rust
+//! use std::fs::File;
+//! use std::io::BufReader;
+//!
+//! use yule_log::index::ULogIndexedReader;
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! # let dir = tempfile::tempdir()?;
+//! # let index_path = dir.path().join("sample_log_small.uli");
+//! let log = BufReader::new(File::open("test_data/input/sample_log_small.ulg")?);
+//!
+//! // Scans the log, and saves the index, or loads the index saved before.
+//! let mut reader = ULogIndexedReader::open_with_index(log, &index_path)?;
+//!
+//! let start = reader.index().topics()[0].first_timestamp().unwrap_or(0);
+//! for msg in reader.parser_at(start + 1_000_000)? {
+//!     msg?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The index file starts with the magic `ULogIdx` and a version byte, followed by the size, file header
+//! timestamp and data section offset of the log, the subscription changes, and for each topic instance, the
+//! timestamp and offset of each of its data messages.  All values are little endian.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::builder::ULogParserBuilder;
+use crate::errors::ULogError;
+use crate::formats::parse_format;
+use crate::message_buf::MessageBuf;
+use crate::model::msg::Subscription;
+use crate::model::{def, Shared};
+use crate::parser::{ULogMessageType, ULogParser};
+
+const INDEX_MAGIC: &[u8; 7] = b"ULogIdx";
+const INDEX_VERSION: u8 = 1;
+
+/// The data messages of a topic instance, in the order logged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopicIndex {
+    pub name: String,
+    pub multi_id: u8,
+    // (timestamp, offset of the message in the log)
+    entries: Vec<(u64, u64)>,
+}
+
+impl TopicIndex {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn first_timestamp(&self) -> Option<u64> {
+        self.entries.first().map(|&(timestamp, _)| timestamp)
+    }
+
+    pub fn last_timestamp(&self) -> Option<u64> {
+        self.entries.last().map(|&(timestamp, _)| timestamp)
+    }
+
+    /// The average rate of the messages, from the first to the last, or `None` with fewer than two messages or no
+    /// time between them.
+    pub fn mean_rate_hz(&self) -> Option<f64> {
+        let first = self.first_timestamp()?;
+        let last = self.last_timestamp()?;
+        if last <= first {
+            return None;
+        }
+        Some((self.len() - 1) as f64 * 1e6 / (last - first) as f64)
+    }
+
+    pub fn info(&self) -> TopicInfo {
+        TopicInfo {
+            name: self.name.clone(),
+            multi_id: self.multi_id,
+            count: self.len(),
+            first_timestamp: self.first_timestamp(),
+            last_timestamp: self.last_timestamp(),
+            mean_rate_hz: self.mean_rate_hz(),
+        }
+    }
+
+    /// Returns the offset of the first message logged at or after `timestamp`, assuming the timestamps of the
+    /// topic are monotonic.
+    pub fn offset_at(&self, timestamp: u64) -> Option<u64> {
+        let index = self.entries.partition_point(|&(t, _)| t < timestamp);
+        self.entries.get(index).map(|&(_, offset)| offset)
+    }
+
+    /// Returns the number of messages logged from `start` to `end` inclusive, assuming the timestamps of the
+    /// topic are monotonic.
+    pub fn count_between(&self, start: u64, end: u64) -> usize {
+        let first = self.entries.partition_point(|&(t, _)| t < start);
+        let last = self.entries.partition_point(|&(t, _)| t <= end);
+        last.saturating_sub(first)
+    }
+}
+
+/// The columns which log viewers list topic instances with.  See `ULogIndex::topic_infos()`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopicInfo {
+    pub name: String,
+    pub multi_id: u8,
+    /// The number of data messages.
+    pub count: usize,
+    pub first_timestamp: Option<u64>,
+    pub last_timestamp: Option<u64>,
+    /// See `TopicIndex::mean_rate_hz()`.
+    pub mean_rate_hz: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SubscriptionChange {
+    Add(Subscription),
+    Remove(u16),
+}
+
+/// The index of a log.  See the module documentation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ULogIndex {
+    file_size: u64,
+    header_timestamp: u64,
+    data_offset: u64,
+    end_offset: u64,
+    // The subscription changes in the data section, with their offsets, in the order logged.
+    subscriptions: Vec<(u64, SubscriptionChange)>,
+    topics: Vec<TopicIndex>,
+}
+
+impl ULogIndex {
+    /// Reads the whole log, from the current position of `reader`, which must be the start of the log.
+    pub fn build<R: Read>(reader: R) -> Result<Self, ULogError> {
+        let mut messages = ULogParserBuilder::new(reader)
+            .include_header(true)
+            .build()?
+            .raw_messages();
+
+        let mut index = ULogIndex {
+            file_size: 0,
+            header_timestamp: 0,
+            data_offset: 0,
+            end_offset: 0,
+            subscriptions: Vec::new(),
+            topics: Vec::new(),
+        };
+        let mut formats: HashMap<Shared<str>, Shared<def::Format>> = HashMap::new();
+        let mut topics: HashMap<(String, u8), usize> = HashMap::new();
+        // msg_id -> (topic, offset of the timestamp in the message)
+        let mut subscribed: HashMap<u16, (usize, Option<usize>)> = HashMap::new();
+
+        loop {
+            let offset = messages.position() as u64;
+            let Some(message) = messages.next() else {
+                break;
+            };
+            let (header, contents) = message?;
+
+            match header.msg_type {
+                ULogMessageType::HEADER => {
+                    index.header_timestamp = LittleEndian::read_u64(&contents[8..16])
+                }
+                ULogMessageType::FORMAT => {
+                    let format = parse_format(MessageBuf::from_vec(contents.to_vec()))?;
+                    formats.insert(format.name.clone(), Shared::new(format));
+                }
+                ULogMessageType::ADD_SUBSCRIPTION if contents.len() >= 3 => {
+                    let sub = Subscription {
+                        multi_id: contents[0],
+                        msg_id: LittleEndian::read_u16(&contents[1..3]),
+                        message_name: String::from_utf8(contents[3..].to_vec())?,
+                    };
+                    if index.subscriptions.is_empty() {
+                        index.data_offset = offset;
+                    }
+
+                    let key = (sub.message_name.clone(), sub.multi_id);
+                    let topic = *topics.entry(key).or_insert_with(|| {
+                        index.topics.push(TopicIndex {
+                            name: sub.message_name.clone(),
+                            multi_id: sub.multi_id,
+                            entries: Vec::new(),
+                        });
+                        index.topics.len() - 1
+                    });
+                    let timestamp_offset = formats
+                        .get(sub.message_name.as_str())
+                        .and_then(|format| timestamp_offset(format, &formats));
+                    subscribed.insert(sub.msg_id, (topic, timestamp_offset));
+
+                    index
+                        .subscriptions
+                        .push((offset, SubscriptionChange::Add(sub)));
+                }
+                ULogMessageType::REMOVE_SUBSCRIPTION if contents.len() >= 2 => {
+                    let msg_id = LittleEndian::read_u16(&contents[0..2]);
+                    subscribed.remove(&msg_id);
+                    index
+                        .subscriptions
+                        .push((offset, SubscriptionChange::Remove(msg_id)));
+                }
+                ULogMessageType::DATA if contents.len() >= 2 => {
+                    let msg_id = LittleEndian::read_u16(&contents[0..2]);
+                    if let Some(&(topic, Some(timestamp_offset))) = subscribed.get(&msg_id) {
+                        let start = 2 + timestamp_offset;
+                        if let Some(bytes) = contents.get(start..start + 8) {
+                            index.topics[topic]
+                                .entries
+                                .push((LittleEndian::read_u64(bytes), offset));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        index.end_offset = messages.position() as u64;
+        index.file_size = index.end_offset;
+        Ok(index)
+    }
+
+    /// The offset of the first subscription, where the data section starts.
+    pub fn data_offset(&self) -> u64 {
+        self.data_offset
+    }
+
+    /// The topic instances, in the order of their first subscription.
+    pub fn topics(&self) -> &[TopicIndex] {
+        &self.topics
+    }
+
+    /// Summarizes the topic instances, in the order of `topics()`, from the index built in one pass over the log.
+    pub fn topic_infos(&self) -> Vec<TopicInfo> {
+        self.topics.iter().map(TopicIndex::info).collect()
+    }
+
+    pub fn topic(&self, name: &str, multi_id: u8) -> Option<&TopicIndex> {
+        self.topics
+            .iter()
+            .find(|t| t.name == name && t.multi_id == multi_id)
+    }
+
+    /// Returns the offset of the first data message of any topic logged at or after `timestamp`.
+    pub fn offset_at(&self, timestamp: u64) -> Option<u64> {
+        self.topics
+            .iter()
+            .filter_map(|topic| topic.offset_at(timestamp))
+            .min()
+    }
+
+    /// Returns the subscriptions active at `offset`.
+    fn subscriptions_at(&self, offset: u64) -> Vec<Subscription> {
+        let mut active: Vec<Subscription> = Vec::new();
+        for (_, change) in self.subscriptions.iter().take_while(|(o, _)| *o < offset) {
+            match change {
+                SubscriptionChange::Add(sub) => {
+                    active.retain(|s| s.msg_id != sub.msg_id);
+                    active.push(sub.clone());
+                }
+                SubscriptionChange::Remove(msg_id) => active.retain(|s| s.msg_id != *msg_id),
+            }
+        }
+        active
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ULogError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ULogError> {
+        Self::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    fn write(&self, writer: &mut impl Write) -> Result<(), ULogError> {
+        writer.write_all(INDEX_MAGIC)?;
+        writer.write_u8(INDEX_VERSION)?;
+        writer.write_u64::<LittleEndian>(self.file_size)?;
+        writer.write_u64::<LittleEndian>(self.header_timestamp)?;
+        writer.write_u64::<LittleEndian>(self.data_offset)?;
+        writer.write_u64::<LittleEndian>(self.end_offset)?;
+
+        writer.write_u32::<LittleEndian>(self.subscriptions.len() as u32)?;
+        for (offset, change) in &self.subscriptions {
+            writer.write_u64::<LittleEndian>(*offset)?;
+            match change {
+                SubscriptionChange::Add(sub) => {
+                    writer.write_u8(b'A')?;
+                    writer.write_u16::<LittleEndian>(sub.msg_id)?;
+                    writer.write_u8(sub.multi_id)?;
+                    write_string(writer, &sub.message_name)?;
+                }
+                SubscriptionChange::Remove(msg_id) => {
+                    writer.write_u8(b'R')?;
+                    writer.write_u16::<LittleEndian>(*msg_id)?;
+                }
+            }
+        }
+
+        writer.write_u32::<LittleEndian>(self.topics.len() as u32)?;
+        for topic in &self.topics {
+            write_string(writer, &topic.name)?;
+            writer.write_u8(topic.multi_id)?;
+            writer.write_u64::<LittleEndian>(topic.entries.len() as u64)?;
+            for &(timestamp, offset) in &topic.entries {
+                writer.write_u64::<LittleEndian>(timestamp)?;
+                writer.write_u64::<LittleEndian>(offset)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read(reader: &mut impl Read) -> Result<Self, ULogError> {
+        read_index(reader).map_err(|e| match e {
+            ULogError::Io(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                ULogError::InvalidIndex("The index file is truncated.".to_owned())
+            }
+            e => e,
+        })
+    }
+}
+
+fn read_index(reader: &mut impl Read) -> Result<ULogIndex, ULogError> {
+    let mut magic = [0u8; 7];
+    reader.read_exact(&mut magic)?;
+    if &magic != INDEX_MAGIC {
+        return Err(ULogError::InvalidIndex("Not a ULOG index file.".to_owned()));
+    }
+    let version = reader.read_u8()?;
+    if version != INDEX_VERSION {
+        return Err(ULogError::InvalidIndex(format!(
+            "Unsupported index version {version}."
+        )));
+    }
+
+    let mut index = ULogIndex {
+        file_size: reader.read_u64::<LittleEndian>()?,
+        header_timestamp: reader.read_u64::<LittleEndian>()?,
+        data_offset: reader.read_u64::<LittleEndian>()?,
+        end_offset: reader.read_u64::<LittleEndian>()?,
+        subscriptions: Vec::new(),
+        topics: Vec::new(),
+    };
+
+    for _ in 0..reader.read_u32::<LittleEndian>()? {
+        let offset = reader.read_u64::<LittleEndian>()?;
+        let change = match reader.read_u8()? {
+            b'A' => {
+                let msg_id = reader.read_u16::<LittleEndian>()?;
+                let multi_id = reader.read_u8()?;
+                SubscriptionChange::Add(Subscription {
+                    multi_id,
+                    msg_id,
+                    message_name: read_string(reader)?,
+                })
+            }
+            b'R' => SubscriptionChange::Remove(reader.read_u16::<LittleEndian>()?),
+            kind => {
+                return Err(ULogError::InvalidIndex(format!(
+                    "Unknown subscription change 0x{kind:02X}."
+                )))
+            }
+        };
+        index.subscriptions.push((offset, change));
+    }
+
+    for _ in 0..reader.read_u32::<LittleEndian>()? {
+        let name = read_string(reader)?;
+        let multi_id = reader.read_u8()?;
+        let len = reader.read_u64::<LittleEndian>()?;
+        // The length isn't trusted for the allocation, so a corrupt file can't exhaust memory.
+        let mut entries = Vec::with_capacity(len.min(1 << 16) as usize);
+        for _ in 0..len {
+            entries.push((
+                reader.read_u64::<LittleEndian>()?,
+                reader.read_u64::<LittleEndian>()?,
+            ));
+        }
+        index.topics.push(TopicIndex {
+            name,
+            multi_id,
+            entries,
+        });
+    }
+
+    Ok(index)
+}
+
+fn write_string(writer: &mut impl Write, s: &str) -> Result<(), ULogError> {
+    let len = u16::try_from(s.len())
+        .map_err(|_| ULogError::InvalidIndex(format!("The topic name `{s}` is too long.")))?;
+    writer.write_u16::<LittleEndian>(len)?;
+    writer.write_all(s.as_bytes())?;
+    Ok(())
+}
+
+fn read_string(reader: &mut impl Read) -> Result<String, ULogError> {
+    let mut bytes = vec![0u8; usize::from(reader.read_u16::<LittleEndian>()?)];
+    reader.read_exact(&mut bytes)?;
+    Ok(String::from_utf8(bytes)?)
+}
+
+/// Returns the offset of the `timestamp` field in a message with `format`, or `None` if it has none.
+fn timestamp_offset(
+    format: &def::Format,
+    formats: &HashMap<Shared<str>, Shared<def::Format>>,
+) -> Option<usize> {
+    let mut offset = 0;
+    for field in &format.fields {
+        if field.name.as_ref() == "timestamp" {
+            return matches!(field.r#type.base_type, def::BaseType::UINT64).then_some(offset);
+        }
+        offset += field.r#type.byte_size(formats).ok()?;
+    }
+    None
+}
+
+/// A seekable log with an index, from which parsing can start at any time.
+pub struct ULogIndexedReader<R: Read + Seek> {
+    reader: R,
+    index: ULogIndex,
+}
+
+impl<R: Read + Seek> ULogIndexedReader<R> {
+    /// Indexes the log, reading it from the start.
+    pub fn open(mut reader: R) -> Result<Self, ULogError> {
+        reader.seek(SeekFrom::Start(0))?;
+        let mut index = ULogIndex::build(&mut reader)?;
+        // The log may continue past the indexed messages, with appended data.
+        index.file_size = reader.seek(SeekFrom::End(0))?;
+        Ok(ULogIndexedReader { reader, index })
+    }
+
+    /// Loads the index of the log from `index_path` if it was saved there for the same log, or otherwise
+    /// indexes the log and saves the index to `index_path`.
+    pub fn open_with_index(mut reader: R, index_path: impl AsRef<Path>) -> Result<Self, ULogError> {
+        let index_path = index_path.as_ref();
+
+        match ULogIndex::load(index_path) {
+            Ok(index) if Self::matches(&mut reader, &index)? => {
+                Ok(ULogIndexedReader { reader, index })
+            }
+            Ok(_) | Err(ULogError::InvalidIndex(_)) => Self::reindex(reader, index_path),
+            Err(ULogError::Io(e)) if e.kind() == ErrorKind::NotFound => {
+                Self::reindex(reader, index_path)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn reindex(reader: R, index_path: &Path) -> Result<Self, ULogError> {
+        let reader = Self::open(reader)?;
+        reader.save_index(index_path)?;
+        Ok(reader)
+    }
+
+    /// Checks that `index` was built for the log, by its size and the timestamp of its file header.
+    fn matches(reader: &mut R, index: &ULogIndex) -> Result<bool, ULogError> {
+        let size = reader.seek(SeekFrom::End(0))?;
+        let mut header = [0u8; 16];
+        reader.seek(SeekFrom::Start(0))?;
+        if reader.read_exact(&mut header).is_err() {
+            return Ok(false);
+        }
+
+        Ok(size == index.file_size
+            && LittleEndian::read_u64(&header[8..16]) == index.header_timestamp)
+    }
+
+    pub fn save_index(&self, path: impl AsRef<Path>) -> Result<(), ULogError> {
+        self.index.save(path)
+    }
+
+    pub fn index(&self) -> &ULogIndex {
+        &self.index
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Returns a parser which starts at the first data message of any topic logged at or after `timestamp`.
+    /// See `parser_at_with()`.
+    pub fn parser_at(&mut self, timestamp: u64) -> Result<ULogParser<&mut R>, ULogError> {
+        self.parser_at_with(timestamp, |builder| builder)
+    }
+
+    /// Like `parser_at()`, but configures the parser with `configure`, e.g. to set a subscription allow list.
+    ///
+    /// The definitions section is parsed, but its messages aren't returned.  Messages of other topics logged
+    /// slightly before `timestamp` may follow the first message returned, as topics are interleaved.
+    pub fn parser_at_with<'a, F>(
+        &'a mut self,
+        timestamp: u64,
+        configure: F,
+    ) -> Result<ULogParser<&'a mut R>, ULogError>
+    where
+        F: FnOnce(ULogParserBuilder<&'a mut R>) -> ULogParserBuilder<&'a mut R>,
+    {
+        let offset = self
+            .index
+            .offset_at(timestamp)
+            .unwrap_or(self.index.end_offset);
+        let subscriptions = self.index.subscriptions_at(offset);
+
+        self.reader.seek(SeekFrom::Start(0))?;
+        let mut parser = configure(ULogParserBuilder::new(&mut self.reader).skip_by_seeking()).build()?;
+
+        // Read up to the first subscription, which starts the data section.
+        while parser.next().transpose()?.is_some() {
+            if parser.in_data_section() {
+                parser.resume_at(offset as usize, &subscriptions)?;
+                break;
+            }
+        }
+
+        Ok(parser)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::model::def::BaseType;
+    use crate::model::msg::UlogMessage;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    fn log(start: f64) -> Result<Vec<u8>, ULogError> {
+        LogBuilder::new()
+            .info("sys_name", "PX4")
+            .topic(Topic::new("a").field(
+                "x",
+                BaseType::FLOAT,
+                Generator::Ramp { start, step: 1.0 },
+            ))
+            .topic(Topic::new("b").rate_hz(20.0).field(
+                "y",
+                BaseType::UINT8,
+                Generator::Constant(1.0),
+            ))
+            .build()
+    }
+
+    fn logged_data<R: Read>(parser: ULogParser<R>) -> Result<Vec<(String, u64)>, ULogError> {
+        let mut logged = Vec::new();
+        for msg in parser {
+            if let UlogMessage::LoggedData(data) = msg? {
+                logged.push((data.data.name.to_string(), data.timestamp));
+            }
+        }
+        Ok(logged)
+    }
+
+    #[test]
+    fn test_index() -> Result<(), ULogError> {
+        let bytes = log(0.0)?;
+        let mut reader = ULogIndexedReader::open(Cursor::new(bytes.clone()))?;
+
+        let index = reader.index();
+        assert_eq!(index.topics().len(), 2);
+        let a = index.topic("a", 0).unwrap();
+        assert_eq!(a.len(), 10);
+        assert_eq!(a.first_timestamp(), Some(1_000_000));
+        assert_eq!(a.last_timestamp(), Some(1_900_000));
+        assert_eq!(index.topic("b", 0).unwrap().len(), 20);
+
+        let infos = index.topic_infos();
+        assert_eq!(
+            infos[1],
+            TopicInfo {
+                name: "b".to_owned(),
+                multi_id: 0,
+                count: 20,
+                first_timestamp: Some(1_000_000),
+                last_timestamp: Some(1_950_000),
+                mean_rate_hz: Some(20.0),
+            }
+        );
+
+        let all = logged_data(ULogParserBuilder::new(bytes.as_slice()).build()?)?;
+        let from = logged_data(reader.parser_at(1_500_000)?)?;
+        assert_eq!(from[0], ("a".to_string(), 1_500_000));
+        assert_eq!(from, all[all.len() - from.len()..]);
+
+        // The allow list is applied after seeking.
+        let from = logged_data(
+            reader.parser_at_with(1_500_000, |b| b.set_subscription_allow_list(["b"]))?,
+        )?;
+        assert_eq!(from.len(), 10);
+        assert!(from.iter().all(|(name, _)| name == "b"));
+
+        assert!(logged_data(reader.parser_at(5_000_000)?)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_file() -> Result<(), ULogError> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("log.uli");
+        let bytes = log(0.0)?;
+
+        let reader = ULogIndexedReader::open_with_index(Cursor::new(bytes.clone()), &path)?;
+        assert_eq!(ULogIndex::load(&path)?, *reader.index());
+
+        // The saved index is loaded, and is used to seek.
+        let mut reloaded = ULogIndexedReader::open_with_index(Cursor::new(bytes.clone()), &path)?;
+        assert_eq!(reloaded.index(), reader.index());
+        assert_eq!(logged_data(reloaded.parser_at(1_900_000)?)?.len(), 3);
+
+        // The index of a different log is rebuilt.
+        let mut modified = log(1.0)?;
+        modified.extend([0; 4]);
+        let reindexed = ULogIndexedReader::open_with_index(Cursor::new(modified.clone()), &path)?;
+        assert_eq!(reindexed.index().file_size, modified.len() as u64);
+
+        std::fs::write(&path, b"ULogIdx\x09")?;
+        assert!(matches!(
+            ULogIndex::load(&path),
+            Err(ULogError::InvalidIndex(_))
+        ));
+        std::fs::write(&path, &b"ULogIdx\x01"[..])?;
+        assert!(matches!(
+            ULogIndex::load(&path),
+            Err(ULogError::InvalidIndex(_))
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,252 @@
+//! Aligns the samples of several topics in one pass, holding the latest sample of each topic until the next.
+//!
+//! `aligned()` emits a row each time one of the topics is logged, once all of them have been, with the latest
+//! sample of every topic.  Samples logged within `tolerance_us` of each other are grouped into one row, so that
+//! topics published together don't each produce a row.
+//!
+//! ```rust
+//! use yule_log::builder::ULogParserBuilder;
+//! use yule_log::join;
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+//! let parser = ULogParserBuilder::new(input.as_slice()).build()?;
+//!
+//! for row in join::aligned(parser, ["vehicle_status", "actuator_outputs"], 1_000) {
+//!     let row = row?;
+//!     println!("{} {} {}", row.timestamp, row.samples[0].name, row.samples[1].name);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::errors::ULogError;
+use crate::export::{column_names, for_each_scalar};
+use crate::model::msg::{LoggedData, UlogMessage};
+use crate::model::{inst, Shared};
+
+/// The latest sample of each topic at a point in time.
+#[derive(Debug, Clone)]
+pub struct AlignedRow {
+    /// The timestamp of the last sample grouped into the row.
+    pub timestamp: u64,
+    /// The latest sample of each topic, in the order the topics were given.
+    pub samples: Vec<Shared<inst::Format>>,
+}
+
+impl AlignedRow {
+    /// The value of a scalar column of the `topic`th sample, named as exported, e.g. `q[0]` or `setpoint.vx`.
+    pub fn value(&self, topic: usize, column: &str) -> Option<f64> {
+        let sample = self.samples.get(topic)?;
+        let position = column_names(sample)
+            .iter()
+            .position(|(name, _)| name == column)?;
+        let mut values = Vec::new();
+        for_each_scalar(sample, &mut |value| values.push(value.as_f64()));
+        values.get(position).copied().flatten()
+    }
+}
+
+// Groups the samples of the topics into rows, for `Aligned` and `derived::Derived`.
+#[derive(Debug)]
+pub(crate) struct Aligner {
+    // (topic, multi_id)
+    topics: Vec<(String, u8)>,
+    tolerance_us: u64,
+    latest: Vec<Option<Shared<inst::Format>>>,
+    // The timestamps of the first and last samples of the row being grouped.
+    pending: Option<(u64, u64)>,
+}
+
+impl Aligner {
+    pub(crate) fn new<T, S>(topics: T, tolerance_us: u64) -> Self
+    where
+        T: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let topics: Vec<_> = topics
+            .into_iter()
+            .map(|topic| parse_topic(topic.as_ref()))
+            .collect();
+        Aligner {
+            latest: vec![None; topics.len()],
+            topics,
+            tolerance_us,
+            pending: None,
+        }
+    }
+
+    // The index of the topic of `data`, if it is one of the topics.
+    pub(crate) fn position(&self, data: &LoggedData) -> Option<usize> {
+        let multi_id = data.data.multi_id_index.unwrap_or(0);
+        self.topics
+            .iter()
+            .position(|(name, id)| *name == *data.data.name && *id == multi_id)
+    }
+
+    // Holds the sample of the `index`th topic, and returns the previous row if the sample starts a new one.
+    pub(crate) fn push(
+        &mut self,
+        index: usize,
+        timestamp: u64,
+        sample: inst::Format,
+    ) -> Option<AlignedRow> {
+        let row = match self.pending {
+            Some((first, last)) if timestamp <= first.saturating_add(self.tolerance_us) => {
+                self.pending = Some((first, last.max(timestamp)));
+                None
+            }
+            Some((_, last)) => {
+                self.pending = Some((timestamp, timestamp));
+                self.row(last)
+            }
+            None => {
+                self.pending = Some((timestamp, timestamp));
+                None
+            }
+        };
+        self.latest[index] = Some(Shared::new(sample));
+        row
+    }
+
+    // Returns the last row, at the end of the messages.
+    pub(crate) fn finish(&mut self) -> Option<AlignedRow> {
+        let (_, last) = self.pending.take()?;
+        self.row(last)
+    }
+
+    // The row of the held samples, unless a topic hasn't been logged yet.
+    fn row(&self, timestamp: u64) -> Option<AlignedRow> {
+        let samples = self.latest.iter().cloned().collect::<Option<Vec<_>>>()?;
+        Some(AlignedRow { timestamp, samples })
+    }
+}
+
+/// The iterator returned by `aligned()`.
+#[derive(Debug)]
+pub struct Aligned<I> {
+    messages: I,
+    aligner: Aligner,
+}
+
+/// Aligns the samples of `topics`, each a topic name followed by `.NN` for a multi_id other than 0, e.g.
+/// `actuator_outputs.01`.  See the module documentation.
+pub fn aligned<I, T, S>(messages: I, topics: T, tolerance_us: u64) -> Aligned<I::IntoIter>
+where
+    I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+    T: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    Aligned {
+        messages: messages.into_iter(),
+        aligner: Aligner::new(topics, tolerance_us),
+    }
+}
+
+fn parse_topic(topic: &str) -> (String, u8) {
+    let instance = topic
+        .rsplit_once('.')
+        .and_then(|(name, multi_id)| Some((name, multi_id.parse().ok()?)));
+    match instance {
+        Some((name, multi_id)) => (name.to_owned(), multi_id),
+        None => (topic.to_owned(), 0),
+    }
+}
+
+impl<I> Iterator for Aligned<I>
+where
+    I: Iterator<Item = Result<UlogMessage, ULogError>>,
+{
+    type Item = Result<AlignedRow, ULogError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let data = match self.messages.next() {
+                None => return self.aligner.finish().map(Ok),
+                Some(Err(err)) => return Some(Err(err)),
+                Some(Ok(UlogMessage::LoggedData(data))) => data,
+                Some(Ok(_)) => continue,
+            };
+
+            if let Some(index) = self.aligner.position(&data) {
+                if let Some(row) = self.aligner.push(index, data.timestamp, data.data) {
+                    return Some(Ok(row));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::model::def::BaseType;
+    use crate::model::inst::FieldValue;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    fn value(format: &inst::Format) -> f32 {
+        match format.fields[0].value {
+            FieldValue::ScalarF32(v) => v,
+            ref other => panic!("Unexpected value {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_aligned() -> Result<(), ULogError> {
+        let bytes = LogBuilder::new()
+            .duration(Duration::from_millis(500))
+            .topic(Topic::new("fast").rate_hz(10.0).field(
+                "x",
+                BaseType::FLOAT,
+                Generator::Ramp {
+                    start: 0.0,
+                    step: 1.0,
+                },
+            ))
+            .topic(Topic::new("slow").multi_id(1).rate_hz(5.0).field(
+                "y",
+                BaseType::FLOAT,
+                Generator::Ramp {
+                    start: 10.0,
+                    step: 1.0,
+                },
+            ))
+            .build()?;
+        let parser = ULogParserBuilder::new(bytes.as_slice()).build()?;
+
+        let rows = aligned(parser, ["fast", "slow.01"], 0).collect::<Result<Vec<_>, _>>()?;
+        let rows: Vec<_> = rows
+            .iter()
+            .map(|row| {
+                (
+                    row.timestamp,
+                    value(&row.samples[0]),
+                    value(&row.samples[1]),
+                )
+            })
+            .collect();
+        // `slow` is held between its samples.
+        assert_eq!(
+            rows,
+            [
+                (1_000_000, 0.0, 10.0),
+                (1_100_000, 1.0, 10.0),
+                (1_200_000, 2.0, 11.0),
+                (1_300_000, 3.0, 11.0),
+                (1_400_000, 4.0, 12.0),
+            ]
+        );
+
+        // Samples within 150 ms of the first of a row are grouped into it.
+        let parser = ULogParserBuilder::new(bytes.as_slice()).build()?;
+        assert_eq!(aligned(parser, ["fast", "slow.01"], 150_000).count(), 3);
+
+        let parser = ULogParserBuilder::new(bytes.as_slice()).build()?;
+        assert_eq!(aligned(parser, ["fast", "slow"], 0).count(), 0);
+
+        Ok(())
+    }
+}
@@ -1,17 +1,63 @@
 #![allow(clippy::needless_return)]
+pub mod aggregate;
+pub mod analysis;
+pub mod batch;
+pub mod bitfield;
+#[cfg(feature = "thread_safe")]
+pub mod bridge;
 pub mod builder;
+pub mod cache;
+pub mod cancel;
+pub mod compat;
+#[cfg(feature = "dev_corpus")]
+pub mod corpus;
+#[cfg(feature = "compression")]
+pub mod compression;
+#[cfg(feature = "crypto")]
+pub mod crypto;
 pub mod datastream;
+pub mod decoder;
+pub mod derived;
+mod diag;
 mod display;
 pub mod encode;
 pub mod errors;
+pub mod export;
+pub mod extract;
 mod field_helpers;
-mod formats;
+pub mod filter;
+pub mod formats;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod index;
+pub mod join;
 pub mod message_buf;
+pub mod metadata;
+pub mod metrics;
 pub mod model;
 #[allow(clippy::redundant_else)]
 pub mod parser;
+#[cfg(feature = "pipeline")]
+pub mod pipeline;
+#[cfg(feature = "px4_topics")]
+pub mod px4_topics;
+pub mod replay;
+#[cfg(feature = "object_store")]
+pub mod remote;
 mod roundtrip_test;
+pub mod sink;
+pub mod state;
+#[cfg(feature = "serve")]
+pub mod serve;
+pub mod tail;
+pub mod testkit;
+pub mod timeseries;
+pub mod timesync;
 mod tokenizer;
+pub mod validation;
+#[cfg(feature = "viz")]
+pub mod viz;
+pub mod writer;
 
 #[cfg(feature = "macros")]
 pub use yule_log_macros::{ULogData, ULogMessages};
@@ -1,6 +1,6 @@
-use crate::model::CChar;
+use crate::model::{CChar, CCharSlice};
 use crate::errors::ULogError;
-use crate::model::inst;
+use crate::model::{def, inst};
 
 /// Trait for deriving accessors for logged data.
 pub trait ULogAccessorFactory {
@@ -94,6 +94,34 @@ impl_fromfield_array!(f64, ArrayF64);
 impl_fromfield_array!(bool, ArrayBool);
 impl_fromfield_array!(CChar, ArrayChar);
 
+/// Reads a `char` array as a string without its trailing NULs.  Invalid UTF-8 is replaced with U+FFFD.
+impl FromField for String {
+    fn from_field(field: &inst::Field) -> Result<Self, ULogError> {
+        match &field.value {
+            inst::FieldValue::ArrayChar(chars) => Ok(chars.to_string_lossy_trimmed()),
+            other => Err(ULogError::TypeMismatch(format!(
+                "Expected String but got {:?}",
+                other
+            ))),
+        }
+    }
+}
+
+impl<T, const N: usize> FromField for [T; N]
+where
+    Vec<T>: FromField,
+{
+    fn from_field(field: &inst::Field) -> Result<Self, ULogError> {
+        Vec::<T>::from_field(field)?.try_into().map_err(|values: Vec<T>| {
+            ULogError::TypeMismatch(format!(
+                "Expected {N} elements for field {}, but found {}",
+                field.name,
+                values.len()
+            ))
+        })
+    }
+}
+
 impl<T> FromField for Vec<T>
 where
     T: ULogAccessorFactory,
@@ -138,3 +166,169 @@ where
         }
     }
 }
+
+// --- Writing ---
+
+/// Trait for writing a struct as the data of a ULOG subscription, derived by `#[derive(ULogData)]`.
+///
+/// The format of a struct lists its fields in declaration order.  When written as a top level message,
+/// a `uint64_t timestamp` field is prepended unless the struct already has a `timestamp` field.
+pub trait ULogEncode {
+    /// The name of the format, which is also the subscription name.
+    const FORMAT_NAME: &'static str;
+
+    /// The `multi_id` of the subscription.
+    const MULTI_ID: u8;
+
+    /// Returns the format of this struct.
+    fn ulog_format(top_level: bool) -> Result<def::Format, ULogError>;
+
+    /// Appends the formats of the structs nested within this one to `formats`, innermost first.
+    fn ulog_nested_formats(formats: &mut Vec<def::Format>) -> Result<(), ULogError>;
+
+    /// Encodes the fields of this struct.  A top level message is encoded with `Some(timestamp)`,
+    /// which replaces the value of any `timestamp` field.
+    fn encode_fields(&self, timestamp: Option<u64>, buf: &mut Vec<u8>);
+
+    /// Encodes zeros in place of the fields of this struct, for a nested optional field which is `None`.
+    fn encode_zeroed_fields(buf: &mut Vec<u8>);
+}
+
+/// IntoField
+///
+/// The counterpart of `FromField`, which provides the ULOG type of a struct field
+/// and encodes its value, so that the #[derive(ULogData)] proc macro can generate
+/// the format definition and LOGGED_DATA messages of the annotated struct.
+///
+/// `Vec` fields can't be written, because the format must declare a fixed array size.  Use `[T; N]` instead.
+pub trait IntoField {
+    fn type_expr() -> Result<def::TypeExpr, ULogError>;
+
+    /// Appends the formats of any structs nested within this field to `formats`, innermost first.
+    fn nested_formats(_formats: &mut Vec<def::Format>) -> Result<(), ULogError> {
+        Ok(())
+    }
+
+    fn encode_field(&self, buf: &mut Vec<u8>);
+
+    /// Encodes zeros in place of a value, for an optional field which is `None`.
+    fn encode_zeroed(buf: &mut Vec<u8>);
+}
+
+macro_rules! impl_intofield_scalar {
+    ($ty:ty, $base_type:ident, |$v:ident| $bytes:expr) => {
+        impl IntoField for $ty {
+            fn type_expr() -> Result<def::TypeExpr, ULogError> {
+                Ok(def::TypeExpr {
+                    base_type: def::BaseType::$base_type,
+                    array_size: None,
+                })
+            }
+
+            fn encode_field(&self, buf: &mut Vec<u8>) {
+                let $v = *self;
+                buf.extend_from_slice(&$bytes);
+            }
+
+            fn encode_zeroed(buf: &mut Vec<u8>) {
+                buf.extend_from_slice(&[0; std::mem::size_of::<$ty>()]);
+            }
+        }
+    };
+}
+
+impl_intofield_scalar!(u8, UINT8, |v| v.to_le_bytes());
+impl_intofield_scalar!(u16, UINT16, |v| v.to_le_bytes());
+impl_intofield_scalar!(u32, UINT32, |v| v.to_le_bytes());
+impl_intofield_scalar!(u64, UINT64, |v| v.to_le_bytes());
+impl_intofield_scalar!(i8, INT8, |v| v.to_le_bytes());
+impl_intofield_scalar!(i16, INT16, |v| v.to_le_bytes());
+impl_intofield_scalar!(i32, INT32, |v| v.to_le_bytes());
+impl_intofield_scalar!(i64, INT64, |v| v.to_le_bytes());
+impl_intofield_scalar!(f32, FLOAT, |v| v.to_le_bytes());
+impl_intofield_scalar!(f64, DOUBLE, |v| v.to_le_bytes());
+impl_intofield_scalar!(bool, BOOL, |v| [u8::from(v)]);
+impl_intofield_scalar!(CChar, CHAR, |v| [v.0]);
+
+impl<T: IntoField, const N: usize> IntoField for [T; N] {
+    fn type_expr() -> Result<def::TypeExpr, ULogError> {
+        let element = T::type_expr()?;
+        if element.is_array() {
+            return Err(ULogError::TypeMismatch(format!(
+                "Nested arrays of {element} can't be written to a ULOG format."
+            )));
+        }
+
+        Ok(def::TypeExpr {
+            array_size: Some(N),
+            ..element
+        })
+    }
+
+    fn nested_formats(formats: &mut Vec<def::Format>) -> Result<(), ULogError> {
+        T::nested_formats(formats)
+    }
+
+    fn encode_field(&self, buf: &mut Vec<u8>) {
+        for value in self {
+            value.encode_field(buf);
+        }
+    }
+
+    fn encode_zeroed(buf: &mut Vec<u8>) {
+        for _ in 0..N {
+            T::encode_zeroed(buf);
+        }
+    }
+}
+
+impl<T> IntoField for Vec<T> {
+    fn type_expr() -> Result<def::TypeExpr, ULogError> {
+        Err(ULogError::TypeMismatch(
+            "Vec fields have no fixed size, so can't be written to a ULOG format.  Use an array [T; N] instead."
+                .to_string(),
+        ))
+    }
+
+    // Unreachable, as a struct with a `Vec` field has no format to write data for.
+    fn encode_field(&self, _buf: &mut Vec<u8>) {}
+
+    fn encode_zeroed(_buf: &mut Vec<u8>) {}
+}
+
+impl IntoField for String {
+    fn type_expr() -> Result<def::TypeExpr, ULogError> {
+        Err(ULogError::TypeMismatch(
+            "String fields have no fixed size, so can't be written to a ULOG format.  Use an array [CChar; N] instead."
+                .to_string(),
+        ))
+    }
+
+    // Unreachable, as a struct with a `String` field has no format to write data for.
+    fn encode_field(&self, _buf: &mut Vec<u8>) {}
+
+    fn encode_zeroed(_buf: &mut Vec<u8>) {}
+}
+
+impl<T: ULogEncode> IntoField for T {
+    fn type_expr() -> Result<def::TypeExpr, ULogError> {
+        Ok(def::TypeExpr {
+            base_type: def::BaseType::OTHER(T::FORMAT_NAME.to_string()),
+            array_size: None,
+        })
+    }
+
+    fn nested_formats(formats: &mut Vec<def::Format>) -> Result<(), ULogError> {
+        T::ulog_nested_formats(formats)?;
+        formats.push(T::ulog_format(false)?);
+        Ok(())
+    }
+
+    fn encode_field(&self, buf: &mut Vec<u8>) {
+        self.encode_fields(None, buf);
+    }
+
+    fn encode_zeroed(buf: &mut Vec<u8>) {
+        T::encode_zeroed_fields(buf);
+    }
+}
@@ -5,6 +5,7 @@ use std::mem::size_of;
 use byteorder::{ByteOrder, LittleEndian};
 
 use crate::errors::ULogError;
+use crate::model::CChar;
 
 /// `MessageBuf` wraps a vector of bytes and allows the user to
 /// successively take values from it without manually calculating
@@ -48,6 +49,96 @@ pub struct MessageBuf {
     current_index: usize,
 }
 
+/// A value of a ULOG primitive type, which `MessageBuf::take()` and `MessageBuf::take_array()` read.
+pub trait ULogPrimitive: Sized + Copy {
+    /// The size of the value in a message, in bytes.
+    const SIZE: usize;
+
+    /// Converts the `SIZE` little endian bytes of a value.
+    fn from_le_bytes(bytes: &[u8]) -> Self;
+
+    /// Converts consecutive values, whose bytes are a multiple of `SIZE` long.
+    fn from_le_slice(bytes: &[u8]) -> Vec<Self> {
+        bytes
+            .chunks_exact(Self::SIZE)
+            .map(Self::from_le_bytes)
+            .collect()
+    }
+}
+
+impl ULogPrimitive for u8 {
+    const SIZE: usize = 1;
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        bytes[0]
+    }
+
+    fn from_le_slice(bytes: &[u8]) -> Vec<Self> {
+        bytes.to_vec()
+    }
+}
+
+impl ULogPrimitive for i8 {
+    const SIZE: usize = 1;
+
+    #[allow(clippy::cast_possible_wrap)]
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        bytes[0] as i8
+    }
+}
+
+/// A value of `0` is `false`, and any other value is `true`.
+impl ULogPrimitive for bool {
+    const SIZE: usize = 1;
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        bytes[0] != 0
+    }
+}
+
+impl ULogPrimitive for CChar {
+    const SIZE: usize = 1;
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        CChar(bytes[0])
+    }
+}
+
+macro_rules! impl_ulog_primitive {
+    ($($type:ty => $read:ident, $read_into:ident;)*) => {
+        $(
+            impl ULogPrimitive for $type {
+                const SIZE: usize = size_of::<$type>();
+
+                fn from_le_bytes(bytes: &[u8]) -> Self {
+                    LittleEndian::$read(bytes)
+                }
+
+                fn from_le_slice(bytes: &[u8]) -> Vec<Self> {
+                    let mut values = vec![<$type>::default(); bytes.len() / Self::SIZE];
+                    LittleEndian::$read_into(bytes, &mut values);
+                    values
+                }
+            }
+        )*
+    };
+}
+
+impl_ulog_primitive! {
+    u16 => read_u16, read_u16_into;
+    i16 => read_i16, read_i16_into;
+    u32 => read_u32, read_u32_into;
+    i32 => read_i32, read_i32_into;
+    u64 => read_u64, read_u64_into;
+    i64 => read_i64, read_i64_into;
+    f32 => read_f32, read_f32_into;
+    f64 => read_f64, read_f64_into;
+}
+
+/// A position in a `MessageBuf`, returned by `MessageBuf::checkpoint()`, which `MessageBuf::rollback()` returns to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint(usize);
+
 impl MessageBuf {
     /// Creates a new `MessageBuf` from the provided byte vector.
     ///
@@ -115,6 +206,53 @@ impl MessageBuf {
         self.buf[self.current_index..].to_vec()
     }
 
+    // Returns all the bytes of the buffer, including those already taken.
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+
+    // All the bytes of the buffer, including those already taken.
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Takes the next value of a primitive type from the buffer and advances the index.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the next value or an error message if
+    /// the buffer is exhausted.
+    pub fn take<T: ULogPrimitive>(&mut self) -> Result<T, ULogError> {
+        self.advance(T::SIZE).map(T::from_le_bytes)
+    }
+
+    /// Takes the next `len` values of a primitive type from the buffer, converted in bulk, and advances the index.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the values or an error message if
+    /// the buffer has fewer than `len` values remaining.
+    ///
+    /// ```rust
+    /// use yule_log::message_buf::MessageBuf;
+    ///
+    /// let mut message_buf = MessageBuf::from_vec(vec![0x01, 0x00, 0x02, 0x00, 0xFF]);
+    /// assert_eq!(message_buf.take_array::<u16>(2).unwrap(), [1, 2]);
+    /// assert!(message_buf.take_array::<u16>(1).is_err());
+    /// assert_eq!(message_buf.take::<i8>().unwrap(), -1);
+    /// ```
+    pub fn take_array<T: ULogPrimitive>(&mut self, len: usize) -> Result<Vec<T>, ULogError> {
+        let size = len.checked_mul(T::SIZE).ok_or_else(|| {
+            ULogError::ParseError(format!("MessageBuf: Array of {len} values is too large"))
+        })?;
+        self.advance(size).map(T::from_le_slice)
+    }
+
+    /// Returns the next value of a primitive type from the buffer without advancing the index.
+    pub fn peek_value<T: ULogPrimitive>(&self) -> Result<T, ULogError> {
+        self.peek(T::SIZE).map(T::from_le_bytes)
+    }
+
     /// Takes the next `u8` value from the buffer and advances the index.
     ///
     /// # Returns
@@ -122,7 +260,7 @@ impl MessageBuf {
     /// A `Result` containing the next `u8` value or an error message if
     /// the buffer is exhausted.
     pub fn take_u8(&mut self) -> Result<u8, ULogError> {
-        self.advance(size_of::<u8>()).map(|bytes| bytes[0])
+        self.take()
     }
 
     /// Takes the next `i8` value from the buffer and advances the index.
@@ -131,9 +269,8 @@ impl MessageBuf {
     ///
     /// A `Result` containing the next `i8` value or an error message if
     /// the buffer is exhausted.
-    #[allow(clippy::cast_possible_wrap)]
     pub fn take_i8(&mut self) -> Result<i8, ULogError> {
-        self.advance(size_of::<i8>()).map(|bytes| bytes[0] as i8)
+        self.take()
     }
 
     /// Takes the next `u16` value (in little-endian format) from the buffer
@@ -144,7 +281,7 @@ impl MessageBuf {
     /// A `Result` containing the next `u16` value or an error message if
     /// the buffer is exhausted.
     pub fn take_u16(&mut self) -> Result<u16, ULogError> {
-        self.advance(size_of::<u16>()).map(LittleEndian::read_u16)
+        self.take()
     }
 
     /// Takes the next `i16` value (in little-endian format) from the buffer
@@ -155,7 +292,7 @@ impl MessageBuf {
     /// A `Result` containing the next `i16` value or an error message if
     /// the buffer is exhausted.
     pub fn take_i16(&mut self) -> Result<i16, ULogError> {
-        self.advance(size_of::<i16>()).map(LittleEndian::read_i16)
+        self.take()
     }
 
     /// Takes the next `u32` value (in little-endian format) from the buffer
@@ -166,7 +303,7 @@ impl MessageBuf {
     /// A `Result` containing the next `u32` value or an error message if
     /// the buffer is exhausted.
     pub fn take_u32(&mut self) -> Result<u32, ULogError> {
-        self.advance(size_of::<u32>()).map(LittleEndian::read_u32)
+        self.take()
     }
 
     /// Takes the next `i32` value (in little-endian format) from the buffer
@@ -177,7 +314,7 @@ impl MessageBuf {
     /// A `Result` containing the next `i32` value or an error message if
     /// the buffer is exhausted.
     pub fn take_i32(&mut self) -> Result<i32, ULogError> {
-        self.advance(size_of::<i32>()).map(LittleEndian::read_i32)
+        self.take()
     }
 
     /// Takes the next `u64` value (in little-endian format) from the buffer
@@ -188,7 +325,7 @@ impl MessageBuf {
     /// A `Result` containing the next `u64` value or an error message if
     /// the buffer is exhausted.
     pub fn take_u64(&mut self) -> Result<u64, ULogError> {
-        self.advance(size_of::<u64>()).map(LittleEndian::read_u64)
+        self.take()
     }
 
     /// Takes the next `i64` value (in little-endian format) from the buffer
@@ -199,7 +336,7 @@ impl MessageBuf {
     /// A `Result` containing the next `i64` value or an error message if
     /// the buffer is exhausted.
     pub fn take_i64(&mut self) -> Result<i64, ULogError> {
-        self.advance(size_of::<i64>()).map(LittleEndian::read_i64)
+        self.take()
     }
 
     /// Takes the next `f32` value (in little-endian format) from the buffer
@@ -210,7 +347,7 @@ impl MessageBuf {
     /// A `Result` containing the next `f32` value or an error message if
     /// the buffer is exhausted.
     pub fn take_f32(&mut self) -> Result<f32, ULogError> {
-        self.advance(size_of::<f32>()).map(LittleEndian::read_f32)
+        self.take()
     }
 
     /// Takes the next `f64` value (in little-endian format) from the buffer
@@ -221,7 +358,7 @@ impl MessageBuf {
     /// A `Result` containing the next `f64` value or an error message if
     /// the buffer is exhausted.
     pub fn take_f64(&mut self) -> Result<f64, ULogError> {
-        self.advance(size_of::<f64>()).map(LittleEndian::read_f64)
+        self.take()
     }
 
     /// Takes the next `bool` value from the buffer and advances the index.
@@ -232,21 +369,71 @@ impl MessageBuf {
     /// A `Result` containing the next `bool` value or an error message if
     /// the buffer is exhausted.
     pub fn take_bool(&mut self) -> Result<bool, ULogError> {
-        self.take_u8().map(|val| val != 0)
+        self.take()
     }
 
-    /// Advances the internal index by the given size and returns the
-    /// corresponding byte slice from the buffer.
-    ///
-    /// # Arguments
-    ///
-    /// * `size` - The number of bytes to advance and return.
+    /// Returns the next `u8` value from the buffer without advancing the index.
+    pub fn peek_u8(&self) -> Result<u8, ULogError> {
+        self.peek_value()
+    }
+
+    /// Returns the next `i8` value from the buffer without advancing the index.
+    pub fn peek_i8(&self) -> Result<i8, ULogError> {
+        self.peek_value()
+    }
+
+    /// Returns the next `u16` value (in little-endian format) from the buffer without advancing the index.
+    pub fn peek_u16(&self) -> Result<u16, ULogError> {
+        self.peek_value()
+    }
+
+    /// Returns the next `i16` value (in little-endian format) from the buffer without advancing the index.
+    pub fn peek_i16(&self) -> Result<i16, ULogError> {
+        self.peek_value()
+    }
+
+    /// Returns the next `u32` value (in little-endian format) from the buffer without advancing the index.
+    pub fn peek_u32(&self) -> Result<u32, ULogError> {
+        self.peek_value()
+    }
+
+    /// Returns the next `i32` value (in little-endian format) from the buffer without advancing the index.
+    pub fn peek_i32(&self) -> Result<i32, ULogError> {
+        self.peek_value()
+    }
+
+    /// Returns the next `u64` value (in little-endian format) from the buffer without advancing the index.
+    pub fn peek_u64(&self) -> Result<u64, ULogError> {
+        self.peek_value()
+    }
+
+    /// Returns the next `i64` value (in little-endian format) from the buffer without advancing the index.
+    pub fn peek_i64(&self) -> Result<i64, ULogError> {
+        self.peek_value()
+    }
+
+    /// Returns the next `f32` value (in little-endian format) from the buffer without advancing the index.
+    pub fn peek_f32(&self) -> Result<f32, ULogError> {
+        self.peek_value()
+    }
+
+    /// Returns the next `f64` value (in little-endian format) from the buffer without advancing the index.
+    pub fn peek_f64(&self) -> Result<f64, ULogError> {
+        self.peek_value()
+    }
+
+    /// Returns the next `bool` value from the buffer without advancing the index.
+    pub fn peek_bool(&self) -> Result<bool, ULogError> {
+        self.peek_value()
+    }
+
+    /// Returns the next `size` bytes of the buffer without advancing the index.
     ///
     /// # Returns
     ///
     /// A `Result` containing a reference to the next slice of bytes or an
     /// error message if there are not enough remaining bytes in the buffer.
-    pub fn advance(&mut self, size: usize) -> Result<&[u8], ULogError> {
+    pub fn peek(&self, size: usize) -> Result<&[u8], ULogError> {
         if self.current_index + size > self.buf.len() {
             Err(ULogError::ParseError(format!(
                 "MessageBuf: Out of bounds: tried to read {} bytes, but only {} remaining",
@@ -254,10 +441,73 @@ impl MessageBuf {
                 self.buf.len() - self.current_index
             )))
         } else {
-            let bytes = &self.buf[self.current_index..self.current_index + size];
-            self.current_index += size;
-            Ok(bytes)
+            Ok(&self.buf[self.current_index..self.current_index + size])
+        }
+    }
+
+    /// Returns the current position, for `rollback()` to return to.
+    ///
+    /// A decoder can try to parse the buffer one way, and if that fails, roll back and try another, without
+    /// cloning the buffer:
+    ///
+    /// ```rust
+    /// use yule_log::message_buf::MessageBuf;
+    ///
+    /// let mut message_buf = MessageBuf::from_vec(vec![0x01, 0x02, 0x03]);
+    /// assert_eq!(message_buf.peek_u16().unwrap(), 0x0201);
+    ///
+    /// let checkpoint = message_buf.checkpoint();
+    /// assert!(message_buf.take_u16().is_ok());
+    /// assert!(message_buf.take_u16().is_err());
+    ///
+    /// message_buf.rollback(checkpoint);
+    /// assert_eq!(message_buf.take_u8().unwrap(), 0x01);
+    /// assert_eq!(message_buf.len(), 2);
+    /// ```
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.current_index)
+    }
+
+    /// Returns to a position returned by `checkpoint()`, so that the values taken since are taken again.
+    ///
+    /// The checkpoint must have come from this buffer.  One from another buffer is clamped to the end of this one.
+    pub fn rollback(&mut self, checkpoint: Checkpoint) {
+        self.current_index = checkpoint.0.min(self.buf.len());
+    }
+
+    /// Calls `parse` with the buffer, and rolls back to where it started if `parse` fails.
+    ///
+    /// # Returns
+    ///
+    /// The result of `parse`.
+    pub fn attempt<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ULogError>,
+    ) -> Result<T, ULogError> {
+        let checkpoint = self.checkpoint();
+        let result = parse(self);
+        if result.is_err() {
+            self.rollback(checkpoint);
         }
+        result
+    }
+
+    /// Advances the internal index by the given size and returns the
+    /// corresponding byte slice from the buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The number of bytes to advance and return.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a reference to the next slice of bytes or an
+    /// error message if there are not enough remaining bytes in the buffer.
+    pub fn advance(&mut self, size: usize) -> Result<&[u8], ULogError> {
+        let start = self.current_index;
+        self.peek(size)?;
+        self.current_index += size;
+        Ok(&self.buf[start..start + size])
     }
 
     /// Skips the specified number of bytes in the buffer by advancing
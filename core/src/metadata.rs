@@ -0,0 +1,259 @@
+//! What kind of log a log is: the vehicle and system which logged it, whether it was logged from boot or while armed,
+//! with which logging profile, and whether it is a replay log.
+//!
+//! PX4 records the system in info messages, the airframe and MAVLink system id in the `SYS_AUTOSTART` and
+//! `MAV_SYS_ID` parameters, and the logging mode and profile in the `SDLOG_MODE` and `SDLOG_PROFILE` parameters.  A log written by PX4's replay, which runs the estimator again over the sensor data of another log, has
+//! a `replay` info message naming the original log.
+//!
+//! ```rust
+//! use yule_log::builder::ULogParserBuilder;
+//! use yule_log::metadata::LogMetadata;
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+//! let parser = ULogParserBuilder::new(input.as_slice()).build()?;
+//!
+//! let metadata = LogMetadata::read(parser)?;
+//! println!("{:?} {:?}, replay: {}", metadata.sys_name, metadata.logging_mode, metadata.is_replay());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::errors::ULogError;
+use crate::model::inst::{FieldValue, ParameterValue};
+use crate::model::msg::UlogMessage;
+use crate::model::CCharSlice;
+
+/// The info key of a replay log, naming the log replayed.
+pub const REPLAY_KEY: &str = "replay";
+
+/// When PX4 logs, by the `SDLOG_MODE` parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoggingMode {
+    Disabled,
+    /// From arming until disarming, the default.
+    WhileArmed,
+    FromBootUntilDisarm,
+    FromBootUntilShutdown,
+    /// While the AUX1 RC channel is high.
+    AuxChannel,
+    FromFirstArmUntilShutdown,
+    /// A value this version doesn't know.
+    Other(i32),
+}
+
+impl LoggingMode {
+    pub fn from_param(value: i32) -> Self {
+        match value {
+            -1 => LoggingMode::Disabled,
+            0 => LoggingMode::WhileArmed,
+            1 => LoggingMode::FromBootUntilDisarm,
+            2 => LoggingMode::FromBootUntilShutdown,
+            3 => LoggingMode::AuxChannel,
+            4 => LoggingMode::FromFirstArmUntilShutdown,
+            value => LoggingMode::Other(value),
+        }
+    }
+
+    /// Whether logging starts at boot, rather than on arming or from the RC.  `None` for an unknown mode.
+    pub fn from_boot(self) -> Option<bool> {
+        match self {
+            LoggingMode::FromBootUntilDisarm | LoggingMode::FromBootUntilShutdown => Some(true),
+            LoggingMode::Disabled
+            | LoggingMode::WhileArmed
+            | LoggingMode::AuxChannel
+            | LoggingMode::FromFirstArmUntilShutdown => Some(false),
+            LoggingMode::Other(_) => None,
+        }
+    }
+}
+
+/// Which vehicle of a fleet logged a log, from `LogMetadata::vehicle_identity()`.  Those the log doesn't record are
+/// `None`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct VehicleIdentity {
+    /// The unique id of the flight controller, from the `sys_uuid` info message.
+    pub uuid: Option<String>,
+    /// The system name, e.g. `PX4`, from the `sys_name` info message.
+    pub name: Option<String>,
+    /// The airframe id, by the `SYS_AUTOSTART` parameter, e.g. 4001 for a generic quadcopter.
+    pub airframe: Option<i32>,
+    /// The MAVLink system id, by the `MAV_SYS_ID` parameter.
+    pub mav_sys_id: Option<i32>,
+}
+
+impl VehicleIdentity {
+    /// An id telling apart the vehicles of a fleet: the uuid, or for logs without one, the system name and MAVLink
+    /// system id, e.g. `PX4:3`.  `None` if the log records neither.
+    pub fn fleet_id(&self) -> Option<String> {
+        if let Some(uuid) = &self.uuid {
+            return Some(uuid.clone());
+        }
+        Some(format!("{}:{}", self.name.as_ref()?, self.mav_sys_id?))
+    }
+}
+
+/// The sets of topics PX4 logs, by the bits of the `SDLOG_PROFILE` parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoggingProfile(pub u32);
+
+impl LoggingProfile {
+    pub const DEFAULT: u32 = 1 << 0;
+    /// The topics needed to replay the estimator.
+    pub const ESTIMATOR_REPLAY: u32 = 1 << 1;
+    pub const THERMAL_CALIBRATION: u32 = 1 << 2;
+    pub const SYSTEM_IDENTIFICATION: u32 = 1 << 3;
+    pub const HIGH_RATE: u32 = 1 << 4;
+    pub const DEBUG: u32 = 1 << 5;
+    pub const SENSOR_COMPARISON: u32 = 1 << 6;
+    pub const VISION_AND_AVOIDANCE: u32 = 1 << 7;
+
+    /// Whether all bits of `profile`, e.g. `LoggingProfile::ESTIMATOR_REPLAY`, are set.
+    pub fn contains(self, profile: u32) -> bool {
+        self.0 & profile == profile
+    }
+}
+
+/// The metadata of a log, from the messages before its first logged data.  Those the log doesn't record are `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LogMetadata {
+    pub sys_name: Option<String>,
+    pub ver_sw: Option<String>,
+    pub ver_hw: Option<String>,
+    /// The unique id of the vehicle, from the `sys_uuid` info message.
+    pub sys_uuid: Option<String>,
+    /// The airframe id, from the `SYS_AUTOSTART` parameter.
+    pub airframe: Option<i32>,
+    /// The MAVLink system id, from the `MAV_SYS_ID` parameter.
+    pub mav_sys_id: Option<i32>,
+    /// The log replayed to produce this one, from the `replay` info message.
+    pub replay: Option<String>,
+    pub logging_mode: Option<LoggingMode>,
+    pub logging_profile: Option<LoggingProfile>,
+}
+
+impl LogMetadata {
+    /// Reads the metadata from the messages of a log, stopping at its first logged data, which follows the
+    /// definitions section.  Set an empty subscription allow list on the parser, so that no data is decoded.
+    pub fn read<I>(messages: I) -> Result<Self, ULogError>
+    where
+        I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+    {
+        let mut metadata = LogMetadata::default();
+        for msg in messages {
+            match msg? {
+                UlogMessage::LoggedData(_) | UlogMessage::Ignored { .. } => break,
+                UlogMessage::Info(info) => {
+                    let FieldValue::ArrayChar(chars) = &info.value else {
+                        continue;
+                    };
+                    let field = match info.key.as_str() {
+                        "sys_name" => &mut metadata.sys_name,
+                        "ver_sw" => &mut metadata.ver_sw,
+                        "ver_hw" => &mut metadata.ver_hw,
+                        "sys_uuid" => &mut metadata.sys_uuid,
+                        REPLAY_KEY => &mut metadata.replay,
+                        _ => continue,
+                    };
+                    *field = Some(chars.to_string_lossy_trimmed());
+                }
+                UlogMessage::Parameter(param) => match (param.key.as_str(), param.value) {
+                    ("SDLOG_MODE", ParameterValue::INT32(mode)) => {
+                        metadata.logging_mode = Some(LoggingMode::from_param(mode));
+                    }
+                    ("SDLOG_PROFILE", ParameterValue::INT32(profile)) => {
+                        metadata.logging_profile = Some(LoggingProfile(profile as u32));
+                    }
+                    ("SYS_AUTOSTART", ParameterValue::INT32(airframe)) => {
+                        metadata.airframe = Some(airframe);
+                    }
+                    ("MAV_SYS_ID", ParameterValue::INT32(id)) => metadata.mav_sys_id = Some(id),
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        Ok(metadata)
+    }
+
+    /// Whether the log was written by replaying another, i.e. has a `replay` info message.
+    pub fn is_replay(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    /// The vehicle which logged the log.
+    pub fn vehicle_identity(&self) -> VehicleIdentity {
+        VehicleIdentity {
+            uuid: self.sys_uuid.clone(),
+            name: self.sys_name.clone(),
+            airframe: self.airframe,
+            mav_sys_id: self.mav_sys_id,
+        }
+    }
+
+    /// Whether logging started at boot, rather than on arming.  `None` if the mode isn't recorded, or unknown.
+    pub fn logged_from_boot(&self) -> Option<bool> {
+        self.logging_mode?.from_boot()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::model::def::BaseType;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    #[test]
+    fn test_log_metadata() -> Result<(), ULogError> {
+        let bytes = LogBuilder::new()
+            .info("sys_name", "PX4")
+            .info("ver_hw", "PX4_FMU_V5")
+            .info("sys_uuid", "000600000000363633345107002d0029")
+            .info(REPLAY_KEY, "log_12_2024-1-1-10-00-00.ulg")
+            .parameter_i32("SDLOG_MODE", 1)
+            .parameter_i32("SDLOG_PROFILE", 3)
+            .parameter_i32("SYS_AUTOSTART", 4001)
+            .topic(Topic::new("a").field("x", BaseType::UINT8, Generator::Constant(0.0)))
+            .build()?;
+        let parser = ULogParserBuilder::new(bytes.as_slice())
+            .set_subscription_allow_list(Vec::<String>::new())
+            .build()?;
+
+        let metadata = LogMetadata::read(parser)?;
+        assert_eq!(metadata.sys_name.as_deref(), Some("PX4"));
+        assert_eq!(metadata.ver_hw.as_deref(), Some("PX4_FMU_V5"));
+        assert_eq!(metadata.ver_sw, None);
+        assert_eq!(
+            metadata.sys_uuid.as_deref(),
+            Some("000600000000363633345107002d0029")
+        );
+        assert!(metadata.is_replay());
+        assert_eq!(
+            metadata.logging_mode,
+            Some(LoggingMode::FromBootUntilDisarm)
+        );
+        assert_eq!(metadata.logged_from_boot(), Some(true));
+        let profile = metadata.logging_profile.unwrap();
+        assert!(profile.contains(LoggingProfile::DEFAULT | LoggingProfile::ESTIMATOR_REPLAY));
+        assert!(!profile.contains(LoggingProfile::HIGH_RATE));
+        assert_eq!(metadata.airframe, Some(4001));
+        assert_eq!(
+            metadata.vehicle_identity().fleet_id().as_deref(),
+            Some("000600000000363633345107002d0029")
+        );
+
+        let bytes = LogBuilder::new()
+            .info("sys_name", "PX4")
+            .parameter_i32("MAV_SYS_ID", 3)
+            .build()?;
+        let metadata = LogMetadata::read(ULogParserBuilder::new(bytes.as_slice()).build()?)?;
+        assert!(!metadata.is_replay());
+        assert_eq!(metadata.logged_from_boot(), None);
+        let identity = metadata.vehicle_identity();
+        assert_eq!(identity.mav_sys_id, Some(3));
+        assert_eq!(identity.fleet_id().as_deref(), Some("PX4:3"));
+        assert_eq!(VehicleIdentity::default().fleet_id(), None);
+        Ok(())
+    }
+}
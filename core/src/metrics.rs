@@ -0,0 +1,231 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::errors::ULogError;
+use crate::parser::ULogMessageType;
+
+/// Hooks called by the parser as it reads messages, e.g. to export throughput counters.
+///
+/// All methods default to doing nothing, so implementations only override the hooks they need.
+/// Hooks are called from the parser's hot loop, and should be cheap, e.g. incrementing atomic counters.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use yule_log::builder::ULogParserBuilder;
+/// use yule_log::metrics::CountingMetrics;
+///
+/// # fn main() -> Result<(), yule_log::errors::ULogError> {
+/// let metrics = Arc::new(CountingMetrics::default());
+/// let input = std::fs::read("test_data/input/short_list.ulg")?;
+///
+/// let parser = ULogParserBuilder::new(input.as_slice())
+///     .metrics(metrics.clone())
+///     .build()?;
+/// parser.for_each(drop);
+///
+/// assert_eq!(metrics.bytes(), input.len() as u64);
+/// # Ok(())
+/// # }
+/// ```
+pub trait ParserMetrics: Send + Sync {
+    /// Called for each message read, including messages skipped by the subscription allow list.
+    /// `bytes` includes the message header.
+    fn incr_msg(&self, _ty: ULogMessageType, _bytes: usize) {}
+
+    /// Called for each `LoggedData` message decoded.  `bytes` includes the message header.
+    fn incr_topic(&self, _topic: &str, _multi_id: u8, _bytes: usize) {}
+
+    /// Called for each dropout mark.
+    fn incr_dropout(&self, _duration_ms: u16) {}
+
+    /// Called for each error returned by the parser.
+    fn incr_error(&self, _error: &ULogError) {}
+
+    /// Called by `aggregate::across_logs()` as each file is finished, with the error if it failed.  Called
+    /// from the thread which processed the file.
+    fn incr_file(&self, _path: &Path, _error: Option<&ULogError>) {}
+}
+
+/// The default metrics, which discard all hooks.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoopMetrics;
+
+impl ParserMetrics for NoopMetrics {}
+
+impl<T: ParserMetrics + ?Sized> ParserMetrics for Arc<T> {
+    fn incr_msg(&self, ty: ULogMessageType, bytes: usize) {
+        (**self).incr_msg(ty, bytes);
+    }
+
+    fn incr_topic(&self, topic: &str, multi_id: u8, bytes: usize) {
+        (**self).incr_topic(topic, multi_id, bytes);
+    }
+
+    fn incr_dropout(&self, duration_ms: u16) {
+        (**self).incr_dropout(duration_ms);
+    }
+
+    fn incr_error(&self, error: &ULogError) {
+        (**self).incr_error(error);
+    }
+
+    fn incr_file(&self, path: &Path, error: Option<&ULogError>) {
+        (**self).incr_file(path, error);
+    }
+}
+
+/// Totals of messages, bytes, dropouts and errors.  Share it with the parser through an `Arc`, and read it
+/// from any thread, e.g. to compute rates between two scrapes.
+#[derive(Debug, Default)]
+pub struct CountingMetrics {
+    messages: AtomicU64,
+    bytes: AtomicU64,
+    dropouts: AtomicU64,
+    errors: AtomicU64,
+    files: AtomicU64,
+}
+
+impl CountingMetrics {
+    /// The number of messages read, including the file header.
+    pub fn messages(&self) -> u64 {
+        self.messages.load(Ordering::Relaxed)
+    }
+
+    /// The number of bytes read, including the file header and message headers.
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn dropouts(&self) -> u64 {
+        self.dropouts.load(Ordering::Relaxed)
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    /// The number of files finished by `aggregate::across_logs()`, whether they succeeded or failed.
+    pub fn files(&self) -> u64 {
+        self.files.load(Ordering::Relaxed)
+    }
+}
+
+impl ParserMetrics for CountingMetrics {
+    fn incr_msg(&self, _ty: ULogMessageType, bytes: usize) {
+        self.messages.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    fn incr_dropout(&self, _duration_ms: u16) {
+        self.dropouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn incr_error(&self, _error: &ULogError) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn incr_file(&self, _path: &Path, _error: Option<&ULogError>) {
+        self.files.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::model::def::BaseType;
+    use crate::testkit::{FaultyReader, Generator, LogBuilder, Topic};
+
+    #[derive(Default)]
+    struct TopicMetrics {
+        topics: Mutex<HashMap<(String, u8), usize>>,
+    }
+
+    impl ParserMetrics for TopicMetrics {
+        fn incr_topic(&self, topic: &str, multi_id: u8, _bytes: usize) {
+            *self
+                .topics
+                .lock()
+                .unwrap()
+                .entry((topic.to_owned(), multi_id))
+                .or_default() += 1;
+        }
+    }
+
+    fn fixture() -> Result<Vec<u8>, ULogError> {
+        LogBuilder::new()
+            .topic(Topic::new("a").rate_hz(20.0).field(
+                "x",
+                BaseType::UINT8,
+                Generator::Constant(1.0),
+            ))
+            .topic(Topic::new("a").multi_id(1).rate_hz(5.0).field(
+                "x",
+                BaseType::UINT8,
+                Generator::Constant(2.0),
+            ))
+            .dropout_at(Duration::from_millis(300), 50)
+            .build()
+    }
+
+    #[test]
+    fn test_counting_metrics() -> Result<(), ULogError> {
+        let bytes = fixture()?;
+        let metrics = Arc::new(CountingMetrics::default());
+
+        let messages = ULogParserBuilder::new(bytes.as_slice())
+            .include_header(true)
+            .metrics(metrics.clone())
+            .build()?
+            .count();
+
+        assert_eq!(metrics.messages(), messages as u64);
+        assert_eq!(metrics.bytes(), bytes.len() as u64);
+        assert_eq!(metrics.dropouts(), 1);
+        assert_eq!(metrics.errors(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_topic_metrics() -> Result<(), ULogError> {
+        let bytes = fixture()?;
+        let metrics = Arc::new(TopicMetrics::default());
+
+        ULogParserBuilder::new(bytes.as_slice())
+            .metrics(metrics.clone())
+            .build()?
+            .for_each(drop);
+
+        let topics = metrics.topics.lock().unwrap();
+        assert_eq!(topics[&("a".to_owned(), 0)], 20);
+        assert_eq!(topics[&("a".to_owned(), 1)], 5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_metrics() -> Result<(), ULogError> {
+        let bytes = fixture()?;
+        let metrics = Arc::new(CountingMetrics::default());
+
+        let reader = FaultyReader::new(bytes.as_slice()).error_at(100, std::io::ErrorKind::Other);
+        let result: Result<Vec<_>, _> = ULogParserBuilder::new(reader)
+            .metrics(metrics.clone())
+            .build()?
+            .collect();
+
+        assert!(result.is_err());
+        assert_eq!(metrics.errors(), 1);
+
+        Ok(())
+    }
+}
@@ -1,5 +1,12 @@
+use std::collections::HashMap;
+
+use crate::errors::{CastError, ULogError};
+
 pub(crate) const MAGIC: [u8; 7] = [b'U', b'L', b'o', b'g', 0x01, 0x12, 0x35];
 
+// The contents of a SYNC message.
+pub(crate) const SYNC_MAGIC: [u8; 8] = [0x2F, 0x73, 0x13, 0x20, 0x25, 0x0C, 0xBB, 0x12];
+
 // Alias controlling which type to use for shared ownership of strings and structs.
 // Since the parser is single-threaded, `Rc` is used by default.
 // When the `thread_safe` feature is enabled, `Arc` is used instead,
@@ -10,13 +17,48 @@ pub type Shared<T> = std::sync::Arc<T>;
 #[cfg(not(feature = "thread_safe"))]
 pub type Shared<T> = std::rc::Rc<T>;
 
+/// FNV-1a, for hashes which must be the same in every build and process, which those of `DefaultHasher` needn't be.
+/// Integers are hashed as little endian `u64`s, whatever the platform.
+#[derive(Debug, Clone)]
+pub(crate) struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        StableHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl std::hash::Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
 
 pub mod msg {
+    use std::any::Any;
+    use std::fmt;
+    use std::sync::Arc;
+
     use crate::errors::ULogError;
     use crate::model::MAGIC;
-    use crate::model::{def, inst};
+    use crate::model::{def, inst, CChar};
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     pub enum UlogMessage {
         Header(FileHeader),
         FlagBits(FlagBits),
@@ -35,9 +77,106 @@ pub mod msg {
             message_contents: Vec<u8>,
         },
         Ignored {msg_type: u8},
+        UnknownTyped(UnknownTyped),
+        Custom(Custom),
     }
 
-    #[derive(Debug, Copy, Clone)]
+    impl UlogMessage {
+        /// Creates an `Info` message.  See `Info::new()`.
+        pub fn info(key: impl Into<String>, value: impl InfoValue) -> Self {
+            UlogMessage::Info(Info::new(key, value))
+        }
+
+        /// Creates a `Custom` message holding `value`, to be returned by a custom message handler.
+        pub fn custom<T: Any + Send + Sync>(value: T) -> Self {
+            UlogMessage::Custom(Custom::new(value))
+        }
+
+        /// Creates a `MultiInfo` message which is not continued.  See `MultiInfo::new()`.
+        pub fn multi_info(key: impl Into<String>, value: impl InfoValue) -> Self {
+            UlogMessage::MultiInfo(MultiInfo::new(key, value, false))
+        }
+
+        /// Returns the msg_id of a REMOVE_SUBSCRIPTION message.  The parser reports these as
+        /// `Unhandled`, with the msg_id in the first two bytes of the contents.
+        pub fn removed_subscription(&self) -> Option<u16> {
+            match self {
+                UlogMessage::Unhandled { msg_type: b'R', message_contents } => match message_contents[..] {
+                    [lo, hi, ..] => Some(u16::from_le_bytes([lo, hi])),
+                    _ => None,
+                },
+                _ => None,
+            }
+        }
+
+        pub fn kind(&self) -> MessageKind {
+            match self {
+                UlogMessage::Header(_) => MessageKind::Header,
+                UlogMessage::FlagBits(_) => MessageKind::FlagBits,
+                UlogMessage::FormatDefinition(_) => MessageKind::FormatDefinition,
+                UlogMessage::LoggedData(_) => MessageKind::LoggedData,
+                UlogMessage::AddSubscription(_) => MessageKind::AddSubscription,
+                UlogMessage::Info(_) => MessageKind::Info,
+                UlogMessage::MultiInfo(_) => MessageKind::MultiInfo,
+                UlogMessage::Parameter(_) => MessageKind::Parameter,
+                UlogMessage::DefaultParameter(_) => MessageKind::DefaultParameter,
+                UlogMessage::LoggedString(_) => MessageKind::LoggedString,
+                UlogMessage::TaggedLoggedString(_) => MessageKind::TaggedLoggedString,
+                UlogMessage::DropoutMark(_) => MessageKind::DropoutMark,
+                UlogMessage::Unhandled { .. } => MessageKind::Unhandled,
+                UlogMessage::Ignored { .. } => MessageKind::Ignored,
+                UlogMessage::UnknownTyped(_) => MessageKind::UnknownTyped,
+                UlogMessage::Custom(_) => MessageKind::Custom,
+            }
+        }
+
+        /// Returns true for `LoggedData` messages.
+        pub fn is_data(&self) -> bool {
+            matches!(self, UlogMessage::LoggedData(_))
+        }
+
+        /// The topic of a `LoggedData` or `AddSubscription` message.
+        pub fn topic_name(&self) -> Option<&str> {
+            match self {
+                UlogMessage::LoggedData(data) => Some(&data.data.name),
+                UlogMessage::AddSubscription(sub) => Some(&sub.message_name),
+                _ => None,
+            }
+        }
+
+        /// The timestamp of the messages which have one: the header, data messages and logged strings.
+        pub fn timestamp(&self) -> Option<u64> {
+            match self {
+                UlogMessage::Header(header) => Some(header.timestamp),
+                UlogMessage::LoggedData(data) => Some(data.timestamp),
+                UlogMessage::LoggedString(log) | UlogMessage::TaggedLoggedString(log) => Some(log.timestamp),
+                _ => None,
+            }
+        }
+    }
+
+    /// The variant of a `UlogMessage`, for routing messages without matching on their contents.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum MessageKind {
+        Header,
+        FlagBits,
+        FormatDefinition,
+        LoggedData,
+        AddSubscription,
+        Info,
+        MultiInfo,
+        Parameter,
+        DefaultParameter,
+        LoggedString,
+        TaggedLoggedString,
+        DropoutMark,
+        Unhandled,
+        Ignored,
+        UnknownTyped,
+        Custom,
+    }
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
     pub struct FileHeader {
         pub version: u8,
         pub timestamp: u64,
@@ -55,7 +194,7 @@ pub mod msg {
         }
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     pub struct FlagBits {
         pub compat_flags: [u8; 8],
         pub incompat_flags: [u8; 8],
@@ -78,7 +217,7 @@ pub mod msg {
         }
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     // Represents both Logged Messages and Tagged Logged Messages
     pub struct LoggedString {
         pub level: LogLevel,
@@ -100,7 +239,7 @@ pub mod msg {
         Debug = b'7',
     }
 
-    #[derive(Debug, Clone)]
+    #[derive(Debug, Clone, PartialEq, Eq)]
     pub struct Subscription {
         pub multi_id: u8,
         pub msg_id: u16,
@@ -122,14 +261,127 @@ pub mod msg {
         pub value: inst::FieldValue,
     }
 
-    #[derive(Debug)]
+    /// A Rust value which can be written as the value of an `Info` or `MultiInfo` message,
+    /// with the matching ULOG type.
+    ///
+    /// Strings are written as `char[N]`, where `N` is their length in bytes, scalars as the
+    /// corresponding primitive type, and slices, arrays and vectors of scalars as arrays.
+    pub trait InfoValue {
+        fn into_info_value(self) -> (def::TypeExpr, inst::FieldValue);
+    }
+
+    impl InfoValue for &str {
+        fn into_info_value(self) -> (def::TypeExpr, inst::FieldValue) {
+            let chars: Vec<CChar> = self.bytes().map(CChar).collect();
+            (
+                def::TypeExpr {
+                    base_type: def::BaseType::CHAR,
+                    array_size: Some(chars.len()),
+                },
+                inst::FieldValue::ArrayChar(chars),
+            )
+        }
+    }
+
+    impl InfoValue for String {
+        fn into_info_value(self) -> (def::TypeExpr, inst::FieldValue) {
+            self.as_str().into_info_value()
+        }
+    }
+
+    impl InfoValue for &String {
+        fn into_info_value(self) -> (def::TypeExpr, inst::FieldValue) {
+            self.as_str().into_info_value()
+        }
+    }
+
+    macro_rules! impl_info_value {
+        ($ty:ty, $base_type:ident, $scalar:ident, $array:ident) => {
+            impl InfoValue for $ty {
+                fn into_info_value(self) -> (def::TypeExpr, inst::FieldValue) {
+                    (
+                        def::TypeExpr {
+                            base_type: def::BaseType::$base_type,
+                            array_size: None,
+                        },
+                        inst::FieldValue::$scalar(self),
+                    )
+                }
+            }
+
+            impl InfoValue for Vec<$ty> {
+                fn into_info_value(self) -> (def::TypeExpr, inst::FieldValue) {
+                    (
+                        def::TypeExpr {
+                            base_type: def::BaseType::$base_type,
+                            array_size: Some(self.len()),
+                        },
+                        inst::FieldValue::$array(self),
+                    )
+                }
+            }
+
+            impl InfoValue for &[$ty] {
+                fn into_info_value(self) -> (def::TypeExpr, inst::FieldValue) {
+                    self.to_vec().into_info_value()
+                }
+            }
+
+            impl<const N: usize> InfoValue for [$ty; N] {
+                fn into_info_value(self) -> (def::TypeExpr, inst::FieldValue) {
+                    self.to_vec().into_info_value()
+                }
+            }
+        };
+    }
+
+    impl_info_value!(u8, UINT8, ScalarU8, ArrayU8);
+    impl_info_value!(u16, UINT16, ScalarU16, ArrayU16);
+    impl_info_value!(u32, UINT32, ScalarU32, ArrayU32);
+    impl_info_value!(u64, UINT64, ScalarU64, ArrayU64);
+    impl_info_value!(i8, INT8, ScalarI8, ArrayI8);
+    impl_info_value!(i16, INT16, ScalarI16, ArrayI16);
+    impl_info_value!(i32, INT32, ScalarI32, ArrayI32);
+    impl_info_value!(i64, INT64, ScalarI64, ArrayI64);
+    impl_info_value!(f32, FLOAT, ScalarF32, ArrayF32);
+    impl_info_value!(f64, DOUBLE, ScalarF64, ArrayF64);
+    impl_info_value!(bool, BOOL, ScalarBool, ArrayBool);
+
+    impl Info {
+        /// Creates an `Info` message, inferring the ULOG type from `value`, e.g. `Info::new("ver_sw", "1.14.0")`.
+        pub fn new(key: impl Into<String>, value: impl InfoValue) -> Self {
+            let (r#type, value) = value.into_info_value();
+            Info {
+                key: key.into(),
+                r#type,
+                value,
+            }
+        }
+    }
+
+    impl MultiInfo {
+        /// Creates a `MultiInfo` message, inferring the ULOG type from `value`.
+        ///
+        /// Set `is_continued` for the second and later parts of a value split over several messages.
+        pub fn new(key: impl Into<String>, value: impl InfoValue, is_continued: bool) -> Self {
+            let (r#type, value) = value.into_info_value();
+            MultiInfo {
+                is_continued,
+                key: key.into(),
+                r#type,
+                value,
+            }
+        }
+    }
+
+    #[derive(Debug, Clone)]
     pub struct Parameter {
         pub key: String,
         pub r#type: def::TypeExpr,
         pub value: inst::ParameterValue,
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     pub struct DefaultParameter {
         pub key: String,
         pub default_types: u8,
@@ -137,7 +389,7 @@ pub mod msg {
         pub value: inst::ParameterValue,
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     pub struct DefaultType {
         pub system_wide: bool,
         pub configuration: bool,
@@ -162,11 +414,67 @@ pub mod msg {
         pub data: inst::Format,
     }
 
+    /// A message whose type is not defined by the ULOG spec, e.g. an experimental
+    /// message type logged by a firmware fork.
+    ///
+    /// Custom decoders for such messages can be registered using `decoder::DecoderRegistry`.
+    #[derive(Debug, Clone)]
+    pub struct UnknownTyped {
+        pub msg_type: u8,
+        pub payload: Vec<u8>,
+    }
+
+    /// A message with a custom message type, decoded into a value of its handler's choosing.  See
+    /// `ULogParserBuilder::register_handler()`.
+    ///
+    /// The raw payload is kept, so that the message can be written back unchanged.
+    #[derive(Clone)]
+    pub struct Custom {
+        pub msg_type: u8,
+        pub payload: Vec<u8>,
+        value: Arc<dyn Any + Send + Sync>,
+    }
+
+    impl Custom {
+        /// The message type and payload are filled in by the parser once the handler returns.
+        pub fn new<T: Any + Send + Sync>(value: T) -> Self {
+            Custom {
+                msg_type: 0,
+                payload: Vec::new(),
+                value: Arc::new(value),
+            }
+        }
+
+        pub fn is<T: Any>(&self) -> bool {
+            self.value.is::<T>()
+        }
+
+        pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+            self.value.downcast_ref()
+        }
+    }
+
+    impl fmt::Debug for Custom {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("Custom")
+                .field("msg_type", &self.msg_type)
+                .field("payload", &self.payload)
+                .finish_non_exhaustive()
+        }
+    }
+
     #[derive(Debug, Copy, Clone)]
     pub struct Dropout {
         pub(crate) duration: u16,
     }
 
+    impl LogLevel {
+        /// Whether this level is as severe as `level`, or more, e.g. `Err` is at least `Warning`.
+        pub fn is_at_least(self, level: LogLevel) -> bool {
+            self as u8 <= level as u8
+        }
+    }
+
     impl TryFrom<u8> for LogLevel {
         type Error = ULogError;
 
@@ -233,6 +541,70 @@ pub mod def {
         CHAR,
         OTHER(String),
     }
+
+    /// Builds a `Format`, which is validated by `build()`.  See `Format::validate()`.
+    ///
+    /// ```rust
+    /// use yule_log::encode::Encode;
+    /// use yule_log::model::def::{BaseType, FormatBuilder};
+    ///
+    /// # fn main() -> Result<(), yule_log::errors::ULogError> {
+    /// let format = FormatBuilder::new("my_topic")
+    ///     .field("timestamp", BaseType::UINT64)
+    ///     .array("output", BaseType::FLOAT, 8)
+    ///     .field("armed", BaseType::BOOL)
+    ///     .padding(7)
+    ///     .build()?;
+    ///
+    /// let mut definition = Vec::new();
+    /// format.encode(&mut definition)?;
+    /// assert_eq!(definition, b"my_topic:uint64_t timestamp;float[8] output;bool armed;uint8_t[7] _padding0;");
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[derive(Debug, Clone)]
+    pub struct FormatBuilder {
+        pub(crate) format: Format,
+    }
+
+    /// The byte offset of each field of a format within a message, from `Format::layout()`, so that values can be
+    /// sliced straight out of the contents of a message without parsing it.
+    ///
+    /// ```rust
+    /// use std::collections::HashMap;
+    /// use yule_log::formats::parse_format_str;
+    ///
+    /// # fn main() -> Result<(), yule_log::errors::ULogError> {
+    /// let format = parse_format_str("vehicle_local_position:uint64_t timestamp;float[3] vel;float vel_d;")?;
+    /// let layout = format.layout(&HashMap::new())?;
+    ///
+    /// assert_eq!(layout.offset_of("vel_d"), Some(20));
+    /// assert_eq!(layout.offset_of("vel[1]"), Some(12));
+    ///
+    /// let message = [0u8; 24];
+    /// let vel_d = f32::from_le_bytes(layout.slice("vel_d", &message).unwrap().try_into().unwrap());
+    /// assert_eq!(vel_d, 0.0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Layout {
+        pub name: Shared<str>,
+        /// The size of a message, including all padding.
+        pub size: usize,
+        pub fields: Vec<FieldLayout>,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct FieldLayout {
+        pub name: Shared<str>,
+        pub r#type: TypeExpr,
+        pub offset: usize,
+        /// The size of each element of an array, or of the field if it is a scalar.
+        pub element_size: usize,
+        /// The layout of a nested format.
+        pub nested: Option<Shared<Layout>>,
+    }
 }
 
 /// This module defines structs that represent data instances, based on the structures
@@ -249,6 +621,9 @@ pub mod inst {
         pub timestamp: Option<u64>,
         pub name: Shared<str>,
         pub fields: Vec<Field>,
+        /// The index in `def_format.fields` of each entry in `fields`, which differ once the timestamp or
+        /// padding fields have been filtered out.  If empty, `fields` are assumed to match `def_format.fields`.
+        pub field_indices: Vec<u16>,
         pub multi_id_index: Option<u8>,
         pub def_format: Shared<def::Format>,
     }
@@ -301,6 +676,103 @@ pub mod inst {
 }
 
 impl inst::FieldValue {
+    /// Returns a `char` array as a string, without its trailing NULs.  The ULOG spec doesn't require strings to be
+    /// NUL terminated, but PX4 pads them with NULs to the size of the array.  Returns `None` for other values, and
+    /// for arrays which aren't valid UTF-8.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            inst::FieldValue::ArrayChar(chars) => std::str::from_utf8(chars.trim_end_nul().as_bytes()).ok(),
+            _ => None,
+        }
+    }
+
+    // Appends the elements of `part` to this array if it is an array of the same type, for `MultiInfo` messages
+    // which continue a value.  Returns `part` otherwise.
+    pub(crate) fn extend_array(&mut self, part: inst::FieldValue) -> Result<(), inst::FieldValue> {
+        use inst::FieldValue::*;
+        match (self, part) {
+            (ArrayU8(a), ArrayU8(b)) => a.extend(b),
+            (ArrayU16(a), ArrayU16(b)) => a.extend(b),
+            (ArrayU32(a), ArrayU32(b)) => a.extend(b),
+            (ArrayU64(a), ArrayU64(b)) => a.extend(b),
+            (ArrayI8(a), ArrayI8(b)) => a.extend(b),
+            (ArrayI16(a), ArrayI16(b)) => a.extend(b),
+            (ArrayI32(a), ArrayI32(b)) => a.extend(b),
+            (ArrayI64(a), ArrayI64(b)) => a.extend(b),
+            (ArrayF32(a), ArrayF32(b)) => a.extend(b),
+            (ArrayF64(a), ArrayF64(b)) => a.extend(b),
+            (ArrayBool(a), ArrayBool(b)) => a.extend(b),
+            (ArrayChar(a), ArrayChar(b)) => a.extend(b),
+            (ArrayOther(a), ArrayOther(b)) => a.extend(b),
+            (_, part) => return Err(part),
+        }
+        Ok(())
+    }
+
+    /// Returns a numeric scalar as an `f64`, e.g. for statistics.  Booleans are 0 or 1, and 64 bit integers
+    /// beyond 2^53 lose precision.  Returns `None` for chars, arrays and nested formats.
+    pub fn as_f64(&self) -> Option<f64> {
+        use inst::FieldValue::*;
+        match *self {
+            ScalarU8(x) => Some(f64::from(x)),
+            ScalarU16(x) => Some(f64::from(x)),
+            ScalarU32(x) => Some(f64::from(x)),
+            ScalarU64(x) => Some(x as f64),
+            ScalarI8(x) => Some(f64::from(x)),
+            ScalarI16(x) => Some(f64::from(x)),
+            ScalarI32(x) => Some(f64::from(x)),
+            ScalarI64(x) => Some(x as f64),
+            ScalarF32(x) => Some(f64::from(x)),
+            ScalarF64(x) => Some(x),
+            ScalarBool(x) => Some(f64::from(u8::from(x))),
+            _ => None,
+        }
+    }
+
+    /// Returns a numeric scalar as an `i64`.  Booleans are 0 or 1, and floats convert if they are whole
+    /// numbers within range.  Fails with `CastError::Overflow` for a `u64` above `i64::MAX`, or a float out of
+    /// range, NaN or infinite, and with `CastError::PrecisionLoss` for a float with a fractional part.
+    pub fn to_i64_checked(&self) -> Result<i64, CastError> {
+        use inst::FieldValue::*;
+        let float = match *self {
+            ScalarU64(x) => return i64::try_from(x).map_err(|_| CastError::Overflow),
+            ScalarF32(x) => f64::from(x),
+            ScalarF64(x) => x,
+            ref value => {
+                return value
+                    .as_f64()
+                    .map(|x| x as i64)
+                    .ok_or(CastError::NotNumeric)
+            }
+        };
+        // i64::MAX rounds up to 2^63 as an f64, which is out of range.
+        if !float.is_finite() || float < i64::MIN as f64 || float >= i64::MAX as f64 {
+            Err(CastError::Overflow)
+        } else if float.fract() != 0.0 {
+            Err(CastError::PrecisionLoss)
+        } else {
+            Ok(float as i64)
+        }
+    }
+
+    /// Returns a numeric scalar as an `f64`, like `as_f64()`, but fails with `CastError::PrecisionLoss` for a
+    /// 64 bit integer which an `f64` can't hold exactly.
+    pub fn to_f64_checked(&self) -> Result<f64, CastError> {
+        use inst::FieldValue::*;
+        let x = self.as_f64().ok_or(CastError::NotNumeric)?;
+        let exact = match *self {
+            // 2^64 and 2^63 are not in range, and the casts saturate, so compare before casting back.
+            ScalarU64(v) => x < u64::MAX as f64 && x as u64 == v,
+            ScalarI64(v) => x < i64::MAX as f64 && x as i64 == v,
+            _ => true,
+        };
+        if exact {
+            Ok(x)
+        } else {
+            Err(CastError::PrecisionLoss)
+        }
+    }
+
     pub fn to_scalars(&self) -> Option<Vec<inst::FieldValue>> {
         use inst::FieldValue::*;
         match self {
@@ -323,6 +795,36 @@ impl inst::FieldValue {
 }
 
 impl inst::Format {
+    /// Returns the index in `def_format.fields` of the field at `index` in `fields`.
+    pub fn def_index(&self, index: usize) -> usize {
+        self.field_indices
+            .get(index)
+            .map_or(index, |&def_index| def_index as usize)
+    }
+
+    /// Returns the field named `name`, or `None` if there is none, or it was filtered out.
+    pub fn field(&self, name: &str) -> Option<&inst::Field> {
+        self.fields.iter().find(|field| &*field.name == name)
+    }
+
+    /// Returns the field defined at `def_index` in `def_format.fields`, or `None` if it was filtered out.
+    pub fn field_at(&self, def_index: usize) -> Option<&inst::Field> {
+        if self.field_indices.is_empty() {
+            return self.fields.get(def_index);
+        }
+
+        // Fast path when no fields before `def_index` were filtered out.
+        if self.field_indices.get(def_index).is_some_and(|&i| i as usize == def_index) {
+            return Some(&self.fields[def_index]);
+        }
+
+        let def_index = u16::try_from(def_index).ok()?;
+        self.field_indices
+            .binary_search(&def_index)
+            .ok()
+            .map(|index| &self.fields[index])
+    }
+
     #[deprecated]
     pub fn flatten(&self) -> Vec<(String, inst::FieldValue)> {
         let prefix: String = self.to_string();
@@ -379,6 +881,357 @@ impl def::TypeExpr {
     pub fn is_array(&self) -> bool {
         self.array_size.is_some()
     }
+
+    /// Returns the number of bytes occupied by a value of this type.
+    ///
+    /// Nested `OTHER` types are resolved using `formats`.
+    pub fn byte_size(
+        &self,
+        formats: &HashMap<Shared<str>, Shared<def::Format>>,
+    ) -> Result<usize, ULogError> {
+        self.nested_byte_size(formats, 0)
+    }
+
+    // `depth` counts the formats this type is nested in.  Nested deeper than there are formats, a format must
+    // contain itself, which would otherwise recurse without end.
+    fn nested_byte_size(
+        &self,
+        formats: &HashMap<Shared<str>, Shared<def::Format>>,
+        depth: usize,
+    ) -> Result<usize, ULogError> {
+        let element_size = match self.base_type.primitive_size() {
+            Some(size) => size,
+            None => match &self.base_type {
+                def::BaseType::OTHER(type_name) if depth > formats.len() => {
+                    return Err(ULogError::ParseError(format!("The format `{type_name}` contains itself.")));
+                }
+                def::BaseType::OTHER(type_name) => formats
+                    .get(type_name.as_str())
+                    .ok_or_else(|| ULogError::UndefinedFormat(type_name.clone()))?
+                    .nested_byte_size(formats, depth + 1)?,
+                _ => unreachable!("All other base types are primitives"),
+            },
+        };
+
+        element_size
+            .checked_mul(self.array_size.unwrap_or(1))
+            .ok_or_else(|| ULogError::ParseError(format!("The size of `{self}` overflows.")))
+    }
+}
+
+impl def::BaseType {
+    /// Returns the size in bytes of a primitive type, or `None` for nested `OTHER` types.
+    pub fn primitive_size(&self) -> Option<usize> {
+        use def::BaseType::*;
+        match self {
+            UINT8 | INT8 | BOOL | CHAR => Some(1),
+            UINT16 | INT16 => Some(2),
+            UINT32 | INT32 | FLOAT => Some(4),
+            UINT64 | INT64 | DOUBLE => Some(8),
+            OTHER(_) => None,
+        }
+    }
+}
+
+impl def::Field {
+    pub fn is_padding(&self) -> bool {
+        self.name.starts_with("_padding")
+    }
+}
+
+impl def::FormatBuilder {
+    pub fn new(name: &str) -> Self {
+        def::FormatBuilder {
+            format: def::Format {
+                name: name.into(),
+                fields: Vec::new(),
+                padding: 0,
+            },
+        }
+    }
+
+    /// Adds a scalar field.  A `BaseType::OTHER` names a nested format.
+    #[must_use]
+    pub fn field(self, name: &str, base_type: def::BaseType) -> Self {
+        self.typed_field(name, def::TypeExpr { base_type, array_size: None })
+    }
+
+    /// Adds an array field of `len` elements.
+    #[must_use]
+    pub fn array(self, name: &str, base_type: def::BaseType, len: usize) -> Self {
+        self.typed_field(name, def::TypeExpr { base_type, array_size: Some(len) })
+    }
+
+    #[must_use]
+    pub fn typed_field(mut self, name: &str, r#type: def::TypeExpr) -> Self {
+        self.format.fields.push(def::Field { name: name.into(), r#type });
+        self
+    }
+
+    /// Adds `len` bytes of padding, as the `uint8_t[len] _paddingN` field PX4 writes, numbered from 0.
+    #[must_use]
+    pub fn padding(self, len: usize) -> Self {
+        let name = format!("_padding{}", self.format.fields.iter().filter(|f| f.is_padding()).count());
+        self.array(&name, def::BaseType::UINT8, len)
+    }
+
+    /// Returns the format of a topic, which is also checked to start with its `timestamp`.  See
+    /// `Format::validate_topic()`.
+    pub fn build(self) -> Result<def::Format, ULogError> {
+        self.format.validate_topic()?;
+        Ok(self.format)
+    }
+
+    /// Returns the format of a nested type, which needs no `timestamp`.  See `Format::validate()`.
+    pub fn build_nested(self) -> Result<def::Format, ULogError> {
+        self.format.validate()?;
+        Ok(self.format)
+    }
+}
+
+// Whether `name` can be tokenized as a single identifier of a format definition.
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+impl def::Format {
+    /// Checks that the format can be written and parsed back: the names of the format, its fields and nested types
+    /// are identifiers, the field names are unique, arrays have at least one element, and padding fields, named
+    /// `_padding` and a number, are `uint8_t` arrays.
+    ///
+    /// Returns `ULogError::InvalidConfiguration` naming the first problem.
+    pub fn validate(&self) -> Result<(), ULogError> {
+        let invalid = |problem: String| Err(ULogError::InvalidConfiguration(format!("Invalid format `{}`: {problem}", self.name)));
+
+        if !is_identifier(&self.name) {
+            return invalid("the name isn't an identifier.".to_string());
+        }
+
+        for (i, field) in self.fields.iter().enumerate() {
+            if !is_identifier(&field.name) {
+                return invalid(format!("the field name `{}` isn't an identifier.", field.name));
+            }
+            if self.fields[..i].iter().any(|f| f.name == field.name) {
+                return invalid(format!("the field `{}` is defined twice.", field.name));
+            }
+            if let def::BaseType::OTHER(type_name) = &field.r#type.base_type {
+                if !is_identifier(type_name) {
+                    return invalid(format!("the type `{type_name}` of `{}` isn't an identifier.", field.name));
+                }
+            }
+            if field.r#type.array_size == Some(0) {
+                return invalid(format!("the array `{}` has no elements.", field.name));
+            }
+            if field.is_padding() {
+                let numbered = field.name["_padding".len()..].bytes().all(|b| b.is_ascii_digit());
+                let bytes = field.r#type.base_type == def::BaseType::UINT8 && field.r#type.is_array();
+                if !numbered || !bytes {
+                    return invalid(format!(
+                        "the padding field `{}` must be a `uint8_t` array named `_padding` and a number, e.g. `uint8_t[3] _padding0`.",
+                        field.name
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks the format as `validate()` does, and that it is the format of a topic: its first field is a
+    /// `uint64_t timestamp`, which the parser takes the timestamp of each message from.
+    pub fn validate_topic(&self) -> Result<(), ULogError> {
+        self.validate()?;
+
+        let timestamp = def::TypeExpr { base_type: def::BaseType::UINT64, array_size: None };
+        match self.fields.first() {
+            Some(field) if field.name.as_ref() == "timestamp" && field.r#type == timestamp => Ok(()),
+            _ => Err(ULogError::InvalidConfiguration(format!(
+                "Invalid format `{}`: the first field of a topic must be `uint64_t timestamp`.",
+                self.name
+            ))),
+        }
+    }
+
+    /// Returns a hash of the name and layout of the format: the name, base type and array size of each of its fields,
+    /// in order.  The hash is the same in every build and on every platform, so that it can be stored to tell whether
+    /// a later log lays the format out differently.
+    ///
+    /// Nested formats are hashed by name only, so a change to the layout of a nested format changes only its own
+    /// fingerprint.
+    pub fn fingerprint(&self) -> u64 {
+        use std::hash::Hasher;
+
+        fn write_str(hasher: &mut StableHasher, s: &str) {
+            hasher.write_usize(s.len());
+            hasher.write(s.as_bytes());
+        }
+
+        let mut hasher = StableHasher::default();
+        write_str(&mut hasher, &self.name);
+        for field in &self.fields {
+            write_str(&mut hasher, &field.r#type.base_type.to_string());
+            write_str(&mut hasher, &field.name);
+            // Scalars are told from arrays of one element.
+            hasher.write_u64(field.r#type.array_size.map_or(0, |size| size as u64 + 1));
+        }
+        hasher.finish()
+    }
+
+    /// Returns the size in bytes of a message with this format, including all padding fields.
+    ///
+    /// Nested `OTHER` types are resolved using `formats`.
+    pub fn byte_size(
+        &self,
+        formats: &HashMap<Shared<str>, Shared<def::Format>>,
+    ) -> Result<usize, ULogError> {
+        self.nested_byte_size(formats, 0)
+    }
+
+    fn nested_byte_size(
+        &self,
+        formats: &HashMap<Shared<str>, Shared<def::Format>>,
+        depth: usize,
+    ) -> Result<usize, ULogError> {
+        self.fields.iter().try_fold(0usize, |size, field| {
+            size.checked_add(field.r#type.nested_byte_size(formats, depth)?)
+                .ok_or_else(|| ULogError::ParseError(format!("The size of format `{}` overflows.", self.name)))
+        })
+    }
+
+    /// Returns the smallest valid size in bytes of a logged message with this format.
+    ///
+    /// According to the ULOG spec, padding fields at the end of a top level message are not logged,
+    /// so the size of any trailing padding fields is subtracted from `byte_size()`.
+    /// Padding inside nested formats is always present.
+    pub fn min_byte_size(
+        &self,
+        formats: &HashMap<Shared<str>, Shared<def::Format>>,
+    ) -> Result<usize, ULogError> {
+        let trailing_padding: usize = self
+            .fields
+            .iter()
+            .rev()
+            .take_while(|field| field.is_padding())
+            .map(|field| field.r#type.byte_size(formats))
+            .sum::<Result<usize, ULogError>>()?;
+
+        Ok(self.byte_size(formats)? - trailing_padding)
+    }
+
+    /// Returns the offset and size of each field in a message with this format, including padding fields.  See
+    /// `Layout`.
+    ///
+    /// Nested `OTHER` types are resolved using `formats`, and each nested format is laid out once.
+    pub fn layout(
+        &self,
+        formats: &HashMap<Shared<str>, Shared<def::Format>>,
+    ) -> Result<def::Layout, ULogError> {
+        // Reports undefined formats, formats which contain themselves and sizes which overflow, so that laying out
+        // can't fail or recurse without end.
+        let size = self.byte_size(formats)?;
+        Ok(self.layout_sub(formats, size, &mut HashMap::new()))
+    }
+
+    fn layout_sub(
+        &self,
+        formats: &HashMap<Shared<str>, Shared<def::Format>>,
+        size: usize,
+        nested_layouts: &mut HashMap<String, Shared<def::Layout>>,
+    ) -> def::Layout {
+        let mut offset = 0;
+        let fields = self
+            .fields
+            .iter()
+            .map(|field| {
+                let (element_size, nested) = match &field.r#type.base_type {
+                    def::BaseType::OTHER(type_name) => {
+                        let nested = match nested_layouts.get(type_name) {
+                            Some(nested) => nested.clone(),
+                            None => {
+                                let format = &formats[type_name.as_str()];
+                                let size = format.byte_size(formats).expect("checked by the top level format");
+                                let nested = Shared::new(format.layout_sub(formats, size, nested_layouts));
+                                nested_layouts.insert(type_name.clone(), nested.clone());
+                                nested
+                            }
+                        };
+                        (nested.size, Some(nested))
+                    }
+                    base_type => (base_type.primitive_size().expect("not nested"), None),
+                };
+                let field = def::FieldLayout {
+                    name: field.name.clone(),
+                    r#type: field.r#type.clone(),
+                    offset,
+                    element_size,
+                    nested,
+                };
+                offset += field.size();
+                field
+            })
+            .collect();
+
+        def::Layout { name: self.name.clone(), size, fields }
+    }
+}
+
+impl def::Layout {
+    pub fn field(&self, name: &str) -> Option<&def::FieldLayout> {
+        self.fields.iter().find(|field| field.name.as_ref() == name)
+    }
+
+    /// Returns the offset in a message of the value at `path`: a field name, e.g. `vel_d`, an array element, e.g.
+    /// `accel[2]`, or a field of a nested format, e.g. `esc[1].esc_rpm`.  `None` if there is no such value.
+    pub fn offset_of(&self, path: &str) -> Option<usize> {
+        self.locate(path).map(|(offset, _, _)| offset)
+    }
+
+    /// Returns the bytes of the value at `path`, as for `offset_of()`, out of the contents of a message of this
+    /// format, less the msg_id.  `None` if there is no such value, or `message` is too short to hold it, e.g. when
+    /// trailing padding was left out.
+    pub fn slice<'a>(&self, path: &str, message: &'a [u8]) -> Option<&'a [u8]> {
+        let (offset, size, _) = self.locate(path)?;
+        message.get(offset..offset + size)
+    }
+
+    // The offset and size of the value at `path`, and the field it is, or is an element of.
+    pub(crate) fn locate(&self, path: &str) -> Option<(usize, usize, &def::FieldLayout)> {
+        let (segment, rest) = match path.split_once('.') {
+            Some((segment, rest)) => (segment, Some(rest)),
+            None => (path, None),
+        };
+        let (name, index) = match segment.strip_suffix(']').and_then(|s| s.split_once('[')) {
+            Some((name, index)) => (name, Some(index.parse::<usize>().ok()?)),
+            None => (segment, None),
+        };
+
+        let field = self.field(name)?;
+        let (offset, size) = match index {
+            Some(index) if index < field.r#type.array_size? => {
+                (field.offset + index * field.element_size, field.element_size)
+            }
+            Some(_) => return None,
+            None => (field.offset, field.size()),
+        };
+        match rest {
+            None => Some((offset, size, field)),
+            // An array of nested formats must be indexed before its fields.
+            Some(_) if field.r#type.is_array() && index.is_none() => None,
+            Some(rest) => {
+                let (nested_offset, size, field) = field.nested.as_ref()?.locate(rest)?;
+                Some((offset + nested_offset, size, field))
+            }
+        }
+    }
+}
+
+impl def::FieldLayout {
+    /// The size of the field, which is that of all the elements of an array.
+    pub fn size(&self) -> usize {
+        self.element_size * self.r#type.array_size.unwrap_or(1)
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -485,6 +1338,225 @@ impl CCharSlice for Vec<CChar> {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
+    use crate::formats::parse_format;
+    use crate::message_buf::MessageBuf;
+    use crate::model::{def, Shared};
+
+    fn format(definition: &str) -> def::Format {
+        parse_format(MessageBuf::from_vec(definition.as_bytes().to_vec())).unwrap()
+    }
+
+    #[test]
+    fn test_format_byte_size() {
+        let nested = format("nested:uint8_t a;uint8_t[3] _padding0;float b;");
+        let top = format("top:uint64_t timestamp;nested[2] items;bool flag;uint8_t[7] _padding0;");
+
+        let mut formats: HashMap<Shared<str>, Shared<def::Format>> = HashMap::new();
+        formats.insert(nested.name.clone(), Shared::new(nested.clone()));
+
+        // Padding inside nested formats always counts.
+        assert_eq!(nested.byte_size(&formats).unwrap(), 8);
+        assert_eq!(nested.min_byte_size(&formats).unwrap(), 8);
+
+        // Trailing padding of the top level message may be omitted.
+        assert_eq!(top.byte_size(&formats).unwrap(), 8 + 2 * 8 + 1 + 7);
+        assert_eq!(top.min_byte_size(&formats).unwrap(), 8 + 2 * 8 + 1);
+
+        // Nested formats must be defined.
+        assert!(top.byte_size(&HashMap::new()).is_err());
+
+        // Sizes which overflow are errors.
+        let huge = format("huge:uint64_t[2305843009213693952] x;");
+        assert!(huge.byte_size(&formats).is_err());
+    }
+
+    #[test]
+    fn test_format_layout() {
+        let nested = format("nested:uint8_t a;uint8_t[3] _padding0;float b;");
+        let top = format("top:uint64_t timestamp;nested[2] items;bool flag;nested single;uint8_t[7] _padding0;");
+
+        let mut formats: HashMap<Shared<str>, Shared<def::Format>> = HashMap::new();
+        formats.insert(nested.name.clone(), Shared::new(nested));
+
+        let layout = top.layout(&formats).unwrap();
+        assert_eq!(layout.size, 8 + 2 * 8 + 1 + 8 + 7);
+        let offsets: Vec<(&str, usize, usize)> =
+            layout.fields.iter().map(|f| (f.name.as_ref(), f.offset, f.size())).collect();
+        assert_eq!(
+            offsets,
+            [("timestamp", 0, 8), ("items", 8, 16), ("flag", 24, 1), ("single", 25, 8), ("_padding0", 33, 7)]
+        );
+        // Each nested format is laid out once.
+        assert!(Shared::ptr_eq(
+            layout.fields[1].nested.as_ref().unwrap(),
+            layout.fields[3].nested.as_ref().unwrap()
+        ));
+
+        assert_eq!(layout.offset_of("flag"), Some(24));
+        assert_eq!(layout.offset_of("items[1]"), Some(16));
+        assert_eq!(layout.offset_of("items[1].b"), Some(20));
+        assert_eq!(layout.offset_of("single.b"), Some(29));
+        for missing in ["nope", "items[2]", "items.b", "flag[0]", "single.c", "items[x]", "flag.a"] {
+            assert_eq!(layout.offset_of(missing), None, "{missing}");
+        }
+
+        let message: Vec<u8> = (0..33).collect();
+        assert_eq!(layout.slice("items[1].b", &message), Some(&message[20..24]));
+        assert_eq!(layout.slice("items", &message), Some(&message[8..24]));
+        // The trailing padding was left out.
+        assert_eq!(layout.slice("_padding0", &message), None);
+
+        assert!(top.layout(&HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_format_builder() {
+        use crate::model::def::{BaseType, FormatBuilder};
+
+        let format = FormatBuilder::new("my_topic")
+            .field("timestamp", BaseType::UINT64)
+            .array("nested", BaseType::OTHER("nested".to_string()), 2)
+            .padding(3)
+            .padding(1)
+            .build()
+            .unwrap();
+        assert_eq!(
+            format,
+            self::format("my_topic:uint64_t timestamp;nested[2] nested;uint8_t[3] _padding0;uint8_t[1] _padding1;")
+        );
+
+        let topic = || FormatBuilder::new("my_topic").field("timestamp", BaseType::UINT64);
+        for (builder, problem) in [
+            (FormatBuilder::new("my_topic").field("x", BaseType::FLOAT), "first field"),
+            (FormatBuilder::new("my_topic").field("timestamp", BaseType::UINT32), "first field"),
+            (FormatBuilder::new("my topic").field("timestamp", BaseType::UINT64), "name"),
+            (topic().field("x", BaseType::FLOAT).field("x", BaseType::BOOL), "twice"),
+            (topic().field("x y", BaseType::FLOAT), "`x y`"),
+            (topic().field("x", BaseType::OTHER("a:b".to_string())), "`a:b`"),
+            (topic().array("x", BaseType::FLOAT, 0), "no elements"),
+            (topic().array("_padding0", BaseType::INT8, 3), "padding"),
+            (topic().array("_padding_x", BaseType::UINT8, 3), "padding"),
+        ] {
+            let error = builder.build().unwrap_err().to_string();
+            assert!(error.contains(problem), "{error}");
+        }
+
+        // A nested format needs no timestamp.
+        assert!(FormatBuilder::new("nested").field("x", BaseType::FLOAT).build_nested().is_ok());
+    }
+
+    #[test]
+    fn test_format_fingerprint() {
+        let fingerprint = |definition: &str| format(definition).fingerprint();
+        let base = fingerprint("my_topic:uint64_t timestamp;float[3] x;nested y;");
+
+        // Whitespace and comments aren't part of the layout.
+        assert_eq!(base, fingerprint("my_topic: uint64_t timestamp; float[3] x; // y\nnested y;"));
+        // Fixed, so that fingerprints can be stored and compared by later builds.
+        assert_eq!(fingerprint("a:uint8_t b;"), 0xb9f0_ef39_1f7a_2448);
+
+        for changed in [
+            "other:uint64_t timestamp;float[3] x;nested y;",
+            "my_topic:uint64_t timestamp;double[3] x;nested y;",
+            "my_topic:uint64_t timestamp;float[4] x;nested y;",
+            "my_topic:uint64_t timestamp;float[3] z;nested y;",
+            "my_topic:uint64_t timestamp;nested y;float[3] x;",
+            "my_topic:uint64_t timestamp;float[3] x;other y;",
+            "my_topic:uint64_t timestamp;float[3] x;nested[1] y;",
+            "my_topic:uint64_t timestamp;float[3] x;",
+        ] {
+            assert_ne!(base, fingerprint(changed), "{changed}");
+        }
+    }
+
+    #[test]
+    fn test_message_classification() -> Result<(), crate::errors::ULogError> {
+        use crate::builder::ULogParserBuilder;
+        use crate::model::msg::{MessageKind, UlogMessage};
+        use crate::testkit::{Generator, LogBuilder, Topic};
+
+        let bytes = LogBuilder::new()
+            .topic(Topic::new("a").field("x", def::BaseType::FLOAT, Generator::Constant(1.0)))
+            .build()?;
+        let messages = ULogParserBuilder::new(bytes.as_slice())
+            .include_header(true)
+            .build()?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        assert_eq!(messages[0].kind(), MessageKind::Header);
+        assert!(messages[0].timestamp().is_some());
+
+        let data: Vec<_> = messages.iter().filter(|m| m.is_data()).collect();
+        assert_eq!(data.len(), 10);
+        assert!(data.iter().all(|m| m.kind() == MessageKind::LoggedData && m.topic_name() == Some("a")));
+        assert_eq!(data[1].timestamp(), Some(1_100_000));
+
+        let subscription = messages.iter().find(|m| m.kind() == MessageKind::AddSubscription).unwrap();
+        assert_eq!((subscription.topic_name(), subscription.timestamp()), (Some("a"), None));
+        assert_eq!(UlogMessage::info("k", 1u32).topic_name(), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_as_str() {
+        use crate::model::inst::FieldValue;
+        use crate::model::CChar;
+
+        let chars = |bytes: &[u8]| FieldValue::ArrayChar(bytes.iter().copied().map(CChar).collect());
+
+        assert_eq!(chars(b"gps\0\0").as_str(), Some("gps"));
+        assert_eq!(chars(b"a\0b").as_str(), Some("a\0b"));
+        assert_eq!(chars(b"").as_str(), Some(""));
+        assert_eq!(chars(&[0xFF]).as_str(), None);
+        assert_eq!(FieldValue::ScalarU8(1).as_str(), None);
+    }
+
+    #[test]
+    fn test_checked_casts() {
+        use crate::errors::CastError;
+        use crate::model::inst::FieldValue::*;
+
+        assert_eq!(ScalarU64(1 << 62).to_i64_checked(), Ok(1 << 62));
+        assert_eq!(
+            ScalarU64(u64::MAX).to_i64_checked(),
+            Err(CastError::Overflow)
+        );
+        assert_eq!(ScalarBool(true).to_i64_checked(), Ok(1));
+        assert_eq!(ScalarF32(-3.0).to_i64_checked(), Ok(-3));
+        assert_eq!(
+            ScalarF64(2.5).to_i64_checked(),
+            Err(CastError::PrecisionLoss)
+        );
+        assert_eq!(ScalarF64(1e19).to_i64_checked(), Err(CastError::Overflow));
+        assert_eq!(
+            ScalarF32(f32::NAN).to_i64_checked(),
+            Err(CastError::Overflow)
+        );
+        assert_eq!(
+            ArrayU8(vec![1]).to_i64_checked(),
+            Err(CastError::NotNumeric)
+        );
+
+        assert_eq!(ScalarU64(1 << 53).to_f64_checked(), Ok(9007199254740992.0));
+        assert_eq!(
+            ScalarU64((1 << 53) + 1).to_f64_checked(),
+            Err(CastError::PrecisionLoss)
+        );
+        assert_eq!(
+            ScalarU64(u64::MAX).to_f64_checked(),
+            Err(CastError::PrecisionLoss)
+        );
+        assert_eq!(ScalarI64(i64::MIN).to_f64_checked(), Ok(i64::MIN as f64));
+        assert_eq!(
+            ScalarI64(i64::MAX).to_f64_checked(),
+            Err(CastError::PrecisionLoss)
+        );
+        assert_eq!(ScalarF32(1.5).to_f64_checked(), Ok(1.5));
+    }
+
     #[test]
     #[cfg(feature = "thread_safe")]
     fn all_types_are_send_and_sync() {
@@ -506,6 +1578,9 @@ mod tests {
         assert_send_sync::<msg::DefaultType>();
         assert_send_sync::<msg::LoggedData>();
         assert_send_sync::<msg::Dropout>();
+        assert_send_sync::<msg::UnknownTyped>();
+        assert_send_sync::<msg::Custom>();
+        assert_send_sync::<msg::MessageKind>();
 
         // def
         assert_send_sync::<def::Format>();
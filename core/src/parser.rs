@@ -1,91 +1,117 @@
 #![allow(non_camel_case_types)]
 
 use crate::field_helpers::parse_primitive_array;
-use std::collections::{HashMap, HashSet};
-use std::io::Read;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{Read, Seek};
+use std::time::{Duration, Instant};
 use byteorder::{ByteOrder, LittleEndian};
 
+use crate::cache::DefinitionsCache;
+use crate::cancel::CancellationToken;
 use crate::datastream::DataStream;
+use crate::decoder::DecoderRegistry;
+use crate::diag;
 use crate::errors::ULogError;
 use crate::errors::ULogError::{UndefinedFormat, UndefinedSubscription};
 use crate::field_helpers::{parse_array, parse_data_field};
-use crate::formats::{parse_field, parse_format};
+pub use crate::filter::SubscriptionFilter;
+use crate::formats::{parse_field, parse_format, parse_format_recovering};
 use crate::message_buf::MessageBuf;
+use crate::metrics::{NoopMetrics, ParserMetrics};
 use crate::model::def::BaseType;
 use crate::model::msg::{
-    Dropout, FileHeader, FlagBits, LogLevel, LoggedData, MultiInfo, Subscription, UlogMessage,
+    Dropout, FileHeader, FlagBits, LogLevel, LoggedData, LoggedString, MultiInfo, Subscription, UlogMessage,
 };
-use crate::model::{def, inst, msg, Shared, MAGIC};
+use crate::model::{def, inst, msg, CCharSlice, Shared, MAGIC, SYNC_MAGIC};
+use crate::state::ParserState;
 use crate::tokenizer::TokenList;
+use crate::validation::{
+    DuplicateSubscription, DuplicateSubscriptionPolicy, ResyncRegion, SequenceTracker, TimestampAdjustment,
+    TimestampClock, TimestampRepair, ValidationReport,
+};
 
 pub struct ULogParser<R: Read> {
     state: State,
     file_header: Option<FileHeader>,
-    overridden_params: HashSet<String>,
     pub formats: HashMap<Shared<str>, Shared<def::Format>>,
     subscriptions: HashMap<u16, msg::Subscription>,
+    // Valid range of LOGGED_DATA message sizes (excluding msg_id) for each subscription.
+    message_sizes: HashMap<u16, (usize, usize)>,
     message_name_with_multi_id: HashSet<String>,
     subscription_filter: SubscriptionFilter,
-    datastream: DataStream<R>,
+    pub(crate) datastream: DataStream<R>,
     max_bytes_to_read: Option<usize>,
     pub(crate) include_header: bool,
     pub(crate) include_timestamp: bool,
     pub(crate) include_padding: bool,
+    // Per topic overrides of `include_timestamp` and `include_padding`.
+    pub(crate) include_timestamp_overrides: HashMap<String, bool>,
+    pub(crate) include_padding_overrides: HashMap<String, bool>,
+    pub(crate) ignore_invalid_appended_offsets: bool,
+    pub(crate) error_on_message_size_mismatch: bool,
+    pub(crate) duplicate_subscription_policy: DuplicateSubscriptionPolicy,
+    pub(crate) max_subscriptions: Option<usize>,
+    pub(crate) timestamp_repair: Option<TimestampRepair>,
+    pub(crate) resync_on_error: bool,
+    pub(crate) strict: bool,
+    pub(crate) emit_ignored: bool,
+    pub(crate) emit_unhandled: bool,
+    pub(crate) recover_format_errors: bool,
+    // The formats with fields left out by `recover_format_errors`.
+    damaged_formats: HashSet<String>,
+    timestamp_clocks: HashMap<u16, TimestampClock>,
+    sequence: SequenceTracker,
+    validation_report: ValidationReport,
+    pub(crate) decoders: DecoderRegistry,
+    pub(crate) metrics: Box<dyn ParserMetrics>,
+    pub(crate) definitions_cache: Option<DefinitionsCache>,
+    pub(crate) cancellation: Option<CancellationToken>,
+    // Whether `ULogError::Cancelled` was returned, after which the parser ends.
+    cancelled: bool,
+    // Definitions read from the cache, or parsed after hashing, which are yet to be returned.
+    queued_definitions: VecDeque<Result<UlogMessage, ULogError>>,
+    // The message which ended a cached definitions section, to be parsed once the definitions have been returned.
+    pending_message: Option<(ULogMessageType, MessageBuf)>,
 }
 
-pub struct SubscriptionFilter {
-    allowed_subscription_names: Option<HashSet<String>>,
-    allowed_subscription_ids: Option<HashSet<u16>>,
-}
-
-impl Default for SubscriptionFilter {
-    fn default() -> Self {
-        Self {
-            allowed_subscription_names: None,
-            allowed_subscription_ids: None,
+impl<R: Read + Seek> ULogParser<R> {
+    /// Moves a parser which has read the definitions section to the data message at `offset`, replacing its
+    /// subscriptions with `subscriptions`, e.g. those active at `offset` according to a `ULogIndex`.
+    pub(crate) fn resume_at(&mut self, offset: usize, subscriptions: &[Subscription]) -> Result<(), ULogError> {
+        if self.state != State::DATA {
+            return Err(ULogError::ParseError(format!(
+                "Can only resume a parser in the data section, not in {:?}.",
+                self.state
+            )));
         }
-    }
-}
 
-impl SubscriptionFilter {
-    pub fn new(subscr_names: impl IntoIterator<Item = String>) -> Self {
-        let names: HashSet<String> = subscr_names.into_iter().collect::<HashSet<_>>();
-        Self {
-            allowed_subscription_names: Some(names),
-            allowed_subscription_ids: Some(HashSet::new()),
+        self.datastream.seek_to(offset)?;
+        self.subscriptions.clear();
+        self.message_sizes.clear();
+        self.timestamp_clocks.clear();
+        self.sequence.clear();
+        self.subscription_filter.clear_ids();
+        for sub in subscriptions {
+            self.add_subscription(sub)?;
         }
-    }
 
-    fn update_ids(&mut self, sub: &Subscription) {
-        // Because msg_ids are not known ahead of time the API specifies allowed subscriptions by name.
-        // Once the AddSubscription messages come in, then we can convert the strings names to msg_ids
-        // to more efficiently filter the subscriptions.
-        if let Some(allowed_subscription_names) = &self.allowed_subscription_names {
-            if allowed_subscription_names.contains(&sub.message_name) {
-                // Unwrap is safe here because of the initialisation code in set_allowed_subscription_names().
-                self.allowed_subscription_ids
-                    .as_mut()
-                    .unwrap()
-                    .insert(sub.msg_id);
-            }
-        }
-    }
-
-    fn is_allowed(&self, msg_id: u16) -> bool {
-        match &self.allowed_subscription_ids {
-            None => true,
-            Some(set) => set.contains(&msg_id),
-        }
+        Ok(())
     }
 }
 
+/// The phase of the log a parser is in, returned by `ULogParser::state()`.
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
-enum State {
+pub enum State {
+    /// The file header is yet to be read.
     HEADER = 0,
+    /// In the definitions section, before the first subscription.
     DEFINITIONS = 1,
+    /// In the data section, from the first subscription.
     DATA = 2,
+    /// At the end of the log, or of its data before any appended data.
     EOF = 3,
+    /// Stopped by an error which the parser can't continue from.
     ERROR = 10,
 }
 
@@ -93,10 +119,21 @@ impl<R: Read> Iterator for ULogParser<R> {
     type Item = Result<msg::UlogMessage, ULogError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.next_sub() {
-            Ok(Some(data)) => Some(Ok(data)),
-            Ok(None) => None, // Iterator exhausted.
-            Err(e) => Some(Err(e)),
+        loop {
+            match self.next_sub().and_then(|msg| self.check_strict(msg)) {
+                Ok(Some(data)) => {
+                    self.validation_report.message_counts.record(&data);
+                    if self.emits(&data) {
+                        return Some(Ok(data));
+                    }
+                }
+                Ok(None) => return None, // Iterator exhausted.
+                Err(e) => {
+                    self.metrics.incr_error(&e);
+                    self.validation_report.message_counts.skipped_on_error += 1;
+                    return Some(Err(e));
+                }
+            }
         }
     }
 }
@@ -106,9 +143,9 @@ impl<R: Read> ULogParser<R> {
         Ok(ULogParser {
             state: State::HEADER,
             file_header: None,
-            overridden_params: HashSet::new(),
             formats: HashMap::new(),
             subscriptions: HashMap::new(),
+            message_sizes: HashMap::new(),
             message_name_with_multi_id: HashSet::new(),
             subscription_filter: SubscriptionFilter::default(),
             datastream: DataStream::new(reader),
@@ -116,14 +153,34 @@ impl<R: Read> ULogParser<R> {
             include_header: false,
             include_timestamp: false,
             include_padding: false,
+            include_timestamp_overrides: HashMap::new(),
+            include_padding_overrides: HashMap::new(),
+            ignore_invalid_appended_offsets: false,
+            error_on_message_size_mismatch: false,
+            duplicate_subscription_policy: DuplicateSubscriptionPolicy::default(),
+            max_subscriptions: None,
+            timestamp_repair: None,
+            timestamp_clocks: HashMap::new(),
+            sequence: SequenceTracker::default(),
+            resync_on_error: false,
+            strict: false,
+            emit_ignored: false,
+            emit_unhandled: false,
+            recover_format_errors: false,
+            damaged_formats: HashSet::new(),
+            validation_report: ValidationReport::default(),
+            decoders: DecoderRegistry::new(),
+            metrics: Box::new(NoopMetrics),
+            definitions_cache: None,
+            cancellation: None,
+            cancelled: false,
+            queued_definitions: VecDeque::new(),
+            pending_message: None,
         })
     }
 
-    pub(crate) fn set_allowed_subscription_names(
-        &mut self,
-        subscr_names: impl IntoIterator<Item = String>,
-    ) {
-        self.subscription_filter = SubscriptionFilter::new(subscr_names);
+    pub(crate) fn set_subscription_filter(&mut self, filter: SubscriptionFilter) {
+        self.subscription_filter = filter;
     }
 
     /// Deprecated. Use `ULogParserBuilder::set_subscription_allow_list()` instead.
@@ -140,6 +197,137 @@ impl<R: Read> ULogParser<R> {
         }
     }
 
+    /// Returns the offsets of the fields of a format within its messages, resolving nested formats among those
+    /// defined so far.  See `def::Format::layout()`.
+    pub fn get_layout(&self, message_name: &str) -> Result<def::Layout, ULogError> {
+        self.get_format(message_name)?.layout(&self.formats)
+    }
+
+    pub(crate) fn in_data_section(&self) -> bool {
+        self.state == State::DATA
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Returns what the parser has learned from the messages read so far, so that parsing can be resumed from the
+    /// next message with `ULogParserBuilder::resume()`.  See the `state` module.
+    ///
+    /// Returns `ULogError::InvalidParserState` unless the parser is in the definitions or data section, and between
+    /// messages, i.e. not holding definitions read from the definitions cache which are yet to be returned.
+    pub fn snapshot(&self) -> Result<ParserState, ULogError> {
+        let (State::DEFINITIONS | State::DATA, Some(file_header)) = (self.state, self.file_header) else {
+            return Err(ULogError::InvalidParserState(format!(
+                "Can only snapshot a parser in the definitions or data section, not in {:?}.",
+                self.state
+            )));
+        };
+        if !self.queued_definitions.is_empty() || self.pending_message.is_some() {
+            return Err(ULogError::InvalidParserState(
+                "Can't snapshot a parser which is returning cached definitions.".to_owned(),
+            ));
+        }
+
+        let mut formats: Vec<def::Format> = self.formats.values().map(|format| (**format).clone()).collect();
+        formats.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut subscriptions: Vec<msg::Subscription> = self.subscriptions.values().cloned().collect();
+        subscriptions.sort_by_key(|sub| sub.msg_id);
+        let mut multi_id_topics: Vec<String> = self.message_name_with_multi_id.iter().cloned().collect();
+        multi_id_topics.sort();
+        let mut damaged_formats: Vec<String> = self.damaged_formats.iter().cloned().collect();
+        damaged_formats.sort();
+        let mut timestamp_clocks: Vec<(u16, TimestampClock)> =
+            self.timestamp_clocks.iter().map(|(msg_id, clock)| (*msg_id, clock.clone())).collect();
+        timestamp_clocks.sort_by_key(|(msg_id, _)| *msg_id);
+
+        Ok(ParserState {
+            state: self.state,
+            offset: self.datastream.num_bytes_read as u64,
+            file_header,
+            appended_data_offset: self.max_bytes_to_read.map(|offset| offset as u64),
+            formats,
+            subscriptions,
+            multi_id_topics,
+            damaged_formats,
+            timestamp_clocks,
+        })
+    }
+
+    // Continues from `state`, with the reader at its offset in the log.
+    pub(crate) fn restore(&mut self, state: ParserState) -> Result<(), ULogError> {
+        self.state = state.state;
+        self.file_header = Some(state.file_header);
+        self.datastream.num_bytes_read = usize::try_from(state.offset)
+            .map_err(|_| ULogError::InvalidParserState(format!("The offset {} is too large.", state.offset)))?;
+        self.max_bytes_to_read = state
+            .appended_data_offset
+            .map(usize::try_from)
+            .transpose()
+            .map_err(|_| ULogError::InvalidParserState("The appended data offset is too large.".to_owned()))?;
+        for format in state.formats {
+            self.formats.insert(format.name.clone(), Shared::new(format));
+        }
+        self.message_name_with_multi_id.extend(state.multi_id_topics);
+        self.damaged_formats.extend(state.damaged_formats);
+        for sub in &state.subscriptions {
+            self.add_subscription(sub)?;
+        }
+        // After the subscriptions, as adding a subscription resets its clock.
+        self.timestamp_clocks.extend(state.timestamp_clocks);
+        Ok(())
+    }
+
+    /// Returns the problems found in the messages read so far.
+    pub fn validation_report(&self) -> &ValidationReport {
+        &self.validation_report
+    }
+
+    /// Returns an estimate of the heap memory, in bytes, held by the parser's formats, subscriptions and
+    /// validation report.  Buffers for the message being parsed are not included.
+    pub fn approx_memory_usage(&self) -> usize {
+        fn table<K, V>(capacity: usize) -> usize {
+            // Each entry of a hash table has one control byte.
+            capacity * (std::mem::size_of::<K>() + std::mem::size_of::<V>() + 1)
+        }
+
+        let formats: usize = self
+            .formats
+            .values()
+            .map(|format| {
+                std::mem::size_of::<def::Format>()
+                    + format.name.len()
+                    + format.fields.capacity() * std::mem::size_of::<def::Field>()
+                    + format
+                        .fields
+                        .iter()
+                        .map(|field| match &field.r#type.base_type {
+                            BaseType::OTHER(name) => field.name.len() + name.capacity(),
+                            _ => field.name.len(),
+                        })
+                        .sum::<usize>()
+            })
+            .sum();
+
+        let subscriptions: usize = self.subscriptions.values().map(|sub| sub.message_name.capacity()).sum();
+        let multi_id_names: usize = self.message_name_with_multi_id.iter().map(String::capacity).sum();
+        let duplicates = &self.validation_report.duplicate_subscriptions;
+
+        table::<Shared<str>, Shared<def::Format>>(self.formats.capacity())
+            + formats
+            + table::<u16, msg::Subscription>(self.subscriptions.capacity())
+            + subscriptions
+            + table::<u16, (usize, usize)>(self.message_sizes.capacity())
+            + table::<String, ()>(self.message_name_with_multi_id.capacity())
+            + multi_id_names
+            + self.subscription_filter.approx_memory_usage()
+            + duplicates.capacity() * std::mem::size_of::<DuplicateSubscription>()
+            + duplicates
+                .iter()
+                .map(|d| d.previous.message_name.capacity() + d.duplicate.message_name.capacity())
+                .sum::<usize>()
+    }
+
     pub fn get_subscription(&self, msg_id: u16) -> Result<&msg::Subscription, ULogError> {
         match self.subscriptions.get(&msg_id) {
             None => Err(UndefinedSubscription(msg_id)),
@@ -153,11 +341,56 @@ impl<R: Read> ULogParser<R> {
         Ok(MessageBuf::from_vec(message))
     }
 
+    // Reads a message, unless it will be dropped by `next()`, in which case it is skipped without being copied, and
+    // None is returned.  These are the data of topics left out by the subscription allow list, and unless they are
+    // emitted, the messages of the data section which the parser doesn't interpret.
+    fn read_message_unless_skipped(
+        &mut self,
+        header: &ULogMessageHeader,
+    ) -> Result<Option<MessageBuf>, ULogError> {
+        let msg_size = header.msg_size as usize;
+        if self.state != State::DATA {
+            return self.read_message(msg_size).map(Some);
+        }
+
+        match header.msg_type {
+            ULogMessageType::DATA if msg_size >= 2 => {
+                let msg_id = self.datastream.read_u16()?;
+                if self.subscriptions.contains_key(&msg_id) && !self.subscription_filter.is_allowed(msg_id) {
+                    self.datastream.skip(msg_size - 2)?;
+                    return Ok(None);
+                }
+
+                let mut message = vec![0; msg_size];
+                message[..2].copy_from_slice(&msg_id.to_le_bytes());
+                self.datastream.read_exact(&mut message[2..])?;
+                Ok(Some(MessageBuf::from_vec(message)))
+            }
+            ULogMessageType::FORMAT | ULogMessageType::FLAG_BITS if !self.emit_unhandled => {
+                self.datastream.skip(msg_size)?;
+                Ok(None)
+            }
+            _ => self.read_message(msg_size).map(Some),
+        }
+    }
+
+    // Whether `msg` is returned by `next()`, rather than only counted.
+    fn emits(&self, msg: &UlogMessage) -> bool {
+        match msg {
+            UlogMessage::Ignored { .. } => self.emit_ignored,
+            // Subscription removals are returned regardless, so that consumers tracking msg_ids, e.g. `ULogWriter`,
+            // don't mistake a reused msg_id for the topic it replaced.
+            UlogMessage::Unhandled { .. } => self.emit_unhandled || msg.removed_subscription().is_some(),
+            _ => true,
+        }
+    }
+
     #[allow(clippy::single_match_else)]
     fn next_sub(&mut self) -> Result<Option<msg::UlogMessage>, ULogError> {
         if self.state == State::HEADER {
             match self.read_file_header() {
                 Ok(header) => {
+                    self.metrics.incr_msg(ULogMessageType::HEADER, MAGIC.len() + 9);
                     self.file_header = Some(header);
                     self.state = State::DEFINITIONS;
 
@@ -175,56 +408,60 @@ impl<R: Read> ULogParser<R> {
             }
         }
 
-        if self.state == State::EOF {
-            return Ok(None);
+        if let Some(msg) = self.queued_definitions.pop_front() {
+            return msg.map(Some);
         }
 
-        // ⚠️ ULOG files can contain binary crash dumps at offsets determined by the FLAG_BITS message.
-        // In such cases self.max_bytes_to_read will contain the offset in the stream where the crash dump begins.
-        // We must return EOF when we reach this limit to avoid attempting to parse invalid ULOG data.
-        let max_bytes_to_read = self.max_bytes_to_read;
-
-        if let Some(max_bytes_to_read) = max_bytes_to_read {
-            if self.datastream.num_bytes_read >= max_bytes_to_read {
-                self.state = State::EOF;
-                return Ok(None);
-            }
-        }
-
-        let (message_type, message_buf) = match self.read_message_header()? {
-            None => {
-                self.state = State::EOF;
-                return Ok(None);
-            }
-            Some(header) => (
-                header.msg_type,
-                self.read_message(header.msg_size as usize)?,
-            ),
+        let offset = self.datastream.num_bytes_read;
+
+        let (message_type, message_buf) = match self.pending_message.take() {
+            Some(pending) => pending,
+            None => match self.next_message_header()? {
+                None => return Ok(None),
+                Some(header) => match self.read_message_unless_skipped(&header)? {
+                    Some(message_buf) => (header.msg_type, message_buf),
+                    // Returned without the contents, to be counted and dropped by `next()`.
+                    None if matches!(header.msg_type, ULogMessageType::DATA) => {
+                        return Ok(Some(UlogMessage::Ignored {
+                            msg_type: header.msg_type.into(),
+                        }));
+                    }
+                    None => {
+                        return Ok(Some(UlogMessage::Unhandled {
+                            msg_type: header.msg_type.into(),
+                            message_contents: Vec::new(),
+                        }));
+                    }
+                },
+            },
         };
 
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("ulog_message", msg_type = ?message_type, offset).entered();
+
         match self.state {
             State::DEFINITIONS => {
+                // The FLAG_BITS message is parsed as it's read, since it determines where the log ends.
+                if !matches!(message_type, ULogMessageType::FLAG_BITS | ULogMessageType::ADD_SUBSCRIPTION) {
+                    if let Some(cache) = self.definitions_cache.take() {
+                        self.read_definitions(&cache, message_type, message_buf.into_remaining_bytes())?;
+                        return self.queued_definitions.pop_front().transpose();
+                    }
+                }
+
                 let msg = self.parse_definition(message_type, message_buf)?;
 
                 match msg {
                     UlogMessage::FormatDefinition(ref format) => {
-                        if format.name.contains("heartbeat") {
-                            println!("Heartbeat {format}");
-                        }
+                        diag::trace!("FORMAT {format}");
 
                         self.formats.insert(format.name.clone(), Shared::new(format.clone()));
                     }
                     UlogMessage::AddSubscription(ref sub) => {
-                        self.subscriptions.insert(sub.msg_id, sub.clone());
-                        self.subscription_filter.update_ids(sub);
-
-                        if sub.multi_id > 0 {
-                            self.message_name_with_multi_id
-                                .insert(sub.message_name.clone());
-                        }
-
                         // Now that we've seen the first subscription message we can advance to state 'DATA.'
                         self.state = State::DATA;
+
+                        self.add_subscription(sub)?;
                     }
                     _ => (),
                 }
@@ -232,20 +469,48 @@ impl<R: Read> ULogParser<R> {
                 return Ok(Some(msg));
             }
             State::DATA => {
-                let mut msg = self.parse_data(message_type, message_buf)?;
+                // Kept to be searched for a SYNC message if the message turns out to be corrupt.
+                let contents = self.resync_on_error.then(|| message_buf.as_bytes().to_vec());
+                let mut msg = match self.parse_data(message_type, message_buf) {
+                    Ok(msg) => msg,
+                    Err(err) if self.resync_on_error => {
+                        return self.resync(offset, &err, &contents.unwrap_or_default());
+                    }
+                    Err(err) => return Err(err),
+                };
 
                 match msg {
                     UlogMessage::AddSubscription(ref sub) => {
-                        self.subscriptions.insert(sub.msg_id, sub.clone());
-                        self.subscription_filter.update_ids(sub);
-
-                        if sub.multi_id > 0 {
-                            self.message_name_with_multi_id
-                                .insert(sub.message_name.clone());
+                        self.add_subscription(sub)?;
+                    }
+                    UlogMessage::Unhandled { msg_type: b'S', ref message_contents } => {
+                        let valid = message_contents[..] == SYNC_MAGIC;
+                        if !valid {
+                            diag::warn!("SYNC message at offset {offset} has invalid magic bytes.");
+                        }
+                        self.validation_report.sync.record(offset as u64, valid);
+                        if valid {
+                            self.sequence.sync(offset as u64);
                         }
                     }
                     UlogMessage::LoggedData(ref mut logged_data) => {
-                        logged_data.filter_fields(self.include_timestamp, self.include_padding);
+                        if let Some(sub) = self.subscriptions.get(&logged_data.msg_id) {
+                            self.sequence.record(
+                                sub,
+                                logged_data.timestamp,
+                                offset as u64..self.datastream.num_bytes_read as u64,
+                                &mut self.validation_report.sequence_issues,
+                            );
+                        }
+
+                        let topic = &logged_data.data.name;
+                        let include_timestamp = self.includes_timestamp(topic);
+                        let include_padding = self.includes_padding(topic);
+                        logged_data.filter_fields(include_timestamp, include_padding);
+
+                        if let Some(strategy) = self.timestamp_repair {
+                            self.repair_timestamp(strategy, logged_data);
+                        }
                     }
                     _ => {}
                 }
@@ -261,6 +526,224 @@ impl<R: Read> ULogParser<R> {
         }
     }
 
+    // In strict mode, returns an error in place of a message which the parser could not interpret.
+    fn check_strict(&self, msg: Option<UlogMessage>) -> Result<Option<UlogMessage>, ULogError> {
+        if !self.strict {
+            return Ok(msg);
+        }
+        let section = if self.state == State::DEFINITIONS { "definitions" } else { "data" };
+        match &msg {
+            // SYNC and REMOVE_SUBSCRIPTION messages are interpreted, though returned as `Unhandled`.
+            Some(UlogMessage::Unhandled { msg_type, .. }) if !matches!(msg_type, b'S' | b'R') => {
+                Err(ULogError::UninterpretedMessage(format!(
+                    "Unhandled message of type '{}' in the {section} section.",
+                    char::from(*msg_type)
+                )))
+            }
+            Some(UlogMessage::UnknownTyped(unknown)) => Err(ULogError::UninterpretedMessage(format!(
+                "Unknown message type 0x{:02X} in the {section} section.",
+                unknown.msg_type
+            ))),
+            _ => Ok(msg),
+        }
+    }
+
+    // Skips from the corrupt message at `start` to the next SYNC message, which is returned in its place, and records
+    // the skipped bytes.  The search starts after the header of the corrupt message, at its `contents`, which are put
+    // back to be read again:  if its size is corrupt, its contents may hold the SYNC message and those after it.
+    fn resync(&mut self, start: usize, err: &ULogError, contents: &[u8]) -> Result<Option<UlogMessage>, ULogError> {
+        let mut pattern = vec![SYNC_MAGIC.len() as u8, 0, b'S'];
+        pattern.extend_from_slice(&SYNC_MAGIC);
+        self.datastream.unread(contents);
+
+        let mut window = vec![0; pattern.len()];
+        let mut scanned = 0;
+        let end = loop {
+            if self.max_bytes_to_read.is_some_and(|max| self.datastream.num_bytes_read >= max) {
+                break None;
+            }
+            let mut byte = [0];
+            if self.datastream.read_exact(&mut byte)? == 0 {
+                self.datastream.num_bytes_read -= 1;
+                break None;
+            }
+            window.rotate_left(1);
+            window[pattern.len() - 1] = byte[0];
+            scanned += 1;
+            if scanned >= pattern.len() && window == pattern {
+                break Some(self.datastream.num_bytes_read - pattern.len());
+            }
+        };
+
+        diag::warn!("Skipping corrupt data from offset {start}: {err}");
+        self.validation_report.message_counts.skipped_on_error += 1;
+        self.validation_report.resync_regions.push(ResyncRegion {
+            start: start as u64,
+            end: end.unwrap_or(self.datastream.num_bytes_read) as u64,
+            error: err.to_string(),
+        });
+
+        match end {
+            Some(offset) => {
+                self.metrics.incr_msg(ULogMessageType::SYNC, pattern.len());
+                self.validation_report.sync.record(offset as u64, true);
+                self.sequence.sync(offset as u64);
+                Ok(Some(UlogMessage::Unhandled {
+                    msg_type: b'S',
+                    message_contents: SYNC_MAGIC.to_vec(),
+                }))
+            }
+            None => {
+                self.state = State::EOF;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Reads the rest of the definitions section without parsing it, and queues the cached messages of the section,
+    /// or if it isn't cached, the parsed messages.
+    fn read_definitions(
+        &mut self,
+        cache: &DefinitionsCache,
+        first_type: ULogMessageType,
+        first_contents: Vec<u8>,
+    ) -> Result<(), ULogError> {
+        let mut raw = vec![(first_type, first_contents)];
+        while let Some(header) = self.next_message_header()? {
+            let message_buf = self.read_message(header.msg_size as usize)?;
+            if let ULogMessageType::ADD_SUBSCRIPTION = header.msg_type {
+                self.pending_message = Some((header.msg_type, message_buf));
+                break;
+            }
+            raw.push((header.msg_type, message_buf.into_remaining_bytes()));
+        }
+
+        let key = DefinitionsCache::key(&raw);
+        if let Some(messages) = cache.get(key) {
+            self.queued_definitions = messages.iter().cloned().map(Ok).collect();
+        } else {
+            self.queued_definitions = raw
+                .into_iter()
+                .map(|(msg_type, contents)| self.parse_definition(msg_type, MessageBuf::from_vec(contents)))
+                .collect();
+
+            if self.queued_definitions.iter().all(Result::is_ok) {
+                let messages = self.queued_definitions.iter().filter_map(|msg| msg.as_ref().ok().cloned());
+                cache.insert(key, messages.collect());
+            }
+        }
+
+        for msg in self.queued_definitions.iter().flatten() {
+            if let UlogMessage::FormatDefinition(format) = msg {
+                self.formats.insert(format.name.clone(), Shared::new(format.clone()));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn includes_timestamp(&self, topic: &str) -> bool {
+        self.include_timestamp_overrides
+            .get(topic)
+            .copied()
+            .unwrap_or(self.include_timestamp)
+    }
+
+    fn includes_padding(&self, topic: &str) -> bool {
+        self.include_padding_overrides
+            .get(topic)
+            .copied()
+            .unwrap_or(self.include_padding)
+    }
+
+    fn add_subscription(&mut self, sub: &msg::Subscription) -> Result<(), ULogError> {
+        if let Some(limit) = self.max_subscriptions {
+            if self.subscriptions.len() >= limit && !self.subscriptions.contains_key(&sub.msg_id) {
+                return Err(ULogError::SubscriptionLimitExceeded(format!(
+                    "Can't subscribe to `{}` (msg_id {}), {limit} subscriptions are already held.",
+                    sub.message_name, sub.msg_id
+                )));
+            }
+        }
+
+        if let Some(previous) = self.subscriptions.get(&sub.msg_id) {
+            let policy = self.duplicate_subscription_policy;
+            if policy == DuplicateSubscriptionPolicy::Warn {
+                diag::warn!(
+                    "Duplicate subscription for msg_id {}: `{}` (multi_id {}) replaces `{}` (multi_id {}).",
+                    sub.msg_id,
+                    sub.message_name,
+                    sub.multi_id,
+                    previous.message_name,
+                    previous.multi_id
+                );
+            }
+
+            self.validation_report.duplicate_subscriptions.push(DuplicateSubscription {
+                previous: previous.clone(),
+                duplicate: sub.clone(),
+            });
+
+            if policy == DuplicateSubscriptionPolicy::Error {
+                return Err(ULogError::DuplicateSubscription(sub.msg_id));
+            }
+        }
+
+        self.subscriptions.insert(sub.msg_id, sub.clone());
+        self.subscription_filter.update_ids(sub);
+        self.timestamp_clocks.remove(&sub.msg_id);
+        self.sequence.remove(sub.msg_id);
+
+        if sub.multi_id > 0 {
+            self.message_name_with_multi_id
+                .insert(sub.message_name.clone());
+        }
+
+        // If the size can't be computed, e.g. because a nested format is undefined,
+        // the error will be reported when the first LOGGED_DATA message is parsed.
+        // The msg_id may have been used by another subscription, so any previous size is discarded.
+        self.message_sizes.remove(&sub.msg_id);
+        if let Ok(format) = self.get_format(&sub.message_name) {
+            if let (Ok(min_size), Ok(max_size)) =
+                (format.min_byte_size(&self.formats), format.byte_size(&self.formats))
+            {
+                self.message_sizes.insert(sub.msg_id, (min_size, max_size));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn repair_timestamp(&mut self, strategy: TimestampRepair, logged_data: &mut LoggedData) {
+        let clock = self.timestamp_clocks.entry(logged_data.msg_id).or_default();
+        let (adjusted, record) = clock.repair(strategy, logged_data.timestamp);
+
+        if record {
+            diag::debug!(
+                "Repaired timestamp of `{}` from {} to {adjusted}.",
+                logged_data.data.name,
+                logged_data.timestamp
+            );
+            self.validation_report.timestamp_adjustments.push(TimestampAdjustment {
+                message_name: logged_data.data.name.to_string(),
+                multi_id: self.subscriptions.get(&logged_data.msg_id).map_or(0, |sub| sub.multi_id),
+                original: logged_data.timestamp,
+                adjusted,
+            });
+        }
+        if adjusted != logged_data.timestamp {
+            logged_data.set_timestamp(adjusted);
+        }
+    }
+
+    fn remove_subscription(&mut self, msg_id: u16) {
+        self.subscriptions.remove(&msg_id);
+        self.timestamp_clocks.remove(&msg_id);
+        self.sequence.remove(msg_id);
+        self.message_sizes.remove(&msg_id);
+        self.subscription_filter.remove_id(msg_id);
+    }
+
     pub fn parse_data(
         &mut self,
         message_type: ULogMessageType,
@@ -274,16 +757,38 @@ impl<R: Read> ULogParser<R> {
             }
             ULogMessageType::REMOVE_SUBSCRIPTION => {
                 let msg_id = message_buf.take_u16()?;
-                self.subscriptions.remove(&msg_id);
+                self.remove_subscription(msg_id);
+
+                // Keep the msg_id in the contents, so the message can be re-encoded.
+                let mut message_contents = msg_id.to_le_bytes().to_vec();
+                message_contents.extend(message_buf.into_remaining_bytes());
                 Ok(UlogMessage::Unhandled {
                     msg_type: message_type.into(),
-                    message_contents: message_buf.into_remaining_bytes(),
+                    message_contents,
                 })
             }
             ULogMessageType::DATA => {
                 let msg_id = message_buf.take_u16()?;
                 if let Ok(sub) = self.get_subscription(msg_id) {
                     if self.subscription_filter.is_allowed(sub.msg_id) {
+                        #[cfg(feature = "tracing")]
+                        let _span = tracing::trace_span!(
+                            "logged_data",
+                            topic = %sub.message_name,
+                            multi_id = sub.multi_id,
+                            msg_id
+                        )
+                        .entered();
+
+                        self.metrics.incr_topic(&sub.message_name, sub.multi_id, message_buf.len() + 5);
+                        if self.uses_damaged_format(&sub.message_name) {
+                            let mut message_contents = msg_id.to_le_bytes().to_vec();
+                            message_contents.extend(message_buf.into_remaining_bytes());
+                            return Ok(UlogMessage::Unhandled {
+                                msg_type: message_type.into(),
+                                message_contents,
+                            });
+                        }
                         let logged_data = self.parse_data_message(sub, message_buf)?;
 
                         return Ok(msg::UlogMessage::LoggedData(logged_data));
@@ -312,11 +817,12 @@ impl<R: Read> ULogParser<R> {
                     msg: String::from_utf8(message_buf.into_remaining_bytes())?,
                 }))
             }
-            ULogMessageType::DROPOUT => Ok(msg::UlogMessage::DropoutMark(Dropout {
-                duration: message_buf.take_u16()?,
-            })),
-            // FIXME: Implement SYNC
-            //ULogMessageType::SYNC => {}
+            ULogMessageType::DROPOUT => {
+                let duration = message_buf.take_u16()?;
+                self.metrics.incr_dropout(duration);
+                Ok(msg::UlogMessage::DropoutMark(Dropout { duration }))
+            }
+            // SYNC messages are returned as `Unhandled`, and counted in the validation report by `next_sub()`.
             ULogMessageType::PARAMETER => {
                 let param = self.parse_parameter(message_buf)?;
                 Ok(msg::UlogMessage::Parameter(param))
@@ -333,8 +839,9 @@ impl<R: Read> ULogParser<R> {
                 let multi_info = self.parse_multi_info(message_buf)?;
                 Ok(msg::UlogMessage::MultiInfo(multi_info))
             }
+            ULogMessageType::UNKNOWN(byte) => self.decoders.decode(byte, message_buf),
             _ => {
-                log::debug!("Received unhandled message type {message_type:?}. Ignoring.");
+                diag::debug!("Received unhandled message type {message_type:?}. Ignoring.");
                 Ok(UlogMessage::Unhandled {
                     msg_type: message_type.into(),
                     message_contents: message_buf.into_remaining_bytes(),
@@ -362,34 +869,281 @@ impl<R: Read> ULogParser<R> {
         })
     }
 
+    /// Returns the remaining messages without decoding them, for tools which only split, route or copy messages.
+    ///
+    /// Only the framing is read, along with the `FLAG_BITS` message, so that reading stops at any appended data.
+    /// The file header is returned as a `HEADER` message if `include_header` is set, and hasn't already been read.
+    pub fn raw_messages(self) -> RawMessages<R> {
+        RawMessages {
+            parser: self,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Returns the remaining `LoggedData` messages, skipping the other messages.  Errors are passed through.
+    pub fn data_only(self) -> impl Iterator<Item = Result<LoggedData, ULogError>> {
+        self.filter_map(|msg| match msg {
+            Ok(UlogMessage::LoggedData(data)) => Some(Ok(data)),
+            Ok(_) => None,
+            Err(err) => Some(Err(err)),
+        })
+    }
+
+    /// Returns the data of the remaining messages of `topic`, of all its instances.  Set a subscription allow list
+    /// on the builder as well, so that the other topics aren't decoded only to be skipped.
+    pub fn topic(self, topic: impl Into<String>) -> impl Iterator<Item = Result<inst::Format, ULogError>> {
+        let topic = topic.into();
+        self.data_only().filter_map(move |data| match data {
+            Ok(data) if *data.data.name == *topic => Some(Ok(data.data)),
+            Ok(_) => None,
+            Err(err) => Some(Err(err)),
+        })
+    }
+
+    /// Returns the remaining logged strings, tagged or not.
+    pub fn logged_strings(self) -> impl Iterator<Item = Result<LoggedString, ULogError>> {
+        self.filter_map(|msg| match msg {
+            Ok(UlogMessage::LoggedString(log) | UlogMessage::TaggedLoggedString(log)) => Some(Ok(log)),
+            Ok(_) => None,
+            Err(err) => Some(Err(err)),
+        })
+    }
+
+    /// Returns the remaining messages along with their timestamps.  See `UlogMessage::timestamp()`.
+    pub fn with_timestamps(self) -> impl Iterator<Item = Result<(Option<u64>, UlogMessage), ULogError>> {
+        self.map(|msg| msg.map(|msg| (msg.timestamp(), msg)))
+    }
+
+    /// Returns the remaining messages in batches, each read within about `budget`, e.g. a frame of a GUI which loads
+    /// the log incrementally.  See `ChunksBudgeted`.
+    pub fn chunks_budgeted(self, budget: Duration) -> ChunksBudgeted<R> {
+        ChunksBudgeted { parser: self, budget }
+    }
+
+    /// Reads the rest of the log, and returns the values of its `MultiInfo` messages by key, in the order they were
+    /// logged.  A message which continues the previous value of its key is appended to it when both are arrays of
+    /// the same type, e.g. the parts of a long string, and is a value of its own otherwise.
+    pub fn collect_multi_info(self) -> Result<HashMap<String, Vec<inst::FieldValue>>, ULogError> {
+        let mut values: HashMap<String, Vec<inst::FieldValue>> = HashMap::new();
+        for msg in self {
+            let UlogMessage::MultiInfo(info) = msg? else {
+                continue;
+            };
+            let key_values = values.entry(info.key).or_default();
+            match key_values.last_mut() {
+                Some(last) if info.is_continued => {
+                    if let Err(value) = last.extend_array(info.value) {
+                        key_values.push(value);
+                    }
+                }
+                _ => key_values.push(info.value),
+            }
+        }
+        Ok(values)
+    }
+
+    /// Like `collect_multi_info()`, keeping only the `char` array values, as strings without trailing NULs, e.g.
+    /// the lines of `perf_counter_preflight`.
+    pub fn collect_multi_info_text(self) -> Result<HashMap<String, Vec<String>>, ULogError> {
+        let mut text = HashMap::new();
+        for (key, values) in self.collect_multi_info()? {
+            let strings: Vec<String> = values
+                .iter()
+                .filter_map(|value| match value {
+                    inst::FieldValue::ArrayChar(chars) => Some(chars.to_string_lossy_trimmed()),
+                    _ => None,
+                })
+                .collect();
+            if !strings.is_empty() {
+                text.insert(key, strings);
+            }
+        }
+        Ok(text)
+    }
+
+    fn next_raw(&mut self, buf: &mut Vec<u8>) -> Result<Option<ULogMessageHeader>, ULogError> {
+        if self.state == State::HEADER {
+            let Ok(header) = self.read_file_header() else {
+                self.state = State::ERROR;
+                return Err(ULogError::InvalidHeader);
+            };
+            self.metrics.incr_msg(ULogMessageType::HEADER, MAGIC.len() + 9);
+            self.file_header = Some(header);
+            self.state = State::DEFINITIONS;
+
+            if self.include_header {
+                *buf = header.to_bytes();
+                return Ok(Some(ULogMessageHeader {
+                    msg_size: MAGIC.len() as u16 + 9,
+                    msg_type: ULogMessageType::HEADER,
+                }));
+            }
+        }
+
+        let Some(header) = self.next_message_header()? else {
+            return Ok(None);
+        };
+
+        buf.resize(header.msg_size as usize, 0);
+        self.datastream.read_exact(buf)?;
+        if self.datastream.eof {
+            self.state = State::ERROR;
+            return Err(ULogError::UnexpectedEndOfFile);
+        }
+
+        if self.state == State::DEFINITIONS {
+            match header.msg_type {
+                ULogMessageType::FLAG_BITS => {
+                    let flag_bits = self.parse_flag_bits(MessageBuf::from_vec(buf.clone()))?;
+                    if flag_bits.has_data_appended() {
+                        self.max_bytes_to_read = self.first_appended_data_offset(&flag_bits)?;
+                    }
+                }
+                ULogMessageType::ADD_SUBSCRIPTION => self.state = State::DATA,
+                _ => {}
+            }
+        }
+
+        Ok(Some(header))
+    }
+
+    // Reads the header of the next message, or returns None at the end of the file or at the appended data.
+    fn next_message_header(&mut self) -> Result<Option<ULogMessageHeader>, ULogError> {
+        if self.state == State::EOF || self.cancelled {
+            return Ok(None);
+        }
+        if self.cancellation.as_ref().is_some_and(CancellationToken::is_cancelled) {
+            self.cancelled = true;
+            return Err(ULogError::Cancelled);
+        }
+
+        // ⚠️ ULOG files can contain binary crash dumps at offsets determined by the FLAG_BITS message.
+        // In such cases self.max_bytes_to_read will contain the offset in the stream where the crash dump begins.
+        // We must return EOF when we reach this limit to avoid attempting to parse invalid ULOG data.
+        let max_bytes_to_read = self.max_bytes_to_read;
+
+        if let Some(max_bytes_to_read) = max_bytes_to_read {
+            if self.datastream.num_bytes_read >= max_bytes_to_read {
+                if self.state == State::DEFINITIONS {
+                    // Appended data can only follow the data section.
+                    self.reject_appended_offset(format!(
+                        "Offset {max_bytes_to_read} points inside the definitions section."
+                    ))?;
+                } else {
+                    self.state = State::EOF;
+                    return Ok(None);
+                }
+            }
+        }
+
+        match self.read_message_header()? {
+            None => {
+                self.state = State::EOF;
+                Ok(None)
+            }
+            Some(header) => {
+                self.check_message_ends_before_appended_data(header.msg_size)?;
+                self.metrics.incr_msg(header.msg_type, header.msg_size as usize + 3);
+                Ok(Some(header))
+            }
+        }
+    }
+
     fn read_message_header(&mut self) -> Result<Option<ULogMessageHeader>, ULogError> {
+        let position = self.datastream.num_bytes_read;
         let msg_size = self.datastream.read_u16()?;
 
         // ⚠️This is the only place where we check for EOF when calling a datastream read method.
         // If we encounter EOF anywhere else, it counts as a true 'Unexpected EOF' and is treated as an error.
         if self.datastream.eof {
+            if let Some(max_bytes_to_read) = self.max_bytes_to_read {
+                if max_bytes_to_read > position {
+                    self.reject_appended_offset(format!(
+                        "Offset {max_bytes_to_read} points past the end of the file ({position} bytes)."
+                    ))?;
+                }
+            }
             return Ok(None);
         }
 
         let msg_type = ULogMessageType::from(self.datastream.read_u8()?);
-        log::trace!("MSG HEADER: {msg_size} {msg_type:?}");
+        diag::trace!("MSG HEADER: {msg_size} {msg_type:?}");
 
         Ok(Some(ULogMessageHeader { msg_size, msg_type }))
     }
 
+    // Verify that a message starting at the current position does not run into the appended data.
+    // A valid offset always falls on a message boundary.
+    fn check_message_ends_before_appended_data(&mut self, msg_size: u16) -> Result<(), ULogError> {
+        if let Some(max_bytes_to_read) = self.max_bytes_to_read {
+            let message_end = self.datastream.num_bytes_read + msg_size as usize;
+
+            if max_bytes_to_read < message_end {
+                self.reject_appended_offset(format!(
+                    "Offset {max_bytes_to_read} points inside a message ending at {message_end}."
+                ))?;
+            }
+        }
+        Ok(())
+    }
+
+    // Either reports an invalid appended data offset as an error, or discards it
+    // if the parser has been configured to ignore invalid offsets.
+    fn reject_appended_offset(&mut self, reason: String) -> Result<(), ULogError> {
+        if self.ignore_invalid_appended_offsets {
+            diag::warn!("Invalid appended data offset. {reason} Ignoring.");
+            self.max_bytes_to_read = None;
+            Ok(())
+        } else {
+            self.state = State::ERROR;
+            Err(ULogError::InvalidAppendedDataOffset(reason))
+        }
+    }
+
+    // Whether the format `name`, or a format nested in it, has fields left out by `recover_format_errors`.
+    fn uses_damaged_format(&self, name: &str) -> bool {
+        if self.damaged_formats.is_empty() {
+            return false;
+        }
+
+        let mut visited = HashSet::new();
+        let mut pending = vec![name];
+        while let Some(name) = pending.pop() {
+            if self.damaged_formats.contains(name) {
+                return true;
+            }
+            if !visited.insert(name) {
+                continue;
+            }
+            if let Some(format) = self.formats.get(name) {
+                pending.extend(format.fields.iter().filter_map(|field| match &field.r#type.base_type {
+                    def::BaseType::OTHER(nested) => Some(nested.as_str()),
+                    _ => None,
+                }));
+            }
+        }
+        false
+    }
+
     fn parse_data_message(
         &self,
         sub: &msg::Subscription,
         mut message_buf: MessageBuf,
     ) -> Result<msg::LoggedData, ULogError> {
         let format = self.get_format(&sub.message_name)?;
-        let _message_len = message_buf.len();
+        self.check_message_size(sub, message_buf.len())?;
+        if !self.message_sizes.contains_key(&sub.msg_id) {
+            // The size couldn't be computed when subscribing, so report why, e.g. a format which contains itself,
+            // before parsing would fail less clearly, or not terminate.
+            format.byte_size(&self.formats)?;
+        }
 
         if !format.fields.iter().any(|f| f.name.as_ref() == "timestamp") {
             return Err(ULogError::MissingTimestamp);
         }
 
-        let mut data_format = self.parse_data_message_sub(format, &mut message_buf)?;
+        let include_padding = self.includes_padding(&sub.message_name);
+        let mut data_format = self.parse_data_message_sub(format, &mut message_buf, include_padding)?;
 
         if self.message_name_with_multi_id.contains(&sub.message_name) {
             data_format.multi_id_index = Some(sub.multi_id);
@@ -400,10 +1154,6 @@ impl<R: Read> ULogParser<R> {
         // See the comment in `parse_data_message_sub()` for more information.
         let timestamp = data_format.timestamp.ok_or(ULogError::MissingTimestamp)?;
 
-        if !message_buf.is_empty() {
-            log::warn!("Leftover bytes in messagebuf after parsing LOGGED_DATA message! Possible data corruption.");
-        }
-
         Ok(msg::LoggedData {
             timestamp,
             msg_id: sub.msg_id,
@@ -411,24 +1161,61 @@ impl<R: Read> ULogParser<R> {
         })
     }
 
+    // Compare the size of a LOGGED_DATA message against the size implied by its format.
+    // A mismatch usually means the format definitions do not match the firmware that wrote the data.
+    fn check_message_size(&self, sub: &msg::Subscription, message_len: usize) -> Result<(), ULogError> {
+        let Some(&(min_size, max_size)) = self.message_sizes.get(&sub.msg_id) else {
+            return Ok(());
+        };
+
+        if (min_size..=max_size).contains(&message_len) {
+            return Ok(());
+        }
+
+        let expected = if min_size == max_size {
+            format!("{max_size}")
+        } else {
+            format!("{min_size}-{max_size}")
+        };
+
+        let description = format!(
+            "LOGGED_DATA message for `{}` (msg_id {}) contains {message_len} bytes, but its format requires {expected} bytes. Possible data corruption or format mismatch.",
+            sub.message_name, sub.msg_id
+        );
+
+        if self.error_on_message_size_mismatch {
+            Err(ULogError::MessageSizeMismatch(description))
+        } else {
+            diag::warn!("{description}");
+            Ok(())
+        }
+    }
+
     fn parse_data_message_sub(
         &self,
         format: Shared<def::Format>,
         message_buf: &mut MessageBuf,
+        include_padding: bool,
     ) -> Result<inst::Format, ULogError> {
         let mut fields: Vec<inst::Field> = Vec::with_capacity(format.fields.len());
+        let mut field_indices: Vec<u16> = Vec::with_capacity(format.fields.len());
         let mut timestamp: Option<u64> = None;
 
-        for field in &format.fields {
+        for (def_index, field) in format.fields.iter().enumerate() {
+            // A message holds at most 64KB, so a format can't define more fields than that.
+            let def_index = u16::try_from(def_index)
+                .map_err(|_| ULogError::ParseError(format!("Too many fields in format {}.", format.name)))?;
+
             // Handle padding fields.
-            if field.name.starts_with("_padding") {
-                if let Some(padding_field) = self.parse_padding(field, message_buf)? {
+            if field.is_padding() {
+                if let Some(padding_field) = self.parse_padding(field, message_buf, include_padding)? {
                     fields.push(padding_field);
+                    field_indices.push(def_index);
                 }
                 continue;
             }
 
-            let value: inst::FieldValue = self.parse_field_value(field, message_buf)?;
+            let value: inst::FieldValue = self.parse_field_value(field, message_buf, include_padding)?;
 
             // ⚠️ Extract the timestamp field if present.
             // According to the ULOG spec, the timestamp for a LOGGED_DATA message is the value of
@@ -447,12 +1234,14 @@ impl<R: Read> ULogParser<R> {
                 r#type: field.r#type.clone(),
                 value,
             });
+            field_indices.push(def_index);
         }
 
         Ok(inst::Format {
             name: format.name.clone(),
             timestamp,
             fields,
+            field_indices,
             // ⚠️ The proper value for this field can't be known at this point in the code.
             // It can be filled out only after we've seen all the subscriptions in the file.
             // We have omitted it here so it can be filled later on if required.
@@ -461,20 +1250,22 @@ impl<R: Read> ULogParser<R> {
         })
     }
 
+    #[allow(clippy::unused_self)]
     fn parse_padding(
         &self,
         field: &def::Field,
         message_buf: &mut MessageBuf,
+        include_padding: bool,
     ) -> Result<Option<inst::Field>, ULogError> {
         let Some(array_size) = field.r#type.array_size else {
-            log::warn!("Encountered padding, and type is scalar. Ignoring.");
+            diag::warn!("Encountered padding, and type is scalar. Ignoring.");
             return Ok(None);
         };
         
         if array_size <= message_buf.len() {
-            log::debug!("Encountered padding, and padding <= message.len(). Ok.");
+            diag::debug!("Encountered padding, and padding <= message.len(). Ok.");
 
-            if self.include_padding {
+            if include_padding {
                 let array = message_buf.advance(array_size)?.to_vec();
                 return Ok( Some( inst::Field {
                     name: Shared::from(field.name.clone()),
@@ -487,8 +1278,8 @@ impl<R: Read> ULogParser<R> {
             }
         } else {
             match message_buf.len() {
-                0 => log::debug!("Encountered padding, and message.len() == 0. Ignoring as per ULOG spec."),
-                _ => log::error!("Encountered padding, and padding > message.len(). Ignoring and hoping for the best"),
+                0 => diag::debug!("Encountered padding, and message.len() == 0. Ignoring as per ULOG spec."),
+                _ => diag::error!("Encountered padding, and padding > message.len(). Ignoring and hoping for the best"),
             }
         }
         Ok(None)
@@ -498,6 +1289,7 @@ impl<R: Read> ULogParser<R> {
         &self,
         field: &def::Field,
         message_buf: &mut MessageBuf,
+        include_padding: bool,
     ) -> Result<inst::FieldValue, ULogError> {
         match field.r#type.array_size {
             None => {
@@ -520,13 +1312,13 @@ impl<R: Read> ULogParser<R> {
                     OTHER(type_name) => {
                         let child_format = self.get_format(type_name)?;
                         ScalarOther(
-                            self.parse_data_message_sub(child_format, message_buf)?
+                            self.parse_data_message_sub(child_format, message_buf, include_padding)?
                                 .into(),
                         )
                     }
                 })
             }
-            Some(array_size) => self.parse_array_field(field, array_size, message_buf),
+            Some(array_size) => self.parse_array_field(field, array_size, message_buf, include_padding),
         }
     }
 
@@ -535,6 +1327,7 @@ impl<R: Read> ULogParser<R> {
         field: &def::Field,
         array_size: usize,
         message_buf: &mut MessageBuf,
+        include_padding: bool,
     ) -> Result<inst::FieldValue, ULogError> {
         use def::BaseType::*;
         use inst::FieldValue::*;
@@ -555,7 +1348,7 @@ impl<R: Read> ULogParser<R> {
             OTHER(type_name) => {
                 let child_format = &self.get_format(type_name)?;
                 ArrayOther(parse_array(array_size, message_buf, |buf| {
-                    self.parse_data_message_sub(child_format.clone(), buf)
+                    self.parse_data_message_sub(child_format.clone(), buf, include_padding)
                 })?)
             }
         })
@@ -588,16 +1381,22 @@ impl<R: Read> ULogParser<R> {
                 let flag_bits = self.parse_flag_bits(message_buf)?;
 
                 if flag_bits.has_data_appended() {
-                    // Stop reading from this stream at the first non-zero appended data offset in the list.
-                    self.max_bytes_to_read = flag_bits
-                        .appended_data_offsets
-                        .iter()
-                        .find(|&&offset| offset > 0)
-                        .map(|&offset| offset as usize);
+                    // Stop reading from this stream at the first valid non-zero appended data offset in the list.
+                    self.max_bytes_to_read = self.first_appended_data_offset(&flag_bits)?;
                 }
 
                 Ok(UlogMessage::FlagBits(flag_bits))
             }
+            ULogMessageType::FORMAT if self.recover_format_errors => {
+                let (format, issues) = parse_format_recovering(message_buf)?;
+                if issues.is_empty() {
+                    self.damaged_formats.remove(&*format.name);
+                } else {
+                    self.damaged_formats.insert(format.name.to_string());
+                    self.validation_report.format_issues.extend(issues);
+                }
+                Ok(msg::UlogMessage::FormatDefinition(format))
+            }
             ULogMessageType::FORMAT => {
                 let format = parse_format(message_buf)?;
                 Ok(msg::UlogMessage::FormatDefinition(format))
@@ -628,11 +1427,10 @@ impl<R: Read> ULogParser<R> {
             }
              */
             ULogMessageType::UNKNOWN(byte) => {
-                log::warn!("Unknown message type: 0x{byte:02X}");
-                Ok(UlogMessage::Unhandled {
-                    msg_type: message_type.into(),
-                    message_contents: message_buf.into_remaining_bytes(),
-                })
+                if !self.decoders.contains(byte) {
+                    diag::warn!("Unknown message type: 0x{byte:02X}");
+                }
+                self.decoders.decode(byte, message_buf)
             }
             _ => {
                 // FIXME: Handle other variants in definitions section.
@@ -644,10 +1442,29 @@ impl<R: Read> ULogParser<R> {
         }
     }
 
+    fn first_appended_data_offset(
+        &mut self,
+        flag_bits: &FlagBits,
+    ) -> Result<Option<usize>, ULogError> {
+        for &offset in flag_bits.appended_data_offsets.iter().filter(|&&offset| offset > 0) {
+            // The FLAG_BITS message directly follows the header, so any offset we have already
+            // read past must point into the header or the definitions section.
+            if offset as usize > self.datastream.num_bytes_read {
+                return Ok(Some(offset as usize));
+            }
+
+            self.reject_appended_offset(format!(
+                "Offset {offset} points inside the header or definitions section."
+            ))?;
+        }
+
+        Ok(None)
+    }
+
     #[allow(clippy::unused_self)]
     fn parse_flag_bits(&self, mut message_buf: MessageBuf) -> Result<FlagBits, ULogError> {
         if message_buf.len() != 40 {
-            log::warn!(
+            diag::warn!(
                 "Length of flag bits >40bytes (Contained {len} extra bytes).  Ignoring.",
                 len = message_buf.len()
             );
@@ -685,9 +1502,9 @@ impl<R: Read> ULogParser<R> {
         let mut tokens = TokenList::from_str(&raw_key);
         let field = parse_field(&mut tokens)?;
 
-        let value: inst::FieldValue = self.parse_field_value(&field, &mut message_buf)?;
+        let value: inst::FieldValue = self.parse_field_value(&field, &mut message_buf, self.include_padding)?;
 
-        log::debug!("INFO {:?} {}:\t{}", field.r#type, &field.name, value);
+        diag::debug!("INFO {:?} {}:\t{}", field.r#type, &field.name, value);
 
         Ok(msg::Info {
             key: field.name.to_string(),
@@ -706,10 +1523,10 @@ impl<R: Read> ULogParser<R> {
         let mut tokens = TokenList::from_str(&raw_key);
         let field = parse_field(&mut tokens)?;
 
-        let value: inst::FieldValue = self.parse_field_value(&field, &mut message_buf)?;
+        let value: inst::FieldValue = self.parse_field_value(&field, &mut message_buf, self.include_padding)?;
 
-        log::debug!("MULTI_INFO {:?} {}:\t{}", field.r#type, &field.name, value);
-        log::debug!("is_continued = {is_continued}");
+        diag::debug!("MULTI_INFO {:?} {}:\t{}", field.r#type, &field.name, value);
+        diag::debug!("is_continued = {is_continued}");
 
         let result: MultiInfo = MultiInfo {
             key: field.name.to_string(),
@@ -743,7 +1560,7 @@ impl<R: Read> ULogParser<R> {
                 }
             };
 
-            log::debug!("INFO {:?} {}:\t{:?}", field.r#type, &field.name, value);
+            diag::debug!("INFO {:?} {}:\t{:?}", field.r#type, &field.name, value);
 
             Ok(msg::Parameter {
                 key: field.name.to_string(),
@@ -779,7 +1596,7 @@ impl<R: Read> ULogParser<R> {
                 }
             };
 
-            log::debug!("INFO {:?} {}:\t{:?}", field.r#type, &field.name, value);
+            diag::debug!("INFO {:?} {}:\t{:?}", field.r#type, &field.name, value);
 
             Ok(msg::DefaultParameter {
                 key: field.name.to_string(),
@@ -791,89 +1608,246 @@ impl<R: Read> ULogParser<R> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct ULogMessageHeader {
     pub msg_size: u16,
     pub msg_type: ULogMessageType,
 }
 
-#[derive(Debug, Copy, Clone)]
-#[repr(u8)]
+/// The undecoded messages of a log, returned by `ULogParser::raw_messages()`.
+///
+/// Each message's contents borrow a buffer which is reused for the next message, so `RawMessages` can't implement
+/// `Iterator`.  Call `next()` in a `while let` loop instead:
+///
+/// ```rust
+/// use yule_log::builder::ULogParserBuilder;
+///
+/// # fn main() -> Result<(), yule_log::errors::ULogError> {
+/// let input = std::fs::read("test_data/input/short_list.ulg")?;
+/// let mut messages = ULogParserBuilder::new(input.as_slice()).build()?.raw_messages();
+///
+/// let mut bytes = 0;
+/// while let Some(message) = messages.next() {
+///     let (header, contents) = message?;
+///     assert_eq!(usize::from(header.msg_size), contents.len());
+///     bytes += 3 + contents.len();
+/// }
+///
+/// assert_eq!(bytes + 16, input.len());
+/// # Ok(())
+/// # }
+/// ```
+pub struct RawMessages<R: Read> {
+    parser: ULogParser<R>,
+    buf: Vec<u8>,
+}
+
+impl<R: Read> RawMessages<R> {
+    /// Returns the offset in the stream of the next message.
+    pub fn position(&self) -> usize {
+        self.parser.datastream.num_bytes_read
+    }
+
+    /// Returns the header and contents of the next message, excluding the 3 byte message header.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Result<(ULogMessageHeader, &[u8]), ULogError>> {
+        match self.parser.next_raw(&mut self.buf) {
+            Ok(Some(header)) => Some(Ok((header, &self.buf))),
+            Ok(None) => None,
+            Err(e) => {
+                self.parser.metrics.incr_error(&e);
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// The messages of a log in batches which each take about a time budget to read, returned by
+/// `ULogParser::chunks_budgeted()`.
+///
+/// Each batch holds at least one message, errors included, and ends once the budget has been spent, or at the end of
+/// the log.  A viewer can keep the iterator between frames, and call `next()` once per frame:
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use yule_log::builder::ULogParserBuilder;
+///
+/// # fn main() -> Result<(), yule_log::errors::ULogError> {
+/// let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+/// let mut chunks = ULogParserBuilder::new(input.as_slice())
+///     .build()?
+///     .chunks_budgeted(Duration::from_millis(16));
+///
+/// // Once per frame:
+/// if let Some(batch) = chunks.next() {
+///     for msg in batch {
+///         let _ = msg?;
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct ChunksBudgeted<R: Read> {
+    parser: ULogParser<R>,
+    budget: Duration,
+}
+
+impl<R: Read> ChunksBudgeted<R> {
+    /// The parser, e.g. for its `validation_report()` so far.
+    pub fn parser(&self) -> &ULogParser<R> {
+        &self.parser
+    }
+
+    pub fn into_parser(self) -> ULogParser<R> {
+        self.parser
+    }
+}
+
+impl<R: Read> Iterator for ChunksBudgeted<R> {
+    type Item = Vec<Result<UlogMessage, ULogError>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = Instant::now();
+        let mut batch = vec![self.parser.next()?];
+        while start.elapsed() < self.budget {
+            match self.parser.next() {
+                Some(msg) => batch.push(msg),
+                None => break,
+            }
+        }
+        Some(batch)
+    }
+}
+
+/// The type of a ULOG message, by the byte of its header.  `HEADER` stands for the file header, which has no type
+/// byte, and `UNKNOWN` for bytes the ULOG spec doesn't define.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
 pub enum ULogMessageType {
-    FORMAT = b'F',
-    DATA = b'D',
-    INFO = b'I',
-    INFO_MULTIPLE = b'M',
-    PARAMETER = b'P',
-    PARAMETER_DEFAULT = b'Q',
-    ADD_SUBSCRIPTION = b'A',
-    REMOVE_SUBSCRIPTION = b'R',
-    SYNC = b'S',
-    DROPOUT = b'O',
-    LOGGING = b'L',
-    LOGGING_TAGGED = b'C',
-    FLAG_BITS = b'B',
+    FORMAT,
+    DATA,
+    INFO,
+    INFO_MULTIPLE,
+    PARAMETER,
+    PARAMETER_DEFAULT,
+    ADD_SUBSCRIPTION,
+    REMOVE_SUBSCRIPTION,
+    SYNC,
+    DROPOUT,
+    LOGGING,
+    LOGGING_TAGGED,
+    FLAG_BITS,
     // ⚠️Header is not a real Ulog 'message' type, but we treat it as one for convenience.
-    HEADER = 254,
-    UNKNOWN(u8) = 255,
+    HEADER,
+    UNKNOWN(u8),
+}
+
+/// The message types of the ULOG spec, with their header bytes.
+const MESSAGE_TYPES: [(ULogMessageType, u8); 13] = [
+    (ULogMessageType::FORMAT, b'F'),
+    (ULogMessageType::DATA, b'D'),
+    (ULogMessageType::INFO, b'I'),
+    (ULogMessageType::INFO_MULTIPLE, b'M'),
+    (ULogMessageType::PARAMETER, b'P'),
+    (ULogMessageType::PARAMETER_DEFAULT, b'Q'),
+    (ULogMessageType::ADD_SUBSCRIPTION, b'A'),
+    (ULogMessageType::REMOVE_SUBSCRIPTION, b'R'),
+    (ULogMessageType::SYNC, b'S'),
+    (ULogMessageType::DROPOUT, b'O'),
+    (ULogMessageType::LOGGING, b'L'),
+    (ULogMessageType::LOGGING_TAGGED, b'C'),
+    (ULogMessageType::FLAG_BITS, b'B'),
+];
+
+/// `MESSAGE_TYPES` by header byte, so that converting the byte of each message header is a lookup.
+const TYPES_BY_BYTE: [Option<ULogMessageType>; 256] = {
+    let mut types = [None; 256];
+    let mut i = 0;
+    while i < MESSAGE_TYPES.len() {
+        let (msg_type, byte) = MESSAGE_TYPES[i];
+        types[byte as usize] = Some(msg_type);
+        i += 1;
+    }
+    types
+};
+
+/// The byte `HEADER` converts to.  No message has it.
+const HEADER_BYTE: u8 = 254;
+
+impl ULogMessageType {
+    /// The message types of the ULOG spec, which the parser reads, in the order of the spec.  `HEADER` and
+    /// `UNKNOWN` are not included.
+    pub fn iter_known() -> impl Iterator<Item = ULogMessageType> {
+        MESSAGE_TYPES.iter().map(|&(msg_type, _)| msg_type)
+    }
+
+    /// Whether the type is one of `iter_known()`.
+    pub fn is_known(self) -> bool {
+        !matches!(self, ULogMessageType::HEADER | ULogMessageType::UNKNOWN(_))
+    }
 }
 
 impl From<u8> for ULogMessageType {
     fn from(byte: u8) -> Self {
-        match byte {
-            b'F' => ULogMessageType::FORMAT,
-            b'D' => ULogMessageType::DATA,
-            b'I' => ULogMessageType::INFO,
-            b'M' => ULogMessageType::INFO_MULTIPLE,
-            b'P' => ULogMessageType::PARAMETER,
-            b'Q' => ULogMessageType::PARAMETER_DEFAULT,
-            b'A' => ULogMessageType::ADD_SUBSCRIPTION,
-            b'R' => ULogMessageType::REMOVE_SUBSCRIPTION,
-            b'S' => ULogMessageType::SYNC,
-            b'O' => ULogMessageType::DROPOUT,
-            b'L' => ULogMessageType::LOGGING,
-            b'C' => ULogMessageType::LOGGING_TAGGED,
-            b'B' => ULogMessageType::FLAG_BITS,
-            _ => ULogMessageType::UNKNOWN(byte),
-        }
+        TYPES_BY_BYTE[usize::from(byte)].unwrap_or(ULogMessageType::UNKNOWN(byte))
     }
 }
 
 impl From<ULogMessageType> for u8 {
     fn from(msg_type: ULogMessageType) -> Self {
         match msg_type {
-            ULogMessageType::FORMAT => b'F',
-            ULogMessageType::DATA => b'D',
-            ULogMessageType::INFO => b'I',
-            ULogMessageType::INFO_MULTIPLE => b'M',
-            ULogMessageType::PARAMETER => b'P',
-            ULogMessageType::PARAMETER_DEFAULT => b'Q',
-            ULogMessageType::ADD_SUBSCRIPTION => b'A',
-            ULogMessageType::REMOVE_SUBSCRIPTION => b'R',
-            ULogMessageType::SYNC => b'S',
-            ULogMessageType::DROPOUT => b'O',
-            ULogMessageType::LOGGING => b'L',
-            ULogMessageType::LOGGING_TAGGED => b'C',
-            ULogMessageType::FLAG_BITS => b'B',
-            ULogMessageType::HEADER => 254,
+            ULogMessageType::HEADER => HEADER_BYTE,
             ULogMessageType::UNKNOWN(byte) => byte,
+            known => MESSAGE_TYPES
+                .iter()
+                .find(|&&(t, _)| t == known)
+                .map(|&(_, byte)| byte)
+                .expect("every known type is in the table"),
         }
     }
 }
 
 impl LoggedData {
-    pub fn filter_fields(&mut self, include_timestamp: bool, include_padding: bool) {
-        self.data.fields.retain(|field| {
-            if field.name.as_ref() == "timestamp" {
-                return include_timestamp;
+    /// Sets the timestamp of the message, including the value of its `timestamp` field, if included.
+    pub fn set_timestamp(&mut self, timestamp: u64) {
+        self.timestamp = timestamp;
+        self.data.timestamp = Some(timestamp);
+
+        if let Some(field) = self.data.fields.iter_mut().find(|f| f.name.as_ref() == "timestamp") {
+            if let inst::FieldValue::ScalarU64(value) = &mut field.value {
+                *value = timestamp;
             }
+        }
+    }
 
-            if field.name.starts_with("_padding") {
-                return include_padding;
-            }
+    /// Removes the top-level timestamp and padding fields, unless included.
+    ///
+    /// `data.field_indices` keeps track of the definition index of each remaining field.
+    pub fn filter_fields(&mut self, include_timestamp: bool, include_padding: bool) {
+        if include_timestamp && include_padding {
+            return;
+        }
 
-            true
-        });
+        let format = &mut self.data;
+        let fields = std::mem::take(&mut format.fields);
+        let field_indices = std::mem::take(&mut format.field_indices);
+        let def_indices = (0..fields.len()).map(|i| field_indices.get(i).copied().unwrap_or(i as u16));
+
+        for (field, def_index) in fields.into_iter().zip(def_indices) {
+            let include = if field.name.as_ref() == "timestamp" {
+                include_timestamp
+            } else if field.name.starts_with("_padding") {
+                include_padding
+            } else {
+                true
+            };
+
+            if include {
+                format.fields.push(field);
+                format.field_indices.push(def_index);
+            }
+        }
     }
 }
 
@@ -889,6 +1863,422 @@ mod tests {
         }
     }
 
+    // Contents of a LOGGED_DATA message for the `my_topic` format used by `test_log()`.
+    fn my_topic_data() -> Vec<u8> {
+        let mut data_message = vec![0x00, 0x00];
+        data_message.extend_from_slice(&1234u64.to_le_bytes());
+        data_message.extend_from_slice(&1.5f32.to_le_bytes());
+        data_message
+    }
+
+    // Builds a small log with the given appended data offset, and three identical LOGGED_DATA messages.
+    // Returns the log bytes and the end position of each message.
+    fn log_with_appended_offset(appended_data_offset: u64) -> (Vec<u8>, Vec<usize>) {
+        test_log(appended_data_offset, vec![my_topic_data(); 3])
+    }
+
+    // Builds a small log containing a single `my_topic` subscription, followed by the given LOGGED_DATA messages.
+    // The data_appended flag is set if `appended_data_offset` is non-zero.
+    // Returns the log bytes and the end position of each message.
+    fn test_log(appended_data_offset: u64, data_messages: Vec<Vec<u8>>) -> (Vec<u8>, Vec<usize>) {
+        let incompat_flag = u8::from(appended_data_offset > 0);
+
+        let mut messages = vec![
+            UlogMessage::Header(FileHeader { version: 1, timestamp: 0 }),
+            UlogMessage::FlagBits(FlagBits {
+                compat_flags: [0; 8],
+                incompat_flags: [incompat_flag, 0, 0, 0, 0, 0, 0, 0],
+                appended_data_offsets: [appended_data_offset, 0, 0],
+            }),
+            UlogMessage::FormatDefinition(
+                parse_format(MessageBuf::from_vec(b"my_topic:uint64_t timestamp;float value;".to_vec()))
+                    .unwrap(),
+            ),
+            UlogMessage::AddSubscription(Subscription {
+                multi_id: 0,
+                msg_id: 0,
+                message_name: "my_topic".to_string(),
+            }),
+        ];
+
+        for message_contents in data_messages {
+            messages.push(UlogMessage::Unhandled { msg_type: b'D', message_contents });
+        }
+
+        let mut bytes = Vec::new();
+        let mut message_ends = Vec::new();
+        for message in &messages {
+            message.encode(&mut bytes).unwrap();
+            message_ends.push(bytes.len());
+        }
+
+        (bytes, message_ends)
+    }
+
+    fn count_logged_data(bytes: Vec<u8>, ignore_invalid_offsets: bool) -> Result<usize, ULogError> {
+        let parser = crate::builder::ULogParserBuilder::new(io::Cursor::new(bytes))
+            .ignore_invalid_appended_offsets(ignore_invalid_offsets)
+            .build()?;
+
+        count_messages(parser)
+    }
+
+    fn count_messages<R: Read>(parser: ULogParser<R>) -> Result<usize, ULogError> {
+        let mut count = 0;
+        for msg in parser {
+            if let UlogMessage::LoggedData(_) = msg? {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    #[test]
+    fn test_message_types() {
+        let known: Vec<ULogMessageType> = ULogMessageType::iter_known().collect();
+        assert_eq!(known.len(), 13);
+        for msg_type in known {
+            assert_eq!(ULogMessageType::from(u8::from(msg_type)), msg_type);
+            assert!(msg_type.is_known());
+        }
+        assert_eq!(u8::from(ULogMessageType::DATA), b'D');
+        assert_eq!(ULogMessageType::from(b'X'), ULogMessageType::UNKNOWN(b'X'));
+        assert_eq!(u8::from(ULogMessageType::UNKNOWN(b'X')), b'X');
+        assert!(!ULogMessageType::iter_known().any(|t| t == ULogMessageType::HEADER));
+        assert!(!ULogMessageType::HEADER.is_known());
+        assert!(!ULogMessageType::UNKNOWN(b'X').is_known());
+
+        // Each byte converts to its type in the table, or to UNKNOWN.
+        for byte in 0..=u8::MAX {
+            let expected = MESSAGE_TYPES
+                .iter()
+                .find(|&&(_, b)| b == byte)
+                .map_or(ULogMessageType::UNKNOWN(byte), |&(msg_type, _)| msg_type);
+            assert_eq!(ULogMessageType::from(byte), expected);
+        }
+    }
+
+    #[test]
+    fn test_valid_appended_data_offset() {
+        let (_, message_ends) = log_with_appended_offset(0);
+        let (mut bytes, _) = log_with_appended_offset(message_ends[5] as u64);
+        bytes.truncate(message_ends[5]);
+        bytes.extend_from_slice(b"crash dump, definitely not ULOG");
+
+        assert_eq!(count_logged_data(bytes, false).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_appended_data_offset_inside_definitions() {
+        let (_, message_ends) = log_with_appended_offset(0);
+
+        // Points into the FLAG_BITS message itself.
+        let (bytes, _) = log_with_appended_offset(20);
+        assert!(matches!(
+            count_logged_data(bytes.clone(), false),
+            Err(ULogError::InvalidAppendedDataOffset(_))
+        ));
+        assert_eq!(count_logged_data(bytes, true).unwrap(), 3);
+
+        // Points at the boundary between the FORMAT and ADD_SUBSCRIPTION messages.
+        let (bytes, _) = log_with_appended_offset(message_ends[2] as u64);
+        assert!(matches!(
+            count_logged_data(bytes.clone(), false),
+            Err(ULogError::InvalidAppendedDataOffset(_))
+        ));
+        assert_eq!(count_logged_data(bytes, true).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_appended_data_offset_inside_message() {
+        let (_, message_ends) = log_with_appended_offset(0);
+        let (bytes, _) = log_with_appended_offset(message_ends[4] as u64 + 5);
+
+        assert!(matches!(
+            count_logged_data(bytes.clone(), false),
+            Err(ULogError::InvalidAppendedDataOffset(_))
+        ));
+        assert_eq!(count_logged_data(bytes, true).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_appended_data_offset_past_eof() {
+        let (bytes, _) = log_with_appended_offset(1_000_000);
+
+        assert!(matches!(
+            count_logged_data(bytes.clone(), false),
+            Err(ULogError::InvalidAppendedDataOffset(_))
+        ));
+        assert_eq!(count_logged_data(bytes, true).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_message_size_mismatch() {
+        let mut too_long = my_topic_data();
+        too_long.push(0xFF);
+        let mut too_short = my_topic_data();
+        too_short.pop();
+
+        for data_message in [too_long, too_short] {
+            let (bytes, _) = test_log(0, vec![my_topic_data(), data_message]);
+
+            let parser = crate::builder::ULogParserBuilder::new(io::Cursor::new(bytes.clone()))
+                .error_on_message_size_mismatch(true)
+                .build()
+                .unwrap();
+            assert!(matches!(count_messages(parser), Err(ULogError::MessageSizeMismatch(_))));
+        }
+
+        // Only a warning by default.
+        let mut too_long = my_topic_data();
+        too_long.push(0xFF);
+        let (bytes, _) = test_log(0, vec![my_topic_data(), too_long]);
+        let parser = ULogParser::new(io::Cursor::new(bytes)).unwrap();
+        assert_eq!(count_messages(parser).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_format_containing_itself() {
+        let mut bytes = Vec::new();
+        for message in [
+            UlogMessage::Header(FileHeader { version: 1, timestamp: 0 }),
+            UlogMessage::FlagBits(FlagBits {
+                compat_flags: [0; 8],
+                incompat_flags: [0; 8],
+                appended_data_offsets: [0; 3],
+            }),
+            UlogMessage::FormatDefinition(
+                parse_format(MessageBuf::from_vec(b"outer:uint64_t timestamp;inner x;".to_vec())).unwrap(),
+            ),
+            UlogMessage::FormatDefinition(parse_format(MessageBuf::from_vec(b"inner:outer y;".to_vec())).unwrap()),
+            UlogMessage::AddSubscription(Subscription {
+                multi_id: 0,
+                msg_id: 0,
+                message_name: "outer".to_string(),
+            }),
+            UlogMessage::Unhandled { msg_type: b'D', message_contents: vec![0; 12] },
+        ] {
+            message.encode(&mut bytes).unwrap();
+        }
+
+        let parser = ULogParser::new(io::Cursor::new(bytes)).unwrap();
+        match count_messages(parser) {
+            Err(ULogError::ParseError(e)) => assert!(e.contains("contains itself"), "{e}"),
+            result => panic!("expected a parse error, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn test_recover_format_errors() -> Result<(), ULogError> {
+        let mut bytes = Vec::new();
+        let mut messages = vec![
+            UlogMessage::Header(FileHeader { version: 1, timestamp: 0 }),
+            UlogMessage::FlagBits(FlagBits {
+                compat_flags: [0; 8],
+                incompat_flags: [0; 8],
+                appended_data_offsets: [0; 3],
+            }),
+            UlogMessage::FormatDefinition(parse_format(MessageBuf::from_vec(
+                b"my_topic:uint64_t timestamp;float value;".to_vec(),
+            ))?),
+            UlogMessage::Unhandled {
+                msg_type: b'F',
+                message_contents: b"bad_topic:uint64_t timestamp;uint8_t[ x;".to_vec(),
+            },
+        ];
+        for (msg_id, name) in ["my_topic", "bad_topic"].into_iter().enumerate() {
+            messages.push(UlogMessage::AddSubscription(Subscription {
+                multi_id: 0,
+                msg_id: msg_id as u16,
+                message_name: name.to_string(),
+            }));
+        }
+        messages.push(UlogMessage::Unhandled { msg_type: b'D', message_contents: my_topic_data() });
+        messages.push(UlogMessage::Unhandled { msg_type: b'D', message_contents: vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 7] });
+        for message in &messages {
+            message.encode(&mut bytes).unwrap();
+        }
+
+        // By default, the format is an error, and so are its subscription and data.
+        let error = ULogParser::new(bytes.as_slice())?.find_map(Result::err).unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Parse error: Invalid field of format `bad_topic` at columns 30..40: Unexpected end of definition at column 40."
+        );
+
+        let mut parser = crate::builder::ULogParserBuilder::new(bytes.as_slice())
+            .recover_format_errors(true)
+            .emit_unhandled(true)
+            .build()?;
+        let messages = parser.by_ref().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(messages.iter().filter(|msg| msg.is_data()).count(), 1);
+        assert!(matches!(
+            messages.last(),
+            Some(UlogMessage::Unhandled { msg_type: b'D', message_contents }) if message_contents[..2] == [1, 0]
+        ));
+
+        let issues = &parser.validation_report().format_issues;
+        assert_eq!(issues.len(), 1);
+        assert_eq!((issues[0].format.as_str(), issues[0].span.clone()), ("bad_topic", 30..40));
+        assert!(!parser.validation_report().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_sizes_of_sample_logs() {
+        // None of the sample logs should trigger a message size mismatch.
+        for entry in std::fs::read_dir("test_data/input").unwrap() {
+            let path = entry.unwrap().path();
+            let reader = io::BufReader::new(std::fs::File::open(&path).unwrap());
+
+            let parser = crate::builder::ULogParserBuilder::new(reader)
+                .error_on_message_size_mismatch(true)
+                .build()
+                .unwrap();
+
+            assert!(count_messages(parser).is_ok(), "Size mismatch in {path:?}");
+        }
+    }
+
+    #[test]
+    fn test_unknown_message_type() {
+        let (mut bytes, _) = test_log(0, vec![my_topic_data()]);
+        let unknown = UlogMessage::UnknownTyped(msg::UnknownTyped { msg_type: b'X', payload: vec![1, 2, 3] });
+        unknown.encode(&mut bytes).unwrap();
+
+        // Without a decoder, the message is passed through and can be re-encoded unchanged.
+        let last = ULogParser::new(io::Cursor::new(bytes.clone())).unwrap().last().unwrap().unwrap();
+        match &last {
+            UlogMessage::UnknownTyped(msg) => {
+                assert_eq!(msg.msg_type, b'X');
+                assert_eq!(msg.payload, vec![1, 2, 3]);
+            }
+            other => panic!("Unexpected message {other:?}"),
+        }
+        let mut encoded = Vec::new();
+        last.encode(&mut encoded).unwrap();
+        assert!(bytes.ends_with(&encoded));
+
+        // With a decoder.
+        let mut decoders = DecoderRegistry::new();
+        decoders
+            .register(b'X', |buf| {
+                Ok(UlogMessage::DropoutMark(Dropout { duration: buf.take_u16()? }))
+            })
+            .unwrap();
+
+        let last = crate::builder::ULogParserBuilder::new(io::Cursor::new(bytes))
+            .decoders(decoders)
+            .build()
+            .unwrap()
+            .last()
+            .unwrap()
+            .unwrap();
+        assert!(matches!(last, UlogMessage::DropoutMark(Dropout { duration: 0x0201 })));
+    }
+
+    #[test]
+    fn test_iterator_adapters() -> Result<(), ULogError> {
+        use crate::testkit::{Generator, LogBuilder, Topic};
+        use std::time::Duration;
+
+        let bytes = LogBuilder::new()
+            .topic(Topic::new("a").field("x", BaseType::FLOAT, Generator::Constant(1.0)))
+            .topic(Topic::new("b").rate_hz(2.0).field("y", BaseType::UINT8, Generator::Constant(2.0)))
+            .log_at(Duration::from_millis(250), LogLevel::Warning, "low battery")
+            .build()?;
+        let parser = || crate::builder::ULogParserBuilder::new(bytes.as_slice()).build();
+
+        assert_eq!(parser()?.data_only().count(), 12);
+
+        let b = parser()?.topic("b").collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(b.len(), 2);
+        assert!(b.iter().all(|format| format.name.as_ref() == "b"));
+
+        let logs = parser()?.logged_strings().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(logs.len(), 1);
+        assert_eq!((logs[0].msg.as_str(), logs[0].timestamp), ("low battery", 1_250_000));
+
+        let timestamps: Vec<_> =
+            parser()?.with_timestamps().filter_map(|msg| msg.ok()?.0).collect();
+        assert_eq!(timestamps.len(), 13);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_collect_multi_info() -> Result<(), ULogError> {
+        use crate::model::msg::MultiInfo;
+        use crate::writer::ULogWriter;
+
+        let mut writer = ULogWriter::new(Vec::new());
+        writer.write_message(&UlogMessage::Header(FileHeader { version: 1, timestamp: 0 }))?;
+        writer.write_message(&UlogMessage::FlagBits(FlagBits {
+            compat_flags: [0; 8],
+            incompat_flags: [0; 8],
+            appended_data_offsets: [0; 3],
+        }))?;
+        for info in [
+            MultiInfo::new("perf_counter_preflight", "sensors: 10 events\n", false),
+            MultiInfo::new("perf_counter_preflight", "logger: 3 events\n", true),
+            MultiInfo::new("perf_counter_preflight", "ekf2: 1 event\0\0", false),
+            MultiInfo::new("boot_count", 7u32, false),
+            MultiInfo::new("boot_count", 8u32, true),
+        ] {
+            writer.write_message(&UlogMessage::MultiInfo(info))?;
+        }
+        let bytes = writer.into_inner()?;
+        let parser = || crate::builder::ULogParserBuilder::new(bytes.as_slice()).build();
+
+        let values = parser()?.collect_multi_info()?;
+        assert_eq!(values["perf_counter_preflight"].len(), 2);
+        // Scalars can't be joined.
+        assert_eq!(values["boot_count"], [inst::FieldValue::ScalarU32(7), inst::FieldValue::ScalarU32(8)]);
+
+        let text = parser()?.collect_multi_info_text()?;
+        assert_eq!(
+            text["perf_counter_preflight"],
+            ["sensors: 10 events\nlogger: 3 events\n", "ekf2: 1 event"]
+        );
+        assert!(!text.contains_key("boot_count"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_message_handler() {
+        #[derive(Debug, PartialEq)]
+        struct Vendor(u8, u8);
+
+        let (mut bytes, _) = test_log(0, vec![my_topic_data()]);
+        let unknown = UlogMessage::UnknownTyped(msg::UnknownTyped { msg_type: b'X', payload: vec![1, 2, 3] });
+        unknown.encode(&mut bytes).unwrap();
+
+        let last = crate::builder::ULogParserBuilder::new(io::Cursor::new(bytes.clone()))
+            .register_handler(b'X', |buf| Ok(UlogMessage::custom(Vendor(buf.take_u8()?, buf.take_u8()?))))
+            .unwrap()
+            .build()
+            .unwrap()
+            .last()
+            .unwrap()
+            .unwrap();
+        let UlogMessage::Custom(custom) = &last else {
+            panic!("Unexpected message {last:?}");
+        };
+        assert_eq!(custom.msg_type, b'X');
+        assert_eq!(custom.downcast_ref::<Vendor>(), Some(&Vendor(1, 2)));
+        assert!(!custom.is::<u8>());
+
+        // The payload is kept whole, so the message is re-encoded unchanged.
+        let mut encoded = Vec::new();
+        last.encode(&mut encoded).unwrap();
+        assert!(bytes.ends_with(&encoded));
+
+        let standard = crate::builder::ULogParserBuilder::new(io::Cursor::new(bytes))
+            .register_handler(b'D', |_| Ok(UlogMessage::custom(())));
+        assert!(matches!(standard, Err(ULogError::InvalidConfiguration(_))));
+    }
+
     #[test]
     fn test_round_trip_format() {
         let input = b"my_format:uint64_t timestamp;custom_type custom_field;bool is_happy;custom_type2[4] custom_field;uint8_t[8] pet_ids;";
@@ -946,5 +2336,296 @@ mod tests {
 
         assert_eq!(emitted_bytes, input_bytes);
     }
-}
 
+    #[test]
+    fn test_per_topic_field_overrides() {
+        use crate::builder::ULogParserBuilder;
+        use crate::testkit::{Generator, LogBuilder, Topic};
+
+        let topic = |name: &str| {
+            Topic::new(name)
+                .field("x", BaseType::FLOAT, Generator::Constant(1.0))
+                .array_field("_padding0", BaseType::UINT8, 4, Generator::Constant(0.0))
+        };
+        let bytes = LogBuilder::new()
+            .topic(topic("estimator_states"))
+            .topic(topic("sensor_combined"))
+            .build()
+            .unwrap();
+
+        let parser = ULogParserBuilder::new(bytes.as_slice())
+            .include_timestamp(false)
+            .include_timestamp_for("estimator_states", true)
+            .include_padding(true)
+            .include_padding_for("estimator_states", false)
+            .build()
+            .unwrap();
+
+        let mut seen = HashSet::new();
+        for message in parser {
+            if let UlogMessage::LoggedData(data) = message.unwrap() {
+                let names: Vec<&str> = data.data.fields.iter().map(|f| f.name.as_ref()).collect();
+                match data.data.name.as_ref() {
+                    "estimator_states" => assert_eq!(names, ["timestamp", "x"]),
+                    _ => assert_eq!(names, ["x", "_padding0"]),
+                }
+                seen.insert(data.data.name.to_string());
+            }
+        }
+        assert_eq!(seen.len(), 2);
+    }
+
+    #[test]
+    fn test_filtered_field_indices() {
+        use crate::builder::ULogParserBuilder;
+        use crate::testkit::{Generator, LogBuilder, Topic};
+
+        let bytes = LogBuilder::new()
+            .topic(
+                Topic::new("a")
+                    .field("x", BaseType::FLOAT, Generator::Constant(1.5))
+                    .array_field("_padding0", BaseType::UINT8, 4, Generator::Constant(0.0))
+                    .field("y", BaseType::UINT32, Generator::Ramp { start: 0.0, step: 1.0 }),
+            )
+            .build()
+            .unwrap();
+
+        let parse = |include: bool| -> Vec<LoggedData> {
+            ULogParserBuilder::new(bytes.as_slice())
+                .include_timestamp(include)
+                .include_padding(include)
+                .build()
+                .unwrap()
+                .filter_map(|m| match m.unwrap() {
+                    UlogMessage::LoggedData(data) => Some(data),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        let filtered = parse(false);
+        let complete = parse(true);
+        assert_eq!(filtered.len(), complete.len());
+
+        for (filtered, complete) in filtered.iter().zip(&complete) {
+            let format = &filtered.data;
+            assert_eq!(format.field_indices, [1, 3]);
+            assert_eq!(format.def_index(1), 3);
+            // Definition order: timestamp, x, _padding0, y.
+            assert!(format.field_at(0).is_none());
+            assert_eq!(format.field_at(1).unwrap().name.as_ref(), "x");
+            assert!(format.field_at(2).is_none());
+            assert_eq!(format.field_at(3).unwrap().name.as_ref(), "y");
+
+            // The filtered fields are re-inserted when encoding.
+            let encode = |data: &LoggedData| {
+                let mut buf = Vec::new();
+                crate::encode::Encode::encode(data, &mut buf).unwrap();
+                buf
+            };
+            assert_eq!(encode(filtered), encode(complete));
+        }
+    }
+
+    #[test]
+    fn test_remove_subscription() -> Result<(), ULogError> {
+        use crate::builder::ULogParserBuilder;
+        use crate::testkit::{Generator, LogBuilder, Topic};
+        use std::time::Duration;
+
+        // Remove msg_id 0, then reuse it for `b`.
+        let mut resubscribe = vec![2, 0, b'R', 0, 0];
+        resubscribe.extend_from_slice(&[4, 0, b'A', 0, 0, 0, b'b']);
+
+        let bytes = LogBuilder::new()
+            .topic(Topic::new("a").field("x", BaseType::UINT32, Generator::Constant(1.0)))
+            .topic(Topic::new("b").field("y", BaseType::FLOAT, Generator::Constant(2.0)))
+            .garbage_at(Duration::from_millis(450), &resubscribe)
+            .build()?;
+
+        let messages = ULogParserBuilder::new(bytes.as_slice())
+            .set_subscription_allow_list(["a"])
+            .emit_unhandled(true)
+            .build()?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let removed = messages
+            .iter()
+            .position(|m| m.removed_subscription() == Some(0))
+            .expect("REMOVE_SUBSCRIPTION message");
+
+        // The msg_id is kept, so the message round trips.
+        let mut encoded = Vec::new();
+        messages[removed].encode(&mut encoded)?;
+        assert_eq!(encoded, [2, 0, b'R', 0, 0]);
+
+        let topics = |messages: &[UlogMessage]| -> Vec<String> {
+            messages
+                .iter()
+                .filter_map(|m| match m {
+                    UlogMessage::LoggedData(data) => Some(data.data.name.to_string()),
+                    _ => None,
+                })
+                .collect()
+        };
+        assert_eq!(topics(&messages[..removed]), ["a"; 5]);
+        // `b` isn't allowed, so its data is ignored once it takes over msg_id 0.
+        assert!(topics(&messages[removed..]).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_emit_unhandled() -> Result<(), ULogError> {
+        use crate::builder::ULogParserBuilder;
+        use crate::testkit::{Generator, LogBuilder, Topic};
+        use std::time::Duration;
+
+        let bytes = LogBuilder::new()
+            .topic(Topic::new("a").field("x", BaseType::UINT32, Generator::Constant(1.0)))
+            .garbage_at(Duration::from_millis(350), &[2, 0, b'F', b'a', b':'])
+            .build()?;
+        let unhandled = |emit: bool| -> Result<Vec<UlogMessage>, ULogError> {
+            let messages = ULogParserBuilder::new(bytes.as_slice())
+                .emit_unhandled(emit)
+                .build()?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(messages
+                .into_iter()
+                .filter(|m| matches!(m, UlogMessage::Unhandled { .. }))
+                .collect())
+        };
+
+        assert!(unhandled(false)?.is_empty());
+        let [UlogMessage::Unhandled { msg_type, message_contents }] = &unhandled(true)?[..] else {
+            panic!("expected one Unhandled message");
+        };
+        assert_eq!((*msg_type, &message_contents[..]), (b'F', &b"a:"[..]));
+
+        Ok(())
+    }
+
+    // Concatenates the framed raw messages, and returns the error which ended them, if any.
+    fn raw_bytes(bytes: &[u8]) -> (Vec<u8>, Option<ULogError>) {
+        let mut messages = crate::builder::ULogParserBuilder::new(bytes)
+            .include_header(true)
+            .build()
+            .unwrap()
+            .raw_messages();
+
+        let mut copy = Vec::new();
+        while let Some(message) = messages.next() {
+            match message {
+                Ok((header, contents)) => {
+                    assert_eq!(usize::from(header.msg_size), contents.len());
+                    if !matches!(header.msg_type, ULogMessageType::HEADER) {
+                        copy.extend_from_slice(&header.msg_size.to_le_bytes());
+                        copy.push(header.msg_type.into());
+                    }
+                    copy.extend_from_slice(contents);
+                }
+                Err(e) => return (copy, Some(e)),
+            }
+        }
+        (copy, None)
+    }
+
+    #[test]
+    fn test_raw_messages() {
+        for name in ["sample_log_small", "short_list", "powers", "trig_stats"] {
+            let input = std::fs::read(format!("test_data/input/{name}.ulg")).unwrap();
+            let (copy, error) = raw_bytes(&input);
+            assert!(error.is_none(), "{name}");
+            assert!(copy == input, "{name}");
+        }
+
+        // Reading stops at the appended data.
+        let (_, message_ends) = log_with_appended_offset(0);
+        let (mut bytes, _) = log_with_appended_offset(message_ends[5] as u64);
+        bytes.truncate(message_ends[5]);
+        let expected = bytes.clone();
+        bytes.extend_from_slice(b"crash dump, definitely not ULOG");
+
+        let (copy, error) = raw_bytes(&bytes);
+        assert!(error.is_none());
+        assert_eq!(copy, expected);
+
+        // A truncated message is an error, rather than returned with missing contents.
+        let (copy, error) = raw_bytes(&expected[..expected.len() - 1]);
+        assert!(matches!(error, Some(ULogError::UnexpectedEndOfFile)));
+        assert_eq!(copy, expected[..message_ends[4]]);
+    }
+
+    #[test]
+    fn test_max_subscriptions() -> Result<(), ULogError> {
+        use crate::builder::ULogParserBuilder;
+        use crate::testkit::{Generator, LogBuilder, Topic};
+        use std::time::Duration;
+
+        // Subscribes to and removes another instance of `a` 1000 times, with msg_ids 2..=1001.
+        let mut churn = Vec::new();
+        for i in 1..=1000u16 {
+            let msg_id = i + 1;
+            churn.extend_from_slice(&[4, 0, b'A', u8::try_from(i % 200).unwrap()]);
+            churn.extend_from_slice(&msg_id.to_le_bytes());
+            churn.push(b'a');
+            churn.extend_from_slice(&[2, 0, b'R']);
+            churn.extend_from_slice(&msg_id.to_le_bytes());
+        }
+
+        let builder = || {
+            LogBuilder::new()
+                .topic(Topic::new("a").field("x", BaseType::UINT32, Generator::Constant(1.0)))
+                .topic(Topic::new("b").field("y", BaseType::FLOAT, Generator::Constant(2.0)))
+        };
+        let bytes = builder().garbage_at(Duration::from_millis(450), &churn).build()?;
+
+        let plain = builder().build()?;
+        let mut parser = ULogParserBuilder::new(plain.as_slice()).build()?;
+        parser.by_ref().for_each(drop);
+        let baseline = parser.approx_memory_usage();
+        assert!(baseline > 0);
+
+        // Removed subscriptions are evicted, so no more than three are held at once, and memory doesn't grow.
+        let mut parser = ULogParserBuilder::new(bytes.as_slice()).max_subscriptions(3).build()?;
+        assert!(parser.by_ref().all(|m| m.is_ok()));
+        assert_eq!(parser.subscriptions.len(), 2);
+        assert!(parser.approx_memory_usage() < baseline * 2);
+
+        // Beyond the limit, `b` isn't subscribed, and its data is reported as errors.
+        let mut parser = ULogParserBuilder::new(bytes.as_slice()).max_subscriptions(1).build()?;
+        let messages: Vec<_> = parser.by_ref().collect();
+        assert!(matches!(
+            messages.iter().find_map(|m| m.as_ref().err()),
+            Some(ULogError::SubscriptionLimitExceeded(_))
+        ));
+        let a = messages
+            .iter()
+            .filter(|m| matches!(m, Ok(UlogMessage::LoggedData(data)) if data.data.name.as_ref() == "a"))
+            .count();
+        assert_eq!(a, 10);
+        assert_eq!(parser.subscriptions.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_chunks_budgeted() -> Result<(), ULogError> {
+        let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+        let all = ULogParser::new(input.as_slice())?.count();
+
+        // A zero budget still makes progress, one message at a time.
+        let chunks = ULogParser::new(input.as_slice())?.chunks_budgeted(Duration::ZERO);
+        let lens: Vec<usize> = chunks.map(|batch| batch.len()).collect();
+        assert_eq!(lens.len(), all);
+        assert!(lens.iter().all(|&len| len == 1));
+
+        // A generous budget reads the whole log at once.
+        let mut chunks = ULogParser::new(input.as_slice())?.chunks_budgeted(Duration::from_secs(3600));
+        assert_eq!(chunks.next().map(|batch| batch.len()), Some(all));
+        assert!(chunks.next().is_none());
+        assert_eq!(chunks.into_parser().state(), State::EOF);
+
+        Ok(())
+    }
+}
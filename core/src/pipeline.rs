@@ -0,0 +1,463 @@
+//! Runs an export described by a TOML file: the logs to read, the topics and time range to keep, derived channels,
+//! resampling, and the sinks to write to.  Requires the `pipeline` feature.
+//!
+//! ```toml
+//! [input]
+//! paths = ["logs"]            # .ulg files, or directories searched for them
+//!
+//! [filter]
+//! topics = ["vehicle_local_position", "battery_status"]
+//! start_us = 1000000          # Data messages outside the range are dropped.
+//!
+//! [derived]                   # See `derived::DerivedChannels`.
+//! topics = ["vehicle_local_position"]
+//! channel = [{ name = "ground_speed", expr = "sqrt(vx*vx + vy*vy)" }]
+//!
+//! [resample]
+//! interval_us = 100000        # At most one sample of each topic instance per interval.
+//!
+//! [[sink]]
+//! type = "csv"                # or "ndjson", "sql" with a `dialect`, "avro"
+//! path = "out/{stem}"         # {stem} is the file name of the log without its extension.
+//! ```
+//!
+//! Relative paths are resolved against the directory of the TOML file.  The sinks are written one after the other,
+//! each from a new parser, and the logs are processed on a pool of threads, as by `batch::process_dir()`.
+//!
+//! ```rust,no_run
+//! use yule_log::pipeline;
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let report = pipeline::from_toml("export.toml")?.run()?;
+//! for (log, error) in &report.errors {
+//!     eprintln!("{}: {error}", log.display());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use serde::Deserialize;
+
+use crate::batch::{find_files, run_parallel, BatchReport};
+use crate::builder::ULogParserBuilder;
+use crate::derived::DerivedChannels;
+use crate::errors::ULogError;
+use crate::export::avro::AvroExporter;
+use crate::export::csv::CsvExporter;
+use crate::export::identifiers::Dialect;
+use crate::export::ndjson;
+use crate::export::sql::SqlExporter;
+use crate::model::msg::UlogMessage;
+
+type Messages<'a> = Box<dyn Iterator<Item = Result<UlogMessage, ULogError>> + 'a>;
+
+/// The contents of a pipeline file.  See the module documentation.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PipelineConfig {
+    pub input: InputConfig,
+    #[serde(default)]
+    pub filter: FilterConfig,
+    pub derived: Option<DerivedConfig>,
+    pub resample: Option<ResampleConfig>,
+    #[serde(rename = "sink")]
+    pub sinks: Vec<SinkConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InputConfig {
+    pub paths: Vec<PathBuf>,
+    /// Whether directories are searched recursively.  Defaults to `true`.
+    #[serde(default = "default_recursive")]
+    pub recursive: bool,
+}
+
+fn default_recursive() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FilterConfig {
+    /// The topics whose data is kept, or all topics if `None`.  The derived topic is always kept.
+    pub topics: Option<Vec<String>>,
+    pub start_us: Option<u64>,
+    pub end_us: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DerivedConfig {
+    /// The name of the derived topic.  Defaults to `derived`.
+    pub topic: Option<String>,
+    /// The topics the channels are computed from, as for `join::aligned()`.
+    pub topics: Vec<String>,
+    #[serde(default)]
+    pub tolerance_us: u64,
+    #[serde(rename = "channel")]
+    pub channels: Vec<ChannelConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChannelConfig {
+    pub name: String,
+    /// See `derived::expr`.
+    pub expr: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResampleConfig {
+    pub interval_us: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum SinkConfig {
+    /// A directory of CSV files, as written by `export::csv`.
+    Csv { path: PathBuf },
+    /// A JSON Lines file, as written by `export::ndjson`.
+    Ndjson { path: PathBuf },
+    /// A file of SQL statements, as written by `export::sql`, in the dialect `postgres`, `mysql`, `sqlite` or
+    /// `sql`, which is the default.
+    Sql {
+        path: PathBuf,
+        dialect: Option<String>,
+    },
+    /// A directory of Avro files, as written by `export::avro`.
+    Avro { path: PathBuf },
+}
+
+impl SinkConfig {
+    fn path(&self) -> &Path {
+        match self {
+            SinkConfig::Csv { path }
+            | SinkConfig::Ndjson { path }
+            | SinkConfig::Sql { path, .. }
+            | SinkConfig::Avro { path } => path,
+        }
+    }
+}
+
+/// A pipeline read from a TOML file, ready to run.
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    config: PipelineConfig,
+    base_dir: PathBuf,
+}
+
+/// Reads the pipeline described by the TOML file at `path`.
+///
+/// Returns `ULogError::InvalidConfiguration` if the file isn't a valid pipeline, or one of its expressions can't
+/// be parsed.
+pub fn from_toml(path: impl AsRef<Path>) -> Result<Pipeline, ULogError> {
+    let path = path.as_ref();
+    let toml = fs::read_to_string(path)?;
+    let base_dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    Pipeline::parse(&toml, base_dir)
+        .map_err(|err| ULogError::InvalidConfiguration(format!("{}: {err}", path.display())))
+}
+
+impl Pipeline {
+    /// Parses a pipeline, with relative paths resolved against `base_dir`.
+    pub fn parse(toml: &str, base_dir: impl Into<PathBuf>) -> Result<Self, ULogError> {
+        let config: PipelineConfig =
+            toml::from_str(toml).map_err(|err| ULogError::InvalidConfiguration(err.to_string()))?;
+        let pipeline = Pipeline {
+            config,
+            base_dir: base_dir.into(),
+        };
+        // Reports invalid expressions before any log is read.
+        pipeline.derived_channels()?;
+        if let Some(SinkConfig::Sql { dialect, .. }) = pipeline
+            .config
+            .sinks
+            .iter()
+            .find(|sink| matches!(sink, SinkConfig::Sql { .. }))
+        {
+            sql_dialect(dialect.as_deref())?;
+        }
+        Ok(pipeline)
+    }
+
+    pub fn config(&self) -> &PipelineConfig {
+        &self.config
+    }
+
+    /// Runs the sinks on each log.  An error exporting a log is recorded in the report, with the log's path, and
+    /// the other logs are still exported.  The output paths of each log are its result.
+    pub fn run(&self) -> Result<BatchReport<Vec<PathBuf>>, ULogError> {
+        let mut files = Vec::new();
+        for path in &self.config.input.paths {
+            let path = self.base_dir.join(path);
+            if path.is_dir() {
+                find_files(&path, self.config.input.recursive, &mut files)?;
+            } else {
+                files.push(path);
+            }
+        }
+        files.sort();
+        files.dedup();
+
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        let mut report = BatchReport {
+            results: Vec::new(),
+            errors: Vec::new(),
+            skipped: Vec::new(),
+        };
+        for (path, outcome) in run_parallel(&files, threads, |path| self.run_log(path)) {
+            match outcome {
+                Ok(outputs) => report.results.push((path, outputs)),
+                Err(err) => report.errors.push((path, err)),
+            }
+        }
+        Ok(report)
+    }
+
+    fn run_log(&self, log: &Path) -> Result<Vec<PathBuf>, ULogError> {
+        let stem = log.file_stem().unwrap_or_default().to_string_lossy();
+        let mut outputs = Vec::new();
+        for sink in &self.config.sinks {
+            let template = sink.path().to_string_lossy().replace("{stem}", &stem);
+            let output = self.base_dir.join(template);
+            let messages = self.messages(log)?;
+
+            match sink {
+                SinkConfig::Csv { .. } => {
+                    CsvExporter::new(&output).export(messages)?;
+                }
+                SinkConfig::Ndjson { .. } => {
+                    let mut writer = BufWriter::new(create(&output)?);
+                    ndjson::export(messages, &mut writer)?;
+                    writer.flush()?;
+                }
+                SinkConfig::Sql { dialect, .. } => {
+                    let mut writer = BufWriter::new(create(&output)?);
+                    SqlExporter::new(sql_dialect(dialect.as_deref())?).export_log(
+                        &stem,
+                        messages,
+                        &mut writer,
+                    )?;
+                    writer.flush()?;
+                }
+                SinkConfig::Avro { .. } => {
+                    AvroExporter::new(&output).export(messages)?;
+                }
+            }
+            outputs.push(output);
+        }
+        Ok(outputs)
+    }
+
+    // The messages of `log`, through the filter, derived channels and resampling.
+    fn messages(&self, log: &Path) -> Result<Messages<'_>, ULogError> {
+        let filter = &self.config.filter;
+        let derived = self.config.derived.as_ref();
+
+        let mut builder = ULogParserBuilder::new(BufReader::new(File::open(log)?));
+        if let Some(topics) = &filter.topics {
+            let sources = derived.into_iter().flat_map(|derived| &derived.topics);
+            let names: HashSet<String> = topics
+                .iter()
+                .chain(sources)
+                .map(|t| topic_name(t).to_owned())
+                .collect();
+            builder = builder.set_subscription_allow_list(names);
+        }
+        let mut messages: Messages = Box::new(builder.build()?);
+
+        let (start, end) = (
+            filter.start_us.unwrap_or(0),
+            filter.end_us.unwrap_or(u64::MAX),
+        );
+        if (start, end) != (0, u64::MAX) {
+            messages = Box::new(messages.filter(move |msg| match msg {
+                Ok(UlogMessage::LoggedData(data)) => (start..=end).contains(&data.timestamp),
+                _ => true,
+            }));
+        }
+
+        if let Some(channels) = self.derived_channels()? {
+            messages = Box::new(channels.apply(messages));
+        }
+
+        if let Some(topics) = &filter.topics {
+            let mut kept: HashSet<String> =
+                topics.iter().map(|t| topic_name(t).to_owned()).collect();
+            kept.extend(derived.map(|d| d.topic.clone().unwrap_or_else(|| "derived".to_owned())));
+            messages = Box::new(messages.filter(move |msg| match msg {
+                Ok(UlogMessage::LoggedData(data)) => kept.contains(&*data.data.name),
+                _ => true,
+            }));
+        }
+
+        if let Some(resample) = &self.config.resample {
+            let interval = resample.interval_us.max(1);
+            // topic instance -> the interval of its last sample kept.
+            let mut last: HashMap<(String, u8), u64> = HashMap::new();
+            messages = Box::new(messages.filter(move |msg| match msg {
+                Ok(UlogMessage::LoggedData(data)) => {
+                    let key = (
+                        data.data.name.to_string(),
+                        data.data.multi_id_index.unwrap_or(0),
+                    );
+                    let bucket = data.timestamp / interval;
+                    last.insert(key, bucket) != Some(bucket)
+                }
+                _ => true,
+            }));
+        }
+
+        Ok(messages)
+    }
+
+    fn derived_channels(&self) -> Result<Option<DerivedChannels>, ULogError> {
+        let Some(derived) = &self.config.derived else {
+            return Ok(None);
+        };
+        let mut channels = DerivedChannels::new(&derived.topics, derived.tolerance_us);
+        if let Some(topic) = &derived.topic {
+            channels = channels.topic_name(topic);
+        }
+        for channel in &derived.channels {
+            channels = channels
+                .derive_expr(&channel.name, &channel.expr)
+                .map_err(|err| {
+                    ULogError::InvalidConfiguration(format!("Channel `{}`: {err}", channel.name))
+                })?;
+        }
+        Ok(Some(channels))
+    }
+}
+
+// The name of a topic given with an optional `.NN` instance suffix.
+fn topic_name(topic: &str) -> &str {
+    match topic.rsplit_once('.') {
+        Some((name, multi_id)) if multi_id.parse::<u8>().is_ok() => name,
+        _ => topic,
+    }
+}
+
+fn sql_dialect(name: Option<&str>) -> Result<Dialect, ULogError> {
+    match name.unwrap_or("sql") {
+        "sql" => Ok(Dialect::Sql),
+        "postgres" => Ok(Dialect::Postgres),
+        "mysql" => Ok(Dialect::MySql),
+        "sqlite" => Ok(Dialect::Sqlite),
+        other => Err(ULogError::InvalidConfiguration(format!(
+            "Unknown SQL dialect `{other}`, expected `sql`, `postgres`, `mysql` or `sqlite`."
+        ))),
+    }
+}
+
+fn create(path: &Path) -> Result<File, ULogError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    Ok(File::create(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::model::def::BaseType;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    #[test]
+    fn test_run() -> Result<(), ULogError> {
+        let dir = tempfile::tempdir()?;
+        let log = LogBuilder::new()
+            .duration(Duration::from_secs(2))
+            .topic(
+                Topic::new("position")
+                    .rate_hz(10.0)
+                    .field("vx", BaseType::FLOAT, Generator::Constant(3.0))
+                    .field("vy", BaseType::FLOAT, Generator::Constant(4.0)),
+            )
+            .topic(Topic::new("other").field("x", BaseType::UINT8, Generator::Constant(1.0)))
+            .build()?;
+        fs::create_dir(dir.path().join("logs"))?;
+        fs::write(dir.path().join("logs/flight.ulg"), log)?;
+        fs::write(
+            dir.path().join("export.toml"),
+            r#"
+                [input]
+                paths = ["logs"]
+
+                [filter]
+                topics = ["position"]
+                start_us = 1500000
+
+                [derived]
+                topics = ["position"]
+                channel = [{ name = "speed", expr = "sqrt(vx*vx + vy*vy)" }]
+
+                [resample]
+                interval_us = 500000
+
+                [[sink]]
+                type = "ndjson"
+                path = "out/{stem}.ndjson"
+
+                [[sink]]
+                type = "csv"
+                path = "out/{stem}"
+            "#,
+        )?;
+
+        let report = from_toml(dir.path().join("export.toml"))?.run()?;
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        let outputs = &report.results[0].1;
+        assert_eq!(outputs[0], dir.path().join("out/flight.ndjson"));
+
+        let lines: Vec<serde_json::Value> = fs::read_to_string(&outputs[0])?
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let data: Vec<_> = lines.iter().filter(|line| line["type"] == "data").collect();
+        // From 1.5 s to 2.9 s, the first sample of each topic in every 0.5 s.
+        let topics: Vec<_> = data
+            .iter()
+            .map(|line| line["topic"].as_str().unwrap())
+            .collect();
+        assert_eq!(topics.iter().filter(|t| **t == "position").count(), 3);
+        assert_eq!(topics.iter().filter(|t| **t == "derived").count(), 3);
+        assert!(!topics.contains(&"other"));
+        let speed = data.iter().find(|line| line["topic"] == "derived").unwrap();
+        assert_eq!(speed["data"]["speed"], 5.0);
+
+        assert!(outputs[1].join("derived_0.csv").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid() {
+        let parse = |toml: &str| Pipeline::parse(toml, "");
+        let input = "[input]\npaths = []\n";
+        assert!(parse(input).is_err(), "No sinks");
+        assert!(parse(&format!(
+            "{input}[[sink]]\ntype = \"parquet\"\npath = \"x\""
+        ))
+        .is_err());
+        assert!(parse(&format!(
+            "{input}[[sink]]\ntype = \"sql\"\npath = \"x\"\ndialect = \"oracle\""
+        ))
+        .is_err());
+        assert!(parse(&format!(
+            "{input}[[sink]]\ntype = \"csv\"\npath = \"x\"\n[derived]\ntopics = []\nchannel = [{{ name = \"a\", expr = \"1 +\" }}]"
+        ))
+        .is_err());
+        assert!(parse(&format!("{input}[[sink]]\ntype = \"csv\"\npath = \"x\"")).is_ok());
+    }
+}
@@ -0,0 +1,236 @@
+//! The names of common PX4 topics, from the uORB message definitions of PX4 v1.15, as constants and as the
+//! `Px4Topic` enum, so that allow lists and derive attributes don't rely on string literals.  Requires the
+//! `px4_topics` feature.
+//!
+//! ```rust
+//! use yule_log::builder::ULogParserBuilder;
+//! use yule_log::px4_topics::{self, Px4Topic};
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+//! let parser = ULogParserBuilder::new(input.as_slice())
+//!     .set_subscription_allow_list([px4_topics::VEHICLE_STATUS, Px4Topic::BatteryStatus.as_str()])
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! With the `macros` feature, `#[yule_log(px4_topic)]` on a `ULogData` struct checks at compile time that its
+//! subscription name is one of these topics.
+//!
+//! The list is not exhaustive: topics which are missing, or were added by a later version of PX4, must still be given
+//! by name.
+
+use std::fmt;
+
+macro_rules! px4_topics {
+    ($($constant:ident, $variant:ident, $name:literal;)*) => {
+        $(
+            #[doc = concat!("`", $name, "`")]
+            pub const $constant: &str = $name;
+        )*
+
+        /// A common PX4 topic.  See the module documentation.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        #[non_exhaustive]
+        pub enum Px4Topic {
+            $(
+                #[doc = concat!("`", $name, "`")]
+                $variant,
+            )*
+        }
+
+        impl Px4Topic {
+            /// All the topics, in alphabetical order.
+            pub const ALL: &'static [Px4Topic] = &[$(Px4Topic::$variant),*];
+
+            /// The name of the topic, as in the subscriptions of a log.
+            pub fn as_str(self) -> &'static str {
+                match self {
+                    $(Px4Topic::$variant => $name,)*
+                }
+            }
+
+            /// The topic named `name`, if it is one of the topics.
+            pub fn from_name(name: &str) -> Option<Self> {
+                match name {
+                    $($name => Some(Px4Topic::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+px4_topics! {
+    ACTION_REQUEST, ActionRequest, "action_request";
+    ACTUATOR_ARMED, ActuatorArmed, "actuator_armed";
+    ACTUATOR_MOTORS, ActuatorMotors, "actuator_motors";
+    ACTUATOR_OUTPUTS, ActuatorOutputs, "actuator_outputs";
+    ACTUATOR_SERVOS, ActuatorServos, "actuator_servos";
+    ACTUATOR_TEST, ActuatorTest, "actuator_test";
+    ADC_REPORT, AdcReport, "adc_report";
+    AIRSPEED, Airspeed, "airspeed";
+    AIRSPEED_VALIDATED, AirspeedValidated, "airspeed_validated";
+    AIRSPEED_WIND, AirspeedWind, "airspeed_wind";
+    AUTOTUNE_ATTITUDE_CONTROL_STATUS, AutotuneAttitudeControlStatus, "autotune_attitude_control_status";
+    BATTERY_STATUS, BatteryStatus, "battery_status";
+    CAMERA_CAPTURE, CameraCapture, "camera_capture";
+    CAMERA_TRIGGER, CameraTrigger, "camera_trigger";
+    CELLULAR_STATUS, CellularStatus, "cellular_status";
+    COLLISION_CONSTRAINTS, CollisionConstraints, "collision_constraints";
+    CONTROL_ALLOCATOR_STATUS, ControlAllocatorStatus, "control_allocator_status";
+    CPULOAD, Cpuload, "cpuload";
+    DEBUG_ARRAY, DebugArray, "debug_array";
+    DEBUG_KEY_VALUE, DebugKeyValue, "debug_key_value";
+    DEBUG_VALUE, DebugValue, "debug_value";
+    DEBUG_VECT, DebugVect, "debug_vect";
+    DIFFERENTIAL_PRESSURE, DifferentialPressure, "differential_pressure";
+    DISTANCE_SENSOR, DistanceSensor, "distance_sensor";
+    EKF2_TIMESTAMPS, Ekf2Timestamps, "ekf2_timestamps";
+    ESC_STATUS, EscStatus, "esc_status";
+    ESTIMATOR_EVENT_FLAGS, EstimatorEventFlags, "estimator_event_flags";
+    ESTIMATOR_INNOVATION_TEST_RATIOS, EstimatorInnovationTestRatios, "estimator_innovation_test_ratios";
+    ESTIMATOR_INNOVATION_VARIANCES, EstimatorInnovationVariances, "estimator_innovation_variances";
+    ESTIMATOR_INNOVATIONS, EstimatorInnovations, "estimator_innovations";
+    ESTIMATOR_SELECTOR_STATUS, EstimatorSelectorStatus, "estimator_selector_status";
+    ESTIMATOR_SENSOR_BIAS, EstimatorSensorBias, "estimator_sensor_bias";
+    ESTIMATOR_STATES, EstimatorStates, "estimator_states";
+    ESTIMATOR_STATUS, EstimatorStatus, "estimator_status";
+    ESTIMATOR_STATUS_FLAGS, EstimatorStatusFlags, "estimator_status_flags";
+    EVENT, Event, "event";
+    FAILSAFE_FLAGS, FailsafeFlags, "failsafe_flags";
+    FAILURE_DETECTOR_STATUS, FailureDetectorStatus, "failure_detector_status";
+    FOLLOW_TARGET, FollowTarget, "follow_target";
+    GEOFENCE_RESULT, GeofenceResult, "geofence_result";
+    GIMBAL_DEVICE_ATTITUDE_STATUS, GimbalDeviceAttitudeStatus, "gimbal_device_attitude_status";
+    GPS_DUMP, GpsDump, "gps_dump";
+    GPS_INJECT_DATA, GpsInjectData, "gps_inject_data";
+    HEATER_STATUS, HeaterStatus, "heater_status";
+    HOME_POSITION, HomePosition, "home_position";
+    HOVER_THRUST_ESTIMATE, HoverThrustEstimate, "hover_thrust_estimate";
+    INPUT_RC, InputRc, "input_rc";
+    IRLOCK_REPORT, IrlockReport, "irlock_report";
+    LANDING_GEAR, LandingGear, "landing_gear";
+    LANDING_TARGET_POSE, LandingTargetPose, "landing_target_pose";
+    LOG_MESSAGE, LogMessage, "log_message";
+    LOGGER_STATUS, LoggerStatus, "logger_status";
+    MAG_WORKER_DATA, MagWorkerData, "mag_worker_data";
+    MAGNETOMETER_BIAS_ESTIMATE, MagnetometerBiasEstimate, "magnetometer_bias_estimate";
+    MANUAL_CONTROL_SETPOINT, ManualControlSetpoint, "manual_control_setpoint";
+    MANUAL_CONTROL_SWITCHES, ManualControlSwitches, "manual_control_switches";
+    MAVLINK_LOG, MavlinkLog, "mavlink_log";
+    MISSION, Mission, "mission";
+    MISSION_RESULT, MissionResult, "mission_result";
+    MOUNT_ORIENTATION, MountOrientation, "mount_orientation";
+    NAVIGATOR_MISSION_ITEM, NavigatorMissionItem, "navigator_mission_item";
+    NAVIGATOR_STATUS, NavigatorStatus, "navigator_status";
+    OBSTACLE_DISTANCE, ObstacleDistance, "obstacle_distance";
+    OFFBOARD_CONTROL_MODE, OffboardControlMode, "offboard_control_mode";
+    ONBOARD_COMPUTER_STATUS, OnboardComputerStatus, "onboard_computer_status";
+    ORBIT_STATUS, OrbitStatus, "orbit_status";
+    PARAMETER_UPDATE, ParameterUpdate, "parameter_update";
+    PING, Ping, "ping";
+    POSITION_CONTROLLER_LANDING_STATUS, PositionControllerLandingStatus, "position_controller_landing_status";
+    POSITION_CONTROLLER_STATUS, PositionControllerStatus, "position_controller_status";
+    POSITION_SETPOINT_TRIPLET, PositionSetpointTriplet, "position_setpoint_triplet";
+    POWER_BUTTON_STATE, PowerButtonState, "power_button_state";
+    POWER_MONITOR, PowerMonitor, "power_monitor";
+    PWM_INPUT, PwmInput, "pwm_input";
+    PX4IO_STATUS, Px4ioStatus, "px4io_status";
+    RADIO_STATUS, RadioStatus, "radio_status";
+    RATE_CTRL_STATUS, RateCtrlStatus, "rate_ctrl_status";
+    RC_CHANNELS, RcChannels, "rc_channels";
+    RTL_TIME_ESTIMATE, RtlTimeEstimate, "rtl_time_estimate";
+    SAFETY_BUTTON, SafetyButton, "safety_button";
+    SATELLITE_INFO, SatelliteInfo, "satellite_info";
+    SENSOR_ACCEL, SensorAccel, "sensor_accel";
+    SENSOR_BARO, SensorBaro, "sensor_baro";
+    SENSOR_COMBINED, SensorCombined, "sensor_combined";
+    SENSOR_GPS, SensorGps, "sensor_gps";
+    SENSOR_GYRO, SensorGyro, "sensor_gyro";
+    SENSOR_GYRO_FFT, SensorGyroFft, "sensor_gyro_fft";
+    SENSOR_MAG, SensorMag, "sensor_mag";
+    SENSOR_OPTICAL_FLOW, SensorOpticalFlow, "sensor_optical_flow";
+    SENSOR_PREFLIGHT_MAG, SensorPreflightMag, "sensor_preflight_mag";
+    SENSOR_SELECTION, SensorSelection, "sensor_selection";
+    SENSORS_STATUS_IMU, SensorsStatusImu, "sensors_status_imu";
+    SYSTEM_POWER, SystemPower, "system_power";
+    TAKEOFF_STATUS, TakeoffStatus, "takeoff_status";
+    TASK_STACK_INFO, TaskStackInfo, "task_stack_info";
+    TECS_STATUS, TecsStatus, "tecs_status";
+    TELEMETRY_STATUS, TelemetryStatus, "telemetry_status";
+    TRAJECTORY_SETPOINT, TrajectorySetpoint, "trajectory_setpoint";
+    TRANSPONDER_REPORT, TransponderReport, "transponder_report";
+    TUNE_CONTROL, TuneControl, "tune_control";
+    VEHICLE_ACCELERATION, VehicleAcceleration, "vehicle_acceleration";
+    VEHICLE_AIR_DATA, VehicleAirData, "vehicle_air_data";
+    VEHICLE_ANGULAR_VELOCITY, VehicleAngularVelocity, "vehicle_angular_velocity";
+    VEHICLE_ATTITUDE, VehicleAttitude, "vehicle_attitude";
+    VEHICLE_ATTITUDE_SETPOINT, VehicleAttitudeSetpoint, "vehicle_attitude_setpoint";
+    VEHICLE_COMMAND, VehicleCommand, "vehicle_command";
+    VEHICLE_COMMAND_ACK, VehicleCommandAck, "vehicle_command_ack";
+    VEHICLE_CONSTRAINTS, VehicleConstraints, "vehicle_constraints";
+    VEHICLE_CONTROL_MODE, VehicleControlMode, "vehicle_control_mode";
+    VEHICLE_GLOBAL_POSITION, VehicleGlobalPosition, "vehicle_global_position";
+    VEHICLE_GPS_POSITION, VehicleGpsPosition, "vehicle_gps_position";
+    VEHICLE_IMU, VehicleImu, "vehicle_imu";
+    VEHICLE_IMU_STATUS, VehicleImuStatus, "vehicle_imu_status";
+    VEHICLE_LAND_DETECTED, VehicleLandDetected, "vehicle_land_detected";
+    VEHICLE_LOCAL_POSITION, VehicleLocalPosition, "vehicle_local_position";
+    VEHICLE_LOCAL_POSITION_SETPOINT, VehicleLocalPositionSetpoint, "vehicle_local_position_setpoint";
+    VEHICLE_MAGNETOMETER, VehicleMagnetometer, "vehicle_magnetometer";
+    VEHICLE_ODOMETRY, VehicleOdometry, "vehicle_odometry";
+    VEHICLE_OPTICAL_FLOW, VehicleOpticalFlow, "vehicle_optical_flow";
+    VEHICLE_RATES_SETPOINT, VehicleRatesSetpoint, "vehicle_rates_setpoint";
+    VEHICLE_ROI, VehicleRoi, "vehicle_roi";
+    VEHICLE_STATUS, VehicleStatus, "vehicle_status";
+    VEHICLE_THRUST_SETPOINT, VehicleThrustSetpoint, "vehicle_thrust_setpoint";
+    VEHICLE_TORQUE_SETPOINT, VehicleTorqueSetpoint, "vehicle_torque_setpoint";
+    VEHICLE_VISUAL_ODOMETRY, VehicleVisualOdometry, "vehicle_visual_odometry";
+    VTOL_VEHICLE_STATUS, VtolVehicleStatus, "vtol_vehicle_status";
+    WIND, Wind, "wind";
+    YAW_ESTIMATOR_STATUS, YawEstimatorStatus, "yaw_estimator_status";
+}
+
+impl fmt::Display for Px4Topic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl AsRef<str> for Px4Topic {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<Px4Topic> for String {
+    fn from(topic: Px4Topic) -> Self {
+        topic.as_str().to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        assert_eq!(
+            Px4Topic::VehicleLocalPosition.as_str(),
+            VEHICLE_LOCAL_POSITION
+        );
+        assert_eq!(
+            Px4Topic::from_name("sensor_gyro_fft"),
+            Some(Px4Topic::SensorGyroFft)
+        );
+        assert_eq!(Px4Topic::from_name("vehicle_local_positon"), None);
+        assert!(Px4Topic::ALL
+            .windows(2)
+            .all(|w| w[0].as_str() < w[1].as_str()));
+        for topic in Px4Topic::ALL {
+            assert_eq!(Px4Topic::from_name(&String::from(*topic)), Some(*topic));
+        }
+    }
+}
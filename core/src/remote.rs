@@ -0,0 +1,245 @@
+//! Reads logs from an `object_store::ObjectStore`, e.g. S3, GCS or Azure Blob Storage, with range requests.
+//!
+//! Only the chunks which the parser reads are fetched, so a query which stops once the definitions have been
+//! read, e.g. for the info messages and parameters of a log, avoids downloading the logged data.
+//!
+//! ```rust,no_run
+//! use std::sync::Arc;
+//!
+//! use object_store::memory::InMemory;
+//! use object_store::path::Path;
+//! use yule_log::builder::ULogParserBuilder;
+//! use yule_log::model::msg::UlogMessage;
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! // In practice, e.g. an `AmazonS3` store, built with the `aws` feature of `object_store`.
+//! let store = Arc::new(InMemory::new());
+//!
+//! let parser = ULogParserBuilder::open_object(store, Path::from("logs/flight.ulg"))?.build()?;
+//! for msg in parser {
+//!     match msg? {
+//!         UlogMessage::Info(info) => println!("{info}"),
+//!         UlogMessage::AddSubscription(_) => break,
+//!         _ => {}
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::Arc;
+
+use object_store::path::Path;
+use object_store::ObjectStore;
+use tokio::runtime::Runtime;
+
+use crate::builder::ULogParserBuilder;
+use crate::errors::ULogError;
+
+/// The default number of bytes fetched by each range request of an `ObjectReader`.
+pub const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// A reader which fetches an object in chunks, with a range request for each.
+///
+/// The requests are run on a runtime owned by the reader, so it must not be used from within an async context.
+/// Use e.g. `tokio::task::spawn_blocking()` there.
+pub struct ObjectReader {
+    store: Arc<dyn ObjectStore>,
+    path: Path,
+    runtime: Runtime,
+    size: u64,
+    position: u64,
+    chunk_size: usize,
+    chunk: Vec<u8>,
+    chunk_start: u64,
+    bytes_fetched: u64,
+}
+
+impl ObjectReader {
+    pub fn new(store: Arc<dyn ObjectStore>, path: Path) -> Result<Self, ULogError> {
+        Self::with_chunk_size(store, path, DEFAULT_CHUNK_SIZE)
+    }
+
+    pub fn with_chunk_size(
+        store: Arc<dyn ObjectStore>,
+        path: Path,
+        chunk_size: usize,
+    ) -> Result<Self, ULogError> {
+        if chunk_size == 0 {
+            return Err(ULogError::InvalidConfiguration(
+                "The chunk size must be greater than zero.".to_owned(),
+            ));
+        }
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let size = runtime
+            .block_on(store.head(&path))
+            .map_err(io::Error::from)?
+            .size;
+
+        Ok(ObjectReader {
+            store,
+            path,
+            runtime,
+            size,
+            position: 0,
+            chunk_size,
+            chunk: Vec::new(),
+            chunk_start: 0,
+            bytes_fetched: 0,
+        })
+    }
+
+    /// The size of the object.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The number of bytes fetched so far.
+    pub fn bytes_fetched(&self) -> u64 {
+        self.bytes_fetched
+    }
+
+    fn fetch(&mut self) -> io::Result<()> {
+        let end = self.size.min(self.position + self.chunk_size as u64);
+        let bytes = self
+            .runtime
+            .block_on(self.store.get_range(&self.path, self.position..end))?;
+
+        self.bytes_fetched += bytes.len() as u64;
+        self.chunk = bytes.into();
+        self.chunk_start = self.position;
+        Ok(())
+    }
+}
+
+impl Read for ObjectReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.position >= self.size {
+            return Ok(0);
+        }
+
+        let chunk_end = self.chunk_start + self.chunk.len() as u64;
+        if self.position < self.chunk_start || self.position >= chunk_end {
+            self.fetch()?;
+        }
+
+        let offset = (self.position - self.chunk_start) as usize;
+        let len = buf.len().min(self.chunk.len() - offset);
+        buf[..len].copy_from_slice(&self.chunk[offset..offset + len]);
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl Seek for ObjectReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+
+        self.position = position.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Invalid seek to a negative or overflowing position.",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+impl ULogParserBuilder<ObjectReader> {
+    /// Starts a builder for the log at `path` in `store`.
+    pub fn open_object(store: Arc<dyn ObjectStore>, path: Path) -> Result<Self, ULogError> {
+        Ok(ULogParserBuilder::new(ObjectReader::new(store, path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use object_store::memory::InMemory;
+    use object_store::PutPayload;
+
+    use super::*;
+    use crate::model::def::BaseType;
+    use crate::model::msg::UlogMessage;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    fn store(bytes: Vec<u8>) -> Result<Arc<dyn ObjectStore>, ULogError> {
+        let store = InMemory::new();
+        tokio::runtime::Builder::new_current_thread()
+            .build()?
+            .block_on(store.put(&Path::from("flight.ulg"), PutPayload::from(bytes)))
+            .map_err(io::Error::from)?;
+        Ok(Arc::new(store))
+    }
+
+    #[test]
+    fn test_read_object() -> Result<(), ULogError> {
+        let bytes = LogBuilder::new()
+            .info("sys_name", "PX4")
+            .topic(Topic::new("a").field(
+                "x",
+                BaseType::FLOAT,
+                Generator::Ramp {
+                    start: 0.0,
+                    step: 1.0,
+                },
+            ))
+            .build()?;
+        let store = store(bytes.clone())?;
+
+        let reader = ObjectReader::with_chunk_size(store, Path::from("flight.ulg"), 7)?;
+        assert_eq!(reader.size(), bytes.len() as u64);
+
+        let expected: Vec<_> = ULogParserBuilder::new(bytes.as_slice())
+            .build()?
+            .collect::<Result<_, _>>()?;
+        let read: Vec<_> = ULogParserBuilder::new(reader)
+            .build()?
+            .collect::<Result<_, _>>()?;
+        assert_eq!(format!("{read:?}"), format!("{expected:?}"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_metadata_only() -> Result<(), ULogError> {
+        let bytes = LogBuilder::new()
+            .duration(Duration::from_secs(60))
+            .info("sys_name", "PX4")
+            .topic(Topic::new("a").field("x", BaseType::FLOAT, Generator::Constant(1.0)))
+            .build()?;
+        let store = store(bytes.clone())?;
+
+        let mut reader = ObjectReader::with_chunk_size(store, Path::from("flight.ulg"), 256)?;
+        let mut parser = ULogParserBuilder::new(&mut reader).build()?;
+        let mut info = None;
+        for msg in parser.by_ref() {
+            match msg? {
+                UlogMessage::Info(i) => info = Some(i.key),
+                UlogMessage::AddSubscription(_) => break,
+                _ => {}
+            }
+        }
+        drop(parser);
+
+        assert_eq!(info.as_deref(), Some("sys_name"));
+        assert!(reader.bytes_fetched() < bytes.len() as u64 / 10);
+
+        // Seeking back to a fetched position refetches its chunk.
+        reader.seek(SeekFrom::Start(0))?;
+        let mut magic = [0u8; 7];
+        reader.read_exact(&mut magic)?;
+        assert_eq!(&magic, b"ULog\x01\x12\x35");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,255 @@
+//! Replays a recorded log in real time, e.g. to test downstream telemetry consumers against recorded flights.
+//!
+//! ```no_run
+//! use yule_log::parser::ULogParser;
+//! use yule_log::replay::Replayer;
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let reader = std::io::BufReader::new(std::fs::File::open("flight.ulg")?);
+//!
+//! Replayer::new(ULogParser::new(reader)?)
+//!     .speed(2.0)
+//!     .for_each(|msg| println!("{msg:?}"))?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::errors::ULogError;
+use crate::model::msg::UlogMessage;
+
+/// Delivers messages at the pace given by their timestamps.
+///
+/// `LoggedData` and logged string messages are delayed until their timestamp, relative to the first
+/// timestamped message, has elapsed in real time divided by the replay speed.  Messages without a timestamp,
+/// such as parameters, are delivered immediately.  Messages whose timestamp lies before that of an
+/// earlier message, which happens when topics are logged slightly out of order, are not delayed.
+pub struct Replayer<I> {
+    messages: I,
+    speed: f64,
+    max_gap: Option<Duration>,
+}
+
+impl<I> Replayer<I>
+where
+    I: Iterator<Item = Result<UlogMessage, ULogError>>,
+{
+    pub fn new<M>(messages: M) -> Self
+    where
+        M: IntoIterator<IntoIter = I>,
+    {
+        Replayer {
+            messages: messages.into_iter(),
+            speed: 1.0,
+            max_gap: None,
+        }
+    }
+
+    /// The replay speed, as a multiple of real time.  Must be finite and greater than zero.  Defaults to `1.0`.
+    #[must_use]
+    pub fn speed(mut self, speed: f64) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Caps the real time waited between two messages.
+    ///
+    /// Logs sometimes contain messages with bogus timestamps far in the future, which would otherwise stall
+    /// the replay.  With a cap, a jump back in time by more than `max_gap` also resynchronizes the replay
+    /// to the earlier timestamps, instead of delivering all following messages without delay.
+    #[must_use]
+    pub fn max_gap(mut self, max_gap: Option<Duration>) -> Self {
+        self.max_gap = max_gap;
+        self
+    }
+
+    /// Calls `f` with each message once it is due.  Returns the first parse error, if any.
+    pub fn for_each<F>(self, mut f: F) -> Result<(), ULogError>
+    where
+        F: FnMut(UlogMessage),
+    {
+        let mut pacer = Pacer::new(self.speed, self.max_gap)?;
+        let start = Instant::now();
+
+        for message in self.messages {
+            let message = message?;
+
+            if let Some(offset) = timestamp(&message).map(|t| pacer.advance(t)) {
+                let due = start + offset;
+                let now = Instant::now();
+                if due > now {
+                    thread::sleep(due - now);
+                }
+            }
+
+            f(message);
+        }
+
+        Ok(())
+    }
+
+    /// Like `for_each()`, but waits using `tokio::time` instead of blocking the thread.
+    ///
+    /// Parsing itself still reads from the synchronous underlying reader, so the log should already be
+    /// in memory or on a local file system.
+    #[cfg(feature = "tokio")]
+    pub async fn for_each_async<F>(self, mut f: F) -> Result<(), ULogError>
+    where
+        F: FnMut(UlogMessage),
+    {
+        let mut pacer = Pacer::new(self.speed, self.max_gap)?;
+        let start = tokio::time::Instant::now();
+
+        for message in self.messages {
+            let message = message?;
+
+            if let Some(offset) = timestamp(&message).map(|t| pacer.advance(t)) {
+                tokio::time::sleep_until(start + offset).await;
+            }
+
+            f(message);
+        }
+
+        Ok(())
+    }
+}
+
+fn timestamp(message: &UlogMessage) -> Option<u64> {
+    match message {
+        UlogMessage::LoggedData(data) => Some(data.timestamp),
+        UlogMessage::LoggedString(log) | UlogMessage::TaggedLoggedString(log) => {
+            Some(log.timestamp)
+        }
+        _ => None,
+    }
+}
+
+/// Maps log timestamps to offsets from the start of the replay.
+struct Pacer {
+    speed: f64,
+    max_gap: Option<Duration>,
+    /// The latest timestamp seen, in microseconds.
+    latest: Option<u64>,
+    offset: Duration,
+}
+
+impl Pacer {
+    fn new(speed: f64, max_gap: Option<Duration>) -> Result<Self, ULogError> {
+        if !speed.is_finite() || speed <= 0.0 {
+            return Err(ULogError::InvalidConfiguration(format!(
+                "The replay speed must be finite and greater than zero, got {speed}."
+            )));
+        }
+
+        Ok(Pacer {
+            speed,
+            max_gap,
+            latest: None,
+            offset: Duration::ZERO,
+        })
+    }
+
+    fn scale(&self, micros: u64) -> Duration {
+        Duration::try_from_secs_f64(micros as f64 / 1e6 / self.speed).unwrap_or(Duration::MAX)
+    }
+
+    /// Returns the offset at which the message with `timestamp` is due.
+    fn advance(&mut self, timestamp: u64) -> Duration {
+        match self.latest {
+            Some(latest) if timestamp > latest => {
+                let mut gap = self.scale(timestamp - latest);
+                if let Some(max_gap) = self.max_gap {
+                    gap = gap.min(max_gap);
+                }
+                self.offset = self.offset.saturating_add(gap);
+                self.latest = Some(timestamp);
+            }
+            Some(latest) => {
+                let jumped_back = self
+                    .max_gap
+                    .is_some_and(|max_gap| self.scale(latest - timestamp) > max_gap);
+                if jumped_back {
+                    self.latest = Some(timestamp);
+                }
+            }
+            None => self.latest = Some(timestamp),
+        }
+
+        self.offset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::ULogParser;
+
+    fn short_list() -> Result<Vec<u8>, ULogError> {
+        Ok(std::fs::read("test_data/input/short_list.ulg")?)
+    }
+
+    #[test]
+    fn test_pacer() -> Result<(), ULogError> {
+        let mut pacer = Pacer::new(2.0, Some(Duration::from_secs(1)))?;
+
+        assert_eq!(pacer.advance(1_000_000), Duration::ZERO);
+        assert_eq!(pacer.advance(2_000_000), Duration::from_millis(500));
+        // Slightly out of order.
+        assert_eq!(pacer.advance(1_900_000), Duration::from_millis(500));
+        // A bogus timestamp far in the future, and the jump back.
+        assert_eq!(pacer.advance(1_000_000_000), Duration::from_millis(1500));
+        assert_eq!(pacer.advance(2_200_000), Duration::from_millis(1500));
+        assert_eq!(pacer.advance(2_400_000), Duration::from_millis(1600));
+
+        assert!(Pacer::new(0.0, None).is_err());
+        assert!(Pacer::new(f64::NAN, None).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_for_each() -> Result<(), ULogError> {
+        let input = short_list()?;
+        let expected = ULogParser::new(input.as_slice())?.count();
+
+        // The 90us of short_list take 90ms at this speed.
+        let start = Instant::now();
+        let mut messages = 0;
+        Replayer::new(ULogParser::new(input.as_slice())?)
+            .speed(0.001)
+            .for_each(|_| messages += 1)?;
+
+        assert_eq!(messages, expected);
+        assert!(start.elapsed() >= Duration::from_millis(90));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_for_each_async() -> Result<(), ULogError> {
+        let input = short_list()?;
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()?;
+
+        let start = Instant::now();
+        let mut timestamps = Vec::new();
+        runtime.block_on(
+            Replayer::new(ULogParser::new(input.as_slice())?)
+                .speed(0.001)
+                .for_each_async(|msg| {
+                    if let UlogMessage::LoggedData(data) = msg {
+                        timestamps.push(data.timestamp);
+                    }
+                }),
+        )?;
+
+        assert_eq!(timestamps.len(), 10);
+        assert!(start.elapsed() >= Duration::from_millis(90));
+
+        Ok(())
+    }
+}
@@ -28,11 +28,12 @@ mod tests {
                 BufReader::new(File::open(&input_path).expect("Failed to open input file"));
 
             // ⚠️ This test will pass _only_ if all LoggedData messages are included.
-            // If a subscription_allow_list is set, then IgnoredMessages will not be emitted.
+            // If a subscription_allow_list is set, then the data of the other topics is not emitted.
             let parser = ULogParserBuilder::new(reader)
                 .include_header(true)
                 .include_timestamp(true)
                 .include_padding(true)
+                .emit_unhandled(true)
                 .build()
                 .expect("Failed to build parser");
 
@@ -103,7 +104,7 @@ mod tests {
     }
 
     // Function to extract the relative path after the given component
-    fn extract_relative_path(file_path: &PathBuf, component: &str) -> String {
+    fn extract_relative_path(file_path: &Path, component: &str) -> String {
         if let Some(pos) = file_path.to_str().unwrap_or("").find(component) {
             // Add the component back as the prefix
             format!(
@@ -0,0 +1,630 @@
+//! Serves decoded ULOG messages to network clients, e.g. a live telemetry dashboard.
+//!
+//! Messages are published to a `TelemetryHub`, typically from a `ULogParser` following a growing log,
+//! and fanned out to every connected client.  Two transports are provided:
+//!
+//! - `serve_websocket()`: each message is sent as a WebSocket text frame holding a JSON object,
+//!   in the format of `export::ndjson`.
+//! - `serve_binary()`: each message is sent as a frame consisting of a little endian `u32` length,
+//!   followed by the ULOG encoding of the message.  New clients first receive the header and the
+//!   definitions published so far, so the concatenated frame contents form a valid ULOG stream which
+//!   can be read with `ULogParser`.
+//!
+//! Clients control which topics they receive by sending text commands, as WebSocket text frames or
+//! as length-prefixed frames respectively:
+//!
+//! - `subscribe <topic> [<topic> ...]`
+//! - `unsubscribe <topic> [<topic> ...]`
+//!
+//! where `*` stands for all topics.  Clients start out subscribed to all topics.  Subscriptions only
+//! apply to `LoggedData` messages; other messages, such as log strings, are always sent.
+//! `TelemetryHub::wait_for_commands()` waits for commands to be applied, e.g. before publishing to a
+//! client which has just subscribed.
+//!
+//! Each client has a bounded queue.  Data messages for a client whose queue is full are dropped, so a
+//! slow client never stalls the publisher or other clients.  A client which falls behind on any other
+//! message is disconnected, as it would no longer be able to decode the stream.
+//!
+//! `TelemetryHub::close()` ends the streams of the connected clients: binary protocol connections are
+//! shut down, and WebSocket clients are sent a Close frame.
+//!
+//! The server uses a thread per client with blocking I/O, and publishing blocks on nothing but the hub's
+//! lock.  It doesn't depend on an async runtime; from async code, publish to the hub from a blocking task.
+
+use std::collections::HashSet;
+use std::io::{self, Read, Write};
+use std::net::Shutdown;
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError, TrySendError};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tungstenite::{Message, WebSocket};
+
+use crate::diag;
+use crate::encode::Encode;
+use crate::errors::ULogError;
+use crate::export::ndjson;
+use crate::model::msg::UlogMessage;
+
+/// The default number of messages queued per client.
+pub const DEFAULT_QUEUE_CAPACITY: usize = 1024;
+
+/// The maximum size of a command frame sent by a binary protocol client.
+const MAX_COMMAND_SIZE: usize = 64 * 1024;
+
+/// How long a WebSocket connection waits for client commands before checking for outgoing messages.
+const WEBSOCKET_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Protocol {
+    Json,
+    Binary,
+}
+
+/// The topics a client is subscribed to.
+///
+/// While subscribed to all topics, `topics` holds the topics unsubscribed from since.
+#[derive(Debug)]
+struct Subscriptions {
+    all: bool,
+    topics: HashSet<String>,
+}
+
+impl Subscriptions {
+    fn contains(&self, topic: &str) -> bool {
+        self.all != self.topics.contains(topic)
+    }
+
+    /// Applies a client command.
+    fn apply(&mut self, command: &str) -> Result<(), ULogError> {
+        let mut words = command.split_whitespace();
+        let subscribe = match words.next() {
+            Some("subscribe") => true,
+            Some("unsubscribe") => false,
+            _ => {
+                return Err(ULogError::ParseError(format!(
+                    "Invalid client command: {command}"
+                )))
+            }
+        };
+
+        for topic in words {
+            if topic == "*" {
+                self.all = subscribe;
+                self.topics.clear();
+            } else if subscribe != self.all {
+                self.topics.insert(topic.to_owned());
+            } else {
+                self.topics.remove(topic);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+type Payload = Arc<Vec<u8>>;
+
+struct Client {
+    protocol: Protocol,
+    subscriptions: Arc<Mutex<Subscriptions>>,
+    sender: SyncSender<Payload>,
+}
+
+/// A published message, encoded once for each protocol.
+struct Encoded {
+    json: Option<Payload>,
+    binary: Payload,
+}
+
+impl Encoded {
+    fn payload(&self, protocol: Protocol) -> Option<&Payload> {
+        match protocol {
+            Protocol::Json => self.json.as_ref(),
+            Protocol::Binary => Some(&self.binary),
+        }
+    }
+}
+
+#[derive(Default)]
+struct HubState {
+    /// The header and definitions published so far, sent to new clients.
+    preamble: Vec<Arc<Encoded>>,
+    clients: Vec<Client>,
+    queue_capacity: usize,
+}
+
+/// Fans published messages out to connected clients.  Cloning a hub yields a handle to the same hub.
+#[derive(Clone)]
+pub struct TelemetryHub {
+    state: Arc<Mutex<HubState>>,
+    /// The number of client commands applied, signalled on each.
+    commands: Arc<(Mutex<u64>, Condvar)>,
+}
+
+impl Default for TelemetryHub {
+    fn default() -> Self {
+        Self::new(DEFAULT_QUEUE_CAPACITY)
+    }
+}
+
+impl TelemetryHub {
+    /// Creates a hub which queues up to `queue_capacity` messages per client.
+    pub fn new(queue_capacity: usize) -> Self {
+        TelemetryHub {
+            state: Arc::new(Mutex::new(HubState {
+                queue_capacity: queue_capacity.max(1),
+                ..HubState::default()
+            })),
+            commands: Arc::default(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HubState> {
+        // A poisoned lock only means a client thread panicked, the state itself stays consistent.
+        self.state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Returns the number of connected clients.
+    pub fn client_count(&self) -> usize {
+        self.lock().clients.len()
+    }
+
+    /// Waits until `count` client commands in all have been applied, invalid ones included, or until
+    /// `timeout` has passed.  Returns whether they were.
+    pub fn wait_for_commands(&self, count: u64, timeout: Duration) -> bool {
+        let (applied, condvar) = &*self.commands;
+        let deadline = Instant::now() + timeout;
+        let mut applied = applied
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        while *applied < count {
+            let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
+                return false;
+            };
+            applied = condvar
+                .wait_timeout(applied, remaining)
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .0;
+        }
+        true
+    }
+
+    /// Disconnects the connected clients once they have been sent the messages queued for them.  Clients
+    /// which connect later are served as before.
+    pub fn close(&self) {
+        // Dropping the senders ends each client's queue after its last message.
+        self.lock().clients.clear();
+    }
+
+    /// Applies a command of a client to its subscriptions.
+    fn apply_command(
+        &self,
+        subscriptions: &Mutex<Subscriptions>,
+        command: &str,
+    ) -> Result<(), ULogError> {
+        let result = subscriptions
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .apply(command);
+
+        let (applied, condvar) = &*self.commands;
+        *applied
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) += 1;
+        condvar.notify_all();
+        result
+    }
+
+    /// Publishes a message to all clients subscribed to it.
+    pub fn publish(&self, message: &UlogMessage) -> Result<(), ULogError> {
+        let is_preamble = matches!(
+            message,
+            UlogMessage::Header(_)
+                | UlogMessage::FlagBits(_)
+                | UlogMessage::FormatDefinition(_)
+                | UlogMessage::AddSubscription(_)
+                | UlogMessage::Info(_)
+                | UlogMessage::MultiInfo(_)
+                | UlogMessage::Parameter(_)
+                | UlogMessage::DefaultParameter(_)
+        );
+
+        let topic = match message {
+            UlogMessage::LoggedData(data) => Some(&*data.data.name),
+            _ => None,
+        };
+
+        let mut binary = Vec::new();
+        message.encode(&mut binary)?;
+
+        let encoded = Arc::new(Encoded {
+            json: ndjson::to_json(message).map(|json| Arc::new(json.into_bytes())),
+            binary: Arc::new(binary),
+        });
+
+        let mut state = self.lock();
+
+        if is_preamble {
+            state.preamble.push(encoded.clone());
+        }
+
+        state.clients.retain(|client| {
+            let Some(payload) = encoded.payload(client.protocol) else {
+                return true;
+            };
+
+            if let Some(topic) = topic {
+                let subscriptions = client
+                    .subscriptions
+                    .lock()
+                    .unwrap_or_else(std::sync::PoisonError::into_inner);
+                if !subscriptions.contains(topic) {
+                    return true;
+                }
+            }
+
+            match client.sender.try_send(payload.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => topic.is_some(),
+                Err(TrySendError::Disconnected(_)) => false,
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Publishes all messages of `messages`, e.g. a `ULogParser`.
+    ///
+    /// For binary protocol clients to be able to decode the stream, the parser must be configured to
+    /// include the header, timestamps and padding, as for `ULogWriter`.
+    pub fn publish_all<I>(&self, messages: I) -> Result<(), ULogError>
+    where
+        I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+    {
+        for message in messages {
+            self.publish(&message?)?;
+        }
+        Ok(())
+    }
+
+    /// Registers a client, returning the preamble it must be sent before any queued messages.
+    fn register(
+        &self,
+        protocol: Protocol,
+    ) -> (Vec<Payload>, Arc<Mutex<Subscriptions>>, Receiver<Payload>) {
+        let mut state = self.lock();
+        let (sender, receiver) = mpsc::sync_channel(state.queue_capacity);
+        let subscriptions = Arc::new(Mutex::new(Subscriptions {
+            all: true,
+            topics: HashSet::new(),
+        }));
+
+        let preamble = state
+            .preamble
+            .iter()
+            .filter_map(|encoded| encoded.payload(protocol).cloned())
+            .collect();
+
+        state.clients.push(Client {
+            protocol,
+            subscriptions: subscriptions.clone(),
+            sender,
+        });
+
+        (preamble, subscriptions, receiver)
+    }
+}
+
+/// Accepts WebSocket clients on `listener`, serving them messages published to `hub` as JSON.
+///
+/// Blocks for as long as the listener accepts connections.
+pub fn serve_websocket(listener: TcpListener, hub: TelemetryHub) -> Result<(), ULogError> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let hub = hub.clone();
+
+        thread::spawn(move || {
+            if let Err(e) = handle_websocket_client(stream, &hub) {
+                diag::debug!("WebSocket client disconnected: {e}");
+            }
+        });
+    }
+
+    Ok(())
+}
+
+fn handle_websocket_client(stream: TcpStream, hub: &TelemetryHub) -> Result<(), ULogError> {
+    let mut websocket =
+        tungstenite::accept(stream).map_err(|e| ULogError::Io(io::Error::other(e.to_string())))?;
+
+    websocket
+        .get_mut()
+        .set_read_timeout(Some(WEBSOCKET_POLL_INTERVAL))?;
+
+    let (preamble, subscriptions, receiver) = hub.register(Protocol::Json);
+
+    for payload in preamble {
+        send_text(&mut websocket, &payload)?;
+    }
+
+    loop {
+        loop {
+            match receiver.try_recv() {
+                Ok(payload) => send_text(&mut websocket, &payload)?,
+                Err(TryRecvError::Empty) => break,
+                // The hub dropped this client, e.g. on `TelemetryHub::close()`.
+                Err(TryRecvError::Disconnected) => {
+                    websocket
+                        .close(None)
+                        .and_then(|()| websocket.flush())
+                        .map_err(|e| ULogError::Io(io::Error::other(e.to_string())))?;
+                    return Ok(());
+                }
+            }
+        }
+
+        match websocket.read() {
+            Ok(Message::Text(command)) => {
+                if let Err(e) = hub.apply_command(&subscriptions, command.as_str()) {
+                    send_text(&mut websocket, error_json(&e).as_bytes())?;
+                }
+            }
+            Ok(Message::Close(_)) => return Ok(()),
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e))
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            Err(e) => return Err(ULogError::Io(io::Error::other(e.to_string()))),
+        }
+    }
+}
+
+fn send_text(websocket: &mut WebSocket<TcpStream>, payload: &[u8]) -> Result<(), ULogError> {
+    let text = String::from_utf8(payload.to_vec())?;
+    websocket
+        .send(Message::text(text))
+        .map_err(|e| ULogError::Io(io::Error::other(e.to_string())))
+}
+
+fn error_json(e: &ULogError) -> String {
+    format!(
+        "{{\"type\":\"error\",\"message\":{}}}",
+        crate::export::json_string(&e.to_string())
+    )
+}
+
+/// Accepts clients of the length-prefixed binary protocol on `listener`, serving them messages published to `hub`.
+///
+/// Blocks for as long as the listener accepts connections.
+pub fn serve_binary(listener: TcpListener, hub: TelemetryHub) -> Result<(), ULogError> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let hub = hub.clone();
+
+        thread::spawn(move || {
+            if let Err(e) = handle_binary_client(stream, &hub) {
+                diag::debug!("Binary protocol client disconnected: {e}");
+            }
+        });
+    }
+
+    Ok(())
+}
+
+fn handle_binary_client(mut stream: TcpStream, hub: &TelemetryHub) -> Result<(), ULogError> {
+    let (preamble, subscriptions, receiver) = hub.register(Protocol::Binary);
+
+    let mut command_stream = stream.try_clone()?;
+    let command_hub = hub.clone();
+    thread::spawn(move || {
+        while let Ok(command) = read_frame(&mut command_stream) {
+            let command = String::from_utf8_lossy(&command);
+            if let Err(e) = command_hub.apply_command(&subscriptions, &command) {
+                diag::warn!("{e}");
+            }
+        }
+    });
+
+    for payload in preamble {
+        write_frame(&mut stream, &payload)?;
+    }
+
+    // The receiver is disconnected once the hub drops this client.
+    for payload in receiver {
+        write_frame(&mut stream, &payload)?;
+    }
+
+    // Also ends the command thread, whose clone of the stream would otherwise keep the connection open.
+    stream.flush()?;
+    stream.shutdown(Shutdown::Both)?;
+    Ok(())
+}
+
+/// Writes a length-prefixed frame.
+pub fn write_frame<W: Write>(writer: &mut W, payload: &[u8]) -> Result<(), ULogError> {
+    let len = u32::try_from(payload.len())
+        .map_err(|_| ULogError::InvalidConfiguration("Frame too large.".to_owned()))?;
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(payload)?;
+    Ok(())
+}
+
+/// Reads a length-prefixed frame.
+pub fn read_frame<R: Read>(reader: &mut R) -> Result<Vec<u8>, ULogError> {
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len) as usize;
+
+    if len > MAX_COMMAND_SIZE {
+        return Err(ULogError::ParseError(format!(
+            "Frame of {len} bytes exceeds the maximum of {MAX_COMMAND_SIZE} bytes."
+        )));
+    }
+
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload)?;
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::parser::ULogParser;
+
+    const SAMPLE_LOG: &str = "test_data/input/sample_log_small.ulg";
+
+    fn sample_parser() -> Result<ULogParser<std::io::BufReader<std::fs::File>>, ULogError> {
+        ULogParserBuilder::new(std::io::BufReader::new(std::fs::File::open(SAMPLE_LOG)?))
+            .include_header(true)
+            .include_timestamp(true)
+            .include_padding(true)
+            .build()
+    }
+
+    fn first_topic() -> Result<String, ULogError> {
+        for message in sample_parser()? {
+            if let UlogMessage::LoggedData(data) = message? {
+                return Ok(data.data.name.to_string());
+            }
+        }
+        panic!("No data in sample log");
+    }
+
+    #[test]
+    fn test_subscriptions() {
+        let mut subscriptions = Subscriptions {
+            all: true,
+            topics: HashSet::new(),
+        };
+        assert!(subscriptions.contains("a"));
+
+        subscriptions.apply("unsubscribe *").unwrap();
+        subscriptions.apply("subscribe a b").unwrap();
+        assert!(subscriptions.contains("a"));
+        assert!(!subscriptions.contains("c"));
+
+        subscriptions.apply("unsubscribe a").unwrap();
+        assert!(!subscriptions.contains("a"));
+
+        subscriptions.apply("subscribe *").unwrap();
+        subscriptions.apply("unsubscribe b").unwrap();
+        assert!(subscriptions.contains("a"));
+        assert!(!subscriptions.contains("b"));
+
+        assert!(subscriptions.apply("publish a").is_err());
+    }
+
+    #[test]
+    fn test_serve_binary() -> Result<(), ULogError> {
+        let topic = first_topic()?;
+        let hub = TelemetryHub::new(100_000);
+
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let server_hub = hub.clone();
+        thread::spawn(move || serve_binary(listener, server_hub));
+
+        let mut client = TcpStream::connect(addr)?;
+        write_frame(&mut client, b"unsubscribe *")?;
+        write_frame(&mut client, format!("subscribe {topic}").as_bytes())?;
+        assert!(hub.wait_for_commands(2, Duration::from_secs(5)));
+
+        hub.publish_all(sample_parser()?)?;
+        hub.close();
+
+        let expected = sample_parser()?
+            .filter(|m| matches!(m, Ok(UlogMessage::LoggedData(d)) if *d.data.name == *topic))
+            .count();
+        assert!(expected > 0);
+
+        // Read frames until the server closes the connection, then decode their contents as a ULOG stream.
+        let mut stream = Vec::new();
+        loop {
+            let mut len = [0u8; 4];
+            match client.read_exact(&mut len) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            }
+            let mut payload = vec![0u8; u32::from_le_bytes(len) as usize];
+            client.read_exact(&mut payload)?;
+            stream.extend_from_slice(&payload);
+        }
+
+        let mut received = 0;
+        for message in ULogParser::new(stream.as_slice())? {
+            if let UlogMessage::LoggedData(data) = message? {
+                assert_eq!(*data.data.name, *topic);
+                received += 1;
+            }
+        }
+        assert_eq!(received, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_serve_websocket() -> Result<(), ULogError> {
+        let topic = first_topic()?;
+        let hub = TelemetryHub::new(100_000);
+
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let server_hub = hub.clone();
+        thread::spawn(move || serve_websocket(listener, server_hub));
+
+        let (mut client, _) = tungstenite::connect(format!("ws://{addr}"))
+            .map_err(|e| ULogError::Io(io::Error::other(e.to_string())))?;
+        let send = |client: &mut WebSocket<_>, text: &str| {
+            client
+                .send(Message::text(text))
+                .map_err(|e| ULogError::Io(io::Error::other(e.to_string())))
+        };
+        send(&mut client, "unsubscribe *")?;
+        send(&mut client, &format!("subscribe {topic}"))?;
+        assert!(hub.wait_for_commands(2, Duration::from_secs(5)));
+
+        hub.publish_all(sample_parser()?)?;
+        hub.close();
+
+        let expected = sample_parser()?
+            .filter(|m| matches!(m, Ok(UlogMessage::LoggedData(d)) if *d.data.name == *topic))
+            .count();
+
+        // Read messages until the server closes the connection.
+        let mut saw_header = false;
+        let mut data = 0;
+        loop {
+            let message = client
+                .read()
+                .map_err(|e| ULogError::Io(io::Error::other(e.to_string())))?;
+            let text = match message {
+                Message::Text(text) => text,
+                Message::Close(_) => break,
+                _ => continue,
+            };
+            let value: serde_json::Value = serde_json::from_str(text.as_str()).unwrap();
+
+            match value["type"].as_str().unwrap() {
+                "header" => saw_header = true,
+                "data" => {
+                    assert_eq!(value["topic"], topic.as_str());
+                    data += 1;
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(data, expected);
+        assert!(saw_header);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,317 @@
+//! Sends decoded messages to a destination one at a time, e.g. to a message broker for live ingestion.
+//!
+//! `UlogSink` is the interface of such destinations.  `StreamSink` is a sink which publishes each data message
+//! to a subject per topic, e.g. a Kafka topic or a NATS subject, through a `Publisher` which wraps the client of
+//! the broker.  The `nats` and `kafka` features add `nats::NatsPublisher` and `kafka::KafkaPublisher`.
+//!
+//! Other brokers need a `Publisher` of your own:
+//!
+//! ```rust
+//! use yule_log::errors::ULogError;
+//! use yule_log::sink::{Publication, Publisher};
+//!
+//! struct StdoutPublisher;
+//!
+//! impl Publisher for StdoutPublisher {
+//!     fn publish(&mut self, publication: &Publication) -> Result<(), ULogError> {
+//!         println!("{}: {}", publication.subject, String::from_utf8_lossy(publication.payload));
+//!         Ok(())
+//!     }
+//! }
+//! ```
+//!
+//! With `Encoding::Avro`, data messages are encoded as Avro records, framed as by the Confluent serializers: a zero
+//! byte and the 4 byte ID of the schema, which the publisher registers with `Publisher::register_schema()`.  With
+//! the `schema_registry` feature, `registry::RegistryPublisher` registers them with a Confluent schema registry.
+
+#[cfg(feature = "kafka")]
+pub mod kafka;
+#[cfg(feature = "nats")]
+pub mod nats;
+#[cfg(feature = "schema_registry")]
+pub mod registry;
+
+use std::collections::HashMap;
+
+use crate::errors::ULogError;
+use crate::export::avro;
+use crate::export::ndjson::NdjsonExporter;
+use crate::export::InvalidFloatPolicy;
+use crate::model::msg::UlogMessage;
+
+/// A destination for decoded messages.
+pub trait UlogSink {
+    /// Sends a message.  Sinks may ignore messages they have no use for.
+    fn send(&mut self, message: &UlogMessage) -> Result<(), ULogError>;
+
+    /// Sends any messages which the sink has buffered.
+    fn flush(&mut self) -> Result<(), ULogError> {
+        Ok(())
+    }
+
+    /// Sends `messages`, and then flushes the sink.  Returns the number of messages sent.
+    fn send_all<I>(&mut self, messages: I) -> Result<u64, ULogError>
+    where
+        I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+        Self: Sized,
+    {
+        let mut sent = 0;
+        for message in messages {
+            self.send(&message?)?;
+            sent += 1;
+        }
+        self.flush()?;
+        Ok(sent)
+    }
+}
+
+/// A message to be published to a broker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Publication<'a> {
+    /// The Kafka topic or NATS subject.
+    pub subject: &'a str,
+    /// The key of the message, which Kafka partitions by.  The `multi_id` of data messages, so that the messages
+    /// of a topic instance stay in order, and empty for other messages.
+    pub key: &'a str,
+    /// The MIME type of the payload.
+    pub content_type: &'a str,
+    pub payload: &'a [u8],
+}
+
+/// Publishes messages to a broker, e.g. with a Kafka producer or a NATS connection.
+///
+/// Errors should be returned as `ULogError::ExportError`.
+pub trait Publisher {
+    fn publish(&mut self, publication: &Publication) -> Result<(), ULogError>;
+
+    /// Waits until the messages published so far have been delivered.
+    fn flush(&mut self) -> Result<(), ULogError> {
+        Ok(())
+    }
+
+    /// Registers `schema`, an Avro schema, under `subject` in a schema registry, and returns its ID.  Called
+    /// once per topic by a `StreamSink` with `Encoding::Avro`, with the subject of the topic followed by
+    /// `-value`.
+    fn register_schema(&mut self, subject: &str, schema: &str) -> Result<u32, ULogError> {
+        let _ = (subject, schema);
+        Err(ULogError::InvalidConfiguration(
+            "Avro encoding needs a publisher with a schema registry.".to_owned(),
+        ))
+    }
+}
+
+/// The encoding of the payloads published by a `StreamSink`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// A JSON object per message, as written by `export::ndjson`.
+    #[default]
+    Json,
+    /// An Avro record per data message, as encoded by `export::avro`.  Other messages are still JSON.
+    Avro,
+}
+
+/// A sink which publishes each data message to the subject of its topic.  See the module documentation.
+#[derive(Debug, Clone)]
+pub struct StreamSink<P> {
+    publisher: P,
+    subject_prefix: String,
+    other_subject: Option<String>,
+    encoding: Encoding,
+    json: NdjsonExporter,
+    // topic -> the ID of its Avro schema.
+    schema_ids: HashMap<String, u32>,
+    published: u64,
+}
+
+impl<P: Publisher> StreamSink<P> {
+    pub fn new(publisher: P) -> Self {
+        StreamSink {
+            publisher,
+            subject_prefix: String::new(),
+            other_subject: None,
+            encoding: Encoding::default(),
+            json: NdjsonExporter::new(),
+            schema_ids: HashMap::new(),
+            published: 0,
+        }
+    }
+
+    /// Prepended to the topic name to give the subject of a data message, e.g. `px4.` for NATS.  Defaults to
+    /// nothing.
+    #[must_use]
+    pub fn subject_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.subject_prefix = prefix.into();
+        self
+    }
+
+    /// The subject of the other messages exported by `export::ndjson`, such as logged strings and parameter
+    /// changes.  Defaults to `None`, which drops them.
+    #[must_use]
+    pub fn other_messages(mut self, subject: Option<String>) -> Self {
+        self.other_subject = subject;
+        self
+    }
+
+    /// The encoding of the payloads.  Defaults to `Encoding::Json`.
+    #[must_use]
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// How NaN and infinite floats are encoded.  Defaults to `InvalidFloatPolicy::AsNull`.
+    #[must_use]
+    pub fn invalid_floats(mut self, policy: InvalidFloatPolicy) -> Self {
+        self.json = self.json.invalid_floats(policy);
+        self
+    }
+
+    /// The number of messages published.
+    pub fn published(&self) -> u64 {
+        self.published
+    }
+
+    pub fn into_inner(self) -> P {
+        self.publisher
+    }
+}
+
+impl<P: Publisher> UlogSink for StreamSink<P> {
+    fn send(&mut self, message: &UlogMessage) -> Result<(), ULogError> {
+        let (subject, key) = match message {
+            UlogMessage::LoggedData(data) => (
+                format!("{}{}", self.subject_prefix, data.data.name),
+                data.data.multi_id_index.unwrap_or(0).to_string(),
+            ),
+            _ => match &self.other_subject {
+                Some(subject) => (subject.clone(), String::new()),
+                None => return Ok(()),
+            },
+        };
+
+        let (content_type, payload) = match (self.encoding, message) {
+            (Encoding::Avro, UlogMessage::LoggedData(data)) => {
+                let schema_id = match self.schema_ids.get(&*data.data.name) {
+                    Some(&id) => id,
+                    None => {
+                        let schema = avro::data_schema(&data.data)?;
+                        let id = self
+                            .publisher
+                            .register_schema(&format!("{subject}-value"), &schema)?;
+                        self.schema_ids.insert(data.data.name.to_string(), id);
+                        id
+                    }
+                };
+                let mut payload = vec![0];
+                payload.extend_from_slice(&schema_id.to_be_bytes());
+                avro::encode(data, &mut payload);
+                ("avro/binary", payload)
+            }
+            _ => match self.json.to_json(message) {
+                Some(json) => ("application/json", json.into_bytes()),
+                None => return Ok(()),
+            },
+        };
+
+        self.publisher.publish(&Publication {
+            subject: &subject,
+            key: &key,
+            content_type,
+            payload: &payload,
+        })?;
+        self.published += 1;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), ULogError> {
+        self.publisher.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::model::def::BaseType;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    #[derive(Default)]
+    struct VecPublisher {
+        publications: Vec<(String, String, Vec<u8>)>,
+        schemas: Vec<String>,
+        flushed: bool,
+    }
+
+    impl Publisher for VecPublisher {
+        fn publish(&mut self, publication: &Publication) -> Result<(), ULogError> {
+            self.publications.push((
+                publication.subject.to_owned(),
+                publication.key.to_owned(),
+                publication.payload.to_vec(),
+            ));
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), ULogError> {
+            self.flushed = true;
+            Ok(())
+        }
+
+        fn register_schema(&mut self, subject: &str, _schema: &str) -> Result<u32, ULogError> {
+            self.schemas.push(subject.to_owned());
+            Ok(41 + self.schemas.len() as u32)
+        }
+    }
+
+    fn log() -> Result<Vec<u8>, ULogError> {
+        let topic = |multi_id| {
+            Topic::new("battery_status")
+                .multi_id(multi_id)
+                .rate_hz(2.0)
+                .field("voltage_v", BaseType::FLOAT, Generator::Constant(16.5))
+        };
+        LogBuilder::new().topic(topic(0)).topic(topic(1)).build()
+    }
+
+    #[test]
+    fn test_stream_sink() -> Result<(), ULogError> {
+        let bytes = log()?;
+        let parser = ULogParserBuilder::new(bytes.as_slice()).build()?;
+
+        let mut sink = StreamSink::new(VecPublisher::default()).subject_prefix("px4.");
+        let sent = sink.send_all(parser)?;
+        assert!(sent > 4);
+        assert_eq!(sink.published(), 4);
+
+        let publisher = sink.into_inner();
+        assert!(publisher.flushed);
+        let (subject, key, payload) = &publisher.publications[1];
+        assert_eq!(
+            (subject.as_str(), key.as_str()),
+            ("px4.battery_status", "1")
+        );
+        let json: serde_json::Value = serde_json::from_slice(payload).unwrap();
+        assert_eq!(json["multi_id"], 1);
+        assert_eq!(json["data"]["voltage_v"], 16.5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_avro() -> Result<(), ULogError> {
+        let bytes = log()?;
+        let parser = ULogParserBuilder::new(bytes.as_slice()).build()?;
+
+        let mut sink = StreamSink::new(VecPublisher::default()).encoding(Encoding::Avro);
+        sink.send_all(parser)?;
+
+        let publisher = sink.into_inner();
+        assert_eq!(publisher.schemas, ["battery_status-value"]);
+        assert_eq!(publisher.publications.len(), 4);
+        for (_, _, payload) in &publisher.publications {
+            assert_eq!(payload[..5], [0, 0, 0, 0, 42]);
+        }
+
+        Ok(())
+    }
+}
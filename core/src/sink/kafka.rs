@@ -0,0 +1,113 @@
+//! A `Publisher` over a `kafka::producer::Producer`, which speaks the Kafka protocol without librdkafka.
+//!
+//! ```rust,no_run
+//! use yule_log::builder::ULogParserBuilder;
+//! use yule_log::sink::kafka::KafkaPublisher;
+//! use yule_log::sink::{StreamSink, UlogSink};
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let publisher = KafkaPublisher::connect(vec!["localhost:9092".to_owned()])?;
+//! let mut sink = StreamSink::new(publisher).subject_prefix("px4.");
+//! sink.send_all(ULogParserBuilder::new(std::fs::File::open("flight.ulg")?).build()?)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use kafka::producer::{Producer, Record};
+
+use crate::errors::ULogError;
+use crate::sink::{Publication, Publisher};
+
+/// The default number of messages sent to the brokers per produce request.
+pub const DEFAULT_BATCH_SIZE: usize = 1000;
+
+/// Publishes to Kafka topics, in batches of produce requests.
+///
+/// Kafka messages are keyed by `Publication::key`.  Content types are not sent, as the producer does not support
+/// headers; Avro payloads carry the ID of their schema instead.
+pub struct KafkaPublisher {
+    producer: Producer,
+    batch_size: usize,
+    // (topic, key, payload) of the messages not yet sent.
+    batch: Vec<(String, String, Vec<u8>)>,
+}
+
+impl KafkaPublisher {
+    /// Connects to the Kafka cluster of `hosts`, e.g. `localhost:9092`.
+    pub fn connect(hosts: Vec<String>) -> Result<Self, ULogError> {
+        let producer = Producer::from_hosts(hosts).create().map_err(kafka_error)?;
+        Ok(Self::new(producer))
+    }
+
+    /// Publishes with `producer`, e.g. one built with the acknowledgements or compression you need.
+    pub fn new(producer: Producer) -> Self {
+        KafkaPublisher {
+            producer,
+            batch_size: DEFAULT_BATCH_SIZE,
+            batch: Vec::new(),
+        }
+    }
+
+    /// The number of messages sent per produce request.  Defaults to `DEFAULT_BATCH_SIZE`.
+    pub fn batch_size(mut self, batch_size: usize) -> Result<Self, ULogError> {
+        if batch_size == 0 {
+            return Err(ULogError::InvalidConfiguration(
+                "The batch size must be greater than zero.".to_owned(),
+            ));
+        }
+        self.batch_size = batch_size;
+        Ok(self)
+    }
+
+    pub fn into_inner(self) -> Producer {
+        self.producer
+    }
+}
+
+impl Publisher for KafkaPublisher {
+    fn publish(&mut self, publication: &Publication) -> Result<(), ULogError> {
+        self.batch.push((
+            publication.subject.to_owned(),
+            publication.key.to_owned(),
+            publication.payload.to_vec(),
+        ));
+        if self.batch.len() >= self.batch_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Sends the batched messages, and waits for the brokers to acknowledge them.
+    fn flush(&mut self) -> Result<(), ULogError> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+
+        // An empty key is sent as no key, which spreads the messages over the partitions.
+        let records: Vec<_> = self
+            .batch
+            .iter()
+            .map(|(topic, key, payload)| {
+                Record::from_key_value(topic, key.as_bytes(), payload.as_slice())
+            })
+            .collect();
+        let confirms = self.producer.send_all(&records).map_err(kafka_error)?;
+        for confirm in confirms {
+            for partition in confirm.partition_confirms {
+                if let Err(e) = partition.offset {
+                    return Err(ULogError::ExportError(format!(
+                        "Kafka rejected the messages for partition {} of {}: {e:?}",
+                        partition.partition, confirm.topic
+                    )));
+                }
+            }
+        }
+
+        self.batch.clear();
+        Ok(())
+    }
+}
+
+fn kafka_error(e: kafka::Error) -> ULogError {
+    ULogError::ExportError(format!("Kafka: {e}"))
+}
@@ -0,0 +1,174 @@
+//! A `Publisher` over an `async_nats::Client`.
+//!
+//! ```rust,no_run
+//! use yule_log::builder::ULogParserBuilder;
+//! use yule_log::sink::nats::NatsPublisher;
+//! use yule_log::sink::{StreamSink, UlogSink};
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let publisher = NatsPublisher::connect("nats://localhost:4222")?;
+//! let mut sink = StreamSink::new(publisher).subject_prefix("px4.x500-07.");
+//! sink.send_all(ULogParserBuilder::new(std::fs::File::open("flight.ulg")?).build()?)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use async_nats::header::HeaderMap;
+use async_nats::{Client, ConnectOptions};
+use bytes::Bytes;
+use tokio::runtime::Runtime;
+
+use crate::errors::ULogError;
+use crate::sink::{Publication, Publisher};
+
+/// The header which carries `Publication::key`, as NATS messages have no key of their own.
+pub const KEY_HEADER: &str = "Ulog-Key";
+
+/// Publishes to NATS subjects, with the content type and key of each publication as headers.
+///
+/// The client runs on a runtime owned by the publisher, so it must not be used from within an async context.  Use
+/// e.g. `tokio::task::spawn_blocking()` there.
+pub struct NatsPublisher {
+    client: Client,
+    runtime: Runtime,
+}
+
+impl NatsPublisher {
+    /// Connects to the NATS server at `url`.
+    pub fn connect(url: &str) -> Result<Self, ULogError> {
+        Self::connect_with_options(url, ConnectOptions::new())
+    }
+
+    /// Like `connect()`, with `options` for e.g. credentials or TLS.
+    pub fn connect_with_options(url: &str, options: ConnectOptions) -> Result<Self, ULogError> {
+        // One worker, so that the connection keeps up pings and reconnects between calls.
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?;
+        let client = runtime
+            .block_on(options.connect(url))
+            .map_err(|e| ULogError::ExportError(format!("Cannot connect to NATS at {url}: {e}")))?;
+        Ok(NatsPublisher { client, runtime })
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+}
+
+impl Publisher for NatsPublisher {
+    fn publish(&mut self, publication: &Publication) -> Result<(), ULogError> {
+        let mut headers = HeaderMap::new();
+        headers.insert("Content-Type", publication.content_type);
+        if !publication.key.is_empty() {
+            headers.insert(KEY_HEADER, publication.key);
+        }
+
+        let payload = Bytes::copy_from_slice(publication.payload);
+        self.runtime
+            .block_on(self.client.publish_with_headers(
+                publication.subject.to_owned(),
+                headers,
+                payload,
+            ))
+            .map_err(|e| {
+                ULogError::ExportError(format!("Cannot publish to {}: {e}", publication.subject))
+            })
+    }
+
+    fn flush(&mut self) -> Result<(), ULogError> {
+        self.runtime
+            .block_on(self.client.flush())
+            .map_err(|e| ULogError::ExportError(format!("Cannot flush the NATS connection: {e}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::model::def::BaseType;
+    use crate::sink::{StreamSink, UlogSink};
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    /// The subject, headers and payload of a message.
+    type Message = (String, String, Vec<u8>);
+    type Server = thread::JoinHandle<io::Result<Vec<Message>>>;
+
+    /// A NATS server on a local port which accepts one client, and returns the subject, headers and payload of each
+    /// `HPUB` until the client disconnects.
+    fn serve() -> io::Result<(String, Server)> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("nats://{}", listener.local_addr()?);
+
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept()?;
+            stream.write_all(
+                b"INFO {\"server_id\":\"test\",\"version\":\"2.10.0\",\"proto\":1,\"headers\":true,\
+                  \"max_payload\":1048576}\r\n",
+            )?;
+            let mut reader = BufReader::new(stream.try_clone()?);
+            let mut messages = Vec::new();
+            let mut line = String::new();
+            loop {
+                line.clear();
+                if reader.read_line(&mut line)? == 0 {
+                    return Ok(messages);
+                }
+                let words: Vec<&str> = line.split_whitespace().collect();
+                match words[..] {
+                    ["PING"] => stream.write_all(b"PONG\r\n")?,
+                    ["HPUB", subject, header_len, total_len] => {
+                        let header_len: usize = header_len.parse().unwrap();
+                        let total_len: usize = total_len.parse().unwrap();
+                        let mut message = vec![0; total_len + 2];
+                        reader.read_exact(&mut message)?;
+                        let payload = message[header_len..total_len].to_vec();
+                        message.truncate(header_len);
+                        messages.push((
+                            subject.to_owned(),
+                            String::from_utf8(message).unwrap(),
+                            payload,
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+        });
+
+        Ok((url, server))
+    }
+
+    #[test]
+    fn test_publish() -> Result<(), ULogError> {
+        let (url, server) = serve()?;
+        let bytes = LogBuilder::new()
+            .topic(Topic::new("battery_status").multi_id(1).rate_hz(2.0).field(
+                "voltage_v",
+                BaseType::FLOAT,
+                Generator::Constant(16.5),
+            ))
+            .build()?;
+
+        let mut sink = StreamSink::new(NatsPublisher::connect(&url)?).subject_prefix("px4.");
+        sink.send_all(ULogParserBuilder::new(bytes.as_slice()).build()?)?;
+        drop(sink);
+
+        let messages = server.join().unwrap()?;
+        assert_eq!(messages.len(), 2);
+        let (subject, headers, payload) = &messages[0];
+        assert_eq!(subject, "px4.battery_status");
+        assert!(headers.starts_with("NATS/1.0\r\n"));
+        assert!(headers.contains("Content-Type: application/json\r\n"));
+        assert!(headers.contains("Ulog-Key: 1\r\n"));
+        let json: serde_json::Value = serde_json::from_slice(payload).unwrap();
+        assert_eq!(json["data"]["voltage_v"], 16.5);
+
+        Ok(())
+    }
+}
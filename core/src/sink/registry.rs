@@ -0,0 +1,226 @@
+//! Registers the Avro schemas of a `StreamSink` with a Confluent schema registry, over HTTP.
+//!
+//! `RegistryPublisher` wraps any other publisher, so that it can publish Avro payloads:
+//!
+//! ```rust
+//! use yule_log::sink::registry::{RegistryPublisher, SchemaRegistry};
+//! use yule_log::sink::{Encoding, Publisher, StreamSink};
+//!
+//! fn avro_sink<P: Publisher>(publisher: P) -> StreamSink<RegistryPublisher<P>> {
+//!     let registry = SchemaRegistry::new("http://localhost:8081").header("Authorization", "Basic ...");
+//!     StreamSink::new(RegistryPublisher::new(publisher, registry)).encoding(Encoding::Avro)
+//! }
+//! ```
+
+use std::fmt::Write;
+
+use ureq::Agent;
+
+use crate::errors::ULogError;
+use crate::sink::{Publication, Publisher};
+
+/// A client of the REST API of a Confluent schema registry.
+#[derive(Debug, Clone)]
+pub struct SchemaRegistry {
+    agent: Agent,
+    url: String,
+    headers: Vec<(String, String)>,
+}
+
+impl SchemaRegistry {
+    /// A client of the registry at `url`, e.g. `http://localhost:8081`.
+    pub fn new(url: impl Into<String>) -> Self {
+        let agent = Agent::config_builder()
+            .http_status_as_error(false)
+            .build()
+            .into();
+        SchemaRegistry {
+            agent,
+            url: url.into().trim_end_matches('/').to_owned(),
+            headers: Vec::new(),
+        }
+    }
+
+    /// Sends a header with each request, e.g. `Authorization` for the API key of a hosted registry.
+    #[must_use]
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Registers the Avro `schema` under `subject`, and returns its ID.  Registering a schema which the subject
+    /// already has returns the ID it was given then.
+    pub fn register(&self, subject: &str, schema: &str) -> Result<u32, ULogError> {
+        let url = format!(
+            "{}/subjects/{}/versions",
+            self.url,
+            encode_path_segment(subject)
+        );
+        let mut request = self
+            .agent
+            .post(&url)
+            .header("Content-Type", "application/vnd.schemaregistry.v1+json");
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+
+        let body = serde_json::json!({ "schema": schema }).to_string();
+        let mut response = request
+            .send(body)
+            .map_err(|e| registry_error(subject, &e))?;
+        let status = response.status();
+        let text = response
+            .body_mut()
+            .read_to_string()
+            .map_err(|e| registry_error(subject, &e))?;
+        let json: serde_json::Value = serde_json::from_str(&text).unwrap_or_default();
+
+        if !status.is_success() {
+            let message = json["message"].as_str().unwrap_or(&text);
+            return Err(ULogError::ExportError(format!(
+                "The schema registry rejected the schema of {subject}: status {status}: {message}"
+            )));
+        }
+        json["id"]
+            .as_u64()
+            .and_then(|id| u32::try_from(id).ok())
+            .ok_or_else(|| {
+                ULogError::ExportError(format!(
+                    "The schema registry returned no ID for the schema of {subject}: {text}"
+                ))
+            })
+    }
+}
+
+fn registry_error(subject: &str, e: &ureq::Error) -> ULogError {
+    ULogError::ExportError(format!("Cannot register the schema of {subject}: {e}"))
+}
+
+/// Percent-encodes the characters of `segment` which are not unreserved in URLs.
+fn encode_path_segment(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            let _ = write!(encoded, "%{byte:02X}");
+        }
+    }
+    encoded
+}
+
+/// A publisher which registers schemas with a `SchemaRegistry`, and publishes through another publisher.
+#[derive(Debug, Clone)]
+pub struct RegistryPublisher<P> {
+    publisher: P,
+    registry: SchemaRegistry,
+}
+
+impl<P: Publisher> RegistryPublisher<P> {
+    pub fn new(publisher: P, registry: SchemaRegistry) -> Self {
+        RegistryPublisher {
+            publisher,
+            registry,
+        }
+    }
+
+    pub fn into_inner(self) -> P {
+        self.publisher
+    }
+}
+
+impl<P: Publisher> Publisher for RegistryPublisher<P> {
+    fn publish(&mut self, publication: &Publication) -> Result<(), ULogError> {
+        self.publisher.publish(publication)
+    }
+
+    fn flush(&mut self) -> Result<(), ULogError> {
+        self.publisher.flush()
+    }
+
+    fn register_schema(&mut self, subject: &str, schema: &str) -> Result<u32, ULogError> {
+        self.registry.register(subject, schema)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    use super::*;
+
+    /// Returns the request line and body of the request it answered.
+    type Server = thread::JoinHandle<io::Result<(String, String)>>;
+
+    /// Answers one request on a local port with `status` and `body`.
+    fn serve(status: &'static str, body: &'static str) -> io::Result<(String, Server)> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/", listener.local_addr()?);
+
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept()?;
+            let mut reader = BufReader::new(stream.try_clone()?);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line)?;
+            let mut content_length = 0;
+            let mut line = String::new();
+            while reader.read_line(&mut line)? > 2 {
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length: ") {
+                    content_length = value.trim().parse().unwrap();
+                }
+                line.clear();
+            }
+            let mut request_body = vec![0; content_length];
+            reader.read_exact(&mut request_body)?;
+
+            let mut stream = stream;
+            write!(
+                stream,
+                "HTTP/1.1 {status}\r\nContent-Type: application/vnd.schemaregistry.v1+json\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )?;
+            Ok((request_line, String::from_utf8(request_body).unwrap()))
+        });
+
+        Ok((url, server))
+    }
+
+    #[test]
+    fn test_register() -> Result<(), ULogError> {
+        let (url, server) = serve("200 OK", r#"{"id":42}"#)?;
+        let schema = r#"{"type":"record","name":"battery_status","fields":[]}"#;
+        let id = SchemaRegistry::new(url).register("px4/battery_status-value", schema)?;
+        assert_eq!(id, 42);
+
+        let (request_line, body) = server.join().unwrap()?;
+        assert_eq!(
+            request_line,
+            "POST /subjects/px4%2Fbattery_status-value/versions HTTP/1.1\r\n"
+        );
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["schema"], schema);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejected() -> Result<(), ULogError> {
+        let (url, server) = serve(
+            "409 Conflict",
+            r#"{"error_code":409,"message":"Schema being registered is incompatible"}"#,
+        )?;
+        let result = SchemaRegistry::new(url).register("battery_status-value", "{}");
+        server.join().unwrap()?;
+
+        match result {
+            Err(ULogError::ExportError(message)) => {
+                assert!(message.contains("incompatible"), "{message}")
+            }
+            other => panic!("Unexpected result {other:?}"),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,393 @@
+//! Pausing a parser between messages and resuming it later, e.g. in another process, against the rest of the log.
+//!
+//! `ULogParser::snapshot()` captures what the parser has learned from the messages read so far: the file header, the
+//! formats, the current subscriptions, the offset of the appended data and, for `repair_timestamps()`, the last
+//! timestamp of each subscription, so that a resumed parser repairs timestamps as one which was never paused.  A `ParserState` can be saved with
+//! `write()` and loaded with `read()`, which suits chunked processing where each invocation may only run for a
+//! bounded time.  `ULogParserBuilder::resume()` then builds a parser which continues from the state, reading the log
+//! from the offset of the snapshot.
+//!
+//! The configuration of the parser, e.g. its subscription allow list, is not part of the state, so the builder which
+//! resumes should be configured as the first was.  The msg_ids of the allow list are recomputed from the
+//! subscriptions on resuming.  The validation report starts afresh.
+//!
+//! ```rust
+//! use yule_log::builder::ULogParserBuilder;
+//! use yule_log::state::ParserState;
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+//!
+//! // The first chunk: parse some messages, then save the state.
+//! let mut parser = ULogParserBuilder::new(input.as_slice()).build()?;
+//! for msg in parser.by_ref().take(1000) {
+//!     msg?;
+//! }
+//! let mut saved = Vec::new();
+//! parser.snapshot()?.write(&mut saved)?;
+//!
+//! // A later chunk: load the state, and parse the rest of the log from where the first stopped.
+//! let state = ParserState::read(&mut saved.as_slice())?;
+//! let rest = &input[state.offset() as usize..];
+//! for msg in ULogParserBuilder::new(rest).resume(state).build()? {
+//!     msg?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The saved state starts with the magic `ULogPst` and a version byte, followed by the phase, offset, file header
+//! and appended data offset, then the definitions of the formats, the current subscriptions and the timestamp repair
+//! state of each subscription.  All values are little endian.
+
+use std::io::{Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::encode::Encode;
+use crate::errors::ULogError;
+use crate::formats::parse_format_str;
+use crate::model::def;
+use crate::model::msg::{FileHeader, Subscription};
+use crate::parser::State;
+use crate::validation::TimestampClock;
+
+const STATE_MAGIC: &[u8; 7] = b"ULogPst";
+const STATE_VERSION: u8 = 2;
+
+/// The state of a parser between two messages.  See the module documentation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParserState {
+    pub(crate) state: State,
+    pub(crate) offset: u64,
+    pub(crate) file_header: FileHeader,
+    pub(crate) appended_data_offset: Option<u64>,
+    pub(crate) formats: Vec<def::Format>,
+    pub(crate) subscriptions: Vec<Subscription>,
+    // The topics subscribed with a multi_id other than 0, whose data messages carry their multi_id.
+    pub(crate) multi_id_topics: Vec<String>,
+    // The formats with fields left out by `recover_format_errors`.
+    pub(crate) damaged_formats: Vec<String>,
+    // The timestamps seen by `repair_timestamps`, by msg_id.
+    pub(crate) timestamp_clocks: Vec<(u16, TimestampClock)>,
+}
+
+impl ParserState {
+    /// The phase of the log the parser was in, `State::DEFINITIONS` or `State::DATA`.
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// The offset in the log of the next message, from which a resumed parser reads.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn file_header(&self) -> FileHeader {
+        self.file_header
+    }
+
+    /// The formats defined so far, by name.
+    pub fn formats(&self) -> &[def::Format] {
+        &self.formats
+    }
+
+    /// The current subscriptions, by msg_id.
+    pub fn subscriptions(&self) -> &[Subscription] {
+        &self.subscriptions
+    }
+
+    pub fn write(&self, writer: &mut impl Write) -> Result<(), ULogError> {
+        writer.write_all(STATE_MAGIC)?;
+        writer.write_u8(STATE_VERSION)?;
+        writer.write_u8(self.state as u8)?;
+        writer.write_u64::<LittleEndian>(self.offset)?;
+        writer.write_u8(self.file_header.version)?;
+        writer.write_u64::<LittleEndian>(self.file_header.timestamp)?;
+        match self.appended_data_offset {
+            Some(offset) => {
+                writer.write_u8(1)?;
+                writer.write_u64::<LittleEndian>(offset)?;
+            }
+            None => writer.write_u8(0)?,
+        }
+
+        writer.write_u32::<LittleEndian>(self.formats.len() as u32)?;
+        for format in &self.formats {
+            let mut definition = Vec::new();
+            format.encode(&mut definition)?;
+            write_bytes(writer, &definition)?;
+        }
+
+        writer.write_u32::<LittleEndian>(self.subscriptions.len() as u32)?;
+        for sub in &self.subscriptions {
+            writer.write_u16::<LittleEndian>(sub.msg_id)?;
+            writer.write_u8(sub.multi_id)?;
+            write_bytes(writer, sub.message_name.as_bytes())?;
+        }
+
+        for names in [&self.multi_id_topics, &self.damaged_formats] {
+            writer.write_u32::<LittleEndian>(names.len() as u32)?;
+            for name in names {
+                write_bytes(writer, name.as_bytes())?;
+            }
+        }
+
+        writer.write_u32::<LittleEndian>(self.timestamp_clocks.len() as u32)?;
+        for (msg_id, clock) in &self.timestamp_clocks {
+            writer.write_u16::<LittleEndian>(*msg_id)?;
+            writer.write_u8(u8::from(clock.last.is_some()))?;
+            writer.write_u64::<LittleEndian>(clock.last.unwrap_or(0))?;
+            writer.write_u64::<LittleEndian>(clock.last_original)?;
+            writer.write_u64::<LittleEndian>(clock.offset)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a state saved by `write()`.
+    ///
+    /// Returns `ULogError::InvalidParserState` if it isn't a saved state, or was saved by an unsupported version.
+    pub fn read(reader: &mut impl Read) -> Result<Self, ULogError> {
+        let mut magic = [0u8; 7];
+        reader.read_exact(&mut magic)?;
+        if &magic != STATE_MAGIC {
+            return Err(ULogError::InvalidParserState(
+                "Not a saved parser state.".to_owned(),
+            ));
+        }
+        let version = reader.read_u8()?;
+        if version != STATE_VERSION {
+            return Err(ULogError::InvalidParserState(format!(
+                "Unsupported parser state version {version}."
+            )));
+        }
+
+        let state = match reader.read_u8()? {
+            1 => State::DEFINITIONS,
+            2 => State::DATA,
+            state => {
+                return Err(ULogError::InvalidParserState(format!(
+                    "Can't resume in state {state}."
+                )))
+            }
+        };
+        let offset = reader.read_u64::<LittleEndian>()?;
+        let file_header = FileHeader {
+            version: reader.read_u8()?,
+            timestamp: reader.read_u64::<LittleEndian>()?,
+        };
+        let appended_data_offset = match reader.read_u8()? {
+            0 => None,
+            _ => Some(reader.read_u64::<LittleEndian>()?),
+        };
+
+        let mut formats = Vec::new();
+        for _ in 0..reader.read_u32::<LittleEndian>()? {
+            formats.push(parse_format_str(&read_string(reader)?)?);
+        }
+
+        let mut subscriptions = Vec::new();
+        for _ in 0..reader.read_u32::<LittleEndian>()? {
+            let msg_id = reader.read_u16::<LittleEndian>()?;
+            let multi_id = reader.read_u8()?;
+            subscriptions.push(Subscription {
+                multi_id,
+                msg_id,
+                message_name: read_string(reader)?,
+            });
+        }
+
+        let mut read_names = || -> Result<Vec<String>, ULogError> {
+            (0..reader.read_u32::<LittleEndian>()?)
+                .map(|_| read_string(reader))
+                .collect()
+        };
+        let multi_id_topics = read_names()?;
+        let damaged_formats = read_names()?;
+
+        let mut timestamp_clocks = Vec::new();
+        for _ in 0..reader.read_u32::<LittleEndian>()? {
+            let msg_id = reader.read_u16::<LittleEndian>()?;
+            let has_last = reader.read_u8()? != 0;
+            let last = reader.read_u64::<LittleEndian>()?;
+            timestamp_clocks.push((
+                msg_id,
+                TimestampClock {
+                    last: has_last.then_some(last),
+                    last_original: reader.read_u64::<LittleEndian>()?,
+                    offset: reader.read_u64::<LittleEndian>()?,
+                },
+            ));
+        }
+
+        Ok(ParserState {
+            state,
+            offset,
+            file_header,
+            appended_data_offset,
+            formats,
+            subscriptions,
+            multi_id_topics,
+            damaged_formats,
+            timestamp_clocks,
+        })
+    }
+}
+
+fn write_bytes(writer: &mut impl Write, bytes: &[u8]) -> Result<(), ULogError> {
+    // A message holds at most 64KB, so neither can a definition or name.
+    let len = u16::try_from(bytes.len()).map_err(|_| {
+        ULogError::InvalidParserState(format!(
+            "A definition of {} bytes is too long.",
+            bytes.len()
+        ))
+    })?;
+    writer.write_u16::<LittleEndian>(len)?;
+    writer.write_all(bytes)?;
+    Ok(())
+}
+
+fn read_string(reader: &mut impl Read) -> Result<String, ULogError> {
+    let mut bytes = vec![0u8; usize::from(reader.read_u16::<LittleEndian>()?)];
+    reader.read_exact(&mut bytes)?;
+    Ok(String::from_utf8(bytes)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::model::def::BaseType;
+    use crate::model::msg::UlogMessage;
+    use crate::parser::ULogParser;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+    use crate::validation::TimestampRepair;
+
+    fn debug_messages<R: Read>(parser: ULogParser<R>) -> Result<Vec<String>, ULogError> {
+        parser.map(|msg| Ok(format!("{:?}", msg?))).collect()
+    }
+
+    fn debug_messages_n<R: Read>(
+        parser: &mut ULogParser<R>,
+        n: usize,
+    ) -> Result<Vec<String>, ULogError> {
+        parser
+            .by_ref()
+            .take(n)
+            .map(|msg| Ok(format!("{:?}", msg?)))
+            .collect()
+    }
+
+    #[test]
+    fn test_snapshot_and_resume() -> Result<(), ULogError> {
+        let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+        let all = debug_messages(ULogParserBuilder::new(input.as_slice()).build()?)?;
+
+        // In the definitions section, in the data section, and at the end.
+        for (split, expected_state) in [
+            (5, State::DEFINITIONS),
+            (all.len() / 2, State::DATA),
+            (all.len(), State::EOF),
+        ] {
+            let mut parser = ULogParserBuilder::new(input.as_slice()).build()?;
+            let mut messages = debug_messages_n(&mut parser, split)?;
+            if expected_state == State::EOF {
+                assert!(parser.next().is_none());
+                assert_eq!(parser.state(), State::EOF);
+                assert!(matches!(
+                    parser.snapshot(),
+                    Err(ULogError::InvalidParserState(_))
+                ));
+                continue;
+            }
+            assert_eq!(parser.state(), expected_state);
+
+            let mut saved = Vec::new();
+            parser.snapshot()?.write(&mut saved)?;
+            let state = ParserState::read(&mut saved.as_slice())?;
+            assert_eq!(state, parser.snapshot()?);
+            assert_eq!(state.state(), expected_state);
+
+            let rest = &input[state.offset() as usize..];
+            messages.extend(debug_messages(
+                ULogParserBuilder::new(rest).resume(state).build()?,
+            )?);
+            assert_eq!(messages, all, "split at {split}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resume_repairs_timestamps() -> Result<(), ULogError> {
+        // A late sample for msg_id 0, logged after the sample at 1.4s.
+        let mut late = vec![14, 0, b'D', 0, 0];
+        late.extend_from_slice(&1_100_000u64.to_le_bytes());
+        late.extend_from_slice(&1u32.to_le_bytes());
+        let input = LogBuilder::new()
+            .topic(Topic::new("a").field("x", BaseType::UINT32, Generator::Constant(1.0)))
+            .garbage_at(Duration::from_millis(450), &late)
+            .build()?;
+
+        fn timestamps<R: Read>(
+            parser: &mut ULogParser<R>,
+            n: usize,
+        ) -> Result<Vec<u64>, ULogError> {
+            let mut timestamps = Vec::new();
+            for msg in parser.by_ref().take(n) {
+                if let UlogMessage::LoggedData(data) = msg? {
+                    timestamps.push(data.timestamp);
+                }
+            }
+            Ok(timestamps)
+        }
+
+        for repair in [
+            TimestampRepair::ClampMonotonic,
+            TimestampRepair::OffsetOnWrap,
+        ] {
+            let builder = |input| ULogParserBuilder::new(input).repair_timestamps(repair);
+            let all = timestamps(&mut builder(input.as_slice()).build()?, usize::MAX)?;
+
+            // Split after the late sample, whose repair must carry over to the rest of the log.
+            let mut parser = builder(input.as_slice()).build()?;
+            let mut split = timestamps(&mut parser, 12)?;
+            assert!(split.len() > 6);
+
+            let mut saved = Vec::new();
+            parser.snapshot()?.write(&mut saved)?;
+            let state = ParserState::read(&mut saved.as_slice())?;
+            let rest = &input[state.offset() as usize..];
+            split.extend(timestamps(
+                &mut builder(rest).resume(state).build()?,
+                usize::MAX,
+            )?);
+
+            assert_eq!(split, all, "{repair:?}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_state() -> Result<(), ULogError> {
+        let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+        let parser = ULogParserBuilder::new(input.as_slice()).build()?;
+        // The header hasn't been read.
+        assert!(matches!(
+            parser.snapshot(),
+            Err(ULogError::InvalidParserState(_))
+        ));
+
+        assert!(matches!(
+            ParserState::read(&mut &b"ULogIdx\x01"[..]),
+            Err(ULogError::InvalidParserState(_))
+        ));
+        assert!(matches!(
+            ParserState::read(&mut &b"ULogPst\x01"[..]),
+            Err(ULogError::InvalidParserState(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,651 @@
+//! Follows a ULOG file which is still being written, e.g. by a logger streaming to disk, or reads only the end of a
+//! finished one with `tail_window()`.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::builder::ULogParserBuilder;
+use crate::errors::ULogError;
+use crate::export::ndjson;
+use crate::model::msg::UlogMessage;
+use crate::model::SYNC_MAGIC;
+use crate::parser::ULogParser;
+use crate::state::ParserState;
+
+/// The number of bytes read at a time while looking backwards for a SYNC message.
+const SYNC_SCAN_BLOCK: u64 = 64 * 1024;
+
+/// A reader which waits for more data at the end of its input, like `tail -f`.
+///
+/// When the underlying reader reaches its end, `TailReader` polls it again every `poll_interval`.
+/// It only reports the end of the input once no new data arrived for `idle_timeout`, or once stopped
+/// through the handle returned by `stop_handle()`.  Without an idle timeout, it waits indefinitely.
+pub struct TailReader<R: Read> {
+    reader: R,
+    poll_interval: Duration,
+    idle_timeout: Option<Duration>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl<R: Read> TailReader<R> {
+    pub fn new(reader: R, poll_interval: Duration, idle_timeout: Option<Duration>) -> Self {
+        TailReader {
+            reader,
+            poll_interval,
+            idle_timeout,
+            stopped: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Returns a flag which, once set, makes the reader report the end of the input instead of waiting for more data.
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        self.stopped.clone()
+    }
+}
+
+impl<R: Read> Read for TailReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let idle_since = Instant::now();
+
+        loop {
+            let n = self.reader.read(buf)?;
+            if n > 0 {
+                return Ok(n);
+            }
+
+            let timed_out = self
+                .idle_timeout
+                .is_some_and(|timeout| idle_since.elapsed() >= timeout);
+
+            if timed_out || self.stopped.load(Ordering::Relaxed) {
+                return Ok(0);
+            }
+
+            thread::sleep(self.poll_interval);
+        }
+    }
+}
+
+/// Controls when `tail_ndjson()` flushes its writer.
+///
+/// Regardless of the policy, the writer is also flushed whenever all queued messages have been
+/// written, i.e. while waiting for the log to grow, so that consumers never lag behind the log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushPolicy {
+    EveryLine,
+    EveryLines(usize),
+    Interval(Duration),
+}
+
+/// Options for `tail_ndjson()`.
+#[derive(Debug, Clone)]
+pub struct TailOptions {
+    poll_interval: Duration,
+    idle_timeout: Option<Duration>,
+    queue_capacity: usize,
+    flush_policy: FlushPolicy,
+}
+
+impl Default for TailOptions {
+    fn default() -> Self {
+        TailOptions {
+            poll_interval: Duration::from_millis(100),
+            idle_timeout: None,
+            queue_capacity: 1024,
+            flush_policy: FlushPolicy::Interval(Duration::from_millis(250)),
+        }
+    }
+}
+
+impl TailOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How often to check whether the log has grown, once all available data has been read.
+    #[must_use]
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Stop once the log has not grown for `idle_timeout`.  By default, the log is followed indefinitely.
+    #[must_use]
+    pub fn idle_timeout(mut self, idle_timeout: Option<Duration>) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// The number of messages which may be queued while the writer is busy.
+    ///
+    /// Once the queue is full, parsing pauses until the writer catches up, so a slow consumer
+    /// limits memory use instead of causing unbounded buffering.
+    #[must_use]
+    pub fn queue_capacity(mut self, queue_capacity: usize) -> Self {
+        self.queue_capacity = queue_capacity;
+        self
+    }
+
+    #[must_use]
+    pub fn flush_policy(mut self, flush_policy: FlushPolicy) -> Self {
+        self.flush_policy = flush_policy;
+        self
+    }
+}
+
+/// Follows the growing log at `path`, writing each message to `writer` as a JSON line as soon as it appears.
+///
+/// The log is parsed on a background thread, which hands lines to the calling thread through a
+/// bounded queue.  Returns the number of lines written once the log stops growing (see `TailOptions::idle_timeout()`),
+/// or the first parse or write error.  On a write error, the background thread stops as well.
+/// See `export::ndjson` for the line format.
+pub fn tail_ndjson<W: Write>(
+    path: impl AsRef<Path>,
+    writer: &mut W,
+    options: &TailOptions,
+) -> Result<u64, ULogError> {
+    if options.queue_capacity == 0 {
+        return Err(ULogError::InvalidConfiguration(
+            "The queue capacity must be greater than zero.".to_owned(),
+        ));
+    }
+
+    let reader = TailReader::new(
+        File::open(path)?,
+        options.poll_interval,
+        options.idle_timeout,
+    );
+    let stopped = reader.stop_handle();
+
+    let (sender, receiver) =
+        mpsc::sync_channel::<Result<String, ULogError>>(options.queue_capacity);
+
+    let producer = thread::spawn(move || {
+        let parser = match ULogParser::new(BufReader::new(reader)) {
+            Ok(parser) => parser,
+            Err(e) => {
+                let _ = sender.send(Err(e));
+                return;
+            }
+        };
+
+        for message in parser {
+            let line = match message {
+                Ok(message) => match ndjson::to_json(&message) {
+                    Some(line) => Ok(line),
+                    None => continue,
+                },
+                Err(e) => Err(e),
+            };
+
+            let is_err = line.is_err();
+
+            // The receiver is gone if writing failed.
+            if sender.send(line).is_err() || is_err {
+                return;
+            }
+        }
+    });
+
+    let result = write_lines(&receiver, writer, options.flush_policy);
+
+    // Unblock the producer, whether it is waiting for the log to grow or for space in the queue.
+    stopped.store(true, Ordering::Relaxed);
+    drop(receiver);
+
+    if producer.join().is_err() {
+        return Err(ULogError::InternalError(
+            "The tail thread panicked.".to_owned(),
+        ));
+    }
+
+    result
+}
+
+fn write_lines<W: Write>(
+    receiver: &mpsc::Receiver<Result<String, ULogError>>,
+    writer: &mut W,
+    flush_policy: FlushPolicy,
+) -> Result<u64, ULogError> {
+    let mut lines = 0u64;
+    let mut unflushed = 0usize;
+    let mut last_flush = Instant::now();
+
+    loop {
+        let line = match receiver.try_recv() {
+            Ok(line) => line,
+            Err(TryRecvError::Empty) => {
+                if unflushed > 0 {
+                    writer.flush()?;
+                    unflushed = 0;
+                    last_flush = Instant::now();
+                }
+
+                match receiver.recv() {
+                    Ok(line) => line,
+                    Err(_) => break,
+                }
+            }
+            Err(TryRecvError::Disconnected) => break,
+        };
+
+        writer.write_all(line?.as_bytes())?;
+        writer.write_all(b"\n")?;
+        lines += 1;
+        unflushed += 1;
+
+        let flush = match flush_policy {
+            FlushPolicy::EveryLine => true,
+            FlushPolicy::EveryLines(n) => unflushed >= n,
+            FlushPolicy::Interval(interval) => last_flush.elapsed() >= interval,
+        };
+
+        if flush {
+            writer.flush()?;
+            unflushed = 0;
+            last_flush = Instant::now();
+        }
+    }
+
+    writer.flush()?;
+    Ok(lines)
+}
+
+/// The messages of the last part of a log, returned by `tail_window()`.
+pub struct TailWindow<R: Read> {
+    parser: ULogParser<R>,
+    start: u64,
+    end: u64,
+}
+
+impl<R: Read> TailWindow<R> {
+    /// The timestamp from which the data and logged strings are returned.
+    pub fn start_timestamp(&self) -> u64 {
+        self.start
+    }
+
+    /// The last timestamp of the log.
+    pub fn end_timestamp(&self) -> u64 {
+        self.end
+    }
+
+    pub fn parser(&self) -> &ULogParser<R> {
+        &self.parser
+    }
+
+    pub fn into_parser(self) -> ULogParser<R> {
+        self.parser
+    }
+}
+
+impl<R: Read> Iterator for TailWindow<R> {
+    type Item = Result<UlogMessage, ULogError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let msg = self.parser.next()?;
+            match &msg {
+                Ok(msg) if timestamp(msg).is_some_and(|t| t < self.start) => continue,
+                _ => return Some(msg),
+            }
+        }
+    }
+}
+
+/// Parses only the last `window` of a log, e.g. for a quick check after a flight, without reading the data before it.
+/// See `tail_window_with()`.
+pub fn tail_window<R: Read + Seek>(
+    reader: R,
+    window: Duration,
+) -> Result<TailWindow<R>, ULogError> {
+    tail_window_with(reader, window, |builder| builder)
+}
+
+/// Like `tail_window()`, but configures the parser of the window with `configure`, e.g. to set a subscription allow
+/// list.
+///
+/// The definitions and the subscriptions are read from the log, seeking over the data messages rather than decoding
+/// them.  The parser then resumes at the last SYNC message logged before the window, searching backwards from the end
+/// of the file, with the subscriptions added or removed before it, and data and logged strings older than the window
+/// are dropped.
+///
+/// PX4 writes SYNC messages regularly, and the end of the log is found by parsing from the last one.  A log without
+/// them gains nothing over parsing it whole, and costs more:  its whole data section is decoded once to find the
+/// timestamp it ends at, and again by the parser of the window.
+pub fn tail_window_with<R, F>(
+    mut reader: R,
+    window: Duration,
+    configure: F,
+) -> Result<TailWindow<R>, ULogError>
+where
+    R: Read + Seek,
+    F: FnOnce(ULogParserBuilder<R>) -> ULogParserBuilder<R>,
+{
+    let heads = read_heads(&mut reader)?;
+    let data_offset = heads[0].offset;
+    let file_size = reader.seek(SeekFrom::End(0))?;
+
+    let last_sync = last_sync_before(&mut reader, data_offset, file_size, file_size)?;
+    let end = probe(&mut reader, &heads, last_sync.unwrap_or(data_offset), false)?.unwrap_or(0);
+    let start = end.saturating_sub(u64::try_from(window.as_micros()).unwrap_or(u64::MAX));
+
+    // Walk back from the last SYNC message to one logged before the window.
+    let mut offset = data_offset;
+    let mut before = last_sync.map(|sync| sync + 1);
+    while let Some(to) = before {
+        let Some(sync) = last_sync_before(&mut reader, data_offset, to, file_size)? else {
+            break;
+        };
+        // A SYNC whose first message fails to parse may be a match inside another message.
+        if probe(&mut reader, &heads, sync, true)
+            .ok()
+            .flatten()
+            .is_some_and(|first| first <= start)
+        {
+            offset = sync;
+            break;
+        }
+        before = Some(sync);
+    }
+
+    reader.seek(SeekFrom::Start(offset))?;
+    let state = state_at(&heads, offset);
+    let parser = configure(ULogParserBuilder::new(reader))
+        .resume(state)
+        .build()?;
+    Ok(TailWindow { parser, start, end })
+}
+
+// Reads the definitions and the subscriptions of the log, and returns the state after each run of ADD_SUBSCRIPTION and
+// REMOVE_SUBSCRIPTION messages, in the order of their offsets.  The first is the state at the start of the data section.
+fn read_heads<R: Read + Seek>(reader: &mut R) -> Result<Vec<ParserState>, ULogError> {
+    reader.seek(SeekFrom::Start(0))?;
+    let mut parser = ULogParserBuilder::new(&mut *reader)
+        .set_subscription_allow_list(Vec::<String>::new())
+        .emit_ignored(true)
+        .emit_unhandled(true)
+        .skip_by_seeking()
+        .build()?;
+
+    let mut heads: Vec<ParserState> = Vec::new();
+    let mut in_run = false;
+    while let Some(msg) = parser.next().transpose()? {
+        let changes_subscriptions = matches!(msg, UlogMessage::AddSubscription(_))
+            || msg.removed_subscription().is_some();
+        if changes_subscriptions {
+            let state = parser.snapshot()?;
+            match heads.last_mut() {
+                Some(head) if in_run => *head = state,
+                _ => heads.push(state),
+            }
+        }
+        in_run = changes_subscriptions;
+    }
+    if heads.is_empty() {
+        return Err(ULogError::ParseError("The log has no subscriptions.".to_owned()));
+    }
+    Ok(heads)
+}
+
+// The state of `heads` in effect at `offset`, to resume parsing there.
+fn state_at(heads: &[ParserState], offset: u64) -> ParserState {
+    let i = heads.partition_point(|head| head.offset <= offset).max(1);
+    ParserState {
+        offset,
+        ..heads[i - 1].clone()
+    }
+}
+
+// Parses from the SYNC message at `offset` with the state of `heads` there, and returns the timestamp of the first message which
+// has one if `first`, or else the latest timestamp up to the end of the log.
+fn probe<R: Read + Seek>(
+    reader: &mut R,
+    heads: &[ParserState],
+    offset: u64,
+    first: bool,
+) -> Result<Option<u64>, ULogError> {
+    reader.seek(SeekFrom::Start(offset))?;
+    let state = state_at(heads, offset);
+    let parser = ULogParserBuilder::new(&mut *reader).resume(state).build()?;
+
+    let mut latest = None;
+    for msg in parser {
+        let Some(timestamp) = timestamp(&msg?) else {
+            continue;
+        };
+        if first {
+            return Ok(Some(timestamp));
+        }
+        latest = latest.max(Some(timestamp));
+    }
+    Ok(latest)
+}
+
+// The offset of the last SYNC message which starts in `from..to`, if any.
+fn last_sync_before<R: Read + Seek>(
+    reader: &mut R,
+    from: u64,
+    to: u64,
+    file_size: u64,
+) -> Result<Option<u64>, ULogError> {
+    let mut pattern = vec![SYNC_MAGIC.len() as u8, 0, b'S'];
+    pattern.extend_from_slice(&SYNC_MAGIC);
+
+    let mut end = to;
+    let mut block = Vec::new();
+    while end > from {
+        let start = end.saturating_sub(SYNC_SCAN_BLOCK).max(from);
+        // Past `end` by the length of the pattern, for a pattern which starts in this block.
+        let read_end = (end + pattern.len() as u64 - 1).min(file_size);
+        block.resize((read_end - start) as usize, 0);
+        reader.seek(SeekFrom::Start(start))?;
+        reader.read_exact(&mut block)?;
+
+        if let Some(i) = block
+            .windows(pattern.len())
+            .take((end - start) as usize)
+            .rposition(|window| window == pattern.as_slice())
+        {
+            return Ok(Some(start + i as u64));
+        }
+        end = start;
+    }
+    Ok(None)
+}
+
+// The timestamp of a data message or logged string.  That of the header is when logging started, not of the data.
+fn timestamp(msg: &UlogMessage) -> Option<u64> {
+    match msg {
+        UlogMessage::Header(_) => None,
+        msg => msg.timestamp(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::model::def::BaseType;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    #[test]
+    fn test_tail_reader() -> Result<(), ULogError> {
+        let mut reader = TailReader::new(
+            &b"abc"[..],
+            Duration::from_millis(1),
+            Some(Duration::from_millis(20)),
+        );
+
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        assert_eq!(bytes, b"abc");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tail_ndjson() -> Result<(), ULogError> {
+        let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+        let expected = ndjson::export(ULogParser::new(input.as_slice())?, &mut io::sink())?;
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("growing.ulg");
+        let (first, rest) = input.split_at(input.len() / 2);
+        std::fs::write(&path, first)?;
+
+        let appender = {
+            let path = path.clone();
+            let rest = rest.to_vec();
+            thread::spawn(move || -> io::Result<()> {
+                thread::sleep(Duration::from_millis(100));
+                let mut file = OpenOptions::new().append(true).open(path)?;
+                for chunk in rest.chunks(1000) {
+                    file.write_all(chunk)?;
+                    file.flush()?;
+                    thread::sleep(Duration::from_millis(2));
+                }
+                Ok(())
+            })
+        };
+
+        let options = TailOptions::new()
+            .poll_interval(Duration::from_millis(5))
+            .idle_timeout(Some(Duration::from_millis(500)))
+            .queue_capacity(4)
+            .flush_policy(FlushPolicy::EveryLines(10));
+
+        let mut output = Vec::new();
+        let lines = tail_ndjson(&path, &mut output, &options)?;
+        appender.join().unwrap()?;
+
+        assert_eq!(lines, expected);
+        assert_eq!(String::from_utf8(output)?.lines().count() as u64, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_error() {
+        struct FailingWriter;
+
+        impl Write for FailingWriter {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::other("closed"))
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        // Without an idle timeout, the background thread must still stop after the write error.
+        let result = tail_ndjson(
+            "test_data/input/sample_log_small.ulg",
+            &mut FailingWriter,
+            &TailOptions::new(),
+        );
+
+        assert!(matches!(result, Err(ULogError::Io(_))));
+    }
+
+    fn data_timestamps<I>(messages: I) -> Result<Vec<u64>, ULogError>
+    where
+        I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+    {
+        let mut timestamps = Vec::new();
+        for msg in messages {
+            if let UlogMessage::LoggedData(data) = msg? {
+                timestamps.push(data.timestamp);
+            }
+        }
+        Ok(timestamps)
+    }
+
+    #[test]
+    fn test_tail_window_late_subscriptions() -> Result<(), ULogError> {
+        // Two of the subscriptions of this log are added after its data starts.
+        let bytes = std::fs::read("test_data/input/sample_log_small.ulg")?;
+        let mut all = Vec::new();
+        for msg in ULogParser::new(bytes.as_slice())? {
+            if let UlogMessage::LoggedData(data) = msg? {
+                all.push((data.msg_id, data.timestamp));
+            }
+        }
+        let late = all.last().unwrap().0;
+
+        let window = tail_window(Cursor::new(&bytes), Duration::from_secs(1))?;
+        let start = window.start_timestamp();
+        let mut tail = Vec::new();
+        for msg in window {
+            if let UlogMessage::LoggedData(data) = msg? {
+                tail.push((data.msg_id, data.timestamp));
+            }
+        }
+
+        // Messages logged before the window are dropped even if their timestamp is in it, as one of this log is.
+        let expected: Vec<_> = all.into_iter().filter(|&(_, t)| t >= start).collect();
+        assert_eq!(tail, expected[expected.len() - tail.len()..]);
+        assert!(expected.len() - tail.len() <= 1);
+        assert!(tail.iter().any(|&(msg_id, _)| msg_id == late));
+        Ok(())
+    }
+
+    #[test]
+    fn test_tail_window() -> Result<(), ULogError> {
+        let mut sync = vec![SYNC_MAGIC.len() as u8, 0, b'S'];
+        sync.extend_from_slice(&SYNC_MAGIC);
+        let log = |with_syncs: bool| {
+            let mut builder = LogBuilder::new()
+                .start_timestamp(0)
+                .duration(Duration::from_secs(20))
+                .topic(Topic::new("a").rate_hz(10.0).field(
+                    "x",
+                    BaseType::UINT16,
+                    Generator::Ramp {
+                        start: 0.0,
+                        step: 1.0,
+                    },
+                ));
+            if with_syncs {
+                for second in 1..20 {
+                    builder = builder.garbage_at(Duration::from_millis(second * 1000 + 50), &sync);
+                }
+            }
+            builder.build()
+        };
+
+        for with_syncs in [true, false] {
+            let bytes = log(with_syncs)?;
+            let all = data_timestamps(ULogParser::new(bytes.as_slice())?)?;
+
+            let window = tail_window(Cursor::new(&bytes), Duration::from_secs(5))?;
+            assert_eq!(window.end_timestamp(), *all.last().unwrap());
+            let start = window.start_timestamp();
+            assert_eq!(start, window.end_timestamp() - 5_000_000);
+
+            let offset = window.parser().snapshot()?.offset();
+            let tail = data_timestamps(window)?;
+            let expected: Vec<u64> = all.iter().copied().filter(|&t| t >= start).collect();
+            assert_eq!(tail, expected);
+            // With SYNC messages, parsing resumes shortly before the window.
+            assert_eq!(offset > bytes.len() as u64 / 2, with_syncs);
+        }
+
+        let bytes = log(true)?;
+        let window = tail_window(Cursor::new(&bytes), Duration::from_secs(60))?;
+        assert_eq!(data_timestamps(window)?.len(), 200);
+        Ok(())
+    }
+}
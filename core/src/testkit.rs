@@ -0,0 +1,1005 @@
+//! Builds synthetic ULOG files, so tests can create fixtures without shipping binary logs.
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use yule_log::model::def::BaseType;
+//! use yule_log::parser::ULogParser;
+//! use yule_log::testkit::{Generator, LogBuilder, Topic};
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let bytes = LogBuilder::new()
+//!     .duration(Duration::from_secs(1))
+//!     .info("sys_name", "testkit")
+//!     .topic(
+//!         Topic::new("sensor_baro")
+//!             .rate_hz(50.0)
+//!             .field("pressure", BaseType::FLOAT, Generator::Ramp { start: 1013.0, step: 0.1 }),
+//!     )
+//!     .dropout_at(Duration::from_millis(500), 20)
+//!     .build()?;
+//!
+//! let messages = ULogParser::new(bytes.as_slice())?.collect::<Result<Vec<_>, _>>()?;
+//! # assert!(!messages.is_empty());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! All messages are written in timestamp order.  The generated files only use primitive field types.
+//!
+//! `FaultyReader` injects faults into any reader, e.g. to check that a corrupted log produces an error
+//! instead of a panic.
+//!
+//! `snapshot()` hashes the output of an exporter, to pin the conversion of a log in a test.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+use crate::errors::ULogError;
+use crate::export::identifiers::Dialect;
+use crate::export::ndjson::NdjsonExporter;
+use crate::export::sql::SqlExporter;
+use crate::export::{column_names, for_each_scalar, FloatFormat, InvalidFloatPolicy, TopicIndex};
+use crate::encode::Encode;
+use crate::model::def::{self, BaseType, FormatBuilder, TypeExpr};
+use crate::model::msg::{LogLevel, UlogMessage};
+use crate::model::MAGIC;
+
+/// The position of a value in the generated log, passed to `Generator::Fn`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    /// The index of the message within its topic instance.
+    pub index: u64,
+    /// The timestamp of the message in microseconds.
+    pub timestamp: u64,
+    /// The index of the value within an array field, or 0.
+    pub element: usize,
+}
+
+/// Produces the values of a field.  Values are converted to the type of the field with `as` casts,
+/// except for `bool` fields, which are `true` for any non-zero value.
+pub enum Generator {
+    Constant(f64),
+    /// `start + step * index`.
+    Ramp {
+        start: f64,
+        step: f64,
+    },
+    /// `amplitude * sin(2π * t / period)`, where `t` is the time since the start of the log.
+    Sine {
+        amplitude: f64,
+        period: Duration,
+    },
+    Fn(Box<dyn Fn(&Sample) -> f64>),
+}
+
+impl Generator {
+    pub fn from_fn(f: impl Fn(&Sample) -> f64 + 'static) -> Self {
+        Generator::Fn(Box::new(f))
+    }
+
+    fn value(&self, sample: &Sample, start_timestamp: u64) -> f64 {
+        match self {
+            Generator::Constant(v) => *v,
+            Generator::Ramp { start, step } => start + step * sample.index as f64,
+            Generator::Sine { amplitude, period } => {
+                let t = (sample.timestamp - start_timestamp) as f64 / 1e6;
+                amplitude * (std::f64::consts::TAU * t / period.as_secs_f64()).sin()
+            }
+            Generator::Fn(f) => f(sample),
+        }
+    }
+}
+
+struct FieldSpec {
+    name: String,
+    base_type: BaseType,
+    array_size: Option<usize>,
+    generator: Generator,
+}
+
+/// A topic instance, logged at a fixed rate.  A `uint64_t timestamp` field is added automatically.
+pub struct Topic {
+    name: String,
+    multi_id: u8,
+    rate_hz: f64,
+    fields: Vec<FieldSpec>,
+}
+
+impl Topic {
+    /// Creates the topic instance with multi ID 0, logged at 10 Hz.
+    pub fn new(name: &str) -> Self {
+        Topic {
+            name: name.to_owned(),
+            multi_id: 0,
+            rate_hz: 10.0,
+            fields: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn multi_id(mut self, multi_id: u8) -> Self {
+        self.multi_id = multi_id;
+        self
+    }
+
+    #[must_use]
+    pub fn rate_hz(mut self, rate_hz: f64) -> Self {
+        self.rate_hz = rate_hz;
+        self
+    }
+
+    #[must_use]
+    pub fn field(mut self, name: &str, base_type: BaseType, generator: Generator) -> Self {
+        self.fields.push(FieldSpec {
+            name: name.to_owned(),
+            base_type,
+            array_size: None,
+            generator,
+        });
+        self
+    }
+
+    #[must_use]
+    pub fn array_field(
+        mut self,
+        name: &str,
+        base_type: BaseType,
+        size: usize,
+        generator: Generator,
+    ) -> Self {
+        self.fields.push(FieldSpec {
+            name: name.to_owned(),
+            base_type,
+            array_size: Some(size),
+            generator,
+        });
+        self
+    }
+
+    fn format(&self) -> Result<def::Format, ULogError> {
+        let mut format = FormatBuilder::new(&self.name).field("timestamp", BaseType::UINT64);
+        for field in &self.fields {
+            format = format.typed_field(
+                &field.name,
+                TypeExpr {
+                    base_type: field.base_type.clone(),
+                    array_size: field.array_size,
+                },
+            );
+        }
+        format.build()
+    }
+
+    fn validate(&self) -> Result<(), ULogError> {
+        if !self.rate_hz.is_finite() || self.rate_hz <= 0.0 {
+            return Err(ULogError::InvalidConfiguration(format!(
+                "The rate of topic {} must be finite and greater than zero.",
+                self.name
+            )));
+        }
+
+        for field in &self.fields {
+            if field.base_type.primitive_size().is_none() {
+                return Err(ULogError::InvalidConfiguration(format!(
+                    "Field {} of topic {} has nested type {}, only primitive types are supported.",
+                    field.name, self.name, field.base_type
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn encode_sample(&self, sample: u64, timestamp: u64, start_timestamp: u64) -> Vec<u8> {
+        let mut payload = timestamp.to_le_bytes().to_vec();
+
+        for field in &self.fields {
+            for element in 0..field.array_size.unwrap_or(1) {
+                let sample = Sample {
+                    index: sample,
+                    timestamp,
+                    element,
+                };
+                let value = field.generator.value(&sample, start_timestamp);
+                encode_value(&mut payload, &field.base_type, value);
+            }
+        }
+
+        payload
+    }
+}
+
+fn encode_value(buf: &mut Vec<u8>, base_type: &BaseType, value: f64) {
+    match base_type {
+        BaseType::UINT8 | BaseType::CHAR => buf.push(value as u8),
+        BaseType::BOOL => buf.push(u8::from(value != 0.0)),
+        BaseType::UINT16 => buf.extend_from_slice(&(value as u16).to_le_bytes()),
+        BaseType::UINT32 => buf.extend_from_slice(&(value as u32).to_le_bytes()),
+        BaseType::UINT64 => buf.extend_from_slice(&(value as u64).to_le_bytes()),
+        BaseType::INT8 => buf.extend_from_slice(&(value as i8).to_le_bytes()),
+        BaseType::INT16 => buf.extend_from_slice(&(value as i16).to_le_bytes()),
+        BaseType::INT32 => buf.extend_from_slice(&(value as i32).to_le_bytes()),
+        BaseType::INT64 => buf.extend_from_slice(&(value as i64).to_le_bytes()),
+        BaseType::FLOAT => buf.extend_from_slice(&(value as f32).to_le_bytes()),
+        BaseType::DOUBLE => buf.extend_from_slice(&value.to_le_bytes()),
+        BaseType::OTHER(_) => unreachable!("Rejected by Topic::validate()"),
+    }
+}
+
+enum Event {
+    Dropout(u16),
+    Logging(LogLevel, String),
+    Garbage(Vec<u8>),
+}
+
+/// Builds a synthetic ULOG file.  See the module documentation for an example.
+pub struct LogBuilder {
+    start_timestamp: u64,
+    duration: Duration,
+    info: Vec<(String, Vec<u8>)>,
+    parameters: Vec<(String, Vec<u8>)>,
+    topics: Vec<Topic>,
+    events: Vec<(Duration, Event)>,
+}
+
+impl Default for LogBuilder {
+    fn default() -> Self {
+        LogBuilder {
+            start_timestamp: 1_000_000,
+            duration: Duration::from_secs(1),
+            info: Vec::new(),
+            parameters: Vec::new(),
+            topics: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+}
+
+impl LogBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The timestamp of the header and the first data messages, in microseconds.  Defaults to one second.
+    #[must_use]
+    pub fn start_timestamp(mut self, start_timestamp: u64) -> Self {
+        self.start_timestamp = start_timestamp;
+        self
+    }
+
+    /// How long topics are logged for.  Defaults to one second.
+    #[must_use]
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// Adds a string info message.
+    #[must_use]
+    pub fn info(mut self, key: &str, value: &str) -> Self {
+        self.info.push((
+            format!("char[{}] {key}", value.len()),
+            value.as_bytes().to_vec(),
+        ));
+        self
+    }
+
+    #[must_use]
+    pub fn parameter_i32(mut self, name: &str, value: i32) -> Self {
+        self.parameters
+            .push((format!("int32_t {name}"), value.to_le_bytes().to_vec()));
+        self
+    }
+
+    #[must_use]
+    pub fn parameter_f32(mut self, name: &str, value: f32) -> Self {
+        self.parameters
+            .push((format!("float {name}"), value.to_le_bytes().to_vec()));
+        self
+    }
+
+    #[must_use]
+    pub fn topic(mut self, topic: Topic) -> Self {
+        self.topics.push(topic);
+        self
+    }
+
+    /// Inserts a dropout of `duration_ms` at `offset` from the start of the log.
+    ///
+    /// Only the dropout message is written, data messages continue at their regular rate.
+    #[must_use]
+    pub fn dropout_at(mut self, offset: Duration, duration_ms: u16) -> Self {
+        self.events.push((offset, Event::Dropout(duration_ms)));
+        self
+    }
+
+    /// Inserts a logged string at `offset` from the start of the log.
+    #[must_use]
+    pub fn log_at(mut self, offset: Duration, level: LogLevel, message: &str) -> Self {
+        self.events
+            .push((offset, Event::Logging(level, message.to_owned())));
+        self
+    }
+
+    /// Inserts raw bytes between the messages at `offset` from the start of the log, to simulate corruption.
+    #[must_use]
+    pub fn garbage_at(mut self, offset: Duration, bytes: &[u8]) -> Self {
+        self.events.push((offset, Event::Garbage(bytes.to_vec())));
+        self
+    }
+
+    /// Returns the bytes of the ULOG file.
+    pub fn build(&self) -> Result<Vec<u8>, ULogError> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(1);
+        bytes.extend_from_slice(&self.start_timestamp.to_le_bytes());
+
+        // Flag bits: no compat or incompat flags, no appended data.
+        write_message(&mut bytes, b'B', &[0u8; 40])?;
+
+        let mut formats: Vec<(&str, def::Format)> = Vec::new();
+        for topic in &self.topics {
+            topic.validate()?;
+
+            let format = topic.format()?;
+            match formats.iter().find(|(name, _)| *name == topic.name) {
+                Some((_, existing)) if *existing != format => {
+                    return Err(ULogError::InvalidConfiguration(format!(
+                        "Instances of topic {} have different fields.",
+                        topic.name
+                    )))
+                }
+                Some(_) => {}
+                None => {
+                    let mut definition = Vec::new();
+                    format.encode(&mut definition)?;
+                    write_message(&mut bytes, b'F', &definition)?;
+                    formats.push((&topic.name, format));
+                }
+            }
+        }
+
+        for (key, value) in &self.info {
+            write_key_value(&mut bytes, b'I', key, value)?;
+        }
+        for (key, value) in &self.parameters {
+            write_key_value(&mut bytes, b'P', key, value)?;
+        }
+
+        for (msg_id, topic) in self.topics.iter().enumerate() {
+            let mut payload = vec![topic.multi_id];
+            payload.extend_from_slice(&msg_id_for(msg_id)?.to_le_bytes());
+            payload.extend_from_slice(topic.name.as_bytes());
+            write_message(&mut bytes, b'A', &payload)?;
+        }
+
+        // Events sort before data messages with the same timestamp.
+        let mut entries: Vec<(u64, Option<usize>, Option<&Event>)> = self
+            .events
+            .iter()
+            .map(|(offset, event)| (self.offset_timestamp(*offset), None, Some(event)))
+            .collect();
+
+        let end = self.offset_timestamp(self.duration);
+        for (msg_id, topic) in self.topics.iter().enumerate() {
+            let interval = 1e6 / topic.rate_hz;
+            for sample in 0.. {
+                let timestamp = self.start_timestamp + (sample as f64 * interval) as u64;
+                if timestamp >= end {
+                    break;
+                }
+                entries.push((timestamp, Some(msg_id), None));
+            }
+        }
+
+        entries.sort_by_key(|(timestamp, msg_id, _)| (*timestamp, *msg_id));
+
+        let mut samples = vec![0u64; self.topics.len()];
+        for (timestamp, msg_id, event) in entries {
+            match (msg_id, event) {
+                (Some(msg_id), _) => {
+                    let topic = &self.topics[msg_id];
+                    let mut payload = msg_id_for(msg_id)?.to_le_bytes().to_vec();
+                    payload.extend(topic.encode_sample(
+                        samples[msg_id],
+                        timestamp,
+                        self.start_timestamp,
+                    ));
+                    samples[msg_id] += 1;
+                    write_message(&mut bytes, b'D', &payload)?;
+                }
+                (None, Some(Event::Dropout(duration))) => {
+                    write_message(&mut bytes, b'O', &duration.to_le_bytes())?;
+                }
+                (None, Some(Event::Logging(level, message))) => {
+                    let mut payload = vec![*level as u8];
+                    payload.extend_from_slice(&timestamp.to_le_bytes());
+                    payload.extend_from_slice(message.as_bytes());
+                    write_message(&mut bytes, b'L', &payload)?;
+                }
+                (None, Some(Event::Garbage(garbage))) => bytes.extend_from_slice(garbage),
+                (None, None) => unreachable!("Every entry is either data or an event"),
+            }
+        }
+
+        Ok(bytes)
+    }
+
+    fn offset_timestamp(&self, offset: Duration) -> u64 {
+        self.start_timestamp
+            .saturating_add(u64::try_from(offset.as_micros()).unwrap_or(u64::MAX))
+    }
+}
+
+fn msg_id_for(index: usize) -> Result<u16, ULogError> {
+    u16::try_from(index)
+        .map_err(|_| ULogError::InvalidConfiguration("Too many topic instances.".to_owned()))
+}
+
+fn write_message(bytes: &mut Vec<u8>, msg_type: u8, payload: &[u8]) -> Result<(), ULogError> {
+    let size = u16::try_from(payload.len()).map_err(|_| {
+        ULogError::InvalidConfiguration(format!(
+            "Message of type '{}' is too large: {} bytes.",
+            msg_type as char,
+            payload.len()
+        ))
+    })?;
+
+    bytes.extend_from_slice(&size.to_le_bytes());
+    bytes.push(msg_type);
+    bytes.extend_from_slice(payload);
+    Ok(())
+}
+
+fn write_key_value(
+    bytes: &mut Vec<u8>,
+    msg_type: u8,
+    key: &str,
+    value: &[u8],
+) -> Result<(), ULogError> {
+    let key_len = u8::try_from(key.len())
+        .map_err(|_| ULogError::InvalidConfiguration(format!("Key too long: {key}")))?;
+
+    let mut payload = vec![key_len];
+    payload.extend_from_slice(key.as_bytes());
+    payload.extend_from_slice(value);
+    write_message(bytes, msg_type, &payload)
+}
+
+/// A reader which injects faults at chosen byte offsets of the underlying reader.
+///
+/// ```
+/// use yule_log::parser::ULogParser;
+/// use yule_log::testkit::{FaultyReader, LogBuilder};
+///
+/// # fn main() -> Result<(), yule_log::errors::ULogError> {
+/// let bytes = LogBuilder::new().build()?;
+/// let reader = FaultyReader::new(bytes.as_slice()).flip_bit(20, 3).short_reads(1);
+///
+/// for message in ULogParser::new(reader)? {
+///     // Every fault must surface as an error or altered data, never as a panic.
+///     let _ = message;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct FaultyReader<R> {
+    reader: R,
+    position: u64,
+    bit_flips: Vec<(u64, u8)>,
+    truncate_at: Option<u64>,
+    short_reads: Option<(u64, usize)>,
+    errors: Vec<(u64, io::ErrorKind)>,
+}
+
+impl<R: Read> FaultyReader<R> {
+    pub fn new(reader: R) -> Self {
+        FaultyReader {
+            reader,
+            position: 0,
+            bit_flips: Vec::new(),
+            truncate_at: None,
+            short_reads: None,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Inverts bit `bit` (0 being the least significant) of the byte at `offset`.
+    #[must_use]
+    pub fn flip_bit(mut self, offset: u64, bit: u8) -> Self {
+        self.bit_flips.push((offset, 1 << (bit % 8)));
+        self
+    }
+
+    /// Reports the end of the input at `offset`.
+    #[must_use]
+    pub fn truncate_at(mut self, offset: u64) -> Self {
+        self.truncate_at = Some(offset);
+        self
+    }
+
+    /// Returns at most `max_len` bytes per read, which some readers, e.g. sockets, do at any time.
+    #[must_use]
+    pub fn short_reads(self, max_len: usize) -> Self {
+        self.short_reads_from(0, max_len)
+    }
+
+    /// Like `short_reads()`, starting at `offset`.
+    #[must_use]
+    pub fn short_reads_from(mut self, offset: u64, max_len: usize) -> Self {
+        self.short_reads = Some((offset, max_len.max(1)));
+        self
+    }
+
+    /// Fails the first read at `offset` with an error of `kind`.  Later reads succeed again.
+    #[must_use]
+    pub fn error_at(mut self, offset: u64, kind: io::ErrorKind) -> Self {
+        self.errors.push((offset, kind));
+        self
+    }
+
+    /// The number of bytes read so far.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+}
+
+impl<R: Read> Read for FaultyReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let position = self.position;
+
+        if let Some(index) = self
+            .errors
+            .iter()
+            .position(|(offset, _)| *offset <= position)
+        {
+            let (_, kind) = self.errors.remove(index);
+            return Err(io::Error::new(
+                kind,
+                format!("Injected fault at offset {position}"),
+            ));
+        }
+
+        // Stop short of the next fault, so that it triggers at exactly its offset.
+        let mut len = buf.len() as u64;
+        if let Some(truncate_at) = self.truncate_at {
+            len = len.min(truncate_at.saturating_sub(position));
+        }
+        for (offset, _) in &self.errors {
+            len = len.min(offset - position);
+        }
+        if let Some((offset, max_len)) = self.short_reads {
+            if offset <= position {
+                len = len.min(max_len as u64);
+            } else {
+                len = len.min(offset - position);
+            }
+        }
+
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let n = self.reader.read(&mut buf[..len as usize])?;
+        let end = position + n as u64;
+
+        for (offset, mask) in &self.bit_flips {
+            if (position..end).contains(offset) {
+                buf[(offset - position) as usize] ^= mask;
+            }
+        }
+
+        self.position = end;
+        Ok(n)
+    }
+}
+
+/// The exporter whose output `snapshot()` hashes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotSink {
+    /// The CSV files of `export::csv`, in order of topic name and multi_id.  The manifest is not included.
+    Csv,
+    /// The lines of `export::ndjson`, in message order.
+    Ndjson,
+    /// The statements of `export::sql` in the dialect.
+    Sql(Dialect),
+}
+
+/// A 128 bit FNV-1a hash of exported output.  Displayed as 32 hex digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Digest(pub u128);
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+/// Hashes the output of exporting `messages` with `sink`, so that a test can pin the conversion of a log, e.g. to
+/// notice a change of output after upgrading this crate:
+///
+/// ```
+/// use yule_log::builder::ULogParserBuilder;
+/// use yule_log::testkit::{self, SnapshotSink};
+///
+/// # fn main() -> Result<(), yule_log::errors::ULogError> {
+/// let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+/// let parser = ULogParserBuilder::new(input.as_slice()).build()?;
+///
+/// let digest = testkit::snapshot(parser, SnapshotSink::Csv)?;
+/// println!("{digest}"); // Compare with a digest recorded earlier.
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Floats are written in their shortest form, and NaN and infinite floats as `nan`, `inf` and `-inf`, whatever the
+/// defaults of the exporters.  SQL statements write them as strings for `Dialect::Postgres`, and as `NULL` for the
+/// other dialects, which have no literals for them.  The hash itself doesn't depend on the platform or the version of Rust.
+pub fn snapshot<I>(messages: I, sink: SnapshotSink) -> Result<Digest, ULogError>
+where
+    I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+{
+    let mut hasher = Fnv128::default();
+    match sink {
+        SnapshotSink::Csv => hash_csv(messages, &mut hasher)?,
+        SnapshotSink::Ndjson => {
+            NdjsonExporter::new()
+                .invalid_floats(InvalidFloatPolicy::AsNaNString)
+                .float_format(FloatFormat::Shortest)
+                .export(messages, &mut hasher)?;
+        }
+        SnapshotSink::Sql(dialect) => {
+            let invalid_floats = match dialect {
+                Dialect::Postgres => InvalidFloatPolicy::AsNaNString,
+                _ => InvalidFloatPolicy::AsNull,
+            };
+            SqlExporter::new(dialect)
+                .invalid_floats(invalid_floats)
+                .export(messages, &mut hasher)?;
+        }
+    }
+    Ok(Digest(hasher.0))
+}
+
+// Writes the files `export::csv` would, one after the other, each preceded by its name.
+fn hash_csv<I>(messages: I, hasher: &mut Fnv128) -> Result<(), ULogError>
+where
+    I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+{
+    let mut topic_index = TopicIndex::default();
+    let mut files: Vec<((String, u8), String)> = Vec::new();
+
+    for message in messages {
+        let UlogMessage::LoggedData(data) = message? else {
+            continue;
+        };
+        let (index, multi_id) = topic_index.get(&data);
+        if index == files.len() {
+            let columns = column_names(&data.data).into_iter().map(|(name, _)| name);
+            let header: Vec<String> = std::iter::once("timestamp".to_owned())
+                .chain(columns)
+                .collect();
+            files.push((
+                (data.data.name.to_string(), multi_id),
+                header.join(",") + "\n",
+            ));
+        }
+
+        let mut values = vec![data.timestamp.to_string()];
+        for_each_scalar(&data.data, &mut |value| {
+            values.extend(
+                InvalidFloatPolicy::AsNaNString.format_scalar(value, FloatFormat::Shortest),
+            );
+        });
+        let contents = &mut files[index].1;
+        contents.push_str(&values.join(","));
+        contents.push('\n');
+    }
+
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    for ((name, multi_id), contents) in files {
+        writeln!(hasher, "{}_{multi_id}.csv", name.replace('/', "_"))?;
+        hasher.write_all(contents.as_bytes())?;
+    }
+    Ok(())
+}
+
+// FNV-1a, whose output, unlike that of `DefaultHasher`, is specified.
+struct Fnv128(u128);
+
+impl Default for Fnv128 {
+    fn default() -> Self {
+        Fnv128(0x6c62272e07bb014262b821756295c58d)
+    }
+}
+
+impl Write for Fnv128 {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for byte in buf {
+            self.0 ^= u128::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0000000001000000000000000000013b);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::model::inst::{FieldValue, ParameterValue};
+    use crate::model::msg::UlogMessage;
+    use crate::parser::ULogParser;
+
+    fn parse(bytes: &[u8]) -> Result<Vec<UlogMessage>, ULogError> {
+        ULogParserBuilder::new(bytes)
+            .include_header(true)
+            .build()?
+            .collect()
+    }
+
+    #[test]
+    fn test_build() -> Result<(), ULogError> {
+        let bytes = LogBuilder::new()
+            .start_timestamp(5_000_000)
+            .duration(Duration::from_millis(100))
+            .info("sys_name", "testkit")
+            .parameter_i32("SYS_AUTOSTART", 4001)
+            .topic(Topic::new("a").rate_hz(100.0).field(
+                "x",
+                BaseType::FLOAT,
+                Generator::Ramp {
+                    start: 1.0,
+                    step: 0.5,
+                },
+            ))
+            .topic(
+                Topic::new("b")
+                    .rate_hz(50.0)
+                    .multi_id(1)
+                    .array_field(
+                        "v",
+                        BaseType::INT16,
+                        3,
+                        Generator::from_fn(|s| s.element as f64),
+                    )
+                    .field("armed", BaseType::BOOL, Generator::Constant(1.0)),
+            )
+            .dropout_at(Duration::from_millis(50), 20)
+            .log_at(Duration::from_millis(60), LogLevel::Warning, "hello")
+            .build()?;
+
+        let messages = parse(&bytes)?;
+
+        let mut a = Vec::new();
+        let mut b = 0;
+        let mut dropouts = 0;
+        let mut logs = Vec::new();
+        let mut params = 0;
+
+        for message in &messages {
+            match message {
+                UlogMessage::LoggedData(data) if &*data.data.name == "a" => {
+                    match data.data.fields[0].value {
+                        FieldValue::ScalarF32(x) => a.push((data.timestamp, x)),
+                        ref other => panic!("Unexpected value {other:?}"),
+                    }
+                }
+                UlogMessage::LoggedData(data) => {
+                    assert_eq!(data.data.multi_id_index, Some(1));
+                    assert_eq!(
+                        data.data.fields[0].value,
+                        FieldValue::ArrayI16(vec![0, 1, 2])
+                    );
+                    assert_eq!(data.data.fields[1].value, FieldValue::ScalarBool(true));
+                    b += 1;
+                }
+                UlogMessage::DropoutMark(_) => dropouts += 1,
+                UlogMessage::LoggedString(log) => logs.push((log.timestamp, log.msg.clone())),
+                UlogMessage::Parameter(param) => {
+                    assert!(matches!(param.value, ParameterValue::INT32(4001)));
+                    params += 1;
+                }
+                _ => {}
+            }
+        }
+
+        assert_eq!(a.len(), 10);
+        assert_eq!(a[0], (5_000_000, 1.0));
+        assert_eq!(a[3], (5_030_000, 2.5));
+        assert_eq!(b, 5);
+        assert_eq!(dropouts, 1);
+        assert_eq!(logs, [(5_060_000, "hello".to_owned())]);
+        assert_eq!(params, 1);
+
+        // Messages are in timestamp order.
+        let timestamps: Vec<u64> = messages
+            .iter()
+            .filter_map(|m| match m {
+                UlogMessage::LoggedData(data) => Some(data.timestamp),
+                _ => None,
+            })
+            .collect();
+        assert!(timestamps.windows(2).all(|w| w[0] <= w[1]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_garbage() -> Result<(), ULogError> {
+        let bytes = LogBuilder::new()
+            .topic(Topic::new("a").field("x", BaseType::UINT8, Generator::Constant(1.0)))
+            .garbage_at(Duration::from_millis(500), &[0xFF; 16])
+            .build()?;
+
+        // The garbage reads as a message running past the end of the file, so the data after it is lost.
+        let data = ULogParser::new(bytes.as_slice())?
+            .filter(|m| matches!(m, Ok(UlogMessage::LoggedData(_))))
+            .count();
+        assert_eq!(data, 5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_topics() {
+        let mismatch = LogBuilder::new()
+            .topic(Topic::new("a").field("x", BaseType::UINT8, Generator::Constant(0.0)))
+            .topic(Topic::new("a").multi_id(1).field(
+                "y",
+                BaseType::UINT8,
+                Generator::Constant(0.0),
+            ))
+            .build();
+        assert!(matches!(mismatch, Err(ULogError::InvalidConfiguration(_))));
+
+        let nested = LogBuilder::new()
+            .topic(Topic::new("a").field(
+                "x",
+                BaseType::OTHER("b".to_owned()),
+                Generator::Constant(0.0),
+            ))
+            .build();
+        assert!(matches!(nested, Err(ULogError::InvalidConfiguration(_))));
+    }
+
+    fn fixture() -> Result<Vec<u8>, ULogError> {
+        LogBuilder::new()
+            .duration(Duration::from_millis(200))
+            .info("sys_name", "testkit")
+            .parameter_f32("MC_ROLL_P", 6.5)
+            .topic(Topic::new("a").rate_hz(50.0).field(
+                "x",
+                BaseType::DOUBLE,
+                Generator::Sine {
+                    amplitude: 1.0,
+                    period: Duration::from_millis(100),
+                },
+            ))
+            .topic(Topic::new("b").rate_hz(20.0).array_field(
+                "c",
+                BaseType::CHAR,
+                4,
+                Generator::Constant(65.0),
+            ))
+            .log_at(Duration::from_millis(100), LogLevel::Info, "halfway")
+            .build()
+    }
+
+    /// Parses `reader` to the end, rendering each message or error as a string.
+    fn parse_all<R: Read>(reader: R) -> Vec<String> {
+        match ULogParserBuilder::new(reader).include_header(true).build() {
+            Ok(parser) => parser.map(|m| format!("{m:?}")).collect(),
+            Err(e) => vec![format!("{e:?}")],
+        }
+    }
+
+    #[test]
+    fn test_short_reads() -> Result<(), ULogError> {
+        let bytes = fixture()?;
+        let expected = parse_all(bytes.as_slice());
+
+        assert_eq!(
+            parse_all(FaultyReader::new(bytes.as_slice()).short_reads(1)),
+            expected
+        );
+        assert_eq!(
+            parse_all(FaultyReader::new(bytes.as_slice()).short_reads_from(100, 3)),
+            expected
+        );
+        // Interrupted reads are retried.
+        assert_eq!(
+            parse_all(
+                FaultyReader::new(bytes.as_slice()).error_at(100, io::ErrorKind::Interrupted)
+            ),
+            expected
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_at() -> Result<(), ULogError> {
+        let bytes = fixture()?;
+        let reader = FaultyReader::new(bytes.as_slice()).error_at(200, io::ErrorKind::BrokenPipe);
+
+        let result: Result<Vec<_>, _> = ULogParser::new(reader)?.collect();
+        assert!(matches!(result, Err(ULogError::Io(e)) if e.kind() == io::ErrorKind::BrokenPipe));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncation() -> Result<(), ULogError> {
+        let bytes = fixture()?;
+        let complete = parse_all(bytes.as_slice()).len();
+
+        for offset in 0..bytes.len() as u64 {
+            let reader = FaultyReader::new(bytes.as_slice()).truncate_at(offset);
+            assert!(parse_all(reader).len() <= complete, "Truncated at {offset}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bit_flips() -> Result<(), ULogError> {
+        for bytes in [fixture()?, std::fs::read("test_data/input/short_list.ulg")?] {
+            for offset in 0..bytes.len() as u64 {
+                for bit in [0, 7] {
+                    // Must not panic.
+                    parse_all(FaultyReader::new(bytes.as_slice()).flip_bit(offset, bit));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot() -> Result<(), ULogError> {
+        let log = |value: f64| {
+            LogBuilder::new()
+                .topic(Topic::new("a").field("x", BaseType::FLOAT, Generator::Constant(value)))
+                .topic(Topic::new("b").multi_id(1).field(
+                    "y",
+                    BaseType::DOUBLE,
+                    Generator::Constant(f64::NAN),
+                ))
+                .build()
+        };
+        let (bytes, other) = (log(0.5)?, log(0.25)?);
+        let sinks = [
+            SnapshotSink::Csv,
+            SnapshotSink::Ndjson,
+            SnapshotSink::Sql(Dialect::Sqlite),
+        ];
+
+        for sink in sinks {
+            let digest = snapshot(ULogParser::new(bytes.as_slice())?, sink)?;
+            assert_eq!(
+                digest,
+                snapshot(ULogParser::new(bytes.as_slice())?, sink)?,
+                "{sink:?}"
+            );
+            assert_ne!(
+                digest,
+                snapshot(ULogParser::new(other.as_slice())?, sink)?,
+                "{sink:?}"
+            );
+        }
+
+        // The FNV-1a offset basis.
+        let empty = snapshot(std::iter::empty(), SnapshotSink::Csv)?;
+        assert_eq!(empty.to_string(), "6c62272e07bb014262b821756295c58d");
+
+        Ok(())
+    }
+}
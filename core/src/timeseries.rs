@@ -0,0 +1,301 @@
+//! Reduces the samples of a field to what a plot needs.
+//!
+//! Fields are named by a `FieldPath`, e.g. `vehicle_local_position/x` or `actuator_outputs.01/output[3]`.
+//!
+//! ```rust
+//! use yule_log::builder::ULogParserBuilder;
+//! use yule_log::timeseries;
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+//! let parser = ULogParserBuilder::new(input.as_slice()).build()?;
+//!
+//! // One point per 100 ms, however many samples were logged.
+//! for bucket in timeseries::envelope(parser, "vehicle_local_position/x", 100_000)? {
+//!     println!("{} {} {} {}", bucket.timestamp, bucket.min, bucket.max, bucket.mean);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::errors::ULogError;
+use crate::export::{column_names, for_each_scalar};
+use crate::model::msg::{LoggedData, UlogMessage};
+
+/// A scalar field of a topic instance: the topic, followed by `.NN` for a multi_id other than 0, a `/`, and the
+/// column name, as exported, e.g. `velocity[2]` for an array element and `setpoint.vx` for a nested field.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FieldPath {
+    pub topic: String,
+    pub multi_id: u8,
+    pub column: String,
+}
+
+impl FieldPath {
+    pub fn new(topic: impl Into<String>, multi_id: u8, column: impl Into<String>) -> Self {
+        FieldPath {
+            topic: topic.into(),
+            multi_id,
+            column: column.into(),
+        }
+    }
+}
+
+impl FromStr for FieldPath {
+    type Err = ULogError;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            ULogError::InvalidFieldName(format!(
+                "`{path}` is not a field path of the form `topic/column` or `topic.NN/column`."
+            ))
+        };
+
+        let (topic, column) = path.split_once('/').ok_or_else(invalid)?;
+        let (topic, multi_id) = match topic.rsplit_once('.') {
+            Some((topic, multi_id)) => (topic, multi_id.parse().map_err(|_| invalid())?),
+            None => (topic, 0),
+        };
+        if topic.is_empty() || column.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(FieldPath::new(topic, multi_id, column))
+    }
+}
+
+impl fmt::Display for FieldPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.multi_id {
+            0 => write!(f, "{}/{}", self.topic, self.column),
+            multi_id => write!(f, "{}.{multi_id:02}/{}", self.topic, self.column),
+        }
+    }
+}
+
+/// Finds the values of a set of field paths in logged data.  The column of each path is looked up once per
+/// subscription.
+#[derive(Debug)]
+pub(crate) struct FieldLookup {
+    paths: Vec<FieldPath>,
+    // msg_id -> (topic, [(path index, column index)]), ordered by column index.
+    columns: HashMap<u16, (String, Vec<(usize, usize)>)>,
+}
+
+impl FieldLookup {
+    pub(crate) fn new(paths: Vec<FieldPath>) -> Self {
+        FieldLookup {
+            paths,
+            columns: HashMap::new(),
+        }
+    }
+
+    /// Calls `f` with the index of each path found in `data`, and its value.  Values which aren't numeric,
+    /// e.g. chars, are skipped.
+    pub(crate) fn for_each(
+        &mut self,
+        data: &LoggedData,
+        mut f: impl FnMut(usize, f64),
+    ) -> Result<(), ULogError> {
+        let cached = self.columns.get(&data.msg_id);
+        if cached.is_none_or(|(topic, _)| *topic != *data.data.name) {
+            let columns = self.resolve(data)?;
+            self.columns
+                .insert(data.msg_id, (data.data.name.to_string(), columns));
+        }
+        let (_, columns) = &self.columns[&data.msg_id];
+        if columns.is_empty() {
+            return Ok(());
+        }
+
+        let mut column = 0;
+        let mut wanted = columns.iter().peekable();
+        for_each_scalar(&data.data, &mut |value| {
+            while let Some(&(path, _)) = wanted.next_if(|&&(_, c)| c == column) {
+                if let Some(value) = value.as_f64() {
+                    f(path, value);
+                }
+            }
+            column += 1;
+        });
+
+        Ok(())
+    }
+
+    fn resolve(&self, data: &LoggedData) -> Result<Vec<(usize, usize)>, ULogError> {
+        let multi_id = data.data.multi_id_index.unwrap_or(0);
+        let matching = self
+            .paths
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p.topic == *data.data.name && p.multi_id == multi_id);
+
+        let names = column_names(&data.data);
+        let mut columns = Vec::new();
+        for (i, path) in matching {
+            let column = names
+                .iter()
+                .position(|(name, _)| *name == path.column)
+                .ok_or_else(|| {
+                    ULogError::InvalidFieldName(format!(
+                        "`{}` has no column `{}`.",
+                        path.topic, path.column
+                    ))
+                })?;
+            columns.push((i, column));
+        }
+        columns.sort_by_key(|&(_, column)| column);
+
+        Ok(columns)
+    }
+}
+
+/// The samples of a field within one bucket of time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EnvelopeBucket {
+    /// The start of the bucket, a multiple of the bucket width.
+    pub timestamp: u64,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub count: u64,
+}
+
+/// Reads a log and returns the minimum, maximum and mean of `field_path`'s samples per `bucket_us`
+/// microseconds, in a single pass.  Buckets without samples are omitted, and NaN values are skipped.
+///
+/// Samples are expected in order of time: a sample earlier than the current bucket starts a new bucket.
+pub fn envelope<I>(
+    messages: I,
+    field_path: &str,
+    bucket_us: u64,
+) -> Result<Vec<EnvelopeBucket>, ULogError>
+where
+    I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+{
+    if bucket_us == 0 {
+        return Err(ULogError::InvalidConfiguration(
+            "The bucket width must be greater than zero.".to_owned(),
+        ));
+    }
+
+    let mut lookup = FieldLookup::new(vec![field_path.parse()?]);
+    let mut buckets: Vec<EnvelopeBucket> = Vec::new();
+    let mut sum = 0.0;
+
+    for msg in messages {
+        let UlogMessage::LoggedData(data) = msg? else {
+            continue;
+        };
+        let timestamp = data.timestamp - data.timestamp % bucket_us;
+
+        lookup.for_each(&data, |_, value| {
+            if value.is_nan() {
+                return;
+            }
+
+            match buckets.last_mut() {
+                Some(bucket) if bucket.timestamp == timestamp => {
+                    bucket.min = bucket.min.min(value);
+                    bucket.max = bucket.max.max(value);
+                    bucket.count += 1;
+                    sum += value;
+                    bucket.mean = sum / bucket.count as f64;
+                }
+                _ => {
+                    sum = value;
+                    buckets.push(EnvelopeBucket {
+                        timestamp,
+                        min: value,
+                        max: value,
+                        mean: value,
+                        count: 1,
+                    });
+                }
+            }
+        })?;
+    }
+
+    Ok(buckets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::model::def::BaseType;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    #[test]
+    fn test_field_path() -> Result<(), ULogError> {
+        assert_eq!("a/x".parse::<FieldPath>()?, FieldPath::new("a", 0, "x"));
+        assert_eq!(
+            "a.01/v[2]".parse::<FieldPath>()?,
+            FieldPath::new("a", 1, "v[2]")
+        );
+        assert_eq!(FieldPath::new("a", 1, "s.vx").to_string(), "a.01/s.vx");
+        assert!("a".parse::<FieldPath>().is_err());
+        assert!("a.x/y".parse::<FieldPath>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_envelope() -> Result<(), ULogError> {
+        let bytes = LogBuilder::new()
+            .topic(
+                Topic::new("a")
+                    .field(
+                        "x",
+                        BaseType::FLOAT,
+                        Generator::Ramp {
+                            start: 0.0,
+                            step: 1.0,
+                        },
+                    )
+                    .field("y", BaseType::INT16, Generator::Constant(-3.0)),
+            )
+            .topic(
+                Topic::new("a")
+                    .multi_id(1)
+                    .field("x", BaseType::FLOAT, Generator::Constant(7.0))
+                    .field("y", BaseType::INT16, Generator::Constant(-3.0)),
+            )
+            .build()?;
+        let parser = || ULogParserBuilder::new(bytes.as_slice()).build();
+
+        // 10 samples at 10 Hz from 1 s, in 4 buckets of 300 ms starting at 0.9 s.
+        let buckets = envelope(parser()?, "a/x", 300_000)?;
+        let summary: Vec<_> = buckets
+            .iter()
+            .map(|b| (b.timestamp, b.min, b.max, b.mean, b.count))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (900_000, 0.0, 1.0, 0.5, 2),
+                (1_200_000, 2.0, 4.0, 3.0, 3),
+                (1_500_000, 5.0, 7.0, 6.0, 3),
+                (1_800_000, 8.0, 9.0, 8.5, 2),
+            ]
+        );
+
+        let buckets = envelope(parser()?, "a.01/x", 1_000_000)?;
+        assert_eq!(buckets.len(), 1);
+        assert_eq!(
+            (buckets[0].min, buckets[0].max, buckets[0].count),
+            (7.0, 7.0, 10)
+        );
+
+        assert!(matches!(
+            envelope(parser()?, "a/z", 1_000),
+            Err(ULogError::InvalidFieldName(_))
+        ));
+        assert!(envelope(parser()?, "b/x", 1_000)?.is_empty());
+
+        Ok(())
+    }
+}
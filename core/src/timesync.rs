@@ -0,0 +1,257 @@
+//! Converts timestamps between the clock of a companion computer and the FMU clock, using the offsets
+//! estimated by the PX4 timesync, and logged in the `timesync_status` topic.
+//!
+//! Topics forwarded from a companion computer, e.g. over MAVLink or uXRCE-DDS, may be logged with the companion's
+//! timestamps.  The FMU time of a companion timestamp is the timestamp plus the `estimated_offset`.  The offset
+//! drifts as the clocks do, so it is interpolated between the `timesync_status` samples.
+//!
+//! The mapper is built in a first pass over the log, and can then correct the timestamps of the companion topics
+//! in a second pass, before the messages are passed on, e.g. to an exporter:
+//!
+//! ```rust,no_run
+//! use yule_log::builder::ULogParserBuilder;
+//! use yule_log::timesync::TimeMapper;
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let input = std::fs::read("flight.ulg")?;
+//! let mapper = TimeMapper::from_messages(ULogParserBuilder::new(input.as_slice()).build()?, 0)?;
+//!
+//! let messages = ULogParserBuilder::new(input.as_slice()).build()?;
+//! let manifest = yule_log::export::csv::export_dir(mapper.correct(["vehicle_visual_odometry"], messages), "csv")?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashSet;
+
+use crate::errors::ULogError;
+use crate::model::inst::FieldValue;
+use crate::model::msg::UlogMessage;
+
+/// The topics logged by the PX4 timesync.  `timesync` was renamed to `timesync_status` in PX4 v1.13.
+pub const TIMESYNC_TOPICS: [&str; 2] = ["timesync_status", "timesync"];
+
+/// Maps timestamps between the companion and FMU clocks.
+#[derive(Debug, Clone, Default)]
+pub struct TimeMapper {
+    // (FMU timestamp, offset) samples, ordered by FMU timestamp.
+    samples: Vec<(u64, i64)>,
+}
+
+impl TimeMapper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the `estimated_offset` of each sample of the `multi_id` instance of the timesync topic.
+    /// Each instance corresponds to a link to a companion computer.
+    pub fn from_messages<I>(messages: I, multi_id: u8) -> Result<Self, ULogError>
+    where
+        I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+    {
+        let mut mapper = TimeMapper::new();
+
+        for msg in messages {
+            let UlogMessage::LoggedData(data) = msg? else {
+                continue;
+            };
+            if !TIMESYNC_TOPICS.contains(&data.data.name.as_ref())
+                || data.data.multi_id_index.unwrap_or(0) != multi_id
+            {
+                continue;
+            }
+
+            let offset = data
+                .data
+                .fields
+                .iter()
+                .find(|f| f.name.as_ref() == "estimated_offset");
+            match offset.map(|f| &f.value) {
+                Some(FieldValue::ScalarI64(offset)) => mapper.push(data.timestamp, *offset),
+                _ => {
+                    return Err(ULogError::InvalidFieldName(format!(
+                        "`{}` has no int64_t estimated_offset field.",
+                        data.data.name
+                    )))
+                }
+            }
+        }
+
+        Ok(mapper)
+    }
+
+    /// Adds an offset estimated at `fmu_timestamp`.
+    pub fn push(&mut self, fmu_timestamp: u64, offset: i64) {
+        let index = self.samples.partition_point(|&(t, _)| t <= fmu_timestamp);
+        self.samples.insert(index, (fmu_timestamp, offset));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Returns the offset at `fmu_timestamp`, interpolated between the nearest samples.
+    /// Before the first sample and after the last, the offset of that sample is used.
+    pub fn offset_at(&self, fmu_timestamp: u64) -> Option<i64> {
+        let index = self.samples.partition_point(|&(t, _)| t <= fmu_timestamp);
+
+        match (
+            index.checked_sub(1).map(|i| self.samples[i]),
+            self.samples.get(index),
+        ) {
+            (None, None) => None,
+            (Some((_, offset)), None) | (None, Some(&(_, offset))) => Some(offset),
+            (Some((t0, o0)), Some(&(t1, o1))) => {
+                let fraction =
+                    i128::from(fmu_timestamp - t0) * i128::from(o1 - o0) / i128::from(t1 - t0);
+                Some(o0 + fraction as i64)
+            }
+        }
+    }
+
+    /// Converts a companion timestamp to FMU time.  Returns `None` if there are no samples, or the result
+    /// would be negative.
+    pub fn to_fmu(&self, companion_timestamp: u64) -> Option<u64> {
+        // The offset is indexed by FMU time, so refine the estimate of the FMU time.  The offset drifts slowly,
+        // so a few iterations converge.
+        let mut fmu_timestamp = add_offset(companion_timestamp, self.samples.first()?.1)?;
+        for _ in 0..3 {
+            fmu_timestamp = add_offset(companion_timestamp, self.offset_at(fmu_timestamp)?)?;
+        }
+        Some(fmu_timestamp)
+    }
+
+    /// Converts an FMU timestamp to companion time.  Returns `None` if there are no samples, or the result
+    /// would be negative.
+    pub fn to_companion(&self, fmu_timestamp: u64) -> Option<u64> {
+        add_offset(fmu_timestamp, self.offset_at(fmu_timestamp)?.checked_neg()?)
+    }
+
+    /// Converts the timestamps of the `LoggedData` messages of `topics` from companion to FMU time.
+    /// Messages of other topics, and messages whose timestamp can't be converted, are passed through unchanged.
+    pub fn correct<'a, I>(
+        &'a self,
+        topics: impl IntoIterator<Item = impl Into<String>>,
+        messages: I,
+    ) -> impl Iterator<Item = Result<UlogMessage, ULogError>> + 'a
+    where
+        I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+        I::IntoIter: 'a,
+    {
+        let topics: HashSet<String> = topics.into_iter().map(Into::into).collect();
+
+        messages.into_iter().map(move |msg| {
+            let mut msg = msg?;
+            if let UlogMessage::LoggedData(data) = &mut msg {
+                if topics.contains(data.data.name.as_ref()) {
+                    if let Some(timestamp) = self.to_fmu(data.timestamp) {
+                        data.set_timestamp(timestamp);
+                    }
+                }
+            }
+            Ok(msg)
+        })
+    }
+}
+
+fn add_offset(timestamp: u64, offset: i64) -> Option<u64> {
+    timestamp.checked_add_signed(offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::model::def::BaseType;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    #[test]
+    fn test_interpolation() {
+        let mut mapper = TimeMapper::new();
+        assert_eq!(mapper.offset_at(0), None);
+        assert_eq!(mapper.to_fmu(0), None);
+
+        mapper.push(101_000, 200);
+        mapper.push(1_000, 100);
+
+        assert_eq!(mapper.offset_at(0), Some(100));
+        assert_eq!(mapper.offset_at(51_000), Some(150));
+        assert_eq!(mapper.offset_at(200_000), Some(200));
+
+        // 10_900 + 110 = 11_010, where the offset is 110.
+        assert_eq!(mapper.to_fmu(10_900), Some(11_010));
+        assert_eq!(mapper.to_companion(11_010), Some(10_900));
+        assert_eq!(mapper.to_companion(50), None);
+    }
+
+    #[test]
+    fn test_correct_topics() -> Result<(), ULogError> {
+        let bytes = LogBuilder::new()
+            .topic(Topic::new("timesync_status").field(
+                "estimated_offset",
+                BaseType::INT64,
+                Generator::Ramp {
+                    start: 5_000.0,
+                    step: 10.0,
+                },
+            ))
+            .topic(Topic::new("vehicle_visual_odometry").field(
+                "x",
+                BaseType::FLOAT,
+                Generator::Constant(1.0),
+            ))
+            .topic(Topic::new("sensor_combined").field(
+                "x",
+                BaseType::FLOAT,
+                Generator::Constant(1.0),
+            ))
+            .build()?;
+
+        let mapper =
+            TimeMapper::from_messages(ULogParserBuilder::new(bytes.as_slice()).build()?, 0)?;
+        assert_eq!(mapper.offset_at(1_000_000), Some(5_000));
+        assert_eq!(mapper.offset_at(1_050_000), Some(5_005));
+
+        let messages = ULogParserBuilder::new(bytes.as_slice())
+            .include_timestamp(true)
+            .build()?;
+        let mut corrected = Vec::new();
+        for msg in mapper.correct(["vehicle_visual_odometry"], messages) {
+            if let UlogMessage::LoggedData(data) = msg? {
+                corrected.push((data.data.name.to_string(), data.timestamp));
+            }
+        }
+
+        let timestamps = |topic: &str| -> Vec<u64> {
+            corrected
+                .iter()
+                .filter(|(name, _)| name == topic)
+                .map(|(_, t)| *t)
+                .collect()
+        };
+        assert_eq!(
+            timestamps("vehicle_visual_odometry")[..2],
+            [1_005_000, 1_105_010]
+        );
+        assert_eq!(timestamps("sensor_combined")[..2], [1_000_000, 1_100_000]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_offset_field() -> Result<(), ULogError> {
+        let bytes = LogBuilder::new()
+            .topic(Topic::new("timesync_status").field(
+                "observed_offset",
+                BaseType::INT64,
+                Generator::Constant(1.0),
+            ))
+            .build()?;
+
+        let result =
+            TimeMapper::from_messages(ULogParserBuilder::new(bytes.as_slice()).build()?, 0);
+        assert!(matches!(result, Err(ULogError::InvalidFieldName(_))));
+
+        Ok(())
+    }
+}
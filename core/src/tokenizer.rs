@@ -6,53 +6,97 @@ use crate::errors::ULogError;
 
 lazy_static! {
     static ref TOKEN_REGEXP: Regex = Regex::new(
-        r"(?P<identifier>[a-zA-Z_][a-zA-Z0-9_]*)|(?P<number>[0-9]+)|(?P<colon>:)|(?P<semicolon>;)|(?P<lbrace>\[)|(?P<rbrace>\])|(?P<whitespace>\s+)|(?P<unknown>.)"
+        r"(?P<identifier>[a-zA-Z_][a-zA-Z0-9_]*)|(?P<number>[0-9]+)|(?P<colon>:)|(?P<semicolon>;)|(?P<lbrace>\[)|(?P<rbrace>\])|(?P<whitespace>[\s\x00]+)|(?P<comment>(?://|#)[^\n]*)|(?P<unknown>.)"
     )
     .unwrap();
 }
 
+/// The tokens of a definition, each with the column it starts at.
 #[derive(Debug)]
-pub struct TokenList<'a>(VecDeque<Token<'a>>);
+pub struct TokenList<'a> {
+    tokens: VecDeque<(Token<'a>, usize)>,
+    // The column just past the end of the input.
+    end: usize,
+}
 
 impl<'a> TokenList<'a> {
     #[allow(dead_code)]
     pub fn new(tokens: VecDeque<Token<'a>>) -> Self {
-        TokenList(tokens)
+        let end = tokens.len() + 1;
+        TokenList {
+            tokens: tokens.into_iter().zip(1..).collect(),
+            end,
+        }
     }
 
     pub(crate) fn from_str(s: &'a str) -> Self {
-        TokenList(tokenize(s))
+        TokenList {
+            tokens: tokenize_with_columns(s),
+            end: s.chars().count() + 1,
+        }
     }
 
     pub fn is_empty(&self) -> bool {
-        self.0.is_empty()
+        self.tokens.is_empty()
     }
 
     #[allow(dead_code)]
     pub fn remaining(&self) -> usize {
-        self.0.len()
+        self.tokens.len()
     }
 
     pub fn peek(&self) -> Option<&Token<'a>> {
-        self.0.front()
+        self.tokens.front().map(|(token, _)| token)
+    }
+
+    /// The column, counted in characters from 1, of the next token, or just past the end of the input if there are
+    /// no more tokens.
+    pub fn column(&self) -> usize {
+        self.tokens.front().map_or(self.end, |&(_, column)| column)
+    }
+
+    /// Consumes any semicolons at the front, so that stray or doubled semicolons are tolerated.
+    pub fn skip_semicolons(&mut self) {
+        while self.peek() == Some(&Token::Semicolon) {
+            self.tokens.pop_front();
+        }
+    }
+
+    /// Splits off the tokens up to, but not including, the next semicolon, e.g. those of a field, so that an
+    /// invalid field can't consume the tokens of the next.
+    pub fn take_until_semicolon(&mut self) -> TokenList<'a> {
+        let len = self
+            .tokens
+            .iter()
+            .position(|(token, _)| *token == Token::Semicolon)
+            .unwrap_or(self.tokens.len());
+        let rest = self.tokens.split_off(len);
+        let tokens = std::mem::replace(&mut self.tokens, rest);
+        TokenList {
+            tokens,
+            end: self.column(),
+        }
     }
 
     pub fn consume_one(&mut self) -> Result<Token<'a>, ULogError> {
-        self.0.pop_front().ok_or(ULogError::UnexpectedEndOfFile)
+        match self.tokens.pop_front() {
+            Some((token, _)) => Ok(token),
+            None => Err(ULogError::ParseError(format!(
+                "Unexpected end of definition at column {}.",
+                self.end
+            ))),
+        }
     }
 
     pub fn consume_two(&mut self) -> Result<(Token<'a>, Token<'a>), ULogError> {
-        Ok((
-            self.0.pop_front().ok_or(ULogError::UnexpectedEndOfFile)?,
-            self.0.pop_front().ok_or(ULogError::UnexpectedEndOfFile)?,
-        ))
+        Ok((self.consume_one()?, self.consume_one()?))
     }
 
     pub fn consume_three(&mut self) -> Result<(Token<'a>, Token<'a>, Token<'a>), ULogError> {
         Ok((
-            self.0.pop_front().ok_or(ULogError::UnexpectedEndOfFile)?,
-            self.0.pop_front().ok_or(ULogError::UnexpectedEndOfFile)?,
-            self.0.pop_front().ok_or(ULogError::UnexpectedEndOfFile)?,
+            self.consume_one()?,
+            self.consume_one()?,
+            self.consume_one()?,
         ))
     }
 }
@@ -66,28 +110,52 @@ pub enum Token<'a> {
     LBrace,
     RBrace,
     Unknown(char),
-    // Whitespace is skipped by the tokenizer, not represented as a token.
+    /// Digits too many for a `usize`.
+    InvalidNumber(&'a str),
+    // Whitespace, NUL padding and comments (`//` or `#` to the end of the line) are skipped by the tokenizer, not
+    // represented as tokens.
 }
 
+#[allow(dead_code)]
 pub fn tokenize(input: &str) -> VecDeque<Token<'_>> {
+    tokenize_with_columns(input)
+        .into_iter()
+        .map(|(token, _)| token)
+        .collect()
+}
+
+/// Tokenizes `input`, with the column, counted in characters from 1, each token starts at.
+fn tokenize_with_columns(input: &str) -> VecDeque<(Token<'_>, usize)> {
     let mut tokens = VecDeque::new();
+    let mut column = 1;
+    let mut offset = 0;
 
     for caps in TOKEN_REGEXP.captures_iter(input) {
-        if let Some(identifier) = caps.name("identifier") {
-            tokens.push_back(Token::Identifier(identifier.as_str()));
+        let whole = caps.get(0).unwrap();
+        column += input[offset..whole.start()].chars().count();
+        offset = whole.start();
+
+        let token = if let Some(identifier) = caps.name("identifier") {
+            Token::Identifier(identifier.as_str())
         } else if let Some(number) = caps.name("number") {
-            tokens.push_back(Token::Number(number.as_str().parse::<usize>().unwrap()));
+            match number.as_str().parse::<usize>() {
+                Ok(number) => Token::Number(number),
+                Err(_) => Token::InvalidNumber(number.as_str()),
+            }
         } else if caps.name("colon").is_some() {
-            tokens.push_back(Token::Colon);
+            Token::Colon
         } else if caps.name("semicolon").is_some() {
-            tokens.push_back(Token::Semicolon);
+            Token::Semicolon
         } else if caps.name("lbrace").is_some() {
-            tokens.push_back(Token::LBrace);
+            Token::LBrace
         } else if caps.name("rbrace").is_some() {
-            tokens.push_back(Token::RBrace);
+            Token::RBrace
         } else if let Some(unknown) = caps.name("unknown") {
-            tokens.push_back(Token::Unknown(unknown.as_str().chars().next().unwrap()));
-        }
+            Token::Unknown(unknown.as_str().chars().next().unwrap())
+        } else {
+            continue;
+        };
+        tokens.push_back((token, column));
     }
 
     tokens
@@ -136,4 +204,37 @@ mod tests {
         let tokens = tokenize(input);
         assert_eq!(expected_tokens, tokens);
     }
+
+    #[test]
+    fn test_tokenize_comments_and_columns() {
+        let input = "msg:\tint x; // note\n# annotation\név y;\0\0";
+        let mut token_list = TokenList::from_str(input);
+
+        let mut columns = Vec::new();
+        while !token_list.is_empty() {
+            columns.push(token_list.column());
+            token_list.consume_one().unwrap();
+        }
+        assert_eq!(columns, [1, 4, 6, 10, 11, 34, 35, 37, 38]);
+        assert_eq!(token_list.column(), 41);
+
+        assert_eq!(
+            tokenize(input),
+            VecDeque::from([
+                Token::Identifier("msg"),
+                Token::Colon,
+                Token::Identifier("int"),
+                Token::Identifier("x"),
+                Token::Semicolon,
+                Token::Unknown('é'),
+                Token::Identifier("v"),
+                Token::Identifier("y"),
+                Token::Semicolon,
+            ])
+        );
+        assert_eq!(
+            tokenize("99999999999999999999999"),
+            VecDeque::from([Token::InvalidNumber("99999999999999999999999")])
+        );
+    }
 }
@@ -0,0 +1,678 @@
+pub mod config;
+pub mod coverage;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::ops::Range;
+
+use crate::model::msg::{Subscription, UlogMessage};
+
+pub use config::{check_config, ConfigSpec, ConfigViolation};
+pub use coverage::{check_coverage, CoverageIssue, CoverageReport, LoggingProfile, TopicCoverage};
+
+/// What the parser does when an `ADD_SUBSCRIPTION` message reuses the msg_id of a subscription
+/// which has not been removed.
+///
+/// Duplicates are recorded in the parser's `ValidationReport` whatever the policy.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateSubscriptionPolicy {
+    /// The new subscription replaces the previous one.
+    ReplaceSilently,
+
+    /// The new subscription replaces the previous one, and a warning is logged.
+    #[default]
+    Warn,
+
+    /// The previous subscription is kept, and the parser returns `ULogError::DuplicateSubscription`.
+    Error,
+}
+
+/// An `ADD_SUBSCRIPTION` message for a msg_id which was already subscribed.
+#[derive(Debug, Clone)]
+pub struct DuplicateSubscription {
+    pub previous: Subscription,
+    pub duplicate: Subscription,
+}
+
+impl DuplicateSubscription {
+    /// Returns true if the duplicate subscribes to the same topic instance, i.e. it is a plain repeat.
+    pub fn is_repeat(&self) -> bool {
+        self.previous.message_name == self.duplicate.message_name
+            && self.previous.multi_id == self.duplicate.multi_id
+    }
+}
+
+/// How the parser repairs the timestamps of a subscription which go backwards, e.g. after a dropout
+/// or when a 32 bit timestamp wraps.  See `ULogParserBuilder::repair_timestamps()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampRepair {
+    /// A timestamp earlier than the previous one is raised to the previous timestamp.
+    /// Later timestamps are not changed.
+    ClampMonotonic,
+
+    /// When a timestamp goes backwards, it and all later timestamps of the subscription are offset, so that
+    /// they continue from the previous timestamp.  If the previous timestamp fits in 32 bits and the jump back
+    /// is more than half the 32 bit range, the timestamp is taken to have wrapped and the offset is 2^32.
+    OffsetOnWrap,
+}
+
+/// A timestamp changed by the parser's `TimestampRepair`.
+///
+/// With `TimestampRepair::OffsetOnWrap`, only the timestamp at which the offset changed is recorded, and the
+/// later timestamps of the subscription are shifted by the same amount.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimestampAdjustment {
+    pub message_name: String,
+    pub multi_id: u8,
+    pub original: u64,
+    pub adjusted: u64,
+}
+
+// The timestamps of a subscription seen so far.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct TimestampClock {
+    pub(crate) last: Option<u64>,
+    pub(crate) last_original: u64,
+    pub(crate) offset: u64,
+}
+
+impl TimestampClock {
+    // Returns the repaired timestamp, and whether the repair should be recorded.
+    pub(crate) fn repair(&mut self, strategy: TimestampRepair, timestamp: u64) -> (u64, bool) {
+        let (adjusted, record) = match (strategy, self.last) {
+            (_, None) => (timestamp, false),
+            (TimestampRepair::ClampMonotonic, Some(last)) => (timestamp.max(last), timestamp < last),
+            (TimestampRepair::OffsetOnWrap, Some(last)) => {
+                let shifted = timestamp.saturating_add(self.offset);
+                if shifted < last {
+                    let wrapped = self.last_original <= u64::from(u32::MAX)
+                        && self.last_original - timestamp > u64::from(u32::MAX / 2);
+                    self.offset = if wrapped {
+                        self.offset + (1 << 32)
+                    } else {
+                        last - timestamp
+                    };
+                    (timestamp.saturating_add(self.offset), true)
+                } else {
+                    (shifted, false)
+                }
+            }
+        };
+
+        self.last = Some(adjusted);
+        self.last_original = timestamp;
+        (adjusted, record)
+    }
+}
+
+/// The SYNC messages of a log, which the parser resynchronizes at after a corrupt message.  See
+/// `ULogParserBuilder::resync_on_error()`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SyncStats {
+    pub count: u64,
+    /// The offsets of the first and last SYNC messages.
+    pub first_offset: Option<u64>,
+    pub last_offset: Option<u64>,
+    /// The most bytes from one SYNC message to the next, which bounds the data lost to a resynchronization.
+    pub max_interval: u64,
+    /// The number of SYNC messages without the magic bytes of the ULOG spec.
+    pub invalid: u64,
+}
+
+impl SyncStats {
+    pub(crate) fn record(&mut self, offset: u64, valid: bool) {
+        if !valid {
+            self.invalid += 1;
+            return;
+        }
+        if let Some(last) = self.last_offset {
+            self.max_interval = self.max_interval.max(offset - last);
+        }
+        self.count += 1;
+        self.first_offset.get_or_insert(offset);
+        self.last_offset = Some(offset);
+    }
+}
+
+/// The data messages of a topic instance whose timestamps repeat or go back, in one interval between SYNC messages.
+///
+/// The data of each topic instance is logged in timestamp order, so these are duplicated or reordered messages, e.g.
+/// from a logger which rewrote a buffer.  Timestamps are compared before `repair_timestamps()` changes them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SequenceIssue {
+    pub message_name: String,
+    pub multi_id: u8,
+    /// The offset of the SYNC message which starts the interval, or of the first data message if no SYNC message
+    /// came before.
+    pub interval_start: u64,
+    /// The bytes from the start of the first message of the interval whose timestamp repeated or went back, to the
+    /// end of the last.
+    pub range: Range<u64>,
+    /// The messages with the same timestamp as the one before.
+    pub duplicates: u64,
+    /// The messages with an earlier timestamp than the one before.
+    pub backwards: u64,
+}
+
+// The last timestamp of each subscription, and its `SequenceIssue` in the current SYNC interval.
+#[derive(Debug, Default)]
+pub(crate) struct SequenceTracker {
+    last: HashMap<u16, u64>,
+    interval_start: Option<u64>,
+    // Indices in `ValidationReport::sequence_issues`, by msg_id.
+    current: HashMap<u16, usize>,
+}
+
+impl SequenceTracker {
+    pub(crate) fn sync(&mut self, offset: u64) {
+        self.interval_start = Some(offset);
+        self.current.clear();
+    }
+
+    // Forgets a subscription, whose msg_id may be reused by another.
+    pub(crate) fn remove(&mut self, msg_id: u16) {
+        self.last.remove(&msg_id);
+        self.current.remove(&msg_id);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.last.clear();
+        self.current.clear();
+    }
+
+    // Records the data message of `sub` at `range`, adding to `issues` if its timestamp repeats or goes back.
+    pub(crate) fn record(
+        &mut self,
+        sub: &Subscription,
+        timestamp: u64,
+        range: Range<u64>,
+        issues: &mut Vec<SequenceIssue>,
+    ) {
+        let interval_start = *self.interval_start.get_or_insert(range.start);
+        let Some(last) = self.last.insert(sub.msg_id, timestamp) else {
+            return;
+        };
+        if timestamp > last {
+            return;
+        }
+
+        let index = *self.current.entry(sub.msg_id).or_insert_with(|| {
+            issues.push(SequenceIssue {
+                message_name: sub.message_name.clone(),
+                multi_id: sub.multi_id,
+                interval_start,
+                range: range.clone(),
+                duplicates: 0,
+                backwards: 0,
+            });
+            issues.len() - 1
+        });
+        let issue = &mut issues[index];
+        issue.range.end = range.end;
+        if timestamp == last {
+            issue.duplicates += 1;
+        } else {
+            issue.backwards += 1;
+        }
+    }
+}
+
+/// Bytes skipped by the parser after a corrupt message, up to the SYNC message it resumed at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResyncRegion {
+    /// The offset of the corrupt message.
+    pub start: u64,
+    /// The offset of the SYNC message, or the end of the log if none followed.
+    pub end: u64,
+    /// The error the corrupt message was parsed with.
+    pub error: String,
+}
+
+impl ResyncRegion {
+    pub fn len(&self) -> u64 {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+/// A field of a FORMAT message which could not be parsed.  With `ULogParserBuilder::recover_format_errors()`, the
+/// field is left out of the format and the issue recorded, rather than returned as an error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatIssue {
+    /// The name of the format.
+    pub format: String,
+    /// The columns of the field in the definition, counted in characters from 1, up to the semicolon after it.
+    pub span: Range<usize>,
+    pub error: String,
+}
+
+impl fmt::Display for FormatIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid field of format `{}` at columns {}..{}: {}",
+            self.format, self.span.start, self.span.end, self.error
+        )
+    }
+}
+
+/// The messages the parser returned without interpreting them, or skipped because of an error, which a pipeline
+/// would otherwise lose without notice.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MessageCounts {
+    /// The `UlogMessage::Unhandled` messages by message type.  SYNC and REMOVE_SUBSCRIPTION messages, which the
+    /// parser interprets, are not counted.
+    pub unhandled: BTreeMap<u8, u64>,
+    /// The `UlogMessage::Ignored` data messages of topics left out by the subscription allow list.
+    pub ignored: u64,
+    /// The `UlogMessage::UnknownTyped` messages, whose type has no registered decoder, by message type.
+    pub unknown: BTreeMap<u8, u64>,
+    /// The messages which could not be parsed: the errors returned by the parser, and the corrupt messages it
+    /// resynchronized after with `ULogParserBuilder::resync_on_error()`.
+    pub skipped_on_error: u64,
+}
+
+impl MessageCounts {
+    pub(crate) fn record(&mut self, message: &UlogMessage) {
+        match message {
+            UlogMessage::Unhandled { msg_type: b'S' | b'R', .. } => {}
+            UlogMessage::Unhandled { msg_type, .. } => *self.unhandled.entry(*msg_type).or_default() += 1,
+            UlogMessage::Ignored { .. } => self.ignored += 1,
+            UlogMessage::UnknownTyped(unknown) => *self.unknown.entry(unknown.msg_type).or_default() += 1,
+            _ => {}
+        }
+    }
+
+    /// The number of messages counted.
+    pub fn total(&self) -> u64 {
+        self.unhandled.values().sum::<u64>()
+            + self.ignored
+            + self.unknown.values().sum::<u64>()
+            + self.skipped_on_error
+    }
+}
+
+/// Problems found in the structure of a log, which the parser tolerated.
+///
+/// The report covers the messages read so far.  Iterate the parser with `by_ref()` to read it at the end:
+///
+/// ```rust
+/// use yule_log::builder::ULogParserBuilder;
+///
+/// # fn main() -> Result<(), yule_log::errors::ULogError> {
+/// let input = std::fs::read("test_data/input/short_list.ulg")?;
+/// let mut parser = ULogParserBuilder::new(input.as_slice()).build()?;
+///
+/// parser.by_ref().for_each(drop);
+///
+/// assert!(parser.validation_report().is_empty());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct ValidationReport {
+    pub duplicate_subscriptions: Vec<DuplicateSubscription>,
+    pub timestamp_adjustments: Vec<TimestampAdjustment>,
+    pub sync: SyncStats,
+    pub sequence_issues: Vec<SequenceIssue>,
+    pub resync_regions: Vec<ResyncRegion>,
+    pub message_counts: MessageCounts,
+    pub format_issues: Vec<FormatIssue>,
+}
+
+impl ValidationReport {
+    /// Returns true if no problem was found.  Valid SYNC messages are not problems, nor are the `message_counts`,
+    /// which are expected in most logs.
+    pub fn is_empty(&self) -> bool {
+        self.duplicate_subscriptions.is_empty()
+            && self.timestamp_adjustments.is_empty()
+            && self.sequence_issues.is_empty()
+            && self.resync_regions.is_empty()
+            && self.sync.invalid == 0
+            && self.format_issues.is_empty()
+    }
+
+    /// The number of bytes skipped to resynchronize.
+    pub fn resync_bytes(&self) -> u64 {
+        self.resync_regions.iter().map(ResyncRegion::len).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::errors::ULogError;
+    use crate::model::def::BaseType;
+    use crate::model::msg::UlogMessage;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    // Subscribes msg_id 0 to `b` while `a` is still subscribed to it.
+    fn fixture() -> Result<Vec<u8>, ULogError> {
+        LogBuilder::new()
+            .topic(Topic::new("a").field("x", BaseType::UINT32, Generator::Constant(1.0)))
+            .topic(Topic::new("b").field("y", BaseType::FLOAT, Generator::Constant(2.0)))
+            .garbage_at(Duration::from_millis(450), &[4, 0, b'A', 0, 0, 0, b'b'])
+            .build()
+    }
+
+    fn topics(messages: &[Result<UlogMessage, ULogError>]) -> Vec<String> {
+        messages
+            .iter()
+            .filter_map(|m| match m {
+                Ok(UlogMessage::LoggedData(data)) => Some(data.data.name.to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_replace() -> Result<(), ULogError> {
+        for policy in [
+            DuplicateSubscriptionPolicy::ReplaceSilently,
+            DuplicateSubscriptionPolicy::Warn,
+        ] {
+            let bytes = fixture()?;
+            let mut parser = ULogParserBuilder::new(bytes.as_slice())
+                .duplicate_subscription_policy(policy)
+                .build()?;
+            let messages: Vec<_> = parser.by_ref().collect();
+
+            assert!(messages.iter().all(Result::is_ok));
+            assert_eq!(topics(&messages).iter().filter(|t| *t == "a").count(), 5);
+            assert_eq!(topics(&messages).iter().filter(|t| *t == "b").count(), 15);
+
+            let report = parser.validation_report();
+            assert_eq!(report.duplicate_subscriptions.len(), 1);
+            let duplicate = &report.duplicate_subscriptions[0];
+            assert_eq!(duplicate.previous.message_name, "a");
+            assert_eq!(duplicate.duplicate.message_name, "b");
+            assert!(!duplicate.is_repeat());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_error() -> Result<(), ULogError> {
+        let bytes = fixture()?;
+        let mut parser = ULogParserBuilder::new(bytes.as_slice())
+            .duplicate_subscription_policy(DuplicateSubscriptionPolicy::Error)
+            .build()?;
+        let messages: Vec<_> = parser.by_ref().collect();
+
+        let errors: Vec<_> = messages.iter().filter_map(|m| m.as_ref().err()).collect();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0], ULogError::DuplicateSubscription(0)));
+
+        // The previous subscription is kept.
+        assert_eq!(topics(&messages).iter().filter(|t| *t == "a").count(), 10);
+        assert_eq!(parser.validation_report().duplicate_subscriptions.len(), 1);
+
+        Ok(())
+    }
+
+    // A `LOGGED_DATA` message for msg_id 0 of `a`, with the given timestamp.
+    fn sample(timestamp: u64) -> Vec<u8> {
+        let mut bytes = vec![14, 0, b'D', 0, 0];
+        bytes.extend_from_slice(&timestamp.to_le_bytes());
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes
+    }
+
+    fn timestamps(bytes: &[u8], strategy: TimestampRepair) -> Result<(Vec<u64>, ValidationReport), ULogError> {
+        let mut parser = ULogParserBuilder::new(bytes)
+            .include_timestamp(true)
+            .repair_timestamps(strategy)
+            .build()?;
+
+        let mut timestamps = Vec::new();
+        for msg in parser.by_ref() {
+            if let UlogMessage::LoggedData(data) = msg? {
+                // The timestamp field is repaired along with the message timestamp.
+                assert!(matches!(
+                    data.data.fields[0].value,
+                    crate::model::inst::FieldValue::ScalarU64(t) if t == data.timestamp
+                ));
+                timestamps.push(data.timestamp);
+            }
+        }
+        Ok((timestamps, parser.validation_report().clone()))
+    }
+
+    #[test]
+    fn test_repair_timestamps() -> Result<(), ULogError> {
+        // A late sample, logged after the sample at 1.4s.
+        let bytes = LogBuilder::new()
+            .topic(Topic::new("a").field("x", BaseType::UINT32, Generator::Constant(1.0)))
+            .garbage_at(Duration::from_millis(450), &sample(1_100_000))
+            .build()?;
+
+        let (clamped, report) = timestamps(&bytes, TimestampRepair::ClampMonotonic)?;
+        assert_eq!(clamped[3..7], [1_300_000, 1_400_000, 1_400_000, 1_500_000]);
+        assert_eq!(
+            report.timestamp_adjustments,
+            [TimestampAdjustment {
+                message_name: "a".to_string(),
+                multi_id: 0,
+                original: 1_100_000,
+                adjusted: 1_400_000,
+            }]
+        );
+
+        // Later timestamps continue from the previous one.
+        let (offset, report) = timestamps(&bytes, TimestampRepair::OffsetOnWrap)?;
+        assert_eq!(offset[3..7], [1_300_000, 1_400_000, 1_400_000, 1_800_000]);
+        assert_eq!(*offset.last().unwrap(), 2_200_000);
+        assert_eq!(report.timestamp_adjustments.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resync_on_error() -> Result<(), ULogError> {
+        // Data for an unknown msg_id, two good messages, and a SYNC message.
+        let corrupt = [6, 0, b'D', 0xEE, 0xEE, 1, 2, 3, 4];
+        let mut sync = vec![8, 0, b'S'];
+        sync.extend_from_slice(&crate::model::SYNC_MAGIC);
+        let bytes = LogBuilder::new()
+            .topic(Topic::new("a").field("x", BaseType::UINT32, Generator::Constant(1.0)))
+            .garbage_at(Duration::from_millis(450), &corrupt)
+            .garbage_at(Duration::from_millis(650), &sync)
+            .build()?;
+        let find = |pattern: &[u8]| bytes.windows(pattern.len()).position(|w| w == pattern).unwrap() as u64;
+
+        let mut parser = ULogParserBuilder::new(bytes.as_slice()).build()?;
+        let messages: Vec<_> = parser.by_ref().collect();
+        assert_eq!(messages.iter().filter(|m| m.is_err()).count(), 1);
+        assert_eq!(topics(&messages).len(), 10);
+        assert_eq!(parser.validation_report().sync.count, 1);
+
+        let mut parser = ULogParserBuilder::new(bytes.as_slice())
+            .resync_on_error(true)
+            .build()?;
+        let messages: Vec<_> = parser.by_ref().collect();
+        assert!(messages.iter().all(Result::is_ok));
+        assert_eq!(topics(&messages).len(), 8);
+
+        let report = parser.validation_report();
+        assert_eq!(report.resync_regions.len(), 1);
+        let region = &report.resync_regions[0];
+        assert_eq!((region.start, region.end), (find(&corrupt), find(&sync)));
+        assert!(region.error.contains("unknown msg_id"));
+        assert_eq!(report.sync.count, 1);
+        assert_eq!(report.sync.first_offset, Some(find(&sync)));
+        assert!(!report.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resync_inside_corrupt_message() -> Result<(), ULogError> {
+        // Data for an unknown msg_id whose size also spans a SYNC message and the two data messages after it.
+        let mut sync = vec![8, 0, b'S'];
+        sync.extend_from_slice(&crate::model::SYNC_MAGIC);
+        let mut corrupt = vec![2 + 4 + 11 + 2 * 17, 0, b'D', 0xEE, 0xEE, 1, 2, 3, 4];
+        corrupt.extend_from_slice(&sync);
+        let bytes = LogBuilder::new()
+            .topic(Topic::new("a").field("x", BaseType::UINT32, Generator::Constant(1.0)))
+            .garbage_at(Duration::from_millis(450), &corrupt)
+            .build()?;
+        let start = bytes.windows(corrupt.len()).position(|w| w == corrupt).unwrap() as u64;
+
+        let mut parser = ULogParserBuilder::new(bytes.as_slice())
+            .resync_on_error(true)
+            .build()?;
+        let messages: Vec<_> = parser.by_ref().collect();
+        assert!(messages.iter().all(Result::is_ok));
+        // None of the data messages after the SYNC message is lost.
+        assert_eq!(topics(&messages).len(), 10);
+
+        let report = parser.validation_report();
+        assert_eq!(report.resync_regions.len(), 1);
+        let region = &report.resync_regions[0];
+        assert_eq!((region.start, region.end), (start, start + 9));
+        assert_eq!(report.sync.first_offset, Some(start + 9));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sequence_issues() -> Result<(), ULogError> {
+        // A late sample, then after a SYNC message, a repeated one.
+        let mut sync = vec![8, 0, b'S'];
+        sync.extend_from_slice(&crate::model::SYNC_MAGIC);
+        let bytes = LogBuilder::new()
+            .topic(Topic::new("a").field("x", BaseType::UINT32, Generator::Constant(1.0)))
+            .garbage_at(Duration::from_millis(450), &sample(1_100_000))
+            .garbage_at(Duration::from_millis(650), &sync)
+            .garbage_at(Duration::from_millis(850), &sample(1_800_000))
+            .build()?;
+        let find = |pattern: &[u8]| bytes.windows(pattern.len()).rposition(|w| w == pattern).unwrap() as u64;
+        let range = |pattern: &[u8]| find(pattern)..find(pattern) + pattern.len() as u64;
+
+        let mut parser = ULogParserBuilder::new(bytes.as_slice()).build()?;
+        parser.by_ref().for_each(drop);
+
+        let report = parser.validation_report();
+        assert_eq!(
+            report.sequence_issues,
+            [
+                SequenceIssue {
+                    message_name: "a".to_string(),
+                    multi_id: 0,
+                    interval_start: find(&sample(1_000_000)),
+                    range: range(&sample(1_100_000)),
+                    duplicates: 0,
+                    backwards: 1,
+                },
+                SequenceIssue {
+                    message_name: "a".to_string(),
+                    multi_id: 0,
+                    interval_start: find(&sync),
+                    range: range(&sample(1_800_000)),
+                    duplicates: 1,
+                    backwards: 0,
+                },
+            ]
+        );
+        assert!(!report.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_counts() -> Result<(), ULogError> {
+        let corrupt = [6, 0, b'D', 0xEE, 0xEE, 1, 2, 3, 4];
+        let mut sync = vec![8, 0, b'S'];
+        sync.extend_from_slice(&crate::model::SYNC_MAGIC);
+        let bytes = LogBuilder::new()
+            .topic(Topic::new("a").field("x", BaseType::UINT32, Generator::Constant(1.0)))
+            .topic(Topic::new("b").field("y", BaseType::UINT32, Generator::Constant(2.0)))
+            .garbage_at(Duration::from_millis(250), &[2, 0, b'Z', 1, 2])
+            .garbage_at(Duration::from_millis(350), &[2, 0, b'F', b'a', b':'])
+            .garbage_at(Duration::from_millis(450), &corrupt)
+            .garbage_at(Duration::from_millis(650), &sync)
+            .build()?;
+
+        let mut parser = ULogParserBuilder::new(bytes.as_slice())
+            .set_subscription_allow_list(["a".to_owned()])
+            .build()?;
+        parser.by_ref().for_each(drop);
+
+        let counts = &parser.validation_report().message_counts;
+        assert_eq!(counts.unhandled, BTreeMap::from([(b'F', 1)]));
+        assert_eq!(counts.ignored, 10);
+        assert_eq!(counts.unknown, BTreeMap::from([(b'Z', 1)]));
+        assert_eq!(counts.skipped_on_error, 1);
+        assert_eq!(counts.total(), 13);
+
+        // The corrupt message and the data up to the SYNC message are skipped as one.
+        let mut parser = ULogParserBuilder::new(bytes.as_slice())
+            .resync_on_error(true)
+            .build()?;
+        parser.by_ref().for_each(drop);
+        assert_eq!(parser.validation_report().message_counts.skipped_on_error, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict() -> Result<(), ULogError> {
+        let mut sync = vec![8, 0, b'S'];
+        sync.extend_from_slice(&crate::model::SYNC_MAGIC);
+        let bytes = LogBuilder::new()
+            .topic(Topic::new("a").field("x", BaseType::UINT32, Generator::Constant(1.0)))
+            .garbage_at(Duration::from_millis(250), &[2, 0, b'Z', 1, 2])
+            .garbage_at(Duration::from_millis(350), &[2, 0, b'F', b'a', b':'])
+            .garbage_at(Duration::from_millis(650), &sync)
+            .build()?;
+
+        let messages: Vec<_> = ULogParserBuilder::new(bytes.as_slice()).build()?.collect();
+        assert!(messages.iter().all(Result::is_ok));
+
+        let messages: Vec<_> = ULogParserBuilder::new(bytes.as_slice())
+            .strict(true)
+            .build()?
+            .collect();
+        let errors: Vec<_> = messages
+            .iter()
+            .filter_map(|m| m.as_ref().err().map(ToString::to_string))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                "Uninterpreted message: Unknown message type 0x5A in the data section.",
+                "Uninterpreted message: Unhandled message of type 'F' in the data section.",
+            ]
+        );
+        assert_eq!(topics(&messages).len(), 10);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_timestamp_wrap() {
+        let mut clock = TimestampClock::default();
+        let repaired: Vec<_> = [u64::from(u32::MAX) - 100, u64::from(u32::MAX) - 10, 40, 140]
+            .into_iter()
+            .map(|t| clock.repair(TimestampRepair::OffsetOnWrap, t))
+            .collect();
+
+        let wrap = 1 << 32;
+        assert_eq!(
+            repaired,
+            [
+                (wrap - 101, false),
+                (wrap - 11, false),
+                (wrap + 40, true),
+                (wrap + 140, false)
+            ]
+        );
+    }
+}
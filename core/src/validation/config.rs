@@ -0,0 +1,306 @@
+//! Checks the configuration recorded in a log, i.e. its parameters and info messages, against a specification,
+//! e.g. to gate the upload of logs from a fleet on the expected firmware and tuning.
+
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use regex::Regex;
+
+use crate::errors::ULogError;
+use crate::model::inst::{FieldValue, ParameterValue};
+use crate::model::msg::UlogMessage;
+use crate::model::CCharSlice;
+
+/// The parameter values and info messages required of a log.
+///
+/// ```rust
+/// use yule_log::builder::ULogParserBuilder;
+/// use yule_log::validation::{check_config, ConfigSpec};
+///
+/// # fn main() -> Result<(), yule_log::errors::ULogError> {
+/// let spec = ConfigSpec::new()
+///     .parameter_range("MPC_XY_VEL_MAX", 1.0..=12.0)
+///     .info_matches("ver_sw_release", r"^\d+$")?;
+///
+/// // No logged data is needed, so none is decoded.
+/// let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+/// let parser = ULogParserBuilder::new(input.as_slice())
+///     .set_subscription_allow_list(Vec::<String>::new())
+///     .build()?;
+///
+/// for violation in check_config(parser, &spec)? {
+///     println!("{violation}");
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ConfigSpec {
+    parameters: Vec<(String, RangeInclusive<f64>)>,
+    info: Vec<(String, Option<Regex>)>,
+}
+
+impl ConfigSpec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requires the parameter `name` to be set to `value`.
+    #[must_use]
+    pub fn parameter_equals(self, name: impl Into<String>, value: f64) -> Self {
+        self.parameter_range(name, value..=value)
+    }
+
+    /// Requires the value of the parameter `name` to lie within `range`.
+    #[must_use]
+    pub fn parameter_range(mut self, name: impl Into<String>, range: RangeInclusive<f64>) -> Self {
+        self.parameters.push((name.into(), range));
+        self
+    }
+
+    /// Requires an info message with the key `key`.
+    #[must_use]
+    pub fn info_key(mut self, key: impl Into<String>) -> Self {
+        self.info.push((key.into(), None));
+        self
+    }
+
+    /// Requires an info message with the key `key`, whose value matches the regular expression `pattern`,
+    /// e.g. `r"^v1\.14\."` for the `ver_sw_release` of a firmware version.  Char array values are matched
+    /// as strings, and other values by their display form.
+    pub fn info_matches(
+        mut self,
+        key: impl Into<String>,
+        pattern: &str,
+    ) -> Result<Self, ULogError> {
+        let regex = Regex::new(pattern).map_err(|e| {
+            ULogError::InvalidConfiguration(format!("Invalid info pattern `{pattern}`: {e}"))
+        })?;
+        self.info.push((key.into(), Some(regex)));
+        Ok(self)
+    }
+}
+
+/// A difference between the configuration recorded in a log and a `ConfigSpec`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigViolation {
+    MissingParameter {
+        name: String,
+    },
+
+    /// The parameter was set, initially or in flight, to a value outside the expected range.
+    ParameterOutOfRange {
+        name: String,
+        value: f64,
+        expected: RangeInclusive<f64>,
+    },
+
+    MissingInfo {
+        key: String,
+    },
+
+    InfoMismatch {
+        key: String,
+        value: String,
+        pattern: String,
+    },
+}
+
+impl fmt::Display for ConfigViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigViolation::MissingParameter { name } => write!(f, "parameter {name} is not set"),
+            ConfigViolation::ParameterOutOfRange {
+                name,
+                value,
+                expected,
+            } if expected.start() == expected.end() => {
+                write!(
+                    f,
+                    "parameter {name} is {value}, expected {}",
+                    expected.start()
+                )
+            }
+            ConfigViolation::ParameterOutOfRange {
+                name,
+                value,
+                expected,
+            } => write!(
+                f,
+                "parameter {name} is {value}, expected {} to {}",
+                expected.start(),
+                expected.end()
+            ),
+            ConfigViolation::MissingInfo { key } => write!(f, "info {key} is missing"),
+            ConfigViolation::InfoMismatch {
+                key,
+                value,
+                pattern,
+            } => write!(
+                f,
+                "info {key} is {value:?}, which doesn't match `{pattern}`"
+            ),
+        }
+    }
+}
+
+/// Reads a log and returns the differences between its configuration and `spec`, in the order found.
+/// Missing parameters and info messages are reported last, in the order of `spec`.
+///
+/// Every value of a parameter is checked, including changes in flight.
+pub fn check_config<I>(messages: I, spec: &ConfigSpec) -> Result<Vec<ConfigViolation>, ULogError>
+where
+    I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+{
+    let mut violations = Vec::new();
+    let mut parameters_seen = vec![false; spec.parameters.len()];
+    let mut info_seen = vec![false; spec.info.len()];
+
+    for msg in messages {
+        match msg? {
+            UlogMessage::Parameter(param) => {
+                let value = match param.value {
+                    ParameterValue::INT32(v) => f64::from(v),
+                    ParameterValue::FLOAT(v) => f64::from(v),
+                };
+
+                for (i, (name, range)) in spec.parameters.iter().enumerate() {
+                    if *name == param.key {
+                        parameters_seen[i] = true;
+                        if !range.contains(&value) {
+                            violations.push(ConfigViolation::ParameterOutOfRange {
+                                name: name.clone(),
+                                value,
+                                expected: range.clone(),
+                            });
+                        }
+                    }
+                }
+            }
+            UlogMessage::Info(info) => {
+                for (i, (key, pattern)) in spec.info.iter().enumerate() {
+                    if *key != info.key {
+                        continue;
+                    }
+                    info_seen[i] = true;
+
+                    if let Some(pattern) = pattern {
+                        let value = match &info.value {
+                            FieldValue::ArrayChar(chars) => chars.to_string_lossy_trimmed(),
+                            value => value.to_string(),
+                        };
+                        if !pattern.is_match(&value) {
+                            violations.push(ConfigViolation::InfoMismatch {
+                                key: key.clone(),
+                                value,
+                                pattern: pattern.to_string(),
+                            });
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let missing_parameters = spec.parameters.iter().zip(parameters_seen);
+    violations.extend(
+        missing_parameters
+            .filter(|(_, seen)| !seen)
+            .map(|((name, _), _)| ConfigViolation::MissingParameter { name: name.clone() }),
+    );
+    let missing_info = spec.info.iter().zip(info_seen);
+    violations.extend(
+        missing_info
+            .filter(|(_, seen)| !seen)
+            .map(|((key, _), _)| ConfigViolation::MissingInfo { key: key.clone() }),
+    );
+
+    Ok(violations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::testkit::LogBuilder;
+
+    fn violations(spec: &ConfigSpec) -> Result<Vec<ConfigViolation>, ULogError> {
+        let bytes = LogBuilder::new()
+            .info("ver_sw_release", "v1.14.3")
+            .info("sys_name", "PX4")
+            .parameter_i32("MAV_SYS_ID", 1)
+            .parameter_f32("MPC_XY_VEL_MAX", 12.0)
+            .build()?;
+
+        check_config(ULogParserBuilder::new(bytes.as_slice()).build()?, spec)
+    }
+
+    #[test]
+    fn test_config_matches() -> Result<(), ULogError> {
+        let spec = ConfigSpec::new()
+            .parameter_equals("MAV_SYS_ID", 1.0)
+            .parameter_range("MPC_XY_VEL_MAX", 1.0..=12.0)
+            .info_key("sys_name")
+            .info_matches("ver_sw_release", r"^v1\.14\.")?;
+
+        assert_eq!(violations(&spec)?, []);
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_violations() -> Result<(), ULogError> {
+        let spec = ConfigSpec::new()
+            .parameter_equals("MAV_SYS_ID", 2.0)
+            .parameter_range("MPC_XY_VEL_MAX", 1.0..=10.0)
+            .parameter_equals("SDLOG_PROFILE", 1.0)
+            .info_key("ver_hw")
+            .info_matches("ver_sw_release", r"^v1\.15\.")?;
+
+        let violations = violations(&spec)?;
+        assert_eq!(
+            violations,
+            [
+                ConfigViolation::InfoMismatch {
+                    key: "ver_sw_release".to_string(),
+                    value: "v1.14.3".to_string(),
+                    pattern: r"^v1\.15\.".to_string(),
+                },
+                ConfigViolation::ParameterOutOfRange {
+                    name: "MAV_SYS_ID".to_string(),
+                    value: 1.0,
+                    expected: 2.0..=2.0,
+                },
+                ConfigViolation::ParameterOutOfRange {
+                    name: "MPC_XY_VEL_MAX".to_string(),
+                    value: 12.0,
+                    expected: 1.0..=10.0,
+                },
+                ConfigViolation::MissingParameter {
+                    name: "SDLOG_PROFILE".to_string()
+                },
+                ConfigViolation::MissingInfo {
+                    key: "ver_hw".to_string()
+                },
+            ]
+        );
+        assert_eq!(
+            violations[1].to_string(),
+            "parameter MAV_SYS_ID is 1, expected 2"
+        );
+        assert_eq!(
+            violations[2].to_string(),
+            "parameter MPC_XY_VEL_MAX is 12, expected 1 to 10"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        assert!(matches!(
+            ConfigSpec::new().info_matches("ver_sw_release", "("),
+            Err(ULogError::InvalidConfiguration(_))
+        ));
+    }
+}
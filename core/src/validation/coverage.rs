@@ -0,0 +1,334 @@
+//! Checks that a log contains the topics a logger was expected to capture, at the expected rates.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::errors::ULogError;
+use crate::model::msg::UlogMessage;
+
+/// The topics a log is expected to contain, and their minimum rates.
+///
+/// ```rust
+/// use yule_log::builder::ULogParserBuilder;
+/// use yule_log::validation::{check_coverage, LoggingProfile};
+///
+/// # fn main() -> Result<(), yule_log::errors::ULogError> {
+/// let profile = LoggingProfile::new()
+///     .topic("vehicle_local_position", 5.0)
+///     .topic("actuator_outputs", 5.0);
+///
+/// let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+/// let report = check_coverage(ULogParserBuilder::new(input.as_slice()).build()?, &profile)?;
+///
+/// for issue in &report.issues {
+///     println!("{issue}");
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct LoggingProfile {
+    topics: Vec<(String, u8, f64)>,
+    gap_factor: f64,
+}
+
+impl Default for LoggingProfile {
+    fn default() -> Self {
+        LoggingProfile {
+            topics: Vec::new(),
+            gap_factor: 5.0,
+        }
+    }
+}
+
+impl LoggingProfile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expects the first instance of `topic`, at `min_rate_hz` or more.
+    #[must_use]
+    pub fn topic(self, topic: impl Into<String>, min_rate_hz: f64) -> Self {
+        self.topic_instance(topic, 0, min_rate_hz)
+    }
+
+    /// Expects the `multi_id` instance of `topic`, at `min_rate_hz` or more.
+    #[must_use]
+    pub fn topic_instance(
+        mut self,
+        topic: impl Into<String>,
+        multi_id: u8,
+        min_rate_hz: f64,
+    ) -> Self {
+        self.topics.push((topic.into(), multi_id, min_rate_hz));
+        self
+    }
+
+    /// Reports an interval between two samples of an expected topic as a gap if it is longer than `factor`
+    /// periods at the topic's minimum rate.  Defaults to `5.0`.
+    #[must_use]
+    pub fn gap_factor(mut self, factor: f64) -> Self {
+        self.gap_factor = factor;
+        self
+    }
+}
+
+/// The samples of a topic instance found in a log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopicCoverage {
+    pub topic: String,
+    pub multi_id: u8,
+    pub count: u64,
+    pub first_timestamp: u64,
+    pub last_timestamp: u64,
+}
+
+impl TopicCoverage {
+    /// The mean rate of the samples, or `None` if there are fewer than two.
+    pub fn rate_hz(&self) -> Option<f64> {
+        let duration = self.last_timestamp.checked_sub(self.first_timestamp)?;
+        if self.count < 2 || duration == 0 {
+            return None;
+        }
+        Some((self.count - 1) as f64 * 1e6 / duration as f64)
+    }
+}
+
+/// A difference between a log and a `LoggingProfile`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CoverageIssue {
+    MissingTopic {
+        topic: String,
+        multi_id: u8,
+    },
+
+    /// The mean rate of the topic is below the profile's minimum.  A topic with a single sample has a rate of 0.
+    RateTooLow {
+        topic: String,
+        multi_id: u8,
+        rate_hz: f64,
+        min_rate_hz: f64,
+    },
+
+    /// No samples of the topic were logged between the timestamps `start` and `end`.
+    Gap {
+        topic: String,
+        multi_id: u8,
+        start: u64,
+        end: u64,
+    },
+}
+
+impl fmt::Display for CoverageIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CoverageIssue::MissingTopic { topic, multi_id } => {
+                write!(f, "{topic}.{multi_id:02} is missing")
+            }
+            CoverageIssue::RateTooLow {
+                topic,
+                multi_id,
+                rate_hz,
+                min_rate_hz,
+            } => write!(
+                f,
+                "{topic}.{multi_id:02} is logged at {rate_hz:.1} Hz, expected at least {min_rate_hz:.1} Hz"
+            ),
+            CoverageIssue::Gap {
+                topic,
+                multi_id,
+                start,
+                end,
+            } => write!(
+                f,
+                "{topic}.{multi_id:02} has a gap of {:.3} s at {:.3} s",
+                (end - start) as f64 / 1e6,
+                *start as f64 / 1e6
+            ),
+        }
+    }
+}
+
+/// The topics found in a log, and the differences from a `LoggingProfile`.
+#[derive(Debug, Clone, Default)]
+pub struct CoverageReport {
+    /// Every topic instance found, ordered by topic and multi_id.
+    pub topics: Vec<TopicCoverage>,
+    /// The issues of each expected topic, in the order of the profile.  Gaps are reported in order of time.
+    pub issues: Vec<CoverageIssue>,
+}
+
+impl CoverageReport {
+    pub fn topic(&self, topic: &str, multi_id: u8) -> Option<&TopicCoverage> {
+        self.topics
+            .iter()
+            .find(|t| t.topic == topic && t.multi_id == multi_id)
+    }
+}
+
+/// Reads a log, counting the samples of every topic instance, and checks the expected topics of `profile`.
+pub fn check_coverage<I>(messages: I, profile: &LoggingProfile) -> Result<CoverageReport, ULogError>
+where
+    I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+{
+    let mut topics: BTreeMap<(String, u8), TopicCoverage> = BTreeMap::new();
+    let mut gaps: Vec<Vec<CoverageIssue>> = vec![Vec::new(); profile.topics.len()];
+
+    for msg in messages {
+        let UlogMessage::LoggedData(data) = msg? else {
+            continue;
+        };
+        let multi_id = data.data.multi_id_index.unwrap_or(0);
+        let timestamp = data.timestamp;
+
+        let key = (data.data.name.to_string(), multi_id);
+        let Some(coverage) = topics.get_mut(&key) else {
+            topics.insert(
+                key.clone(),
+                TopicCoverage {
+                    topic: key.0,
+                    multi_id,
+                    count: 1,
+                    first_timestamp: timestamp,
+                    last_timestamp: timestamp,
+                },
+            );
+            continue;
+        };
+
+        for (i, (topic, expected_multi_id, min_rate_hz)) in profile.topics.iter().enumerate() {
+            if *topic != coverage.topic || *expected_multi_id != multi_id {
+                continue;
+            }
+            let max_gap = profile.gap_factor * 1e6 / min_rate_hz;
+            if timestamp.saturating_sub(coverage.last_timestamp) as f64 > max_gap {
+                gaps[i].push(CoverageIssue::Gap {
+                    topic: topic.clone(),
+                    multi_id,
+                    start: coverage.last_timestamp,
+                    end: timestamp,
+                });
+            }
+        }
+
+        coverage.count += 1;
+        coverage.first_timestamp = coverage.first_timestamp.min(timestamp);
+        coverage.last_timestamp = coverage.last_timestamp.max(timestamp);
+    }
+
+    let mut issues = Vec::new();
+    for ((topic, multi_id, min_rate_hz), gaps) in profile.topics.iter().zip(gaps) {
+        let Some(coverage) = topics.get(&(topic.clone(), *multi_id)) else {
+            issues.push(CoverageIssue::MissingTopic {
+                topic: topic.clone(),
+                multi_id: *multi_id,
+            });
+            continue;
+        };
+
+        let rate_hz = coverage.rate_hz().unwrap_or(0.0);
+        if rate_hz < *min_rate_hz {
+            issues.push(CoverageIssue::RateTooLow {
+                topic: topic.clone(),
+                multi_id: *multi_id,
+                rate_hz,
+                min_rate_hz: *min_rate_hz,
+            });
+        }
+        issues.extend(gaps);
+    }
+
+    Ok(CoverageReport {
+        topics: topics.into_values().collect(),
+        issues,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::model::def::BaseType;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    fn report(profile: &LoggingProfile) -> Result<CoverageReport, ULogError> {
+        let bytes = LogBuilder::new()
+            .duration(Duration::from_secs(3))
+            .topic(Topic::new("a").rate_hz(10.0).field(
+                "x",
+                BaseType::UINT8,
+                Generator::Constant(1.0),
+            ))
+            .topic(Topic::new("a").multi_id(1).rate_hz(1.0).field(
+                "x",
+                BaseType::UINT8,
+                Generator::Constant(2.0),
+            ))
+            .build()?;
+
+        check_coverage(ULogParserBuilder::new(bytes.as_slice()).build()?, profile)
+    }
+
+    #[test]
+    fn test_coverage() -> Result<(), ULogError> {
+        let report = report(
+            &LoggingProfile::new()
+                .topic("a", 10.0)
+                .topic_instance("a", 1, 1.0),
+        )?;
+        assert_eq!(report.issues, []);
+
+        assert_eq!(report.topics.len(), 2);
+        let a = report.topic("a", 0).unwrap();
+        assert_eq!(a.count, 30);
+        assert!((a.rate_hz().unwrap() - 10.0).abs() < 1e-9);
+        assert_eq!(report.topic("a", 1).unwrap().count, 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_coverage_issues() -> Result<(), ULogError> {
+        let profile = LoggingProfile::new()
+            .topic("b", 1.0)
+            .topic_instance("a", 1, 4.0)
+            .gap_factor(3.0);
+        let report = report(&profile)?;
+
+        let gap = |start, end| CoverageIssue::Gap {
+            topic: "a".to_string(),
+            multi_id: 1,
+            start,
+            end,
+        };
+        assert_eq!(
+            report.issues,
+            [
+                CoverageIssue::MissingTopic {
+                    topic: "b".to_string(),
+                    multi_id: 0
+                },
+                CoverageIssue::RateTooLow {
+                    topic: "a".to_string(),
+                    multi_id: 1,
+                    rate_hz: 1.0,
+                    min_rate_hz: 4.0
+                },
+                gap(1_000_000, 2_000_000),
+                gap(2_000_000, 3_000_000),
+            ]
+        );
+        assert_eq!(
+            report.issues[1].to_string(),
+            "a.01 is logged at 1.0 Hz, expected at least 4.0 Hz"
+        );
+        assert_eq!(
+            report.issues[2].to_string(),
+            "a.01 has a gap of 1.000 s at 1.000 s"
+        );
+
+        Ok(())
+    }
+}
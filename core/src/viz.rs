@@ -0,0 +1,318 @@
+//! Plot data for log viewers.
+//!
+//! A `Timeseries` holds the samples of the fields a viewer plots, read in one pass.  `line_series()` returns a field's
+//! samples as `(seconds, value)` points, which `plotters::series::LineSeries::new()` takes as they are, and which
+//! `egui_plot::PlotPoints` takes once mapped to `[x, y]`.  A `Downsampled` series keeps min/max summaries of its
+//! samples at halving resolutions, so that a viewer can redraw any span of a long log with a bounded number of points,
+//! e.g. a couple per pixel, as it pans and zooms.
+//!
+//! ```rust
+//! use yule_log::builder::ULogParserBuilder;
+//! use yule_log::viz::{self, Downsampled, Timeseries};
+//!
+//! # fn main() -> Result<(), yule_log::errors::ULogError> {
+//! let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+//! let parser = ULogParserBuilder::new(input.as_slice()).build()?;
+//! let timeseries = Timeseries::collect(parser, &["vehicle_local_position/x", "vehicle_local_position/y"])?;
+//!
+//! let x = viz::line_series("vehicle_local_position/x", &timeseries)?;
+//! println!("{} points", x.len());
+//!
+//! // At most 800 points, whatever the span.
+//! let y = Downsampled::new(timeseries.get("vehicle_local_position/y").unwrap());
+//! let points = y.points(0, u64::MAX, 800);
+//! assert!(points.len() <= 800);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::errors::ULogError;
+use crate::model::msg::UlogMessage;
+use crate::timeseries::{FieldLookup, FieldPath};
+
+const MICROS_PER_SECOND: f64 = 1_000_000.0;
+
+/// The samples of one field.  NaN values are left out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series {
+    pub path: FieldPath,
+    /// The timestamps of the samples, in microseconds.
+    pub timestamps: Vec<u64>,
+    pub values: Vec<f64>,
+}
+
+impl Series {
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// The samples as `(seconds, value)` points.
+    pub fn points(&self) -> Vec<(f64, f64)> {
+        self.timestamps
+            .iter()
+            .zip(&self.values)
+            .map(|(&timestamp, &value)| (seconds(timestamp), value))
+            .collect()
+    }
+}
+
+/// The samples of a set of fields, in the order of their paths.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timeseries {
+    series: Vec<Series>,
+}
+
+impl Timeseries {
+    /// Reads the samples of `field_paths` from a log, in a single pass.  See `timeseries::FieldPath` for the form of
+    /// the paths.
+    ///
+    /// Returns `ULogError::InvalidFieldName` if a path is malformed, or names a column its topic doesn't have.
+    pub fn collect<I>(messages: I, field_paths: &[&str]) -> Result<Self, ULogError>
+    where
+        I: IntoIterator<Item = Result<UlogMessage, ULogError>>,
+    {
+        let paths = field_paths
+            .iter()
+            .map(|path| path.parse())
+            .collect::<Result<Vec<FieldPath>, _>>()?;
+        let mut series: Vec<Series> = paths
+            .iter()
+            .map(|path| Series {
+                path: path.clone(),
+                timestamps: Vec::new(),
+                values: Vec::new(),
+            })
+            .collect();
+
+        let mut lookup = FieldLookup::new(paths);
+        for msg in messages {
+            let UlogMessage::LoggedData(data) = msg? else {
+                continue;
+            };
+            lookup.for_each(&data, |i, value| {
+                if !value.is_nan() {
+                    series[i].timestamps.push(data.timestamp);
+                    series[i].values.push(value);
+                }
+            })?;
+        }
+
+        Ok(Timeseries { series })
+    }
+
+    /// The series of `field_path`, if it was collected.
+    pub fn get(&self, field_path: &str) -> Option<&Series> {
+        let path: FieldPath = field_path.parse().ok()?;
+        self.series.iter().find(|series| series.path == path)
+    }
+
+    pub fn series(&self) -> &[Series] {
+        &self.series
+    }
+}
+
+/// The samples of `field_path` as `(seconds, value)` points, for a line plot.
+///
+/// Returns `ULogError::InvalidFieldName` if the field wasn't collected.
+pub fn line_series(
+    field_path: &str,
+    timeseries: &Timeseries,
+) -> Result<Vec<(f64, f64)>, ULogError> {
+    timeseries
+        .get(field_path)
+        .map(Series::points)
+        .ok_or_else(|| ULogError::InvalidFieldName(format!("`{field_path}` wasn't collected.")))
+}
+
+/// The smallest and largest samples of a run of consecutive samples, with their timestamps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Bucket {
+    min: (u64, f64),
+    max: (u64, f64),
+}
+
+impl Bucket {
+    fn merge(self, other: Bucket) -> Bucket {
+        Bucket {
+            min: if other.min.1 < self.min.1 {
+                other.min
+            } else {
+                self.min
+            },
+            max: if other.max.1 > self.max.1 {
+                other.max
+            } else {
+                self.max
+            },
+        }
+    }
+}
+
+/// A series, with min/max summaries of its samples in runs of 2, 4, 8 and so on, up to the whole series.  The
+/// summaries take about as much memory as the samples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Downsampled {
+    timestamps: Vec<u64>,
+    values: Vec<f64>,
+    // levels[k] summarizes runs of 2^(k + 1) samples.
+    levels: Vec<Vec<Bucket>>,
+}
+
+impl Downsampled {
+    pub fn new(series: &Series) -> Self {
+        let mut levels: Vec<Vec<Bucket>> = Vec::new();
+        let samples: Vec<Bucket> = series
+            .timestamps
+            .iter()
+            .zip(&series.values)
+            .map(|(&timestamp, &value)| Bucket {
+                min: (timestamp, value),
+                max: (timestamp, value),
+            })
+            .collect();
+
+        let mut below = &samples;
+        while below.len() > 1 {
+            let level = below
+                .chunks(2)
+                .map(|pair| pair.iter().copied().reduce(Bucket::merge).unwrap())
+                .collect();
+            levels.push(level);
+            below = levels.last().unwrap();
+        }
+
+        Downsampled {
+            timestamps: series.timestamps.clone(),
+            values: series.values.clone(),
+            levels,
+        }
+    }
+
+    /// The number of samples.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns at most `max_points` `(seconds, value)` points tracing the samples from `start` to `end`, in
+    /// microseconds.  When there are more samples than that, the smallest and largest of each run of samples are
+    /// returned, in order of time, so that peaks still show.  The runs at either end may reach a little outside the
+    /// span.
+    ///
+    /// Timestamps are expected in order, as `Timeseries::collect()` reads them.
+    pub fn points(&self, start: u64, end: u64, max_points: usize) -> Vec<(f64, f64)> {
+        let first = self.timestamps.partition_point(|&t| t < start);
+        let last = self.timestamps.partition_point(|&t| t <= end);
+        if first >= last {
+            return Vec::new();
+        }
+
+        if last - first <= max_points {
+            return (first..last)
+                .map(|i| (seconds(self.timestamps[i]), self.values[i]))
+                .collect();
+        }
+
+        // The finest level with runs few enough for two points each.
+        let Some((run, level)) = self
+            .levels
+            .iter()
+            .enumerate()
+            .map(|(k, level)| (1 << (k + 1), level))
+            .find(|&(run, _)| 2 * ((last - 1) / run - first / run + 1) <= max_points)
+        else {
+            return Vec::new();
+        };
+
+        let mut points = Vec::with_capacity(max_points);
+        for bucket in &level[first / run..=(last - 1) / run] {
+            let (a, b) = if bucket.min.0 <= bucket.max.0 {
+                (bucket.min, bucket.max)
+            } else {
+                (bucket.max, bucket.min)
+            };
+            points.push((seconds(a.0), a.1));
+            if b != a {
+                points.push((seconds(b.0), b.1));
+            }
+        }
+        points
+    }
+}
+
+fn seconds(timestamp: u64) -> f64 {
+    timestamp as f64 / MICROS_PER_SECOND
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::model::def::BaseType;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    fn series(values: &[f64]) -> Series {
+        Series {
+            path: FieldPath::new("a", 0, "x"),
+            timestamps: (0..values.len() as u64).map(|i| i * 1_000_000).collect(),
+            values: values.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_line_series() -> Result<(), ULogError> {
+        let bytes = LogBuilder::new()
+            .topic(Topic::new("a").rate_hz(10.0).field(
+                "x",
+                BaseType::FLOAT,
+                Generator::Ramp {
+                    start: 0.0,
+                    step: 1.0,
+                },
+            ))
+            .build()?;
+        let parser = ULogParserBuilder::new(bytes.as_slice()).build()?;
+        let timeseries = Timeseries::collect(parser, &["a/x"])?;
+
+        let points = line_series("a/x", &timeseries)?;
+        assert_eq!(points.len(), 10);
+        assert_eq!(points[1].1 - points[0].1, 1.0);
+        assert!(points[0].0 < points[1].0);
+        assert!(matches!(
+            line_series("a/y", &timeseries),
+            Err(ULogError::InvalidFieldName(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_downsampled() {
+        let values: Vec<f64> = (0..100)
+            .map(|i| if i == 37 { 1000.0 } else { f64::from(i % 7) })
+            .collect();
+        let downsampled = Downsampled::new(&series(&values));
+        assert_eq!(downsampled.len(), 100);
+
+        // Few enough samples are returned as they are.
+        assert_eq!(
+            downsampled.points(10_000_000, 14_000_000, 5),
+            series(&values).points()[10..15]
+        );
+
+        // Otherwise the peak survives, in order of time.
+        let points = downsampled.points(0, u64::MAX, 20);
+        assert!(points.len() <= 20);
+        assert!(points.contains(&(37.0, 1000.0)));
+        assert!(points.windows(2).all(|w| w[0].0 < w[1].0));
+
+        assert!(downsampled.points(200_000_000, u64::MAX, 20).is_empty());
+        assert!(downsampled.points(0, u64::MAX, 1).is_empty());
+    }
+}
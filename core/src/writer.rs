@@ -0,0 +1,780 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::ops::Range;
+
+use crate::encode::Encode;
+use crate::errors::ULogError;
+use crate::formats::FormatRegistry;
+#[cfg(feature = "macros")]
+use crate::macro_utils::ULogEncode;
+use crate::model::def;
+use crate::model::inst::FieldValue;
+use crate::model::msg::{MultiInfo, Subscription, UlogMessage};
+use crate::parser::ULogMessageType;
+
+/// The largest message the writer can emit, excluding the 3 byte message header.
+/// The size of a message is stored in a u16.
+pub const MAX_MESSAGE_SIZE: usize = u16::MAX as usize;
+
+/// Writes a sequence of `UlogMessage`s as a ULOG file.
+///
+/// Messages are encoded as is, so it is up to the caller to emit them in a valid order,
+/// starting with the `Header`.  This makes it straightforward to re-encode the output of a `ULogParser`:
+///
+/// ```rust,no_run
+/// # use std::fs::File;
+/// # use std::io::BufReader;
+/// # use yule_log::builder::ULogParserBuilder;
+/// # use yule_log::writer::ULogWriter;
+/// # fn main() -> Result<(), yule_log::errors::ULogError> {
+/// let parser = ULogParserBuilder::new(BufReader::new(File::open("sample.ulg")?))
+///     .include_header(true)
+///     .include_timestamp(true)
+///     .include_padding(true)
+///     .emit_unhandled(true)
+///     .build()?;
+///
+/// let mut writer = ULogWriter::new(File::create("copy.ulg")?);
+///
+/// for msg in parser {
+///     writer.write_message(&msg?)?;
+/// }
+///
+/// writer.into_inner()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Large messages
+///
+/// Messages larger than `MAX_MESSAGE_SIZE` are rejected with `ULogError::MessageTooLarge`, and nothing is written,
+/// except for `MultiInfo` messages with a primitive array value.  These are split into a message followed by
+/// continued messages, which readers concatenate.  Char arrays are split on UTF-8 character boundaries.
+///
+/// # Subscriptions
+///
+/// The writer keeps track of the subscriptions written, whether through `write_message()` or `add_subscription()`
+/// and its variants.  Like the PX4 logger, `add_subscription()` allocates msg_ids in increasing order starting
+/// from 0, and never reuses the msg_id of a removed subscription, so the same sequence of calls always
+/// produces the same file.
+///
+/// # Derived topics
+///
+/// With the `macros` feature, structs deriving `ULogData` can be written directly.  `add_topic()` writes the
+/// format of the struct, and of the structs nested within it, and `log()` writes a `LOGGED_DATA` message,
+/// subscribing to the topic on first use.
+pub struct ULogWriter<W: Write> {
+    writer: W,
+    subscriptions: BTreeMap<u16, Subscription>,
+    // The next msg_id to allocate.  A u32, so that exhaustion of the u16 range can be represented.
+    next_msg_id: u32,
+    // The formats written, by name, and whether the data section has started.
+    formats: HashMap<String, def::Format>,
+    in_data_section: bool,
+}
+
+impl<W: Write> ULogWriter<W> {
+    pub fn new(writer: W) -> Self {
+        ULogWriter {
+            writer,
+            subscriptions: BTreeMap::new(),
+            next_msg_id: 0,
+            formats: HashMap::new(),
+            in_data_section: false,
+        }
+    }
+
+    pub fn write_message(&mut self, msg: &UlogMessage) -> Result<(), ULogError> {
+        if let UlogMessage::Header(_) = msg {
+            msg.encode(&mut self.writer)?;
+            return Ok(());
+        }
+
+        let mut content = Vec::new();
+        msg.encode_content(&mut content)?;
+
+        match msg {
+            UlogMessage::MultiInfo(info) if content.len() > MAX_MESSAGE_SIZE => {
+                for part in split_multi_info(info, content.len())? {
+                    self.write_message(&UlogMessage::MultiInfo(part))?;
+                }
+            }
+            _ => self.write_frame(msg.message_type(), &content)?,
+        }
+
+        // Written subscriptions are tracked as the parser would, replacing any duplicate msg_id.
+        match msg {
+            UlogMessage::FormatDefinition(format) => {
+                self.formats.insert(format.name.to_string(), format.clone());
+            }
+            UlogMessage::AddSubscription(sub) => {
+                self.in_data_section = true;
+                self.track_subscription(sub.clone());
+            }
+            UlogMessage::LoggedData(_) => self.in_data_section = true,
+            _ => {
+                if let Some(msg_id) = msg.removed_subscription() {
+                    self.subscriptions.remove(&msg_id);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the format of `T`, preceded by the formats of the structs nested within it.
+    /// Formats which have already been written are skipped.
+    ///
+    /// Returns `ULogError::InvalidConfiguration` if a different format with the same name has been written,
+    /// e.g. for a struct without a `timestamp` field which is both nested and logged as a topic.  Formats must
+    /// be written before the data section, so this is also an error once a subscription or `LOGGED_DATA`
+    /// message has been written.
+    #[cfg(feature = "macros")]
+    pub fn add_topic<T: ULogEncode>(&mut self) -> Result<(), ULogError> {
+        let mut formats = Vec::new();
+        T::ulog_nested_formats(&mut formats)?;
+        formats.push(T::ulog_format(true)?);
+
+        for format in formats {
+            self.add_format(format)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the formats of `registry`, each after the formats nested in it.  See `FormatRegistry::resolve()`.
+    ///
+    /// Returns `ULogError::UnresolvedFormat` if a nested format is missing from `registry`, or formats contain
+    /// themselves, in which case nothing is written.  Formats which have already been written are skipped, and
+    /// otherwise fail as for `add_topic()`.
+    pub fn add_formats(&mut self, registry: &FormatRegistry) -> Result<(), ULogError> {
+        for format in registry.resolve()? {
+            self.add_format(format.clone())?;
+        }
+
+        Ok(())
+    }
+
+    // Writes `format` unless it has already been written.
+    fn add_format(&mut self, format: def::Format) -> Result<(), ULogError> {
+        match self.formats.get(format.name.as_ref()) {
+            Some(written) if *written == format => return Ok(()),
+            Some(_) => {
+                return Err(ULogError::InvalidConfiguration(format!(
+                    "A different format named `{}` has already been written.",
+                    format.name
+                )));
+            }
+            None => {}
+        }
+        if self.in_data_section {
+            return Err(ULogError::InvalidConfiguration(format!(
+                "The format `{}` can't be written after the first subscription.",
+                format.name
+            )));
+        }
+        self.write_message(&UlogMessage::FormatDefinition(format))
+    }
+
+    /// Writes `value` as a `LOGGED_DATA` message of its topic, with the given timestamp, which replaces
+    /// the value of any `timestamp` field of `value`.
+    ///
+    /// The topic is subscribed on first use, with the next msg_id.  Its format must have been written
+    /// with `add_topic()`, otherwise `ULogError::InvalidConfiguration` is returned.
+    #[cfg(feature = "macros")]
+    pub fn log<T: ULogEncode>(&mut self, value: &T, timestamp: u64) -> Result<(), ULogError> {
+        let msg_id = match self.msg_id(T::FORMAT_NAME, T::MULTI_ID) {
+            Some(msg_id) => msg_id,
+            None => {
+                if self.formats.get(T::FORMAT_NAME) != Some(&T::ulog_format(true)?) {
+                    return Err(ULogError::InvalidConfiguration(format!(
+                        "The format `{}` has not been written as a topic.  Call add_topic() first.",
+                        T::FORMAT_NAME
+                    )));
+                }
+                self.add_subscription_instance(T::FORMAT_NAME, T::MULTI_ID)?
+                    .msg_id
+            }
+        };
+
+        let mut content = msg_id.to_le_bytes().to_vec();
+        value.encode_fields(Some(timestamp), &mut content);
+        self.write_frame(ULogMessageType::DATA, &content)?;
+        self.in_data_section = true;
+
+        Ok(())
+    }
+
+    /// Subscribes to the first free instance of `message_name`, with the next msg_id,
+    /// and writes the `ADD_SUBSCRIPTION` message.
+    ///
+    /// The format of `message_name` must already have been written.
+    pub fn add_subscription(&mut self, message_name: &str) -> Result<Subscription, ULogError> {
+        let multi_id = (0..=u8::MAX)
+            .find(|multi_id| self.msg_id(message_name, *multi_id).is_none())
+            .ok_or_else(|| {
+                ULogError::InvalidConfiguration(format!(
+                    "All instances of `{message_name}` are already subscribed."
+                ))
+            })?;
+
+        self.add_subscription_instance(message_name, multi_id)
+    }
+
+    /// Subscribes to the `multi_id` instance of `message_name`, with the next msg_id.
+    pub fn add_subscription_instance(
+        &mut self,
+        message_name: &str,
+        multi_id: u8,
+    ) -> Result<Subscription, ULogError> {
+        let msg_id = u16::try_from(self.next_msg_id).map_err(|_| {
+            ULogError::InvalidConfiguration("All msg_ids have been allocated.".to_string())
+        })?;
+
+        self.add_subscription_with_id(message_name, multi_id, msg_id)
+    }
+
+    /// Subscribes to the `multi_id` instance of `message_name` with an explicit `msg_id`.
+    ///
+    /// Returns `ULogError::DuplicateSubscription` if `msg_id` is used by a current subscription, and
+    /// `ULogError::InvalidConfiguration` if the instance is already subscribed.  Later allocated msg_ids
+    /// are greater than `msg_id`.
+    pub fn add_subscription_with_id(
+        &mut self,
+        message_name: &str,
+        multi_id: u8,
+        msg_id: u16,
+    ) -> Result<Subscription, ULogError> {
+        if self.subscriptions.contains_key(&msg_id) {
+            return Err(ULogError::DuplicateSubscription(msg_id));
+        }
+        if let Some(existing) = self.msg_id(message_name, multi_id) {
+            return Err(ULogError::InvalidConfiguration(format!(
+                "`{message_name}` multi_id {multi_id} is already subscribed with msg_id {existing}."
+            )));
+        }
+
+        let sub = Subscription {
+            multi_id,
+            msg_id,
+            message_name: message_name.to_owned(),
+        };
+        self.write_message(&UlogMessage::AddSubscription(sub.clone()))?;
+
+        Ok(sub)
+    }
+
+    /// Writes a `REMOVE_SUBSCRIPTION` message for `msg_id`.
+    pub fn remove_subscription(&mut self, msg_id: u16) -> Result<(), ULogError> {
+        if !self.subscriptions.contains_key(&msg_id) {
+            return Err(ULogError::UndefinedSubscription(msg_id));
+        }
+
+        self.write_message(&UlogMessage::Unhandled {
+            msg_type: ULogMessageType::REMOVE_SUBSCRIPTION.into(),
+            message_contents: msg_id.to_le_bytes().to_vec(),
+        })
+    }
+
+    /// Returns the msg_id of the current subscription to the `multi_id` instance of `message_name`.
+    pub fn msg_id(&self, message_name: &str, multi_id: u8) -> Option<u16> {
+        self.subscriptions
+            .values()
+            .find(|sub| sub.message_name == message_name && sub.multi_id == multi_id)
+            .map(|sub| sub.msg_id)
+    }
+
+    /// Returns the current subscriptions, ordered by msg_id.
+    pub fn subscriptions(&self) -> impl Iterator<Item = &Subscription> {
+        self.subscriptions.values()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Flushes the underlying writer and returns it.
+    pub fn into_inner(mut self) -> Result<W, ULogError> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    // Writes a message header followed by `content`.
+    fn write_frame(&mut self, msg_type: ULogMessageType, content: &[u8]) -> Result<(), ULogError> {
+        let size = u16::try_from(content.len()).map_err(|_| {
+            ULogError::MessageTooLarge(format!(
+                "{msg_type:?} message of {} bytes exceeds the maximum message size of {MAX_MESSAGE_SIZE} bytes",
+                content.len()
+            ))
+        })?;
+
+        self.writer.write_all(&size.to_le_bytes())?;
+        self.writer.write_all(&[u8::from(msg_type)])?;
+        self.writer.write_all(content)?;
+        Ok(())
+    }
+
+    fn track_subscription(&mut self, sub: Subscription) {
+        self.next_msg_id = self.next_msg_id.max(u32::from(sub.msg_id) + 1);
+        self.subscriptions.insert(sub.msg_id, sub);
+    }
+}
+
+// Splits a `MultiInfo` message whose encoded content is `content_len` bytes into continued messages
+// which each fit in `MAX_MESSAGE_SIZE`.
+fn split_multi_info(info: &MultiInfo, content_len: usize) -> Result<Vec<MultiInfo>, ULogError> {
+    let too_large = |reason: &str| {
+        ULogError::MessageTooLarge(format!(
+            "MultiInfo message `{}` of {content_len} bytes cannot be split, because {reason}",
+            info.key
+        ))
+    };
+
+    let (Some(element_size), Some(array_size)) = (
+        info.r#type.base_type.primitive_size(),
+        info.r#type.array_size,
+    ) else {
+        return Err(too_large("its value is not an array of a primitive type"));
+    };
+
+    // The key of each part is no longer than the key of the whole message, as its array size is smaller.
+    let key_size = content_len - array_size * element_size;
+    let elements_per_part = MAX_MESSAGE_SIZE.saturating_sub(key_size) / element_size;
+    if elements_per_part == 0 {
+        return Err(too_large("its key leaves no space for the value"));
+    }
+
+    let ranges = match &info.value {
+        FieldValue::ArrayChar(chars) => {
+            let bytes: Vec<u8> = chars.iter().map(|c| c.0).collect();
+            char_ranges(&bytes, elements_per_part)
+        }
+        _ => (0..array_size)
+            .step_by(elements_per_part)
+            .map(|start| start..(start + elements_per_part).min(array_size))
+            .collect(),
+    };
+
+    macro_rules! split {
+        ($($variant:ident),*) => {
+            match &info.value {
+                $(FieldValue::$variant(values) => ranges
+                    .iter()
+                    .map(|range| FieldValue::$variant(values[range.clone()].to_vec()))
+                    .collect::<Vec<_>>(),)*
+                _ => return Err(too_large("its value does not match its array type")),
+            }
+        };
+    }
+
+    let values = split!(
+        ArrayU8, ArrayU16, ArrayU32, ArrayU64, ArrayI8, ArrayI16, ArrayI32, ArrayI64, ArrayF32,
+        ArrayF64, ArrayBool, ArrayChar
+    );
+
+    Ok(ranges
+        .iter()
+        .zip(values)
+        .enumerate()
+        .map(|(index, (range, value))| {
+            let mut r#type = info.r#type.clone();
+            r#type.array_size = Some(range.len());
+            MultiInfo {
+                is_continued: info.is_continued || index > 0,
+                key: info.key.clone(),
+                r#type,
+                value,
+            }
+        })
+        .collect())
+}
+
+// Splits UTF-8 bytes into ranges of at most `max_len` bytes, without splitting a multi-byte character.
+fn char_ranges(bytes: &[u8], max_len: usize) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+
+    while start < bytes.len() {
+        let mut end = (start + max_len).min(bytes.len());
+        // Back off from continuation bytes, unless the whole part is a single invalid character.
+        let boundary = (start + 1..=end)
+            .rev()
+            .find(|i| *i == bytes.len() || bytes[*i] & 0xC0 != 0x80);
+        if let Some(boundary) = boundary {
+            end = boundary;
+        }
+        ranges.push(start..end);
+        start = end;
+    }
+
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ULogParserBuilder;
+    use crate::model::def::BaseType;
+    use crate::model::CChar;
+    use crate::testkit::{Generator, LogBuilder, Topic};
+
+    // Writes the header and definitions of a synthetic log with topics `a` and `b`, up to its first subscription.
+    fn definitions() -> Result<ULogWriter<Vec<u8>>, ULogError> {
+        let bytes = LogBuilder::new()
+            .topic(Topic::new("a").field("x", BaseType::UINT8, Generator::Constant(1.0)))
+            .topic(Topic::new("b").field("y", BaseType::UINT8, Generator::Constant(2.0)))
+            .build()?;
+        let parser = ULogParserBuilder::new(bytes.as_slice())
+            .include_header(true)
+            .build()?;
+
+        let mut writer = ULogWriter::new(Vec::new());
+        for msg in parser {
+            let msg = msg?;
+            if matches!(msg, UlogMessage::AddSubscription(_)) {
+                break;
+            }
+            writer.write_message(&msg)?;
+        }
+        Ok(writer)
+    }
+
+    fn parsed_subscriptions(bytes: &[u8]) -> Result<Vec<(u16, String, u8)>, ULogError> {
+        let mut subscriptions = Vec::new();
+        for msg in ULogParserBuilder::new(bytes).build()? {
+            if let UlogMessage::AddSubscription(sub) = msg? {
+                subscriptions.push((sub.msg_id, sub.message_name, sub.multi_id));
+            }
+        }
+        Ok(subscriptions)
+    }
+
+    #[test]
+    fn test_write_messages() -> Result<(), ULogError> {
+        let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+
+        let parser = ULogParserBuilder::new(input.as_slice())
+            .include_header(true)
+            .include_timestamp(true)
+            .include_padding(true)
+            .emit_unhandled(true)
+            .build()?;
+
+        let mut writer = ULogWriter::new(Vec::new());
+        for msg in parser {
+            writer.write_message(&msg?)?;
+        }
+
+        assert_eq!(writer.into_inner()?, input);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_formats() -> Result<(), ULogError> {
+        use crate::formats::parse_format_str;
+
+        let mut writer = definitions()?;
+        let registry: FormatRegistry = [
+            "c:uint64_t timestamp;inner[2] x;",
+            "inner:float v;",
+            "b:uint64_t timestamp;uint8_t y;",
+        ]
+        .into_iter()
+        .map(parse_format_str)
+        .collect::<Result<_, _>>()?;
+        writer.add_formats(&registry)?;
+        writer.add_subscription("c")?;
+
+        let written: Vec<String> = ULogParserBuilder::new(writer.into_inner()?.as_slice())
+            .build()?
+            .filter_map(|msg| match msg {
+                Ok(UlogMessage::FormatDefinition(format)) => Some(format.name.to_string()),
+                _ => None,
+            })
+            .collect();
+        // `b` had already been written with the same fields.
+        assert_eq!(written, ["a", "b", "inner", "c"]);
+
+        let mut writer = definitions()?;
+        let registry: FormatRegistry = [parse_format_str("c:uint64_t timestamp;missing x;")?]
+            .into_iter()
+            .collect();
+        assert!(matches!(
+            writer.add_formats(&registry),
+            Err(ULogError::UnresolvedFormat(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_allocate_msg_ids() -> Result<(), ULogError> {
+        let mut writer = definitions()?;
+
+        assert_eq!(writer.add_subscription("a")?.msg_id, 0);
+        assert_eq!(writer.add_subscription("b")?.msg_id, 1);
+        let instance = writer.add_subscription("a")?;
+        assert_eq!((instance.msg_id, instance.multi_id), (2, 1));
+        assert_eq!(writer.msg_id("a", 1), Some(2));
+
+        // Removed msg_ids are not reused.
+        writer.remove_subscription(1)?;
+        assert_eq!(writer.msg_id("b", 0), None);
+        assert_eq!(writer.add_subscription("b")?.msg_id, 3);
+
+        let bytes = writer.into_inner()?;
+        assert_eq!(
+            parsed_subscriptions(&bytes)?,
+            [
+                (0, "a".to_string(), 0),
+                (1, "b".to_string(), 0),
+                (2, "a".to_string(), 1),
+                (3, "b".to_string(), 0)
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_explicit_msg_ids() -> Result<(), ULogError> {
+        let mut writer = definitions()?;
+
+        writer.add_subscription_with_id("a", 0, 10)?;
+        writer.add_subscription_instance("a", 3)?;
+        assert_eq!(writer.msg_id("a", 3), Some(11));
+
+        assert!(matches!(
+            writer.add_subscription_with_id("b", 0, 10),
+            Err(ULogError::DuplicateSubscription(10))
+        ));
+        assert!(matches!(
+            writer.add_subscription_with_id("a", 0, 12),
+            Err(ULogError::InvalidConfiguration(_))
+        ));
+        assert!(matches!(
+            writer.remove_subscription(12),
+            Err(ULogError::UndefinedSubscription(12))
+        ));
+
+        // A rejected subscription writes nothing.
+        let ids: Vec<_> = writer.subscriptions().map(|sub| sub.msg_id).collect();
+        assert_eq!(ids, [10, 11]);
+        assert_eq!(parsed_subscriptions(&writer.into_inner()?)?.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_track_written_subscriptions() -> Result<(), ULogError> {
+        let input = std::fs::read("test_data/input/sample_log_small.ulg")?;
+
+        let mut writer = ULogWriter::new(Vec::new());
+        for msg in ULogParserBuilder::new(input.as_slice())
+            .include_header(true)
+            .build()?
+        {
+            writer.write_message(&msg?)?;
+        }
+
+        let last = writer
+            .subscriptions()
+            .map(|sub| sub.msg_id)
+            .max()
+            .unwrap_or_default();
+        assert_eq!(writer.add_subscription("vehicle_status")?.msg_id, last + 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_track_removed_subscriptions() -> Result<(), ULogError> {
+        let mut writer = definitions()?;
+        writer.add_subscription("a")?;
+        writer.add_subscription("b")?;
+        writer.remove_subscription(0)?;
+        writer.add_subscription_with_id("b", 1, 2)?;
+        let bytes = writer.into_inner()?;
+
+        // A default parser doesn't emit unhandled messages, but still returns the removal.
+        let mut copy = ULogWriter::new(Vec::new());
+        for msg in ULogParserBuilder::new(bytes.as_slice())
+            .include_header(true)
+            .build()?
+        {
+            copy.write_message(&msg?)?;
+        }
+
+        assert_eq!(copy.msg_id("a", 0), None);
+        let subscriptions: Vec<_> = copy
+            .subscriptions()
+            .map(|sub| (sub.msg_id, sub.message_name.as_str(), sub.multi_id))
+            .collect();
+        assert_eq!(subscriptions, [(1, "b", 0), (2, "b", 1)]);
+        assert_eq!(copy.into_inner()?, bytes);
+
+        Ok(())
+    }
+
+    fn multi_info(
+        key: &str,
+        base_type: BaseType,
+        array_size: usize,
+        value: FieldValue,
+    ) -> UlogMessage {
+        UlogMessage::MultiInfo(MultiInfo {
+            is_continued: false,
+            key: key.to_string(),
+            r#type: crate::model::def::TypeExpr {
+                base_type,
+                array_size: Some(array_size),
+            },
+            value,
+        })
+    }
+
+    fn parsed_multi_info(bytes: &[u8]) -> Result<Vec<MultiInfo>, ULogError> {
+        let mut parts = Vec::new();
+        for msg in ULogParserBuilder::new(bytes).build()? {
+            if let UlogMessage::MultiInfo(info) = msg? {
+                parts.push(info);
+            }
+        }
+        Ok(parts)
+    }
+
+    #[test]
+    fn test_split_multi_info_chars() -> Result<(), ULogError> {
+        // Two byte characters, offset by one byte so that a naive split would cut a character in half.
+        let text = format!("x{}", "é".repeat(50_000));
+        let chars: Vec<CChar> = text.bytes().map(CChar).collect();
+
+        let mut writer = definitions()?;
+        writer.write_message(&multi_info(
+            "boot_log",
+            BaseType::CHAR,
+            chars.len(),
+            FieldValue::ArrayChar(chars),
+        ))?;
+
+        let parts = parsed_multi_info(&writer.into_inner()?)?;
+        assert_eq!(parts.len(), 2);
+        assert!(!parts[0].is_continued);
+        assert!(parts[1].is_continued);
+
+        let mut joined = Vec::new();
+        for part in &parts {
+            assert_eq!(part.key, "boot_log");
+            let FieldValue::ArrayChar(chars) = &part.value else {
+                panic!("Expected a char array, got {:?}", part.value);
+            };
+            let bytes: Vec<u8> = chars.iter().map(|c| c.0).collect();
+            assert!(std::str::from_utf8(&bytes).is_ok());
+            assert_eq!(part.r#type.array_size, Some(bytes.len()));
+            joined.extend(bytes);
+        }
+        assert_eq!(joined, text.as_bytes());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_multi_info_array() -> Result<(), ULogError> {
+        let values: Vec<u32> = (0..40_000).collect();
+
+        let mut writer = definitions()?;
+        writer.write_message(&multi_info(
+            "samples",
+            BaseType::UINT32,
+            values.len(),
+            FieldValue::ArrayU32(values.clone()),
+        ))?;
+
+        let parts = parsed_multi_info(&writer.into_inner()?)?;
+        assert_eq!(parts.len(), 3);
+
+        let joined: Vec<u32> = parts
+            .iter()
+            .flat_map(|part| match &part.value {
+                FieldValue::ArrayU32(values) => values.clone(),
+                other => panic!("Expected a u32 array, got {other:?}"),
+            })
+            .collect();
+        assert_eq!(joined, values);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_too_large() -> Result<(), ULogError> {
+        let mut writer = definitions()?;
+        let written = writer.get_ref().len();
+
+        let info = UlogMessage::Info(crate::model::msg::Info {
+            key: "boot_log".to_string(),
+            r#type: crate::model::def::TypeExpr {
+                base_type: BaseType::CHAR,
+                array_size: Some(70_000),
+            },
+            value: FieldValue::ArrayChar(vec![CChar(b'x'); 70_000]),
+        });
+        assert!(matches!(
+            writer.write_message(&info),
+            Err(ULogError::MessageTooLarge(_))
+        ));
+
+        // Keys are limited to 255 bytes.
+        let long_key = multi_info(
+            &"k".repeat(300),
+            BaseType::UINT8,
+            1,
+            FieldValue::ArrayU8(vec![1]),
+        );
+        assert!(writer.write_message(&long_key).is_err());
+
+        assert_eq!(writer.get_ref().len(), written);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_info_constructors() -> Result<(), ULogError> {
+        let mut writer = definitions()?;
+        writer.write_message(&UlogMessage::info("ver_sw", "1.14.0"))?;
+        writer.write_message(&UlogMessage::info("ver_sw_release", 0x010e_00ffu32))?;
+        writer.write_message(&UlogMessage::info("time_ref_utc", -3600i32))?;
+        writer.write_message(&UlogMessage::info("gains", [0.5f32, 2.0]))?;
+        writer.write_message(&UlogMessage::multi_info(
+            "perf_counter_preflight",
+            "boot".to_string(),
+        ))?;
+
+        let mut types = Vec::new();
+        let mut lines = Vec::new();
+        for msg in ULogParserBuilder::new(writer.into_inner()?.as_slice()).build()? {
+            match msg? {
+                UlogMessage::Info(info) => {
+                    types.push(info.r#type.to_string());
+                    lines.push(info.to_string());
+                }
+                UlogMessage::MultiInfo(info) => {
+                    types.push(info.r#type.to_string());
+                    lines.push(info.to_string());
+                }
+                _ => {}
+            }
+        }
+
+        assert_eq!(
+            types,
+            ["char[6]", "uint32_t", "int32_t", "float[2]", "char[4]"]
+        );
+        assert_eq!(lines[0], "ver_sw: \"1.14.0\"");
+        assert_eq!(lines[1], "ver_sw_release: 0x10E00FF");
+        assert_eq!(lines[2], "time_ref_utc: -3600");
+        assert_eq!(lines[4], "perf_counter_preflight: \"boot\"");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,10 @@
+use yule_log::ULogData;
+
+#[derive(ULogData)]
+#[yule_log(subscription_name = "vehicle_local_positon", px4_topic)]
+pub struct LocalPosition {
+    timestamp: u64,
+    x: f32,
+}
+
+fn main() {}
@@ -0,0 +1,17 @@
+use yule_log::ULogData;
+
+#[derive(ULogData)]
+#[yule_log(px4_topic)]
+pub struct VehicleLocalPosition {
+    timestamp: u64,
+    x: f32,
+}
+
+#[derive(ULogData)]
+#[yule_log(subscription_name = "battery_status", px4_topic)]
+pub struct Battery {
+    timestamp: u64,
+    voltage_v: f32,
+}
+
+fn main() {}
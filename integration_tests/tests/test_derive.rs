@@ -142,7 +142,7 @@ fn test_add_subscription() -> Result<(), Box<dyn std::error::Error>> {
     let reader =
         BufReader::new(File::open("../core/test_data/input/sample_log_small.ulg").unwrap());
 
-    const EXTRA_SUBSCR_NAME: &'static str = "vehicle_gps_position";
+    const EXTRA_SUBSCR_NAME: &str = "vehicle_gps_position";
 
     let stream = LoggedMessages::builder(reader)
         .add_subscription(EXTRA_SUBSCR_NAME)?
@@ -436,3 +436,76 @@ fn readme_builder_example() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn test_msg_id_reuse() -> Result<(), Box<dyn std::error::Error>> {
+    use std::time::Duration;
+    use yule_log::model::def::BaseType;
+    use yule_log::testkit::{Generator, LogBuilder, Topic};
+
+    #[derive(ULogMessages)]
+    pub enum ReusedMessages {
+        TopicA(TopicA),
+    }
+
+    #[derive(ULogData, Debug)]
+    pub struct TopicA {
+        timestamp: u64,
+        x: u32,
+    }
+
+    // Halfway through the log, msg_id 0 is removed and reused for `topic_b`, which has the same size.
+    let mut resubscribe = vec![2, 0, b'R', 0, 0];
+    resubscribe.extend_from_slice(&[10, 0, b'A', 0, 0, 0]);
+    resubscribe.extend_from_slice(b"topic_b");
+
+    let bytes = LogBuilder::new()
+        .topic(Topic::new("topic_a").field("x", BaseType::UINT32, Generator::Constant(1.0)))
+        .topic(Topic::new("topic_b").field("y", BaseType::FLOAT, Generator::Constant(2.0)))
+        .garbage_at(Duration::from_millis(450), &resubscribe)
+        .build()?;
+
+    let messages = ReusedMessages::stream(bytes.as_slice())?.collect::<Result<Vec<_>, _>>()?;
+
+    assert_eq!(messages.len(), 5);
+    assert!(messages
+        .iter()
+        .all(|ReusedMessages::TopicA(a)| a.x == 1 && a.timestamp < 1_450_000));
+
+    Ok(())
+}
+
+#[test]
+fn test_string_fields() -> Result<(), Box<dyn std::error::Error>> {
+    use yule_log::model::def::BaseType;
+    use yule_log::testkit::{Generator, LogBuilder, Topic};
+
+    #[derive(ULogMessages)]
+    pub enum TextMessages {
+        MissionResult(MissionResult),
+    }
+
+    #[derive(ULogData, Debug)]
+    pub struct MissionResult {
+        timestamp: u64,
+        name: String,
+    }
+
+    let bytes = LogBuilder::new()
+        .topic(Topic::new("mission_result").array_field(
+            "name",
+            BaseType::CHAR,
+            8,
+            Generator::from_fn(|sample| f64::from(b"survey\0\0"[sample.element])),
+        ))
+        .build()?;
+
+    let messages = TextMessages::stream(bytes.as_slice())?.collect::<Result<Vec<_>, _>>()?;
+
+    assert_eq!(messages.len(), 10);
+    assert!(messages
+        .iter()
+        .all(|TextMessages::MissionResult(result)| result.name == "survey"));
+
+    Ok(())
+}
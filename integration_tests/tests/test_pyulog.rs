@@ -0,0 +1,249 @@
+//! Compares the CSV export of yule_log with that of `ulog2csv` from pyulog, which is the reference for the layout of
+//! the export, to catch drifts in timestamp handling, multi_id mapping and field naming.
+//!
+//! Skipped unless pyulog is installed.  `$ULOG2CSV` overrides the path of `ulog2csv`.  With the `dev_corpus`
+//! feature, the logs of the corpus which are already cached are compared too.
+//!
+//! `pyulog_fixture` holds the files `ulog2csv` writes for the log of `fixture_log()`, so that the comparison itself
+//! is tested without pyulog.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use yule_log::builder::ULogParserBuilder;
+use yule_log::export::csv::CsvExporter;
+use yule_log::model::def::BaseType;
+use yule_log::testkit::{Generator, LogBuilder, Topic};
+
+/// The relative tolerance of values, as pyulog writes floats in the shortest form of their own.
+const TOLERANCE: f64 = 1e-6;
+
+type Csv = (Vec<String>, Vec<Vec<String>>);
+
+fn ulog2csv() -> Option<String> {
+    let program = std::env::var("ULOG2CSV").unwrap_or_else(|_| "ulog2csv".to_owned());
+    Command::new(&program)
+        .arg("--help")
+        .output()
+        .is_ok_and(|output| output.status.success())
+        .then_some(program)
+}
+
+fn logs() -> Vec<PathBuf> {
+    #[allow(unused_mut)]
+    let mut logs: Vec<PathBuf> = fs::read_dir("../core/test_data/input")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "ulg"))
+        .collect();
+
+    #[cfg(feature = "dev_corpus")]
+    {
+        use yule_log::corpus::Corpus;
+
+        let corpus =
+            Corpus::from_manifest_file(Corpus::default_cache_dir(), "../core/test_data/corpus.txt")
+                .unwrap();
+        logs.extend(corpus.cached().into_iter().map(|(_, path)| path));
+    }
+
+    logs.sort();
+    logs
+}
+
+/// The CSV files in `dir`, by the name of their topic instance, i.e. the file name less `prefix` and `.csv`.
+fn read_csv_files(dir: &Path, prefix: &str) -> Result<BTreeMap<String, Csv>, Box<dyn Error>> {
+    let mut files = BTreeMap::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let Some(instance) = name
+            .strip_prefix(prefix)
+            .and_then(|name| name.strip_suffix(".csv"))
+        else {
+            continue;
+        };
+
+        let text = fs::read_to_string(&path)?;
+        let mut lines = text
+            .lines()
+            .map(|line| line.split(',').map(str::to_owned).collect());
+        let header = lines.next().unwrap_or_default();
+        files.insert(instance.to_owned(), (header, lines.collect()));
+    }
+    Ok(files)
+}
+
+fn same_value(ours: &str, theirs: &str) -> bool {
+    // Integers, timestamps above all, are compared exactly, as the tolerance of floats would hide microseconds.
+    if let (Ok(a), Ok(b)) = (ours.parse::<i128>(), theirs.parse::<i128>()) {
+        return a == b;
+    }
+    match (ours.parse::<f64>(), theirs.parse::<f64>()) {
+        (Ok(a), Ok(b)) if a.is_nan() || b.is_nan() => a.is_nan() && b.is_nan(),
+        (Ok(a), Ok(b)) => a == b || (a - b).abs() <= TOLERANCE * a.abs().max(b.abs()),
+        // Non-numeric columns are formatted differently by each, so aren't compared.
+        _ => true,
+    }
+}
+
+/// Returns the differences between the export of `log` by yule_log and by pyulog.
+fn compare(ulog2csv: &str, log: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let theirs_dir = tempfile::tempdir()?;
+    let status = Command::new(ulog2csv)
+        .arg("-o")
+        .arg(theirs_dir.path())
+        .arg(log)
+        .status()?;
+    if !status.success() {
+        return Err(format!("ulog2csv failed on {}: {status}", log.display()).into());
+    }
+
+    compare_with(log, theirs_dir.path())
+}
+
+/// Returns the differences between the export of `log` by yule_log and the files `ulog2csv` wrote for it in
+/// `theirs_dir`.
+fn compare_with(log: &Path, theirs_dir: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let ours_dir = tempfile::tempdir()?;
+    let parser = ULogParserBuilder::new(fs::File::open(log)?).build()?;
+    CsvExporter::new(ours_dir.path()).export(parser)?;
+
+    let stem = log.file_stem().unwrap().to_string_lossy();
+    let ours = read_csv_files(ours_dir.path(), "")?;
+    let theirs = read_csv_files(theirs_dir, &format!("{stem}_"))?;
+
+    let mut differences = Vec::new();
+    for instance in theirs
+        .keys()
+        .filter(|instance| !ours.contains_key(*instance))
+    {
+        differences.push(format!("{instance}: only exported by pyulog"));
+    }
+
+    for (instance, (our_header, our_rows)) in &ours {
+        let Some((their_header, their_rows)) = theirs.get(instance) else {
+            differences.push(format!("{instance}: only exported by yule_log"));
+            continue;
+        };
+        if our_rows.len() != their_rows.len() {
+            differences.push(format!(
+                "{instance}: {} rows, but pyulog has {}",
+                our_rows.len(),
+                their_rows.len()
+            ));
+            continue;
+        }
+
+        for (our_column, name) in our_header.iter().enumerate() {
+            let Some(their_column) = their_header.iter().position(|n| n == name) else {
+                differences.push(format!(
+                    "{instance}: column {name} isn't in pyulog's export"
+                ));
+                continue;
+            };
+            let mismatch = our_rows
+                .iter()
+                .zip(their_rows)
+                .position(|(ours, theirs)| !same_value(&ours[our_column], &theirs[their_column]));
+            if let Some(row) = mismatch {
+                differences.push(format!(
+                    "{instance}: {name} in row {row} is {}, but pyulog has {}",
+                    our_rows[row][our_column], their_rows[row][their_column]
+                ));
+            }
+        }
+    }
+
+    Ok(differences)
+}
+
+#[test]
+fn test_export_matches_pyulog() -> Result<(), Box<dyn Error>> {
+    let Some(ulog2csv) = ulog2csv() else {
+        eprintln!("Skipped: pyulog's ulog2csv isn't installed.");
+        return Ok(());
+    };
+
+    let mut differences = Vec::new();
+    for log in logs() {
+        differences.extend(
+            compare(&ulog2csv, &log)?
+                .into_iter()
+                .map(|difference| format!("{}: {difference}", log.display())),
+        );
+    }
+
+    assert!(differences.is_empty(), "{}", differences.join("\n"));
+    Ok(())
+}
+
+/// Writes `fixture.ulg` to `dir`: two instances of `sensor_accel`, with an array field, and one of
+/// `vehicle_status` at a different rate.
+fn fixture_log(dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let accel = |multi_id, start, step, flags| {
+        Topic::new("sensor_accel")
+            .multi_id(multi_id)
+            .field("x", BaseType::FLOAT, Generator::Ramp { start, step })
+            .array_field("flags", BaseType::UINT8, 2, Generator::Constant(flags))
+    };
+    let bytes = LogBuilder::new()
+        .duration(Duration::from_millis(300))
+        .topic(accel(0, 0.5, 0.25, 3.0))
+        .topic(accel(1, -1.0, 0.5, 7.0))
+        .topic(Topic::new("vehicle_status").rate_hz(5.0).field(
+            "count",
+            BaseType::UINT32,
+            Generator::Ramp {
+                start: 10.0,
+                step: 1.0,
+            },
+        ))
+        .build()?;
+
+    let path = dir.join("fixture.ulg");
+    fs::write(&path, bytes)?;
+    Ok(path)
+}
+
+#[test]
+fn test_export_matches_fixture() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let log = fixture_log(dir.path())?;
+
+    let differences = compare_with(&log, Path::new("tests/pyulog_fixture"))?;
+    assert!(differences.is_empty(), "{}", differences.join("\n"));
+    Ok(())
+}
+
+#[test]
+fn test_compare_finds_differences() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let log = fixture_log(dir.path())?;
+
+    // pyulog's files less the second instance of sensor_accel, and with a timestamp of vehicle_status shifted.
+    let theirs = tempfile::tempdir()?;
+    fs::copy(
+        "tests/pyulog_fixture/fixture_sensor_accel_0.csv",
+        theirs.path().join("fixture_sensor_accel_0.csv"),
+    )?;
+    let status = fs::read_to_string("tests/pyulog_fixture/fixture_vehicle_status_0.csv")?;
+    fs::write(
+        theirs.path().join("fixture_vehicle_status_0.csv"),
+        status.replace("1200000", "1200001"),
+    )?;
+
+    let differences = compare_with(&log, theirs.path())?;
+    assert_eq!(
+        differences,
+        [
+            "sensor_accel_1: only exported by yule_log",
+            "vehicle_status_0: timestamp in row 1 is 1200000, but pyulog has 1200001",
+        ]
+    );
+    Ok(())
+}
@@ -33,8 +33,8 @@ fn test_sub_allow_list() -> Result<(), Box<dyn std::error::Error>> {
 
     for msg_res in stream {
         let msg = msg_res?;
-        match msg {
-            UlogMessage::LoggedData(data) => match data.data.name.as_ref() {
+        if let UlogMessage::LoggedData(data) = msg {
+            match data.data.name.as_ref() {
                 "vehicle_local_position" => {
                     flags.pos_seen = true;
                 }
@@ -47,8 +47,7 @@ fn test_sub_allow_list() -> Result<(), Box<dyn std::error::Error>> {
                 _ => {
                     flags.other_seen = true;
                 }
-            },
-            _ => {}
+            }
         }
 
         if flags.all_seen() {
@@ -73,3 +72,57 @@ fn test_sub_allow_list() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn test_emit_ignored() -> Result<(), Box<dyn std::error::Error>> {
+    let parse = |emit_ignored: bool| -> Result<_, Box<dyn std::error::Error>> {
+        let reader = BufReader::new(File::open("../core/test_data/input/sample_log_small.ulg")?);
+        let mut parser = ULogParserBuilder::new(reader)
+            .set_subscription_allow_list(["vehicle_attitude"])
+            .emit_ignored(emit_ignored)
+            .build()?;
+        let messages = parser.by_ref().collect::<Result<Vec<_>, _>>()?;
+        Ok((messages, parser.validation_report().message_counts.ignored))
+    };
+
+    let (with_ignored, ignored) = parse(true)?;
+    let (without_ignored, skipped) = parse(false)?;
+
+    let is_ignored = |msg: &&UlogMessage| matches!(msg, UlogMessage::Ignored { .. });
+    assert!(ignored > 0);
+    assert_eq!(with_ignored.iter().filter(is_ignored).count() as u64, ignored);
+    assert_eq!(without_ignored.iter().filter(is_ignored).count(), 0);
+    assert_eq!(skipped, ignored);
+
+    // Skipping the ignored messages leaves the other messages as they were.
+    let others = |messages: &[UlogMessage]| -> Vec<String> {
+        messages
+            .iter()
+            .filter(|msg| !is_ignored(msg))
+            .map(|msg| format!("{msg:?}"))
+            .collect()
+    };
+    assert_eq!(others(&with_ignored), others(&without_ignored));
+
+    Ok(())
+}
+
+#[test]
+fn test_skip_by_seeking() -> Result<(), Box<dyn std::error::Error>> {
+    let parse = |seek: bool| -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let file = File::open("../core/test_data/input/sample_log_small.ulg")?;
+        let mut builder = ULogParserBuilder::new(file)
+            .set_subscription_allow_list(["vehicle_attitude", "battery_status"])
+            .read_buffer_size(16);
+        if seek {
+            builder = builder.skip_by_seeking();
+        }
+        builder
+            .build()?
+            .map(|msg| Ok(format!("{:?}", msg?)))
+            .collect()
+    };
+
+    assert_eq!(parse(true)?, parse(false)?);
+    Ok(())
+}
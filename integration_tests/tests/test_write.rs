@@ -0,0 +1,165 @@
+use yule_log::compat;
+use yule_log::errors::ULogError;
+use yule_log::model::msg::{FileHeader, FlagBits, UlogMessage};
+use yule_log::writer::ULogWriter;
+use yule_log::{ULogData, ULogMessages};
+
+#[derive(ULogMessages)]
+pub enum WrittenMessages {
+    VehicleLocalPosition(VehicleLocalPosition),
+    Setpoint(Setpoint),
+}
+
+#[derive(ULogData, Debug, PartialEq, Clone)]
+pub struct VehicleLocalPosition {
+    timestamp: u64,
+    x: f32,
+    y: f32,
+    z: Option<f32>,
+    velocity: [f32; 3],
+    #[yule_log(field_name = "xy_valid")]
+    valid: bool,
+    setpoint: Setpoint,
+}
+
+// Without a `timestamp` field, so one is prepended when it is logged as a topic.
+#[derive(ULogData, Debug, PartialEq, Clone)]
+#[yule_log(multi_id = 1)]
+pub struct Setpoint {
+    vx: f32,
+    alt: f64,
+}
+
+#[derive(ULogData, Debug, PartialEq, Clone)]
+pub struct BatteryStatus {
+    timestamp: u64,
+    voltage_v: f32,
+}
+
+#[derive(ULogData, Debug, PartialEq, Clone)]
+pub struct Unwritable {
+    values: Vec<f32>,
+}
+
+fn writer() -> Result<ULogWriter<Vec<u8>>, ULogError> {
+    let mut writer = ULogWriter::new(Vec::new());
+    writer.write_message(&UlogMessage::Header(FileHeader {
+        version: 1,
+        timestamp: 0,
+    }))?;
+    writer.write_message(&UlogMessage::FlagBits(FlagBits {
+        compat_flags: [0; 8],
+        incompat_flags: [0; 8],
+        appended_data_offsets: [0; 3],
+    }))?;
+    writer.write_message(&UlogMessage::info("sys_name", "yule_log"))?;
+    Ok(writer)
+}
+
+fn position(i: u8) -> VehicleLocalPosition {
+    VehicleLocalPosition {
+        timestamp: 0,
+        x: f32::from(i),
+        y: -f32::from(i),
+        z: i.is_multiple_of(2).then_some(0.5),
+        velocity: [1.0, 2.0, f32::from(i)],
+        valid: i.is_multiple_of(3),
+        setpoint: Setpoint {
+            vx: 0.25,
+            alt: 100.0 + f64::from(i),
+        },
+    }
+}
+
+#[test]
+fn test_add_topic_and_log() -> Result<(), Box<dyn std::error::Error>> {
+    let mut writer = writer()?;
+    writer.add_topic::<VehicleLocalPosition>()?;
+
+    // The nested format was written with the topic, but without a `timestamp` field.
+    assert!(writer.add_topic::<Setpoint>().is_err());
+
+    for i in 0..5 {
+        writer.log(&position(i), 1_000_000 + u64::from(i) * 100_000)?;
+    }
+    assert_eq!(writer.msg_id("vehicle_local_position", 0), Some(0));
+
+    let bytes = writer.into_inner()?;
+    assert_eq!(compat::check(bytes.as_slice())?, []);
+
+    let logged = WrittenMessages::stream(bytes.as_slice())?.collect::<Result<Vec<_>, _>>()?;
+    let expected: Vec<_> = (0..5)
+        .map(|i| {
+            let mut expected = position(i);
+            expected.timestamp = 1_000_000 + u64::from(i) * 100_000;
+            // `None` is written as zero.
+            expected.z = Some(expected.z.unwrap_or(0.0));
+            expected
+        })
+        .collect();
+
+    assert_eq!(logged.len(), 5);
+    for (logged, expected) in logged.into_iter().zip(expected) {
+        match logged {
+            WrittenMessages::VehicleLocalPosition(v) => assert_eq!(v, expected),
+            WrittenMessages::Setpoint(_) => panic!("Unexpected setpoint"),
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_log_with_prepended_timestamp() -> Result<(), Box<dyn std::error::Error>> {
+    let mut writer = writer()?;
+    writer.add_topic::<Setpoint>()?;
+
+    // Formats can't be written once the data section has started.
+    writer.log(&Setpoint { vx: 1.0, alt: 2.0 }, 5_000)?;
+    assert!(matches!(
+        writer.add_topic::<BatteryStatus>(),
+        Err(ULogError::InvalidConfiguration(_))
+    ));
+    assert!(matches!(
+        writer.log(
+            &BatteryStatus {
+                timestamp: 0,
+                voltage_v: 12.0
+            },
+            6_000
+        ),
+        Err(ULogError::InvalidConfiguration(_))
+    ));
+
+    let bytes = writer.into_inner()?;
+    assert_eq!(compat::check(bytes.as_slice())?, []);
+
+    let logged = WrittenMessages::stream(bytes.as_slice())?.collect::<Result<Vec<_>, _>>()?;
+    assert!(matches!(
+        logged.as_slice(),
+        [WrittenMessages::Setpoint(Setpoint { vx, alt })] if *vx == 1.0 && *alt == 2.0
+    ));
+
+    // The prepended timestamp is read by the parser.
+    let timestamps: Vec<_> = yule_log::builder::ULogParserBuilder::new(bytes.as_slice())
+        .build()?
+        .filter_map(|msg| match msg {
+            Ok(UlogMessage::LoggedData(data)) => data.data.timestamp,
+            _ => None,
+        })
+        .collect();
+    assert_eq!(timestamps, [5_000]);
+
+    Ok(())
+}
+
+#[test]
+fn test_vec_field_is_rejected() -> Result<(), ULogError> {
+    let mut writer = writer()?;
+    let Err(ULogError::InvalidConfiguration(message)) = writer.add_topic::<Unwritable>() else {
+        panic!("Expected an error for a Vec field");
+    };
+    assert!(message.contains("values"), "{message}");
+
+    Ok(())
+}
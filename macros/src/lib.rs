@@ -38,7 +38,7 @@ use crate::utils::{extract_option_type, is_option_type, make_index_type};
 use darling::FromDeriveInput;
 use darling::FromField;
 use darling::FromVariant;
-use heck::ToSnakeCase;
+use heck::{ToShoutySnakeCase, ToSnakeCase};
 use proc_macro::TokenStream;
 use quote::quote;
 use syn::spanned::Spanned;
@@ -55,6 +55,9 @@ struct LoggedStructAttr {
     #[darling(default)]
     /// Optional `multi_id` for subscriptions with multiple instances.
     multi_id: Option<u8>,
+    #[darling(default)]
+    /// Checks that the subscription name is one of the constants of `yule_log::px4_topics`.
+    px4_topic: bool,
 }
 
 #[derive(FromField, Default)]
@@ -74,6 +77,9 @@ struct LoggedFieldAttr {
 ///
 /// * `#[yule_log(multi_id = N)]` – optional multi-instance ID. Defaults to `0` if not set.
 ///
+/// * `#[yule_log(px4_topic)]` – optional check that the subscription name is a topic of `yule_log::px4_topics`,
+///   so a misspelled name fails to compile.  Requires the `px4_topics` feature of `yule_log`.
+///
 /// Each field can also use an optional attribute:
 ///
 /// * `#[yule_log(field_name = "...")]` – override the field name used in the ULOG message.
@@ -135,6 +141,26 @@ pub fn derive_logged_struct(input: TokenStream) -> TokenStream {
         .unwrap_or_else(|| struct_name.to_string().to_snake_case());
     let multi_id = attr.multi_id.unwrap_or(0);
 
+    // Refers to the constant of the topic, which doesn't exist if the name is misspelled.
+    let px4_topic_check = if attr.px4_topic {
+        match syn::parse_str::<Ident>(&subscription.to_shouty_snake_case()) {
+            Ok(mut constant) => {
+                constant.set_span(struct_name.span());
+                quote! { const _: &str = ::yule_log::px4_topics::#constant; }
+            }
+            Err(_) => {
+                return syn::Error::new_spanned(
+                    struct_name,
+                    format!("`{subscription}` is not a PX4 topic name."),
+                )
+                .to_compile_error()
+                .into();
+            }
+        }
+    } else {
+        quote! {}
+    };
+
     let accessor_name = Ident::new(&format!("{struct_name}Accessor"), struct_name.span());
 
     fn named_ident(f: &syn::Field) -> &syn::Ident {
@@ -154,6 +180,13 @@ pub fn derive_logged_struct(input: TokenStream) -> TokenStream {
         make_index_type(f)
     }
 
+    fn ulog_field_name(f: &syn::Field) -> String {
+        // This unwrap is safe because LoggedFieldAttr has the `Default` attribute applied.
+        let attr = LoggedFieldAttr::from_field(f).unwrap();
+        attr.field_name
+            .unwrap_or_else(|| named_ident(f).to_string())
+    }
+
     // Generate index_fields to hold the index of the field in the LoggedData message, for efficient lookup.
     let index_fields = fields.iter().map(|f| {
         let idx_ident = idx_ident(f);
@@ -164,12 +197,7 @@ pub fn derive_logged_struct(input: TokenStream) -> TokenStream {
     let accessor_struct = {
         let idx_field_exprs = fields.iter().map(|f| {
             let idx_ident = idx_ident(f);
-            let ulog_name = {
-                // This unwrap is safe because LoggedFieldAttr has the `Default` attribute applied.
-                let attr = LoggedFieldAttr::from_field(f).unwrap();
-                attr.field_name
-                    .unwrap_or_else(|| named_ident(f).to_string())
-            };
+            let ulog_name = ulog_field_name(f);
 
             if is_option_type(&make_index_type(f)) {
                 quote! {
@@ -230,10 +258,11 @@ pub fn derive_logged_struct(input: TokenStream) -> TokenStream {
     // - For any non-optional field `f`, the corresponding index field is `usize` and guaranteed to be present.
     //
     // This invariant is established earlier when generating index fields and validated by construction of the Accessor struct.
-    // Therefore, it is safe to unwrap and index into `format.fields` accordingly.
+    // The indices refer to positions in the `def::Format`, so fields are looked up with `inst::Format::field_at()`,
+    // which stays correct if the parser filtered out fields such as the timestamp or padding.
     //
     // The generated code uses this to:
-    // - Return `None` directly when the index is `None` (field missing),
+    // - Return `None` directly when the index is `None` (field missing) or the field was filtered out,
     // - Or call `FromField` on the inner type if present,
     // - Or call `FromField` on the full type for non-optional fields.
     let get_data_fields =
@@ -247,18 +276,133 @@ pub fn derive_logged_struct(input: TokenStream) -> TokenStream {
                 let inner_ty = extract_option_type(ty).expect("Expected Option inner type.");
 
                 quote! {
-                    #name: match self.#idx_ident {
+                    #name: match self.#idx_ident.and_then(|idx| format.field_at(idx)) {
                         None => None,
-                        Some(idx) => Some(<#inner_ty as #from_field_path>::from_field(&format.fields[idx])?)
+                        Some(field) => Some(<#inner_ty as #from_field_path>::from_field(field)?)
                     }
                 }
             } else {
+                let ulog_name = ulog_field_name(f);
                 quote! {
-                    #name: <#ty as #from_field_path>::from_field(&format.fields[self.#idx_ident])?
+                    #name: <#ty as #from_field_path>::from_field(
+                        format.field_at(self.#idx_ident).ok_or_else(|| {
+                            yule_log::errors::ULogError::InvalidFieldName(format!(
+                                "Field `{}` was filtered out of subscription `{}`.",
+                                #ulog_name,
+                                #subscription
+                            ))
+                        })?
+                    )?
                 }
             }
         });
 
+    // Generate the ULogEncode implementation, which writes the fields in declaration order.
+    // Optional fields are written as their inner type, with zeros in place of `None`.
+    let has_timestamp = fields.iter().any(|f| ulog_field_name(f) == "timestamp");
+
+    let field_types: Vec<_> = fields
+        .iter()
+        .map(|f| match extract_option_type(&f.ty) {
+            Some(inner_ty) if is_option_type(&f.ty) => inner_ty.clone(),
+            _ => f.ty.clone(),
+        })
+        .collect();
+
+    let format_fields = fields.iter().zip(&field_types).map(|(f, ty)| {
+        let ulog_name = ulog_field_name(f);
+        quote! {
+            let format = format.typed_field(
+                #ulog_name,
+                <#ty as yule_log::macro_utils::IntoField>::type_expr().map_err(|e| {
+                    yule_log::errors::ULogError::InvalidConfiguration(format!(
+                        "Field `{}` of `{}` can't be written: {}",
+                        #ulog_name,
+                        #subscription,
+                        e
+                    ))
+                })?,
+            );
+        }
+    });
+
+    let encode_fields = fields.iter().zip(&field_types).map(|(f, ty)| {
+        let name = named_ident(f);
+        let value = if is_option_type(&f.ty) {
+            quote! {
+                match &self.#name {
+                    Some(value) => value.encode_field(buf),
+                    None => <#ty as IntoField>::encode_zeroed(buf),
+                }
+            }
+        } else {
+            quote! { self.#name.encode_field(buf); }
+        };
+
+        if ulog_field_name(f) == "timestamp" {
+            quote! {
+                match timestamp {
+                    Some(timestamp) => timestamp.encode_field(buf),
+                    None => { #value }
+                }
+            }
+        } else {
+            value
+        }
+    });
+
+    let encode_timestamp = if has_timestamp {
+        quote! {}
+    } else {
+        quote! {
+            if let Some(timestamp) = timestamp {
+                timestamp.encode_field(buf);
+            }
+        }
+    };
+
+    let ulog_encode = quote! {
+        #[automatically_derived]
+        impl yule_log::macro_utils::ULogEncode for #struct_name {
+            const FORMAT_NAME: &'static str = #subscription;
+            const MULTI_ID: u8 = #multi_id;
+
+            fn ulog_format(top_level: bool) -> Result<yule_log::model::def::Format, yule_log::errors::ULogError> {
+                let mut format = yule_log::model::def::FormatBuilder::new(#subscription);
+                if top_level && !#has_timestamp {
+                    format = format.field("timestamp", yule_log::model::def::BaseType::UINT64);
+                }
+                #( #format_fields )*
+
+                if top_level {
+                    format.build()
+                } else {
+                    format.build_nested()
+                }
+            }
+
+            fn ulog_nested_formats(formats: &mut Vec<yule_log::model::def::Format>)
+                -> Result<(), yule_log::errors::ULogError>
+            {
+                #( <#field_types as yule_log::macro_utils::IntoField>::nested_formats(formats)?; )*
+                Ok(())
+            }
+
+            #[allow(unused_variables)]
+            fn encode_fields(&self, timestamp: Option<u64>, buf: &mut Vec<u8>) {
+                use ::yule_log::macro_utils::IntoField;
+
+                #encode_timestamp
+                #( #encode_fields )*
+            }
+
+            #[allow(unused_variables)]
+            fn encode_zeroed_fields(buf: &mut Vec<u8>) {
+                #( <#field_types as yule_log::macro_utils::IntoField>::encode_zeroed(buf); )*
+            }
+        }
+    };
+
     let expanded = quote! {
         #[doc = "Represents the mapping of a ULOG LoggedDataMessage."]
         #[doc = concat!("Subscription name: ", #subscription)]
@@ -270,6 +414,8 @@ pub fn derive_logged_struct(input: TokenStream) -> TokenStream {
             const __YULE_LOG_MULTI_ID: u8 = #multi_id;
         }
 
+        #px4_topic_check
+
         #[doc = "Accessor type for efficiently retrieving fields from this message type."]
         #[automatically_derived]
         pub struct #accessor_name {
@@ -310,6 +456,7 @@ pub fn derive_logged_struct(input: TokenStream) -> TokenStream {
             }
         }
 
+        #ulog_encode
     };
 
     expanded.into()
@@ -667,11 +814,18 @@ pub fn derive_logged_enum(input: TokenStream) -> TokenStream {
                         Err(e) => return Some(Err(yule_log::errors::ULogError::from(e))),
                     };
 
+                    // A removed msg_id may be reused by a later subscription to another topic,
+                    // so its accessor must not outlive the subscription.
+                    if let Some(msg_id) = msg.removed_subscription() {
+                        self.subs.remove(&msg_id);
+                    }
+
                     match msg {
                         UlogMessage::AddSubscription(sub) => {
                             match (sub.message_name.as_str(), sub.multi_id) {
                                 #( #add_subscription_arms )*
                                 _ => {
+                                    self.subs.remove(&sub.msg_id);
                                     #extra_subscription_forwarder
                                 }
                             }